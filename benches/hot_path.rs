@@ -0,0 +1,176 @@
+//! Benchmarks del camino de cada mensaje: decodificación del payload (JSON,
+//! protobuf y trama cruda), conversión a registro y construcción de los INSERT por
+//! lote. Usan los mensajes de `tests/fixtures/messages`.
+//!
+//! ```bash
+//! cargo bench --bench hot_path
+//! # Comparar contra una línea base guardada
+//! cargo bench --bench hot_path -- --save-baseline main
+//! cargo bench --bench hot_path -- --baseline main
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use prost::Message;
+use std::collections::HashMap;
+use std::path::Path;
+
+use tracking_consumer_rust::config::siscom::{
+    kafka_message::Decoded, KafkaMessage, Metadata, QueclinkDecoded, SuntechDecoded,
+};
+use tracking_consumer_rust::config::{
+    CurrentStateOrder, InsertMode, PayloadFormat, SanitizationConfig,
+};
+use tracking_consumer_rust::models::{CommunicationRecord, DeviceMessage, NormalizedPosition};
+use tracking_consumer_rust::services::raw_decoder::RawDecoder;
+use tracking_consumer_rust::services::{DatabaseService, KafkaConsumerService};
+
+const FIXTURES: &[&str] = &["suntech_stt", "queclink_gtfri"];
+
+/// Tamaños de lote de los INSERT
+const BATCH_SIZES: &[usize] = &[100, 1000];
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/messages")
+        .join(format!("{}.json", name));
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// El mismo mensaje como lo publica el decodificador en protobuf
+fn to_protobuf(message: &DeviceMessage) -> Vec<u8> {
+    let json = serde_json::to_value(message).unwrap();
+    let strings = |value: &serde_json::Value| -> HashMap<String, String> {
+        value
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .filter(|(_, value)| !value.is_empty())
+            .collect()
+    };
+    let fields = strings(&message.decoded.fields);
+    let decoded = match message.get_manufacturer().as_str() {
+        "queclink" => Decoded::Queclink(QueclinkDecoded { fields }),
+        _ => Decoded::Suntech(SuntechDecoded { fields }),
+    };
+    KafkaMessage {
+        uuid: message.uuid.clone(),
+        data: strings(&json["data"]),
+        metadata: Some(Metadata {
+            worker_id: message.metadata.worker_id as u32,
+            received_epoch: message.metadata.received_epoch as u64,
+            decoded_epoch: message.metadata.decoded_epoch as u64,
+            bytes: message.metadata.bytes as u32,
+            client_ip: message.metadata.client_ip.clone(),
+            client_port: message.metadata.client_port as u32,
+        }),
+        raw: message.raw.clone(),
+        schema_version: 1,
+        decoded: Some(decoded),
+    }
+    .encode_to_vec()
+}
+
+fn to_record(message: &DeviceMessage, sanitization: &SanitizationConfig) -> CommunicationRecord {
+    let position = NormalizedPosition::from_device_message(message);
+    CommunicationRecord::from_position(&position, sanitization).unwrap()
+}
+
+/// Lote de registros de dispositivos distintos, como el de un flush
+fn batch(message: &DeviceMessage, size: usize) -> Vec<CommunicationRecord> {
+    let sanitization = SanitizationConfig::default();
+    (0..size)
+        .map(|index| {
+            let mut message = message.clone();
+            message.uuid = format!("{}-{}", message.uuid, index);
+            message.data.device_id = format!("{}{:06}", message.data.device_id, index);
+            to_record(&message, &sanitization)
+        })
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let json_decoder = RawDecoder::new(PayloadFormat::Json);
+    let protobuf_decoder = RawDecoder::new(PayloadFormat::Protobuf);
+    let raw_decoder = RawDecoder::new(PayloadFormat::Raw);
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1));
+    for name in FIXTURES {
+        let json = fixture(name);
+        let message = DeviceMessage::from_json(&json).unwrap();
+        let protobuf = to_protobuf(&message);
+
+        for (format, decoder, payload) in [
+            ("json", &json_decoder, json.clone()),
+            ("protobuf", &protobuf_decoder, protobuf),
+            ("raw", &raw_decoder, message.raw.clone().into_bytes()),
+        ] {
+            group.bench_function(format!("{}/{}", format, name), |b| {
+                b.iter(|| {
+                    futures::executor::block_on(KafkaConsumerService::decode_payload(
+                        None,
+                        None,
+                        decoder,
+                        black_box(&payload),
+                    ))
+                    .unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn convert(c: &mut Criterion) {
+    let sanitization = SanitizationConfig::default();
+
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Elements(1));
+    for name in FIXTURES {
+        let message = DeviceMessage::from_json(&fixture(name)).unwrap();
+        group.bench_function(*name, |b| {
+            b.iter(|| to_record(black_box(&message), &sanitization))
+        });
+    }
+    group.finish();
+}
+
+fn build_queries(c: &mut Criterion) {
+    let message = DeviceMessage::from_json(&fixture("queclink_gtfri")).unwrap();
+
+    let mut group = c.benchmark_group("insert_query");
+    for &size in BATCH_SIZES {
+        let records = batch(&message, size);
+        group.throughput(Throughput::Elements(size as u64));
+        for (mode_name, mode) in [
+            ("values", InsertMode::Values),
+            ("unnest", InsertMode::Unnest),
+        ] {
+            group.bench_function(format!("history/{}/{}", mode_name, size), |b| {
+                b.iter(|| {
+                    DatabaseService::history_insert_query(
+                        "communications_queclink",
+                        black_box(&records),
+                        "uuid",
+                        mode,
+                    )
+                })
+            });
+            group.bench_function(format!("current_state/{}/{}", mode_name, size), |b| {
+                b.iter(|| {
+                    DatabaseService::current_state_upsert_query(
+                        "communications_current_state",
+                        black_box(&records),
+                        Some(CurrentStateOrder::GpsEpoch),
+                        mode,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, convert, build_queries);
+criterion_main!(benches);
@@ -0,0 +1,202 @@
+//! Throughput de extremo a extremo: arma el procesador con la configuración del
+//! consumer (`DB_*`, `PROCESSING_*`, `.env`), le entrega un lote de mensajes
+//! sintéticos ya decodificados y mide hasta que todos quedan escritos en
+//! PostgreSQL. Escribe en copias de las tablas con sufijo (`_bench` por defecto),
+//! que se crean vacías y se borran al terminar.
+//!
+//! Sin `DB_HOST` no hace nada, así `cargo bench` no necesita una base de datos.
+//!
+//! ```bash
+//! DB_HOST=localhost DB_DATABASE=siscom DB_USERNAME=postgres DB_PASSWORD=postgres \
+//!   cargo bench --bench throughput -- --messages 200000 --devices 5000
+//! ```
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tracking_consumer_rust::config::AppConfig;
+use tracking_consumer_rust::models::{DeviceMessage, Manufacturer};
+use tracking_consumer_rust::services::{
+    Backpressure, DatabaseService, EnricherChain, MessageProcessor,
+};
+
+/// Benchmark de extremo a extremo contra PostgreSQL
+#[derive(Debug, Parser)]
+struct Args {
+    /// Mensajes a procesar
+    #[arg(long, default_value_t = 100_000)]
+    messages: usize,
+
+    /// Dispositivos distintos entre los que se reparten los mensajes
+    #[arg(long, default_value_t = 1_000)]
+    devices: usize,
+
+    /// Sufijo de las tablas de prueba
+    #[arg(long, default_value = "_bench")]
+    table_suffix: String,
+
+    /// Conserva las tablas de prueba al terminar
+    #[arg(long)]
+    keep_tables: bool,
+
+    // `cargo bench` pasa `--bench` a los benchmarks sin harness
+    #[arg(long, hide = true)]
+    bench: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = AppConfig::load()?;
+    if std::env::var("DB_HOST").is_err() {
+        eprintln!("⏭️ throughput: sin DB_HOST, benchmark omitido");
+        return Ok(());
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.processing.worker_threads)
+        .enable_all()
+        .build()?
+        .block_on(run(&args, &config))
+}
+
+async fn run(args: &Args, config: &AppConfig) -> Result<()> {
+    let urls = config.database_urls();
+    let pool = sqlx::PgPool::connect(&urls[0]).await?;
+    let tables = bench_tables(config);
+    for table in &tables {
+        let copy = format!("{}{}", table, args.table_suffix);
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (LIKE {} INCLUDING ALL)",
+            copy, table
+        ))
+        .execute(&pool)
+        .await
+        .with_context(|| format!("creando {} (¿migraciones aplicadas?)", copy))?;
+        sqlx::query(&format!("TRUNCATE {}", copy))
+            .execute(&pool)
+            .await?;
+    }
+
+    let database = Arc::new(
+        DatabaseService::new(
+            &urls,
+            config.database.max_connections,
+            config.processing.batch_processing_size,
+        )
+        .await?
+        .with_retry(config.database.retry.clone())
+        .with_tables(config.database.tables.clone())
+        .with_current_state_mode(config.database.current_state_mode)
+        .with_current_state_order(config.database.current_state_order)
+        .with_insert_mode(config.database.insert_mode)
+        .with_table_suffix(&args.table_suffix),
+    );
+    let processor = MessageProcessor::new(
+        database,
+        config.processing.batch_processing_size,
+        config.database.flush_interval_ms,
+        Backpressure::new(args.messages.max(1)),
+        config.processing.sanitization.clone(),
+    )
+    .with_lanes(config.processing.max_parallel_devices)
+    .with_enrichers(EnricherChain::from_config(&config.processing.enrichment)?);
+
+    let messages = synthetic_messages(args.messages, args.devices.max(1))?;
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    for message in messages {
+        tx.send(message)?;
+    }
+    drop(tx);
+
+    println!(
+        "throughput: {} mensajes, {} dispositivos, lotes de {}, {} carriles, insert {:?}",
+        args.messages,
+        args.devices,
+        config.processing.batch_processing_size,
+        config.processing.max_parallel_devices,
+        config.database.insert_mode
+    );
+    // Con el canal cerrado el procesador termina tras escribir el último lote
+    let started = Instant::now();
+    processor.start_processing(rx).await?;
+    let elapsed = started.elapsed();
+
+    let mut stored = 0i64;
+    for manufacturer in Manufacturer::all() {
+        let table = format!(
+            "{}{}",
+            config.database.tables.history_table(manufacturer.table()),
+            args.table_suffix
+        );
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT count(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await?;
+        stored += count;
+    }
+    println!(
+        "throughput: {} filas en {:.2?} ({:.0} mensajes/s)",
+        stored,
+        elapsed,
+        args.messages as f64 / elapsed.as_secs_f64()
+    );
+
+    if !args.keep_tables {
+        for table in &tables {
+            sqlx::query(&format!("DROP TABLE {}{}", table, args.table_suffix))
+                .execute(&pool)
+                .await?;
+        }
+    }
+    if stored != args.messages as i64 {
+        anyhow::bail!(
+            "se esperaban {} filas y se guardaron {}",
+            args.messages,
+            stored
+        );
+    }
+    Ok(())
+}
+
+/// Tablas en las que escribe el procesador
+fn bench_tables(config: &AppConfig) -> Vec<String> {
+    let tables = &config.database.tables;
+    Manufacturer::all()
+        .map(|manufacturer| tables.history_table(manufacturer.table()))
+        .chain([tables.current_state_table(), tables.rejected_table()])
+        .collect()
+}
+
+/// Mensajes de los fixtures con uuid, dispositivo y hora propios, alternando
+/// fabricantes; cada dispositivo avanza en el tiempo para que el estado actual se
+/// actualice en cada lote
+fn synthetic_messages(count: usize, devices: usize) -> Result<Vec<DeviceMessage>> {
+    let fixtures = ["suntech_stt", "queclink_gtfri"]
+        .iter()
+        .map(|name| {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/messages")
+                .join(format!("{}.json", name));
+            DeviceMessage::from_json(&std::fs::read(path)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let start = chrono::Utc::now().timestamp() - (count / devices) as i64;
+
+    Ok((0..count)
+        .map(|index| {
+            let device = index % devices;
+            let mut message = fixtures[device % fixtures.len()].clone();
+            let epoch = start + (index / devices) as i64;
+            message.uuid = uuid::Uuid::new_v4().to_string();
+            message.data.device_id = format!("{}{:06}", message.data.device_id, device);
+            message.data.gps_epoch = epoch.to_string();
+            message.data.gps_datetime = chrono::DateTime::from_timestamp(epoch, 0)
+                .unwrap_or_default()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+            message
+        })
+        .collect())
+}
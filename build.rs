@@ -4,9 +4,18 @@ fn main() -> Result<()> {
     // Set CMAKE_ARGS for librdkafka SASL support
     println!("cargo:rustc-env=CMAKE_ARGS=-DWITH_SASL=ON -DWITH_SSL=ON");
 
+    // Las migraciones se embeben en el binario con sqlx::migrate!
+    println!("cargo:rerun-if-changed=migrations");
+
     // Compilar el archivo protobuf y generar en src/
     prost_build::Config::new()
         .out_dir("src/")
         .compile_protos(&["siscom.proto"], &["."])?;
+
+    // Servicio gRPC de administración (solo el servidor)
+    tonic_build::configure()
+        .build_client(false)
+        .out_dir("src/")
+        .compile(&["siscom_admin.proto"], &["."])?;
     Ok(())
 }
@@ -0,0 +1,21 @@
+-- Crear tabla communications_rejected para registros que no pudieron insertarse
+CREATE TABLE IF NOT EXISTS communications_rejected (
+    id BIGSERIAL PRIMARY KEY,
+    uuid VARCHAR NOT NULL,
+    device_id VARCHAR NOT NULL,
+    target_table VARCHAR NOT NULL,
+    error_message TEXT NOT NULL,
+    raw_message TEXT,
+    record JSONB,
+    rejected_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+-- Índices para optimizar consultas frecuentes
+CREATE INDEX IF NOT EXISTS idx_communications_rejected_device_id ON communications_rejected(device_id);
+CREATE INDEX IF NOT EXISTS idx_communications_rejected_rejected_at ON communications_rejected(rejected_at);
+
+-- Comentarios de la tabla
+COMMENT ON TABLE communications_rejected IS 'Registros rechazados por PostgreSQL al insertar un lote';
+COMMENT ON COLUMN communications_rejected.target_table IS 'Tabla en la que se intentó insertar el registro';
+COMMENT ON COLUMN communications_rejected.error_message IS 'Error devuelto por PostgreSQL';
+COMMENT ON COLUMN communications_rejected.record IS 'Registro completo serializado como JSON';
@@ -0,0 +1,56 @@
+-- Crear tabla communications_current_state: última comunicación por dispositivo y tipo de mensaje
+CREATE TABLE IF NOT EXISTS communications_current_state (
+    id BIGSERIAL PRIMARY KEY,
+    uuid VARCHAR NOT NULL,
+    device_id VARCHAR NOT NULL,
+    backup_battery_voltage NUMERIC,
+    backup_battery_percent NUMERIC,
+    cell_id VARCHAR,
+    course NUMERIC,
+    delivery_type VARCHAR,
+    engine_status VARCHAR,
+    firmware VARCHAR,
+    fix_status VARCHAR,
+    gps_datetime TIMESTAMP WITHOUT TIME ZONE,
+    gps_epoch BIGINT,
+    idle_time INTEGER,
+    lac VARCHAR,
+    latitude NUMERIC(10, 7),
+    longitude NUMERIC(10, 7),
+    main_battery_voltage NUMERIC,
+    mcc VARCHAR,
+    mnc VARCHAR,
+    model VARCHAR,
+    msg_class VARCHAR,
+    msg_counter INTEGER,
+    alert_type VARCHAR,
+    network_status VARCHAR,
+    odometer BIGINT,
+    rx_lvl INTEGER,
+    satellites INTEGER,
+    speed NUMERIC,
+    speed_time INTEGER,
+    total_distance BIGINT,
+    trip_distance BIGINT,
+    trip_hourmeter INTEGER,
+    bytes_count INTEGER,
+    client_ip VARCHAR,
+    client_port INTEGER,
+    decoded_epoch BIGINT,
+    received_epoch BIGINT,
+    raw_message TEXT,
+    received_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW(),
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+-- Clave del upsert: una fila por dispositivo y tipo de mensaje
+CREATE UNIQUE INDEX IF NOT EXISTS idx_communications_current_state_device_msg_class
+    ON communications_current_state(device_id, msg_class);
+
+CREATE INDEX IF NOT EXISTS idx_communications_current_state_gps_datetime ON communications_current_state(gps_datetime);
+
+-- Comentarios de la tabla
+COMMENT ON TABLE communications_current_state IS 'Último estado conocido de cada dispositivo por tipo de mensaje';
+COMMENT ON COLUMN communications_current_state.device_id IS 'ID del dispositivo que envió el mensaje';
+COMMENT ON COLUMN communications_current_state.msg_class IS 'Tipo de mensaje (STATUS, ALERT, ...)';
+COMMENT ON COLUMN communications_current_state.gps_datetime IS 'Fecha y hora del GPS de la última comunicación';
@@ -0,0 +1,25 @@
+-- no-transaction
+-- Deduplicación por UUID del mensaje: redeliveries y replays no deben crear filas repetidas.
+-- Elimina los duplicados existentes conservando la fila más antigua, por rangos de id y
+-- confirmando cada rango, para no bloquear las escrituras sobre el histórico. El índice
+-- único se crea después, sin bloquear, en 019 y 020.
+DO $$
+DECLARE
+    history TEXT;
+    first_id BIGINT;
+    last_id BIGINT;
+    batch CONSTANT BIGINT := 50000;
+BEGIN
+    FOREACH history IN ARRAY ARRAY['communications_suntech', 'communications_queclink'] LOOP
+        EXECUTE format('SELECT min(id), max(id) FROM %I', history) INTO first_id, last_id;
+        WHILE first_id <= last_id LOOP
+            EXECUTE format(
+                'DELETE FROM %I a USING %I b
+                 WHERE a.uuid = b.uuid AND a.id > b.id AND a.id >= $1 AND a.id < $2',
+                history, history
+            ) USING first_id, first_id + batch;
+            COMMIT;
+            first_id := first_id + batch;
+        END LOOP;
+    END LOOP;
+END $$;
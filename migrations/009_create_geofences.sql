@@ -0,0 +1,38 @@
+-- Geocercas por dispositivo o tenant (GEOFENCE_SOURCE=database)
+CREATE TABLE IF NOT EXISTS geofences (
+    id VARCHAR PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    device_id VARCHAR,
+    tenant VARCHAR,
+    shape JSONB NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_geofences_device_id ON geofences(device_id);
+CREATE INDEX IF NOT EXISTS idx_geofences_tenant ON geofences(tenant);
+
+COMMENT ON TABLE geofences IS 'Geocercas evaluadas sobre cada posición';
+COMMENT ON COLUMN geofences.device_id IS 'Dispositivo al que aplica (NULL = todos los del tenant)';
+COMMENT ON COLUMN geofences.tenant IS 'Tenant al que aplica (NULL con device_id NULL = todos los dispositivos)';
+COMMENT ON COLUMN geofences.shape IS 'Círculo {"type":"circle","latitude","longitude","radius_m"} o polígono {"type":"polygon","points":[[lat,lon],...]}';
+
+-- Eventos de entrada/salida generados por el consumidor
+CREATE TABLE IF NOT EXISTS geofence_events (
+    id BIGSERIAL PRIMARY KEY,
+    geofence_id VARCHAR NOT NULL,
+    device_id VARCHAR NOT NULL,
+    tenant VARCHAR,
+    event VARCHAR(5) NOT NULL,
+    uuid VARCHAR NOT NULL,
+    latitude NUMERIC(10, 7) NOT NULL,
+    longitude NUMERIC(10, 7) NOT NULL,
+    gps_datetime TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_geofence_events_device_geofence ON geofence_events(device_id, geofence_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_geofence_events_created_at ON geofence_events(created_at);
+
+COMMENT ON TABLE geofence_events IS 'Entradas y salidas de geocercas';
+COMMENT ON COLUMN geofence_events.event IS 'enter o exit';
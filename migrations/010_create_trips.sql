@@ -0,0 +1,28 @@
+-- Viajes detectados por el consumidor a partir de la ignición y la velocidad
+CREATE TABLE IF NOT EXISTS trips (
+    id VARCHAR PRIMARY KEY,
+    device_id VARCHAR NOT NULL,
+    tenant VARCHAR,
+    start_time TIMESTAMP WITHOUT TIME ZONE,
+    start_epoch BIGINT NOT NULL,
+    start_latitude NUMERIC(10, 7) NOT NULL,
+    start_longitude NUMERIC(10, 7) NOT NULL,
+    end_uuid VARCHAR NOT NULL,
+    end_time TIMESTAMP WITHOUT TIME ZONE,
+    end_epoch BIGINT NOT NULL,
+    end_latitude NUMERIC(10, 7) NOT NULL,
+    end_longitude NUMERIC(10, 7) NOT NULL,
+    distance_m DOUBLE PRECISION NOT NULL DEFAULT 0,
+    duration_secs BIGINT NOT NULL DEFAULT 0,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_trips_device_start ON trips(device_id, start_epoch DESC);
+CREATE INDEX IF NOT EXISTS idx_trips_open ON trips(device_id) WHERE NOT closed;
+
+COMMENT ON TABLE trips IS 'Viajes por dispositivo: de la primera posición en movimiento al apagado o a una detención prolongada';
+COMMENT ON COLUMN trips.id IS 'UUID del mensaje que abrió el viaje';
+COMMENT ON COLUMN trips.end_uuid IS 'UUID del mensaje que cerró el viaje (o de la última posición si sigue abierto)';
+COMMENT ON COLUMN trips.closed IS 'FALSE mientras el viaje sigue abierto';
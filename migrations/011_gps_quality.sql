@@ -0,0 +1,41 @@
+-- Calidad del fix GPS y tabla de cuarentena (PROCESSING_GPS_QUALITY_POLICY)
+
+ALTER TABLE communications_suntech
+ADD COLUMN IF NOT EXISTS quality_score SMALLINT;
+
+ALTER TABLE communications_queclink
+ADD COLUMN IF NOT EXISTS quality_score SMALLINT;
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT FROM information_schema.tables
+        WHERE table_name = 'communications_current_state'
+          AND table_schema = current_schema()
+    ) THEN
+        ALTER TABLE communications_current_state
+        ADD COLUMN IF NOT EXISTS quality_score SMALLINT;
+    END IF;
+END $$;
+
+COMMENT ON COLUMN communications_suntech.quality_score IS 'Calidad del fix GPS de 0 a 100 (NULL = no evaluada)';
+COMMENT ON COLUMN communications_queclink.quality_score IS 'Calidad del fix GPS de 0 a 100 (NULL = no evaluada)';
+
+-- Posiciones apartadas por baja calidad con la política quarantine
+CREATE TABLE IF NOT EXISTS communications_quarantine (
+    id BIGSERIAL PRIMARY KEY,
+    uuid VARCHAR NOT NULL,
+    device_id VARCHAR NOT NULL,
+    quality_score SMALLINT,
+    reasons TEXT[] NOT NULL,
+    raw_message TEXT,
+    record JSONB,
+    quarantined_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_communications_quarantine_device_id ON communications_quarantine(device_id);
+CREATE INDEX IF NOT EXISTS idx_communications_quarantine_quarantined_at ON communications_quarantine(quarantined_at);
+
+COMMENT ON TABLE communications_quarantine IS 'Posiciones con calidad GPS por debajo del mínimo, fuera del histórico';
+COMMENT ON COLUMN communications_quarantine.reasons IS 'null_island, out_of_range, impossible_jump, low_satellites';
+COMMENT ON COLUMN communications_quarantine.record IS 'Registro completo serializado como JSON';
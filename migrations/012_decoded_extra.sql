@@ -0,0 +1,22 @@
+-- Campos propios del fabricante (DecodedData) que no tienen columna
+
+ALTER TABLE communications_suntech
+ADD COLUMN IF NOT EXISTS decoded_extra JSONB;
+
+ALTER TABLE communications_queclink
+ADD COLUMN IF NOT EXISTS decoded_extra JSONB;
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT FROM information_schema.tables
+        WHERE table_name = 'communications_current_state'
+          AND table_schema = current_schema()
+    ) THEN
+        ALTER TABLE communications_current_state
+        ADD COLUMN IF NOT EXISTS decoded_extra JSONB;
+    END IF;
+END $$;
+
+COMMENT ON COLUMN communications_suntech.decoded_extra IS 'Campos decodificados no vacíos del fabricante (AXIS_X, IN_STATE, ...)';
+COMMENT ON COLUMN communications_queclink.decoded_extra IS 'Campos decodificados no vacíos del fabricante (PROTOCOL_VERSION, ...)';
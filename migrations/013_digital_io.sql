@@ -0,0 +1,36 @@
+-- Señales digitales decodificadas de los campos de bits (enriquecedor io_state)
+
+ALTER TABLE communications_suntech
+ADD COLUMN IF NOT EXISTS ignition BOOLEAN,
+ADD COLUMN IF NOT EXISTS panic_button BOOLEAN,
+ADD COLUMN IF NOT EXISTS door_sensor BOOLEAN,
+ADD COLUMN IF NOT EXISTS relay_output BOOLEAN;
+
+ALTER TABLE communications_queclink
+ADD COLUMN IF NOT EXISTS ignition BOOLEAN,
+ADD COLUMN IF NOT EXISTS panic_button BOOLEAN,
+ADD COLUMN IF NOT EXISTS door_sensor BOOLEAN,
+ADD COLUMN IF NOT EXISTS relay_output BOOLEAN;
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT FROM information_schema.tables
+        WHERE table_name = 'communications_current_state'
+          AND table_schema = current_schema()
+    ) THEN
+        ALTER TABLE communications_current_state
+        ADD COLUMN IF NOT EXISTS ignition BOOLEAN,
+        ADD COLUMN IF NOT EXISTS panic_button BOOLEAN,
+        ADD COLUMN IF NOT EXISTS door_sensor BOOLEAN,
+        ADD COLUMN IF NOT EXISTS relay_output BOOLEAN;
+
+        COMMENT ON COLUMN communications_current_state.ignition IS 'Ignición según el mapa de bits del modelo (NULL = sin mapeo)';
+        COMMENT ON COLUMN communications_current_state.panic_button IS 'Botón de pánico presionado';
+        COMMENT ON COLUMN communications_current_state.door_sensor IS 'Sensor de puerta activo (puerta abierta)';
+        COMMENT ON COLUMN communications_current_state.relay_output IS 'Salida de relé (corte de motor) activa';
+    END IF;
+END $$;
+
+COMMENT ON COLUMN communications_suntech.ignition IS 'Ignición según el mapa de bits del modelo (NULL = sin mapeo)';
+COMMENT ON COLUMN communications_queclink.ignition IS 'Ignición según el mapa de bits del modelo (NULL = sin mapeo)';
@@ -0,0 +1,21 @@
+-- Inicios y cierres de sesión de conductores (iButton, RFID) generados por el consumidor
+CREATE TABLE IF NOT EXISTS driver_events (
+    id BIGSERIAL PRIMARY KEY,
+    device_id VARCHAR NOT NULL,
+    tenant VARCHAR,
+    driver_id VARCHAR NOT NULL,
+    event VARCHAR(6) NOT NULL,
+    uuid VARCHAR NOT NULL,
+    latitude NUMERIC(10, 7),
+    longitude NUMERIC(10, 7),
+    gps_datetime TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_driver_events_device_id ON driver_events(device_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_driver_events_driver_id ON driver_events(driver_id, gps_datetime);
+CREATE INDEX IF NOT EXISTS idx_driver_events_created_at ON driver_events(created_at);
+
+COMMENT ON TABLE driver_events IS 'Sesiones de conductor por dispositivo a partir de los reportes de identificación';
+COMMENT ON COLUMN driver_events.driver_id IS 'Identificación del conductor (DRIVER_ID de los datos decodificados)';
+COMMENT ON COLUMN driver_events.event IS 'login o logout';
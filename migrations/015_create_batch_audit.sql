@@ -0,0 +1,26 @@
+-- Auditoría de las escrituras de lotes (DB_AUDIT_ENABLED): una fila por lote y tabla de histórico
+CREATE TABLE IF NOT EXISTS batch_audit (
+    id BIGSERIAL PRIMARY KEY,
+    batch_id UUID NOT NULL,
+    source VARCHAR NOT NULL,
+    target_table VARCHAR NOT NULL,
+    record_count INTEGER NOT NULL,
+    persisted INTEGER NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    outcome VARCHAR(9) NOT NULL,
+    retries INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    uuids TEXT[] NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_batch_audit_batch_id ON batch_audit(batch_id);
+CREATE INDEX IF NOT EXISTS idx_batch_audit_created_at ON batch_audit(created_at);
+CREATE INDEX IF NOT EXISTS idx_batch_audit_uuids ON batch_audit USING GIN (uuids);
+
+COMMENT ON TABLE batch_audit IS 'Escrituras de lotes en el histórico, para demostrar cuándo se guardó cada mensaje';
+COMMENT ON COLUMN batch_audit.source IS 'primary, nombre del destino de fan-out, tenant o replay';
+COMMENT ON COLUMN batch_audit.persisted IS 'Filas nuevas en el histórico; las repetidas (uuid existente) y las rechazadas no cuentan';
+COMMENT ON COLUMN batch_audit.outcome IS 'persisted o failed';
+COMMENT ON COLUMN batch_audit.retries IS 'Reintentos por errores transitorios antes del resultado';
+COMMENT ON COLUMN batch_audit.uuids IS 'UUID de todos los mensajes del lote';
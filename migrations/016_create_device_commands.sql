@@ -0,0 +1,23 @@
+-- Comandos hacia los equipos (DEVICE_COMMAND_TOPIC) y el estado de su entrega al broker MQTT
+CREATE TABLE IF NOT EXISTS device_commands (
+    id UUID PRIMARY KEY,
+    device_id VARCHAR NOT NULL,
+    tenant VARCHAR,
+    command TEXT NOT NULL,
+    source VARCHAR(5) NOT NULL,
+    status VARCHAR(7) NOT NULL DEFAULT 'pending',
+    error TEXT,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW(),
+    sent_at TIMESTAMP WITHOUT TIME ZONE,
+    updated_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_device_commands_device_id ON device_commands(device_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_device_commands_status ON device_commands(status) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_device_commands_created_at ON device_commands(created_at);
+
+COMMENT ON TABLE device_commands IS 'Comandos recibidos por Kafka o la API de administración y publicados en el tópico MQTT de bajada de cada equipo';
+COMMENT ON COLUMN device_commands.command IS 'Trama del comando tal como se envía al equipo, p. ej. AT+GTRTO=...$';
+COMMENT ON COLUMN device_commands.source IS 'kafka o api';
+COMMENT ON COLUMN device_commands.status IS 'pending, sent (confirmado por el broker MQTT) o failed';
+COMMENT ON COLUMN device_commands.error IS 'Motivo del fallo de publicación';
@@ -0,0 +1,24 @@
+-- Cortes y restablecimientos de alimentación y avisos de batería de respaldo baja generados por el consumidor
+CREATE TABLE IF NOT EXISTS power_events (
+    id BIGSERIAL PRIMARY KEY,
+    device_id VARCHAR NOT NULL,
+    tenant VARCHAR,
+    model VARCHAR,
+    event VARCHAR(18) NOT NULL,
+    voltage NUMERIC(6, 2) NOT NULL,
+    threshold NUMERIC(6, 2) NOT NULL,
+    uuid VARCHAR NOT NULL,
+    latitude NUMERIC(10, 7),
+    longitude NUMERIC(10, 7),
+    gps_datetime TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_power_events_device_id ON power_events(device_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_power_events_event ON power_events(event, gps_datetime);
+CREATE INDEX IF NOT EXISTS idx_power_events_created_at ON power_events(created_at);
+
+COMMENT ON TABLE power_events IS 'Eventos de alimentación por dispositivo a partir de los voltajes de posiciones consecutivas';
+COMMENT ON COLUMN power_events.event IS 'power_lost, power_restored o low_backup_battery';
+COMMENT ON COLUMN power_events.voltage IS 'Voltaje principal (power_*) o de la batería de respaldo (low_backup_battery) que produjo el evento';
+COMMENT ON COLUMN power_events.threshold IS 'Umbral aplicado, general o del modelo';
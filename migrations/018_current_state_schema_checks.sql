@@ -0,0 +1,27 @@
+-- Cambios de 003, 004 y 005 sobre communications_current_state, verificando la tabla
+-- solo en el esquema de las migraciones (DB_SCHEMA): esas migraciones ya publicadas
+-- la buscan en cualquier esquema y no se pueden editar
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT FROM information_schema.columns
+        WHERE table_name = 'communications_current_state'
+          AND table_schema = current_schema()
+          AND column_name = 'client_ip'
+          AND data_type <> 'character varying'
+    ) THEN
+        ALTER TABLE communications_current_state
+        ALTER COLUMN client_ip TYPE VARCHAR USING client_ip::text;
+    END IF;
+
+    IF EXISTS (
+        SELECT FROM information_schema.tables
+        WHERE table_name = 'communications_current_state'
+          AND table_schema = current_schema()
+    ) THEN
+        ALTER TABLE communications_current_state
+        ADD COLUMN IF NOT EXISTS alert_type VARCHAR,
+        ADD COLUMN IF NOT EXISTS backup_battery_percent NUMERIC;
+    END IF;
+END $$;
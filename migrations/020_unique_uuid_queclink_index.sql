@@ -0,0 +1,5 @@
+-- no-transaction
+-- Índice único por UUID (requerido por ON CONFLICT (uuid)), creado sin bloquear las escrituras.
+-- Si falla (p. ej. por un duplicado insertado tras 008) queda un índice inválido: eliminarlo
+-- con DROP INDEX CONCURRENTLY y volver a aplicar las migraciones.
+CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS idx_communications_queclink_uuid_unique ON communications_queclink(uuid);
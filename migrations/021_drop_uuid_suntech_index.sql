@@ -0,0 +1,3 @@
+-- no-transaction
+-- El índice simple por UUID queda cubierto por el único de 019
+DROP INDEX CONCURRENTLY IF EXISTS idx_communications_suntech_uuid;
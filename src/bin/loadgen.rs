@@ -0,0 +1,747 @@
+//! Generador de carga: sintetiza mensajes Suntech/Queclink con recorridos
+//! realistas a una tasa y cantidad de dispositivos configurables, los publica en
+//! Kafka o MQTT e informa el throughput logrado. Sirve para dimensionar despliegues.
+//!
+//! ```text
+//! loadgen --target kafka --brokers localhost:9092 --rate 2000 --devices 5000 --duration 300
+//! loadgen --target mqtt --mqtt-url mqtt://localhost:1883 --topic 'devices/{device_id}'
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::ClientContext;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+
+/// Cada cuánto se envía la tanda de mensajes que corresponde a la tasa
+const TICK: Duration = Duration::from_millis(10);
+
+/// Tiempo máximo para entregar los mensajes pendientes al terminar
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Generador de carga para siscom-consumer
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Destino de los mensajes
+    #[arg(long, value_enum, default_value = "kafka")]
+    target: Target,
+
+    /// Formato: `json` (DeviceMessage, `BROKER_PAYLOAD_FORMAT=json`) o `raw` (trama ASCII)
+    #[arg(long, value_enum, default_value = "json")]
+    format: Format,
+
+    /// Fabricante de los dispositivos simulados
+    #[arg(long, value_enum, default_value = "mixed")]
+    manufacturer: ManufacturerArg,
+
+    /// Mensajes por segundo en total
+    #[arg(long, default_value_t = 1000.0)]
+    rate: f64,
+
+    /// Dispositivos simulados; cada uno reporta cada `devices / rate` segundos
+    #[arg(long, default_value_t = 1000)]
+    devices: usize,
+
+    /// Duración de la prueba en segundos (0 = hasta Ctrl-C o `--count`)
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+
+    /// Mensajes a enviar en total (0 = sin límite)
+    #[arg(long, default_value_t = 0)]
+    count: u64,
+
+    /// Segundos entre reportes de progreso
+    #[arg(long, default_value_t = 5)]
+    report_interval: u64,
+
+    /// Tópico de Kafka, o de MQTT con `{manufacturer}` y `{device_id}`
+    #[arg(long, default_value = "siscom-messages")]
+    topic: String,
+
+    /// Semilla de los recorridos, para repetir una prueba
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Servidores de Kafka
+    #[arg(long, default_value = "localhost:9092")]
+    brokers: String,
+
+    /// `PLAINTEXT`, `SSL`, `SASL_PLAINTEXT` o `SASL_SSL`
+    #[arg(long, default_value = "PLAINTEXT")]
+    security_protocol: String,
+
+    #[arg(long, default_value = "SCRAM-SHA-256")]
+    sasl_mechanism: String,
+
+    #[arg(long)]
+    sasl_username: Option<String>,
+
+    #[arg(long)]
+    sasl_password: Option<String>,
+
+    /// Propiedad adicional de librdkafka (`clave=valor`), repetible
+    #[arg(long = "kafka-config", value_name = "KEY=VALUE")]
+    kafka_config: Vec<String>,
+
+    /// Broker MQTT, `mqtt://host[:puerto]`
+    #[arg(long, default_value = "mqtt://localhost:1883")]
+    mqtt_url: String,
+
+    #[arg(long)]
+    mqtt_username: Option<String>,
+
+    #[arg(long)]
+    mqtt_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Target {
+    Kafka,
+    Mqtt,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ManufacturerArg {
+    Suntech,
+    Queclink,
+    /// Mitad y mitad
+    Mixed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manufacturer {
+    Suntech,
+    Queclink,
+}
+
+impl Manufacturer {
+    fn as_str(self) -> &'static str {
+        match self {
+            Manufacturer::Suntech => "suntech",
+            Manufacturer::Queclink => "queclink",
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.rate <= 0.0 || args.devices == 0 {
+        bail!("--rate y --devices deben ser mayores que 0");
+    }
+
+    let mut fleet = Fleet::new(&args);
+    let mut publisher = match args.target {
+        Target::Kafka => Publisher::Kafka(KafkaPublisher::new(&args)?),
+        Target::Mqtt => Publisher::Mqtt(MqttPublisher::connect(&args).await?),
+    };
+    println!(
+        "🚀 {} dispositivos, {} msg/s a {:?} ({:?}, {:?}); cada dispositivo reporta cada {:.1} s",
+        args.devices,
+        args.rate,
+        args.target,
+        args.format,
+        args.manufacturer,
+        args.devices as f64 / args.rate
+    );
+
+    let started = Instant::now();
+    let deadline = (args.duration > 0).then(|| started + Duration::from_secs(args.duration));
+    let report_every = Duration::from_secs(args.report_interval.max(1));
+    let mut next_report = started + report_every;
+    let mut last_report = (started, 0u64);
+    let mut sent = 0u64;
+    let mut ticker = tokio::time::interval(TICK);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut ctrl_c => break,
+        }
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            break;
+        }
+
+        // Mensajes que ya deberían haberse enviado a esta tasa
+        let mut due = (now.duration_since(started).as_secs_f64() * args.rate) as u64;
+        if args.count > 0 {
+            due = due.min(args.count);
+        }
+        while sent < due {
+            let message = fleet.next_message(args.format);
+            publisher.send(&message).await?;
+            sent += 1;
+        }
+        publisher.flush_pending().await?;
+
+        if now >= next_report {
+            let (at, at_sent) = last_report;
+            let rate = (sent - at_sent) as f64 / now.duration_since(at).as_secs_f64();
+            let (delivered, failed) = publisher.delivery();
+            println!(
+                "📊 {:>6.0} s - enviados: {}, {:.0} msg/s, entregados: {}, fallidos: {}",
+                now.duration_since(started).as_secs_f64(),
+                sent,
+                rate,
+                delivered,
+                failed
+            );
+            last_report = (now, sent);
+            next_report = now + report_every;
+        }
+        if args.count > 0 && sent >= args.count {
+            break;
+        }
+    }
+
+    let sending = started.elapsed();
+    publisher.finish().await?;
+    let elapsed = started.elapsed();
+    let (delivered, failed) = publisher.delivery();
+    println!(
+        "✅ {} mensajes enviados en {:.1} s ({:.0} msg/s); {} entregados en {:.1} s ({:.0} msg/s), {} fallidos",
+        sent,
+        sending.as_secs_f64(),
+        sent as f64 / sending.as_secs_f64(),
+        delivered,
+        elapsed.as_secs_f64(),
+        delivered as f64 / elapsed.as_secs_f64(),
+        failed
+    );
+    Ok(())
+}
+
+/// Mensaje listo para publicar
+struct Message {
+    manufacturer: Manufacturer,
+    device_id: String,
+    payload: Vec<u8>,
+}
+
+/// Dispositivos simulados que se recorren en orden
+struct Fleet {
+    devices: Vec<Device>,
+    next: usize,
+    rng: Rng,
+}
+
+/// Estado de un dispositivo: se mueve en línea recta a su velocidad y cambia de
+/// rumbo, velocidad e ignición de vez en cuando
+struct Device {
+    manufacturer: Manufacturer,
+    id: String,
+    lat: f64,
+    lon: f64,
+    speed: f64,
+    course: f64,
+    ignition: bool,
+    counter: u32,
+    odometer: f64,
+    last_report: Option<DateTime<Utc>>,
+}
+
+impl Fleet {
+    fn new(args: &Args) -> Self {
+        let mut rng = Rng(args.seed.max(1));
+        let devices = (0..args.devices)
+            .map(|index| {
+                let manufacturer = match args.manufacturer {
+                    ManufacturerArg::Suntech => Manufacturer::Suntech,
+                    ManufacturerArg::Queclink => Manufacturer::Queclink,
+                    ManufacturerArg::Mixed if index % 2 == 0 => Manufacturer::Suntech,
+                    ManufacturerArg::Mixed => Manufacturer::Queclink,
+                };
+                let id = match manufacturer {
+                    Manufacturer::Suntech => format!("9{:08}", index),
+                    Manufacturer::Queclink => format!("86{:013}", index),
+                };
+                // Alrededor de la Ciudad de México
+                Device {
+                    manufacturer,
+                    id,
+                    lat: 19.43 + rng.range(-0.3, 0.3),
+                    lon: -99.13 + rng.range(-0.3, 0.3),
+                    speed: rng.range(0.0, 80.0),
+                    course: rng.range(0.0, 360.0),
+                    ignition: rng.chance(0.8),
+                    counter: 0,
+                    odometer: rng.range(0.0, 500_000_000.0).floor(),
+                    last_report: None,
+                }
+            })
+            .collect();
+        Self {
+            devices,
+            next: 0,
+            rng,
+        }
+    }
+
+    fn next_message(&mut self, format: Format) -> Message {
+        let index = self.next;
+        self.next = (index + 1) % self.devices.len();
+        let device = &mut self.devices[index];
+        let now = Utc::now();
+        device.advance(now, &mut self.rng);
+
+        let raw = device.raw_frame(now);
+        let payload = match format {
+            Format::Raw => raw.into_bytes(),
+            Format::Json => device.json_message(now, raw).to_string().into_bytes(),
+        };
+        Message {
+            manufacturer: device.manufacturer,
+            device_id: device.id.clone(),
+            payload,
+        }
+    }
+}
+
+impl Device {
+    fn advance(&mut self, now: DateTime<Utc>, rng: &mut Rng) {
+        let seconds = self
+            .last_report
+            .map_or(0.0, |last| (now - last).num_milliseconds() as f64 / 1000.0);
+        self.last_report = Some(now);
+        self.counter = self.counter.wrapping_add(1);
+
+        if rng.chance(0.02) {
+            self.ignition = !self.ignition;
+        }
+        if !self.ignition {
+            self.speed = 0.0;
+            return;
+        }
+        if rng.chance(0.1) {
+            self.course = (self.course + rng.range(-45.0, 45.0)).rem_euclid(360.0);
+        }
+        self.speed = (self.speed + rng.range(-10.0, 10.0)).clamp(0.0, 110.0);
+
+        let km = self.speed * seconds / 3600.0;
+        let heading = self.course.to_radians();
+        self.lat += km * heading.cos() / 111.32;
+        self.lon += km * heading.sin() / (111.32 * self.lat.to_radians().cos());
+        self.odometer += km * 1000.0;
+    }
+
+    /// Trama como la emite el equipo
+    fn raw_frame(&self, now: DateTime<Utc>) -> String {
+        match self.manufacturer {
+            // ST300STT;id;modelo;firmware;fecha;hora;celda;lat;lon;vel;rumbo;sat;fix;
+            // odómetro;voltaje;entradas;tipo;contador;horómetro;respaldo;tiempo real
+            Manufacturer::Suntech => format!(
+                "ST300STT;{};04;1097B;{};{};0c1e02;{:+.6};{:+.6};{:.2};{:.2};{};1;{:.0};12.4;{}0000000;1;{};0;4.1;1",
+                self.id,
+                now.format("%Y%m%d"),
+                now.format("%H:%M:%S"),
+                self.lat,
+                self.lon,
+                self.speed,
+                self.course,
+                8 + self.counter % 5,
+                self.odometer,
+                if self.ignition { 1 } else { 0 },
+                self.counter % 10_000,
+            ),
+            // +RESP:GTFRI,protocolo,imei,nombre,,tipo,número,precisión,vel,rumbo,alt,
+            // lon,lat,fecha,mcc,mnc,lac,celda,00,kilometraje,...,fecha envío,contador$
+            Manufacturer::Queclink => format!(
+                "+RESP:GTFRI,060100,{},GV300,,10,1,1,{:.1},{:.0},2240.0,{:.6},{:.6},{},0334,0020,1A2B,3C4D,00,{:.1},,,,100,210100,,,,{},{:04X}$",
+                self.id,
+                self.speed,
+                self.course,
+                self.lon,
+                self.lat,
+                now.format("%Y%m%d%H%M%S"),
+                self.odometer / 1000.0,
+                now.format("%Y%m%d%H%M%S"),
+                self.counter % 0x10000,
+            ),
+        }
+    }
+
+    /// DeviceMessage como lo publica el decodificador
+    fn json_message(&self, now: DateTime<Utc>, raw: String) -> Value {
+        let latitude = format!("{:.6}", self.lat);
+        let longitude = format!("{:.6}", self.lon);
+        let speed = format!("{:.2}", self.speed);
+        let course = format!("{:.2}", self.course);
+        let (model, decoded_key, decoded) = match self.manufacturer {
+            Manufacturer::Suntech => (
+                "ST300",
+                "SuntechRaw",
+                json!({
+                    "HEADER": "ST300STT",
+                    "DEVICE_ID": self.id,
+                    "MODEL": "04",
+                    "FW": "1097B",
+                    "GPS_DATE": now.format("%Y%m%d").to_string(),
+                    "GPS_TIME": now.format("%H:%M:%S").to_string(),
+                    "LAT": latitude,
+                    "LON": longitude,
+                    "SPD": speed,
+                    "CRS": course,
+                    "FIX": "1",
+                    "IN_STATE": if self.ignition { "10000000" } else { "00000000" },
+                    "MSG_NUM": self.counter.to_string(),
+                }),
+            ),
+            Manufacturer::Queclink => (
+                "GV300",
+                "QueclinkRaw",
+                json!({
+                    "HEADER": "+RESP:GTFRI",
+                    "PROTOCOL_VERSION": "060100",
+                    "DEVICE_ID": self.id,
+                    "GPS_DATE_TIME": now.format("%Y%m%d%H%M%S").to_string(),
+                    "LAT": latitude,
+                    "LON": longitude,
+                    "SPD": speed,
+                    "CRS": course,
+                    "FIX": "1",
+                    "MSG_NUM": format!("{:04X}", self.counter % 0x10000),
+                }),
+            ),
+        };
+
+        json!({
+            "data": {
+                "DEVICE_ID": self.id,
+                "MODEL": model,
+                "MSG_CLASS": "STATUS",
+                "DELIVERY_TYPE": "REALTIME",
+                "GPS_DATETIME": now.format("%Y-%m-%d %H:%M:%S").to_string(),
+                "GPS_EPOCH": now.timestamp().to_string(),
+                "LATITUD": latitude,
+                "LONGITUD": longitude,
+                "SPEED": speed,
+                "COURSE": course,
+                "SATELLITES": (8 + self.counter % 5).to_string(),
+                "FIX_": "1",
+                "ENGINE_STATUS": if self.ignition { "ON" } else { "OFF" },
+                "MSG_COUNTER": self.counter.to_string(),
+                "MAIN_BATTERY_VOLTAGE": "12.4",
+                "ODOMETER": format!("{:.0}", self.odometer),
+            },
+            "decoded": { decoded_key: decoded },
+            "metadata": {
+                "BYTES": raw.len(),
+                "CLIENT_IP": "127.0.0.1",
+                "CLIENT_PORT": 0,
+                "DECODED_EPOCH": now.timestamp_millis(),
+                "RECEIVED_EPOCH": now.timestamp_millis(),
+                "WORKER_ID": 0,
+            },
+            "raw": raw,
+            "uuid": uuid::Uuid::new_v4().to_string(),
+        })
+    }
+}
+
+/// xorshift64*: suficiente para recorridos sintéticos y repetible con `--seed`
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
+
+enum Publisher {
+    Kafka(KafkaPublisher),
+    Mqtt(MqttPublisher),
+}
+
+impl Publisher {
+    async fn send(&mut self, message: &Message) -> Result<()> {
+        match self {
+            Publisher::Kafka(kafka) => kafka.send(message).await,
+            Publisher::Mqtt(mqtt) => mqtt.send(message).await,
+        }
+    }
+
+    /// Escribe lo que quedó en buffers propios; Kafka entrega en segundo plano
+    async fn flush_pending(&mut self) -> Result<()> {
+        match self {
+            Publisher::Kafka(_) => Ok(()),
+            Publisher::Mqtt(mqtt) => mqtt.flush().await,
+        }
+    }
+
+    /// Espera la entrega de los mensajes pendientes
+    async fn finish(&mut self) -> Result<()> {
+        match self {
+            Publisher::Kafka(kafka) => kafka.finish(),
+            Publisher::Mqtt(mqtt) => mqtt.disconnect().await,
+        }
+    }
+
+    /// Mensajes entregados y fallidos hasta ahora
+    fn delivery(&self) -> (u64, u64) {
+        match self {
+            Publisher::Kafka(kafka) => {
+                let counters = kafka.producer.context();
+                (
+                    counters.delivered.load(Ordering::Relaxed),
+                    counters.failed.load(Ordering::Relaxed),
+                )
+            }
+            // QoS 0: se cuenta como entregado lo escrito en el socket
+            Publisher::Mqtt(mqtt) => (mqtt.written, 0),
+        }
+    }
+}
+
+/// Cuenta las confirmaciones de entrega de librdkafka
+#[derive(Default)]
+struct DeliveryCounter {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    last_error: std::sync::Mutex<Option<String>>,
+}
+
+impl ClientContext for DeliveryCounter {}
+
+impl ProducerContext for DeliveryCounter {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        match result {
+            Ok(_) => {
+                self.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            Err((e, _)) => {
+                if self.failed.fetch_add(1, Ordering::Relaxed) == 0 {
+                    *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) =
+                        Some(e.to_string());
+                    eprintln!("❌ Error de entrega: {}", e);
+                }
+            }
+        }
+    }
+}
+
+struct KafkaPublisher {
+    producer: ThreadedProducer<DeliveryCounter>,
+    topic: String,
+}
+
+impl KafkaPublisher {
+    fn new(args: &Args) -> Result<Self> {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &args.brokers)
+            .set("security.protocol", &args.security_protocol)
+            .set("acks", "1")
+            .set("linger.ms", "5")
+            .set("batch.size", "65536")
+            .set("compression.type", "lz4")
+            .set("queue.buffering.max.kbytes", "102400")
+            .set("message.timeout.ms", "30000");
+        if args.security_protocol.starts_with("SASL") {
+            config.set("sasl.mechanism", &args.sasl_mechanism);
+            if let Some(username) = &args.sasl_username {
+                config.set("sasl.username", username);
+            }
+            if let Some(password) = &args.sasl_password {
+                config.set("sasl.password", password);
+            }
+        }
+        for entry in &args.kafka_config {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("--kafka-config '{}' no es clave=valor", entry))?;
+            config.set(key.trim(), value.trim());
+        }
+
+        let producer = config
+            .create_with_context(DeliveryCounter::default())
+            .context("No se pudo crear el productor de Kafka")?;
+        Ok(Self {
+            producer,
+            topic: args.topic.clone(),
+        })
+    }
+
+    /// Encola en librdkafka; con la cola interna llena espera a que se vacíe
+    async fn send(&self, message: &Message) -> Result<()> {
+        let mut record = BaseRecord::to(&self.topic)
+            .key(&message.device_id)
+            .payload(&message.payload);
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    record = returned;
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                Err((e, _)) => {
+                    self.producer
+                        .context()
+                        .failed
+                        .fetch_add(1, Ordering::Relaxed);
+                    eprintln!("❌ Error encolando en Kafka: {}", e);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        self.producer
+            .flush(FLUSH_TIMEOUT)
+            .context("Mensajes sin entregar al terminar")?;
+        if let Some(error) = self
+            .producer
+            .context()
+            .last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            eprintln!("❌ Primer error de entrega: {}", error);
+        }
+        Ok(())
+    }
+}
+
+/// Publicador MQTT 3.1.1 mínimo con QoS 0, suficiente para generar carga
+struct MqttPublisher {
+    stream: BufWriter<TcpStream>,
+    topic: String,
+    written: u64,
+    buffered: u64,
+}
+
+impl MqttPublisher {
+    async fn connect(args: &Args) -> Result<Self> {
+        let address = args
+            .mqtt_url
+            .strip_prefix("mqtt://")
+            .ok_or_else(|| anyhow!("--mqtt-url debe empezar con mqtt://"))?
+            .trim_end_matches('/');
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:1883", address)
+        };
+        let mut stream = TcpStream::connect(&address)
+            .await
+            .with_context(|| format!("No se pudo conectar a MQTT {}", address))?;
+        stream.set_nodelay(true)?;
+
+        // CONNECT: sesión limpia y sin keep-alive, la carga mantiene viva la conexión
+        let mut flags = 0x02;
+        let mut payload = Vec::new();
+        put_string(&mut payload, &format!("loadgen-{}", std::process::id()));
+        if let Some(username) = &args.mqtt_username {
+            flags |= 0x80;
+            put_string(&mut payload, username);
+        }
+        if let Some(password) = &args.mqtt_password {
+            flags |= 0x40;
+            put_string(&mut payload, password);
+        }
+        let mut body = Vec::new();
+        put_string(&mut body, "MQTT");
+        body.extend_from_slice(&[4, flags, 0, 0]);
+        body.extend_from_slice(&payload);
+        stream.write_all(&packet(0x10, &body)).await?;
+
+        let mut connack = [0u8; 4];
+        tokio::time::timeout(Duration::from_secs(10), stream.read_exact(&mut connack))
+            .await
+            .context("Sin CONNACK del broker MQTT")??;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            bail!("El broker MQTT rechazó la conexión (código {})", connack[3]);
+        }
+
+        Ok(Self {
+            stream: BufWriter::with_capacity(1 << 16, stream),
+            topic: args.topic.clone(),
+            written: 0,
+            buffered: 0,
+        })
+    }
+
+    async fn send(&mut self, message: &Message) -> Result<()> {
+        let topic = self
+            .topic
+            .replace("{manufacturer}", message.manufacturer.as_str())
+            .replace("{device_id}", &message.device_id);
+        let mut body = Vec::with_capacity(topic.len() + message.payload.len() + 2);
+        put_string(&mut body, &topic);
+        body.extend_from_slice(&message.payload);
+        self.stream.write_all(&packet(0x30, &body)).await?;
+        self.buffered += 1;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.stream.flush().await?;
+        self.written += self.buffered;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.flush().await?;
+        self.stream.write_all(&[0xE0, 0]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+/// Paquete MQTT: encabezado fijo con el largo restante en base 128
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+    let mut remaining = body.len();
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
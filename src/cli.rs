@@ -0,0 +1,68 @@
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Consumer de tracking GPS: Kafka → PostgreSQL
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Procesa sin escribir en la BD ni en Kafka e informa lo que se habría escrito
+    /// (equivale a `PIPELINE_DRY_RUN=true`)
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Reprocesa los mensajes de Kafka entre dos timestamps y termina
+    Replay {
+        /// Inicio del rango (RFC 3339, p. ej. 2024-05-01T00:00:00Z)
+        #[arg(long)]
+        from: DateTime<Utc>,
+
+        /// Fin del rango, exclusivo (RFC 3339)
+        #[arg(long)]
+        to: DateTime<Utc>,
+
+        /// Sufijo de las tablas destino (p. ej. `_replay` → communications_suntech_replay)
+        #[arg(long)]
+        table_suffix: Option<String>,
+
+        /// No escribe: compara el topic con la BD e informa los mensajes que faltan
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Reprocesa archivos de captura (`CAPTURE_DIR`) por el pipeline completo y termina
+    ReplayFile {
+        /// Archivos `.ndjson` a reproducir, en orden
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Multiplicador de velocidad: 1 respeta los tiempos originales, 10 va diez
+        /// veces más rápido y 0 no espera entre mensajes
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+
+        /// Sufijo de las tablas destino (p. ej. `_replay` → communications_suntech_replay)
+        #[arg(long)]
+        table_suffix: Option<String>,
+    },
+
+    /// Descifra valores de `raw_message` exportados de la BD (uno por línea) y los
+    /// escribe como archivo de captura para `replay-file`
+    DecryptRaw {
+        /// Archivo con los valores, p. ej. de `\copy (SELECT raw_message ...) TO ...`
+        input: PathBuf,
+
+        /// Archivo de captura `.ndjson` a crear
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// Aplica las migraciones de base de datos pendientes y termina
+    Migrate,
+}
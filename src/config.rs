@@ -1,13 +1,43 @@
 use anyhow::Result;
+use base64::Engine;
 use config::ConfigError;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::time::Duration;
+
+use crate::models::Manufacturer;
 
 /// Tipos de broker soportados
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum BrokerType {
     #[serde(rename = "kafka")]
     Kafka,
+    /// AMQP 0.9.1 (RabbitMQ): `BROKER_TOPIC` es la cola
+    #[serde(rename = "amqp")]
+    Amqp,
+}
+
+impl BrokerType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Kafka => "kafka",
+            Self::Amqp => "amqp",
+        }
+    }
+}
+
+/// Formato del payload de los mensajes del tópico
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// `KafkaMessage` protobuf producido por el decodificador
+    #[default]
+    Protobuf,
+    /// Trama ASCII del equipo tal como llega al listener TCP, sin decodificar
+    Raw,
+    /// `DeviceMessage` serializado en JSON, con `schema_version` opcional
+    Json,
 }
 
 /// Configuración unificada para el broker (Kafka)
@@ -17,6 +47,81 @@ pub struct BrokerConfig {
     pub host: String,
     pub topic: String,
     pub group_id: String,
+    pub kafka: KafkaConfig,
+    pub amqp: AmqpConfig,
+    pub schema_registry: Option<SchemaRegistryConfig>,
+    pub payload_format: PayloadFormat,
+    /// Tópico donde se publican las notificaciones (eventos de geocerca, etc.)
+    pub notifications_topic: Option<String>,
+}
+
+/// Configuración específica de Kafka: seguridad TLS y SASL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KafkaConfig {
+    pub security_protocol: Option<String>,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub sasl_oauthbearer_config: Option<String>,
+    pub ssl_ca_location: Option<String>,
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    pub ssl_key_password: Option<String>,
+}
+
+/// Configuración específica de AMQP 0.9.1: credenciales, enlace de la cola y prefetch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmqpConfig {
+    pub vhost: String,
+    pub username: String,
+    pub password: String,
+    /// Conexión con TLS (`amqps`)
+    pub tls: bool,
+    /// Exchange al que se enlaza la cola (None = la cola ya recibe los mensajes)
+    pub exchange: Option<String>,
+    /// Routing keys del enlace con el exchange
+    pub routing_keys: Vec<String>,
+    /// Mensajes entregados sin confirmar; como se confirman al guardarse en la BD,
+    /// también limita lo que está en vuelo en el pipeline
+    pub prefetch: u16,
+}
+
+impl Default for AmqpConfig {
+    fn default() -> Self {
+        Self {
+            vhost: "/".to_string(),
+            username: "guest".to_string(),
+            password: "guest".to_string(),
+            tls: false,
+            exchange: None,
+            routing_keys: Vec::new(),
+            prefetch: 1000,
+        }
+    }
+}
+
+/// Mecanismos SASL soportados por librdkafka sin dependencias externas
+const SUPPORTED_SASL_MECHANISMS: &[&str] =
+    &["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512", "OAUTHBEARER"];
+
+/// Tablas a las que se puede aplicar retención con DB_MAINTENANCE_RETENTION, además
+/// del histórico de cada fabricante (por su nombre)
+pub const MAINTENANCE_TABLES: &[&str] = &[
+    "current_state",
+    "rejected",
+    "geofence_events",
+    "quarantine",
+    "driver_events",
+    "device_commands",
+    "power_events",
+];
+
+/// Configuración opcional de Confluent Schema Registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaRegistryConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,10 +130,510 @@ pub struct AppConfig {
     pub database: DatabaseConfig,
     pub processing: ProcessingConfig,
     pub logging: LoggingConfig,
+    /// Archivo de mensajes crudos en S3/MinIO (None = desactivado)
+    pub archive: Option<ArchiveConfig>,
+    /// Captura de los payloads recibidos para `replay-file` (None = desactivada)
+    pub capture: Option<CaptureConfig>,
+    /// API HTTP de administración (None = desactivada)
+    pub admin: Option<AdminConfig>,
+    /// API gRPC de administración (None = desactivada)
+    pub grpc_admin: Option<AdminConfig>,
+    /// Evaluación de geocercas (None = desactivada)
+    pub geofence: Option<GeofenceConfig>,
+    /// Detección de viajes (None = desactivada)
+    pub trips: Option<TripConfig>,
+    /// Detección de conducción brusca y excesos de velocidad (None = desactivada)
+    pub driving_behavior: Option<DrivingBehaviorConfig>,
+    /// Identificación de conductores (None = desactivada)
+    pub driver_events: Option<DriverEventsConfig>,
+    /// Cortes de alimentación y batería de respaldo baja (None = desactivados)
+    pub power_events: Option<PowerEventsConfig>,
+    /// Detección de dispositivos desconectados (None = desactivada)
+    pub presence: Option<PresenceConfig>,
+    /// Exportación de trazas OpenTelemetry por OTLP (None = desactivada)
+    pub tracing: Option<TracingConfig>,
+    /// Reinicio de las tareas principales que terminan inesperadamente
+    pub supervisor: SupervisorConfig,
+    /// Espera de PostgreSQL, el broker y MQTT al arrancar
+    pub startup: StartupConfig,
+    /// Detección de etapas atascadas (None = desactivada)
+    pub watchdog: Option<WatchdogConfig>,
+    /// Publicación periódica del estado de la instancia (None = desactivada)
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Lease exclusivo de consumo para relevar instancias (None = desactivado)
+    pub handover: Option<HandoverConfig>,
+    /// Reparto de los dispositivos entre instancias (None = esta instancia procesa todos)
+    pub sharding: Option<ShardConfig>,
+    /// Vista en vivo de los mensajes en la API de administración (None = desactivada)
+    pub live_tail: Option<LiveTailConfig>,
+    /// Webhooks que reciben las notificaciones (None = desactivados)
+    pub webhooks: Option<WebhookConfig>,
+    /// Ingesta por HTTP como fuente adicional (None = desactivada)
+    pub ingest: Option<IngestConfig>,
+    /// Firma HMAC por tenant de los payloads del broker (None = sin verificar)
+    pub payload_auth: Option<PayloadAuthConfig>,
+    /// Republicación de las posiciones normalizadas en MQTT (None = desactivada)
+    pub mqtt_publish: Option<MqttPublishConfig>,
+    /// Confirmaciones a los equipos Queclink por MQTT (None = desactivadas)
+    pub device_acks: Option<DeviceAckConfig>,
+    /// Comandos hacia los equipos por MQTT (None = desactivados)
+    pub device_commands: Option<DeviceCommandConfig>,
+    /// Posiciones procesadas en Redis Streams (None = desactivado)
+    pub redis_stream: Option<RedisStreamConfig>,
+    /// Notificaciones a SNS y posiciones a SQS (None = desactivado)
+    pub aws: Option<AwsSinkConfig>,
+    /// Orden, colas y reintentos de las salidas de posiciones
+    pub output_sinks: OutputSinksConfig,
+    /// Umbrales de `/health/live` y `/health/ready`
+    pub health: HealthConfig,
+}
+
+/// Origen de las definiciones de geocercas
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeofenceSource {
+    /// Archivo JSON indicado en `GEOFENCE_FILE`
+    File,
+    /// Tabla `geofences` del esquema configurado
+    Database,
+}
+
+/// Geocercas por dispositivo o tenant y eventos de entrada/salida
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeofenceConfig {
+    pub source: GeofenceSource,
+    pub file: Option<String>,
+    /// Segundos entre recargas de las definiciones (0 = solo al iniciar)
+    pub refresh_secs: u64,
+}
+
+/// Segmentación de viajes por ignición y velocidad
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripConfig {
+    /// Velocidad a partir de la cual un dispositivo se considera en movimiento
+    pub start_speed_kmh: f64,
+    /// Tiempo detenido (sin apagar la ignición) tras el cual se cierra el viaje
+    pub stop_secs: u64,
+}
+
+impl Default for TripConfig {
+    fn default() -> Self {
+        Self {
+            start_speed_kmh: 5.0,
+            stop_secs: 300,
+        }
+    }
+}
+
+/// Umbrales de conducción, generales o de un tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrivingThresholds {
+    /// Caída de velocidad entre posiciones consecutivas que se considera frenada brusca
+    pub harsh_braking_kmh_s: f64,
+    /// Aumento de velocidad que se considera aceleración brusca
+    pub harsh_acceleration_kmh_s: f64,
+    pub speed_limit_kmh: f64,
+    /// Duración mínima de un exceso de velocidad para notificarlo
+    pub speeding_min_secs: u64,
+}
+
+impl Default for DrivingThresholds {
+    fn default() -> Self {
+        Self {
+            harsh_braking_kmh_s: 12.0,
+            harsh_acceleration_kmh_s: 10.0,
+            speed_limit_kmh: 100.0,
+            speeding_min_secs: 10,
+        }
+    }
+}
+
+/// Eventos de conducción a partir de posiciones consecutivas de cada dispositivo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrivingBehaviorConfig {
+    pub thresholds: DrivingThresholds,
+    /// Archivo JSON con umbrales por tenant: `{"tenant": {"speed_limit_kmh": 90}}`
+    pub tenant_thresholds_file: Option<String>,
+    /// Separación máxima entre posiciones para comparar sus velocidades
+    pub max_gap_secs: u64,
+}
+
+/// Sesiones de conductor a partir de los reportes de identificación
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DriverEventsConfig {
+    /// Cierra la sesión del conductor cuando el vehículo apaga la ignición
+    pub logout_on_ignition_off: bool,
+}
+
+/// Umbrales de alimentación, generales o de un modelo de equipo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerThresholds {
+    /// Voltaje principal por debajo del cual se considera cortada la alimentación
+    pub power_lost_volts: f64,
+    /// Voltaje principal desde el que se considera restablecida; mayor que el de
+    /// corte para no alternar eventos con un voltaje oscilante
+    pub power_restored_volts: f64,
+    /// Voltaje de la batería de respaldo por debajo del cual se avisa
+    pub low_backup_volts: f64,
+}
+
+impl Default for PowerThresholds {
+    fn default() -> Self {
+        Self {
+            power_lost_volts: 6.0,
+            power_restored_volts: 9.0,
+            low_backup_volts: 3.6,
+        }
+    }
+}
+
+/// Eventos de alimentación a partir de los voltajes de posiciones consecutivas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerEventsConfig {
+    pub thresholds: PowerThresholds,
+    /// Archivo JSON con umbrales por modelo: `{"GV300": {"power_lost_volts": 9.0}}`
+    pub model_thresholds_file: Option<String>,
+}
+
+/// Detección de dispositivos que dejan de reportar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceConfig {
+    /// Segundos sin mensajes para considerar un dispositivo desconectado
+    pub offline_after_secs: u64,
+    /// Cada cuántos segundos se revisan los dispositivos
+    pub check_interval_secs: u64,
+}
+
+/// Trazas del pipeline (consumo, parseo, lote, escritura en BD y publicación en
+/// Kafka) exportadas por OTLP/HTTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracingConfig {
+    /// URL base del colector; las trazas se envían a `{endpoint}/v1/traces`
+    pub endpoint: String,
+    pub service_name: String,
+    /// Fracción de trazas nuevas que se muestrean (0.0 - 1.0). Las que llegan con
+    /// contexto en los headers respetan la decisión del productor.
+    pub sample_ratio: f64,
+}
+
+/// Espera con backoff exponencial a que las dependencias acepten conexiones al
+/// arrancar, para no fallar si el consumer inicia antes que ellas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupConfig {
+    /// Espera máxima por cada dependencia (0 = sin espera)
+    pub max_wait_secs: u64,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            max_wait_secs: 60,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10000,
+        }
+    }
+}
+
+/// Reinicio con backoff exponencial de las tareas que fallan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    /// Reinicios seguidos de una tarea antes de apagar el servicio
+    pub max_restarts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Tiempo en ejecución tras el cual una tarea se considera estable y su contador
+    /// de reinicios vuelve a cero
+    pub stable_secs: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30000,
+            stable_secs: 300,
+        }
+    }
+}
+
+/// Detección de etapas del pipeline que dejaron de avanzar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    /// Segundos sin avance para considerar atascada una etapa
+    pub stall_secs: u64,
+    /// Cada cuántos segundos se revisan las etapas
+    pub check_interval_secs: u64,
+    pub action: WatchdogAction,
+}
+
+/// Qué hacer al detectar una etapa atascada. Siempre se registra el estado de las
+/// etapas en el log.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogAction {
+    /// Solo registrar el estado
+    #[default]
+    Log,
+    /// Reiniciar el procesador y el consumo; se pierden los mensajes en curso
+    Restart,
+    /// Terminar el proceso con el código `WATCHDOG_EXIT_CODE` para que el
+    /// orquestador lo reinicie
+    Exit,
+}
+
+/// Heartbeat de la instancia publicado en Kafka para el monitoreo de la flota
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    pub topic: String,
+    pub interval_secs: u64,
+    /// Identificador de la instancia; por defecto el hostname (nombre del pod)
+    pub instance_id: String,
+}
+
+/// Lease de consumo sobre un advisory lock de PostgreSQL: solo la instancia que lo
+/// tiene consume, y una instancia nueva espera a que la anterior drene y lo libere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoverConfig {
+    /// Nombre del lease; las instancias que se relevan deben compartirlo
+    pub lease_key: String,
+    /// Intervalo entre intentos de tomar el lease y verificaciones de que sigue vigente
+    pub poll_interval_ms: u64,
+}
+
+/// Reparto de los dispositivos entre `count` instancias por hash del device_id, para
+/// escalar con brokers sin suscripciones compartidas (cada instancia recibe todo)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardConfig {
+    /// Shard de esta instancia, de 0 a `count - 1`
+    pub index: u32,
+    pub count: u32,
+    /// Tópico Kafka donde se republican los mensajes de otros shards, con `{shard}`
+    /// reemplazado por el shard dueño (None = se descartan)
+    pub republish_topic: Option<String>,
+}
+
+/// Vista en vivo de los mensajes por WebSocket (`/tail` de la API de administración)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveTailConfig {
+    /// Sin filtro de dispositivo se envía uno de cada `sample_rate` mensajes
+    pub sample_rate: u64,
+    /// Campos reemplazados por `[redacted]` en los eventos
+    pub redact_fields: Vec<String>,
+}
+
+/// Envío de las notificaciones a endpoints HTTP (webhooks)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub targets: Vec<WebhookTargetConfig>,
+    /// Reintentos con backoff exponencial ante errores de red o respuestas 5xx/429
+    pub retry: RetryConfig,
+    pub timeout_ms: u64,
+}
+
+/// Endpoint de `WEBHOOK_TARGETS`, con sus variables `WEBHOOK_{NOMBRE}_*`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookTargetConfig {
+    pub name: String,
+    pub url: String,
+    /// Secreto para la firma HMAC-SHA256 del cuerpo (None = sin firma)
+    pub secret: Option<String>,
+    /// Valores del campo `event` que se envían (vacío = todos)
+    pub events: Vec<String>,
+    /// Tenants cuyos eventos se envían (vacío = todos)
+    pub tenants: Vec<String>,
+}
+
+/// Republicación de las posiciones normalizadas y enriquecidas en un broker MQTT
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttPublishConfig {
+    /// `mqtt://host[:1883]` o `mqtts://host[:8883]`
+    pub url: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Tópico de cada posición; admite `{manufacturer}`, `{device_id}` y `{tenant}`
+    pub topic: String,
+    /// 0, 1 o 2
+    pub qos: u8,
+    pub retain: bool,
+    pub keep_alive_secs: u16,
+    /// Posiciones en cola mientras el broker no responde; al llenarse esperan en
+    /// la cola de la salida
+    pub queue_size: usize,
+}
+
+/// Confirmaciones (SACK) de las tramas Queclink, publicadas en MQTT para que el
+/// front-end TCP las reenvíe a cada equipo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceAckConfig {
+    /// Conexión; el tópico admite `{device_id}` y `{msg_num}`
+    pub mqtt: MqttPublishConfig,
+    /// Reportes que se confirman, p. ej. `GTFRI` o `GTHBD` (vacío = todos)
+    pub reports: Vec<String>,
+}
+
+/// Comandos hacia los equipos: llegan por Kafka o por la API de administración, se
+/// guardan en `device_commands` y se publican en el tópico MQTT de bajada de cada
+/// dispositivo, del que los toma el front-end TCP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCommandConfig {
+    /// Conexión; el tópico admite `{device_id}`, `{tenant}` y `{command_id}`
+    pub mqtt: MqttPublishConfig,
+    /// Tópico Kafka de comandos en JSON (None = solo la API de administración)
+    pub kafka_topic: Option<String>,
+    /// Espera máxima a que el broker confirme un comando antes de marcarlo `failed`
+    pub timeout_secs: u64,
+}
+
+/// Salida de las posiciones procesadas a Redis Streams (XADD)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisStreamConfig {
+    pub url: String,
+    /// Clave del stream; admite `{tenant}`, `{source}` y `{manufacturer}`
+    pub key: String,
+    /// Largo aproximado al que se recorta cada stream (0 = sin recorte)
+    pub max_len: usize,
+}
+
+/// Salida a AWS: notificaciones a un tópico SNS y posiciones a una cola SQS. Las
+/// credenciales salen de la cadena estándar de AWS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AwsSinkConfig {
+    pub region: String,
+    /// Endpoint alternativo para SNS y SQS (p. ej. LocalStack)
+    pub endpoint: Option<String>,
+    /// URL de la cola de posiciones (None = sin SQS)
+    pub sqs_queue_url: Option<String>,
+    /// Tópico de las notificaciones (None = sin SNS)
+    pub sns_topic_arn: Option<String>,
+    /// Mensajes por SendMessageBatch (1 a 10)
+    pub sqs_batch_size: usize,
+    /// Espera máxima para completar un lote de SQS
+    pub sqs_flush_interval_ms: u64,
+    /// Mensajes en cola mientras AWS no responde; al llenarse se descartan
+    pub queue_size: usize,
+    pub retry: RetryConfig,
+}
+
+/// Destinos de las posiciones procesadas, en el orden de `OUTPUT_SINKS`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    /// Republicación en MQTT (`MQTT_PUBLISH_*`)
+    Mqtt,
+    /// Cola SQS (`AWS_SQS_QUEUE_URL`)
+    Sqs,
+    /// Redis Streams (`REDIS_STREAM_*`)
+    RedisStream,
+}
+
+/// Pipeline de salidas de las posiciones: cada destino tiene su cola de lotes y
+/// sus reintentos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputSinksConfig {
+    /// Destinos activos, en orden; sin `OUTPUT_SINKS` son todos los configurados
+    pub sinks: Vec<SinkKind>,
+    /// Lotes en cola por destino; al llenarse se descartan
+    pub queue_size: usize,
+    pub retry: RetryConfig,
+}
+
+impl Default for OutputSinksConfig {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            queue_size: 100,
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// Ingesta de mensajes por HTTP (`POST /ingest`), además del broker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestConfig {
+    /// Dirección de escucha, p. ej. `0.0.0.0:8082`
+    pub bind: String,
+    /// Tokens aceptados en `Authorization: Bearer`
+    pub tokens: Vec<String>,
+    /// Máximo de mensajes por petición
+    pub max_batch: usize,
+}
+
+/// Verificación de los mensajes del broker: cada productor firma el payload con la
+/// clave de su tenant (HMAC-SHA256) y envía la firma y el tenant en headers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadAuthConfig {
+    /// Clave de cada tenant
+    pub keys: BTreeMap<String, String>,
+    /// Header con la firma en hex, con o sin prefijo `sha256=`
+    pub signature_header: String,
+    /// Header con el tenant cuya clave firmó el payload
+    pub key_id_header: String,
+}
+
+/// Sondas de salud para Kubernetes, evaluadas en segundo plano
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Cada cuánto se revisan la BD y Kafka
+    pub check_interval_secs: u64,
+    /// Tiempo que la BD o Kafka pueden seguir caídos antes de que `/health/live`
+    /// falle y Kubernetes reinicie el pod
+    pub liveness_grace_secs: u64,
+    /// Mensajes en cola y en los carriles por encima de los cuales la instancia no
+    /// está lista (0 = sin límite)
+    pub max_queue_depth: usize,
+    /// Registros en los buffers de BD por encima de los cuales la instancia no está
+    /// lista (0 = sin límite)
+    pub max_db_buffer: usize,
+}
+
+/// API de administración, HTTP (`ADMIN_BIND`) o gRPC (`GRPC_ADMIN_BIND`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminConfig {
+    /// Dirección de escucha, p. ej. `0.0.0.0:8081`
+    pub bind: String,
+}
+
+/// Archivo de mensajes crudos como Parquet en almacenamiento compatible con S3
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    pub bucket: String,
+    /// Prefijo de las claves dentro del bucket
+    pub prefix: String,
+    /// Endpoint para MinIO u otros compatibles (None = AWS)
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Directorio local donde se escriben los archivos antes de subirlos
+    pub spool_dir: String,
+    /// Mensajes por archivo
+    pub max_records: usize,
+    /// Cada cuánto se cierran los archivos abiertos y se suben los pendientes
+    pub flush_interval_secs: u64,
+}
+
+/// Captura de los payloads tal como llegan, antes de decodificarlos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    /// Directorio de los archivos NDJSON
+    pub dir: String,
+    /// Con `true` el directorio está en el spool del archivo S3 y se suben los
+    /// archivos cerrados
+    pub archive: bool,
+    pub max_file_size_mb: u64,
+    /// Antigüedad máxima de un archivo antes de cerrarlo
+    pub max_file_age_secs: u64,
+    /// Payloads en cola de escritura; al llenarse se descartan
+    pub queue_size: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
+    /// Uno o varios hosts separados por coma; el primero con escritura se usa como primario
     pub host: String,
     pub port: u16,
     pub database: String,
@@ -38,6 +643,429 @@ pub struct DatabaseConfig {
     pub min_connections: u32,
     pub connection_timeout_secs: u64,
     pub idle_timeout_secs: u64,
+    /// Aplica las migraciones embebidas al iniciar
+    pub run_migrations: bool,
+    /// Particionado por tiempo de las tablas de histórico (None = tablas simples)
+    pub partitioning: Option<PartitionConfig>,
+    /// Reintentos de escritura ante errores transitorios
+    pub retry: RetryConfig,
+    /// Intervalo entre health checks mientras el circuito está abierto
+    pub circuit_probe_interval_secs: u64,
+    /// Cada cuánto se revisa el buffer interno de escritura
+    pub flush_interval_ms: u64,
+    /// Antigüedad máxima de un registro en el buffer antes de escribirlo
+    pub buffer_max_age_secs: u64,
+    /// Máximo de registros en el buffer de escritura (0 = sin límite)
+    pub buffer_max_records: usize,
+    /// Qué hacer cuando el buffer alcanza `buffer_max_records`
+    pub buffer_overflow_policy: BufferOverflowPolicy,
+    /// Forma de enviar las filas en los INSERT
+    pub insert_mode: InsertMode,
+    /// Histórico en ClickHouse (None = solo PostgreSQL)
+    pub clickhouse: Option<ClickHouseConfig>,
+    /// Copia de las posiciones en Elasticsearch/OpenSearch (None = desactivada)
+    pub elasticsearch: Option<ElasticsearchConfig>,
+    /// Copia del estado actual en Redis (None = desactivada)
+    pub redis_state: Option<RedisStateConfig>,
+    /// Retención por tabla y ANALYZE tras cargas grandes
+    pub maintenance: MaintenanceConfig,
+    /// Bases de datos adicionales que reciben una copia de cada lote
+    pub fanout: Vec<FanoutTargetConfig>,
+    /// Esquema y nombres de las tablas destino
+    pub tables: TableConfig,
+    /// Cómo se actualiza el estado actual respecto al histórico
+    pub current_state_mode: CurrentStateMode,
+    /// Columna que decide si un mensaje es más nuevo que el estado guardado
+    /// (None = el último mensaje procesado siempre sobrescribe)
+    pub current_state_order: Option<CurrentStateOrder>,
+    /// Registra cada escritura de lote en `batch_audit`
+    pub audit: bool,
+    /// Cifrado de `raw_message` antes de guardarlo (None = en claro)
+    pub raw_encryption: Option<RawEncryptionConfig>,
+}
+
+/// Claves AES-256-GCM para cifrar `raw_message`. Cada valor cifrado lleva el id de
+/// su clave, así que las claves anteriores siguen sirviendo para descifrar tras una
+/// rotación.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawEncryptionConfig {
+    /// Id de la clave con la que se cifra
+    pub key_id: String,
+    /// Clave actual: 32 bytes en base64
+    pub key: String,
+    /// Claves anteriores por id, solo para descifrar
+    pub previous_keys: BTreeMap<String, String>,
+}
+
+impl RawEncryptionConfig {
+    fn validate(&self) -> Result<()> {
+        if self.key_id.is_empty() || self.key_id.contains(':') {
+            return Err(anyhow::anyhow!(
+                "RAW_ENCRYPTION_KEY_ID no puede estar vacío ni contener ':'"
+            ));
+        }
+        let keys = std::iter::once((&self.key_id, &self.key)).chain(&self.previous_keys);
+        for (key_id, key) in keys {
+            let valid = base64::engine::general_purpose::STANDARD
+                .decode(key)
+                .is_ok_and(|key| key.len() == 32);
+            if !valid {
+                return Err(anyhow::anyhow!(
+                    "La clave '{}' de cifrado de raw_message debe ser de 32 bytes en base64",
+                    key_id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Columnas válidas para ordenar las actualizaciones del estado actual
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrentStateOrder {
+    GpsEpoch,
+    GpsDatetime,
+    ReceivedEpoch,
+    DecodedEpoch,
+}
+
+impl CurrentStateOrder {
+    pub fn column(&self) -> &'static str {
+        match self {
+            CurrentStateOrder::GpsEpoch => "gps_epoch",
+            CurrentStateOrder::GpsDatetime => "gps_datetime",
+            CurrentStateOrder::ReceivedEpoch => "received_epoch",
+            CurrentStateOrder::DecodedEpoch => "decoded_epoch",
+        }
+    }
+}
+
+/// Tareas periódicas de mantenimiento de las tablas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    /// Intervalo entre ejecuciones (0 = desactivado)
+    pub interval_secs: u64,
+    /// Días de retención por tabla: un fabricante (`suntech`, ...), `current_state`, `rejected`,
+    /// `geofence_events`, `quarantine`
+    pub retention_days: BTreeMap<String, u32>,
+    /// Filas modificadas desde el último ANALYZE que disparan uno nuevo (0 = nunca)
+    pub analyze_threshold: i64,
+    /// Filas borradas por sentencia, para no bloquear la tabla con un DELETE enorme
+    pub delete_batch_size: i64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 3600,
+            retention_days: BTreeMap::new(),
+            analyze_threshold: 100_000,
+            delete_batch_size: 10_000,
+        }
+    }
+}
+
+/// Base de datos adicional (p. ej. el clúster de analítica) que recibe los mismos
+/// registros que el primario, con su propio buffer y reintentos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanoutTargetConfig {
+    pub name: String,
+    /// Uno o varios hosts separados por coma, igual que `DB_HOST`
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub username: String,
+    pub password: String,
+    pub max_connections: u32,
+    /// Máximo de registros en el buffer del destino (0 = sin límite)
+    pub buffer_max_records: usize,
+    /// Con `drop_oldest` una caída del destino no frena al primario
+    pub buffer_overflow_policy: BufferOverflowPolicy,
+}
+
+impl FanoutTargetConfig {
+    /// URLs de conexión, una por host
+    pub fn urls(&self) -> Vec<String> {
+        postgres_urls(
+            &self.host,
+            self.port,
+            &self.username,
+            &self.password,
+            &self.database,
+        )
+    }
+}
+
+/// Copia del estado actual en Redis, un hash por dispositivo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisStateConfig {
+    pub url: String,
+    /// Prefijo de las claves (`device:` → `device:{device_id}`)
+    pub key_prefix: String,
+    /// Expiración de cada hash (0 = sin expiración)
+    pub ttl_secs: u64,
+}
+
+/// Destino del histórico de posiciones en ClickHouse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClickHouseConfig {
+    /// URL de la interfaz HTTP (p. ej. http://clickhouse:8123)
+    pub url: String,
+    pub database: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Nombre de tabla; admite el marcador `{manufacturer}`
+    pub table: String,
+    pub mode: ClickHouseMode,
+}
+
+/// Copia de las posiciones en Elasticsearch u OpenSearch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElasticsearchConfig {
+    /// URL del cluster (p. ej. http://elasticsearch:9200)
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// API key codificada en base64; tiene prioridad sobre usuario y contraseña
+    pub api_key: Option<String>,
+    /// Prefijo de los índices mensuales `{prefijo}-YYYY.MM`
+    pub index_prefix: String,
+}
+
+/// Relación entre el histórico en ClickHouse y el de PostgreSQL
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickHouseMode {
+    /// Se escribe en ambos; un fallo de ClickHouse solo se registra
+    #[default]
+    Mirror,
+    /// El histórico se escribe solo en ClickHouse; PostgreSQL guarda el estado actual
+    Replace,
+}
+
+/// Forma de construir los INSERT por lotes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InsertMode {
+    /// `VALUES (...), (...)`: el texto de la consulta cambia con el tamaño del chunk
+    #[default]
+    Values,
+    /// `SELECT * FROM UNNEST($1, $2, ...)`: un arreglo por columna y una consulta fija
+    /// que PostgreSQL mantiene preparada
+    Unnest,
+}
+
+/// Política del buffer de escritura al alcanzar su tamaño máximo
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BufferOverflowPolicy {
+    /// Espera a que se libere espacio, lo que frena el consumo de Kafka
+    #[default]
+    Backpressure,
+    /// Descarta los registros más antiguos y los cuenta
+    DropOldest,
+}
+
+/// Relación entre el insert del histórico y el upsert de communications_current_state
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CurrentStateMode {
+    /// Ambos en la misma transacción: un error en uno revierte el otro
+    #[default]
+    Transactional,
+    /// Transacciones separadas con reintentos propios; el histórico se guarda aunque falle el upsert
+    Independent,
+    /// Sin upsert del estado actual
+    Disabled,
+}
+
+/// Esquema y nombres de las tablas destino. Los nombres y el esquema admiten
+/// los marcadores `{manufacturer}` (solo en el histórico) y `{tenant}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableConfig {
+    pub schema: Option<String>,
+    pub history_template: String,
+    pub current_state: String,
+    pub rejected: String,
+    pub tenant: Option<String>,
+}
+
+impl TableConfig {
+    /// Tabla de histórico de un fabricante (`Manufacturer::table`)
+    pub fn history_table(&self, manufacturer: &str) -> String {
+        self.qualify(
+            &self
+                .history_template
+                .replace("{manufacturer}", manufacturer),
+        )
+    }
+
+    pub fn current_state_table(&self) -> String {
+        self.qualify(&self.current_state)
+    }
+
+    pub fn rejected_table(&self) -> String {
+        self.qualify(&self.rejected)
+    }
+
+    /// Definiciones de geocercas cuando `GEOFENCE_SOURCE=database`
+    pub fn geofences_table(&self) -> String {
+        self.qualify("geofences")
+    }
+
+    pub fn geofence_events_table(&self) -> String {
+        self.qualify("geofence_events")
+    }
+
+    /// Posiciones apartadas por la política de calidad GPS `quarantine`
+    pub fn quarantine_table(&self) -> String {
+        self.qualify("communications_quarantine")
+    }
+
+    pub fn trips_table(&self) -> String {
+        self.qualify("trips")
+    }
+
+    pub fn driver_events_table(&self) -> String {
+        self.qualify("driver_events")
+    }
+
+    /// Comandos hacia los equipos y su estado de entrega (`DEVICE_COMMAND_TOPIC`)
+    pub fn device_commands_table(&self) -> String {
+        self.qualify("device_commands")
+    }
+
+    pub fn power_events_table(&self) -> String {
+        self.qualify("power_events")
+    }
+
+    /// Auditoría de las escrituras de lotes (`DB_AUDIT_ENABLED`)
+    pub fn batch_audit_table(&self) -> String {
+        self.qualify("batch_audit")
+    }
+
+    /// Esquema resuelto, si hay uno configurado
+    pub fn schema_name(&self) -> Option<String> {
+        self.schema.as_deref().map(|schema| self.render(schema))
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        match self.schema_name() {
+            Some(schema) => format!("{}.{}", schema, self.render(name)),
+            None => self.render(name),
+        }
+    }
+
+    fn render(&self, template: &str) -> String {
+        template.replace("{tenant}", self.tenant.as_deref().unwrap_or(""))
+    }
+
+    /// Verifica que los nombres resueltos sean identificadores SQL válidos
+    fn validate(&self) -> Result<()> {
+        let templates = [
+            Some(self.history_template.as_str()),
+            Some(self.current_state.as_str()),
+            Some(self.rejected.as_str()),
+            self.schema.as_deref(),
+        ];
+        if self.tenant.is_none() && templates.iter().flatten().any(|t| t.contains("{tenant}")) {
+            return Err(anyhow::anyhow!(
+                "Los nombres de tabla usan {{tenant}} pero DB_TENANT no está definido"
+            ));
+        }
+
+        let mut names: Vec<String> = Manufacturer::all()
+            .map(|manufacturer| self.history_table(manufacturer.table()))
+            .collect();
+        names.push(self.current_state_table());
+        names.push(self.rejected_table());
+        for name in &names {
+            let valid = name.split('.').count() <= 2 && name.split('.').all(is_sql_identifier);
+            if !valid {
+                return Err(anyhow::anyhow!("Nombre de tabla inválido: '{}'", name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            history_template: "communications_{manufacturer}".to_string(),
+            current_state: "communications_current_state".to_string(),
+            rejected: "communications_rejected".to_string(),
+            tenant: None,
+        }
+    }
+}
+
+/// Reintentos con backoff exponencial para escrituras en la BD, webhooks y AWS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Intentos totales, incluyendo el primero
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl RetryConfig {
+    /// Espera antes del reintento número `attempt` (1 = primer reintento)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(
+            self.base_delay_ms
+                .saturating_mul(factor)
+                .min(self.max_delay_ms),
+        )
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 5000,
+        }
+    }
+}
+
+/// Rango de tiempo que cubre cada partición
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionInterval {
+    Daily,
+    Monthly,
+}
+
+/// Columna usada como clave de particionado
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionKey {
+    GpsDatetime,
+    ReceivedAt,
+}
+
+impl PartitionKey {
+    pub fn column(&self) -> &'static str {
+        match self {
+            PartitionKey::GpsDatetime => "gps_datetime",
+            PartitionKey::ReceivedAt => "received_at",
+        }
+    }
+}
+
+/// Particionado declarativo de communications_suntech / communications_queclink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionConfig {
+    pub interval: PartitionInterval,
+    pub key: PartitionKey,
+    /// Días tras los cuales se eliminan las particiones (None = sin retención)
+    pub retention_days: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,15 +1074,301 @@ pub struct ProcessingConfig {
     pub message_buffer_size: usize,
     pub batch_processing_size: usize,
     pub max_parallel_devices: usize,
+    /// Claves de idempotencia recientes recordadas en memoria (0 = desactivado)
+    pub dedup_cache_size: usize,
+    /// Clave de idempotencia de cada mensaje y almacén compartido opcional
+    pub idempotency: IdempotencyConfig,
+    /// Descarte de posiciones repetidas o demasiado antiguas
+    pub position_filter: PositionFilterConfig,
+    /// Calificación de la calidad del fix GPS (None = desactivada)
+    pub gps_quality: Option<GpsQualityConfig>,
+    /// Muestreo de posiciones periódicas de equipos de alta frecuencia
+    pub downsampling: DownsamplingConfig,
+    /// Tiempo máximo para drenar colas y buffers en el shutdown antes de salir
+    pub drain_timeout_secs: u64,
+    /// Memoria estimada de colas y buffers a partir de la cual se frena el consumo
+    /// (None = sin límite)
+    pub memory_soft_limit_mb: Option<u64>,
+    /// Tamaño de lote e intervalo de flush ajustados a la carga (None = fijos)
+    pub adaptive_batch: Option<AdaptiveBatchConfig>,
+    /// Archivo de reglas de mapeo de campos entrantes (YAML, JSON o TOML)
+    pub field_mapping_file: Option<String>,
+    /// Enrutamiento por tenant con carriles y buffers propios (None = desactivado)
+    pub tenant_routing: Option<TenantRoutingConfig>,
+    pub sanitization: SanitizationConfig,
+    /// Etapas de enriquecimiento aplicadas a cada posición
+    pub enrichment: EnrichmentConfig,
+    /// Procesa sin escribir en la BD ni en Kafka e informa lo que se habría escrito
+    pub dry_run: bool,
+}
+
+/// Fuente de la que se obtiene el tenant de un mensaje
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantSource {
+    /// Tópico de Kafka por el que llegó el mensaje (`TENANT_TOPICS`)
+    Topic,
+    /// Prefijo del device_id (`TENANT_DEVICE_PREFIXES`)
+    DevicePrefix,
+    /// Archivo `device_id,tenant` (`TENANT_MAP_FILE`)
+    Lookup,
+}
+
+/// Enrutamiento de mensajes por tenant: cada tenant tiene sus carriles, su buffer de
+/// BD y sus tablas (vía el marcador `{tenant}`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantRoutingConfig {
+    /// Fuentes consultadas en orden; la primera que resuelve gana
+    pub sources: Vec<TenantSource>,
+    /// Tópico adicional -> tenant
+    pub topics: BTreeMap<String, String>,
+    /// Prefijo de device_id -> tenant (gana el prefijo más largo)
+    pub device_prefixes: BTreeMap<String, String>,
+    pub map_file: Option<String>,
+    /// Carriles de procesamiento de cada tenant
+    pub lanes_per_tenant: usize,
+}
+
+/// Límites del ajuste automático de lotes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdaptiveBatchConfig {
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    pub min_flush_interval_ms: u64,
+    pub max_flush_interval_ms: u64,
+    /// Latencia de escritura a partir de la cual el lote se reduce a la mitad
+    pub target_latency_ms: u64,
+    /// Mensajes que se suman al lote tras cada escritura rápida de un lote completo
+    pub increase_step: usize,
+}
+
+/// Cómo se identifica un mensaje ya procesado
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdempotencyKey {
+    /// UUID asignado por el gateway
+    #[default]
+    Uuid,
+    /// device_id + MSG_COUNTER + GPS_EPOCH, estable aunque el gateway reenvíe el
+    /// mismo reporte con otro UUID (usa el UUID si falta alguno de los dos)
+    DeviceCounter,
+}
+
+/// Dónde se recuerdan las claves de idempotencia además de la caché en memoria
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdempotencyBackend {
+    /// Solo la caché en memoria de `PROCESSING_DEDUP_CACHE_SIZE`
+    #[default]
+    Memory,
+    /// También en Redis (`REDIS_URL`), compartido entre instancias y reinicios
+    Redis,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyConfig {
+    pub key: IdempotencyKey,
+    pub backend: IdempotencyBackend,
+    /// Prefijo de las claves en Redis
+    pub key_prefix: String,
+    /// Expiración de cada clave en Redis
+    pub ttl_secs: u64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            key: IdempotencyKey::Uuid,
+            backend: IdempotencyBackend::Memory,
+            key_prefix: "idem:".to_string(),
+            ttl_secs: 86400,
+        }
+    }
+}
+
+/// Filtro de posiciones que no aportan filas útiles al histórico
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PositionFilterConfig {
+    /// Posiciones (device, gps_epoch, lat, lon) recordadas para descartar repetidas (0 = desactivado)
+    pub duplicate_cache_size: usize,
+    /// Antigüedad máxima de `gps_datetime` respecto a la hora actual (0 = sin límite)
+    pub max_age_secs: u64,
+}
+
+/// Qué hacer con las posiciones por debajo de la calidad mínima
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GpsQualityPolicy {
+    /// Se guardan igual, con su `quality_score`
+    Flag,
+    /// Se guardan en `communications_quarantine` en lugar del histórico
+    Quarantine,
+    /// Se descartan
+    Drop,
+}
+
+/// Validación de coordenadas y calidad mínima de cada posición
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpsQualityConfig {
+    pub policy: GpsQualityPolicy,
+    /// Calidad (0-100) por debajo de la cual una posición se considera inválida
+    pub min_score: u8,
+    /// Distancia máxima plausible entre posiciones separadas por menos de `max_jump_secs`
+    pub max_jump_km: f64,
+    pub max_jump_secs: u64,
+    pub min_satellites: i32,
+}
+
+/// Muestreo: una posición periódica por dispositivo cada `interval_secs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownsamplingConfig {
+    /// Segundos de tiempo GPS entre posiciones periódicas guardadas (0 = desactivado)
+    pub interval_secs: u64,
+    /// Dispositivos cuyo último estado se recuerda (LRU)
+    pub max_devices: usize,
+}
+
+impl Default for DownsamplingConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 0,
+            max_devices: 100_000,
+        }
+    }
+}
+
+/// Enriquecedores incluidos, en el orden en que se listan en `PROCESSING_ENRICHERS`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnricherKind {
+    /// Convierte la velocidad reportada a km/h
+    SpeedUnit,
+    /// Convierte `gps_datetime` de la hora local del equipo a UTC
+    Timezone,
+    /// Asigna el tenant de cada dispositivo desde un archivo
+    Tenant,
+    /// Decodifica entradas/salidas digitales con el mapa de bits de cada modelo
+    IoState,
+}
+
+/// Unidad en la que un fabricante reporta la velocidad
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedUnit {
+    Kmh,
+    Knots,
+    Mph,
+}
+
+impl SpeedUnit {
+    /// Factor para convertir a km/h
+    pub fn kmh_factor(self) -> f64 {
+        match self {
+            SpeedUnit::Kmh => 1.0,
+            SpeedUnit::Knots => 1.852,
+            SpeedUnit::Mph => 1.609344,
+        }
+    }
+}
+
+/// Configuración de la etapa de enriquecimiento
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnrichmentConfig {
+    /// Enriquecedores activos, en orden de ejecución
+    pub enrichers: Vec<EnricherKind>,
+    /// Unidad de velocidad por fabricante (`suntech`, `queclink`); sin entrada = km/h
+    pub speed_units: BTreeMap<String, SpeedUnit>,
+    /// Desfase de la hora local de los equipos respecto a UTC, en minutos
+    pub gps_utc_offset_minutes: i32,
+    /// Archivo `device_id,tenant` para el enriquecedor de tenant
+    pub tenant_map_file: Option<String>,
+    /// Archivo JSON con los bits de cada señal por modelo, para `io_state`
+    pub io_map_file: Option<String>,
+}
+
+/// Qué hacer cuando un campo excede el límite de su columna VARCHAR
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldOverflowPolicy {
+    /// Recorta el valor al límite de la columna
+    Truncate,
+    /// Guarda NULL en lugar del valor
+    Null,
+    /// Descarta el mensaje completo
+    Reject,
+}
+
+/// Saneamiento de campos antes de insertar en la BD
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SanitizationConfig {
+    pub policy: FieldOverflowPolicy,
+    /// Límite de caracteres por columna
+    pub column_limits: HashMap<String, usize>,
+}
+
+impl Default for SanitizationConfig {
+    fn default() -> Self {
+        let column_limits = [
+            ("cell_id", 10),
+            ("lac", 10),
+            ("mcc", 10),
+            ("mnc", 10),
+            ("model", 50),
+            ("firmware", 50),
+            ("msg_class", 20),
+        ]
+        .into_iter()
+        .map(|(column, limit)| (column.to_string(), limit))
+        .collect();
+
+        Self {
+            policy: FieldOverflowPolicy::Truncate,
+            column_limits,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     pub level: String,
+    /// Archivo de log además de stdout (None = solo stdout)
     pub file_path: Option<String>,
+    /// Tamaño a partir del cual se rota el archivo (0 = sin límite)
     pub max_file_size_mb: u64,
+    /// Archivos rotados que se conservan
     pub max_files: u32,
+    /// Rotación por tiempo: `hourly`, `daily` o `never`
+    pub rotation: String,
     pub json_format: bool,
+    /// DSN de Sentry para errores graves y pánicos (None = desactivado)
+    pub sentry_dsn: Option<String>,
+    /// Webhook que recibe los mismos incidentes como JSON (None = desactivado)
+    pub error_webhook_url: Option<String>,
+    /// Datos personales que se ocultan en los logs y los incidentes
+    pub redaction: RedactionConfig,
+}
+
+/// Reglas de ocultación de datos personales en los logs (ver `redact`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    pub device_id: DeviceIdRedaction,
+    /// Mostrar solo la red de la IP del equipo
+    pub mask_client_ip: bool,
+    /// Caracteres de la trama cruda que se muestran (None = completa)
+    pub raw_max_chars: Option<usize>,
+    /// Sal del hash del device_id, para que no se pueda revertir probando IMEIs
+    pub hash_salt: Option<String>,
+}
+
+/// Cómo aparece el device_id en los logs
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceIdRedaction {
+    /// Tal cual
+    #[default]
+    None,
+    /// Hash estable: el mismo dispositivo da siempre el mismo valor
+    Hash,
 }
 
 impl AppConfig {
@@ -79,6 +1393,7 @@ impl AppConfig {
         let broker_type_str = env::var("BROKER_TYPE").unwrap_or_else(|_| "kafka".to_string());
         let broker_type = match broker_type_str.to_lowercase().as_str() {
             "kafka" | "redpanda" => BrokerType::Kafka,
+            "amqp" | "rabbitmq" => BrokerType::Amqp,
             _ => {
                 eprintln!(
                     "⚠️ BROKER_TYPE '{}' no reconocido, usando 'kafka' por defecto",
@@ -88,6 +1403,22 @@ impl AppConfig {
             }
         };
 
+        let payload_format = match env_opt("BROKER_PAYLOAD_FORMAT")
+            .map(|format| format.to_lowercase())
+            .as_deref()
+        {
+            None | Some("protobuf") => PayloadFormat::Protobuf,
+            Some("raw") => PayloadFormat::Raw,
+            Some("json") => PayloadFormat::Json,
+            Some(other) => {
+                eprintln!(
+                    "⚠️ BROKER_PAYLOAD_FORMAT '{}' no reconocido, usando 'protobuf'",
+                    other
+                );
+                PayloadFormat::Protobuf
+            }
+        };
+
         let broker_host = env::var("BROKER_HOST").unwrap_or_else(|_| "127.0.0.1:9092".to_string());
 
         let broker_topic =
@@ -96,7 +1427,51 @@ impl AppConfig {
         let broker_group_id =
             env::var("BROKER_GROUP_ID").unwrap_or_else(|_| "siscom-consumer-group".to_string());
 
+        // Schema Registry (opcional, solo si se define la URL)
+        let schema_registry = env_opt("SCHEMA_REGISTRY_URL").map(|url| SchemaRegistryConfig {
+            url,
+            username: env_opt("SCHEMA_REGISTRY_USERNAME"),
+            password: env_opt("SCHEMA_REGISTRY_PASSWORD"),
+        });
+
         // Kafka-specific configuration (usados solo si broker_type es Kafka)
+        let kafka = KafkaConfig {
+            security_protocol: env_opt("KAFKA_SECURITY_PROTOCOL"),
+            sasl_mechanism: env_opt("KAFKA_SASL_MECHANISM"),
+            sasl_username: env_opt("KAFKA_USERNAME"),
+            sasl_password: env_opt("KAFKA_PASSWORD"),
+            sasl_oauthbearer_config: env_opt("KAFKA_SASL_OAUTHBEARER_CONFIG"),
+            ssl_ca_location: env_opt("KAFKA_SSL_CA_LOCATION"),
+            ssl_certificate_location: env_opt("KAFKA_SSL_CERTIFICATE_LOCATION"),
+            ssl_key_location: env_opt("KAFKA_SSL_KEY_LOCATION"),
+            ssl_key_password: env_opt("KAFKA_SSL_KEY_PASSWORD"),
+        };
+
+        // AMQP-specific configuration (usados solo si broker_type es Amqp)
+        let amqp_defaults = AmqpConfig::default();
+        let amqp = AmqpConfig {
+            vhost: env_opt("AMQP_VHOST").unwrap_or(amqp_defaults.vhost),
+            username: env_opt("AMQP_USERNAME").unwrap_or(amqp_defaults.username),
+            password: env_opt("AMQP_PASSWORD").unwrap_or(amqp_defaults.password),
+            tls: env::var("AMQP_TLS")
+                .ok()
+                .and_then(|tls| tls.parse::<bool>().ok())
+                .unwrap_or(false),
+            exchange: env_opt("AMQP_EXCHANGE"),
+            routing_keys: env_opt("AMQP_ROUTING_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+            prefetch: env::var("AMQP_PREFETCH")
+                .ok()
+                .and_then(|prefetch| prefetch.parse::<u16>().ok())
+                .unwrap_or(amqp_defaults.prefetch)
+                .max(1),
+        };
+
         // Database Configuration
         let db_host = env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string());
         let db_port = env::var("DB_PORT")
@@ -122,6 +1497,326 @@ impl AppConfig {
             .unwrap_or_else(|_| "600".to_string())
             .parse::<u64>()
             .unwrap_or(600);
+        let db_run_migrations = env::var("DB_RUN_MIGRATIONS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let db_audit = env::var("DB_AUDIT_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        // La clave puede venir de un archivo montado por el gestor de secretos (KMS);
+        // si no se puede leer queda vacía y la validación falla, nunca se guarda en claro
+        let raw_encryption_key = env_opt("RAW_ENCRYPTION_KEY").or_else(|| {
+            env_opt("RAW_ENCRYPTION_KEY_FILE").map(|path| match fs::read_to_string(&path) {
+                Ok(key) => key.trim().to_string(),
+                Err(e) => {
+                    eprintln!(
+                        "⚠️ No se pudo leer RAW_ENCRYPTION_KEY_FILE '{}': {}",
+                        path, e
+                    );
+                    String::new()
+                }
+            })
+        });
+        let raw_encryption = raw_encryption_key.map(|key| RawEncryptionConfig {
+            key_id: env_opt("RAW_ENCRYPTION_KEY_ID").unwrap_or_else(|| "1".to_string()),
+            key,
+            previous_keys: parse_tenant_pairs("RAW_ENCRYPTION_PREVIOUS_KEYS"),
+        });
+
+        let db_retry = RetryConfig {
+            max_attempts: env::var("DB_RETRY_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse::<u32>()
+                .unwrap_or(3)
+                .max(1),
+            base_delay_ms: env::var("DB_RETRY_BASE_DELAY_MS")
+                .unwrap_or_else(|_| "200".to_string())
+                .parse::<u64>()
+                .unwrap_or(200),
+            max_delay_ms: env::var("DB_RETRY_MAX_DELAY_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse::<u64>()
+                .unwrap_or(5000),
+        };
+        let db_circuit_probe_interval_secs = env::var("DB_CIRCUIT_PROBE_INTERVAL_SECS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u64>()
+            .unwrap_or(5)
+            .max(1);
+
+        let db_flush_interval_ms = env::var("DB_FLUSH_INTERVAL_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<u64>()
+            .unwrap_or(1000)
+            .max(10);
+        let db_buffer_max_age_secs = env::var("DB_BUFFER_MAX_AGE_SECS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u64>()
+            .unwrap_or(5);
+
+        let db_buffer_max_records = env::var("DB_BUFFER_MAX_RECORDS")
+            .unwrap_or_else(|_| "100000".to_string())
+            .parse::<usize>()
+            .unwrap_or(100_000);
+        let db_buffer_overflow_policy = match env_opt("DB_BUFFER_OVERFLOW_POLICY")
+            .unwrap_or_else(|| "backpressure".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "backpressure" => BufferOverflowPolicy::Backpressure,
+            "drop_oldest" => BufferOverflowPolicy::DropOldest,
+            other => {
+                eprintln!(
+                    "⚠️ DB_BUFFER_OVERFLOW_POLICY '{}' no reconocido, usando 'backpressure'",
+                    other
+                );
+                BufferOverflowPolicy::Backpressure
+            }
+        };
+
+        let db_insert_mode = match env_opt("DB_INSERT_MODE")
+            .unwrap_or_else(|| "values".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "values" => InsertMode::Values,
+            "unnest" => InsertMode::Unnest,
+            other => {
+                eprintln!(
+                    "⚠️ DB_INSERT_MODE '{}' no reconocido, usando 'values'",
+                    other
+                );
+                InsertMode::Values
+            }
+        };
+
+        let clickhouse = env_opt("CLICKHOUSE_URL").map(|url| ClickHouseConfig {
+            url,
+            database: env_opt("CLICKHOUSE_DATABASE").unwrap_or_else(|| "default".to_string()),
+            username: env_opt("CLICKHOUSE_USERNAME"),
+            password: env_opt("CLICKHOUSE_PASSWORD"),
+            table: env_opt("CLICKHOUSE_TABLE")
+                .unwrap_or_else(|| "communications_{manufacturer}".to_string()),
+            mode: match env_opt("CLICKHOUSE_MODE")
+                .unwrap_or_else(|| "mirror".to_string())
+                .to_lowercase()
+                .as_str()
+            {
+                "mirror" => ClickHouseMode::Mirror,
+                "replace" => ClickHouseMode::Replace,
+                other => {
+                    eprintln!(
+                        "⚠️ CLICKHOUSE_MODE '{}' no reconocido, usando 'mirror'",
+                        other
+                    );
+                    ClickHouseMode::Mirror
+                }
+            },
+        });
+
+        let elasticsearch = env_opt("ELASTICSEARCH_URL").map(|url| ElasticsearchConfig {
+            url,
+            username: env_opt("ELASTICSEARCH_USERNAME"),
+            password: env_opt("ELASTICSEARCH_PASSWORD"),
+            api_key: env_opt("ELASTICSEARCH_API_KEY"),
+            index_prefix: env_opt("ELASTICSEARCH_INDEX_PREFIX")
+                .unwrap_or_else(|| "siscom-positions".to_string()),
+        });
+
+        let redis_state_ttl_secs = env::var("REDIS_STATE_TTL_SECS")
+            .unwrap_or_else(|_| "86400".to_string())
+            .parse::<u64>()
+            .unwrap_or(86400);
+        let redis_state = env_opt("REDIS_URL").map(|url| RedisStateConfig {
+            url,
+            key_prefix: env_opt("REDIS_KEY_PREFIX").unwrap_or_else(|| "device:".to_string()),
+            ttl_secs: redis_state_ttl_secs,
+        });
+
+        let mut maintenance = MaintenanceConfig::default();
+        if let Ok(interval) = env::var("DB_MAINTENANCE_INTERVAL_SECS") {
+            maintenance.interval_secs = interval.parse::<u64>().unwrap_or(3600);
+        }
+        if let Ok(threshold) = env::var("DB_MAINTENANCE_ANALYZE_THRESHOLD") {
+            maintenance.analyze_threshold = threshold.parse::<i64>().unwrap_or(100_000);
+        }
+        if let Ok(batch_size) = env::var("DB_MAINTENANCE_DELETE_BATCH_SIZE") {
+            maintenance.delete_batch_size = batch_size.parse::<i64>().unwrap_or(10_000).max(1);
+        }
+        if let Some(retention) = env_opt("DB_MAINTENANCE_RETENTION") {
+            for entry in retention.split(',') {
+                match entry.split_once('=') {
+                    Some((table, days))
+                        if MAINTENANCE_TABLES.contains(&table.trim())
+                            || Manufacturer::from_name(table).is_some() =>
+                    {
+                        match days.trim().parse::<u32>() {
+                            Ok(days) => {
+                                maintenance
+                                    .retention_days
+                                    .insert(table.trim().to_string(), days);
+                            }
+                            Err(_) => eprintln!(
+                                "⚠️ Días inválidos en DB_MAINTENANCE_RETENTION: '{}'",
+                                entry
+                            ),
+                        }
+                    }
+                    _ => eprintln!(
+                        "⚠️ Entrada inválida en DB_MAINTENANCE_RETENTION: '{}' (tablas: {:?} y {:?})",
+                        entry,
+                        Manufacturer::all().map(|m| m.as_str()).collect::<Vec<_>>(),
+                        MAINTENANCE_TABLES
+                    ),
+                }
+            }
+        }
+
+        let mut fanout = Vec::new();
+        for name in env_opt("DB_FANOUT_TARGETS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let var = |key: &str| env_opt(&format!("DB_FANOUT_{}_{}", name.to_uppercase(), key));
+            let Some(host) = var("HOST") else {
+                eprintln!(
+                    "⚠️ Destino de fan-out '{}' sin DB_FANOUT_{}_HOST, se ignora",
+                    name,
+                    name.to_uppercase()
+                );
+                continue;
+            };
+
+            let buffer_overflow_policy = match var("BUFFER_OVERFLOW_POLICY")
+                .unwrap_or_else(|| "drop_oldest".to_string())
+                .to_lowercase()
+                .as_str()
+            {
+                "backpressure" => BufferOverflowPolicy::Backpressure,
+                "drop_oldest" => BufferOverflowPolicy::DropOldest,
+                other => {
+                    eprintln!(
+                        "⚠️ DB_FANOUT_{}_BUFFER_OVERFLOW_POLICY '{}' no reconocido, usando 'drop_oldest'",
+                        name.to_uppercase(),
+                        other
+                    );
+                    BufferOverflowPolicy::DropOldest
+                }
+            };
+
+            fanout.push(FanoutTargetConfig {
+                name: name.to_string(),
+                host,
+                port: var("PORT")
+                    .and_then(|port| port.parse::<u16>().ok())
+                    .unwrap_or(db_port),
+                database: var("DATABASE").unwrap_or_else(|| db_database.clone()),
+                username: var("USERNAME").unwrap_or_else(|| db_username.clone()),
+                password: var("PASSWORD").unwrap_or_else(|| db_password.clone()),
+                max_connections: var("MAX_CONNECTIONS")
+                    .and_then(|max| max.parse::<u32>().ok())
+                    .unwrap_or(5),
+                buffer_max_records: var("BUFFER_MAX_RECORDS")
+                    .and_then(|max| max.parse::<usize>().ok())
+                    .unwrap_or(db_buffer_max_records),
+                buffer_overflow_policy,
+            });
+        }
+
+        let defaults = TableConfig::default();
+        let db_tables = TableConfig {
+            schema: env_opt("DB_SCHEMA"),
+            history_template: env_opt("DB_HISTORY_TABLE").unwrap_or(defaults.history_template),
+            current_state: env_opt("DB_CURRENT_STATE_TABLE").unwrap_or(defaults.current_state),
+            rejected: env_opt("DB_REJECTED_TABLE").unwrap_or(defaults.rejected),
+            tenant: env_opt("DB_TENANT"),
+        };
+
+        let db_current_state_mode = match env_opt("DB_CURRENT_STATE_MODE")
+            .unwrap_or_else(|| "transactional".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "transactional" => CurrentStateMode::Transactional,
+            "independent" => CurrentStateMode::Independent,
+            "disabled" => CurrentStateMode::Disabled,
+            other => {
+                eprintln!(
+                    "⚠️ DB_CURRENT_STATE_MODE '{}' no reconocido, usando 'transactional'",
+                    other
+                );
+                CurrentStateMode::Transactional
+            }
+        };
+
+        let db_current_state_order = match env_opt("DB_CURRENT_STATE_ORDER_COLUMN")
+            .unwrap_or_else(|| "gps_epoch".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "none" => None,
+            "gps_epoch" => Some(CurrentStateOrder::GpsEpoch),
+            "gps_datetime" => Some(CurrentStateOrder::GpsDatetime),
+            "received_epoch" => Some(CurrentStateOrder::ReceivedEpoch),
+            "decoded_epoch" => Some(CurrentStateOrder::DecodedEpoch),
+            other => {
+                eprintln!(
+                    "⚠️ DB_CURRENT_STATE_ORDER_COLUMN '{}' no reconocido, usando 'gps_epoch'",
+                    other
+                );
+                Some(CurrentStateOrder::GpsEpoch)
+            }
+        };
+
+        // Particionado por tiempo: DB_PARTITION_INTERVAL=none|daily|monthly
+        let partition_interval = match env_opt("DB_PARTITION_INTERVAL")
+            .unwrap_or_else(|| "none".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "none" => None,
+            "daily" => Some(PartitionInterval::Daily),
+            "monthly" => Some(PartitionInterval::Monthly),
+            other => {
+                eprintln!(
+                    "⚠️ DB_PARTITION_INTERVAL '{}' no reconocido, particionado desactivado",
+                    other
+                );
+                None
+            }
+        };
+        let db_partitioning = partition_interval.map(|interval| {
+            let key = match env_opt("DB_PARTITION_KEY")
+                .unwrap_or_else(|| "gps_datetime".to_string())
+                .to_lowercase()
+                .as_str()
+            {
+                "gps_datetime" => PartitionKey::GpsDatetime,
+                "received_at" => PartitionKey::ReceivedAt,
+                other => {
+                    eprintln!(
+                        "⚠️ DB_PARTITION_KEY '{}' no reconocido, usando 'gps_datetime'",
+                        other
+                    );
+                    PartitionKey::GpsDatetime
+                }
+            };
+            let retention_days = env_opt("DB_PARTITION_RETENTION_DAYS")
+                .and_then(|days| days.parse::<u32>().ok())
+                .filter(|days| *days > 0);
+
+            PartitionConfig {
+                interval,
+                key,
+                retention_days,
+            }
+        });
 
         // Processing Configuration
         let processing_worker_threads = env::var("PROCESSING_WORKER_THREADS")
@@ -140,12 +1835,254 @@ impl AppConfig {
             .unwrap_or_else(|_| "50".to_string())
             .parse::<usize>()
             .unwrap_or(50);
+        let processing_dedup_cache_size = env::var("PROCESSING_DEDUP_CACHE_SIZE")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<usize>()
+            .unwrap_or(0);
+        let adaptive_batch = env::var("PROCESSING_ADAPTIVE_BATCH")
+            .map(|value| value.to_lowercase() == "true")
+            .unwrap_or(false)
+            .then(|| {
+                let parse_or = |key: &str, default: u64| {
+                    env::var(key)
+                        .ok()
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .unwrap_or(default)
+                };
+                let batch = processing_batch_size as u64;
+                AdaptiveBatchConfig {
+                    min_batch_size: parse_or("PROCESSING_MIN_BATCH_SIZE", (batch / 4).max(1)).max(1)
+                        as usize,
+                    max_batch_size: parse_or("PROCESSING_MAX_BATCH_SIZE", batch * 10) as usize,
+                    min_flush_interval_ms: parse_or("PROCESSING_MIN_FLUSH_INTERVAL_MS", 100),
+                    max_flush_interval_ms: parse_or("PROCESSING_MAX_FLUSH_INTERVAL_MS", 5000),
+                    target_latency_ms: parse_or("PROCESSING_TARGET_WRITE_LATENCY_MS", 500),
+                    increase_step: parse_or("PROCESSING_BATCH_INCREASE_STEP", (batch / 10).max(1))
+                        .max(1) as usize,
+                }
+            });
+
+        let mut idempotency = IdempotencyConfig::default();
+        if let Some(key) = env_opt("PROCESSING_IDEMPOTENCY_KEY") {
+            idempotency.key = match key.to_lowercase().as_str() {
+                "uuid" => IdempotencyKey::Uuid,
+                "device_counter" => IdempotencyKey::DeviceCounter,
+                other => {
+                    eprintln!(
+                        "⚠️ PROCESSING_IDEMPOTENCY_KEY '{}' no reconocido (uuid, device_counter), usando uuid",
+                        other
+                    );
+                    IdempotencyKey::Uuid
+                }
+            };
+        }
+        if let Some(store) = env_opt("PROCESSING_IDEMPOTENCY_STORE") {
+            idempotency.backend = match store.to_lowercase().as_str() {
+                "memory" => IdempotencyBackend::Memory,
+                "redis" => IdempotencyBackend::Redis,
+                other => {
+                    eprintln!(
+                        "⚠️ PROCESSING_IDEMPOTENCY_STORE '{}' no reconocido (memory, redis), usando memory",
+                        other
+                    );
+                    IdempotencyBackend::Memory
+                }
+            };
+        }
+        if let Some(prefix) = env_opt("PROCESSING_IDEMPOTENCY_KEY_PREFIX") {
+            idempotency.key_prefix = prefix;
+        }
+        if let Ok(ttl) = env::var("PROCESSING_IDEMPOTENCY_TTL_SECS") {
+            idempotency.ttl_secs = ttl.parse::<u64>().unwrap_or(86400);
+        }
+
+        let position_filter = PositionFilterConfig {
+            duplicate_cache_size: env::var("PROCESSING_DUPLICATE_POSITION_CACHE_SIZE")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<usize>()
+                .unwrap_or(0),
+            max_age_secs: env::var("PROCESSING_MAX_POSITION_AGE_SECS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<u64>()
+                .unwrap_or(0),
+        };
+
+        let gps_quality = env_opt("PROCESSING_GPS_QUALITY_POLICY").and_then(|policy| {
+            let policy = match policy.to_lowercase().as_str() {
+                "flag" => GpsQualityPolicy::Flag,
+                "quarantine" => GpsQualityPolicy::Quarantine,
+                "drop" => GpsQualityPolicy::Drop,
+                other => {
+                    eprintln!(
+                        "⚠️ PROCESSING_GPS_QUALITY_POLICY '{}' no reconocido (flag, quarantine, drop), calidad GPS desactivada",
+                        other
+                    );
+                    return None;
+                }
+            };
+            Some(GpsQualityConfig {
+                policy,
+                min_score: env::var("PROCESSING_GPS_MIN_QUALITY_SCORE")
+                    .unwrap_or_else(|_| "50".to_string())
+                    .parse::<u8>()
+                    .unwrap_or(50)
+                    .min(100),
+                max_jump_km: env::var("PROCESSING_GPS_MAX_JUMP_KM")
+                    .unwrap_or_else(|_| "100".to_string())
+                    .parse::<f64>()
+                    .unwrap_or(100.0),
+                max_jump_secs: env::var("PROCESSING_GPS_MAX_JUMP_SECS")
+                    .unwrap_or_else(|_| "60".to_string())
+                    .parse::<u64>()
+                    .unwrap_or(60),
+                min_satellites: env::var("PROCESSING_GPS_MIN_SATELLITES")
+                    .unwrap_or_else(|_| "3".to_string())
+                    .parse::<i32>()
+                    .unwrap_or(3),
+            })
+        });
+
+        let downsampling = DownsamplingConfig {
+            interval_secs: env::var("PROCESSING_DOWNSAMPLE_INTERVAL_SECS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<u64>()
+                .unwrap_or(0),
+            max_devices: env::var("PROCESSING_DOWNSAMPLE_MAX_DEVICES")
+                .unwrap_or_else(|_| "100000".to_string())
+                .parse::<usize>()
+                .unwrap_or(100_000),
+        };
+
+        // Enrutamiento por tenant: fuentes en orden y mapas `clave=tenant`
+        let mut tenant_sources = Vec::new();
+        for name in env_opt("TENANT_SOURCES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name.to_lowercase().as_str() {
+                "topic" => tenant_sources.push(TenantSource::Topic),
+                "device_prefix" => tenant_sources.push(TenantSource::DevicePrefix),
+                "lookup" => tenant_sources.push(TenantSource::Lookup),
+                other => eprintln!(
+                    "⚠️ Fuente de tenant '{}' no reconocida en TENANT_SOURCES, se ignora",
+                    other
+                ),
+            }
+        }
+        let tenant_routing = (!tenant_sources.is_empty()).then(|| TenantRoutingConfig {
+            sources: tenant_sources,
+            topics: parse_tenant_pairs("TENANT_TOPICS"),
+            device_prefixes: parse_tenant_pairs("TENANT_DEVICE_PREFIXES"),
+            map_file: env_opt("TENANT_MAP_FILE"),
+            lanes_per_tenant: env::var("TENANT_LANES")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse::<usize>()
+                .unwrap_or(4)
+                .max(1),
+        });
+
+        // Sanitization: política de desbordamiento y límites por columna (`columna=limite,...`)
+        let mut sanitization = SanitizationConfig::default();
+        if let Some(policy) = env_opt("PROCESSING_FIELD_OVERFLOW_POLICY") {
+            sanitization.policy = match policy.to_lowercase().as_str() {
+                "truncate" => FieldOverflowPolicy::Truncate,
+                "null" => FieldOverflowPolicy::Null,
+                "reject" => FieldOverflowPolicy::Reject,
+                _ => {
+                    eprintln!(
+                        "⚠️ PROCESSING_FIELD_OVERFLOW_POLICY '{}' no reconocido, usando 'truncate'",
+                        policy
+                    );
+                    FieldOverflowPolicy::Truncate
+                }
+            };
+        }
+        if let Some(limits) = env_opt("PROCESSING_COLUMN_LIMITS") {
+            for entry in limits.split(',') {
+                match entry.split_once('=') {
+                    Some((column, limit)) => match limit.trim().parse::<usize>() {
+                        Ok(limit) => {
+                            sanitization
+                                .column_limits
+                                .insert(column.trim().to_string(), limit);
+                        }
+                        Err(_) => eprintln!(
+                            "⚠️ Límite inválido en PROCESSING_COLUMN_LIMITS: '{}'",
+                            entry
+                        ),
+                    },
+                    None => eprintln!(
+                        "⚠️ Entrada inválida en PROCESSING_COLUMN_LIMITS: '{}'",
+                        entry
+                    ),
+                }
+            }
+        }
+
+        let mut enrichment = EnrichmentConfig::default();
+        for name in env_opt("PROCESSING_ENRICHERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name.to_lowercase().as_str() {
+                "speed_unit" => enrichment.enrichers.push(EnricherKind::SpeedUnit),
+                "timezone" => enrichment.enrichers.push(EnricherKind::Timezone),
+                "tenant" => enrichment.enrichers.push(EnricherKind::Tenant),
+                "io_state" => enrichment.enrichers.push(EnricherKind::IoState),
+                other => eprintln!(
+                    "⚠️ Enriquecedor '{}' no reconocido en PROCESSING_ENRICHERS, se ignora",
+                    other
+                ),
+            }
+        }
+        if let Some(units) = env_opt("ENRICH_SPEED_UNITS") {
+            for entry in units.split(',') {
+                let unit = entry
+                    .split_once('=')
+                    .map(|(manufacturer, unit)| (manufacturer.trim(), unit.trim().to_lowercase()));
+                match unit {
+                    Some((manufacturer, unit)) => {
+                        let unit = match unit.as_str() {
+                            "kmh" => SpeedUnit::Kmh,
+                            "knots" => SpeedUnit::Knots,
+                            "mph" => SpeedUnit::Mph,
+                            _ => {
+                                eprintln!(
+                                    "⚠️ Unidad inválida en ENRICH_SPEED_UNITS: '{}' (kmh, knots, mph)",
+                                    entry
+                                );
+                                continue;
+                            }
+                        };
+                        enrichment
+                            .speed_units
+                            .insert(manufacturer.to_lowercase(), unit);
+                    }
+                    None => eprintln!("⚠️ Entrada inválida en ENRICH_SPEED_UNITS: '{}'", entry),
+                }
+            }
+        }
+        if let Some(offset) = env_opt("ENRICH_GPS_UTC_OFFSET") {
+            match offset.parse::<chrono::FixedOffset>() {
+                Ok(offset) => enrichment.gps_utc_offset_minutes = offset.local_minus_utc() / 60,
+                Err(_) => eprintln!(
+                    "⚠️ ENRICH_GPS_UTC_OFFSET '{}' inválido (formato +HH:MM), usando UTC",
+                    offset
+                ),
+            }
+        }
+        enrichment.tenant_map_file = env_opt("ENRICH_TENANT_MAP_FILE");
+        enrichment.io_map_file = env_opt("ENRICH_IO_MAP_FILE");
 
         // Logging Configuration
         let logging_level = env::var("RUST_LOG")
             .or_else(|_| env::var("LOGGING_LEVEL"))
             .unwrap_or_else(|_| "info".to_string());
-        let logging_file_path = env::var("LOGGING_FILE_PATH").ok();
+        let logging_file_path = env_opt("LOGGING_FILE_PATH");
         let logging_max_file_size_mb = env::var("LOGGING_MAX_FILE_SIZE_MB")
             .unwrap_or_else(|_| "100".to_string())
             .parse::<u64>()
@@ -154,10 +2091,660 @@ impl AppConfig {
             .unwrap_or_else(|_| "10".to_string())
             .parse::<u32>()
             .unwrap_or(10);
+        let logging_rotation = env::var("LOGGING_ROTATION")
+            .map(|rotation| rotation.to_lowercase())
+            .ok()
+            .filter(|rotation| matches!(rotation.as_str(), "hourly" | "daily" | "never"))
+            .unwrap_or_else(|| "daily".to_string());
         let logging_json_format = env::var("LOGGING_JSON_FORMAT")
             .unwrap_or_else(|_| "true".to_string())
             .parse::<bool>()
             .unwrap_or(true);
+        let redaction = RedactionConfig {
+            device_id: match env_opt("LOG_REDACT_DEVICE_ID")
+                .unwrap_or_else(|| "none".to_string())
+                .to_lowercase()
+                .as_str()
+            {
+                "none" => DeviceIdRedaction::None,
+                "hash" => DeviceIdRedaction::Hash,
+                other => {
+                    eprintln!(
+                        "⚠️ LOG_REDACT_DEVICE_ID '{}' no reconocido, usando 'hash'",
+                        other
+                    );
+                    DeviceIdRedaction::Hash
+                }
+            },
+            mask_client_ip: env::var("LOG_REDACT_CLIENT_IP")
+                .ok()
+                .and_then(|mask| mask.parse::<bool>().ok())
+                .unwrap_or(false),
+            raw_max_chars: env::var("LOG_REDACT_RAW_MAX_CHARS")
+                .ok()
+                .and_then(|chars| chars.parse::<usize>().ok()),
+            hash_salt: env_opt("LOG_REDACT_SALT"),
+        };
+
+        let archive_max_records = env::var("ARCHIVE_MAX_RECORDS")
+            .unwrap_or_else(|_| "50000".to_string())
+            .parse::<usize>()
+            .unwrap_or(50_000)
+            .max(1);
+        let archive_flush_interval_secs = env::var("ARCHIVE_FLUSH_INTERVAL_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .unwrap_or(300)
+            .max(1);
+        let archive = env_opt("ARCHIVE_S3_BUCKET").map(|bucket| ArchiveConfig {
+            bucket,
+            prefix: env_opt("ARCHIVE_S3_PREFIX").unwrap_or_else(|| "raw".to_string()),
+            endpoint: env_opt("ARCHIVE_S3_ENDPOINT"),
+            region: env_opt("ARCHIVE_S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+            access_key_id: env_opt("ARCHIVE_S3_ACCESS_KEY_ID"),
+            secret_access_key: env_opt("ARCHIVE_S3_SECRET_ACCESS_KEY"),
+            spool_dir: env_opt("ARCHIVE_SPOOL_DIR")
+                .unwrap_or_else(|| "./archive-spool".to_string()),
+            max_records: archive_max_records,
+            flush_interval_secs: archive_flush_interval_secs,
+        });
+
+        let capture_archive = env::var("CAPTURE_ARCHIVE")
+            .ok()
+            .and_then(|enabled| enabled.parse::<bool>().ok())
+            .unwrap_or(false);
+        let capture_dir = if capture_archive {
+            // Sin archivo configurado, validate() lo rechaza
+            Some(match &archive {
+                Some(archive) => format!("{}/captures", archive.spool_dir.trim_end_matches('/')),
+                None => String::new(),
+            })
+        } else {
+            env_opt("CAPTURE_DIR")
+        };
+        let capture = capture_dir.map(|dir| CaptureConfig {
+            dir,
+            archive: capture_archive,
+            max_file_size_mb: env::var("CAPTURE_MAX_FILE_SIZE_MB")
+                .ok()
+                .and_then(|size| size.parse::<u64>().ok())
+                .unwrap_or(100)
+                .max(1),
+            max_file_age_secs: env::var("CAPTURE_MAX_FILE_AGE_SECS")
+                .ok()
+                .and_then(|age| age.parse::<u64>().ok())
+                .unwrap_or(3600)
+                .max(1),
+            queue_size: env::var("CAPTURE_QUEUE_SIZE")
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok())
+                .unwrap_or(10_000)
+                .max(1),
+        });
+
+        let geofence_refresh_secs = env::var("GEOFENCE_REFRESH_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .unwrap_or(300);
+        let geofence = env_opt("GEOFENCE_SOURCE").and_then(|source| {
+            let source = match source.to_lowercase().as_str() {
+                "file" => GeofenceSource::File,
+                "database" | "db" => GeofenceSource::Database,
+                other => {
+                    eprintln!(
+                        "⚠️ GEOFENCE_SOURCE '{}' no reconocido (file, database), geocercas desactivadas",
+                        other
+                    );
+                    return None;
+                }
+            };
+            Some(GeofenceConfig {
+                source,
+                file: env_opt("GEOFENCE_FILE"),
+                refresh_secs: geofence_refresh_secs,
+            })
+        });
+
+        let trips = env::var("TRIPS_ENABLED")
+            .map(|value| value.to_lowercase() == "true")
+            .unwrap_or(false)
+            .then(|| {
+                let defaults = TripConfig::default();
+                TripConfig {
+                    start_speed_kmh: env::var("TRIP_START_SPEED_KMH")
+                        .ok()
+                        .and_then(|speed| speed.parse::<f64>().ok())
+                        .unwrap_or(defaults.start_speed_kmh),
+                    stop_secs: env::var("TRIP_STOP_SECS")
+                        .ok()
+                        .and_then(|secs| secs.parse::<u64>().ok())
+                        .unwrap_or(defaults.stop_secs),
+                }
+            });
+
+        let driving_behavior = env::var("DRIVING_BEHAVIOR_ENABLED")
+            .map(|value| value.to_lowercase() == "true")
+            .unwrap_or(false)
+            .then(|| {
+                let defaults = DrivingThresholds::default();
+                let parse_or = |key: &str, default: f64| {
+                    env::var(key)
+                        .ok()
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .unwrap_or(default)
+                };
+                DrivingBehaviorConfig {
+                    thresholds: DrivingThresholds {
+                        harsh_braking_kmh_s: parse_or(
+                            "DRIVING_HARSH_BRAKING_KMH_S",
+                            defaults.harsh_braking_kmh_s,
+                        ),
+                        harsh_acceleration_kmh_s: parse_or(
+                            "DRIVING_HARSH_ACCELERATION_KMH_S",
+                            defaults.harsh_acceleration_kmh_s,
+                        ),
+                        speed_limit_kmh: parse_or(
+                            "DRIVING_SPEED_LIMIT_KMH",
+                            defaults.speed_limit_kmh,
+                        ),
+                        speeding_min_secs: env::var("DRIVING_SPEEDING_MIN_SECS")
+                            .ok()
+                            .and_then(|secs| secs.parse::<u64>().ok())
+                            .unwrap_or(defaults.speeding_min_secs),
+                    },
+                    tenant_thresholds_file: env_opt("DRIVING_TENANT_THRESHOLDS_FILE"),
+                    max_gap_secs: env::var("DRIVING_MAX_GAP_SECS")
+                        .ok()
+                        .and_then(|secs| secs.parse::<u64>().ok())
+                        .unwrap_or(10),
+                }
+            });
+
+        let driver_events = env::var("DRIVER_EVENTS_ENABLED")
+            .map(|value| value.to_lowercase() == "true")
+            .unwrap_or(false)
+            .then(|| DriverEventsConfig {
+                logout_on_ignition_off: env::var("DRIVER_LOGOUT_ON_IGNITION_OFF")
+                    .map(|value| value.to_lowercase() == "true")
+                    .unwrap_or(false),
+            });
+
+        let power_events = env::var("POWER_EVENTS_ENABLED")
+            .map(|value| value.to_lowercase() == "true")
+            .unwrap_or(false)
+            .then(|| {
+                let defaults = PowerThresholds::default();
+                let parse_or = |key: &str, default: f64| {
+                    env::var(key)
+                        .ok()
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .unwrap_or(default)
+                };
+                PowerEventsConfig {
+                    thresholds: PowerThresholds {
+                        power_lost_volts: parse_or("POWER_LOST_VOLTS", defaults.power_lost_volts),
+                        power_restored_volts: parse_or(
+                            "POWER_RESTORED_VOLTS",
+                            defaults.power_restored_volts,
+                        ),
+                        low_backup_volts: parse_or(
+                            "POWER_LOW_BACKUP_VOLTS",
+                            defaults.low_backup_volts,
+                        ),
+                    },
+                    model_thresholds_file: env_opt("POWER_MODEL_THRESHOLDS_FILE"),
+                }
+            });
+
+        let presence = env::var("DEVICE_OFFLINE_AFTER_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(|offline_after_secs| PresenceConfig {
+                offline_after_secs,
+                check_interval_secs: env::var("DEVICE_OFFLINE_CHECK_SECS")
+                    .ok()
+                    .and_then(|secs| secs.parse::<u64>().ok())
+                    .unwrap_or(60)
+                    .max(1),
+            });
+
+        let watchdog = env::var("WATCHDOG_STALL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(|stall_secs| WatchdogConfig {
+                stall_secs,
+                check_interval_secs: env::var("WATCHDOG_CHECK_SECS")
+                    .ok()
+                    .and_then(|secs| secs.parse::<u64>().ok())
+                    .unwrap_or(10)
+                    .max(1),
+                action: match env_opt("WATCHDOG_ACTION")
+                    .unwrap_or_else(|| "log".to_string())
+                    .to_lowercase()
+                    .as_str()
+                {
+                    "log" => WatchdogAction::Log,
+                    "restart" => WatchdogAction::Restart,
+                    "exit" => WatchdogAction::Exit,
+                    other => {
+                        eprintln!("⚠️ WATCHDOG_ACTION '{}' no reconocido, usando 'log'", other);
+                        WatchdogAction::Log
+                    }
+                },
+            });
+
+        let heartbeat = env::var("HEARTBEAT_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(|interval_secs| HeartbeatConfig {
+                topic: env_opt("HEARTBEAT_TOPIC")
+                    .unwrap_or_else(|| "siscom-consumer-heartbeats".to_string()),
+                interval_secs,
+                instance_id: env_opt("INSTANCE_ID")
+                    .or_else(|| env_opt("HOSTNAME"))
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            });
+
+        let handover = env_opt("HANDOVER_LEASE_KEY").map(|lease_key| HandoverConfig {
+            lease_key,
+            poll_interval_ms: env::var("HANDOVER_POLL_INTERVAL_MS")
+                .ok()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .filter(|ms| *ms > 0)
+                .unwrap_or(1000),
+        });
+
+        // Por defecto el shard es el ordinal del hostname (`consumer-2` en un StatefulSet)
+        let sharding = env::var("SHARD_COUNT")
+            .ok()
+            .and_then(|count| count.parse::<u32>().ok())
+            .map(|count| ShardConfig {
+                index: env_opt("SHARD_INDEX")
+                    .or_else(|| {
+                        env_opt("HOSTNAME")
+                            .and_then(|host| host.rsplit('-').next().map(str::to_string))
+                    })
+                    .and_then(|index| index.parse::<u32>().ok())
+                    .unwrap_or(u32::MAX),
+                count,
+                republish_topic: env_opt("SHARD_REPUBLISH_TOPIC"),
+            });
+
+        let live_tail = env::var("LIVE_TAIL_ENABLED")
+            .ok()
+            .and_then(|enabled| enabled.parse::<bool>().ok())
+            .unwrap_or(false)
+            .then(|| LiveTailConfig {
+                sample_rate: env::var("LIVE_TAIL_SAMPLE_RATE")
+                    .ok()
+                    .and_then(|rate| rate.parse::<u64>().ok())
+                    .unwrap_or(100)
+                    .max(1),
+                redact_fields: env_opt("LIVE_TAIL_REDACT_FIELDS")
+                    .unwrap_or_else(|| "raw,raw_message,client_ip,client_port".to_string())
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|field| !field.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            });
+
+        let mut webhook_targets = Vec::new();
+        for name in env_opt("WEBHOOK_TARGETS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let var = |key: &str| env_opt(&format!("WEBHOOK_{}_{}", name.to_uppercase(), key));
+            let list = |key: &str| -> Vec<String> {
+                var(key)
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            let Some(url) = var("URL") else {
+                eprintln!(
+                    "⚠️ Webhook '{}' sin WEBHOOK_{}_URL, se ignora",
+                    name,
+                    name.to_uppercase()
+                );
+                continue;
+            };
+            webhook_targets.push(WebhookTargetConfig {
+                name: name.to_string(),
+                url,
+                secret: var("SECRET"),
+                events: list("EVENTS"),
+                tenants: list("TENANTS"),
+            });
+        }
+        let webhooks = (!webhook_targets.is_empty()).then(|| WebhookConfig {
+            targets: webhook_targets,
+            retry: RetryConfig {
+                max_attempts: env::var("WEBHOOK_RETRY_MAX_ATTEMPTS")
+                    .ok()
+                    .and_then(|attempts| attempts.parse::<u32>().ok())
+                    .unwrap_or(5)
+                    .max(1),
+                base_delay_ms: env::var("WEBHOOK_RETRY_BASE_DELAY_MS")
+                    .ok()
+                    .and_then(|delay| delay.parse::<u64>().ok())
+                    .unwrap_or(1000),
+                max_delay_ms: env::var("WEBHOOK_RETRY_MAX_DELAY_MS")
+                    .ok()
+                    .and_then(|delay| delay.parse::<u64>().ok())
+                    .unwrap_or(60_000),
+            },
+            timeout_ms: env::var("WEBHOOK_TIMEOUT_MS")
+                .ok()
+                .and_then(|timeout| timeout.parse::<u64>().ok())
+                .unwrap_or(5000),
+        });
+
+        let payload_auth = Some(parse_tenant_pairs("PAYLOAD_AUTH_KEYS"))
+            .filter(|keys| !keys.is_empty())
+            .map(|keys| PayloadAuthConfig {
+                keys,
+                signature_header: env_opt("PAYLOAD_AUTH_SIGNATURE_HEADER")
+                    .unwrap_or_else(|| "x-siscom-signature".to_string()),
+                key_id_header: env_opt("PAYLOAD_AUTH_KEY_ID_HEADER")
+                    .unwrap_or_else(|| "x-siscom-key-id".to_string()),
+            });
+
+        let ingest = env_opt("INGEST_BIND").map(|bind| IngestConfig {
+            bind,
+            tokens: env_opt("INGEST_TOKENS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|token| !token.is_empty())
+                .map(str::to_string)
+                .collect(),
+            max_batch: env::var("INGEST_MAX_BATCH")
+                .ok()
+                .and_then(|max| max.parse::<usize>().ok())
+                .unwrap_or(1000)
+                .max(1),
+        });
+
+        let mqtt_publish = env_opt("MQTT_PUBLISH_URL").map(|url| MqttPublishConfig {
+            url,
+            client_id: env_opt("MQTT_PUBLISH_CLIENT_ID").unwrap_or_else(|| {
+                format!(
+                    "siscom-consumer-{}",
+                    env_opt("HOSTNAME")
+                        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+                )
+            }),
+            username: env_opt("MQTT_PUBLISH_USERNAME"),
+            password: env_opt("MQTT_PUBLISH_PASSWORD"),
+            topic: env_opt("MQTT_PUBLISH_TOPIC")
+                .unwrap_or_else(|| "normalized/{manufacturer}/{device_id}".to_string()),
+            qos: env::var("MQTT_PUBLISH_QOS")
+                .ok()
+                .and_then(|qos| qos.parse::<u8>().ok())
+                .unwrap_or(0),
+            retain: env::var("MQTT_PUBLISH_RETAIN")
+                .ok()
+                .and_then(|retain| retain.parse::<bool>().ok())
+                .unwrap_or(false),
+            keep_alive_secs: env::var("MQTT_PUBLISH_KEEP_ALIVE_SECS")
+                .ok()
+                .and_then(|secs| secs.parse::<u16>().ok())
+                .unwrap_or(30)
+                .max(1),
+            queue_size: env::var("MQTT_PUBLISH_QUEUE_SIZE")
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok())
+                .unwrap_or(10_000)
+                .max(1),
+        });
+
+        // Sin URL propia se usa el broker de la republicación, con otro client id
+        let device_acks = env_opt("DEVICE_ACK_TOPIC").map(|topic| DeviceAckConfig {
+            mqtt: MqttPublishConfig {
+                url: env_opt("DEVICE_ACK_MQTT_URL")
+                    .or_else(|| env_opt("MQTT_PUBLISH_URL"))
+                    .unwrap_or_default(),
+                client_id: env_opt("DEVICE_ACK_CLIENT_ID").unwrap_or_else(|| {
+                    format!(
+                        "siscom-consumer-acks-{}",
+                        env_opt("HOSTNAME")
+                            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+                    )
+                }),
+                username: env_opt("DEVICE_ACK_MQTT_USERNAME")
+                    .or_else(|| env_opt("MQTT_PUBLISH_USERNAME")),
+                password: env_opt("DEVICE_ACK_MQTT_PASSWORD")
+                    .or_else(|| env_opt("MQTT_PUBLISH_PASSWORD")),
+                topic,
+                qos: env::var("DEVICE_ACK_QOS")
+                    .ok()
+                    .and_then(|qos| qos.parse::<u8>().ok())
+                    .unwrap_or(1),
+                retain: false,
+                keep_alive_secs: 30,
+                queue_size: env::var("DEVICE_ACK_QUEUE_SIZE")
+                    .ok()
+                    .and_then(|size| size.parse::<usize>().ok())
+                    .unwrap_or(10_000)
+                    .max(1),
+            },
+            reports: env_opt("DEVICE_ACK_REPORTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|report| report.trim().to_uppercase())
+                .filter(|report| !report.is_empty())
+                .collect(),
+        });
+
+        // Igual que las confirmaciones: sin URL propia, el broker de la republicación
+        let device_commands = env_opt("DEVICE_COMMAND_TOPIC").map(|topic| DeviceCommandConfig {
+            mqtt: MqttPublishConfig {
+                url: env_opt("DEVICE_COMMAND_MQTT_URL")
+                    .or_else(|| env_opt("MQTT_PUBLISH_URL"))
+                    .unwrap_or_default(),
+                client_id: env_opt("DEVICE_COMMAND_CLIENT_ID").unwrap_or_else(|| {
+                    format!(
+                        "siscom-consumer-commands-{}",
+                        env_opt("HOSTNAME")
+                            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+                    )
+                }),
+                username: env_opt("DEVICE_COMMAND_MQTT_USERNAME")
+                    .or_else(|| env_opt("MQTT_PUBLISH_USERNAME")),
+                password: env_opt("DEVICE_COMMAND_MQTT_PASSWORD")
+                    .or_else(|| env_opt("MQTT_PUBLISH_PASSWORD")),
+                topic,
+                qos: env::var("DEVICE_COMMAND_QOS")
+                    .ok()
+                    .and_then(|qos| qos.parse::<u8>().ok())
+                    .unwrap_or(1),
+                retain: false,
+                keep_alive_secs: 30,
+                queue_size: env::var("DEVICE_COMMAND_QUEUE_SIZE")
+                    .ok()
+                    .and_then(|size| size.parse::<usize>().ok())
+                    .unwrap_or(1000)
+                    .max(1),
+            },
+            kafka_topic: env_opt("DEVICE_COMMAND_KAFKA_TOPIC"),
+            timeout_secs: env::var("DEVICE_COMMAND_TIMEOUT_SECS")
+                .ok()
+                .and_then(|secs| secs.parse::<u64>().ok())
+                .unwrap_or(30)
+                .max(1),
+        });
+
+        let redis_stream = env_opt("REDIS_STREAM_KEY").map(|key| RedisStreamConfig {
+            url: env_opt("REDIS_STREAM_URL")
+                .or_else(|| env_opt("REDIS_URL"))
+                .unwrap_or_default(),
+            key,
+            max_len: env::var("REDIS_STREAM_MAXLEN")
+                .ok()
+                .and_then(|len| len.parse::<usize>().ok())
+                .unwrap_or(100_000),
+        });
+
+        let sqs_queue_url = env_opt("AWS_SQS_QUEUE_URL");
+        let sns_topic_arn = env_opt("AWS_SNS_TOPIC_ARN");
+        let aws = (sqs_queue_url.is_some() || sns_topic_arn.is_some()).then(|| AwsSinkConfig {
+            region: env_opt("AWS_REGION")
+                .or_else(|| env_opt("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: env_opt("AWS_SINK_ENDPOINT"),
+            sqs_queue_url,
+            sns_topic_arn,
+            sqs_batch_size: env::var("AWS_SQS_BATCH_SIZE")
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok())
+                .unwrap_or(10)
+                .clamp(1, 10),
+            sqs_flush_interval_ms: env::var("AWS_SQS_FLUSH_INTERVAL_MS")
+                .ok()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .unwrap_or(1000),
+            queue_size: env::var("AWS_SINK_QUEUE_SIZE")
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok())
+                .unwrap_or(10_000)
+                .max(1),
+            retry: RetryConfig {
+                max_attempts: env::var("AWS_RETRY_MAX_ATTEMPTS")
+                    .ok()
+                    .and_then(|attempts| attempts.parse::<u32>().ok())
+                    .unwrap_or(5)
+                    .max(1),
+                base_delay_ms: env::var("AWS_RETRY_BASE_DELAY_MS")
+                    .ok()
+                    .and_then(|delay| delay.parse::<u64>().ok())
+                    .unwrap_or(500),
+                max_delay_ms: env::var("AWS_RETRY_MAX_DELAY_MS")
+                    .ok()
+                    .and_then(|delay| delay.parse::<u64>().ok())
+                    .unwrap_or(30_000),
+            },
+        });
+
+        let mut output_sinks = OutputSinksConfig::default();
+        match env_opt("OUTPUT_SINKS") {
+            Some(names) => {
+                for name in names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                {
+                    match name.to_lowercase().as_str() {
+                        "mqtt" => output_sinks.sinks.push(SinkKind::Mqtt),
+                        "sqs" => output_sinks.sinks.push(SinkKind::Sqs),
+                        "redis_stream" => output_sinks.sinks.push(SinkKind::RedisStream),
+                        other => eprintln!(
+                            "⚠️ Salida '{}' no reconocida en OUTPUT_SINKS, se ignora",
+                            other
+                        ),
+                    }
+                }
+            }
+            None => {
+                if mqtt_publish.is_some() {
+                    output_sinks.sinks.push(SinkKind::Mqtt);
+                }
+                if aws.as_ref().is_some_and(|aws| aws.sqs_queue_url.is_some()) {
+                    output_sinks.sinks.push(SinkKind::Sqs);
+                }
+                if redis_stream.is_some() {
+                    output_sinks.sinks.push(SinkKind::RedisStream);
+                }
+            }
+        }
+        if let Some(size) = env::var("OUTPUT_SINK_QUEUE_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<usize>().ok())
+        {
+            output_sinks.queue_size = size.max(1);
+        }
+        if let Some(attempts) = env::var("OUTPUT_SINK_RETRY_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|attempts| attempts.parse::<u32>().ok())
+        {
+            output_sinks.retry.max_attempts = attempts.max(1);
+        }
+        if let Some(delay) = env::var("OUTPUT_SINK_RETRY_BASE_DELAY_MS")
+            .ok()
+            .and_then(|delay| delay.parse::<u64>().ok())
+        {
+            output_sinks.retry.base_delay_ms = delay;
+        }
+        if let Some(delay) = env::var("OUTPUT_SINK_RETRY_MAX_DELAY_MS")
+            .ok()
+            .and_then(|delay| delay.parse::<u64>().ok())
+        {
+            output_sinks.retry.max_delay_ms = delay;
+        }
+
+        let tracing = env_opt("OTEL_EXPORTER_OTLP_ENDPOINT").map(|endpoint| TracingConfig {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            service_name: env_opt("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|| "siscom-consumer".to_string()),
+            sample_ratio: env::var("OTEL_TRACES_SAMPLER_ARG")
+                .ok()
+                .and_then(|ratio| ratio.parse::<f64>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0),
+        });
+
+        let mut supervisor = SupervisorConfig::default();
+        if let Ok(max_restarts) = env::var("SUPERVISOR_MAX_RESTARTS") {
+            supervisor.max_restarts = max_restarts.parse::<u32>().unwrap_or(5);
+        }
+        if let Ok(backoff) = env::var("SUPERVISOR_INITIAL_BACKOFF_MS") {
+            supervisor.initial_backoff_ms = backoff.parse::<u64>().unwrap_or(1000);
+        }
+        if let Ok(backoff) = env::var("SUPERVISOR_MAX_BACKOFF_MS") {
+            supervisor.max_backoff_ms = backoff.parse::<u64>().unwrap_or(30000);
+        }
+        if let Ok(stable) = env::var("SUPERVISOR_STABLE_SECS") {
+            supervisor.stable_secs = stable.parse::<u64>().unwrap_or(300);
+        }
+
+        let mut startup = StartupConfig::default();
+        if let Ok(max_wait) = env::var("STARTUP_MAX_WAIT_SECS") {
+            startup.max_wait_secs = max_wait.parse::<u64>().unwrap_or(60);
+        }
+        if let Ok(backoff) = env::var("STARTUP_INITIAL_BACKOFF_MS") {
+            startup.initial_backoff_ms = backoff.parse::<u64>().unwrap_or(500);
+        }
+        if let Ok(backoff) = env::var("STARTUP_MAX_BACKOFF_MS") {
+            startup.max_backoff_ms = backoff.parse::<u64>().unwrap_or(10000);
+        }
+
+        let health = HealthConfig {
+            check_interval_secs: env::var("HEALTH_CHECK_INTERVAL_SECS")
+                .ok()
+                .and_then(|secs| secs.parse::<u64>().ok())
+                .unwrap_or(10)
+                .max(1),
+            liveness_grace_secs: env::var("HEALTH_LIVENESS_GRACE_SECS")
+                .ok()
+                .and_then(|secs| secs.parse::<u64>().ok())
+                .unwrap_or(300),
+            max_queue_depth: env::var("HEALTH_MAX_QUEUE_DEPTH")
+                .ok()
+                .and_then(|depth| depth.parse::<usize>().ok())
+                .unwrap_or(processing_message_buffer_size),
+            max_db_buffer: env::var("HEALTH_MAX_DB_BUFFER")
+                .ok()
+                .and_then(|records| records.parse::<usize>().ok())
+                .unwrap_or(db_buffer_max_records),
+        };
 
         Ok(Self {
             broker: BrokerConfig {
@@ -165,6 +2752,11 @@ impl AppConfig {
                 host: broker_host,
                 topic: broker_topic,
                 group_id: broker_group_id,
+                kafka,
+                amqp,
+                schema_registry,
+                payload_format,
+                notifications_topic: env_opt("KAFKA_NOTIFICATIONS_TOPIC"),
             },
             database: DatabaseConfig {
                 host: db_host,
@@ -176,32 +2768,142 @@ impl AppConfig {
                 min_connections: db_min_connections,
                 connection_timeout_secs: db_connection_timeout_secs,
                 idle_timeout_secs: db_idle_timeout_secs,
+                run_migrations: db_run_migrations,
+                partitioning: db_partitioning,
+                retry: db_retry,
+                circuit_probe_interval_secs: db_circuit_probe_interval_secs,
+                flush_interval_ms: db_flush_interval_ms,
+                buffer_max_age_secs: db_buffer_max_age_secs,
+                buffer_max_records: db_buffer_max_records,
+                buffer_overflow_policy: db_buffer_overflow_policy,
+                insert_mode: db_insert_mode,
+                clickhouse,
+                elasticsearch,
+                redis_state,
+                maintenance,
+                fanout,
+                tables: db_tables,
+                current_state_mode: db_current_state_mode,
+                current_state_order: db_current_state_order,
+                audit: db_audit,
+                raw_encryption,
             },
             processing: ProcessingConfig {
                 worker_threads: processing_worker_threads,
                 message_buffer_size: processing_message_buffer_size,
                 batch_processing_size: processing_batch_size,
                 max_parallel_devices: processing_max_parallel,
+                dedup_cache_size: processing_dedup_cache_size,
+                idempotency,
+                position_filter,
+                gps_quality,
+                downsampling,
+                drain_timeout_secs: env::var("PROCESSING_DRAIN_TIMEOUT_SECS")
+                    .unwrap_or_else(|_| "30".to_string())
+                    .parse::<u64>()
+                    .unwrap_or(30),
+                memory_soft_limit_mb: env::var("PROCESSING_MEMORY_SOFT_LIMIT_MB")
+                    .ok()
+                    .and_then(|mb| mb.parse::<u64>().ok())
+                    .filter(|mb| *mb > 0),
+                adaptive_batch,
+                field_mapping_file: env_opt("PROCESSING_FIELD_MAPPING_FILE"),
+                tenant_routing,
+                sanitization,
+                enrichment,
+                dry_run: env::var("PIPELINE_DRY_RUN")
+                    .ok()
+                    .and_then(|enabled| enabled.parse::<bool>().ok())
+                    .unwrap_or(false),
             },
             logging: LoggingConfig {
                 level: logging_level,
                 file_path: logging_file_path,
                 max_file_size_mb: logging_max_file_size_mb,
                 max_files: logging_max_files,
+                rotation: logging_rotation,
                 json_format: logging_json_format,
+                sentry_dsn: env_opt("SENTRY_DSN"),
+                error_webhook_url: env_opt("ERROR_WEBHOOK_URL"),
+                redaction,
             },
+            archive,
+            capture,
+            admin: env_opt("ADMIN_BIND").map(|bind| AdminConfig { bind }),
+            grpc_admin: env_opt("GRPC_ADMIN_BIND").map(|bind| AdminConfig { bind }),
+            geofence,
+            trips,
+            driving_behavior,
+            driver_events,
+            power_events,
+            presence,
+            tracing,
+            supervisor,
+            startup,
+            watchdog,
+            heartbeat,
+            handover,
+            sharding,
+            live_tail,
+            webhooks,
+            ingest,
+            payload_auth,
+            mqtt_publish,
+            device_acks,
+            device_commands,
+            redis_stream,
+            aws,
+            output_sinks,
+            health,
         })
     }
 
-    /// Obtiene la URL de conexión a PostgreSQL
-    pub fn database_url(&self) -> String {
-        format!(
-            "postgresql://{}:{}@{}:{}/{}",
-            self.database.username,
-            self.database.password,
-            self.database.host,
+    /// Configuración del modo dry-run: sin los destinos que escriben fuera del proceso
+    /// (migraciones, mantenimiento, copias de la BD, salidas, notificaciones, archivo)
+    /// y con un grupo de consumidores propio para no mover los offsets del real
+    pub fn without_side_effects(mut self) -> Self {
+        self.processing.dry_run = true;
+        self.broker.group_id = format!("{}-dry-run", self.broker.group_id);
+        self.broker.notifications_topic = None;
+        self.database.run_migrations = false;
+        self.database.partitioning = None;
+        self.database.maintenance.interval_secs = 0;
+        self.database.audit = false;
+        self.database.clickhouse = None;
+        self.database.elasticsearch = None;
+        self.database.fanout.clear();
+        self.archive = None;
+        self.geofence = None;
+        self.trips = None;
+        self.driving_behavior = None;
+        self.driver_events = None;
+        self.power_events = None;
+        self.presence = None;
+        self.heartbeat = None;
+        self.handover = None;
+        // Sin republicar: los mensajes de otros shards se descartan
+        if let Some(sharding) = &mut self.sharding {
+            sharding.republish_topic = None;
+        }
+        self.webhooks = None;
+        self.mqtt_publish = None;
+        self.device_acks = None;
+        self.device_commands = None;
+        self.redis_stream = None;
+        self.aws = None;
+        self.output_sinks.sinks.clear();
+        self
+    }
+
+    /// Obtiene las URLs de conexión a PostgreSQL, una por host de `DB_HOST`
+    /// (`primario,replica1:5433,...`), en orden de preferencia para el failover
+    pub fn database_urls(&self) -> Vec<String> {
+        postgres_urls(
+            &self.database.host,
             self.database.port,
-            self.database.database
+            &self.database.username,
+            &self.database.password,
+            &self.database.database,
         )
     }
 
@@ -216,8 +2918,84 @@ impl AppConfig {
             return Err(anyhow::anyhow!("Broker topic no puede estar vacío"));
         }
 
+        // Validar configuración de seguridad de Kafka
+        let kafka = &self.broker.kafka;
+        if let Some(mechanism) = &kafka.sasl_mechanism {
+            let mechanism = mechanism.to_uppercase();
+            if !SUPPORTED_SASL_MECHANISMS.contains(&mechanism.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "KAFKA_SASL_MECHANISM '{}' no soportado (usar uno de {:?})",
+                    mechanism,
+                    SUPPORTED_SASL_MECHANISMS
+                ));
+            }
+
+            let protocol = kafka
+                .security_protocol
+                .as_deref()
+                .unwrap_or("")
+                .to_uppercase();
+            if !protocol.starts_with("SASL_") {
+                return Err(anyhow::anyhow!(
+                    "KAFKA_SASL_MECHANISM requiere KAFKA_SECURITY_PROTOCOL SASL_PLAINTEXT o SASL_SSL"
+                ));
+            }
+
+            if mechanism != "OAUTHBEARER"
+                && (kafka.sasl_username.is_none() || kafka.sasl_password.is_none())
+            {
+                return Err(anyhow::anyhow!(
+                    "SASL {} requiere KAFKA_USERNAME y KAFKA_PASSWORD",
+                    mechanism
+                ));
+            }
+        }
+
+        // Notificaciones y heartbeat se publican con un productor Kafka en BROKER_HOST
+        if self.broker.broker_type == BrokerType::Amqp {
+            if self.broker.notifications_topic.is_some() || self.heartbeat.is_some() {
+                return Err(anyhow::anyhow!(
+                    "KAFKA_NOTIFICATIONS_TOPIC y HEARTBEAT_INTERVAL_SECS requieren BROKER_TYPE=kafka"
+                ));
+            }
+            if !self.broker.amqp.routing_keys.is_empty() && self.broker.amqp.exchange.is_none() {
+                return Err(anyhow::anyhow!("AMQP_ROUTING_KEYS requiere AMQP_EXCHANGE"));
+            }
+        }
+
+        if let Some(sharding) = &self.sharding {
+            if sharding.count == 0 || sharding.index >= sharding.count {
+                return Err(anyhow::anyhow!(
+                    "SHARD_INDEX (o el ordinal del hostname) debe estar entre 0 y SHARD_COUNT - 1 (SHARD_COUNT={})",
+                    sharding.count
+                ));
+            }
+            if let Some(topic) = &sharding.republish_topic {
+                if self.broker.broker_type != BrokerType::Kafka {
+                    return Err(anyhow::anyhow!(
+                        "SHARD_REPUBLISH_TOPIC requiere BROKER_TYPE=kafka"
+                    ));
+                }
+                if !topic.contains("{shard}") {
+                    return Err(anyhow::anyhow!(
+                        "SHARD_REPUBLISH_TOPIC debe incluir {{shard}}"
+                    ));
+                }
+            }
+        }
+
+        if self
+            .ingest
+            .as_ref()
+            .is_some_and(|ingest| ingest.tokens.is_empty())
+        {
+            return Err(anyhow::anyhow!(
+                "INGEST_BIND requiere al menos un token en INGEST_TOKENS"
+            ));
+        }
+
         // Validar configuración de base de datos
-        if self.database.host.is_empty() {
+        if self.database_urls().is_empty() {
             return Err(anyhow::anyhow!("Database host no puede estar vacío"));
         }
 
@@ -225,6 +3003,11 @@ impl AppConfig {
             return Err(anyhow::anyhow!("Database name no puede estar vacío"));
         }
 
+        self.database.tables.validate()?;
+        if let Some(raw_encryption) = &self.database.raw_encryption {
+            raw_encryption.validate()?;
+        }
+
         // Validar configuración de procesamiento
         if self.processing.batch_processing_size == 0 {
             return Err(anyhow::anyhow!("Batch processing size debe ser mayor a 0"));
@@ -234,6 +3017,166 @@ impl AppConfig {
             return Err(anyhow::anyhow!("Worker threads debe ser mayor a 0"));
         }
 
+        if let Some(adaptive) = &self.processing.adaptive_batch {
+            if adaptive.min_batch_size > adaptive.max_batch_size
+                || adaptive.min_flush_interval_ms > adaptive.max_flush_interval_ms
+            {
+                return Err(anyhow::anyhow!(
+                    "Los mínimos de lote adaptativo deben ser menores o iguales a los máximos"
+                ));
+            }
+        }
+
+        if self.processing.idempotency.backend == IdempotencyBackend::Redis
+            && self.database.redis_state.is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "PROCESSING_IDEMPOTENCY_STORE=redis requiere REDIS_URL"
+            ));
+        }
+
+        let enrichment = &self.processing.enrichment;
+        if enrichment.enrichers.contains(&EnricherKind::Tenant)
+            && enrichment.tenant_map_file.is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "El enriquecedor 'tenant' requiere ENRICH_TENANT_MAP_FILE"
+            ));
+        }
+        if enrichment.enrichers.contains(&EnricherKind::IoState) && enrichment.io_map_file.is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "El enriquecedor 'io_state' requiere ENRICH_IO_MAP_FILE"
+            ));
+        }
+
+        if let Some(routing) = &self.processing.tenant_routing {
+            let missing = routing.sources.iter().find_map(|source| match source {
+                TenantSource::Topic if routing.topics.is_empty() => Some("TENANT_TOPICS"),
+                TenantSource::DevicePrefix if routing.device_prefixes.is_empty() => {
+                    Some("TENANT_DEVICE_PREFIXES")
+                }
+                TenantSource::Lookup if routing.map_file.is_none() => Some("TENANT_MAP_FILE"),
+                _ => None,
+            });
+            if let Some(variable) = missing {
+                return Err(anyhow::anyhow!(
+                    "TENANT_SOURCES usa una fuente que requiere {}",
+                    variable
+                ));
+            }
+        }
+
+        if self.driving_behavior.is_some()
+            && self.broker.notifications_topic.is_none()
+            && self.webhooks.is_none()
+            && self
+                .aws
+                .as_ref()
+                .is_none_or(|aws| aws.sns_topic_arn.is_none())
+        {
+            return Err(anyhow::anyhow!(
+                "DRIVING_BEHAVIOR_ENABLED requiere KAFKA_NOTIFICATIONS_TOPIC, WEBHOOK_TARGETS o AWS_SNS_TOPIC_ARN"
+            ));
+        }
+
+        if self.capture.as_ref().is_some_and(|capture| capture.archive) && self.archive.is_none() {
+            return Err(anyhow::anyhow!(
+                "CAPTURE_ARCHIVE requiere ARCHIVE_S3_BUCKET"
+            ));
+        }
+
+        if self
+            .redis_stream
+            .as_ref()
+            .is_some_and(|stream| stream.url.is_empty())
+        {
+            return Err(anyhow::anyhow!(
+                "REDIS_STREAM_KEY requiere REDIS_STREAM_URL o REDIS_URL"
+            ));
+        }
+
+        for sink in &self.output_sinks.sinks {
+            let (configured, name, variable) = match sink {
+                SinkKind::Mqtt => (self.mqtt_publish.is_some(), "mqtt", "MQTT_PUBLISH_URL"),
+                SinkKind::Sqs => (
+                    self.aws
+                        .as_ref()
+                        .is_some_and(|aws| aws.sqs_queue_url.is_some()),
+                    "sqs",
+                    "AWS_SQS_QUEUE_URL",
+                ),
+                SinkKind::RedisStream => (
+                    self.redis_stream.is_some(),
+                    "redis_stream",
+                    "REDIS_STREAM_KEY",
+                ),
+            };
+            if !configured {
+                return Err(anyhow::anyhow!(
+                    "OUTPUT_SINKS incluye {}, que requiere {}",
+                    name,
+                    variable
+                ));
+            }
+        }
+
+        if let Some(mqtt) = &self.mqtt_publish {
+            if mqtt.qos > 2 {
+                return Err(anyhow::anyhow!("MQTT_PUBLISH_QOS debe ser 0, 1 o 2"));
+            }
+            if !mqtt.url.starts_with("mqtt://") && !mqtt.url.starts_with("mqtts://") {
+                return Err(anyhow::anyhow!(
+                    "MQTT_PUBLISH_URL debe empezar con mqtt:// o mqtts://"
+                ));
+            }
+        }
+
+        if let Some(acks) = &self.device_acks {
+            if acks.mqtt.qos > 2 {
+                return Err(anyhow::anyhow!("DEVICE_ACK_QOS debe ser 0, 1 o 2"));
+            }
+            if !acks.mqtt.url.starts_with("mqtt://") && !acks.mqtt.url.starts_with("mqtts://") {
+                return Err(anyhow::anyhow!(
+                    "DEVICE_ACK_TOPIC requiere DEVICE_ACK_MQTT_URL o MQTT_PUBLISH_URL con mqtt:// o mqtts://"
+                ));
+            }
+        }
+
+        if let Some(power) = &self.power_events {
+            if power.thresholds.power_restored_volts < power.thresholds.power_lost_volts {
+                return Err(anyhow::anyhow!(
+                    "POWER_RESTORED_VOLTS debe ser mayor o igual que POWER_LOST_VOLTS"
+                ));
+            }
+        }
+
+        if let Some(commands) = &self.device_commands {
+            if commands.mqtt.qos > 2 {
+                return Err(anyhow::anyhow!("DEVICE_COMMAND_QOS debe ser 0, 1 o 2"));
+            }
+            if !commands.mqtt.url.starts_with("mqtt://")
+                && !commands.mqtt.url.starts_with("mqtts://")
+            {
+                return Err(anyhow::anyhow!(
+                    "DEVICE_COMMAND_TOPIC requiere DEVICE_COMMAND_MQTT_URL o MQTT_PUBLISH_URL con mqtt:// o mqtts://"
+                ));
+            }
+            if commands.kafka_topic.is_some() && self.broker.broker_type != BrokerType::Kafka {
+                return Err(anyhow::anyhow!(
+                    "DEVICE_COMMAND_KAFKA_TOPIC requiere BROKER_TYPE=kafka"
+                ));
+            }
+        }
+
+        if let Some(geofence) = &self.geofence {
+            if geofence.source == GeofenceSource::File && geofence.file.is_none() {
+                return Err(anyhow::anyhow!(
+                    "GEOFENCE_SOURCE=file requiere GEOFENCE_FILE"
+                ));
+            }
+        }
+
         Ok(())
     }
 
@@ -245,6 +3188,11 @@ impl AppConfig {
                 host: "127.0.0.1:9092".to_string(),
                 topic: "siscom-messages".to_string(),
                 group_id: "siscom-consumer-group".to_string(),
+                kafka: KafkaConfig::default(),
+                amqp: AmqpConfig::default(),
+                schema_registry: None,
+                payload_format: PayloadFormat::default(),
+                notifications_topic: None,
             },
             database: DatabaseConfig {
                 host: "localhost".to_string(),
@@ -256,19 +3204,88 @@ impl AppConfig {
                 min_connections: 5,
                 connection_timeout_secs: 30,
                 idle_timeout_secs: 600,
+                run_migrations: false,
+                partitioning: None,
+                retry: RetryConfig::default(),
+                circuit_probe_interval_secs: 5,
+                flush_interval_ms: 1000,
+                buffer_max_age_secs: 5,
+                buffer_max_records: 100_000,
+                buffer_overflow_policy: BufferOverflowPolicy::default(),
+                insert_mode: InsertMode::default(),
+                clickhouse: None,
+                elasticsearch: None,
+                redis_state: None,
+                maintenance: MaintenanceConfig::default(),
+                fanout: Vec::new(),
+                tables: TableConfig::default(),
+                current_state_mode: CurrentStateMode::default(),
+                current_state_order: Some(CurrentStateOrder::GpsEpoch),
+                audit: false,
+                raw_encryption: None,
             },
             processing: ProcessingConfig {
                 worker_threads: 4,
                 message_buffer_size: 10000,
                 batch_processing_size: 100,
                 max_parallel_devices: 50,
+                dedup_cache_size: 0,
+                idempotency: IdempotencyConfig::default(),
+                position_filter: PositionFilterConfig::default(),
+                gps_quality: None,
+                downsampling: DownsamplingConfig::default(),
+                drain_timeout_secs: 30,
+                memory_soft_limit_mb: None,
+                adaptive_batch: None,
+                field_mapping_file: None,
+                tenant_routing: None,
+                sanitization: SanitizationConfig::default(),
+                enrichment: EnrichmentConfig::default(),
+                dry_run: false,
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
                 file_path: None,
                 max_file_size_mb: 100,
                 max_files: 10,
+                rotation: "daily".to_string(),
                 json_format: true,
+                sentry_dsn: None,
+                error_webhook_url: None,
+                redaction: RedactionConfig::default(),
+            },
+            archive: None,
+            capture: None,
+            admin: None,
+            grpc_admin: None,
+            geofence: None,
+            trips: None,
+            driving_behavior: None,
+            driver_events: None,
+            power_events: None,
+            presence: None,
+            tracing: None,
+            supervisor: SupervisorConfig::default(),
+            startup: StartupConfig::default(),
+            watchdog: None,
+            heartbeat: None,
+            handover: None,
+            sharding: None,
+            live_tail: None,
+            webhooks: None,
+            ingest: None,
+            payload_auth: None,
+            mqtt_publish: None,
+            device_acks: None,
+            device_commands: None,
+            redis_stream: None,
+            aws: None,
+            output_sinks: OutputSinksConfig::default(),
+            health: HealthConfig {
+                check_interval_secs: 10,
+                liveness_grace_secs: 300,
+                max_queue_depth: 10000,
+                max_db_buffer: 100_000,
             },
         }
     }
@@ -277,18 +3294,156 @@ impl AppConfig {
     pub fn display_safe(&self) -> AppConfigSafe {
         AppConfigSafe {
             broker: BrokerConfigSafe {
-                broker_type: "kafka".to_string(),
+                broker_type: self.broker.broker_type.as_str().to_string(),
                 host: self.broker.host.clone(),
                 topic: self.broker.topic.clone(),
                 group_id: self.broker.group_id.clone(),
+                security_protocol: self.broker.kafka.security_protocol.clone(),
+                sasl_mechanism: self.broker.kafka.sasl_mechanism.clone(),
+                schema_registry_url: self
+                    .broker
+                    .schema_registry
+                    .as_ref()
+                    .map(|registry| registry.url.clone()),
+                payload_format: self.broker.payload_format,
+                notifications_topic: self.broker.notifications_topic.clone(),
+                amqp: (self.broker.broker_type == BrokerType::Amqp).then(|| AmqpConfigSafe {
+                    vhost: self.broker.amqp.vhost.clone(),
+                    username: self.broker.amqp.username.clone(),
+                    tls: self.broker.amqp.tls,
+                    exchange: self.broker.amqp.exchange.clone(),
+                    routing_keys: self.broker.amqp.routing_keys.clone(),
+                    prefetch: self.broker.amqp.prefetch,
+                }),
             },
             database: DatabaseConfigSafe {
                 host: self.database.host.clone(),
                 port: self.database.port,
                 database: self.database.database.clone(),
                 max_connections: self.database.max_connections,
+                run_migrations: self.database.run_migrations,
+                partitioning: self.database.partitioning.clone(),
+                tables: self.database.tables.clone(),
+                current_state_mode: self.database.current_state_mode,
+                current_state_order: self.database.current_state_order,
+                audit: self.database.audit,
+                raw_encryption: self.database.raw_encryption.as_ref().map(|encryption| {
+                    RawEncryptionConfigSafe {
+                        key_id: encryption.key_id.clone(),
+                        previous_key_ids: encryption.previous_keys.keys().cloned().collect(),
+                    }
+                }),
+                clickhouse: self.database.clickhouse.as_ref().map(|clickhouse| {
+                    ClickHouseConfigSafe {
+                        url: clickhouse.url.clone(),
+                        database: clickhouse.database.clone(),
+                        table: clickhouse.table.clone(),
+                        mode: clickhouse.mode,
+                    }
+                }),
+                elasticsearch: self.database.elasticsearch.as_ref().map(|elasticsearch| {
+                    ElasticsearchConfigSafe {
+                        url: elasticsearch.url.clone(),
+                        index_prefix: elasticsearch.index_prefix.clone(),
+                    }
+                }),
+                redis_state: self
+                    .database
+                    .redis_state
+                    .as_ref()
+                    .map(|redis| RedisStateConfigSafe {
+                        key_prefix: redis.key_prefix.clone(),
+                        ttl_secs: redis.ttl_secs,
+                    }),
+                maintenance: self.database.maintenance.clone(),
+                fanout: self
+                    .database
+                    .fanout
+                    .iter()
+                    .map(|target| FanoutTargetConfigSafe {
+                        name: target.name.clone(),
+                        host: target.host.clone(),
+                        port: target.port,
+                        database: target.database.clone(),
+                        max_connections: target.max_connections,
+                        buffer_max_records: target.buffer_max_records,
+                        buffer_overflow_policy: target.buffer_overflow_policy,
+                    })
+                    .collect(),
             },
             processing: self.processing.clone(),
+            archive: self.archive.as_ref().map(|archive| ArchiveConfigSafe {
+                bucket: archive.bucket.clone(),
+                prefix: archive.prefix.clone(),
+                endpoint: archive.endpoint.clone(),
+                spool_dir: archive.spool_dir.clone(),
+            }),
+            capture: self.capture.clone(),
+            admin: self.admin.clone(),
+            grpc_admin: self.grpc_admin.clone(),
+            geofence: self.geofence.clone(),
+            trips: self.trips.clone(),
+            driving_behavior: self.driving_behavior.clone(),
+            driver_events: self.driver_events.clone(),
+            power_events: self.power_events.clone(),
+            presence: self.presence.clone(),
+            tracing: self.tracing.clone(),
+            supervisor: self.supervisor.clone(),
+            startup: self.startup.clone(),
+            watchdog: self.watchdog.clone(),
+            heartbeat: self.heartbeat.clone(),
+            handover: self.handover.clone(),
+            sharding: self.sharding.clone(),
+            live_tail: self.live_tail.clone(),
+            webhooks: self.webhooks.as_ref().map(|webhooks| WebhookConfigSafe {
+                targets: webhooks
+                    .targets
+                    .iter()
+                    .map(|target| WebhookTargetConfigSafe {
+                        name: target.name.clone(),
+                        signed: target.secret.is_some(),
+                        events: target.events.clone(),
+                        tenants: target.tenants.clone(),
+                    })
+                    .collect(),
+                retry: webhooks.retry.clone(),
+                timeout_ms: webhooks.timeout_ms,
+            }),
+            ingest: self.ingest.as_ref().map(|ingest| IngestConfigSafe {
+                bind: ingest.bind.clone(),
+                tokens: ingest.tokens.len(),
+                max_batch: ingest.max_batch,
+            }),
+            payload_auth: self
+                .payload_auth
+                .as_ref()
+                .map(|auth| PayloadAuthConfigSafe {
+                    tenants: auth.keys.keys().cloned().collect(),
+                    signature_header: auth.signature_header.clone(),
+                    key_id_header: auth.key_id_header.clone(),
+                }),
+            mqtt_publish: self.mqtt_publish.as_ref().map(MqttPublishConfigSafe::from),
+            device_acks: self.device_acks.as_ref().map(|acks| DeviceAckConfigSafe {
+                mqtt: MqttPublishConfigSafe::from(&acks.mqtt),
+                reports: acks.reports.clone(),
+            }),
+            device_commands: self.device_commands.as_ref().map(|commands| {
+                DeviceCommandConfigSafe {
+                    mqtt: MqttPublishConfigSafe::from(&commands.mqtt),
+                    kafka_topic: commands.kafka_topic.clone(),
+                    timeout_secs: commands.timeout_secs,
+                }
+            }),
+            redis_stream: self
+                .redis_stream
+                .as_ref()
+                .map(|stream| RedisStreamConfigSafe {
+                    key: stream.key.clone(),
+                    max_len: stream.max_len,
+                }),
+            aws: self.aws.clone(),
+            output_sinks: self.output_sinks.clone(),
+            health: self.health.clone(),
         }
     }
 }
@@ -299,6 +3454,117 @@ pub struct AppConfigSafe {
     pub broker: BrokerConfigSafe,
     pub database: DatabaseConfigSafe,
     pub processing: ProcessingConfig,
+    pub archive: Option<ArchiveConfigSafe>,
+    pub capture: Option<CaptureConfig>,
+    pub admin: Option<AdminConfig>,
+    pub grpc_admin: Option<AdminConfig>,
+    pub geofence: Option<GeofenceConfig>,
+    pub trips: Option<TripConfig>,
+    pub driving_behavior: Option<DrivingBehaviorConfig>,
+    pub driver_events: Option<DriverEventsConfig>,
+    pub power_events: Option<PowerEventsConfig>,
+    pub presence: Option<PresenceConfig>,
+    pub tracing: Option<TracingConfig>,
+    pub supervisor: SupervisorConfig,
+    pub startup: StartupConfig,
+    pub watchdog: Option<WatchdogConfig>,
+    pub heartbeat: Option<HeartbeatConfig>,
+    pub handover: Option<HandoverConfig>,
+    pub sharding: Option<ShardConfig>,
+    pub live_tail: Option<LiveTailConfig>,
+    pub webhooks: Option<WebhookConfigSafe>,
+    pub ingest: Option<IngestConfigSafe>,
+    pub payload_auth: Option<PayloadAuthConfigSafe>,
+    pub mqtt_publish: Option<MqttPublishConfigSafe>,
+    pub device_acks: Option<DeviceAckConfigSafe>,
+    pub device_commands: Option<DeviceCommandConfigSafe>,
+    pub redis_stream: Option<RedisStreamConfigSafe>,
+    pub aws: Option<AwsSinkConfig>,
+    pub output_sinks: OutputSinksConfig,
+    pub health: HealthConfig,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookConfigSafe {
+    pub targets: Vec<WebhookTargetConfigSafe>,
+    pub retry: RetryConfig,
+    pub timeout_ms: u64,
+}
+
+/// Endpoint sin la URL (puede llevar credenciales) ni el secreto
+#[derive(Debug, Serialize)]
+pub struct WebhookTargetConfigSafe {
+    pub name: String,
+    pub signed: bool,
+    pub events: Vec<String>,
+    pub tenants: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestConfigSafe {
+    pub bind: String,
+    /// Cantidad de tokens configurados, sin mostrarlos
+    pub tokens: usize,
+    pub max_batch: usize,
+}
+
+/// Tenants con clave, sin las claves
+#[derive(Debug, Serialize)]
+pub struct PayloadAuthConfigSafe {
+    pub tenants: Vec<String>,
+    pub signature_header: String,
+    pub key_id_header: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MqttPublishConfigSafe {
+    pub url: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub topic: String,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+/// Sin la contraseña
+impl From<&MqttPublishConfig> for MqttPublishConfigSafe {
+    fn from(mqtt: &MqttPublishConfig) -> Self {
+        Self {
+            url: mqtt.url.clone(),
+            client_id: mqtt.client_id.clone(),
+            username: mqtt.username.clone(),
+            topic: mqtt.topic.clone(),
+            qos: mqtt.qos,
+            retain: mqtt.retain,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceAckConfigSafe {
+    pub mqtt: MqttPublishConfigSafe,
+    pub reports: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceCommandConfigSafe {
+    pub mqtt: MqttPublishConfigSafe,
+    pub kafka_topic: Option<String>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisStreamConfigSafe {
+    pub key: String,
+    pub max_len: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveConfigSafe {
+    pub bucket: String,
+    pub prefix: String,
+    pub endpoint: Option<String>,
+    pub spool_dir: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -307,6 +3573,22 @@ pub struct BrokerConfigSafe {
     pub host: String,
     pub topic: String,
     pub group_id: String,
+    pub security_protocol: Option<String>,
+    pub sasl_mechanism: Option<String>,
+    pub schema_registry_url: Option<String>,
+    pub payload_format: PayloadFormat,
+    pub notifications_topic: Option<String>,
+    pub amqp: Option<AmqpConfigSafe>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmqpConfigSafe {
+    pub vhost: String,
+    pub username: String,
+    pub tls: bool,
+    pub exchange: Option<String>,
+    pub routing_keys: Vec<String>,
+    pub prefetch: u16,
 }
 
 #[derive(Debug, Serialize)]
@@ -315,9 +3597,130 @@ pub struct DatabaseConfigSafe {
     pub port: u16,
     pub database: String,
     pub max_connections: u32,
+    pub run_migrations: bool,
+    pub partitioning: Option<PartitionConfig>,
+    pub tables: TableConfig,
+    pub current_state_mode: CurrentStateMode,
+    pub current_state_order: Option<CurrentStateOrder>,
+    pub audit: bool,
+    pub raw_encryption: Option<RawEncryptionConfigSafe>,
+    pub clickhouse: Option<ClickHouseConfigSafe>,
+    pub elasticsearch: Option<ElasticsearchConfigSafe>,
+    pub redis_state: Option<RedisStateConfigSafe>,
+    pub maintenance: MaintenanceConfig,
+    pub fanout: Vec<FanoutTargetConfigSafe>,
+}
+
+/// Ids de las claves, sin las claves
+#[derive(Debug, Serialize)]
+pub struct RawEncryptionConfigSafe {
+    pub key_id: String,
+    pub previous_key_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FanoutTargetConfigSafe {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub max_connections: u32,
+    pub buffer_max_records: usize,
+    pub buffer_overflow_policy: BufferOverflowPolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisStateConfigSafe {
+    pub key_prefix: String,
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClickHouseConfigSafe {
+    pub url: String,
+    pub database: String,
+    pub table: String,
+    pub mode: ClickHouseMode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ElasticsearchConfigSafe {
+    pub url: String,
+    pub index_prefix: String,
+}
+
+/// URLs de PostgreSQL para una lista de hosts `host[:port]` separados por coma
+fn postgres_urls(
+    hosts: &str,
+    port: u16,
+    username: &str,
+    password: &str,
+    database: &str,
+) -> Vec<String> {
+    hosts
+        .split(',')
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(|host| {
+            let address = if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:{}", host, port)
+            };
+            format!(
+                "postgresql://{}:{}@{}/{}",
+                username, password, address, database
+            )
+        })
+        .collect()
+}
+
+/// Identificador SQL sin comillas (`[A-Za-z0-9_]+`), apto para formar nombres de
+/// tablas y esquemas con `format!`
+pub fn is_sql_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Lee una variable de entorno opcional, tratando el valor vacío como ausente
+fn env_opt(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// Lee una variable `clave=tenant,...`; las entradas sin `=` se reportan y se ignoran
+fn parse_tenant_pairs(key: &str) -> BTreeMap<String, String> {
+    let mut pairs = BTreeMap::new();
+    for entry in env_opt(key).unwrap_or_default().split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        match entry.split_once('=') {
+            Some((name, tenant)) => {
+                pairs.insert(name.trim().to_string(), tenant.trim().to_string());
+            }
+            None => eprintln!("⚠️ Entrada inválida en {}: '{}'", key, entry),
+        }
+    }
+    pairs
 }
 
 // Módulo para incluir el código generado de protobuf
 // Este se generará automáticamente con build.rs
 #[path = "siscom.v1.rs"]
 pub mod siscom;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_worker_threads_is_rejected() {
+        let mut config = AppConfig::default_dev();
+        assert!(config.validate().is_ok());
+
+        // Se rechaza antes de crear el runtime, que entraría en pánico
+        config.processing.worker_threads = 0;
+        let error = config.validate().unwrap_err();
+        assert!(error.to_string().contains("Worker threads"));
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::LoggingConfig;
+use crate::errors::ErrorCategory;
+use crate::redact;
+
+/// Incidentes enviados por minuto como máximo; el resto solo queda en los logs
+const MAX_INCIDENTS_PER_MINUTE: u32 = 60;
+
+/// Tiempo máximo de cada envío al webhook
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+static REPORTER: OnceLock<ErrorReporter> = OnceLock::new();
+
+/// Error grave del pipeline (lote perdido, registro enviado a la tabla de rechazados,
+/// circuito de BD abierto o pánico), enviado a Sentry o al webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    /// Etapa del pipeline donde ocurrió
+    pub stage: &'static str,
+    pub message: String,
+    pub category: Option<ErrorCategory>,
+    pub device_id: Option<String>,
+    pub uuid: Option<String>,
+    pub service: &'static str,
+    pub version: &'static str,
+    pub at: DateTime<Utc>,
+}
+
+impl Incident {
+    pub fn new(stage: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            stage,
+            message: message.into(),
+            category: None,
+            device_id: None,
+            uuid: None,
+            service: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            at: Utc::now(),
+        }
+    }
+
+    pub fn with_category(mut self, category: ErrorCategory) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    /// El device_id pasa por las reglas de `LOG_REDACT_DEVICE_ID`, igual que en los logs
+    pub fn with_device(mut self, device_id: &str, uuid: &str) -> Self {
+        self.device_id = Some(redact::device_id(device_id).into_owned());
+        self.uuid = Some(uuid.to_string());
+        self
+    }
+}
+
+/// Cliente de Sentry; hay que conservarlo hasta salir para enviar los eventos pendientes
+pub struct ReporterGuard {
+    _sentry: Option<sentry::ClientInitGuard>,
+}
+
+struct ErrorReporter {
+    sentry: bool,
+    webhook: Option<mpsc::UnboundedSender<Incident>>,
+    // Inicio del minuto actual e incidentes enviados en él
+    window: Mutex<(Instant, u32)>,
+    suppressed: AtomicU64,
+}
+
+/// Configura el envío de incidentes a Sentry (`SENTRY_DSN`) y/o a un webhook
+/// (`ERROR_WEBHOOK_URL`), incluidos los pánicos. Debe llamarse dentro del runtime
+/// de tokio: el webhook se llama desde una tarea en segundo plano.
+pub fn init(config: &LoggingConfig) -> Result<ReporterGuard> {
+    let sentry = match &config.sentry_dsn {
+        Some(dsn) => {
+            let guard = sentry::init(sentry::ClientOptions {
+                dsn: Some(dsn.parse()?),
+                release: sentry::release_name!(),
+                ..Default::default()
+            });
+            info!("🚨 Errores graves y pánicos enviados a Sentry");
+            Some(guard)
+        }
+        None => None,
+    };
+
+    let webhook = match &config.error_webhook_url {
+        Some(url) => {
+            let client = reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?;
+            install_panic_webhook(url.clone());
+
+            let (tx, mut rx) = mpsc::unbounded_channel::<Incident>();
+            let url = url.clone();
+            tokio::spawn(async move {
+                while let Some(incident) = rx.recv().await {
+                    if let Err(e) = post(&client, &url, &incident).await {
+                        warn!("⚠️ No se pudo enviar el incidente al webhook: {}", e);
+                    }
+                }
+            });
+            info!("🚨 Errores graves y pánicos enviados al webhook");
+            Some(tx)
+        }
+        None => None,
+    };
+
+    if sentry.is_some() || webhook.is_some() {
+        let _ = REPORTER.set(ErrorReporter {
+            sentry: sentry.is_some(),
+            webhook,
+            window: Mutex::new((Instant::now(), 0)),
+            suppressed: AtomicU64::new(0),
+        });
+    }
+    Ok(ReporterGuard { _sentry: sentry })
+}
+
+/// Envía un incidente a los destinos configurados; sin destinos no hace nada
+pub fn report(incident: Incident) {
+    if let Some(reporter) = REPORTER.get() {
+        reporter.send(incident);
+    }
+}
+
+impl ErrorReporter {
+    fn send(&self, incident: Incident) {
+        if !self.admit() {
+            return;
+        }
+
+        if self.sentry {
+            sentry::with_scope(
+                |scope| {
+                    scope.set_tag("stage", incident.stage);
+                    if let Some(category) = incident.category {
+                        scope.set_tag("category", category);
+                    }
+                    if let Some(device_id) = &incident.device_id {
+                        scope.set_tag("device_id", device_id);
+                    }
+                    if let Some(uuid) = &incident.uuid {
+                        scope.set_extra("uuid", uuid.as_str().into());
+                    }
+                },
+                || sentry::capture_message(&incident.message, sentry::Level::Error),
+            );
+        }
+        if let Some(webhook) = &self.webhook {
+            let _ = webhook.send(incident);
+        }
+    }
+
+    /// Limita los envíos a `MAX_INCIDENTS_PER_MINUTE` para no saturar el destino
+    /// durante una caída
+    fn admit(&self) -> bool {
+        let mut window = self.window.lock().unwrap();
+        if window.0.elapsed() >= Duration::from_secs(60) {
+            let suppressed = self.suppressed.swap(0, Ordering::Relaxed);
+            if suppressed > 0 {
+                warn!(
+                    "⚠️ {} incidentes no enviados por superar {} por minuto",
+                    suppressed, MAX_INCIDENTS_PER_MINUTE
+                );
+            }
+            *window = (Instant::now(), 0);
+        }
+        if window.1 >= MAX_INCIDENTS_PER_MINUTE {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+}
+
+/// Envía los pánicos al webhook antes de que el proceso termine. Con `panic = "abort"`
+/// no hay tiempo para la tarea en segundo plano: se envía desde un hilo propio, con
+/// su propio runtime y cliente.
+fn install_panic_webhook(url: String) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let incident = Incident::new("panic", info.to_string());
+        let url = url.clone();
+        let _ = std::thread::spawn(move || {
+            let client = reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?;
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(post(&client, &url, &incident))
+        })
+        .join();
+        previous(info);
+    }));
+}
+
+async fn post(client: &reqwest::Client, url: &str, incident: &Incident) -> Result<()> {
+    client
+        .post(url)
+        .json(incident)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
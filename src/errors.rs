@@ -1 +1,107 @@
-// Error types - currently using anyhow::Error throughout the codebase
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use serde::Serialize;
+use std::fmt;
+
+use crate::services::database::is_transient_error;
+
+/// Categoría de un error del pipeline, contada en las estadísticas y añadida como
+/// campo `category` a su log
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Payload que no pudo decodificarse (protobuf, JSON o trama cruda)
+    ParseError,
+    /// Payload sin firma o con una firma que no corresponde a la clave de su tenant
+    SignatureInvalid,
+    /// Posición que no pudo convertirse a registro de BD
+    ConversionError,
+    /// Registro rechazado por la BD: restricción, tipo o valor fuera de rango
+    DbConstraint,
+    /// BD inaccesible: conexión, pool agotado, host en solo lectura
+    DbUnavailable,
+    /// Otro error de la BD
+    DbError,
+    /// El broker no respondió a tiempo al consumir o publicar
+    KafkaTimeout,
+    /// Otro error del broker
+    KafkaError,
+    /// Schema Registry inaccesible o con error; el mensaje se reintenta
+    SchemaRegistryUnavailable,
+}
+
+/// Origen de un error: datos malos que reintentar no arregla, o infraestructura
+/// caída que se recupera sola
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Data,
+    Infrastructure,
+}
+
+impl ErrorCategory {
+    pub fn class(self) -> ErrorClass {
+        match self {
+            Self::ParseError
+            | Self::SignatureInvalid
+            | Self::ConversionError
+            | Self::DbConstraint => ErrorClass::Data,
+            Self::DbUnavailable
+            | Self::DbError
+            | Self::KafkaTimeout
+            | Self::KafkaError
+            | Self::SchemaRegistryUnavailable => ErrorClass::Infrastructure,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ParseError => "parse_error",
+            Self::SignatureInvalid => "signature_invalid",
+            Self::ConversionError => "conversion_error",
+            Self::DbConstraint => "db_constraint",
+            Self::DbUnavailable => "db_unavailable",
+            Self::DbError => "db_error",
+            Self::KafkaTimeout => "kafka_timeout",
+            Self::KafkaError => "kafka_error",
+            Self::SchemaRegistryUnavailable => "schema_registry_unavailable",
+        }
+    }
+
+    /// Clasifica un error de escritura en la BD
+    pub fn of_database(error: &anyhow::Error) -> Self {
+        if is_transient_error(error) {
+            return Self::DbUnavailable;
+        }
+        match error.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Database(db_error)) => Self::of_sqlstate(db_error.code().as_deref()),
+            _ => Self::DbError,
+        }
+    }
+
+    /// Clasifica un error de PostgreSQL por su SQLSTATE
+    pub fn of_sqlstate(code: Option<&str>) -> Self {
+        // 22: valor inválido o fuera de rango, 23: restricción de integridad
+        match code {
+            Some(code) if code.starts_with("22") || code.starts_with("23") => Self::DbConstraint,
+            _ => Self::DbError,
+        }
+    }
+
+    /// Clasifica un error de Kafka al consumir o publicar
+    pub fn of_kafka(error: &KafkaError) -> Self {
+        match error.rdkafka_error_code() {
+            Some(
+                RDKafkaErrorCode::MessageTimedOut
+                | RDKafkaErrorCode::RequestTimedOut
+                | RDKafkaErrorCode::OperationTimedOut,
+            ) => Self::KafkaTimeout,
+            _ => Self::KafkaError,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
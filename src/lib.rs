@@ -0,0 +1,10 @@
+//! Núcleo del consumer: configuración, modelos y servicios del pipeline. El binario
+//! `siscom-consumer` los arma; los benchmarks los usan directamente.
+
+pub mod config;
+pub mod error_reporter;
+pub mod errors;
+pub mod models;
+pub mod redact;
+pub mod services;
+pub mod telemetry;
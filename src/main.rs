@@ -1,24 +1,78 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use std::process::ExitCode;
 use std::sync::Arc;
 use tokio::signal;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 mod boot;
-mod config;
-mod errors;
-mod models;
-mod services;
-
-use config::AppConfig;
-use services::{DatabaseService, KafkaConsumerService, MessageConsumer, MessageProcessor};
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging early
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .json()
-        .init();
+mod cli;
+mod shutdown;
+
+use tracking_consumer_rust::{config, error_reporter, models, redact, services, telemetry};
+
+use cli::{Cli, Command};
+use config::{AppConfig, BrokerType, SinkKind};
+use services::capture::CapturedPayload;
+use services::memory::format_bytes;
+use services::{
+    AdminServer, AmqpConsumerService, ArchiveService, AwsSink, Backpressure, BatchController,
+    CaptureReplay, CaptureWriter, ClickHouseSink, CommandForwarder, DatabaseService,
+    DependencyWait, DeviceAckPublisher, Downsampler, DriverTracker, DrivingBehaviorDetector,
+    DryRunReport, ElasticsearchSink, EnricherChain, FieldMapping, GeofenceService,
+    GpsQualityChecker, GrpcAdminServer, HealthMonitor, HeartbeatPublisher, HttpIngest,
+    IdempotencyStore, InstanceLease, KafkaConsumerService, LiveTail, MaintenanceService,
+    MemoryAccounting, MemoryLimiter, MessageConsumer, MessageProcessor, MessageSink, MqttPublisher,
+    NotificationPublisher, PayloadAuthenticator, PipelineControl, PipelineMetrics, PositionFilter,
+    PowerMonitor, PresenceMonitor, RawCipher, RecordStore, RedisStateSink, RedisStreamSink,
+    ReplayService, ShardFilter, SinkPipeline, Supervisor, TenantRouter, TripDetector, Watchdog,
+    WebhookNotifier,
+};
+use shutdown::ShutdownReason;
+
+fn main() -> ExitCode {
+    match start() {
+        Ok(reason) => reason.into(),
+        // Errores previos a los logs o a la validación de la configuración
+        Err(e) => {
+            let reason = ShutdownReason::of(&e);
+            eprintln!("❌ {:#}", e);
+            eprintln!("🛑 Código de salida {} ({})", reason.exit_code(), reason);
+            reason.into()
+        }
+    }
+}
+
+/// Arranca logs y runtime y ejecuta el comando; devuelve el motivo de salida
+fn start() -> Result<ShutdownReason> {
+    let cli = Cli::parse();
+
+    // La configuración se lee antes de iniciar el logging porque define el
+    // exportador de trazas
+    let (config, load_error) = match AppConfig::load() {
+        Ok(config) => (config, None),
+        Err(e) => (AppConfig::default_dev(), Some(e)),
+    };
+
+    // Se valida antes de crear el runtime: con worker_threads = 0 el builder de tokio
+    // entra en pánico en vez de terminar con ConfigInvalid
+    if load_error.is_none() {
+        config.validate().context(ShutdownReason::ConfigInvalid)?;
+    }
+
+    // El runtime se crea después de leer la configuración para respetar worker_threads
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.processing.worker_threads)
+        .enable_all()
+        .build()?;
+
+    // Logs y exportador de trazas
+    let (telemetry, _error_reporter) = {
+        let _runtime = runtime.enter();
+        let telemetry = telemetry::init(&config.logging, config.tracing.as_ref())?;
+        (telemetry, error_reporter::init(&config.logging)?)
+    };
 
     info!(
         "🚀 Iniciando Siscom Consumer Rust v{}",
@@ -27,192 +81,1755 @@ async fn main() -> Result<()> {
 
     boot::print_banner();
 
-    // Load configuration
-    let config = match AppConfig::load() {
-        Ok(config) => {
-            config.validate()?;
+    match load_error {
+        None => {
             info!("✅ Configuración cargada y validada");
             info!("📋 Config: {:#?}", config.display_safe());
-            config
         }
-        Err(e) => {
+        Some(e) => {
             error!("❌ Error cargando configuración: {}", e);
             warn!("🔄 Usando configuración por defecto de desarrollo");
-            AppConfig::default_dev()
         }
-    };
+    }
     info!("✅ Configuración cargada y validada");
 
-    // Setup graceful shutdown
-    let shutdown_signal = setup_shutdown_handler();
+    // Con --dry-run se desactivan los destinos que escriben fuera del proceso
+    let config = if cli.dry_run || config.processing.dry_run {
+        config.without_side_effects()
+    } else {
+        config
+    };
+    info!(
+        "🧵 Runtime con {} hilos de trabajo",
+        config.processing.worker_threads
+    );
 
-    // Initialize services
-    let services = match initialize_services(&config).await {
-        Ok(services) => services,
+    let reason = match runtime.block_on(run(cli.command, config)) {
+        Ok(reason) => reason,
         Err(e) => {
-            error!("❌ Error inicializando servicios: {}", e);
-            return Err(e);
+            error!("❌ {:#}", e);
+            ShutdownReason::of(&e)
+        }
+    };
+    if reason != ShutdownReason::Completed {
+        error!("🛑 Código de salida {} ({})", reason.exit_code(), reason);
+    }
+    telemetry::shutdown(telemetry);
+    Ok(reason)
+}
+
+/// Ejecuta el subcomando indicado o el consumidor
+async fn run(command: Option<Command>, config: AppConfig) -> Result<ShutdownReason> {
+    let result = match command {
+        Some(Command::Replay {
+            from,
+            to,
+            table_suffix,
+            verify: false,
+        }) => run_replay(&config, from, to, table_suffix.as_deref()).await,
+        Some(Command::Replay {
+            from,
+            to,
+            table_suffix,
+            verify: true,
+        }) => run_verify(&config, from, to, table_suffix.as_deref()).await,
+        Some(Command::ReplayFile {
+            files,
+            speed,
+            table_suffix,
+        }) => run_replay_file(&config, &files, speed, table_suffix.as_deref()).await,
+        Some(Command::DecryptRaw { input, output }) => {
+            run_decrypt_raw(&config, &input, &output).await
+        }
+        Some(Command::Migrate) if config.processing.dry_run => {
+            Err(anyhow::anyhow!("migrate no admite --dry-run")
+                .context(ShutdownReason::ConfigInvalid))
         }
+        Some(Command::Migrate) => run_migrate(&config).await,
+        None => return run_consumer(config).await,
     };
+    result.map(|()| ShutdownReason::Completed)
+}
+
+/// Ejecuta el consumidor hasta el shutdown
+async fn run_consumer(config: AppConfig) -> Result<ShutdownReason> {
+    // AMQP confirma las entregas al procesarlas: un dry-run vaciaría la cola real
+    if config.processing.dry_run && config.broker.broker_type == BrokerType::Amqp {
+        return Err(anyhow::anyhow!(
+            "--dry-run no admite BROKER_TYPE=amqp; usar replay-file con una captura"
+        )
+        .context(ShutdownReason::ConfigInvalid));
+    }
+
+    // Setup graceful shutdown; también lo inicia DrainAndExit de la API gRPC
+    let shutdown = Arc::new(tokio::sync::Notify::new());
+    let mut shutdown_signal = setup_shutdown_handler(shutdown.clone());
+    spawn_log_level_signal_task();
+
+    // En docker-compose el consumer puede arrancar antes que sus dependencias
+    tokio::select! {
+        result = wait_for_dependencies(&config) => result?,
+        _ = &mut shutdown_signal => {
+            info!("🛑 Shutdown solicitado durante la espera de dependencias");
+            return Ok(ShutdownReason::Completed);
+        }
+    }
+
+    // Initialize services
+    let services = initialize_services(&config, shutdown)
+        .await
+        .context("Error inicializando servicios")?;
 
     info!("✅ Todos los servicios inicializados correctamente");
 
     // Start the main processing loop
-    let processing_result = start_processing_loop(services, shutdown_signal).await;
+    let drain_timeout = std::time::Duration::from_secs(config.processing.drain_timeout_secs);
+    let reason = start_processing_loop(
+        services,
+        shutdown_signal,
+        drain_timeout,
+        config.supervisor.clone(),
+        config.watchdog.clone(),
+    )
+    .await;
 
-    match processing_result {
-        Ok(_) => info!("✅ Aplicación terminada correctamente"),
-        Err(e) => error!("❌ Error en loop principal: {}", e),
+    match reason {
+        ShutdownReason::Completed => info!("✅ Aplicación terminada correctamente"),
+        reason => error!("❌ Loop principal terminado: {}", reason),
     }
 
     info!("🛑 Tracking Consumer terminado");
+    Ok(reason)
+}
+
+/// Espera a que PostgreSQL (principal y fan-out), el broker y el broker MQTT de
+/// republicación acepten conexiones. Con `STARTUP_MAX_WAIT_SECS=0` no espera y cada
+/// servicio falla en su primer intento de conexión.
+async fn wait_for_dependencies(config: &AppConfig) -> Result<()> {
+    if config.startup.max_wait_secs == 0 {
+        return Ok(());
+    }
+    let wait = DependencyWait::new(config.startup.clone());
+
+    wait.postgres(&config.database_urls())
+        .await
+        .context(ShutdownReason::DatabaseUnreachable)?;
+    for target in &config.database.fanout {
+        wait.postgres(&target.urls())
+            .await
+            .context(ShutdownReason::DatabaseUnreachable)?;
+    }
+
+    let (broker, default_port) = match config.broker.broker_type {
+        BrokerType::Kafka => ("Kafka", 9092),
+        BrokerType::Amqp if config.broker.amqp.tls => ("AMQP", 5671),
+        BrokerType::Amqp => ("AMQP", 5672),
+    };
+    let addresses: Vec<String> = config
+        .broker
+        .host
+        .split(',')
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(|host| match host.rsplit_once(':') {
+            Some(_) => host.to_string(),
+            None => format!("{}:{}", host, default_port),
+        })
+        .collect();
+    wait.tcp(broker, &addresses)
+        .await
+        .context(ShutdownReason::BrokerUnreachable)?;
+
+    if config.output_sinks.sinks.contains(&SinkKind::Mqtt) {
+        if let Some(mqtt) = &config.mqtt_publish {
+            wait.tcp("MQTT", &[MqttPublisher::address(mqtt)?])
+                .await
+                .context(ShutdownReason::BrokerUnreachable)?;
+        }
+    }
+    if let Some(acks) = &config.device_acks {
+        wait.tcp(
+            "MQTT (confirmaciones)",
+            &[MqttPublisher::address(&acks.mqtt)?],
+        )
+        .await
+        .context(ShutdownReason::BrokerUnreachable)?;
+    }
+    if let Some(commands) = &config.device_commands {
+        wait.tcp(
+            "MQTT (comandos)",
+            &[MqttPublisher::address(&commands.mqtt)?],
+        )
+        .await
+        .context(ShutdownReason::BrokerUnreachable)?;
+    }
     Ok(())
 }
 
 /// Estructura que contiene todos los servicios inicializados
 struct Services {
-    message_consumer: Box<dyn MessageConsumer>,
+    message_consumer: Arc<dyn MessageConsumer>,
     database: Arc<DatabaseService>,
     message_processor: MessageProcessor,
-    message_receiver: tokio::sync::mpsc::UnboundedReceiver<models::DeviceMessage>,
+    health: Arc<HealthMonitor>,
+    capture: Option<Arc<CaptureWriter>>,
+    // Republicación MQTT, para terminar si el broker rechaza las credenciales
+    mqtt: Option<Arc<MqttPublisher>>,
+    // Confirmaciones Queclink (DEVICE_ACK_TOPIC)
+    device_acks: Option<Arc<DeviceAckPublisher>>,
+    // Comandos a los equipos (DEVICE_COMMAND_TOPIC)
+    commands: Option<Arc<CommandForwarder>>,
+    // Lease de consumo entre instancias (HANDOVER_LEASE_KEY)
+    lease: Option<Arc<InstanceLease>>,
 }
 
 /// Inicializa todos los servicios necesarios
-async fn initialize_services(config: &AppConfig) -> Result<Services> {
+async fn initialize_services(
+    config: &AppConfig,
+    shutdown: Arc<tokio::sync::Notify>,
+) -> Result<Services> {
     info!("🔧 Inicializando servicios...");
 
+    // Señal de presión compartida: pausa el consumo cuando la cola supera el buffer configurado
+    let backpressure = Backpressure::new(config.processing.message_buffer_size);
+
+    // Pausa/reanudación de etapas desde la API de administración
+    let pipeline_control = Arc::new(PipelineControl::new(backpressure.clone()));
+
+    // Ritmo por origen, errores por categoría y latencia de escritura, para /stats
+    let metrics = Arc::new(PipelineMetrics::default());
+
+    // Memoria estimada de colas y buffers, para /stats y el límite blando
+    let memory = Arc::new(MemoryAccounting::default());
+
+    // Vista en vivo de los mensajes en /tail; sin la API HTTP nadie podría consultarla
+    let live_tail = match (&config.live_tail, &config.admin) {
+        (Some(live_tail), Some(_)) => Some(Arc::new(LiveTail::new(live_tail.clone()))),
+        (Some(_), None) => {
+            warn!("⚠️ LIVE_TAIL_ENABLED requiere ADMIN_BIND; la vista en vivo queda desactivada");
+            None
+        }
+        (None, _) => None,
+    };
+
     // Initialize database service
     info!("🗄️ Conectando a PostgreSQL...");
-    let database = Arc::new(
-        DatabaseService::new(
-            &config.database_url(),
-            config.database.max_connections,
-            config.processing.batch_processing_size,
-        )
-        .await?,
+    let mut database = DatabaseService::new(
+        &config.database_urls(),
+        config.database.max_connections,
+        config.processing.batch_processing_size,
+    )
+    .await
+    .context(ShutdownReason::DatabaseUnreachable)?
+    .with_retry(config.database.retry.clone())
+    .with_tables(config.database.tables.clone())
+    .with_current_state_mode(config.database.current_state_mode)
+    .with_current_state_order(config.database.current_state_order)
+    .with_buffer_limit(
+        config.database.buffer_max_records,
+        config.database.buffer_overflow_policy,
+    )
+    .with_insert_mode(config.database.insert_mode)
+    .with_pipeline_control(pipeline_control.clone())
+    .with_metrics(metrics.clone());
+    let raw_cipher = raw_cipher(config)?;
+    if let Some(cipher) = &raw_cipher {
+        database = database.with_raw_encryption(cipher.clone());
+    }
+    if config.database.audit {
+        info!(
+            "🧾 Auditoría de lotes en {}",
+            config.database.tables.batch_audit_table()
+        );
+        database = database.with_audit("primary");
+    }
+    if let Some(live_tail) = &live_tail {
+        database = database.with_live_tail(live_tail.clone());
+    }
+    if let Some(partitioning) = &config.database.partitioning {
+        info!("🧩 Particionado por tiempo activo: {:?}", partitioning);
+        database = database.with_partitioning(partitioning.clone());
+    }
+    if let Some(clickhouse) = &config.database.clickhouse {
+        database = database.with_clickhouse(ClickHouseSink::new(
+            clickhouse,
+            config.database.retry.clone(),
+        )?);
+    }
+    if let Some(elasticsearch) = &config.database.elasticsearch {
+        let sink = ElasticsearchSink::new(elasticsearch, config.database.retry.clone())?;
+        // Sin template los índices se crean con el mapeo dinámico y `location` no es geo_point
+        if let Err(e) = sink.install_template().await {
+            warn!(
+                "⚠️ No se pudo instalar el index template de Elasticsearch: {}",
+                e
+            );
+        }
+        database = database.with_elasticsearch(sink);
+    }
+    if let Some(redis_state) = &config.database.redis_state {
+        database = database.with_redis_state(RedisStateSink::new(redis_state).await?);
+    }
+    let database = Arc::new(database);
+
+    if config.database.run_migrations {
+        database.run_migrations().await?;
+    }
+
+    // Retención por tabla y ANALYZE tras cargas grandes
+    let maintenance = (config.database.maintenance.interval_secs > 0).then(|| {
+        let maintenance = Arc::new(MaintenanceService::new(
+            database.clone(),
+            config.database.maintenance.clone(),
+        ));
+        maintenance.start();
+        maintenance
+    });
+
+    // Escritura periódica del buffer de BD, aunque haya poco tráfico
+    database.start_flush_task(
+        std::time::Duration::from_millis(config.database.flush_interval_ms),
+        std::time::Duration::from_secs(config.database.buffer_max_age_secs),
     );
 
-    // Inicializar Kafka consumer
-    info!("📡 Inicializando Kafka consumer...");
-    let message_consumer: Box<dyn MessageConsumer> =
-        Box::new(KafkaConsumerService::new(&config.broker)?);
+    // Copia de los payloads recibidos para `replay-file`
+    let capture = config
+        .capture
+        .as_ref()
+        .map(CaptureWriter::new)
+        .transpose()?
+        .map(Arc::new);
 
-    // Iniciar el consumo y obtener el receiver
-    let message_receiver = message_consumer.start_consuming().await?;
+    // Inicializar el consumidor del broker configurado
+    let field_mapping = load_field_mapping(config)?;
+    let authenticator = payload_authenticator(config);
+    let device_acks = config
+        .device_acks
+        .as_ref()
+        .map(|acks| DeviceAckPublisher::new(acks, config.sharding.as_ref()))
+        .transpose()?
+        .map(Arc::new);
+    // Compartido por el consumidor Kafka y el procesador; republica firmando con la
+    // clave del tenant original para que el shard dueño lo acepte
+    let shard = config
+        .sharding
+        .as_ref()
+        .map(|sharding| ShardFilter::new(sharding.clone(), &config.broker))
+        .transpose()?
+        .map(|shard| match &authenticator {
+            Some(authenticator) => shard.with_authenticator(authenticator.clone()),
+            None => shard,
+        })
+        .map(Arc::new);
+    let message_consumer: Arc<dyn MessageConsumer> = match config.broker.broker_type {
+        BrokerType::Kafka => {
+            info!("📡 Inicializando Kafka consumer...");
+            let mut broker = config.broker.clone();
+            if let Some(shard) = &shard {
+                broker.group_id = shard.consumer_group(&config.broker.group_id);
+                if broker.group_id != config.broker.group_id {
+                    info!(
+                        "🧩 Grupo de consumo propio del shard: {} (los mensajes de otros shards se descartan)",
+                        broker.group_id
+                    );
+                }
+            }
+            let mut kafka_consumer = KafkaConsumerService::new(&broker, backpressure.clone())?;
+            if let Some(mapping) = field_mapping {
+                kafka_consumer = kafka_consumer.with_field_mapping(mapping);
+            }
+            if let Some(routing) = &config.processing.tenant_routing {
+                kafka_consumer = kafka_consumer.with_topic_tenants(&routing.topics);
+            }
+            if let Some(capture) = &capture {
+                kafka_consumer = kafka_consumer.with_capture(capture.clone());
+            }
+            if let Some(authenticator) = authenticator {
+                kafka_consumer = kafka_consumer.with_authenticator(authenticator);
+            }
+            if let Some(device_acks) = &device_acks {
+                kafka_consumer = kafka_consumer.with_device_acks(device_acks.clone());
+            }
+            if let Some(shard) = &shard {
+                kafka_consumer = kafka_consumer.with_shard(shard.clone());
+            }
+            Arc::new(kafka_consumer.with_metrics(metrics.clone()))
+        }
+        BrokerType::Amqp => {
+            info!("📡 Inicializando consumidor AMQP...");
+            let mut amqp_consumer = AmqpConsumerService::new(&config.broker, backpressure.clone())?;
+            if let Some(mapping) = field_mapping {
+                amqp_consumer = amqp_consumer.with_field_mapping(mapping);
+            }
+            if let Some(capture) = &capture {
+                amqp_consumer = amqp_consumer.with_capture(capture.clone());
+            }
+            if let Some(authenticator) = authenticator {
+                amqp_consumer = amqp_consumer.with_authenticator(authenticator);
+            }
+            if let Some(device_acks) = &device_acks {
+                amqp_consumer = amqp_consumer.with_device_acks(device_acks.clone());
+            }
+            if config
+                .processing
+                .tenant_routing
+                .as_ref()
+                .is_some_and(|routing| !routing.topics.is_empty())
+            {
+                warn!("⚠️ Los tópicos por tenant solo aplican con Kafka; se ignoran con AMQP");
+            }
+            Arc::new(amqp_consumer.with_metrics(metrics.clone()))
+        }
+    };
+
+    // Ingesta HTTP: sus mensajes entran por el mismo canal que los del broker
+    let message_consumer = match &config.ingest {
+        Some(ingest) => {
+            let mut ingest =
+                HttpIngest::new(ingest.clone(), backpressure.clone()).with_metrics(metrics.clone());
+            if let Some(capture) = &capture {
+                ingest = ingest.with_capture(capture.clone());
+            }
+            let ingest = Arc::new(ingest);
+            ingest.start().await?;
+            ingest.merge(message_consumer)
+        }
+        None => message_consumer,
+    };
 
     // Inicializar el procesador de mensajes
     let message_processor = MessageProcessor::new(
         database.clone(),
         config.processing.batch_processing_size,
-        5000, // 5 segundos de intervalo de flush
-    );
+        config.database.flush_interval_ms,
+        backpressure.clone(),
+        config.processing.sanitization.clone(),
+    )
+    .with_metrics(metrics.clone())
+    .with_memory(memory.clone())
+    .with_lanes(config.processing.max_parallel_devices)
+    .with_enrichers(EnricherChain::from_config(&config.processing.enrichment)?)
+    .with_circuit_probe_interval(std::time::Duration::from_secs(
+        config.database.circuit_probe_interval_secs,
+    ));
+    let message_processor = match &config.processing.adaptive_batch {
+        Some(adaptive) => message_processor.with_batch_controller(Arc::new(BatchController::new(
+            adaptive.clone(),
+            config.processing.batch_processing_size,
+        ))),
+        None => message_processor,
+    };
+    let message_processor = match connect_idempotency(config, true).await? {
+        Some(idempotency) => message_processor.with_idempotency(idempotency),
+        None => message_processor,
+    };
+    let message_processor = match &config.processing.gps_quality {
+        Some(gps_quality) => {
+            message_processor.with_gps_quality(GpsQualityChecker::new(gps_quality.clone()))
+        }
+        None => message_processor,
+    };
+    let message_processor = match PositionFilter::from_config(&config.processing.position_filter) {
+        Some(filter) => message_processor.with_position_filter(filter),
+        None => message_processor,
+    };
+    let message_processor = match Downsampler::from_config(&config.processing.downsampling) {
+        Some(downsampler) => message_processor.with_downsampler(downsampler),
+        None => message_processor,
+    };
+    let message_processor = match &shard {
+        Some(shard) => message_processor.with_shard(shard.clone()),
+        None => message_processor,
+    };
+    let aws = config
+        .aws
+        .as_ref()
+        .map(AwsSink::new)
+        .transpose()?
+        .map(Arc::new);
+    let mut sinks = SinkPipeline::default();
+    let mut mqtt_publisher = None;
+    for kind in &config.output_sinks.sinks {
+        // La validación garantiza que cada salida listada está configurada
+        let sink: Arc<dyn MessageSink> = match kind {
+            SinkKind::Mqtt => match &config.mqtt_publish {
+                Some(mqtt) => {
+                    let publisher = Arc::new(MqttPublisher::new(mqtt)?);
+                    mqtt_publisher = Some(publisher.clone());
+                    publisher
+                }
+                None => continue,
+            },
+            SinkKind::Sqs => match aws.as_ref().filter(|aws| aws.has_sqs()) {
+                Some(aws) => aws.clone(),
+                None => continue,
+            },
+            SinkKind::RedisStream => match &config.redis_stream {
+                Some(stream) => Arc::new(RedisStreamSink::new(stream).await?),
+                None => continue,
+            },
+        };
+        sinks.push(sink, &config.output_sinks);
+    }
+    let message_processor = message_processor.with_sinks(sinks);
+    let message_processor = match config.processing.dry_run {
+        true => message_processor.with_dry_run(Arc::new(DryRunReport::new())),
+        false => message_processor,
+    };
+    let message_processor = match &live_tail {
+        Some(live_tail) => message_processor.with_live_tail(live_tail.clone()),
+        None => message_processor,
+    };
+
+    let message_processor = apply_tenant_routing(config, &database, message_processor).await?;
+
+    let message_processor = connect_fanout_targets(config, &metrics, raw_cipher.as_ref())
+        .await?
+        .into_iter()
+        .fold(message_processor, |processor, (name, target)| {
+            processor.with_fanout(&name, target)
+        });
+
+    // Archivo de mensajes crudos en S3/MinIO
+    let message_processor = match &config.archive {
+        Some(archive_config) => {
+            let archive = Arc::new(ArchiveService::new(archive_config)?);
+            archive.start(std::time::Duration::from_secs(
+                archive_config.flush_interval_secs,
+            ));
+            message_processor.with_archive(archive)
+        }
+        None => message_processor,
+    };
+
+    // Geocercas, viajes, conducción, conductores, alimentación y conectividad publican sus eventos en el
+    // tópico de notificaciones, en los webhooks y en SNS
+    let has_events = config.geofence.is_some()
+        || config.trips.is_some()
+        || config.driving_behavior.is_some()
+        || config.driver_events.is_some()
+        || config.power_events.is_some()
+        || config.presence.is_some();
+    let webhooks = config
+        .webhooks
+        .as_ref()
+        .filter(|_| has_events)
+        .map(WebhookNotifier::new)
+        .transpose()?
+        .map(Arc::new);
+    let sns = aws.clone().filter(|aws| has_events && aws.has_sns());
+    let notifications = match config.broker.notifications_topic.as_deref() {
+        Some(topic) if has_events => Some(
+            NotificationPublisher::new(&config.broker, topic)?
+                .with_pipeline_control(pipeline_control.clone())
+                .with_metrics(metrics.clone())
+                .with_memory(memory.clone()),
+        ),
+        _ if webhooks.is_some() || sns.is_some() => Some(NotificationPublisher::without_kafka()),
+        _ => None,
+    }
+    .map(|publisher| match &webhooks {
+        Some(webhooks) => publisher.with_webhooks(webhooks.clone()),
+        None => publisher,
+    })
+    .map(|publisher| match &sns {
+        Some(sns) => publisher.with_sns(sns.clone()),
+        None => publisher,
+    });
+
+    // Geocercas: eventos de entrada/salida en geofence_events
+    let geofences = match &config.geofence {
+        Some(geofence_config) => {
+            let geofences = Arc::new(
+                GeofenceService::new(
+                    geofence_config.clone(),
+                    database.clone(),
+                    notifications.clone(),
+                )
+                .await?,
+            );
+            geofences.start();
+            Some(geofences)
+        }
+        None => None,
+    };
+    let message_processor = match &geofences {
+        Some(geofences) => message_processor.with_geofences(geofences.clone()),
+        None => message_processor,
+    };
+
+    // Viajes: abiertos y cerrados en la tabla trips
+    let message_processor = match &config.trips {
+        Some(trip_config) => message_processor.with_trips(Arc::new(
+            TripDetector::new(trip_config.clone(), database.clone(), notifications.clone()).await?,
+        )),
+        None => message_processor,
+    };
+
+    // Conductores: inicios y cierres de sesión en driver_events
+    let message_processor = match &config.driver_events {
+        Some(driver_config) => message_processor.with_drivers(Arc::new(
+            DriverTracker::new(
+                driver_config.clone(),
+                database.clone(),
+                notifications.clone(),
+            )
+            .await?,
+        )),
+        None => message_processor,
+    };
+
+    // Alimentación: cortes, restablecimientos y batería de respaldo baja en power_events
+    let message_processor = match &config.power_events {
+        Some(power_config) => message_processor.with_power(Arc::new(
+            PowerMonitor::new(
+                power_config.clone(),
+                database.clone(),
+                notifications.clone(),
+            )
+            .await?,
+        )),
+        None => message_processor,
+    };
+
+    // Conectividad: DEVICE_OFFLINE/DEVICE_ONLINE según el último mensaje de cada dispositivo
+    let presence = config.presence.as_ref().map(|presence_config| {
+        let presence = Arc::new(PresenceMonitor::new(
+            presence_config.clone(),
+            notifications.clone(),
+        ));
+        presence.start();
+        presence
+    });
+    let message_processor = match &presence {
+        Some(presence) => message_processor.with_presence(presence.clone()),
+        None => message_processor,
+    };
+
+    // Conducción: frenadas, aceleraciones y excesos de velocidad, solo como notificaciones
+    let message_processor = match (&config.driving_behavior, notifications) {
+        (Some(driving_config), Some(notifications)) => message_processor.with_driving_behavior(
+            Arc::new(DrivingBehaviorDetector::new(driving_config.clone(), notifications).await?),
+        ),
+        _ => message_processor,
+    };
+
+    // Sondas de salud: conexión a la BD y a Kafka y profundidad de las colas
+    let health = Arc::new(HealthMonitor::new(
+        config.health.clone(),
+        database.clone(),
+        message_consumer.clone(),
+        message_processor.clone(),
+    ));
+
+    // Frena el consumo antes de que colas y buffers lleven el contenedor al OOM
+    if let Some(soft_limit_mb) = config.processing.memory_soft_limit_mb {
+        MemoryLimiter::new(soft_limit_mb, message_processor.clone(), backpressure).start();
+    }
+
+    // Heartbeat de la instancia para el monitoreo de la flota
+    if let Some(heartbeat) = &config.heartbeat {
+        let publisher = NotificationPublisher::new(&config.broker, &heartbeat.topic)?
+            .with_metrics(metrics.clone());
+        HeartbeatPublisher::new(
+            heartbeat.clone(),
+            publisher,
+            message_processor.clone(),
+            config,
+        )
+        .start();
+    }
+
+    // Comandos a los equipos: por la API de administración y, si se configuró, Kafka
+    let commands = match &config.device_commands {
+        Some(commands_config) => {
+            let commands = Arc::new(CommandForwarder::new(commands_config, database.clone())?);
+            if let Some(topic) = &commands_config.kafka_topic {
+                commands.clone().start_kafka(&config.broker, topic)?;
+            }
+            Some(commands)
+        }
+        None => None,
+    };
+
+    if let Some(admin) = &config.admin {
+        let mut server = AdminServer::new()
+            .with_database(database.clone())
+            .with_pipeline_control(pipeline_control.clone())
+            .with_health(health.clone())
+            .with_processor(message_processor.clone());
+        if let Some(maintenance) = &maintenance {
+            server = server.with_maintenance(maintenance.clone());
+        }
+        if let Some(presence) = &presence {
+            server = server.with_presence(presence.clone());
+        }
+        if let Some(log_level) = telemetry::log_level() {
+            server = server.with_log_level(log_level);
+        }
+        if let Some(live_tail) = &live_tail {
+            server = server.with_live_tail(live_tail.clone());
+        }
+        if let Some(commands) = &commands {
+            server = server.with_commands(commands.clone());
+        }
+        server.start(&admin.bind).await?;
+    }
+
+    if let Some(grpc_admin) = &config.grpc_admin {
+        let mut server = GrpcAdminServer::new(
+            message_processor.clone(),
+            pipeline_control.clone(),
+            shutdown,
+        );
+        if let Some(geofences) = &geofences {
+            server = server.with_geofences(geofences.clone());
+        }
+        server.start(&grpc_admin.bind).await?;
+    }
 
     Ok(Services {
         message_consumer,
         database,
         message_processor,
-        message_receiver,
+        health,
+        capture,
+        mqtt: mqtt_publisher,
+        device_acks,
+        commands,
+        lease: config
+            .handover
+            .as_ref()
+            .map(|handover| Arc::new(InstanceLease::new(handover.clone(), config.database_urls()))),
     })
 }
 
+/// Almacén de claves de idempotencia. Con `shared == false` solo usa la caché en
+/// memoria, para que un replay a tablas de prueba no marque mensajes como procesados.
+async fn connect_idempotency(
+    config: &AppConfig,
+    shared: bool,
+) -> Result<Option<Arc<IdempotencyStore>>> {
+    let redis_url = config
+        .database
+        .redis_state
+        .as_ref()
+        .filter(|_| shared)
+        .map(|redis| redis.url.as_str());
+    Ok(IdempotencyStore::from_config(
+        &config.processing.idempotency,
+        config.processing.dedup_cache_size,
+        redis_url,
+    )
+    .await?
+    .map(Arc::new))
+}
+
+/// Reglas de mapeo de campos de `PROCESSING_FIELD_MAPPING_FILE`, si se configuró
+fn load_field_mapping(config: &AppConfig) -> Result<Option<Arc<FieldMapping>>> {
+    config
+        .processing
+        .field_mapping_file
+        .as_deref()
+        .map(|path| FieldMapping::from_file(path).map(Arc::new))
+        .transpose()
+}
+
+/// Verificación de firmas de `PAYLOAD_AUTH_KEYS`, si se configuró
+fn payload_authenticator(config: &AppConfig) -> Option<Arc<PayloadAuthenticator>> {
+    config
+        .payload_auth
+        .as_ref()
+        .map(|auth| Arc::new(PayloadAuthenticator::new(auth)))
+}
+
+/// Cifrado de `raw_message` con `RAW_ENCRYPTION_KEY`, si se configuró
+fn raw_cipher(config: &AppConfig) -> Result<Option<Arc<RawCipher>>> {
+    config
+        .database
+        .raw_encryption
+        .as_ref()
+        .map(|encryption| RawCipher::new(encryption).map(Arc::new))
+        .transpose()
+}
+
+/// Descifra los valores de `raw_message` de `input` (uno por línea) y los escribe en
+/// `output` como captura de tramas crudas, para reprocesarlas con `replay-file`
+async fn run_decrypt_raw(
+    config: &AppConfig,
+    input: &std::path::Path,
+    output: &std::path::Path,
+) -> Result<()> {
+    let cipher = raw_cipher(config)?.context("decrypt-raw requiere RAW_ENCRYPTION_KEY")?;
+    let values = tokio::fs::read_to_string(input)
+        .await
+        .with_context(|| format!("No se pudo leer {}", input.display()))?;
+
+    let mut capture = String::new();
+    let (mut decrypted, mut failed) = (0u64, 0u64);
+    // `\N` es NULL en la salida de `\copy`
+    for (line, value) in values.lines().map(str::trim).enumerate() {
+        if value.is_empty() || value == "\\N" {
+            continue;
+        }
+        match cipher.decrypt(value) {
+            Ok(frame) => {
+                capture.push_str(&serde_json::to_string(&CapturedPayload::raw_frame(&frame))?);
+                capture.push('\n');
+                decrypted += 1;
+            }
+            Err(e) => {
+                warn!("⚠️ Línea {} sin descifrar: {:#}", line + 1, e);
+                failed += 1;
+            }
+        }
+    }
+    tokio::fs::write(output, capture)
+        .await
+        .with_context(|| format!("No se pudo escribir {}", output.display()))?;
+
+    info!(
+        "🔓 {} tramas descifradas en {} ({} con error)",
+        decrypted,
+        output.display(),
+        failed
+    );
+    Ok(())
+}
+
+/// Aplica las migraciones pendientes y termina
+async fn run_migrate(config: &AppConfig) -> Result<()> {
+    let database = DatabaseService::new(
+        &config.database_urls(),
+        config.database.max_connections,
+        config.processing.batch_processing_size,
+    )
+    .await
+    .context(ShutdownReason::DatabaseUnreachable)?
+    .with_tables(config.database.tables.clone());
+    database.run_migrations().await
+}
+
+/// Conecta las bases de datos de fan-out e inicia la tarea de flush de cada una.
+/// Usan las mismas tablas que el primario; ClickHouse, Elasticsearch y Redis solo reciben los
+/// registros del primario.
+async fn connect_fanout_targets(
+    config: &AppConfig,
+    metrics: &Arc<PipelineMetrics>,
+    raw_cipher: Option<&Arc<RawCipher>>,
+) -> Result<Vec<(String, Arc<DatabaseService>)>> {
+    let mut targets = Vec::new();
+    for target in &config.database.fanout {
+        info!("🔀 Conectando destino de fan-out '{}'...", target.name);
+        let mut database = DatabaseService::new(
+            &target.urls(),
+            target.max_connections,
+            config.processing.batch_processing_size,
+        )
+        .await
+        .context(ShutdownReason::DatabaseUnreachable)?
+        .with_retry(config.database.retry.clone())
+        .with_tables(config.database.tables.clone())
+        .with_current_state_mode(config.database.current_state_mode)
+        .with_current_state_order(config.database.current_state_order)
+        .with_buffer_limit(target.buffer_max_records, target.buffer_overflow_policy)
+        .with_insert_mode(config.database.insert_mode)
+        .with_metrics(metrics.clone());
+        if config.database.audit {
+            database = database.with_audit(&target.name);
+        }
+        if let Some(partitioning) = &config.database.partitioning {
+            database = database.with_partitioning(partitioning.clone());
+        }
+        if let Some(cipher) = raw_cipher {
+            database = database.with_raw_encryption(cipher.clone());
+        }
+        let database = Arc::new(database);
+
+        if config.database.run_migrations {
+            database.run_migrations().await?;
+        }
+
+        database.start_flush_task(
+            std::time::Duration::from_millis(config.database.flush_interval_ms),
+            std::time::Duration::from_secs(config.database.buffer_max_age_secs),
+        );
+        targets.push((target.name.clone(), database));
+    }
+    Ok(targets)
+}
+
+/// Carriles y buffer de BD propios para cada tenant de `TENANT_*`, sobre las mismas
+/// conexiones que `database` pero con sus tablas (`{tenant}` en DB_SCHEMA/tablas)
+async fn apply_tenant_routing(
+    config: &AppConfig,
+    database: &Arc<DatabaseService>,
+    processor: MessageProcessor,
+) -> Result<MessageProcessor> {
+    let Some(routing) = &config.processing.tenant_routing else {
+        return Ok(processor);
+    };
+    let router = TenantRouter::from_config(routing).context(ShutdownReason::ConfigInvalid)?;
+
+    let mut tenants: Vec<(String, Arc<dyn RecordStore>)> =
+        Vec::with_capacity(router.tenants().len());
+    for tenant in router.tenants() {
+        let tenant_database = Arc::new(database.for_tenant(tenant));
+        if config.database.run_migrations {
+            tenant_database.run_migrations().await?;
+        }
+        tenant_database.start_flush_task(
+            std::time::Duration::from_millis(config.database.flush_interval_ms),
+            std::time::Duration::from_secs(config.database.buffer_max_age_secs),
+        );
+        tenants.push((tenant.clone(), tenant_database));
+    }
+
+    Ok(processor.with_tenant_routing(Arc::new(router), tenants, routing.lanes_per_tenant))
+}
+
+/// Procesador de los subcomandos de replay. Con `table_suffix` escribe solo en las
+/// tablas de prueba; sin él reescribe las reales, con sus copias y tenants.
+async fn replay_processor(
+    config: &AppConfig,
+    table_suffix: Option<&str>,
+    backpressure: Backpressure,
+) -> Result<MessageProcessor> {
+    let mut database = DatabaseService::new(
+        &config.database_urls(),
+        config.database.max_connections,
+        config.processing.batch_processing_size,
+    )
+    .await
+    .context(ShutdownReason::DatabaseUnreachable)?
+    .with_retry(config.database.retry.clone())
+    .with_tables(config.database.tables.clone())
+    .with_current_state_mode(config.database.current_state_mode)
+    .with_current_state_order(config.database.current_state_order)
+    .with_buffer_limit(
+        config.database.buffer_max_records,
+        config.database.buffer_overflow_policy,
+    )
+    .with_insert_mode(config.database.insert_mode);
+    let raw_cipher = raw_cipher(config)?;
+    if let Some(cipher) = &raw_cipher {
+        database = database.with_raw_encryption(cipher.clone());
+    }
+    // El estado actual en Redis solo refleja las tablas reales
+    if let (None, Some(redis_state)) = (table_suffix, &config.database.redis_state) {
+        database = database.with_redis_state(RedisStateSink::new(redis_state).await?);
+    }
+    match (table_suffix, &config.database.clickhouse) {
+        (Some(suffix), _) => database = database.with_table_suffix(suffix),
+        // Sin sufijo el replay reescribe el histórico real, incluida la copia en ClickHouse
+        (None, Some(clickhouse)) => {
+            database = database.with_clickhouse(ClickHouseSink::new(
+                clickhouse,
+                config.database.retry.clone(),
+            )?)
+        }
+        (None, None) => {}
+    }
+    // Los documentos usan el uuid como _id, así reindexar el histórico real no duplica
+    if let (None, Some(elasticsearch)) = (table_suffix, &config.database.elasticsearch) {
+        database = database.with_elasticsearch(ElasticsearchSink::new(
+            elasticsearch,
+            config.database.retry.clone(),
+        )?);
+    }
+    if config.database.audit {
+        database = database.with_audit("replay");
+    }
+    if let Some(partitioning) = &config.database.partitioning {
+        database = database.with_partitioning(partitioning.clone());
+    }
+
+    let database = Arc::new(database);
+
+    let processor = MessageProcessor::new(
+        database.clone(),
+        config.processing.batch_processing_size,
+        config.database.flush_interval_ms,
+        backpressure,
+        config.processing.sanitization.clone(),
+    )
+    .with_lanes(config.processing.max_parallel_devices)
+    .with_enrichers(EnricherChain::from_config(&config.processing.enrichment)?)
+    .with_circuit_probe_interval(std::time::Duration::from_secs(
+        config.database.circuit_probe_interval_secs,
+    ));
+    let processor = match config.processing.dry_run {
+        true => processor.with_dry_run(Arc::new(DryRunReport::new())),
+        false => processor,
+    };
+    let processor = match connect_idempotency(config, table_suffix.is_none()).await? {
+        Some(idempotency) => processor.with_idempotency(idempotency),
+        None => processor,
+    };
+    // Como ClickHouse y Redis, los tenants y el fan-out solo aplican al reescribir las
+    // tablas reales
+    let processor = match table_suffix {
+        Some(_) => processor,
+        None => apply_tenant_routing(config, &database, processor).await?,
+    };
+    Ok(match table_suffix {
+        Some(_) => processor,
+        None => connect_fanout_targets(config, &Arc::default(), raw_cipher.as_ref())
+            .await?
+            .into_iter()
+            .fold(processor, |processor, (name, target)| {
+                processor.with_fanout(&name, target)
+            }),
+    })
+}
+
+/// Reprocesa un rango de tiempo del topic hacia la base de datos y termina
+async fn run_replay(
+    config: &AppConfig,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    table_suffix: Option<&str>,
+) -> Result<()> {
+    if from >= to {
+        return Err(anyhow::anyhow!("--from debe ser anterior a --to"));
+    }
+
+    info!(
+        "⏪ Replay de {} desde {} hasta {} (tablas con sufijo: {:?})",
+        config.broker.topic, from, to, table_suffix
+    );
+
+    let backpressure = Backpressure::new(config.processing.message_buffer_size);
+    let processor = replay_processor(config, table_suffix, backpressure.clone()).await?;
+    let mut replay = ReplayService::new(&config.broker, backpressure)?;
+    if let Some(mapping) = load_field_mapping(config)? {
+        replay = replay.with_field_mapping(mapping);
+    }
+    if let Some(authenticator) = payload_authenticator(config) {
+        replay = replay.with_authenticator(authenticator);
+    }
+    if let Some(routing) = &config.processing.tenant_routing {
+        replay = replay.with_topic_tenants(&routing.topics);
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let report = processor.clone();
+    let processor_task = tokio::spawn(async move { processor.start_processing(rx).await });
+
+    // Al terminar el replay se cierra el canal y el procesador guarda el último lote
+    let replayed = replay.run(from, to, tx).await?;
+    processor_task.await??;
+    report.log_dry_run_summary();
+
+    info!("✅ Replay terminado: {} mensajes reprocesados", replayed);
+    Ok(())
+}
+
+/// Reprocesa archivos de captura hacia la base de datos y termina
+async fn run_replay_file(
+    config: &AppConfig,
+    files: &[std::path::PathBuf],
+    speed: f64,
+    table_suffix: Option<&str>,
+) -> Result<()> {
+    if !speed.is_finite() || speed < 0.0 {
+        return Err(anyhow::anyhow!("--speed debe ser 0 o mayor"));
+    }
+
+    info!(
+        "⏯️ Replay de {} archivos de captura a velocidad {} (tablas con sufijo: {:?})",
+        files.len(),
+        speed,
+        table_suffix
+    );
+
+    let backpressure = Backpressure::new(config.processing.message_buffer_size);
+    let processor = replay_processor(config, table_suffix, backpressure.clone()).await?;
+    let mut replay = CaptureReplay::new(&config.broker, backpressure)?;
+    if let Some(mapping) = load_field_mapping(config)? {
+        replay = replay.with_field_mapping(mapping);
+    }
+    if let Some(routing) = &config.processing.tenant_routing {
+        replay = replay.with_topic_tenants(&routing.topics);
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let report = processor.clone();
+    let processor_task = tokio::spawn(async move { processor.start_processing(rx).await });
+
+    // Al terminar los archivos se cierra el canal y el procesador guarda el último lote
+    let (replayed, failed) = replay.run(files, speed, tx).await?;
+    processor_task.await??;
+    report.log_dry_run_summary();
+
+    info!(
+        "✅ Replay terminado: {} mensajes reprocesados, {} payloads con error",
+        replayed, failed
+    );
+    Ok(())
+}
+
+/// Compara un rango del topic con la BD: informa los mensajes que no están en el
+/// histórico ni en la tabla de rechazados, sin escribir nada
+async fn run_verify(
+    config: &AppConfig,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    table_suffix: Option<&str>,
+) -> Result<()> {
+    if from >= to {
+        return Err(anyhow::anyhow!("--from debe ser anterior a --to"));
+    }
+
+    info!(
+        "🔍 Verificando {} desde {} hasta {} contra la BD (tablas con sufijo: {:?})",
+        config.broker.topic, from, to, table_suffix
+    );
+
+    let mut database = DatabaseService::new(
+        &config.database_urls(),
+        config.database.max_connections,
+        config.processing.batch_processing_size,
+    )
+    .await
+    .context(ShutdownReason::DatabaseUnreachable)?
+    .with_tables(config.database.tables.clone());
+    if let Some(suffix) = table_suffix {
+        database = database.with_table_suffix(suffix);
+    }
+
+    let backpressure = Backpressure::new(config.processing.message_buffer_size);
+    let mut replay = ReplayService::new(&config.broker, backpressure)?;
+    if let Some(authenticator) = payload_authenticator(config) {
+        replay = replay.with_authenticator(authenticator);
+    }
+    if let Some(routing) = &config.processing.tenant_routing {
+        replay = replay.with_topic_tenants(&routing.topics);
+    }
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<models::DeviceMessage>();
+
+    let batch_size = config.processing.batch_processing_size.max(1);
+    let checker = tokio::spawn(async move {
+        let mut missing = 0u64;
+        let mut batch = Vec::with_capacity(batch_size);
+        loop {
+            let received = rx.recv_many(&mut batch, batch_size).await;
+            if received == 0 {
+                break;
+            }
+
+            let uuids: Vec<String> = batch.iter().map(|m| m.uuid.clone()).collect();
+            let not_found = database.missing_uuids(&uuids).await?;
+            for message in batch.iter().filter(|m| not_found.contains(&m.uuid)) {
+                warn!(
+                    "🔍 Mensaje no guardado: uuid={} device_id={} received_epoch={}",
+                    message.uuid,
+                    redact::device_id(&message.data.device_id),
+                    message.metadata.received_epoch
+                );
+            }
+            missing += not_found.len() as u64;
+            batch.clear();
+        }
+        Ok::<_, anyhow::Error>(missing)
+    });
+
+    let checked = replay.run(from, to, tx).await?;
+    let missing = checker.await??;
+
+    info!(
+        "✅ Verificación terminada: {} mensajes revisados, {} sin guardar",
+        checked, missing
+    );
+    Ok(())
+}
+
 /// Loop principal de procesamiento
 async fn start_processing_loop(
     services: Services,
     shutdown_signal: tokio::sync::oneshot::Receiver<()>,
-) -> Result<()> {
+    drain_timeout: std::time::Duration,
+    supervisor_config: config::SupervisorConfig,
+    watchdog_config: Option<config::WatchdogConfig>,
+) -> ShutdownReason {
     info!("🚀 Iniciando loop principal de procesamiento...");
 
-    // Start message consumer (spawns its own background task internally)
-    let _consumer_receiver = services.message_consumer.start_consuming().await?;
-    // Note: We ignore this receiver since we already have one from initialization
+    let mut supervisor = Supervisor::new(supervisor_config);
+    let mut shutdown_signal = shutdown_signal;
+
+    // Health check task; también corre mientras se espera el lease
+    let mut health_task = spawn_health_task(services.health.clone());
+    supervisor.started(HEALTH_TASK);
 
-    // Start message processor
-    let processor = services.message_processor.clone();
-    let message_receiver = services.message_receiver;
-    let processor_task = tokio::spawn(async move {
-        if let Err(e) = processor.start_processing(message_receiver).await {
-            error!("Error en message processor: {}", e);
+    // Con HANDOVER_LEASE_KEY solo se consume con el lease tomado: la instancia
+    // anterior drena y lo libera antes de que esta empiece a leer
+    if let Some(lease) = &services.lease {
+        tokio::select! {
+            _ = lease.acquire() => {}
+            _ = &mut shutdown_signal => {
+                info!("🔔 Señal de shutdown recibida en espera del lease");
+                health_task.abort();
+                return ShutdownReason::Completed;
+            }
         }
+    }
+
+    // Start message processor. El supervisor reinicia el consumo junto con el
+    // procesador si este termina
+    let mut processor_task = match services.message_consumer.start_consuming().await {
+        Ok(receiver) => spawn_processor(services.message_processor.clone(), receiver),
+        Err(e) => tokio::spawn(async move { Err(e) }),
+    };
+    supervisor.started(PROCESSOR_TASK);
+
+    // Retención de particiones: se revisa cada hora
+    if services.database.partition_retention_enabled() {
+        let retention_db = services.database.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                match retention_db.drop_expired_partitions().await {
+                    Ok(0) => debug!("🗑️ Sin particiones vencidas"),
+                    Ok(dropped) => info!("🗑️ {} particiones vencidas eliminadas", dropped),
+                    Err(e) => error!("❌ Error aplicando retención de particiones: {}", e),
+                }
+            }
+        });
+    }
+
+    // Statistics task
+    let mut stats_task = spawn_stats_task(
+        services.message_processor.clone(),
+        services.message_consumer.clone(),
+        services.device_acks.clone(),
+        services.commands.clone(),
+    );
+    supervisor.started(STATS_TASK);
+
+    // Watchdog de etapas atascadas; con WATCHDOG_ACTION=restart pide reiniciar el procesador
+    let (restart_sender, mut restart_requests) = tokio::sync::mpsc::unbounded_channel();
+    let watchdog_task = watchdog_config.map(|config| {
+        Watchdog::new(
+            config,
+            services.message_processor.clone(),
+            services.message_consumer.clone(),
+        )
+        .start(restart_sender)
     });
 
-    // Health check task
-    let health_db = services.database.clone();
-    let health_task = tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
-        loop {
-            interval.tick().await;
-            let db_health = health_db.health_check().await.unwrap_or(false);
-            if !db_health {
-                warn!("⚠️ Base de datos no está saludable");
-            } else {
-                info!("💚 Base de datos saludable");
+    // Credenciales MQTT rechazadas: reconectar no sirve, se termina con su código
+    let mqtt_auth_failure = async {
+        let sink = async {
+            match &services.mqtt {
+                Some(mqtt) => mqtt.auth_failure().await,
+                None => std::future::pending().await,
+            }
+        };
+        let acks = async {
+            match &services.device_acks {
+                Some(acks) => acks.auth_failure().await,
+                None => std::future::pending().await,
             }
+        };
+        let commands = async {
+            match &services.commands {
+                Some(commands) => commands.auth_failure().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = sink => {}
+            _ = acks => {}
+            _ = commands => {}
+        }
+    };
+    tokio::pin!(mqtt_auth_failure);
+
+    // Sin la conexión del lease otra instancia puede tomarlo: se drena y se termina.
+    // En un Box para soltarlo (y su conexión) antes de liberar el lease.
+    let mut lease_lost = Box::pin(async {
+        match &services.lease {
+            Some(lease) => lease.lost().await,
+            None => std::future::pending().await,
         }
     });
 
-    // Statistics task
-    let stats_processor = services.message_processor.clone();
-    let stats_task = tokio::spawn(async move {
+    // Esperar la señal de shutdown, reiniciando las tareas que terminen
+    let mut escalated = None;
+    let mut mqtt_rejected = false;
+    let mut lease_expired = false;
+    loop {
+        let (task, outcome) = tokio::select! {
+            _ = &mut shutdown_signal => {
+                info!("🔔 Señal de shutdown recibida");
+                break;
+            }
+            _ = &mut mqtt_auth_failure => {
+                mqtt_rejected = true;
+                break;
+            }
+            _ = &mut lease_lost => {
+                lease_expired = true;
+                break;
+            }
+            outcome = &mut processor_task => (PROCESSOR_TASK, outcome),
+            outcome = &mut health_task => (HEALTH_TASK, outcome),
+            outcome = &mut stats_task => (STATS_TASK, outcome),
+            Some(stage) = restart_requests.recv() => {
+                warn!("🐕 Reiniciando el procesador por la etapa atascada '{}'", stage);
+                // El supervisor lo reinicia, junto con el consumo, al ver la tarea cancelada
+                processor_task.abort();
+                continue;
+            }
+        };
+
+        let Some(backoff) = supervisor.on_exit(task, outcome) else {
+            escalated = Some(task);
+            break;
+        };
+        tokio::select! {
+            _ = &mut shutdown_signal => {
+                info!("🔔 Señal de shutdown recibida");
+                break;
+            }
+            _ = tokio::time::sleep(backoff) => {}
+        }
+
+        match task {
+            PROCESSOR_TASK => {
+                // El canal anterior se cerró con el procesador: pedir uno nuevo al consumidor
+                processor_task = match services.message_consumer.start_consuming().await {
+                    Ok(receiver) => spawn_processor(services.message_processor.clone(), receiver),
+                    Err(e) => tokio::spawn(async move { Err(e) }),
+                };
+            }
+            HEALTH_TASK => health_task = spawn_health_task(services.health.clone()),
+            _ => {
+                stats_task = spawn_stats_task(
+                    services.message_processor.clone(),
+                    services.message_consumer.clone(),
+                    services.device_acks.clone(),
+                    services.commands.clone(),
+                )
+            }
+        }
+        supervisor.started(task);
+    }
+
+    // El drenado detiene el consumo a propósito
+    if let Some(task) = watchdog_task {
+        task.abort();
+    }
+
+    // Graceful shutdown
+    info!("🔄 Iniciando shutdown graceful...");
+    let abandoned = drain(
+        services.message_consumer.as_ref(),
+        &services.message_processor,
+        processor_task,
+        drain_timeout,
+    )
+    .await;
+
+    // Disconnect message consumer
+    if let Err(e) = services.message_consumer.disconnect().await {
+        error!("Error desconectando message consumer: {}", e);
+    }
+    if let Some(capture) = &services.capture {
+        capture.close().await;
+    }
+    services.message_processor.log_dry_run_summary();
+
+    // Con el drenado terminado la siguiente instancia puede empezar a consumir
+    drop(lease_lost);
+    if let Some(lease) = &services.lease {
+        lease.release().await;
+    }
+
+    if let Some(task) = escalated {
+        error!(
+            "❌ Tarea '{}' falló repetidamente ({} mensajes abandonados)",
+            task, abandoned
+        );
+        return ShutdownReason::SupervisorGaveUp;
+    }
+    if mqtt_rejected {
+        return ShutdownReason::MqttAuthFailed;
+    }
+    if lease_expired {
+        return ShutdownReason::LeaseLost;
+    }
+    if abandoned > 0 {
+        error!("❌ Shutdown con {} mensajes abandonados", abandoned);
+        return ShutdownReason::DrainTimeout;
+    }
+    info!("✅ Shutdown completado");
+    ShutdownReason::Completed
+}
+
+/// Tareas principales reiniciadas por el supervisor
+const PROCESSOR_TASK: &str = "procesador";
+const HEALTH_TASK: &str = "health check";
+const STATS_TASK: &str = "estadísticas";
+
+fn spawn_processor(
+    processor: MessageProcessor,
+    message_receiver: tokio::sync::mpsc::UnboundedReceiver<models::DeviceMessage>,
+) -> tokio::task::JoinHandle<Result<()>> {
+    tokio::spawn(async move { processor.start_processing(message_receiver).await })
+}
+
+fn spawn_health_task(health: Arc<HealthMonitor>) -> tokio::task::JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(health.interval());
+        loop {
+            interval.tick().await;
+            health.check().await;
+        }
+    })
+}
+
+fn spawn_stats_task(
+    stats_processor: MessageProcessor,
+    stats_consumer: Arc<dyn MessageConsumer>,
+    device_acks: Option<Arc<DeviceAckPublisher>>,
+    commands: Option<Arc<CommandForwarder>>,
+) -> tokio::task::JoinHandle<Result<()>> {
+    tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
         loop {
             interval.tick().await;
 
             let stats = stats_processor.get_statistics().await;
             info!(
-                "📊 Estadísticas - DB Buffer: {}, Batch Size: {}",
-                stats.db_buffer_size, stats.batch_size
+                "📊 Estadísticas - DB Buffer: {} ({} descartados), Campos inválidos: {} ({} fechas), Ya procesados: {}, Posiciones filtradas: {} baja calidad / {} repetidas / {} antiguas / {} por muestreo, Eventos de geocerca: {}, Eventos de viaje: {}, Eventos de conducción: {}, Eventos de conductor: {}, Eventos de alimentación: {}, Cambios de conectividad: {}, Batch Size: {}, Flush: {}ms, Circuito BD: {}",
+                stats.db_buffer_size,
+                stats.db_buffer_dropped,
+                stats.invalid_fields,
+                stats.invalid_datetimes,
+                stats.processed_duplicates,
+                stats.low_quality_positions,
+                stats.duplicate_positions,
+                stats.stale_positions,
+                stats.downsampled_positions,
+                stats.geofence_events,
+                stats.trip_events,
+                stats.driving_events,
+                stats.driver_events,
+                stats.power_events,
+                stats.presence_events,
+                stats.batch_size,
+                stats.flush_interval_ms,
+                if stats.circuit_open {
+                    "abierto"
+                } else {
+                    "cerrado"
+                }
             );
-        }
-    });
 
-    // Wait for shutdown signal or task completion
-    tokio::select! {
-        _ = shutdown_signal => {
-            info!("🔔 Señal de shutdown recibida");
+            info!(
+                "📈 Mensajes/s: {} - Por fabricante: {} - Errores: {} - Escritura de lotes p50/p95: {}",
+                stats
+                    .sources
+                    .iter()
+                    .map(|source| format!("{}={:.1}", source.source, source.messages_per_sec))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                stats
+                    .manufacturers
+                    .iter()
+                    .map(|(manufacturer, count)| format!("{}={}", manufacturer, count))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                stats
+                    .errors
+                    .iter()
+                    .map(|error| format!(
+                        "{}={} (último {})",
+                        error.category,
+                        error.count,
+                        error.last_at.format("%H:%M:%S")
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                match (stats.flush_latency_p50_ms, stats.flush_latency_p95_ms) {
+                    (Some(p50), Some(p95)) => format!("{:.0}/{:.0}ms", p50, p95),
+                    _ => "-".to_string(),
+                }
+            );
+
+            info!(
+                "🧮 Memoria estimada: {} - cola {}, carriles {}, buffers de BD {}, salida Kafka {}{}",
+                format_bytes(stats.memory.total),
+                format_bytes(stats.memory.input_channel),
+                format_bytes(stats.memory.lanes),
+                format_bytes(stats.memory.db_buffers),
+                format_bytes(stats.memory.kafka_output),
+                if stats.memory.limited {
+                    " (consumo frenado por el límite)"
+                } else {
+                    ""
+                }
+            );
+
+            if let Some(dry_run) = &stats.dry_run {
+                info!(
+                    "🧪 Dry-run: {} registros se habrían insertado, {} tipos de campo inválido, {} sin convertir",
+                    dry_run.inserts.values().sum::<u64>(),
+                    dry_run.field_issues.len(),
+                    dry_run.conversion_errors
+                );
+            }
+
+            if let Some(shard) = &stats.shard {
+                info!(
+                    "🧩 Shard {}/{}: {} mensajes de otros shards descartados, {} republicados ({} errores)",
+                    shard.index,
+                    shard.count,
+                    shard.skipped,
+                    shard.republished,
+                    shard.republish_errors
+                );
+            }
+
+            if let Some(acks) = &device_acks {
+                let acks = acks.statistics();
+                info!(
+                    "📨 Confirmaciones Queclink: {} enviadas, {} descartadas",
+                    acks.sent, acks.dropped
+                );
+            }
+
+            if let Some(commands) = &commands {
+                let commands = commands.statistics();
+                info!(
+                    "📟 Comandos: {} enviados, {} fallidos, {} repetidos",
+                    commands.sent, commands.failed, commands.duplicates
+                );
+            }
+
+            let lane_messages = stats.lanes.iter().map(|lane| lane.messages);
+            info!(
+                "🛣️ Carriles: {} - mensajes por carril min {} / max {}",
+                stats.lanes.len(),
+                lane_messages.clone().min().unwrap_or(0),
+                lane_messages.max().unwrap_or(0)
+            );
+            for (lane, lane_stats) in stats.lanes.iter().enumerate() {
+                debug!(
+                    "🛣️ Carril {}: {} mensajes en {} lotes",
+                    lane, lane_stats.messages, lane_stats.batches
+                );
+            }
+
+            for tenant in &stats.tenants {
+                info!(
+                    "🏢 Tenant '{}' - {} mensajes en {} lotes, Buffer: {} ({} descartados)",
+                    tenant.tenant,
+                    tenant.messages,
+                    tenant.batches,
+                    tenant.buffer_size,
+                    tenant.buffer_dropped
+                );
+            }
+
+            for target in &stats.fanout {
+                info!(
+                    "🔀 Fan-out '{}' - Buffer: {} ({} descartados)",
+                    target.name, target.buffer_size, target.buffer_dropped
+                );
+            }
+
+            for sink in &stats.sinks {
+                info!(
+                    "📦 Salida '{}' - Enviadas: {}, En cola: {} lotes, Fallidas: {}, Descartadas: {}",
+                    sink.name, sink.sent, sink.queued, sink.failed, sink.dropped
+                );
+            }
+
+            let raw_frames = stats_consumer.raw_frames();
+            if raw_frames.decoded + raw_frames.invalid > 0 || !raw_frames.unsupported.is_empty() {
+                info!(
+                    "🔎 Tramas crudas - Decodificadas: {}, Inválidas: {}, No soportadas: {}",
+                    raw_frames.decoded,
+                    raw_frames.invalid,
+                    raw_frames
+                        .unsupported
+                        .iter()
+                        .map(|(report, count)| format!("{}={}", report, count))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+
+            match stats_consumer.lag().await {
+                Ok(partitions) => {
+                    let total_lag: i64 = partitions.iter().map(|p| p.lag).sum();
+                    for p in &partitions {
+                        debug!(
+                            "📉 Lag {}[{}]: {} (committed: {:?}, high-watermark: {})",
+                            p.topic, p.partition, p.lag, p.committed_offset, p.high_watermark
+                        );
+                    }
+                    info!(
+                        "📉 Lag del consumidor: {} mensajes en {} particiones",
+                        total_lag,
+                        partitions.len()
+                    );
+                }
+                Err(e) => warn!("⚠️ No se pudo calcular el lag del consumidor: {}", e),
+            }
         }
-        _ = processor_task => {
-            warn!("⚙️ Processor task terminado inesperadamente");
+    })
+}
+
+/// Detiene la lectura de Kafka y espera a que el procesador escriba lo que tiene en
+/// cola, en sus carriles y en los buffers de BD, informando el progreso cada segundo.
+/// Al vencer `timeout` se abandona lo pendiente. Devuelve los mensajes abandonados.
+async fn drain(
+    message_consumer: &dyn MessageConsumer,
+    processor: &MessageProcessor,
+    mut processor_task: tokio::task::JoinHandle<Result<()>>,
+    timeout: std::time::Duration,
+) -> usize {
+    info!("🚰 Drenando el pipeline (límite {}s)...", timeout.as_secs());
+    if let Err(e) = message_consumer.stop_consuming().await {
+        error!("Error deteniendo el consumo: {}", e);
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut progress = tokio::time::interval(std::time::Duration::from_secs(1));
+
+    // Al cerrarse el canal de entrada los carriles escriben su último lote y el
+    // procesador hace flush de los buffers
+    if !processor_task.is_finished() {
+        loop {
+            tokio::select! {
+                _ = &mut processor_task => break,
+                _ = progress.tick() => {
+                    let status = processor.drain_status().await;
+                    info!(
+                        "⏳ Drenando: {} en cola, {} en carriles, {} en buffers de BD",
+                        status.queued, status.in_lanes, status.buffered
+                    );
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    warn!("⏰ Límite de drenado alcanzado con el procesador activo");
+                    processor_task.abort();
+                    break;
+                }
+            }
         }
-        _ = health_task => {
-            warn!("💊 Health check task terminado inesperadamente");
+    }
+
+    // Reintentar el flush de lo que quedó en los buffers (p. ej. BD caída) hasta el límite
+    loop {
+        if let Err(e) = processor.flush_all_buffers().await {
+            error!("Error flushing buffers: {}", e);
         }
-        _ = stats_task => {
-            warn!("📊 Stats task terminado inesperadamente");
+        let status = processor.drain_status().await;
+        if status.buffered == 0 || tokio::time::Instant::now() >= deadline {
+            break;
         }
+        info!(
+            "⏳ {} registros siguen en los buffers de BD, reintentando",
+            status.buffered
+        );
+        progress.tick().await;
     }
 
-    // Graceful shutdown
-    info!("🔄 Iniciando shutdown graceful...");
-
-    // Flush all pending data
-    if let Err(e) = services.message_processor.flush_all_buffers().await {
-        error!("Error flushing buffers: {}", e);
+    let abandoned = processor.drain_status().await.total();
+    if abandoned > 0 {
+        error!("❌ Drenado incompleto: {} mensajes abandonados", abandoned);
+    } else {
+        info!("✅ Drenado completo, sin mensajes pendientes");
     }
+    abandoned
+}
 
-    // Disconnect message consumer
-    if let Err(e) = services.message_consumer.disconnect().await {
-        error!("Error desconectando message consumer: {}", e);
-    }
+/// Con cada SIGUSR1 pasa los logs al siguiente nivel (configurado → debug → trace)
+#[cfg(unix)]
+fn spawn_log_level_signal_task() {
+    use tokio::signal::unix::{signal, SignalKind};
 
-    info!("✅ Shutdown completado");
-    Ok(())
+    let Some(log_level) = telemetry::log_level() else {
+        return;
+    };
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("⚠️ No se pudo escuchar SIGUSR1: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            info!("🔔 SIGUSR1 recibido");
+            if let Err(e) = log_level.cycle() {
+                error!("❌ Error cambiando el nivel de logs: {}", e);
+            }
+        }
+    });
 }
 
+#[cfg(not(unix))]
+fn spawn_log_level_signal_task() {}
+
 /// Configura el handler para señales de shutdown graceful
-fn setup_shutdown_handler() -> tokio::sync::oneshot::Receiver<()> {
+fn setup_shutdown_handler(
+    requested: Arc<tokio::sync::Notify>,
+) -> tokio::sync::oneshot::Receiver<()> {
     let (tx, rx) = tokio::sync::oneshot::channel();
+    let terminate = terminate_signal();
 
     tokio::spawn(async move {
-        let mut tx = Some(tx);
-
-        // Handle Ctrl+C
-        if let Ok(()) = signal::ctrl_c().await {
-            info!("🔔 Ctrl+C recibido");
-            if let Some(sender) = tx.take() {
-                let _ = sender.send(());
-            }
+        tokio::select! {
+            // Handle Ctrl+C
+            Ok(()) = signal::ctrl_c() => info!("🔔 Ctrl+C recibido"),
+            name = terminate => info!("🔔 {} recibido", name),
+            _ = requested.notified() => info!("🔔 Shutdown solicitado por la API de administración"),
         }
+        let _ = tx.send(());
     });
 
     rx
 }
+
+/// SIGTERM (Kubernetes, `docker stop`) o SIGQUIT. Los handlers se registran al
+/// llamarla, antes del arranque de los servicios: como PID 1 del contenedor el
+/// proceso ignora las señales sin handler y terminaría con SIGKILL tras el período
+/// de gracia, perdiendo los buffers.
+#[cfg(unix)]
+fn terminate_signal() -> impl std::future::Future<Output = &'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let listen = |kind: SignalKind, name: &'static str| match signal(kind) {
+        Ok(signals) => Some(signals),
+        Err(e) => {
+            warn!("⚠️ No se pudo escuchar {}: {}", name, e);
+            None
+        }
+    };
+    let terminate = listen(SignalKind::terminate(), "SIGTERM");
+    let quit = listen(SignalKind::quit(), "SIGQUIT");
+
+    async move {
+        let received = |signals: Option<tokio::signal::unix::Signal>| async move {
+            match signals {
+                Some(mut signals) => signals.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            Some(()) = received(terminate) => "SIGTERM",
+            Some(()) = received(quit) => "SIGQUIT",
+            else => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate_signal() -> impl std::future::Future<Output = &'static str> {
+    std::future::pending()
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Confirmación pendiente de un mensaje de un broker con acks manuales (AMQP).
+///
+/// El mensaje y los registros construidos a partir de él comparten el token; cuando
+/// se suelta la última copia (registro escrito en la BD, o mensaje descartado por
+/// un filtro o perdido tras los reintentos) se envía `tag` al consumidor para que
+/// lo confirme. Si el proceso muere antes, el broker vuelve a entregarlo.
+#[derive(Clone)]
+pub struct AckToken(Arc<PendingAck>);
+
+struct PendingAck {
+    tag: u64,
+    acks: mpsc::UnboundedSender<u64>,
+}
+
+impl AckToken {
+    pub fn new(tag: u64, acks: mpsc::UnboundedSender<u64>) -> Self {
+        Self(Arc::new(PendingAck { tag, acks }))
+    }
+}
+
+impl Drop for PendingAck {
+    fn drop(&mut self) {
+        let _ = self.acks.send(self.tag);
+    }
+}
+
+impl fmt::Debug for AckToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AckToken").field(&self.0.tag).finish()
+    }
+}
@@ -3,7 +3,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use tracing::warn;
 
-use super::{DeviceMessage, Manufacturer};
+use super::{AckToken, Manufacturer, NormalizedPosition};
+use crate::config::{FieldOverflowPolicy, SanitizationConfig};
+use crate::redact;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct CommunicationRecord {
@@ -51,119 +53,301 @@ pub struct CommunicationRecord {
     pub raw_message: Option<String>,
     pub received_at: Option<NaiveDateTime>,
     pub created_at: Option<NaiveDateTime>,
+    /// Calidad del fix GPS de 0 a 100 (NULL = no evaluada)
+    pub quality_score: Option<i16>,
+    /// Campos decodificados propios del fabricante que no tienen columna
+    pub decoded_extra: Option<serde_json::Value>,
+    pub ignition: Option<bool>,
+    pub panic_button: Option<bool>,
+    pub door_sensor: Option<bool>,
+    pub relay_output: Option<bool>,
+    /// Confirmación del mensaje de origen, pendiente hasta que el registro se escriba
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub ack: Option<AckToken>,
 }
 
 impl CommunicationRecord {
-    /// Convierte un DeviceMessage a un CommunicationRecord para insertar en la BD
-    pub fn from_device_message(msg: &DeviceMessage) -> anyhow::Result<Self> {
-        // Validación preventiva de longitudes de campos
-        Self::validate_field_length("cell_id", &msg.data.cell_id, 10, &msg.data.device_id);
-        Self::validate_field_length("lac", &msg.data.lac, 10, &msg.data.device_id);
-        Self::validate_field_length("mcc", &msg.data.mcc, 10, &msg.data.device_id);
-        Self::validate_field_length("mnc", &msg.data.mnc, 10, &msg.data.device_id);
-        Self::validate_field_length("model", &msg.data.model, 50, &msg.data.device_id);
-        Self::validate_field_length("firmware", &msg.data.firmware, 50, &msg.data.device_id);
-        Self::validate_field_length("msg_class", &msg.data.msg_class, 20, &msg.data.device_id);
-
-        let gps_datetime = if !msg.data.gps_datetime.is_empty() {
-            chrono::NaiveDateTime::parse_from_str(&msg.data.gps_datetime, "%Y-%m-%d %H:%M:%S").ok()
-        } else {
-            None
-        };
+    /// Tamaño aproximado en memoria: la estructura más sus textos y campos extra
+    pub fn approx_size(&self) -> usize {
+        let text = [
+            &self.cell_id,
+            &self.delivery_type,
+            &self.engine_status,
+            &self.firmware,
+            &self.fix_status,
+            &self.lac,
+            &self.mcc,
+            &self.mnc,
+            &self.model,
+            &self.msg_class,
+            &self.alert_type,
+            &self.network_status,
+            &self.client_ip,
+            &self.raw_message,
+        ]
+        .iter()
+        .map(|field| field.as_ref().map_or(0, String::len))
+        .sum::<usize>();
+        let extra = self
+            .decoded_extra
+            .as_ref()
+            .map_or(0, |extra| extra.to_string().len());
+        std::mem::size_of::<Self>() + self.uuid.len() + self.device_id.len() + text + extra
+    }
+
+    /// Convierte una posición normalizada en el registro para insertar en la BD,
+    /// aplicando la política de saneamiento a los campos con límite de longitud
+    pub fn from_position(
+        position: &NormalizedPosition,
+        sanitization: &SanitizationConfig,
+    ) -> anyhow::Result<Self> {
+        let device_id = &position.device_id;
+        let field =
+            |name: &str, value: &str| Self::sanitize_field(name, value, device_id, sanitization);
 
-        let client_ip = if msg.metadata.client_ip.is_empty() {
+        let client_ip = if position.client_ip.is_empty() {
             None
         } else {
-            Some(msg.metadata.client_ip.clone())
+            field("client_ip", &position.client_ip)?
         };
 
         let now = Utc::now().naive_utc();
 
         Ok(CommunicationRecord {
             id: None,
-            uuid: msg.uuid.clone(),
-            device_id: msg.data.device_id.clone(),
-            manufacturer: Some(msg.get_manufacturer()),
-            backup_battery_voltage: Self::parse_f64(&msg.data.backup_battery_voltage),
-            backup_battery_percent: Self::parse_f64(&msg.data.backup_battery_percent),
-            cell_id: Some(msg.data.cell_id.clone()),
-            course: Self::parse_f64(&msg.data.course),
-            delivery_type: Some(msg.data.delivery_type.clone()),
-            engine_status: Some(msg.data.engine_status.clone()),
-            firmware: Some(msg.data.firmware.clone()),
-            fix_status: Some(msg.data.fix_status.clone()),
-            gps_datetime,
-            gps_epoch: Self::parse_i64(&msg.data.gps_epoch),
-            idle_time: Self::parse_i32(&msg.data.idle_time),
-            lac: Some(msg.data.lac.clone()),
-            latitude: Self::parse_f64(&msg.data.latitude),
-            longitude: Self::parse_f64(&msg.data.longitude),
-            main_battery_voltage: Self::parse_f64(&msg.data.main_battery_voltage),
-            mcc: Some(msg.data.mcc.clone()),
-            mnc: Some(msg.data.mnc.clone()),
-            model: Some(msg.data.model.clone()),
-            msg_class: Some(msg.data.msg_class.clone()),
-            msg_counter: Self::parse_i32(&msg.data.msg_counter),
-            alert_type: if msg.data.alert.is_empty() {
-                None
-            } else {
-                Some(msg.data.alert.clone())
+            uuid: position.uuid.clone(),
+            device_id: field("device_id", &position.device_id)?
+                .ok_or_else(|| anyhow::anyhow!("device_id no puede ser NULL"))?,
+            manufacturer: Some(position.manufacturer),
+            backup_battery_voltage: position.backup_battery_voltage_v,
+            backup_battery_percent: position.backup_battery_percent,
+            cell_id: field("cell_id", &position.cell_id)?,
+            course: position.course_deg,
+            delivery_type: field("delivery_type", &position.delivery_type)?,
+            engine_status: position
+                .engine_status
+                .map(|status| status.as_str().to_string()),
+            firmware: field("firmware", &position.firmware)?,
+            fix_status: field("fix_status", &position.fix_status)?,
+            gps_datetime: position.gps_datetime,
+            gps_epoch: position.gps_epoch,
+            idle_time: position.idle_time_s,
+            lac: field("lac", &position.lac)?,
+            latitude: position.latitude,
+            longitude: position.longitude,
+            main_battery_voltage: position.main_battery_voltage_v,
+            mcc: field("mcc", &position.mcc)?,
+            mnc: field("mnc", &position.mnc)?,
+            model: field("model", &position.model)?,
+            msg_class: field("msg_class", &position.msg_class)?,
+            msg_counter: position.msg_counter,
+            alert_type: match &position.alert {
+                Some(alert) => field("alert_type", alert)?,
+                None => None,
             },
-            network_status: Some(msg.data.network_status.clone()),
-            odometer: Self::parse_i64(&msg.data.odometer),
-            rx_lvl: Self::parse_i32(&msg.data.rx_lvl),
-            satellites: Self::parse_i32(&msg.data.satellites),
-            speed: Self::parse_f64(&msg.data.speed),
-            speed_time: Self::parse_i32(&msg.data.speed_time),
-            total_distance: Self::parse_i64(&msg.data.total_distance),
-            trip_distance: Self::parse_i64(&msg.data.trip_distance),
-            trip_hourmeter: Self::parse_i32(&msg.data.trip_hourmeter),
-            bytes_count: Some(msg.metadata.bytes),
+            network_status: field("network_status", &position.network_status)?,
+            odometer: position.odometer_m,
+            rx_lvl: position.rx_lvl,
+            satellites: position.satellites,
+            speed: position.speed_kmh,
+            speed_time: position.speed_time_s,
+            total_distance: position.total_distance_m,
+            trip_distance: position.trip_distance_m,
+            trip_hourmeter: position.trip_hourmeter,
+            bytes_count: Some(position.bytes),
             client_ip,
-            client_port: Some(msg.metadata.client_port),
-            decoded_epoch: Some(msg.metadata.decoded_epoch),
-            received_epoch: Some(msg.metadata.received_epoch),
-            raw_message: Some(msg.raw.clone()),
+            client_port: Some(position.client_port),
+            decoded_epoch: Some(position.decoded_epoch),
+            received_epoch: Some(position.received_epoch),
+            raw_message: Some(position.raw.clone()),
             received_at: Some(now),
             created_at: Some(now),
+            quality_score: position.quality_score.map(i16::from),
+            decoded_extra: position.decoded_extra.clone(),
+            ignition: position.digital_io.ignition,
+            panic_button: position.digital_io.panic_button,
+            door_sensor: position.digital_io.door_sensor,
+            relay_output: position.digital_io.relay_output,
+            ack: None,
         })
     }
 
-    // Funciones auxiliares para parsing seguro
-    fn parse_f64(s: &str) -> Option<f64> {
-        if s.is_empty() {
-            return None;
+    /// Agrupa los registros por fabricante, en el orden del registro de fabricantes.
+    /// Los registros sin fabricante van al fabricante por defecto.
+    pub fn group_by_manufacturer(records: Vec<Self>) -> Vec<(Manufacturer, Vec<Self>)> {
+        let mut groups: Vec<(Manufacturer, Vec<Self>)> = Manufacturer::all()
+            .map(|manufacturer| (manufacturer, Vec::new()))
+            .collect();
+        for record in records {
+            let manufacturer = record.manufacturer.unwrap_or_else(|| {
+                warn!("Registro sin fabricante asignado, usando el fabricante por defecto");
+                Manufacturer::default()
+            });
+            if let Some((_, group)) = groups.iter_mut().find(|(m, _)| *m == manufacturer) {
+                group.push(record);
+            }
         }
-        // Remover el signo '+' si existe
-        let clean = s.strip_prefix('+').unwrap_or(s);
-        clean.parse().ok()
+        groups.retain(|(_, group)| !group.is_empty());
+        groups
     }
 
-    fn parse_i64(s: &str) -> Option<i64> {
-        if s.is_empty() {
-            return None;
+    /// Quita los caracteres de control (PostgreSQL rechaza el byte NUL en un texto y
+    /// con él todo el lote) y aplica la política de desbordamiento a un campo con
+    /// límite configurado. Los límites se miden en caracteres, igual que VARCHAR(n).
+    fn sanitize_field(
+        field_name: &str,
+        value: &str,
+        device_id: &str,
+        sanitization: &SanitizationConfig,
+    ) -> anyhow::Result<Option<String>> {
+        let value = if value.chars().any(char::is_control) {
+            warn!(
+                "⚠️ Campo '{}' con caracteres de control en Device {}, se quitan",
+                field_name,
+                redact::device_id(device_id)
+            );
+            value.chars().filter(|c| !c.is_control()).collect()
+        } else {
+            value.to_string()
+        };
+
+        let Some(&max_len) = sanitization.column_limits.get(field_name) else {
+            return Ok(Some(value));
+        };
+
+        let len = value.chars().count();
+        if len <= max_len {
+            return Ok(Some(value));
         }
-        s.parse().ok()
+
+        match sanitization.policy {
+            FieldOverflowPolicy::Truncate => {
+                let truncated: String = value.chars().take(max_len).collect();
+                warn!(
+                    "⚠️ Campo '{}' excede límite en Device {}: longitud {} > {}, valor truncado: '{}'",
+                    field_name,
+                    redact::device_id(device_id),
+                    len,
+                    max_len,
+                    redact::field(field_name, &truncated)
+                );
+                Ok(Some(truncated))
+            }
+            FieldOverflowPolicy::Null => {
+                warn!(
+                    "⚠️ Campo '{}' excede límite en Device {}: longitud {} > {}, se guarda NULL",
+                    field_name,
+                    redact::device_id(device_id),
+                    len,
+                    max_len
+                );
+                Ok(None)
+            }
+            FieldOverflowPolicy::Reject => Err(anyhow::anyhow!(
+                "Campo '{}' excede límite: longitud {} > {}",
+                field_name,
+                len,
+                max_len
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn message() -> DeviceMessage {
+        DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap()
     }
 
-    fn parse_i32(s: &str) -> Option<i32> {
-        if s.is_empty() {
-            return None;
+    fn to_record(
+        message: &DeviceMessage,
+        sanitization: &SanitizationConfig,
+    ) -> Result<CommunicationRecord, String> {
+        let position = NormalizedPosition::from_device_message(message);
+        CommunicationRecord::from_position(&position, sanitization).map_err(|e| e.to_string())
+    }
+
+    fn policy(policy: FieldOverflowPolicy) -> SanitizationConfig {
+        SanitizationConfig {
+            policy,
+            ..SanitizationConfig::default()
         }
-        s.parse().ok()
     }
 
-    // Validación de longitud de campos
-    fn validate_field_length(field_name: &str, value: &str, max_len: usize, device_id: &str) {
-        if value.len() > max_len {
-            warn!(
-                "⚠️ Campo '{}' excede límite en Device {}: longitud {} > {}, valor truncado: '{}'",
-                field_name,
-                device_id,
-                value.len(),
-                max_len,
-                &value[..max_len.min(value.len())]
-            );
+    #[test]
+    fn nan_and_out_of_range_coordinates_are_stored_as_null() {
+        for (latitude, longitude) in [
+            ("NaN", "-99.1"),
+            ("19.4", "NaN"),
+            ("90.5", "-99.1"),
+            ("19.4", "-180.01"),
+        ] {
+            let mut message = message();
+            message.data.latitude = latitude.to_string();
+            message.data.longitude = longitude.to_string();
+
+            let position = NormalizedPosition::from_device_message(&message);
+            assert_eq!(position.issues.len(), 1, "{} {}", latitude, longitude);
+            assert_eq!(position.issues[0].reason, "fuera de rango");
+
+            let record = to_record(&message, &SanitizationConfig::default()).unwrap();
+            assert!(record.latitude.is_none() || record.longitude.is_none());
+            assert!(record.latitude.is_none_or(f64::is_finite));
+            assert!(record.longitude.is_none_or(f64::is_finite));
         }
+
+        // Los extremos del rango son válidos
+        let mut message = message();
+        message.data.latitude = "-90".to_string();
+        message.data.longitude = "180".to_string();
+        let record = to_record(&message, &SanitizationConfig::default()).unwrap();
+        assert_eq!(
+            (record.latitude, record.longitude),
+            (Some(-90.0), Some(180.0))
+        );
+    }
+
+    #[test]
+    fn oversized_strings_follow_the_overflow_policy() {
+        let mut message = message();
+        message.data.model = "ST".repeat(30);
+
+        let truncated = to_record(&message, &policy(FieldOverflowPolicy::Truncate)).unwrap();
+        assert_eq!(truncated.model.as_deref(), Some("ST".repeat(25).as_str()));
+
+        let nulled = to_record(&message, &policy(FieldOverflowPolicy::Null)).unwrap();
+        assert_eq!(nulled.model, None);
+        assert_eq!(nulled.firmware.as_deref(), Some("1097B"));
+
+        let rejected = to_record(&message, &policy(FieldOverflowPolicy::Reject)).unwrap_err();
+        assert!(rejected.contains("'model'"), "{}", rejected);
+
+        // El límite es de caracteres, no de bytes
+        message.data.model = "Ñ".repeat(50);
+        let exact = to_record(&message, &policy(FieldOverflowPolicy::Reject)).unwrap();
+        assert_eq!(exact.model.map(|model| model.chars().count()), Some(50));
+    }
+
+    #[test]
+    fn control_characters_are_removed() {
+        let mut message = message();
+        message.data.firmware = "10\097B\r\n".to_string();
+        message.data.cell_id = "0c1e\u{7}02".to_string();
+
+        let record = to_record(&message, &policy(FieldOverflowPolicy::Reject)).unwrap();
+        assert_eq!(record.firmware.as_deref(), Some("1097B"));
+        assert_eq!(record.cell_id.as_deref(), Some("0c1e02"));
+
+        // Se quitan antes de medir el largo: `cell_id` cabe en sus 10 caracteres
+        message.data.cell_id = format!("{}0c1e02", "\0".repeat(8));
+        let record = to_record(&message, &policy(FieldOverflowPolicy::Reject)).unwrap();
+        assert_eq!(record.cell_id.as_deref(), Some("0c1e02"));
     }
 }
@@ -0,0 +1,76 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Origen de un comando
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandSource {
+    /// Tópico `DEVICE_COMMAND_KAFKA_TOPIC`
+    Kafka,
+    /// `POST /devices/{id}/commands` de la API de administración
+    Api,
+}
+
+impl CommandSource {
+    /// Valor guardado en la columna `source`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandSource::Kafka => "kafka",
+            CommandSource::Api => "api",
+        }
+    }
+}
+
+/// Estado de la entrega de un comando al broker MQTT
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// Guardado y encolado, sin confirmar por el broker
+    Pending,
+    /// Confirmado por el broker (PUBACK/PUBREC, o escrito con QoS 0)
+    Sent,
+    /// No se pudo encolar o el broker no lo confirmó a tiempo
+    Failed,
+}
+
+impl CommandStatus {
+    /// Valor guardado en la columna `status`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandStatus::Pending => "pending",
+            CommandStatus::Sent => "sent",
+            CommandStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Comando hacia un equipo, como llega por Kafka (JSON) y como se publica en su
+/// tópico MQTT de bajada
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCommand {
+    /// Id del comando; sin él se genera uno. Un id repetido no se vuelve a enviar.
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub device_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Trama tal como la recibe el equipo, p. ej. `AT+GTRTO=gv300,0,,,,,,0011$`
+    pub command: String,
+}
+
+/// Fila de `device_commands`
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DeviceCommandRecord {
+    pub id: Uuid,
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub command: String,
+    pub source: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub sent_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
@@ -1,11 +1,9 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::SpanContext;
 use serde::{Deserialize, Serialize};
 
-/// Enum que representa los fabricantes de dispositivos soportados
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-pub enum Manufacturer {
-    Suntech,
-    Queclink,
-}
+use super::schema_version::{self, default_schema_version};
+use super::{AckToken, DecodedData, Manufacturer};
 
 /// Estructura principal que representa un mensaje de dispositivo estandarizado
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,15 +13,58 @@ pub struct DeviceMessage {
     pub metadata: DeviceMetadata,
     pub raw: String,
     pub uuid: String,
+    /// Tenant asignado por el tópico de origen (`TENANT_TOPICS`) o, en AMQP, el de
+    /// la clave que firmó el mensaje. No se lee del payload: lo decide el
+    /// consumidor, nunca quien publica el mensaje.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Versión del layout; los mensajes de versiones anteriores o posteriores se
+    /// convierten a `CURRENT_SCHEMA_VERSION` al leerlos
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    /// Tópico (u otro origen) del que se leyó el mensaje, para las estadísticas
+    #[serde(skip)]
+    pub source: Option<String>,
+    /// Span de consumo del mensaje; el lote que lo procesa se enlaza a él
+    #[serde(skip)]
+    pub trace_context: Option<SpanContext>,
+    /// Confirmación al broker cuando el mensaje se guarda o se descarta (AMQP)
+    #[serde(skip)]
+    pub ack: Option<AckToken>,
+    /// Tenant cuya clave firmó el payload (`PAYLOAD_AUTH_KEYS`), para volver a
+    /// firmarlo al republicarlo en el tópico de otro shard
+    #[serde(skip)]
+    pub signed_by: Option<String>,
 }
 
 impl DeviceMessage {
+    /// Deserializa un mensaje JSON de cualquier versión soportada del envelope
+    pub fn from_json(payload: &[u8]) -> Result<Self> {
+        let message: serde_json::Value =
+            serde_json::from_slice(payload).context("JSON inválido")?;
+        Self::from_json_value(message)
+    }
+
+    /// Igual que `from_json`, para un mensaje ya parseado como JSON
+    pub fn from_json_value(mut message: serde_json::Value) -> Result<Self> {
+        schema_version::upgrade_json(&mut message)?;
+        serde_json::from_value(message).context("Mensaje JSON sin el formato de DeviceMessage")
+    }
+
+    /// Tamaño aproximado en memoria. Los campos decodificados salen de la trama, así
+    /// que ocupan más o menos lo mismo que `raw`.
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.raw.len() * 2
+            + self.uuid.len()
+            + self.data.device_id.len()
+            + self.source.as_ref().map_or(0, String::len)
+            + self.tenant.as_ref().map_or(0, String::len)
+    }
+
     /// Determina el fabricante del dispositivo basándose en el contenido del campo decoded
     pub fn get_manufacturer(&self) -> Manufacturer {
-        match &self.decoded {
-            DecodedData::Suntech { .. } => Manufacturer::Suntech,
-            DecodedData::Queclink { .. } => Manufacturer::Queclink,
-        }
+        self.decoded.manufacturer
     }
 }
 
@@ -96,138 +137,6 @@ pub struct DeviceData {
     pub trip_hourmeter: String,
 }
 
-/// Enum que soporta diferentes formatos de datos decodificados según el fabricante
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum DecodedData {
-    Suntech {
-        #[serde(rename = "SuntechRaw")]
-        suntech_raw: Box<SuntechRaw>,
-    },
-    Queclink {
-        #[serde(rename = "QueclinkRaw")]
-        queclink_raw: Box<QueclinkRaw>,
-    },
-}
-
-/// Datos raw de dispositivos Queclink
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct QueclinkRaw {
-    #[serde(rename = "ALTITUDE", default)]
-    pub altitude: String,
-    #[serde(rename = "CELL_ID", default)]
-    pub cell_id: String,
-    #[serde(rename = "CRS", default)]
-    pub course: String,
-    #[serde(rename = "DEVICE_ID", default)]
-    pub device_id: String,
-    #[serde(rename = "FIX", default)]
-    pub fix: String,
-    #[serde(rename = "GPS_DATE_TIME", default)]
-    pub gps_date_time: String,
-    #[serde(rename = "HEADER", default)]
-    pub header: String,
-    #[serde(rename = "LAC", default)]
-    pub lac: String,
-    #[serde(rename = "LAT", default)]
-    pub latitude: String,
-    #[serde(rename = "LON", default)]
-    pub longitude: String,
-    #[serde(rename = "MCC", default)]
-    pub mcc: String,
-    #[serde(rename = "MNC", default)]
-    pub mnc: String,
-    #[serde(rename = "MSG_NUM", default)]
-    pub msg_num: String,
-    #[serde(rename = "PROTOCOL_VERSION", default)]
-    pub protocol_version: String,
-    #[serde(rename = "RESERVED", default)]
-    pub reserved: String,
-    #[serde(rename = "SEND_DATE_TIME", default)]
-    pub send_date_time: String,
-    #[serde(rename = "SPD", default)]
-    pub speed: String,
-}
-
-/// Datos raw de dispositivos Suntech
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct SuntechRaw {
-    #[serde(rename = "ASSIGN_MAP", default)]
-    pub assign_map: String,
-    #[serde(rename = "AXIST_Y", default)]
-    pub axis_y: String,
-    #[serde(rename = "AXIS_X", default)]
-    pub axis_x: String,
-    #[serde(rename = "AXIS_Z", default)]
-    pub axis_z: String,
-    #[serde(rename = "CELL_ID", default)]
-    pub cell_id: String,
-    #[serde(rename = "CRS", default)]
-    pub course: String,
-    #[serde(rename = "DEVICE_ID", default)]
-    pub device_id: String,
-    #[serde(rename = "FIX", default)]
-    pub fix: String,
-    #[serde(rename = "FW", default)]
-    pub firmware: String,
-    #[serde(rename = "GPS_DATE", default)]
-    pub gps_date: String,
-    #[serde(rename = "GPS_TIME", default)]
-    pub gps_time: String,
-    #[serde(rename = "HEADER", default)]
-    pub header: String,
-    #[serde(rename = "IDLE_TIME", default)]
-    pub idle_time: String,
-    #[serde(rename = "IN_STATE", default)]
-    pub in_state: String,
-    #[serde(rename = "LAC", default)]
-    pub lac: String,
-    #[serde(rename = "LAT", default)]
-    pub latitude: String,
-    #[serde(rename = "LON", default)]
-    pub longitude: String,
-    #[serde(rename = "MCC", default)]
-    pub mcc: String,
-    #[serde(rename = "MNC", default)]
-    pub mnc: String,
-    #[serde(rename = "MODEL", default)]
-    pub model: String,
-    #[serde(rename = "MODE_MAP", default)]
-    pub mode_map: String,
-    #[serde(rename = "MSG_NUM", default)]
-    pub msg_num: String,
-    #[serde(rename = "MSG_TYPE", default)]
-    pub msg_type: String,
-    #[serde(rename = "NET_STATUS", default)]
-    pub net_status: String,
-    #[serde(rename = "ODOMETER_MTS", default)]
-    pub odometer_mts: String,
-    #[serde(rename = "OUT_STATE", default)]
-    pub out_state: String,
-    #[serde(rename = "REPORT_MAP", default)]
-    pub report_map: String,
-    #[serde(rename = "RX_LVL", default)]
-    pub rx_lvl: String,
-    #[serde(rename = "SAT", default)]
-    pub satellites: String,
-    #[serde(rename = "SPD", default)]
-    pub speed: String,
-    #[serde(rename = "SPEED_TIME", default)]
-    pub speed_time: String,
-    #[serde(rename = "STT_RPT_TYPE", default)]
-    pub stt_rpt_type: String,
-    #[serde(rename = "TOTAL_DISTANCE", default)]
-    pub total_distance: String,
-    #[serde(rename = "TRIP_DISTANCE", default)]
-    pub trip_distance: String,
-    #[serde(rename = "TRIP_HOURMETER", default)]
-    pub trip_hourmeter: String,
-    #[serde(rename = "VOLT_BACKUP", default)]
-    pub volt_backup: String,
-    #[serde(rename = "VOLT_MAIN", default)]
-    pub volt_main: String,
-}
-
 /// Metadatos del mensaje (información del servidor receptor)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceMetadata {
@@ -0,0 +1,44 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Inicio o cierre de sesión de un conductor
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverTransition {
+    DriverLogin,
+    DriverLogout,
+}
+
+impl DriverTransition {
+    /// Valor guardado en la columna `event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DriverTransition::DriverLogin => "login",
+            DriverTransition::DriverLogout => "logout",
+        }
+    }
+
+    /// Acción indicada por `DRIVER_STATUS`: `0`, `LOGOUT` u `OFF` cierran la sesión;
+    /// cualquier otro valor (o ninguno) es una identificación del conductor
+    pub fn from_status(status: &str) -> Self {
+        match status.trim().to_uppercase().as_str() {
+            "0" | "LOGOUT" | "OFF" => DriverTransition::DriverLogout,
+            _ => DriverTransition::DriverLogin,
+        }
+    }
+}
+
+/// Identificación de un conductor (iButton, RFID) o cierre de su sesión, guardado en
+/// `driver_events` y publicado en el tópico de notificaciones
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverEvent {
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub driver_id: String,
+    pub event: DriverTransition,
+    /// UUID del mensaje que produjo el evento
+    pub uuid: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub gps_datetime: Option<NaiveDateTime>,
+}
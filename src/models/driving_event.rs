@@ -0,0 +1,59 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Tipo de evento de conducción
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrivingEventKind {
+    HarshBraking,
+    HarshAcceleration,
+    Speeding,
+}
+
+/// Gravedad según cuánto se supera el umbral: hasta 25% `low`, hasta 50% `medium`,
+/// más `high`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn from_ratio(value: f64, threshold: f64) -> Self {
+        let ratio = if threshold > 0.0 {
+            value / threshold
+        } else {
+            f64::INFINITY
+        };
+        if ratio <= 1.25 {
+            Severity::Low
+        } else if ratio <= 1.5 {
+            Severity::Medium
+        } else {
+            Severity::High
+        }
+    }
+}
+
+/// Frenado o aceleración brusca, o exceso de velocidad, publicado en el tópico de
+/// notificaciones
+#[derive(Debug, Clone, Serialize)]
+pub struct DrivingEvent {
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub event: DrivingEventKind,
+    pub severity: Severity,
+    /// UUID del mensaje que produjo el evento (el inicio en los excesos de velocidad)
+    pub uuid: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub gps_datetime: Option<NaiveDateTime>,
+    /// Cambio de velocidad en km/h por segundo, o velocidad máxima del exceso en km/h
+    pub value: f64,
+    /// Umbral aplicado, en la misma unidad que `value`
+    pub threshold: f64,
+    /// Duración del exceso de velocidad (0 en frenadas y aceleraciones)
+    pub duration_secs: i64,
+}
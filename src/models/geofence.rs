@@ -0,0 +1,278 @@
+use chrono::NaiveDateTime;
+use geoutils::Location;
+use serde::{Deserialize, Serialize};
+
+/// Forma de una geocerca. Los polígonos se evalúan en el plano lat/lon, suficiente
+/// para zonas de algunos kilómetros lejos de los polos; los que cruzan el
+/// antimeridiano se evalúan con las longitudes llevadas a 0..360. El borde cuenta
+/// como dentro, igual que en los círculos.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GeofenceShape {
+    Circle {
+        latitude: f64,
+        longitude: f64,
+        radius_m: f64,
+    },
+    /// Vértices `[lat, lon]`; el cierre del polígono es implícito
+    Polygon { points: Vec<[f64; 2]> },
+}
+
+impl GeofenceShape {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        match self {
+            GeofenceShape::Circle {
+                latitude: center_lat,
+                longitude: center_lon,
+                radius_m,
+            } => {
+                let center = Location::new(*center_lat, *center_lon);
+                Location::new(latitude, longitude)
+                    .haversine_distance_to(&center)
+                    .meters()
+                    <= *radius_m
+            }
+            GeofenceShape::Polygon { points } => {
+                let Some(last) = points.last() else {
+                    return false;
+                };
+                // Una arista de más de 180° de longitud cruza el antimeridiano
+                let crosses_antimeridian = points
+                    .iter()
+                    .scan(last, |previous, point| {
+                        let crosses = (point[1] - previous[1]).abs() > 180.0;
+                        *previous = point;
+                        Some(crosses)
+                    })
+                    .any(|crosses| crosses);
+                let unwrap = |lon: f64| {
+                    if crosses_antimeridian && lon < 0.0 {
+                        lon + 360.0
+                    } else {
+                        lon
+                    }
+                };
+                let longitude = unwrap(longitude);
+
+                // Ray casting: un rayo hacia el este cruza un número impar de aristas
+                let mut inside = false;
+                let mut previous = [last[0], unwrap(last[1])];
+                for point in points {
+                    let [lat_a, lon_a] = previous;
+                    let [lat_b, lon_b] = [point[0], unwrap(point[1])];
+                    if on_segment(latitude, longitude, previous, [lat_b, lon_b]) {
+                        return true;
+                    }
+                    if (lat_a > latitude) != (lat_b > latitude)
+                        && longitude
+                            < (lon_b - lon_a) * (latitude - lat_a) / (lat_b - lat_a) + lon_a
+                    {
+                        inside = !inside;
+                    }
+                    previous = [lat_b, lon_b];
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Si el punto está sobre la arista `a`-`b` (con tolerancia de ~1 cm)
+fn on_segment(latitude: f64, longitude: f64, a: [f64; 2], b: [f64; 2]) -> bool {
+    const EPSILON: f64 = 1e-7;
+    let cross = (b[0] - a[0]) * (longitude - a[1]) - (b[1] - a[1]) * (latitude - a[0]);
+    let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+    cross.abs() <= EPSILON * length.max(EPSILON)
+        && latitude >= a[0].min(b[0]) - EPSILON
+        && latitude <= a[0].max(b[0]) + EPSILON
+        && longitude >= a[1].min(b[1]) - EPSILON
+        && longitude <= a[1].max(b[1]) + EPSILON
+}
+
+/// Geocerca asignada a un dispositivo, a todos los dispositivos de un tenant o, sin
+/// ninguno de los dos, a todos los dispositivos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Geofence {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub shape: GeofenceShape,
+}
+
+impl Geofence {
+    pub fn applies_to(&self, device_id: &str, tenant: Option<&str>) -> bool {
+        match (&self.device_id, &self.tenant) {
+            (Some(device), _) => device == device_id,
+            (None, Some(geofence_tenant)) => tenant == Some(geofence_tenant.as_str()),
+            (None, None) => true,
+        }
+    }
+}
+
+/// Tipo de transición respecto a la geocerca
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeofenceTransition {
+    Enter,
+    Exit,
+}
+
+impl GeofenceTransition {
+    /// Valor guardado en la columna `event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeofenceTransition::Enter => "enter",
+            GeofenceTransition::Exit => "exit",
+        }
+    }
+}
+
+/// Entrada o salida de un dispositivo, guardada en `geofence_events` y publicada en
+/// el tópico de notificaciones
+#[derive(Debug, Clone, Serialize)]
+pub struct GeofenceEvent {
+    pub geofence_id: String,
+    pub geofence_name: String,
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub event: GeofenceTransition,
+    /// UUID del mensaje que produjo la transición
+    pub uuid: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub gps_datetime: Option<NaiveDateTime>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[[f64; 2]]) -> GeofenceShape {
+        GeofenceShape::Polygon {
+            points: points.to_vec(),
+        }
+    }
+
+    /// Cuadrado de 1° con la esquina inferior izquierda en (lat, lon)
+    fn square(lat: f64, lon: f64) -> GeofenceShape {
+        polygon(&[
+            [lat, lon],
+            [lat, lon + 1.0],
+            [lat + 1.0, lon + 1.0],
+            [lat + 1.0, lon],
+        ])
+    }
+
+    #[test]
+    fn polygon_interior_and_exterior() {
+        let zone = square(19.0, -99.5);
+        assert!(zone.contains(19.5, -99.0));
+        assert!(!zone.contains(20.5, -99.0));
+        assert!(!zone.contains(19.5, -98.0));
+        assert!(!zone.contains(19.5, -100.0));
+    }
+
+    #[test]
+    fn polygon_boundary_counts_as_inside() {
+        let zone = square(19.0, -99.5);
+        // Las cuatro aristas y los vértices, también los del lado este y norte
+        for (lat, lon) in [
+            (19.0, -99.0),
+            (20.0, -99.0),
+            (19.5, -99.5),
+            (19.5, -98.5),
+            (19.0, -99.5),
+            (20.0, -98.5),
+        ] {
+            assert!(zone.contains(lat, lon), "({}, {})", lat, lon);
+        }
+        // Justo fuera del borde
+        assert!(!zone.contains(20.000_01, -99.0));
+        assert!(!zone.contains(19.5, -98.499_99));
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // Forma de U abierta al norte
+        let zone = polygon(&[
+            [0.0, 0.0],
+            [0.0, 3.0],
+            [3.0, 3.0],
+            [3.0, 2.0],
+            [1.0, 2.0],
+            [1.0, 1.0],
+            [3.0, 1.0],
+            [3.0, 0.0],
+        ]);
+        assert!(zone.contains(2.0, 0.5));
+        assert!(zone.contains(2.0, 2.5));
+        assert!(!zone.contains(2.0, 1.5));
+        assert!(zone.contains(0.5, 1.5));
+    }
+
+    #[test]
+    fn polygon_across_the_antimeridian() {
+        // De 179.5°E a 179.5°W, alrededor de Fiyi
+        let zone = polygon(&[
+            [-17.0, 179.5],
+            [-17.0, -179.5],
+            [-16.0, -179.5],
+            [-16.0, 179.5],
+        ]);
+        assert!(zone.contains(-16.5, 179.9));
+        assert!(zone.contains(-16.5, -179.9));
+        assert!(zone.contains(-16.5, 180.0));
+        assert!(zone.contains(-16.5, -180.0));
+        assert!(!zone.contains(-16.5, 0.0));
+        assert!(!zone.contains(-16.5, 179.0));
+        assert!(!zone.contains(-16.5, -179.0));
+    }
+
+    #[test]
+    fn degenerate_polygons_contain_nothing() {
+        assert!(!polygon(&[]).contains(0.0, 0.0));
+        assert!(!polygon(&[[1.0, 1.0]]).contains(0.0, 0.0));
+    }
+
+    #[test]
+    fn circle_radius_is_measured_in_meters() {
+        let zone = GeofenceShape::Circle {
+            latitude: 19.4326,
+            longitude: -99.1332,
+            radius_m: 500.0,
+        };
+        assert!(zone.contains(19.4326, -99.1332));
+        // ~0.004° de latitud son ~445 m
+        assert!(zone.contains(19.4366, -99.1332));
+        assert!(!zone.contains(19.4426, -99.1332));
+
+        // La distancia no se corta en el antimeridiano
+        let zone = GeofenceShape::Circle {
+            latitude: 0.0,
+            longitude: 180.0,
+            radius_m: 20_000.0,
+        };
+        assert!(zone.contains(0.0, -179.9));
+        assert!(zone.contains(0.0, 179.9));
+    }
+
+    #[test]
+    fn geofence_assignment() {
+        let geofence = |device_id: Option<&str>, tenant: Option<&str>| Geofence {
+            id: "g1".to_string(),
+            name: "Base".to_string(),
+            device_id: device_id.map(str::to_string),
+            tenant: tenant.map(str::to_string),
+            shape: square(0.0, 0.0),
+        };
+        assert!(geofence(None, None).applies_to("A", None));
+        assert!(geofence(Some("A"), Some("acme")).applies_to("A", None));
+        assert!(!geofence(Some("A"), None).applies_to("B", Some("acme")));
+        assert!(geofence(None, Some("acme")).applies_to("B", Some("acme")));
+        assert!(!geofence(None, Some("acme")).applies_to("B", Some("globex")));
+        assert!(!geofence(None, Some("acme")).applies_to("B", None));
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, NaiveDateTime};
+
+/// Formatos con fecha y hora separadas o en ISO 8601 sin desfase, en el orden en que
+/// se prueban
+const DATETIME_FORMATS: &[&str] = &[
+    // Formato de los decodificadores
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S",
+    // Suntech: fecha y hora como campos separados
+    "%Y%m%d %H:%M:%S",
+    "%Y%m%d;%H:%M:%S",
+    // DDMMYY HHMMSS (NMEA)
+    "%d%m%y %H%M%S",
+];
+
+/// `GPS_DATETIME` normalizado
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpsDateTime {
+    pub value: NaiveDateTime,
+    /// El texto traía su zona (desfase explícito, `Z` o epoch) y `value` ya está en
+    /// UTC; si no, es la hora que reportó el equipo
+    pub utc: bool,
+}
+
+/// Interpreta los formatos de fecha que emiten los equipos Suntech y Queclink:
+/// ISO 8601 (con o sin desfase), `YYYY-MM-DD HH:MM:SS`, `YYYYMMDD HH:MM:SS`,
+/// `YYYYMMDDHHMMSS` (Queclink), `DDMMYY HHMMSS`, `DDMMYYHHMMSS` y epoch en segundos
+/// o milisegundos
+pub fn parse_gps_datetime(value: &str) -> Option<GpsDateTime> {
+    let value = value.trim();
+    let local = |value| Some(GpsDateTime { value, utc: false });
+    let utc = |value| Some(GpsDateTime { value, utc: true });
+
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return match value.len() {
+            10 => utc(DateTime::from_timestamp(value.parse().ok()?, 0)?.naive_utc()),
+            13 => utc(DateTime::from_timestamp_millis(value.parse().ok()?)?.naive_utc()),
+            12 => local(NaiveDateTime::parse_from_str(value, "%d%m%y%H%M%S").ok()?),
+            14 => local(NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S").ok()?),
+            _ => None,
+        };
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return utc(datetime.naive_utc());
+    }
+    // ISO 8601 con desfase sin ':' (`+0600`)
+    if let Ok(datetime) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return utc(datetime.naive_utc());
+    }
+
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .and_then(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32, second: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 5)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap()
+    }
+
+    #[test]
+    fn device_formats_keep_the_reported_time() {
+        for value in [
+            "2024-03-05 14:07:09",
+            "2024-03-05T14:07:09",
+            "2024/03/05 14:07:09",
+            "20240305 14:07:09",
+            "20240305;14:07:09",
+            "050324 140709",
+            "050324140709",
+            "20240305140709",
+            "  2024-03-05 14:07:09 ",
+        ] {
+            assert_eq!(
+                parse_gps_datetime(value),
+                Some(GpsDateTime {
+                    value: at(14, 7, 9),
+                    utc: false
+                }),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn zoned_formats_are_converted_to_utc() {
+        for value in [
+            "2024-03-05T14:07:09Z",
+            "2024-03-05T08:07:09-06:00",
+            "2024-03-05T20:07:09+0600",
+            "1709647629",
+            "1709647629000",
+        ] {
+            assert_eq!(
+                parse_gps_datetime(value),
+                Some(GpsDateTime {
+                    value: at(14, 7, 9),
+                    utc: true
+                }),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn fractional_seconds_are_kept() {
+        let parsed = parse_gps_datetime("2024-03-05T14:07:09.250").unwrap();
+        assert_eq!(
+            parsed.value,
+            at(14, 7, 9) + chrono::Duration::milliseconds(250)
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for value in [
+            "",
+            "   ",
+            "2024-02-30 10:00:00",
+            "2024-03-05 25:00:00",
+            // Largo numérico sin formato conocido
+            "12345",
+            "123456789012345",
+            // DDMMYY con mes 13
+            "051324140709",
+            "ayer",
+        ] {
+            assert_eq!(parse_gps_datetime(value), None, "{}", value);
+        }
+    }
+}
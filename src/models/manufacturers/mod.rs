@@ -0,0 +1,246 @@
+//! Registro de fabricantes soportados.
+//!
+//! Cada fabricante vive en su propio archivo y declara un `ManufacturerSpec`: su
+//! nombre, la tabla de histórico, cómo se reconocen sus campos en el mensaje
+//! protobuf y su modelo de datos decodificados. Para agregar uno basta con crear el
+//! archivo y sumarlo a `REGISTRY` (además de su variante en `siscom.proto`).
+
+mod queclink;
+mod suntech;
+
+pub use queclink::{server_ack, ServerAck};
+
+use serde::de::value::MapDeserializer;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::siscom::kafka_message::Decoded;
+
+/// Fabricantes registrados. El primero se usa para los mensajes sin datos
+/// decodificados.
+static REGISTRY: &[&ManufacturerSpec] = &[&suntech::SPEC, &queclink::SPEC];
+
+/// Definición de un fabricante
+pub struct ManufacturerSpec {
+    /// Nombre en minúsculas: reglas de mapeo, unidades de velocidad, archivo Parquet
+    pub name: &'static str,
+    /// Valor de `{manufacturer}` en las plantillas de tabla de histórico
+    pub table: &'static str,
+    /// Clave del modelo decodificado en el JSON del mensaje (`SuntechRaw`, ...)
+    pub decoded_key: &'static str,
+    /// Campos crudos del mensaje protobuf, si el mensaje es de este fabricante
+    pub fields: fn(&Decoded) -> Option<&HashMap<String, String>>,
+    /// Construye el modelo decodificado a partir de los campos crudos
+    pub decode: fn(&HashMap<String, String>) -> serde_json::Value,
+    /// Decodifica la trama cruda del equipo cuando no pasó por el decodificador. None
+    /// si la trama no es de este fabricante.
+    pub parse_raw: Option<fn(&str) -> Option<RawParse>>,
+}
+
+/// Resultado de decodificar una trama reconocida por un fabricante
+pub type RawParse = Result<RawFrame, RawFrameError>;
+
+/// Trama de un fabricante que no pudo decodificarse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawFrameError {
+    /// Tipo de reporte que el decodificador no soporta
+    Unsupported(String),
+    /// Trama incompleta o corrupta
+    Invalid(&'static str),
+}
+
+impl fmt::Display for RawFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawFrameError::Unsupported(report) => {
+                write!(f, "tipo de reporte {} no soportado", report)
+            }
+            RawFrameError::Invalid(reason) => write!(f, "trama inválida: {}", reason),
+        }
+    }
+}
+
+/// Trama cruda decodificada por el consumer: el mapa `data` normalizado y los campos
+/// propios del fabricante
+#[derive(Debug)]
+pub struct RawFrame {
+    pub data: HashMap<String, String>,
+    pub decoded: Decoded,
+}
+
+/// Construye un modelo decodificado tipado desde los campos crudos. Los campos que el
+/// modelo no conoce se descartan y los que faltan toman su valor por defecto.
+pub fn decode_model<T>(fields: &HashMap<String, String>) -> serde_json::Value
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    let entries = fields
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    let model: T = T::deserialize(MapDeserializer::<_, serde::de::value::Error>::new(entries))
+        .unwrap_or_default();
+    serde_json::to_value(model).unwrap_or_default()
+}
+
+/// Fabricante de un dispositivo, referencia a su definición en el registro
+#[derive(Clone, Copy)]
+pub struct Manufacturer(&'static ManufacturerSpec);
+
+impl Manufacturer {
+    /// Todos los fabricantes registrados, en orden de registro
+    pub fn all() -> impl Iterator<Item = Manufacturer> {
+        REGISTRY.iter().map(|spec| Manufacturer(spec))
+    }
+
+    /// Busca un fabricante por nombre, sin distinguir mayúsculas
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().find(|manufacturer| manufacturer.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Fabricante y campos crudos de los datos decodificados del mensaje protobuf
+    pub fn from_decoded(decoded: &Decoded) -> Option<(Self, &HashMap<String, String>)> {
+        Self::all().find_map(|manufacturer| {
+            (manufacturer.0.fields)(decoded).map(|fields| (manufacturer, fields))
+        })
+    }
+
+    /// Decodifica una trama cruda con el primer fabricante que la reconozca
+    pub fn parse_raw(raw: &str) -> Option<(Self, RawParse)> {
+        Self::all().find_map(|manufacturer| {
+            manufacturer
+                .0
+                .parse_raw
+                .and_then(|parse| parse(raw))
+                .map(|frame| (manufacturer, frame))
+        })
+    }
+
+    /// Nombre en minúsculas
+    pub fn as_str(&self) -> &'static str {
+        self.0.name
+    }
+
+    /// Valor de `{manufacturer}` en los nombres de tabla de histórico
+    pub fn table(&self) -> &'static str {
+        self.0.table
+    }
+
+    /// Datos decodificados del fabricante a partir de sus campos crudos
+    pub fn decode(&self, fields: &HashMap<String, String>) -> DecodedData {
+        DecodedData {
+            manufacturer: *self,
+            fields: (self.0.decode)(fields),
+        }
+    }
+}
+
+impl Default for Manufacturer {
+    fn default() -> Self {
+        Manufacturer(REGISTRY[0])
+    }
+}
+
+impl PartialEq for Manufacturer {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.name == other.0.name
+    }
+}
+
+impl Eq for Manufacturer {}
+
+impl std::hash::Hash for Manufacturer {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state);
+    }
+}
+
+impl fmt::Debug for Manufacturer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.name)
+    }
+}
+
+impl fmt::Display for Manufacturer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.name)
+    }
+}
+
+impl Serialize for Manufacturer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for Manufacturer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Manufacturer::from_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("fabricante '{}' no soportado", name)))
+    }
+}
+
+/// Datos decodificados propios del fabricante. En JSON se representan como
+/// `{"SuntechRaw": {...}}`, con la clave del modelo del fabricante.
+#[derive(Debug, Clone)]
+pub struct DecodedData {
+    pub manufacturer: Manufacturer,
+    pub fields: serde_json::Value,
+}
+
+impl DecodedData {
+    /// Datos decodificados del mensaje protobuf; sin datos (o de un fabricante no
+    /// registrado) se usa el modelo vacío del fabricante por defecto
+    pub fn from_proto(decoded: Option<&Decoded>) -> Self {
+        match decoded.and_then(Manufacturer::from_decoded) {
+            Some((manufacturer, fields)) => manufacturer.decode(fields),
+            None => Manufacturer::default().decode(&HashMap::new()),
+        }
+    }
+
+    /// Campos no vacíos, para la columna `decoded_extra` (None si no hay ninguno)
+    pub fn extra(&self) -> Option<serde_json::Value> {
+        let serde_json::Value::Object(fields) = &self.fields else {
+            return None;
+        };
+        let extra: serde_json::Map<_, _> = fields
+            .iter()
+            .filter(|(_, value)| !matches!(value, serde_json::Value::Null))
+            .filter(|(_, value)| value.as_str() != Some(""))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        (!extra.is_empty()).then_some(serde_json::Value::Object(extra))
+    }
+}
+
+impl Serialize for DecodedData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.manufacturer.0.decoded_key, &self.fields)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for DecodedData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = HashMap::<String, serde_json::Value>::deserialize(deserializer)?;
+        let mut entries = entries.into_iter();
+        let (Some((key, fields)), None) = (entries.next(), entries.next()) else {
+            return Err(D::Error::custom(
+                "los datos decodificados deben tener una sola clave",
+            ));
+        };
+        let manufacturer = Manufacturer::all()
+            .find(|manufacturer| manufacturer.0.decoded_key == key)
+            .ok_or_else(|| {
+                D::Error::custom(format!("modelo decodificado '{}' desconocido", key))
+            })?;
+        Ok(DecodedData {
+            manufacturer,
+            fields,
+        })
+    }
+}
@@ -0,0 +1,388 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{decode_model, ManufacturerSpec, RawFrame, RawFrameError, RawParse};
+use crate::config::siscom::{kafka_message::Decoded, QueclinkDecoded};
+
+/// Registro de Queclink (`communications_queclink`)
+pub(super) static SPEC: ManufacturerSpec = ManufacturerSpec {
+    name: "queclink",
+    table: "queclink",
+    decoded_key: "QueclinkRaw",
+    fields: |decoded| match decoded {
+        Decoded::Queclink(queclink) => Some(&queclink.fields),
+        _ => None,
+    },
+    decode: decode_model::<QueclinkRaw>,
+    parse_raw: Some(parse_frame),
+};
+
+/// Decodifica una trama ASCII @Track (`+RESP:GTFRI,...$` o `+BUFF:GTFRI,...$`) de
+/// los reportes GTFRI, GTERI, GTIGN, GTIGF y GTSOS.
+///
+/// El bloque de posición se ubica por su fecha UTC (`YYYYMMDDHHMMSS` precedida de
+/// longitud y latitud), así que la decodificación no depende de los campos propios de
+/// cada modelo antes de él. En reportes con varias posiciones se toma la primera.
+///
+/// Las tramas ASCII @Track no traen checksum (solo el protocolo HEX lleva CRC): la
+/// integridad se valida con el terminador `$`, la fecha de envío y el contador de 4
+/// dígitos hexadecimales del final, que una trama cortada o mezclada no conserva.
+fn parse_frame(raw: &str) -> Option<RawParse> {
+    let raw = raw.trim();
+    let (prefix, body) = raw.split_once(':')?;
+    let delivery_type = match prefix {
+        "+RESP" => "REALTIME",
+        "+BUFF" => "BUFFERED",
+        _ => return None,
+    };
+    let report = body.split(',').next().unwrap_or_default();
+    if !report.starts_with("GT") {
+        return None;
+    }
+
+    let (msg_class, engine_status, alert) = match report {
+        "GTFRI" | "GTERI" => ("STATUS", None, None),
+        "GTIGN" => ("EVENT", Some("ON"), None),
+        "GTIGF" => ("EVENT", Some("OFF"), None),
+        "GTSOS" => ("ALERT", None, Some("SOS")),
+        _ => return Some(Err(RawFrameError::Unsupported(report.to_string()))),
+    };
+    Some(parse_report(prefix, body).map(|(fields, mut data)| {
+        data.insert("MSG_CLASS".to_string(), msg_class.to_string());
+        data.insert("DELIVERY_TYPE".to_string(), delivery_type.to_string());
+        if let Some(engine_status) = engine_status {
+            data.insert("ENGINE_STATUS".to_string(), engine_status.to_string());
+        }
+        if let Some(alert) = alert {
+            data.insert("ALERT".to_string(), alert.to_string());
+        }
+        RawFrame {
+            data,
+            decoded: Decoded::Queclink(QueclinkDecoded { fields }),
+        }
+    }))
+}
+
+/// Confirmación que el servidor debe devolver al equipo por una trama recibida
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerAck {
+    pub device_id: String,
+    /// Tipo de reporte, p. ej. `GTFRI` o `GTHBD`
+    pub report: String,
+    /// Contador de la trama (4 dígitos hexadecimales)
+    pub msg_num: String,
+    /// Trama a enviar al equipo
+    pub frame: String,
+}
+
+/// Confirmación de una trama @Track: `+SACK:GTHBD,<versión>,<contador>$` para los
+/// heartbeat (`+ACK:GTHBD`) y `+SACK:<contador>$` para los reportes `+RESP`/`+BUFF`.
+/// None si la trama no es de Queclink o no tiene contador.
+pub fn server_ack(raw: &str) -> Option<ServerAck> {
+    let (prefix, body) = raw.trim().split_once(':')?;
+    let values: Vec<&str> = body.strip_suffix('$')?.split(',').map(str::trim).collect();
+    let [report, protocol_version, imei, .., count] = values.as_slice() else {
+        return None;
+    };
+    if count.len() != 4 || !count.bytes().all(|b| b.is_ascii_hexdigit()) || imei.is_empty() {
+        return None;
+    }
+    let frame = match (prefix, *report) {
+        ("+ACK", "GTHBD") => format!("+SACK:GTHBD,{},{}$", protocol_version, count),
+        ("+RESP" | "+BUFF", report) if report.starts_with("GT") => format!("+SACK:{}$", count),
+        _ => return None,
+    };
+    Some(ServerAck {
+        device_id: imei.to_string(),
+        report: report.to_string(),
+        msg_num: count.to_string(),
+        frame,
+    })
+}
+
+/// Campos crudos del fabricante y mapa `data` normalizado
+type ParsedReport = (HashMap<String, String>, HashMap<String, String>);
+
+/// Valida la trama (terminador `$`, fecha de envío y contador de 4 dígitos
+/// hexadecimales al final) y extrae los campos crudos y el mapa `data`
+fn parse_report(prefix: &str, body: &str) -> Result<ParsedReport, RawFrameError> {
+    let body = body
+        .strip_suffix('$')
+        .ok_or(RawFrameError::Invalid("sin terminador $"))?;
+    let values: Vec<&str> = body.split(',').map(str::trim).collect();
+    let [report, protocol_version, imei, ..] = values.as_slice() else {
+        return Err(RawFrameError::Invalid("faltan campos"));
+    };
+    let [.., send_time, count] = values.as_slice() else {
+        return Err(RawFrameError::Invalid("faltan campos"));
+    };
+    if count.len() != 4 || !count.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(RawFrameError::Invalid("contador inválido"));
+    }
+    if NaiveDateTime::parse_from_str(send_time, "%Y%m%d%H%M%S").is_err() {
+        return Err(RawFrameError::Invalid("fecha de envío inválida"));
+    }
+    if imei.is_empty() {
+        return Err(RawFrameError::Invalid("sin IMEI"));
+    }
+
+    // Bloque de posición: precisión, velocidad, rumbo, altitud, longitud, latitud,
+    // fecha UTC, MCC, MNC, LAC, celda
+    let gps_at = (6..values.len().saturating_sub(6))
+        .find(|&i| {
+            values[i].len() == 14
+                && values[i].bytes().all(|b| b.is_ascii_digit())
+                && values[i - 1].parse::<f64>().is_ok()
+                && values[i - 2].parse::<f64>().is_ok()
+        })
+        .ok_or(RawFrameError::Invalid("sin bloque de posición"))?;
+    let at = |offset: isize| {
+        values
+            .get(gps_at.checked_add_signed(offset).unwrap_or(usize::MAX))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let mut fields = HashMap::new();
+    let mut field = |key: &str, value: &str| {
+        fields.insert(key.to_string(), value.to_string());
+    };
+    field("HEADER", &format!("{}:{}", prefix, report));
+    field("PROTOCOL_VERSION", protocol_version);
+    field("DEVICE_ID", imei);
+    field("FIX", at(-6));
+    field("SPD", at(-5));
+    field("CRS", at(-4));
+    field("ALTITUDE", at(-3));
+    field("LON", at(-2));
+    field("LAT", at(-1));
+    field("GPS_DATE_TIME", at(0));
+    field("MCC", at(1));
+    field("MNC", at(2));
+    field("LAC", at(3));
+    field("CELL_ID", at(4));
+    field("SEND_DATE_TIME", send_time);
+    field("MSG_NUM", count);
+
+    let mut data = HashMap::new();
+    let mut put = |key: &str, value: &str| {
+        if !value.is_empty() {
+            data.insert(key.to_string(), value.to_string());
+        }
+    };
+    put("DEVICE_ID", imei);
+    put("LATITUD", at(-1));
+    put("LONGITUD", at(-2));
+    put("SPEED", at(-5));
+    put("COURSE", at(-4));
+    put("ALTITUDE", at(-3));
+    put("MCC", at(1));
+    put("MNC", at(2));
+    put("LAC", at(3));
+    put("CELL_ID", at(4));
+    // Precisión 0 = sin fix
+    put(
+        "FIX_",
+        if at(-6).parse::<u32>().unwrap_or(0) > 0 {
+            "1"
+        } else {
+            "0"
+        },
+    );
+    if let Ok(counter) = u32::from_str_radix(count, 16) {
+        put("MSG_COUNTER", &counter.to_string());
+    }
+    // Fecha y hora en UTC
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(at(0), "%Y%m%d%H%M%S") {
+        put(
+            "GPS_DATETIME",
+            &datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
+        put("GPS_EPOCH", &datetime.and_utc().timestamp().to_string());
+    }
+
+    Ok((fields, data))
+}
+
+/// Datos raw de dispositivos Queclink
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct QueclinkRaw {
+    #[serde(rename = "ALTITUDE", default)]
+    pub altitude: String,
+    #[serde(rename = "CELL_ID", default)]
+    pub cell_id: String,
+    #[serde(rename = "CRS", default)]
+    pub course: String,
+    #[serde(rename = "DEVICE_ID", default)]
+    pub device_id: String,
+    #[serde(rename = "DRIVER_ID", default)]
+    pub driver_id: String,
+    #[serde(rename = "DRIVER_STATUS", default)]
+    pub driver_status: String,
+    #[serde(rename = "FIX", default)]
+    pub fix: String,
+    #[serde(rename = "GPS_DATE_TIME", default)]
+    pub gps_date_time: String,
+    #[serde(rename = "HEADER", default)]
+    pub header: String,
+    #[serde(rename = "LAC", default)]
+    pub lac: String,
+    #[serde(rename = "LAT", default)]
+    pub latitude: String,
+    #[serde(rename = "LON", default)]
+    pub longitude: String,
+    #[serde(rename = "MCC", default)]
+    pub mcc: String,
+    #[serde(rename = "MNC", default)]
+    pub mnc: String,
+    #[serde(rename = "MSG_NUM", default)]
+    pub msg_num: String,
+    #[serde(rename = "PROTOCOL_VERSION", default)]
+    pub protocol_version: String,
+    #[serde(rename = "RESERVED", default)]
+    pub reserved: String,
+    #[serde(rename = "SEND_DATE_TIME", default)]
+    pub send_date_time: String,
+    #[serde(rename = "SPD", default)]
+    pub speed: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reporte @Track de un GV300 con el tipo de reporte y el prefijo indicados
+    fn report(prefix: &str, report: &str) -> String {
+        format!(
+            "{}:{},060100,862193020315544,GV300,,10,1,1,18.8,270,2240.0,-99.025835,19.618257,20240311143500,0334,0020,1A2B,3C4D,00,88526.9,,,,100,210100,,,,20240311143502,0A3F$",
+            prefix, report
+        )
+    }
+
+    fn frame(raw: &str) -> RawFrame {
+        parse_frame(raw)
+            .expect("trama de Queclink")
+            .expect("trama válida")
+    }
+
+    fn error(raw: &str) -> RawFrameError {
+        parse_frame(raw).expect("trama de Queclink").unwrap_err()
+    }
+
+    #[test]
+    fn gtfri_position_block() {
+        let frame = frame(&report("+RESP", "GTFRI"));
+        let data = &frame.data;
+        assert_eq!(data["DEVICE_ID"], "862193020315544");
+        assert_eq!(data["MSG_CLASS"], "STATUS");
+        assert_eq!(data["DELIVERY_TYPE"], "REALTIME");
+        assert_eq!(data["LATITUD"], "19.618257");
+        assert_eq!(data["LONGITUD"], "-99.025835");
+        assert_eq!(data["SPEED"], "18.8");
+        assert_eq!(data["COURSE"], "270");
+        assert_eq!(data["ALTITUDE"], "2240.0");
+        assert_eq!(data["FIX_"], "1");
+        assert_eq!(data["GPS_DATETIME"], "2024-03-11 14:35:00");
+        assert_eq!(data["MSG_COUNTER"], "2623");
+        assert_eq!(data["CELL_ID"], "3C4D");
+        assert!(!data.contains_key("ENGINE_STATUS") && !data.contains_key("ALERT"));
+
+        let Decoded::Queclink(decoded) = &frame.decoded else {
+            panic!("decodificado de otro fabricante");
+        };
+        assert_eq!(decoded.fields["HEADER"], "+RESP:GTFRI");
+        assert_eq!(decoded.fields["SEND_DATE_TIME"], "20240311143502");
+        assert_eq!(decoded.fields["MSG_NUM"], "0A3F");
+    }
+
+    #[test]
+    fn event_and_alert_reports() {
+        assert_eq!(frame(&report("+RESP", "GTERI")).data["MSG_CLASS"], "STATUS");
+
+        let ignition_on = frame(&report("+RESP", "GTIGN"));
+        assert_eq!(ignition_on.data["MSG_CLASS"], "EVENT");
+        assert_eq!(ignition_on.data["ENGINE_STATUS"], "ON");
+        let ignition_off = frame(&report("+RESP", "GTIGF"));
+        assert_eq!(ignition_off.data["ENGINE_STATUS"], "OFF");
+
+        let sos = frame(&report("+RESP", "GTSOS"));
+        assert_eq!(sos.data["MSG_CLASS"], "ALERT");
+        assert_eq!(sos.data["ALERT"], "SOS");
+    }
+
+    #[test]
+    fn buffered_reports_and_no_fix() {
+        let buffered = frame(&report("+BUFF", "GTFRI"));
+        assert_eq!(buffered.data["DELIVERY_TYPE"], "BUFFERED");
+        assert_eq!(buffered.data["LATITUD"], "19.618257");
+
+        let no_fix = frame(&report("+RESP", "GTFRI").replace(",10,1,1,18.8,", ",10,1,0,18.8,"));
+        assert_eq!(no_fix.data["FIX_"], "0");
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let raw = report("+RESP", "GTFRI");
+        assert_eq!(
+            error(raw.strip_suffix('$').unwrap()),
+            RawFrameError::Invalid("sin terminador $")
+        );
+        assert_eq!(
+            error(&raw.replace(",0A3F$", ",0A3G$")),
+            RawFrameError::Invalid("contador inválido")
+        );
+        assert_eq!(
+            error(&raw.replace(",0A3F$", ",A3F$")),
+            RawFrameError::Invalid("contador inválido")
+        );
+        assert_eq!(
+            error(&raw.replace(",20240311143502,", ",20241311143502,")),
+            RawFrameError::Invalid("fecha de envío inválida")
+        );
+        assert_eq!(
+            error(&raw.replace(",862193020315544,", ",,")),
+            RawFrameError::Invalid("sin IMEI")
+        );
+        assert_eq!(
+            error("+RESP:GTFRI,060100,862193020315544,20240311143502,0A3F$"),
+            RawFrameError::Invalid("sin bloque de posición")
+        );
+    }
+
+    #[test]
+    fn unsupported_reports_and_other_frames() {
+        assert_eq!(
+            error(&report("+RESP", "GTGEO")),
+            RawFrameError::Unsupported("GTGEO".to_string())
+        );
+        // Ni @Track ni Queclink: lo decide otro fabricante
+        assert!(
+            parse_frame("+ACK:GTHBD,060100,862193020315544,GV300,20240311143502,11F0$").is_none()
+        );
+        assert!(parse_frame("+RESP:XYZ,1$").is_none());
+        assert!(parse_frame("ST300STT;205700001;04").is_none());
+    }
+
+    #[test]
+    fn server_ack_for_reports_and_heartbeats() {
+        let ack = server_ack(&report("+BUFF", "GTFRI")).unwrap();
+        assert_eq!(ack.device_id, "862193020315544");
+        assert_eq!(ack.report, "GTFRI");
+        assert_eq!(ack.msg_num, "0A3F");
+        assert_eq!(ack.frame, "+SACK:0A3F$");
+
+        let heartbeat =
+            server_ack("+ACK:GTHBD,060100,862193020315544,GV300,20240311143502,11F0$").unwrap();
+        assert_eq!(heartbeat.frame, "+SACK:GTHBD,060100,11F0$");
+
+        // Sin contador, sin terminador o de otro tipo no se confirma
+        assert_eq!(server_ack("+RESP:GTFRI,060100,862193020315544$"), None);
+        assert_eq!(
+            server_ack(report("+RESP", "GTFRI").strip_suffix('$').unwrap()),
+            None
+        );
+        assert_eq!(server_ack("+ACK:GTFRI,060100,862193020315544,0A3F$"), None);
+        assert_eq!(server_ack("ST300STT;205700001;04"), None);
+    }
+}
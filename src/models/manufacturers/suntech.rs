@@ -0,0 +1,330 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{decode_model, ManufacturerSpec, RawFrame, RawFrameError, RawParse};
+use crate::config::siscom::{kafka_message::Decoded, SuntechDecoded};
+
+/// Campos comunes a STT, ALT y RES (ST300): cabecera, equipo, posición y estado
+const COMMON_FIELDS: &[&str] = &[
+    "HEADER",
+    "DEVICE_ID",
+    "MODEL",
+    "FW",
+    "GPS_DATE",
+    "GPS_TIME",
+    "CELL_ID",
+    "LAT",
+    "LON",
+    "SPD",
+    "CRS",
+    "SAT",
+    "FIX",
+    "ODOMETER_MTS",
+    "VOLT_MAIN",
+    "IN_STATE",
+];
+
+/// Campos posteriores a los comunes en un reporte de estado (STT) o respuesta (RES)
+const STATUS_FIELDS: &[&str] = &[
+    "STT_RPT_TYPE",
+    "MSG_NUM",
+    "TRIP_HOURMETER",
+    "VOLT_BACKUP",
+    "MSG_TYPE",
+];
+
+/// Campos posteriores a los comunes en una alerta (ALT)
+const ALERT_FIELDS: &[&str] = &[
+    "ALERT_ID",
+    "ALERT_MOD",
+    "ALERT_DATA",
+    "TRIP_HOURMETER",
+    "VOLT_BACKUP",
+    "MSG_TYPE",
+];
+
+/// Registro de Suntech (`communications_suntech`)
+pub(super) static SPEC: ManufacturerSpec = ManufacturerSpec {
+    name: "suntech",
+    table: "suntech",
+    decoded_key: "SuntechRaw",
+    fields: |decoded| match decoded {
+        Decoded::Suntech(suntech) => Some(&suntech.fields),
+        _ => None,
+    },
+    decode: decode_model::<SuntechRaw>,
+    parse_raw: Some(parse_frame),
+};
+
+/// Decodifica una trama ASCII `STT`, `ALT` o `RES` con el formato del ST300
+/// (`ST300STT;205700000;04;1097B;20231201;14:30:22;...`)
+fn parse_frame(raw: &str) -> Option<RawParse> {
+    let values: Vec<&str> = raw.trim().split(';').map(str::trim).collect();
+    let header = values.first()?;
+    let (model_prefix, kind) = header.split_at_checked(header.len().checked_sub(3)?)?;
+    if !model_prefix.starts_with("ST") && !model_prefix.starts_with("SA") {
+        return None;
+    }
+    let (tail, msg_class) = match kind {
+        "STT" | "RES" => (STATUS_FIELDS, "STATUS"),
+        "ALT" => (ALERT_FIELDS, "ALERT"),
+        _ => return Some(Err(RawFrameError::Unsupported(kind.to_string()))),
+    };
+    if values.len() < COMMON_FIELDS.len() {
+        return Some(Err(RawFrameError::Invalid("faltan campos")));
+    }
+
+    let fields: HashMap<String, String> = COMMON_FIELDS
+        .iter()
+        .chain(tail)
+        .zip(&values)
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let field = |key: &str| fields.get(key).map(String::as_str).unwrap_or_default();
+    if field("DEVICE_ID").is_empty() {
+        return Some(Err(RawFrameError::Invalid("sin DEVICE_ID")));
+    }
+
+    let mut data = HashMap::new();
+    let mut put = |key: &str, value: &str| {
+        if !value.is_empty() {
+            data.insert(key.to_string(), value.to_string());
+        }
+    };
+    put("DEVICE_ID", field("DEVICE_ID"));
+    put("MODEL", field("MODEL"));
+    put("FIRMWARE", field("FW"));
+    put("MSG_CLASS", msg_class);
+    put("CELL_ID", field("CELL_ID"));
+    put("LATITUD", field("LAT"));
+    put("LONGITUD", field("LON"));
+    put("SPEED", field("SPD"));
+    put("COURSE", field("CRS"));
+    put("SATELLITES", field("SAT"));
+    put("FIX_", field("FIX"));
+    put("ODOMETER", field("ODOMETER_MTS"));
+    put("MAIN_BATTERY_VOLTAGE", field("VOLT_MAIN"));
+    put("BACKUP_BATTERY_VOLTAGE", field("VOLT_BACKUP"));
+    put("MSG_COUNTER", field("MSG_NUM"));
+    put("ALERT", field("ALERT_ID"));
+
+    // Fecha y hora en UTC
+    let datetime = format!("{} {}", field("GPS_DATE"), field("GPS_TIME"));
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(&datetime, "%Y%m%d %H:%M:%S") {
+        put(
+            "GPS_DATETIME",
+            &datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
+        put("GPS_EPOCH", &datetime.and_utc().timestamp().to_string());
+    }
+    // El primer bit de entradas es la ignición
+    match field("IN_STATE").chars().next() {
+        Some('1') => put("ENGINE_STATUS", "ON"),
+        Some('0') => put("ENGINE_STATUS", "OFF"),
+        _ => {}
+    }
+    match field("MSG_TYPE") {
+        "1" => put("DELIVERY_TYPE", "REALTIME"),
+        "0" => put("DELIVERY_TYPE", "BUFFERED"),
+        _ => {}
+    }
+    // El horómetro llega como HHHHH:MM:SS en algunos firmwares
+    if field("TRIP_HOURMETER").bytes().all(|b| b.is_ascii_digit()) {
+        put("TRIP_HOURMETER", field("TRIP_HOURMETER"));
+    }
+
+    Some(Ok(RawFrame {
+        data,
+        decoded: Decoded::Suntech(SuntechDecoded { fields }),
+    }))
+}
+
+/// Datos raw de dispositivos Suntech
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SuntechRaw {
+    #[serde(rename = "ASSIGN_MAP", default)]
+    pub assign_map: String,
+    #[serde(rename = "AXIST_Y", default)]
+    pub axis_y: String,
+    #[serde(rename = "AXIS_X", default)]
+    pub axis_x: String,
+    #[serde(rename = "AXIS_Z", default)]
+    pub axis_z: String,
+    #[serde(rename = "CELL_ID", default)]
+    pub cell_id: String,
+    #[serde(rename = "CRS", default)]
+    pub course: String,
+    #[serde(rename = "DEVICE_ID", default)]
+    pub device_id: String,
+    #[serde(rename = "DRIVER_ID", default)]
+    pub driver_id: String,
+    #[serde(rename = "DRIVER_STATUS", default)]
+    pub driver_status: String,
+    #[serde(rename = "FIX", default)]
+    pub fix: String,
+    #[serde(rename = "FW", default)]
+    pub firmware: String,
+    #[serde(rename = "GPS_DATE", default)]
+    pub gps_date: String,
+    #[serde(rename = "GPS_TIME", default)]
+    pub gps_time: String,
+    #[serde(rename = "HEADER", default)]
+    pub header: String,
+    #[serde(rename = "IDLE_TIME", default)]
+    pub idle_time: String,
+    #[serde(rename = "IN_STATE", default)]
+    pub in_state: String,
+    #[serde(rename = "LAC", default)]
+    pub lac: String,
+    #[serde(rename = "LAT", default)]
+    pub latitude: String,
+    #[serde(rename = "LON", default)]
+    pub longitude: String,
+    #[serde(rename = "MCC", default)]
+    pub mcc: String,
+    #[serde(rename = "MNC", default)]
+    pub mnc: String,
+    #[serde(rename = "MODEL", default)]
+    pub model: String,
+    #[serde(rename = "MODE_MAP", default)]
+    pub mode_map: String,
+    #[serde(rename = "MSG_NUM", default)]
+    pub msg_num: String,
+    #[serde(rename = "MSG_TYPE", default)]
+    pub msg_type: String,
+    #[serde(rename = "NET_STATUS", default)]
+    pub net_status: String,
+    #[serde(rename = "ODOMETER_MTS", default)]
+    pub odometer_mts: String,
+    #[serde(rename = "OUT_STATE", default)]
+    pub out_state: String,
+    #[serde(rename = "REPORT_MAP", default)]
+    pub report_map: String,
+    #[serde(rename = "RX_LVL", default)]
+    pub rx_lvl: String,
+    #[serde(rename = "SAT", default)]
+    pub satellites: String,
+    #[serde(rename = "SPD", default)]
+    pub speed: String,
+    #[serde(rename = "SPEED_TIME", default)]
+    pub speed_time: String,
+    #[serde(rename = "STT_RPT_TYPE", default)]
+    pub stt_rpt_type: String,
+    #[serde(rename = "TOTAL_DISTANCE", default)]
+    pub total_distance: String,
+    #[serde(rename = "TRIP_DISTANCE", default)]
+    pub trip_distance: String,
+    #[serde(rename = "TRIP_HOURMETER", default)]
+    pub trip_hourmeter: String,
+    #[serde(rename = "VOLT_BACKUP", default)]
+    pub volt_backup: String,
+    #[serde(rename = "VOLT_MAIN", default)]
+    pub volt_main: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STT: &str = "ST300STT;205700001;04;1097B;20240311;14:30:22;0c1e02;+19.432608;-99.133209;032.40;087.50;10;1;1523406;12.43;10000000;1;3187;3715;4.1;1";
+    const ALT: &str = "ST300ALT;205700001;04;1097B;20240311;14:31:05;0c1e02;+19.432650;-99.133100;000.00;087.50;9;1;1523500;12.40;00000000;3;0;;0012:05:33;4.0;0";
+
+    fn frame(raw: &str) -> RawFrame {
+        parse_frame(raw)
+            .expect("trama de Suntech")
+            .expect("trama válida")
+    }
+
+    fn decoded(frame: &RawFrame) -> &HashMap<String, String> {
+        match &frame.decoded {
+            Decoded::Suntech(suntech) => &suntech.fields,
+            _ => panic!("decodificado de otro fabricante"),
+        }
+    }
+
+    #[test]
+    fn status_report_layout() {
+        let frame = frame(STT);
+        let data = &frame.data;
+        assert_eq!(data["DEVICE_ID"], "205700001");
+        assert_eq!(data["MSG_CLASS"], "STATUS");
+        assert_eq!(data["GPS_DATETIME"], "2024-03-11 14:30:22");
+        assert_eq!(data["GPS_EPOCH"], "1710167422");
+        assert_eq!(data["LATITUD"], "+19.432608");
+        assert_eq!(data["SPEED"], "032.40");
+        assert_eq!(data["MSG_COUNTER"], "3187");
+        assert_eq!(data["TRIP_HOURMETER"], "3715");
+        assert_eq!(data["BACKUP_BATTERY_VOLTAGE"], "4.1");
+        assert!(!data.contains_key("ALERT"));
+        assert_eq!(decoded(&frame)["STT_RPT_TYPE"], "1");
+
+        // RES usa el mismo layout que STT
+        let response = self::frame(&STT.replacen("STT", "RES", 1));
+        assert_eq!(response.data, frame.data);
+    }
+
+    #[test]
+    fn alert_layout() {
+        let frame = frame(ALT);
+        let data = &frame.data;
+        assert_eq!(data["MSG_CLASS"], "ALERT");
+        assert_eq!(data["ALERT"], "3");
+        assert_eq!(data["BACKUP_BATTERY_VOLTAGE"], "4.0");
+        // Sin MSG_NUM en las alertas
+        assert!(!data.contains_key("MSG_COUNTER"));
+        let fields = decoded(&frame);
+        assert_eq!(fields["ALERT_MOD"], "0");
+        assert_eq!(fields["ALERT_DATA"], "");
+        assert!(!fields.contains_key("STT_RPT_TYPE"));
+    }
+
+    #[test]
+    fn first_input_bit_is_the_ignition() {
+        assert_eq!(frame(STT).data["ENGINE_STATUS"], "ON");
+        assert_eq!(frame(ALT).data["ENGINE_STATUS"], "OFF");
+        let unknown = STT.replace(";10000000;", ";;");
+        assert!(!frame(&unknown).data.contains_key("ENGINE_STATUS"));
+    }
+
+    #[test]
+    fn msg_type_is_the_delivery_type() {
+        assert_eq!(frame(STT).data["DELIVERY_TYPE"], "REALTIME");
+        assert_eq!(frame(ALT).data["DELIVERY_TYPE"], "BUFFERED");
+        let unknown = format!("{}7", STT.strip_suffix('1').unwrap());
+        assert!(!frame(&unknown).data.contains_key("DELIVERY_TYPE"));
+    }
+
+    #[test]
+    fn non_numeric_trip_hourmeter_is_left_out() {
+        // HHHHH:MM:SS de algunos firmwares: queda solo en los campos decodificados
+        let frame = frame(ALT);
+        assert!(!frame.data.contains_key("TRIP_HOURMETER"));
+        assert_eq!(decoded(&frame)["TRIP_HOURMETER"], "0012:05:33");
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        assert_eq!(
+            parse_frame(&STT.replacen(";205700001;", ";;", 1))
+                .unwrap()
+                .unwrap_err(),
+            RawFrameError::Invalid("sin DEVICE_ID")
+        );
+        assert_eq!(
+            parse_frame("ST300STT;205700001;04;1097B")
+                .unwrap()
+                .unwrap_err(),
+            RawFrameError::Invalid("faltan campos")
+        );
+        assert_eq!(
+            parse_frame(&STT.replacen("STT", "EMG", 1))
+                .unwrap()
+                .unwrap_err(),
+            RawFrameError::Unsupported("EMG".to_string())
+        );
+        // Tramas de otros fabricantes no son de Suntech
+        assert!(parse_frame("+RESP:GTFRI,C30204,860201061504081,,,,,").is_none());
+        assert!(parse_frame("ST").is_none());
+    }
+}
@@ -1,5 +1,32 @@
+pub mod ack;
 pub mod communication_record;
+pub mod device_command;
 pub mod device_message;
+pub mod driver_event;
+pub mod driving_event;
+pub mod geofence;
+pub mod gps_datetime;
+pub mod manufacturers;
+pub mod normalized_position;
+pub mod power_event;
+pub mod presence;
+pub mod schema_version;
+pub mod trip;
 
+#[cfg(test)]
+mod tests;
+
+pub use ack::AckToken;
 pub use communication_record::*;
+pub use device_command::*;
 pub use device_message::*;
+pub use driver_event::*;
+pub use driving_event::*;
+pub use geofence::*;
+pub use gps_datetime::*;
+pub use manufacturers::*;
+pub use normalized_position::*;
+pub use power_event::*;
+pub use presence::*;
+pub use schema_version::CURRENT_SCHEMA_VERSION;
+pub use trip::*;
@@ -0,0 +1,241 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::{parse_gps_datetime, DeviceMessage, Manufacturer};
+
+/// Estado del motor (ignición)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum EngineStatus {
+    On,
+    Off,
+}
+
+impl EngineStatus {
+    /// Valor guardado en la columna `engine_status`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineStatus::On => "ON",
+            EngineStatus::Off => "OFF",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "ON" | "1" => Some(EngineStatus::On),
+            "OFF" | "0" => Some(EngineStatus::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Campo que no pudo convertirse al tipo esperado o que está fuera de rango
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FieldIssue {
+    pub field: &'static str,
+    pub value: String,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for FieldIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}='{}' ({})", self.field, self.value, self.reason)
+    }
+}
+
+/// Señales digitales del equipo, decodificadas por el enriquecedor `io_state` a partir
+/// de los campos de bits del fabricante (None = sin mapeo o sin dato)
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct DigitalIo {
+    pub ignition: Option<bool>,
+    pub panic_button: Option<bool>,
+    pub door_sensor: Option<bool>,
+    pub relay_output: Option<bool>,
+}
+
+/// Posición con los campos de `DeviceData` ya convertidos a números, fechas y enums,
+/// construida una sola vez por mensaje. Los campos inválidos quedan en `None` y se
+/// registran en `issues` en lugar de descartarse en silencio.
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizedPosition {
+    pub uuid: String,
+    pub device_id: String,
+    pub manufacturer: Manufacturer,
+    /// Cliente dueño del dispositivo, asignado por el enriquecedor de tenant
+    pub tenant: Option<String>,
+    /// Origen del mensaje: tópico, cola o `http`
+    pub source: Option<String>,
+    pub msg_class: String,
+    pub alert: Option<String>,
+    pub delivery_type: String,
+
+    pub gps_datetime: Option<NaiveDateTime>,
+    /// `gps_datetime` ya está en UTC (el equipo envió su zona o un epoch)
+    pub gps_datetime_utc: bool,
+    pub gps_epoch: Option<i64>,
+    /// Grados decimales, -90 a 90
+    pub latitude: Option<f64>,
+    /// Grados decimales, -180 a 180
+    pub longitude: Option<f64>,
+    /// Metros sobre el nivel del mar
+    pub altitude_m: Option<f64>,
+    pub speed_kmh: Option<f64>,
+    /// Rumbo en grados, 0 a 360
+    pub course_deg: Option<f64>,
+    pub satellites: Option<i32>,
+    pub fix_status: String,
+
+    pub engine_status: Option<EngineStatus>,
+    pub main_battery_voltage_v: Option<f64>,
+    pub backup_battery_voltage_v: Option<f64>,
+    pub backup_battery_percent: Option<f64>,
+    /// Segundos en ralentí
+    pub idle_time_s: Option<i32>,
+    /// Segundos sobre el límite de velocidad
+    pub speed_time_s: Option<i32>,
+    pub odometer_m: Option<i64>,
+    pub total_distance_m: Option<i64>,
+    pub trip_distance_m: Option<i64>,
+    pub trip_hourmeter: Option<i32>,
+    pub msg_counter: Option<i32>,
+
+    pub cell_id: String,
+    pub lac: String,
+    pub mcc: String,
+    pub mnc: String,
+    pub rx_lvl: Option<i32>,
+    pub network_status: String,
+    pub model: String,
+    pub firmware: String,
+
+    pub bytes: i32,
+    pub client_ip: String,
+    pub client_port: i32,
+    pub decoded_epoch: i64,
+    pub received_epoch: i64,
+    pub raw: String,
+
+    pub issues: Vec<FieldIssue>,
+    /// Calidad del fix GPS de 0 a 100 (None = no evaluada)
+    pub quality_score: Option<u8>,
+    /// Campos decodificados propios del fabricante
+    pub decoded_extra: Option<serde_json::Value>,
+    pub digital_io: DigitalIo,
+}
+
+impl NormalizedPosition {
+    pub fn from_device_message(msg: &DeviceMessage) -> Self {
+        let data = &msg.data;
+        let mut parser = FieldParser::default();
+
+        let gps_datetime = parser.parse("gps_datetime", &data.gps_datetime, parse_gps_datetime);
+        let engine_status = parser.parse("engine_status", &data.engine_status, EngineStatus::parse);
+
+        Self {
+            uuid: msg.uuid.clone(),
+            device_id: data.device_id.clone(),
+            manufacturer: msg.get_manufacturer(),
+            tenant: None,
+            source: msg.source.clone(),
+            msg_class: data.msg_class.clone(),
+            alert: Some(data.alert.clone()).filter(|alert| !alert.is_empty()),
+            delivery_type: data.delivery_type.clone(),
+
+            gps_datetime: gps_datetime.map(|datetime| datetime.value),
+            gps_datetime_utc: gps_datetime.is_some_and(|datetime| datetime.utc),
+            gps_epoch: parser.number("gps_epoch", &data.gps_epoch),
+            latitude: parser.in_range("latitude", &data.latitude, -90.0, 90.0),
+            longitude: parser.in_range("longitude", &data.longitude, -180.0, 180.0),
+            altitude_m: parser.number("altitude", &data.altitude),
+            speed_kmh: parser.in_range("speed", &data.speed, 0.0, f64::MAX),
+            course_deg: parser.in_range("course", &data.course, 0.0, 360.0),
+            satellites: parser.number("satellites", &data.satellites),
+            fix_status: data.fix_status.clone(),
+
+            engine_status,
+            main_battery_voltage_v: parser
+                .number("main_battery_voltage", &data.main_battery_voltage),
+            backup_battery_voltage_v: parser
+                .number("backup_battery_voltage", &data.backup_battery_voltage),
+            backup_battery_percent: parser
+                .number("backup_battery_percent", &data.backup_battery_percent),
+            idle_time_s: parser.number("idle_time", &data.idle_time),
+            speed_time_s: parser.number("speed_time", &data.speed_time),
+            odometer_m: parser.number("odometer", &data.odometer),
+            total_distance_m: parser.number("total_distance", &data.total_distance),
+            trip_distance_m: parser.number("trip_distance", &data.trip_distance),
+            trip_hourmeter: parser.number("trip_hourmeter", &data.trip_hourmeter),
+            msg_counter: parser.number("msg_counter", &data.msg_counter),
+
+            cell_id: data.cell_id.clone(),
+            lac: data.lac.clone(),
+            mcc: data.mcc.clone(),
+            mnc: data.mnc.clone(),
+            rx_lvl: parser.number("rx_lvl", &data.rx_lvl),
+            network_status: data.network_status.clone(),
+            model: data.model.clone(),
+            firmware: data.firmware.clone(),
+
+            bytes: msg.metadata.bytes,
+            client_ip: msg.metadata.client_ip.clone(),
+            client_port: msg.metadata.client_port,
+            decoded_epoch: msg.metadata.decoded_epoch,
+            received_epoch: msg.metadata.received_epoch,
+            raw: msg.raw.clone(),
+
+            issues: parser.issues,
+            quality_score: None,
+            decoded_extra: msg.decoded.extra(),
+            digital_io: DigitalIo::default(),
+        }
+    }
+}
+
+/// Convierte los campos de texto y acumula los errores de conversión.
+/// Un campo vacío es `None` sin error: el dispositivo no lo reportó.
+#[derive(Default)]
+struct FieldParser {
+    issues: Vec<FieldIssue>,
+}
+
+impl FieldParser {
+    fn parse<T>(
+        &mut self,
+        field: &'static str,
+        value: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Option<T> {
+        if value.is_empty() {
+            return None;
+        }
+        let parsed = parse(value);
+        if parsed.is_none() {
+            self.issue(field, value, "formato inválido");
+        }
+        parsed
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, field: &'static str, value: &str) -> Option<T> {
+        // Algunos firmwares envían el signo '+' explícito
+        self.parse(field, value, |value| {
+            value.strip_prefix('+').unwrap_or(value).parse().ok()
+        })
+    }
+
+    fn in_range(&mut self, field: &'static str, value: &str, min: f64, max: f64) -> Option<f64> {
+        let number: f64 = self.number(field, value)?;
+        if !(min..=max).contains(&number) {
+            self.issue(field, value, "fuera de rango");
+            return None;
+        }
+        Some(number)
+    }
+
+    fn issue(&mut self, field: &'static str, value: &str, reason: &'static str) {
+        self.issues.push(FieldIssue {
+            field,
+            value: value.to_string(),
+            reason,
+        });
+    }
+}
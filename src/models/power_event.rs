@@ -0,0 +1,45 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Tipo de evento de alimentación
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerTransition {
+    /// El voltaje principal bajó del umbral de corte: el equipo funciona con la
+    /// batería de respaldo (desconexión o sabotaje)
+    PowerLost,
+    /// El voltaje principal volvió al umbral de restablecimiento
+    PowerRestored,
+    /// La batería de respaldo bajó de su umbral
+    LowBackupBattery,
+}
+
+impl PowerTransition {
+    /// Valor guardado en la columna `event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowerTransition::PowerLost => "power_lost",
+            PowerTransition::PowerRestored => "power_restored",
+            PowerTransition::LowBackupBattery => "low_backup_battery",
+        }
+    }
+}
+
+/// Cambio de alimentación de un dispositivo, guardado en `power_events` y publicado
+/// en el tópico de notificaciones
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerEvent {
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub model: Option<String>,
+    pub event: PowerTransition,
+    /// Voltaje principal (`power_*`) o de la batería de respaldo (`low_backup_battery`)
+    pub voltage: f64,
+    /// Umbral aplicado, general o del modelo
+    pub threshold: f64,
+    /// UUID del mensaje que produjo el evento
+    pub uuid: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub gps_datetime: Option<NaiveDateTime>,
+}
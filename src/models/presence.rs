@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Cambio de conectividad de un dispositivo
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PresenceTransition {
+    /// Sin mensajes durante más de `DEVICE_OFFLINE_AFTER_SECS`
+    DeviceOffline,
+    /// Primer mensaje tras estar desconectado
+    DeviceOnline,
+}
+
+/// Evento publicado en el tópico de notificaciones cuando un dispositivo deja de
+/// reportar o vuelve a hacerlo
+#[derive(Debug, Clone, Serialize)]
+pub struct PresenceEvent {
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub event: PresenceTransition,
+    /// Último mensaje recibido antes del cambio
+    pub last_seen: DateTime<Utc>,
+    /// Segundos sin mensajes hasta el cambio
+    pub silent_secs: i64,
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Layout con el que trabaja el consumer internamente (`DeviceMessage`)
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+/// Versión más nueva del envelope que se sabe leer. Los productores pueden
+/// actualizarse hasta esta versión antes que los consumers.
+pub const MAX_SCHEMA_VERSION: u32 = 2;
+
+/// Campos de `data` renombrados en v2 (nombre v2, nombre v1)
+const V2_RENAMED_FIELDS: &[(&str, &str)] = &[
+    ("LATITUDE", "LATITUD"),
+    ("LONGITUDE", "LONGITUD"),
+    ("FIX", "FIX_"),
+    ("BACKUP_BATTERY_PERCENT", "PERCENT_BACKUP"),
+];
+
+/// Versión efectiva del envelope; 0 (productores sin el campo) equivale a v1
+pub fn effective_version(version: u64) -> Result<u32> {
+    match version {
+        0 => Ok(CURRENT_SCHEMA_VERSION),
+        v if v <= MAX_SCHEMA_VERSION as u64 => Ok(v as u32),
+        v => Err(anyhow!(
+            "schema_version {} no soportada (máxima {})",
+            v,
+            MAX_SCHEMA_VERSION
+        )),
+    }
+}
+
+/// Lleva los datos normalizados de un `KafkaMessage` al layout v1
+pub fn upgrade_data_map(version: u32, data: &mut HashMap<String, String>) -> Result<()> {
+    if effective_version(version as u64)? >= 2 {
+        for (v2_name, v1_name) in V2_RENAMED_FIELDS {
+            if let Some(value) = data.remove(*v2_name) {
+                data.entry(v1_name.to_string()).or_insert(value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lleva un `DeviceMessage` serializado en JSON al layout v1: en v2 los campos de
+/// `data` y de los datos decodificados pueden venir como números o booleanos y
+/// algunos campos cambiaron de nombre
+pub fn upgrade_json(message: &mut Value) -> Result<()> {
+    let version = message
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+
+    if effective_version(version)? >= 2 {
+        if let Some(Value::Object(data)) = message.get_mut("data") {
+            for (v2_name, v1_name) in V2_RENAMED_FIELDS {
+                if let Some(value) = data.remove(*v2_name) {
+                    data.entry(v1_name.to_string()).or_insert(value);
+                }
+            }
+            stringify_values(data);
+        }
+        if let Some(Value::Object(decoded)) = message.get_mut("decoded") {
+            for fields in decoded.values_mut() {
+                if let Value::Object(fields) = fields {
+                    stringify_values(fields);
+                }
+            }
+        }
+    }
+
+    // Un payload que no es objeto no se toca: lo rechaza la deserialización
+    if let Value::Object(fields) = message {
+        fields.insert("schema_version".into(), Value::from(CURRENT_SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
+/// Convierte los valores tipados de v2 a los textos de v1; los nulos se quitan para
+/// que tomen el valor por defecto
+fn stringify_values(fields: &mut serde_json::Map<String, Value>) {
+    fields.retain(|_, value| !value.is_null());
+    for value in fields.values_mut() {
+        let text = match value {
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => String::from(if *flag { "1" } else { "0" }),
+            _ => continue,
+        };
+        *value = Value::String(text);
+    }
+}
+
+/// Versión del layout de un `DeviceMessage` deserializado sin el campo
+pub(crate) fn default_schema_version() -> u32 {
+    CURRENT_SCHEMA_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn payloads_that_are_not_objects_are_left_untouched() {
+        for payload in [json!([]), json!("x"), Value::Null] {
+            let mut upgraded = payload.clone();
+            upgrade_json(&mut upgraded).unwrap();
+            assert_eq!(upgraded, payload);
+        }
+    }
+
+    #[test]
+    fn v2_fields_are_renamed_and_stringified() {
+        let mut message = json!({
+            "schema_version": 2,
+            "data": { "LATITUDE": 19.4326, "FIX": true, "ODOMETER": null },
+        });
+        upgrade_json(&mut message).unwrap();
+        assert_eq!(
+            message,
+            json!({
+                "schema_version": CURRENT_SCHEMA_VERSION,
+                "data": { "LATITUD": "19.4326", "FIX_": "1" },
+            })
+        );
+    }
+}
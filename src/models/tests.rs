@@ -0,0 +1,404 @@
+//! Corpus de mensajes reales en `tests/fixtures/messages` con su registro esperado
+//! (`*.golden.json`) y fuzzing de la deserialización y la conversión a registro.
+//!
+//! Tras un cambio intencional en la conversión, regenerar los archivos esperados con
+//! `UPDATE_GOLDEN=1 cargo test models::tests` y revisar el diff.
+
+use proptest::prelude::*;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use super::{CommunicationRecord, DeviceMessage, NormalizedPosition};
+use crate::config::SanitizationConfig;
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/messages")
+}
+
+/// Mensajes del corpus por nombre, sin los archivos esperados
+fn corpus() -> Vec<(String, Vec<u8>)> {
+    let mut messages: Vec<_> = std::fs::read_dir(corpus_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+                && !path.to_string_lossy().ends_with(".golden.json")
+        })
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, std::fs::read(&path).unwrap())
+        })
+        .collect();
+    messages.sort();
+    assert!(!messages.is_empty(), "corpus vacío");
+    messages
+}
+
+fn to_record(message: &DeviceMessage) -> (NormalizedPosition, CommunicationRecord) {
+    let position = NormalizedPosition::from_device_message(message);
+    let record = CommunicationRecord::from_position(&position, &SanitizationConfig::default())
+        .expect("la política por defecto no rechaza registros");
+    (position, record)
+}
+
+/// Lo que se compara con el archivo esperado: el registro sin las marcas de tiempo
+/// de la conversión y los campos inválidos
+fn golden_value(message: &DeviceMessage) -> Value {
+    let (position, record) = to_record(message);
+    let mut record = serde_json::to_value(&record).unwrap();
+    let record_fields = record.as_object_mut().unwrap();
+    record_fields.remove("received_at");
+    record_fields.remove("created_at");
+    json!({
+        "manufacturer": position.manufacturer,
+        "table": position.manufacturer.table(),
+        "issues": position.issues,
+        "record": record,
+    })
+}
+
+#[test]
+fn corpus_matches_golden_records() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    for (name, payload) in corpus() {
+        let message =
+            DeviceMessage::from_json(&payload).unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+        let actual = golden_value(&message);
+        let golden = corpus_dir().join(format!("{}.golden.json", name));
+        if update {
+            let mut text = serde_json::to_string_pretty(&actual).unwrap();
+            text.push('\n');
+            std::fs::write(&golden, text).unwrap();
+            continue;
+        }
+        let expected: Value = serde_json::from_slice(
+            &std::fs::read(&golden).unwrap_or_else(|e| panic!("{}: {}", golden.display(), e)),
+        )
+        .unwrap();
+        assert_eq!(actual, expected, "{} difiere de {}", name, golden.display());
+    }
+}
+
+#[test]
+fn corpus_round_trips() {
+    for (name, payload) in corpus() {
+        let message = DeviceMessage::from_json(&payload).unwrap();
+        let serialized = serde_json::to_vec(&message).unwrap();
+        let reparsed = DeviceMessage::from_json(&serialized).unwrap();
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::to_value(&reparsed).unwrap(),
+            "{}",
+            name
+        );
+        assert_eq!(golden_value(&message), golden_value(&reparsed), "{}", name);
+    }
+}
+
+#[test]
+fn tenant_is_not_read_from_the_payload() {
+    let (_, payload) = corpus().into_iter().next().unwrap();
+    let mut value: Value = serde_json::from_slice(&payload).unwrap();
+    value["tenant"] = json!("globex");
+    let message = DeviceMessage::from_json_value(value).unwrap();
+    assert_eq!(message.tenant, None);
+
+    // El asignado por el consumidor sí se publica al republicar
+    let mut message = message;
+    message.tenant = Some("acme".to_string());
+    assert_eq!(serde_json::to_value(&message).unwrap()["tenant"], "acme");
+}
+
+/// Campos de `data` de un mensaje v1
+const DATA_FIELDS: &[&str] = &[
+    "ALERT",
+    "ALTITUDE",
+    "BACKUP_BATTERY_VOLTAGE",
+    "PERCENT_BACKUP",
+    "CELL_ID",
+    "COURSE",
+    "DELIVERY_TYPE",
+    "ENGINE_STATUS",
+    "FIRMWARE",
+    "FIX_",
+    "GPS_DATETIME",
+    "GPS_EPOCH",
+    "IDLE_TIME",
+    "LAC",
+    "LATITUD",
+    "LONGITUD",
+    "MAIN_BATTERY_VOLTAGE",
+    "MCC",
+    "MNC",
+    "MODEL",
+    "MSG_CLASS",
+    "MSG_COUNTER",
+    "NETWORK_STATUS",
+    "ODOMETER",
+    "RX_LVL",
+    "SATELLITES",
+    "SPEED",
+    "SPEED_TIME",
+    "TOTAL_DISTANCE",
+    "TRIP_DISTANCE",
+    "TRIP_HOURMETER",
+];
+
+fn envelope(data: Value, decoded_key: &str, schema_version: Option<u64>) -> Value {
+    let mut message = json!({
+        "data": data,
+        "decoded": { decoded_key: { "HEADER": "+RESP:GTFRI" } },
+        "metadata": {
+            "BYTES": 0,
+            "CLIENT_IP": "127.0.0.1",
+            "CLIENT_PORT": 0,
+            "DECODED_EPOCH": 0,
+            "RECEIVED_EPOCH": 0,
+        },
+        "raw": "",
+        "uuid": "00000000-0000-4000-8000-000000000000",
+    });
+    if let Some(version) = schema_version {
+        message["schema_version"] = json!(version);
+    }
+    message
+}
+
+/// Valor JSON cualquiera, hasta unos pocos niveles de profundidad
+fn any_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(3, 32, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+            prop::collection::hash_map(".*", inner, 0..8)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+/// Texto de campo de dispositivo: a veces numérico, a veces basura
+fn field_text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        ".*",
+        "[+-]?[0-9]{0,12}(\\.[0-9]{0,8})?",
+        "[0-9]{8,14}",
+        any::<f64>().prop_map(|value| value.to_string()),
+    ]
+}
+
+/// Posición válida con sus valores esperados en el registro
+#[derive(Debug, Clone)]
+struct ValidPosition {
+    device_id: String,
+    latitude: f64,
+    longitude: f64,
+    speed: f64,
+    course: f64,
+    satellites: i32,
+    msg_counter: i32,
+    odometer: i64,
+    gps_epoch: i64,
+    engine_on: bool,
+    model: String,
+    queclink: bool,
+}
+
+fn valid_position() -> impl Strategy<Value = ValidPosition> {
+    (
+        "[0-9]{9,15}",
+        -90.0..=90.0f64,
+        -180.0..=180.0f64,
+        0.0..=400.0f64,
+        0.0..=360.0f64,
+        0..=64i32,
+        0..=i32::MAX,
+        0..=i64::MAX / 2,
+        946_684_800..=4_102_444_800i64,
+        any::<bool>(),
+        "[A-Z0-9]{1,20}",
+        any::<bool>(),
+    )
+        .prop_map(
+            |(
+                device_id,
+                latitude,
+                longitude,
+                speed,
+                course,
+                satellites,
+                msg_counter,
+                odometer,
+                gps_epoch,
+                engine_on,
+                model,
+                queclink,
+            )| ValidPosition {
+                device_id,
+                latitude,
+                longitude,
+                speed,
+                course,
+                satellites,
+                msg_counter,
+                odometer,
+                gps_epoch,
+                engine_on,
+                model,
+                queclink,
+            },
+        )
+}
+
+impl ValidPosition {
+    fn gps_datetime(&self) -> chrono::NaiveDateTime {
+        chrono::DateTime::from_timestamp(self.gps_epoch, 0)
+            .unwrap()
+            .naive_utc()
+    }
+
+    fn decoded_key(&self) -> &'static str {
+        if self.queclink {
+            "QueclinkRaw"
+        } else {
+            "SuntechRaw"
+        }
+    }
+
+    /// Mensaje v1 con todos los valores como texto, como lo publica el decodificador
+    fn v1(&self) -> Value {
+        let data = json!({
+            "DEVICE_ID": self.device_id,
+            "LATITUD": self.latitude.to_string(),
+            "LONGITUD": self.longitude.to_string(),
+            "SPEED": self.speed.to_string(),
+            "COURSE": self.course.to_string(),
+            "SATELLITES": self.satellites.to_string(),
+            "MSG_COUNTER": self.msg_counter.to_string(),
+            "ODOMETER": self.odometer.to_string(),
+            "GPS_EPOCH": self.gps_epoch.to_string(),
+            "GPS_DATETIME": self.gps_datetime().format("%Y-%m-%d %H:%M:%S").to_string(),
+            "ENGINE_STATUS": if self.engine_on { "ON" } else { "OFF" },
+            "MODEL": self.model,
+        });
+        envelope(data, self.decoded_key(), None)
+    }
+
+    /// El mismo mensaje en v2: valores tipados y campos renombrados
+    fn v2(&self) -> Value {
+        let data = json!({
+            "DEVICE_ID": self.device_id,
+            "LATITUDE": self.latitude,
+            "LONGITUDE": self.longitude,
+            "SPEED": self.speed,
+            "COURSE": self.course,
+            "SATELLITES": self.satellites,
+            "MSG_COUNTER": self.msg_counter,
+            "ODOMETER": self.odometer,
+            "GPS_EPOCH": self.gps_epoch,
+            "GPS_DATETIME": self.gps_datetime().format("%Y-%m-%d %H:%M:%S").to_string(),
+            "ENGINE_STATUS": if self.engine_on { "ON" } else { "OFF" },
+            "MODEL": self.model,
+        });
+        envelope(data, self.decoded_key(), Some(2))
+    }
+
+    fn assert_record(&self, position: &NormalizedPosition, record: &CommunicationRecord) {
+        assert!(position.issues.is_empty(), "{:?}", position.issues);
+        assert_eq!(
+            position.manufacturer.as_str(),
+            if self.queclink { "queclink" } else { "suntech" }
+        );
+        assert_eq!(record.device_id, self.device_id);
+        assert_eq!(record.latitude, Some(self.latitude));
+        assert_eq!(record.longitude, Some(self.longitude));
+        assert_eq!(record.speed, Some(self.speed));
+        assert_eq!(record.course, Some(self.course));
+        assert_eq!(record.satellites, Some(self.satellites));
+        assert_eq!(record.msg_counter, Some(self.msg_counter));
+        assert_eq!(record.odometer, Some(self.odometer));
+        assert_eq!(record.gps_epoch, Some(self.gps_epoch));
+        assert_eq!(record.gps_datetime, Some(self.gps_datetime()));
+        assert_eq!(
+            record.engine_status.as_deref(),
+            Some(if self.engine_on { "ON" } else { "OFF" })
+        );
+        assert_eq!(record.model.as_deref(), Some(self.model.as_str()));
+    }
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(payload in prop::collection::vec(any::<u8>(), 0..512)) {
+        if let Ok(message) = DeviceMessage::from_json(&payload) {
+            let position = NormalizedPosition::from_device_message(&message);
+            let _ = CommunicationRecord::from_position(&position, &SanitizationConfig::default());
+        }
+    }
+
+    #[test]
+    fn arbitrary_envelopes_never_panic(
+        data in any_json(),
+        decoded in any_json(),
+        schema_version in any_json(),
+    ) {
+        let message = json!({
+            "schema_version": schema_version,
+            "data": data,
+            "decoded": decoded,
+            "metadata": {},
+            "raw": "",
+            "uuid": "",
+        });
+        if let Ok(message) = DeviceMessage::from_json_value(message) {
+            let position = NormalizedPosition::from_device_message(&message);
+            let _ = CommunicationRecord::from_position(&position, &SanitizationConfig::default());
+        }
+    }
+
+    #[test]
+    fn arbitrary_fields_convert_without_panic(
+        fields in prop::collection::vec((prop::sample::select(DATA_FIELDS), field_text()), 0..24),
+        device_id in field_text(),
+        queclink in any::<bool>(),
+    ) {
+        let mut data = serde_json::Map::new();
+        data.insert("DEVICE_ID".to_string(), Value::from(device_id));
+        for (field, value) in fields {
+            data.insert(field.to_string(), Value::from(value));
+        }
+        let decoded_key = if queclink { "QueclinkRaw" } else { "SuntechRaw" };
+        let message = DeviceMessage::from_json_value(envelope(Value::Object(data), decoded_key, None))
+            .expect("los campos de texto siempre deserializan");
+        let position = NormalizedPosition::from_device_message(&message);
+        for sanitization in [
+            SanitizationConfig::default(),
+            SanitizationConfig {
+                policy: crate::config::FieldOverflowPolicy::Reject,
+                ..SanitizationConfig::default()
+            },
+        ] {
+            let _ = CommunicationRecord::from_position(&position, &sanitization);
+        }
+    }
+
+    #[test]
+    fn valid_positions_convert_losslessly(position in valid_position()) {
+        for message in [position.v1(), position.v2()] {
+            let message = DeviceMessage::from_json_value(message).unwrap();
+            let (normalized, record) = to_record(&message);
+            position.assert_record(&normalized, &record);
+
+            // Serializar y volver a leer el mensaje no cambia el registro
+            let reparsed = DeviceMessage::from_json(&serde_json::to_vec(&message).unwrap()).unwrap();
+            let (normalized, record) = to_record(&reparsed);
+            position.assert_record(&normalized, &record);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::NaiveDateTime;
+use geoutils::Location;
+use serde::{Deserialize, Serialize};
+
+/// Posición que marca el inicio, el avance o el fin de un viaje
+#[derive(Debug, Clone, Serialize)]
+pub struct TripPoint {
+    /// UUID del mensaje
+    pub uuid: String,
+    pub gps_datetime: Option<NaiveDateTime>,
+    pub gps_epoch: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl TripPoint {
+    pub fn distance_m(&self, other: &TripPoint) -> f64 {
+        Location::new(self.latitude, self.longitude)
+            .haversine_distance_to(&Location::new(other.latitude, other.longitude))
+            .meters()
+    }
+}
+
+/// Viaje de un dispositivo, guardado en la tabla `trips`. Mientras está abierto,
+/// `end` es la última posición procesada.
+#[derive(Debug, Clone)]
+pub struct Trip {
+    /// UUID del mensaje que abrió el viaje
+    pub id: String,
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub start: TripPoint,
+    pub end: TripPoint,
+    pub distance_m: f64,
+    pub closed: bool,
+}
+
+impl Trip {
+    pub fn duration_secs(&self) -> i64 {
+        self.end.gps_epoch - self.start.gps_epoch
+    }
+}
+
+/// Tipo de evento de viaje
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TripTransition {
+    TripStart,
+    TripEnd,
+}
+
+/// Inicio o fin de un viaje, publicado en el tópico de notificaciones
+#[derive(Debug, Clone, Serialize)]
+pub struct TripEvent {
+    pub trip_id: String,
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub event: TripTransition,
+    /// UUID del mensaje que produjo el evento
+    pub uuid: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub gps_datetime: Option<NaiveDateTime>,
+    /// Distancia y duración acumuladas (0 en el inicio)
+    pub distance_m: f64,
+    pub duration_secs: i64,
+}
+
+impl TripEvent {
+    pub fn new(trip: &Trip, event: TripTransition) -> Self {
+        let point = match event {
+            TripTransition::TripStart => &trip.start,
+            TripTransition::TripEnd => &trip.end,
+        };
+        Self {
+            trip_id: trip.id.clone(),
+            device_id: trip.device_id.clone(),
+            tenant: trip.tenant.clone(),
+            event,
+            uuid: point.uuid.clone(),
+            latitude: point.latitude,
+            longitude: point.longitude,
+            gps_datetime: point.gps_datetime,
+            distance_m: trip.distance_m,
+            duration_secs: trip.duration_secs(),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Ocultación de datos personales en logs e incidentes: device_id, IP del equipo y
+//! trama cruda. Las reglas se configuran una vez al iniciar los logs; sin `init` los
+//! valores salen tal cual. Los valores se siguen pudiendo correlacionar: el mismo
+//! device_id da siempre el mismo hash y la IP conserva su red.
+
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use crate::config::{DeviceIdRedaction, RedactionConfig};
+
+static RULES: OnceLock<RedactionConfig> = OnceLock::new();
+
+/// Aplica `config` a partir de ahora; solo la primera llamada tiene efecto
+pub fn init(config: &RedactionConfig) {
+    let _ = RULES.set(config.clone());
+}
+
+/// device_id para los logs: con `LOG_REDACT_DEVICE_ID=hash`, `#` y los 12 primeros
+/// dígitos hex del SHA-256 con la sal
+pub fn device_id(device_id: &str) -> Cow<'_, str> {
+    match RULES.get() {
+        Some(rules) if rules.device_id == DeviceIdRedaction::Hash => {
+            Cow::Owned(hash_device_id(device_id, rules.hash_salt.as_deref()))
+        }
+        _ => Cow::Borrowed(device_id),
+    }
+}
+
+/// Hash de un device_id con la sal dada, el mismo que muestran los logs; sirve para
+/// buscar en los logs los mensajes de un dispositivo
+pub fn hash_device_id(device_id: &str, salt: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.unwrap_or_default().as_bytes());
+    hasher.update(device_id.as_bytes());
+    format!("#{}", &hex::encode(hasher.finalize())[..12])
+}
+
+/// IP del equipo para los logs: con `LOG_REDACT_CLIENT_IP`, solo la red (/24 en IPv4,
+/// /48 en IPv6)
+pub fn client_ip(client_ip: &str) -> Cow<'_, str> {
+    if !RULES.get().is_some_and(|rules| rules.mask_client_ip) {
+        return Cow::Borrowed(client_ip);
+    }
+    mask_client_ip(client_ip)
+}
+
+/// Red de la IP; lo que no se pueda leer como IP se oculta entero
+fn mask_client_ip(client_ip: &str) -> Cow<'_, str> {
+    if client_ip.is_empty() {
+        return Cow::Borrowed(client_ip);
+    }
+    Cow::Owned(match client_ip.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let [a, b, c, ..] = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", a, b, c)
+        }
+        Err(_) => "***".to_string(),
+    })
+}
+
+/// Trama cruda para los logs: con `LOG_REDACT_RAW_MAX_CHARS`, los primeros caracteres
+/// (cabecera y tipo de reporte) y cuántos se omitieron
+pub fn raw(raw: &str) -> Cow<'_, str> {
+    match RULES.get().and_then(|rules| rules.raw_max_chars) {
+        Some(max_chars) => truncate_raw(raw, max_chars),
+        None => Cow::Borrowed(raw),
+    }
+}
+
+/// Los primeros `max_chars` caracteres (no bytes) de la trama
+fn truncate_raw(raw: &str, max_chars: usize) -> Cow<'_, str> {
+    let total = raw.chars().count();
+    if total <= max_chars {
+        return Cow::Borrowed(raw);
+    }
+    let kept: String = raw.chars().take(max_chars).collect();
+    Cow::Owned(format!("{}…(+{})", kept, total - max_chars))
+}
+
+/// Valor de un campo de la posición, con la regla que corresponda a su nombre
+pub fn field<'a>(name: &str, value: &'a str) -> Cow<'a, str> {
+    match name {
+        "device_id" => device_id(value),
+        "client_ip" => client_ip(value),
+        "raw" | "raw_message" => raw(value),
+        _ => Cow::Borrowed(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_id_hash_is_stable_and_hides_the_id() {
+        let hash = hash_device_id("867530912345678", Some("sal"));
+
+        assert_eq!(hash, hash_device_id("867530912345678", Some("sal")));
+        assert_eq!(hash.len(), 13);
+        assert!(hash.starts_with('#'));
+        assert!(hash[1..].chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(!hash.contains("867530912345678"));
+        assert_ne!(hash, hash_device_id("867530912345679", Some("sal")));
+        assert_ne!(hash, hash_device_id("867530912345678", Some("otra")));
+        assert_ne!(hash, hash_device_id("867530912345678", None));
+    }
+
+    #[test]
+    fn short_and_empty_device_ids_are_hashed() {
+        for device_id in ["", "1"] {
+            let hash = hash_device_id(device_id, None);
+            assert_eq!(hash.len(), 13);
+            assert_ne!(hash, format!("#{}", device_id));
+        }
+    }
+
+    #[test]
+    fn client_ip_keeps_only_the_network() {
+        assert_eq!(mask_client_ip("201.141.23.77"), "201.141.23.0/24");
+        assert_eq!(mask_client_ip(" 10.0.0.1 "), "10.0.0.0/24");
+        assert_eq!(
+            mask_client_ip("2001:db8:85a3:8d3:1319:8a2e:370:7348"),
+            "2001:db8:85a3::/48"
+        );
+        assert_eq!(mask_client_ip("::1"), "0:0:0::/48");
+    }
+
+    #[test]
+    fn unparseable_and_empty_client_ips() {
+        assert_eq!(mask_client_ip("201.141.23.77:5000"), "***");
+        assert_eq!(mask_client_ip("desconocida"), "***");
+        assert_eq!(mask_client_ip(""), "");
+    }
+
+    #[test]
+    fn raw_truncation_counts_characters_not_bytes() {
+        // Cada carácter ocupa varios bytes: cortar por bytes caería a mitad de uno
+        let raw = "STT;ñandú;📍📍📍";
+
+        assert_eq!(truncate_raw(raw, 6), "STT;ña…(+7)");
+        assert_eq!(truncate_raw(raw, 12), "STT;ñandú;📍📍…(+1)");
+        assert_eq!(truncate_raw("📍", 0), "…(+1)");
+    }
+
+    #[test]
+    fn short_and_empty_raw_frames_are_kept() {
+        let raw = "STT;ñandú";
+
+        assert!(matches!(truncate_raw(raw, 9), Cow::Borrowed("STT;ñandú")));
+        assert!(matches!(truncate_raw(raw, 100), Cow::Borrowed(_)));
+        assert!(matches!(truncate_raw("", 0), Cow::Borrowed("")));
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::models::{CommandSource, DeviceCommand};
+use crate::redact;
+use crate::services::health::HealthStatus;
+use crate::services::maintenance::MaintenanceService;
+use crate::services::{
+    CommandForwarder, DatabaseService, HealthMonitor, LiveTail, MessageProcessor, PipelineControl,
+    PipelineStage, PresenceMonitor,
+};
+use crate::telemetry::LogLevel;
+
+/// Filas devueltas por `/devices/{id}/positions` si no se indica `limit`
+const DEFAULT_RANGE_LIMIT: i64 = 1000;
+
+/// Máximo de filas por consulta de histórico
+const MAX_RANGE_LIMIT: i64 = 10000;
+
+/// Comandos devueltos por `GET /devices/{id}/commands` si no se indica `limit`
+const DEFAULT_COMMANDS_LIMIT: i64 = 50;
+
+/// API HTTP de administración para operadores
+#[derive(Default)]
+pub struct AdminServer {
+    database: Option<Arc<DatabaseService>>,
+    maintenance: Option<Arc<MaintenanceService>>,
+    pipeline: Option<Arc<PipelineControl>>,
+    health: Option<Arc<HealthMonitor>>,
+    processor: Option<MessageProcessor>,
+    presence: Option<Arc<PresenceMonitor>>,
+    log_level: Option<&'static LogLevel>,
+    live_tail: Option<Arc<LiveTail>>,
+    commands: Option<Arc<CommandForwarder>>,
+}
+
+impl AdminServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Permite consultar lo guardado en la BD para verificar la ingesta
+    pub fn with_database(mut self, database: Arc<DatabaseService>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Expone el estado del mantenimiento de BD y permite lanzarlo a demanda
+    pub fn with_maintenance(mut self, maintenance: Arc<MaintenanceService>) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Permite pausar y reanudar etapas del pipeline en caliente
+    pub fn with_pipeline_control(mut self, control: Arc<PipelineControl>) -> Self {
+        self.pipeline = Some(control);
+        self
+    }
+
+    /// Expone las sondas `/health/live` y `/health/ready` para Kubernetes
+    pub fn with_health(mut self, health: Arc<HealthMonitor>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Expone las estadísticas del procesador como JSON
+    pub fn with_processor(mut self, processor: MessageProcessor) -> Self {
+        self.processor = Some(processor);
+        self
+    }
+
+    /// Expone la lista de dispositivos desconectados
+    pub fn with_presence(mut self, presence: Arc<PresenceMonitor>) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
+    /// Permite cambiar el filtro de los logs en caliente
+    pub fn with_log_level(mut self, log_level: &'static LogLevel) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    /// Expone `/tail`, la vista en vivo de los mensajes por WebSocket
+    pub fn with_live_tail(mut self, live_tail: Arc<LiveTail>) -> Self {
+        self.live_tail = Some(live_tail);
+        self
+    }
+
+    /// Permite enviar comandos a los equipos y consultar su entrega
+    pub fn with_commands(mut self, commands: Arc<CommandForwarder>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Empieza a escuchar en `bind` y atiende las peticiones en segundo plano
+    pub async fn start(self, bind: &str) -> Result<JoinHandle<()>> {
+        let listener = tokio::net::TcpListener::bind(bind).await?;
+        info!("🛠️ API de administración escuchando en {}", bind);
+
+        let router = Router::new()
+            .route("/devices/offline", get(offline_devices))
+            .route("/devices/:device_id/latest", get(device_latest))
+            .route("/devices/:device_id/positions", get(device_positions))
+            .route(
+                "/devices/:device_id/commands",
+                get(device_commands).post(send_command),
+            )
+            .route("/commands/:command_id", get(command_status))
+            .route("/maintenance", get(maintenance_status))
+            .route("/maintenance/run", post(run_maintenance))
+            .route("/pipeline", get(pipeline_status))
+            .route("/pipeline/:stage/pause", post(pause_stage))
+            .route("/pipeline/:stage/resume", post(resume_stage))
+            .route("/health/live", get(liveness))
+            .route("/health/ready", get(readiness))
+            .route("/stats", get(statistics))
+            .route("/log-level", get(log_level).put(set_log_level))
+            .route("/tail", get(tail))
+            .with_state(Arc::new(self));
+
+        Ok(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("❌ API de administración detenida: {}", e);
+            }
+        }))
+    }
+}
+
+type AdminState = State<Arc<AdminServer>>;
+
+/// Rango de `/devices/{id}/positions`, sobre `gps_datetime` (RFC 3339, `to` exclusivo)
+#[derive(Debug, Deserialize)]
+struct RangeQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: Option<i64>,
+}
+
+async fn device_latest(State(admin): AdminState, Path(device_id): Path<String>) -> Response {
+    let Some(database) = &admin.database else {
+        return disabled("consulta de la BD");
+    };
+
+    match database.get_latest_by_device(&device_id).await {
+        Ok(records) if records.is_empty() => (
+            StatusCode::NOT_FOUND,
+            format!("sin estado actual para {}", device_id),
+        )
+            .into_response(),
+        Ok(records) => Json(records).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn device_positions(
+    State(admin): AdminState,
+    Path(device_id): Path<String>,
+    Query(range): Query<RangeQuery>,
+) -> Response {
+    let Some(database) = &admin.database else {
+        return disabled("consulta de la BD");
+    };
+    if range.from >= range.to {
+        return (StatusCode::BAD_REQUEST, "from debe ser anterior a to").into_response();
+    }
+
+    let limit = range
+        .limit
+        .unwrap_or(DEFAULT_RANGE_LIMIT)
+        .clamp(1, MAX_RANGE_LIMIT);
+    match database
+        .get_range(
+            &device_id,
+            range.from.naive_utc(),
+            range.to.naive_utc(),
+            limit,
+        )
+        .await
+    {
+        Ok(records) => Json(records).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Cuerpo de `POST /devices/{id}/commands`
+#[derive(Debug, Deserialize)]
+struct CommandBody {
+    /// Id propio para reintentar sin duplicar; sin él se genera uno
+    id: Option<uuid::Uuid>,
+    tenant: Option<String>,
+    command: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandsQuery {
+    limit: Option<i64>,
+}
+
+async fn send_command(
+    State(admin): AdminState,
+    Path(device_id): Path<String>,
+    Json(body): Json<CommandBody>,
+) -> Response {
+    let Some(commands) = &admin.commands else {
+        return disabled("envío de comandos");
+    };
+    let command = DeviceCommand {
+        id: body.id.unwrap_or_else(uuid::Uuid::new_v4),
+        device_id,
+        tenant: body.tenant,
+        command: body.command,
+    };
+    match commands.submit(command.clone(), CommandSource::Api).await {
+        Ok(true) => (StatusCode::ACCEPTED, Json(command)).into_response(),
+        Ok(false) => (
+            StatusCode::CONFLICT,
+            format!("el comando {} ya se recibió", command.id),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn device_commands(
+    State(admin): AdminState,
+    Path(device_id): Path<String>,
+    Query(query): Query<CommandsQuery>,
+) -> Response {
+    let Some(commands) = &admin.commands else {
+        return disabled("envío de comandos");
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_COMMANDS_LIMIT)
+        .clamp(1, MAX_RANGE_LIMIT);
+    match commands.recent(&device_id, limit).await {
+        Ok(records) => Json(records).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn command_status(State(admin): AdminState, Path(command_id): Path<uuid::Uuid>) -> Response {
+    let Some(commands) = &admin.commands else {
+        return disabled("envío de comandos");
+    };
+    match commands.get(command_id).await {
+        Ok(Some(record)) => Json(record).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            format!("comando {} desconocido", command_id),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn maintenance_status(State(admin): AdminState) -> Response {
+    match &admin.maintenance {
+        Some(maintenance) => Json(maintenance.status().await).into_response(),
+        None => disabled("mantenimiento"),
+    }
+}
+
+async fn run_maintenance(State(admin): AdminState) -> Response {
+    match &admin.maintenance {
+        Some(maintenance) => Json(maintenance.run().await).into_response(),
+        None => disabled("mantenimiento"),
+    }
+}
+
+async fn pipeline_status(State(admin): AdminState) -> Response {
+    match &admin.pipeline {
+        Some(pipeline) => Json(pipeline.status()).into_response(),
+        None => disabled("control del pipeline"),
+    }
+}
+
+async fn pause_stage(State(admin): AdminState, Path(stage): Path<PipelineStage>) -> Response {
+    set_stage_paused(&admin, stage, true)
+}
+
+async fn resume_stage(State(admin): AdminState, Path(stage): Path<PipelineStage>) -> Response {
+    set_stage_paused(&admin, stage, false)
+}
+
+fn set_stage_paused(admin: &AdminServer, stage: PipelineStage, paused: bool) -> Response {
+    match &admin.pipeline {
+        Some(pipeline) => {
+            pipeline.set_paused(stage, paused);
+            Json(pipeline.status()).into_response()
+        }
+        None => disabled("control del pipeline"),
+    }
+}
+
+async fn liveness(State(admin): AdminState) -> Response {
+    match &admin.health {
+        Some(health) => probe(health.liveness()),
+        None => disabled("health check"),
+    }
+}
+
+async fn readiness(State(admin): AdminState) -> Response {
+    match &admin.health {
+        Some(health) => probe(health.readiness()),
+        None => disabled("health check"),
+    }
+}
+
+async fn statistics(State(admin): AdminState) -> Response {
+    match &admin.processor {
+        Some(processor) => Json(processor.get_statistics().await).into_response(),
+        None => disabled("estadísticas"),
+    }
+}
+
+async fn offline_devices(State(admin): AdminState) -> Response {
+    match &admin.presence {
+        Some(presence) => Json(presence.offline_devices()).into_response(),
+        None => disabled("detección de dispositivos desconectados"),
+    }
+}
+
+/// Filtro de logs de `/log-level`, con la sintaxis de `RUST_LOG`
+#[derive(Debug, Serialize, Deserialize)]
+struct LogLevelBody {
+    /// `None` en un PUT vuelve al filtro configurado al arrancar
+    level: Option<String>,
+}
+
+async fn log_level(State(admin): AdminState) -> Response {
+    match admin.log_level {
+        Some(log_level) => Json(LogLevelBody {
+            level: Some(log_level.current()),
+        })
+        .into_response(),
+        None => disabled("cambio de nivel de logs"),
+    }
+}
+
+async fn set_log_level(State(admin): AdminState, Json(body): Json<LogLevelBody>) -> Response {
+    let Some(log_level) = admin.log_level else {
+        return disabled("cambio de nivel de logs");
+    };
+    match log_level.set(body.level.as_deref()) {
+        Ok(level) => Json(LogLevelBody { level: Some(level) }).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Filtro de `/tail`
+#[derive(Debug, Deserialize)]
+struct TailQuery {
+    device_id: Option<String>,
+}
+
+async fn tail(
+    State(admin): AdminState,
+    Query(query): Query<TailQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let Some(live_tail) = &admin.live_tail else {
+        return disabled("vista en vivo");
+    };
+    let live_tail = live_tail.clone();
+    upgrade.on_upgrade(move |socket| stream_tail(socket, live_tail, query.device_id))
+}
+
+/// Envía cada evento como un mensaje de texto JSON hasta que el cliente cierre
+async fn stream_tail(mut socket: WebSocket, live_tail: Arc<LiveTail>, device_id: Option<String>) {
+    info!(
+        "🔭 Vista en vivo abierta ({})",
+        device_id.as_deref().map_or(
+            "muestra de todos los dispositivos".into(),
+            redact::device_id
+        )
+    );
+    let mut subscription = live_tail.subscribe(device_id);
+    loop {
+        let text = tokio::select! {
+            event = subscription.next() => match event {
+                Some(Ok(event)) => serde_json::to_string(&*event).unwrap_or_default(),
+                Some(Err(skipped)) => serde_json::json!({ "lagged": skipped }).to_string(),
+                None => break,
+            },
+            // El cliente solo envía el cierre; cualquier otra cosa se ignora
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if let Err(e) = socket.send(Message::Text(text)).await {
+            debug!("Vista en vivo cerrada por el cliente: {}", e);
+            break;
+        }
+    }
+    info!("🔭 Vista en vivo cerrada");
+}
+
+/// 200 si la sonda pasa y 503 si no, con el último estado en el cuerpo
+fn probe((healthy, status): (bool, HealthStatus)) -> Response {
+    let code = match healthy {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (code, Json(status)).into_response()
+}
+
+fn internal_error(error: anyhow::Error) -> Response {
+    error!("❌ Error en la API de administración: {}", error);
+    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+}
+
+fn disabled(feature: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("{} no está habilitado", feature),
+    )
+        .into_response()
+}
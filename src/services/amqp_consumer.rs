@@ -0,0 +1,573 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions, QueueBindOptions,
+    QueueDeclareOptions,
+};
+use lapin::types::FieldTable;
+use lapin::uri::{AMQPAuthority, AMQPScheme, AMQPUri, AMQPUserInfo};
+use lapin::{Channel, Connection, ConnectionProperties};
+use opentelemetry::trace::TraceContextExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::config::{AmqpConfig, BrokerConfig};
+use crate::errors::ErrorCategory;
+use crate::models::{server_ack, AckToken, DeviceMessage};
+use crate::redact;
+use crate::services::device_ack::DeviceAckPublisher;
+use crate::services::message_consumer::PartitionLag;
+use crate::services::raw_decoder::{RawDecoder, RawFrameStatistics};
+use crate::services::schema_registry::SchemaRegistryClient;
+use crate::services::{
+    Backpressure, CaptureWriter, FieldMapping, KafkaConsumerService, MessageConsumer,
+    PayloadAuthenticator, PipelineMetrics,
+};
+
+/// Máximo entre vueltas del loop de consumo aunque no lleguen mensajes, para que el
+/// watchdog distinga un loop sin tráfico de uno atascado
+const POLL_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Tiempo máximo de espera por las confirmaciones pendientes al desconectar
+const ACK_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Consumidor AMQP 0.9.1 (RabbitMQ) de la cola `BROKER_TOPIC`, con los mismos
+/// formatos de payload que Kafka.
+///
+/// Los mensajes se confirman (`basic.ack`) cuando su registro queda guardado en la
+/// BD o se descarta, no al recibirlos: si el proceso muere, el broker vuelve a
+/// entregar lo pendiente. Los payloads que no se pueden decodificar o sin una firma
+/// válida se rechazan sin reencolar, para que vayan al dead-letter exchange de la
+/// cola si tiene uno.
+pub struct AmqpConsumerService {
+    uri: AMQPUri,
+    queue: String,
+    consumer_tag: String,
+    amqp: AmqpConfig,
+    schema_registry: Option<SchemaRegistryClient>,
+    raw_decoder: Arc<RawDecoder>,
+    field_mapping: Option<Arc<FieldMapping>>,
+    backpressure: Backpressure,
+    metrics: Option<Arc<PipelineMetrics>>,
+    connection: tokio::sync::Mutex<Option<Connection>>,
+    // Canal de la sesión de consumo actual, para el lag y el health check
+    channel: Mutex<Option<Channel>>,
+    // Tareas que confirman las entregas de cada sesión de consumo
+    ack_tasks: Mutex<Vec<JoinHandle<()>>>,
+    stopping: Arc<watch::Sender<bool>>,
+    last_poll: Arc<Mutex<Option<Instant>>>,
+    capture: Option<Arc<CaptureWriter>>,
+    authenticator: Option<Arc<PayloadAuthenticator>>,
+    device_acks: Option<Arc<DeviceAckPublisher>>,
+}
+
+impl AmqpConsumerService {
+    /// Crea el consumidor; la conexión se abre al iniciar el consumo
+    pub fn new(config: &BrokerConfig, backpressure: Backpressure) -> Result<Self> {
+        let amqp = config.amqp.clone();
+        let (host, port) = match config.host.rsplit_once(':') {
+            Some((host, port)) => (
+                host.to_string(),
+                port.parse::<u16>()
+                    .with_context(|| format!("Puerto AMQP inválido en '{}'", config.host))?,
+            ),
+            None => (config.host.clone(), if amqp.tls { 5671 } else { 5672 }),
+        };
+        let uri = AMQPUri {
+            scheme: if amqp.tls {
+                AMQPScheme::AMQPS
+            } else {
+                AMQPScheme::AMQP
+            },
+            authority: AMQPAuthority {
+                userinfo: AMQPUserInfo {
+                    username: amqp.username.clone(),
+                    password: amqp.password.clone(),
+                },
+                host,
+                port,
+            },
+            vhost: amqp.vhost.clone(),
+            query: Default::default(),
+        };
+
+        let schema_registry = config
+            .schema_registry
+            .as_ref()
+            .map(SchemaRegistryClient::new)
+            .transpose()?;
+
+        info!(
+            "✅ Consumidor AMQP configurado para broker: {} (vhost {}, cola {})",
+            config.host, amqp.vhost, config.topic
+        );
+
+        Ok(Self {
+            uri,
+            queue: config.topic.clone(),
+            consumer_tag: config.group_id.clone(),
+            amqp,
+            schema_registry,
+            raw_decoder: Arc::new(RawDecoder::new(config.payload_format)),
+            field_mapping: None,
+            backpressure,
+            metrics: None,
+            connection: tokio::sync::Mutex::new(None),
+            channel: Mutex::new(None),
+            ack_tasks: Mutex::new(Vec::new()),
+            stopping: Arc::new(watch::channel(false).0),
+            last_poll: Arc::new(Mutex::new(None)),
+            capture: None,
+            authenticator: None,
+            device_acks: None,
+        })
+    }
+
+    /// Aplica las reglas de mapeo a cada mensaje antes de convertirlo a DeviceMessage
+    pub fn with_field_mapping(mut self, mapping: Arc<FieldMapping>) -> Self {
+        self.field_mapping = Some(mapping);
+        self
+    }
+
+    /// Rechaza sin reencolar las entregas sin una firma válida de su tenant
+    pub fn with_authenticator(mut self, authenticator: Arc<PayloadAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Publica la confirmación `+SACK` de cada trama Queclink recibida
+    pub fn with_device_acks(mut self, device_acks: Arc<DeviceAckPublisher>) -> Self {
+        self.device_acks = Some(device_acks);
+        self
+    }
+
+    /// Cuenta los errores de recepción y decodificación en las estadísticas del pipeline
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Guarda cada payload recibido, tal como llegó, para reproducirlo con `replay-file`
+    pub fn with_capture(mut self, capture: Arc<CaptureWriter>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Abre un canal nuevo, reconectando si la conexión anterior se perdió, y deja
+    /// la cola declarada, enlazada y con el prefetch configurado
+    async fn open_channel(&self) -> Result<Channel> {
+        let mut connection = self.connection.lock().await;
+        if !connection
+            .as_ref()
+            .is_some_and(|connection| connection.status().connected())
+        {
+            let connected =
+                Connection::connect_uri(self.uri.clone(), ConnectionProperties::default())
+                    .await
+                    .context("Error conectando al broker AMQP")?;
+            info!(
+                "🔌 Conectado al broker AMQP {}:{}",
+                self.uri.authority.host, self.uri.authority.port
+            );
+            *connection = Some(connected);
+        }
+        let channel = connection
+            .as_ref()
+            .expect("conexión AMQP abierta")
+            .create_channel()
+            .await?;
+
+        channel
+            .basic_qos(self.amqp.prefetch, BasicQosOptions::default())
+            .await?;
+        channel
+            .queue_declare(
+                &self.queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .with_context(|| format!("Error declarando la cola AMQP '{}'", self.queue))?;
+        if let Some(exchange) = &self.amqp.exchange {
+            for routing_key in &self.amqp.routing_keys {
+                channel
+                    .queue_bind(
+                        &self.queue,
+                        exchange,
+                        routing_key,
+                        QueueBindOptions::default(),
+                        FieldTable::default(),
+                    )
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Error enlazando la cola '{}' al exchange '{}' ({})",
+                            self.queue, exchange, routing_key
+                        )
+                    })?;
+            }
+        }
+        Ok(channel)
+    }
+
+    /// Confirma las entregas de un canal a medida que se sueltan sus `AckToken`. Termina
+    /// (y cierra el canal) cuando el loop de consumo y todos los tokens se soltaron.
+    fn spawn_acker(channel: Channel, mut acks: mpsc::UnboundedReceiver<u64>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(tag) = acks.recv().await {
+                if let Err(e) = channel.basic_ack(tag, BasicAckOptions::default()).await {
+                    // Sin canal no hay forma de confirmar: el broker reentregará
+                    error!("Error confirmando la entrega AMQP {}: {}", tag, e);
+                }
+            }
+            if channel.status().connected() {
+                let _ = channel.close(200, "consumo terminado").await;
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl MessageConsumer for AmqpConsumerService {
+    async fn start_consuming(&self) -> Result<mpsc::UnboundedReceiver<DeviceMessage>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let channel = self.open_channel().await?;
+        let mut consumer = channel
+            .basic_consume(
+                &self.queue,
+                &self.consumer_tag,
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .with_context(|| format!("Error consumiendo la cola AMQP '{}'", self.queue))?;
+        info!(
+            "🔌 Consumiendo la cola AMQP: {} (prefetch {})",
+            self.queue, self.amqp.prefetch
+        );
+
+        let (ack_tx, ack_rx) = mpsc::unbounded_channel();
+        self.ack_tasks
+            .lock()
+            .unwrap()
+            .push(Self::spawn_acker(channel.clone(), ack_rx));
+        *self.channel.lock().unwrap() = Some(channel.clone());
+
+        let queue = self.queue.clone();
+        let registry = self.schema_registry.clone();
+        let raw_decoder = Arc::clone(&self.raw_decoder);
+        let mapping = self.field_mapping.clone();
+        let metrics = self.metrics.clone();
+        let record_error = move |category| {
+            if let Some(metrics) = &metrics {
+                metrics.record_error(category);
+            }
+        };
+        let mut pressure = self.backpressure.subscribe();
+        let mut stopping = self.stopping.subscribe();
+        let last_poll = self.last_poll.clone();
+        let capture = self.capture.clone();
+        let authenticator = self.authenticator.clone();
+        let device_acks = self.device_acks.clone();
+
+        // Al terminar se suelta `ack_tx`: la tarea de confirmaciones cierra el canal
+        // cuando se confirma lo que siga en el pipeline
+        tokio::spawn(async move {
+            loop {
+                *last_poll.lock().unwrap() = Some(Instant::now());
+                if *stopping.borrow_and_update() {
+                    info!("⏹️ Consumo de AMQP detenido");
+                    break;
+                }
+
+                // Bajo presión no se leen entregas nuevas; el prefetch frena al broker
+                let paused = *pressure.borrow_and_update();
+
+                let delivery = tokio::select! {
+                    Ok(()) = stopping.changed() => continue,
+                    Ok(()) = pressure.changed() => continue,
+                    delivery = consumer.next(), if !paused => delivery,
+                    _ = tokio::time::sleep(POLL_HEARTBEAT) => continue,
+                };
+
+                let delivery = match delivery {
+                    Some(Ok(delivery)) => delivery,
+                    Some(Err(e)) => {
+                        record_error(ErrorCategory::KafkaError);
+                        error!(category = %ErrorCategory::KafkaError, "Error recibiendo mensaje de AMQP: {}", e);
+                        break;
+                    }
+                    None => {
+                        warn!("⚠️ El broker AMQP cerró el consumo de la cola {}", queue);
+                        break;
+                    }
+                };
+
+                let mut signed_by = None;
+                if let Some(authenticator) = &authenticator {
+                    match authenticator
+                        .verify_amqp(delivery.properties.headers().as_ref(), &delivery.data)
+                    {
+                        Ok(key_id) => signed_by = Some(key_id),
+                        Err(e) => {
+                            record_error(ErrorCategory::SignatureInvalid);
+                            warn!(
+                                category = %ErrorCategory::SignatureInvalid,
+                                "🔏 Entrega de {} rechazada: {}",
+                                queue,
+                                e
+                            );
+                            reject(&channel, delivery.delivery_tag).await;
+                            continue;
+                        }
+                    }
+                }
+
+                if let Some(capture) = &capture {
+                    capture.record(&queue, raw_decoder.format(), &delivery.data);
+                }
+
+                let span = info_span!(
+                    "consume",
+                    messaging.destination.name = %queue,
+                    messaging.rabbitmq.destination.routing_key = %delivery.routing_key,
+                    messaging.message.redelivered = delivery.redelivered,
+                );
+
+                // Sin confirmar ni rechazar, el broker vuelve a entregarla si se detiene
+                // el consumo mientras el Schema Registry no responde
+                let decoded = KafkaConsumerService::decode_retrying(
+                    registry.as_ref(),
+                    mapping.as_deref(),
+                    &raw_decoder,
+                    &delivery.data,
+                    &mut stopping,
+                    || {
+                        record_error(ErrorCategory::SchemaRegistryUnavailable);
+                        *last_poll.lock().unwrap() = Some(Instant::now());
+                    },
+                )
+                .instrument(info_span!(parent: &span, "parse"))
+                .await;
+                let Some(decoded) = decoded else {
+                    info!("⏹️ Consumo de AMQP detenido");
+                    break;
+                };
+                match decoded {
+                    Ok(mut device_msg) => {
+                        // Se confirma al equipo solo cuando el mensaje llega al procesador
+                        let ack = device_acks
+                            .as_ref()
+                            .and_then(|_| server_ack(&device_msg.raw));
+                        device_msg.source = Some(queue.clone());
+                        assign_signer(&mut device_msg, signed_by);
+                        device_msg.trace_context =
+                            Some(span.context().span().span_context().clone());
+                        device_msg.ack = Some(AckToken::new(delivery.delivery_tag, ack_tx.clone()));
+                        debug!(
+                            "✅ Mensaje AMQP parseado para dispositivo: {}",
+                            redact::device_id(&device_msg.data.device_id)
+                        );
+
+                        if let Err(e) = tx.send(device_msg) {
+                            error!("Error enviando mensaje al canal: {}", e);
+                            break;
+                        }
+                        if let (Some(acks), Some(ack)) = (&device_acks, &ack) {
+                            acks.acknowledge(ack);
+                        }
+                    }
+                    Err(e) => {
+                        // El heartbeat ya quedó confirmado al equipo; la entrega se
+                        // confirma al broker al soltar el token
+                        if device_acks
+                            .as_ref()
+                            .is_some_and(|acks| acks.acknowledge_heartbeat(&delivery.data))
+                        {
+                            drop(AckToken::new(delivery.delivery_tag, ack_tx.clone()));
+                            continue;
+                        }
+                        record_error(ErrorCategory::ParseError);
+                        error!(category = %ErrorCategory::ParseError, "❌ {:#}", e);
+                        reject(&channel, delivery.delivery_tag).await;
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    fn raw_frames(&self) -> RawFrameStatistics {
+        self.raw_decoder.statistics()
+    }
+
+    async fn lag(&self) -> Result<Vec<PartitionLag>> {
+        let channel = self
+            .channel
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| anyhow!("Consumo AMQP no iniciado"))?;
+
+        // Declaración pasiva: solo consulta la cola. Cuenta los mensajes listos para
+        // entregar, no los entregados sin confirmar.
+        let queue = channel
+            .queue_declare(
+                &self.queue,
+                QueueDeclareOptions {
+                    passive: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+        let ready = i64::from(queue.message_count());
+
+        Ok(vec![PartitionLag {
+            topic: self.queue.clone(),
+            partition: 0,
+            committed_offset: None,
+            high_watermark: ready,
+            lag: ready,
+        }])
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        let connected = self
+            .connection
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|connection| connection.status().connected());
+        let channel_open = self
+            .channel
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|channel| channel.status().connected());
+        if !(connected && channel_open) {
+            error!("AMQP health check failed: conexión o canal cerrados");
+        }
+        Ok(connected && channel_open)
+    }
+
+    fn since_last_poll(&self) -> Option<Duration> {
+        self.last_poll.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    async fn stop_consuming(&self) -> Result<()> {
+        self.stopping.send_replace(true);
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        info!("🔌 Desconectando de AMQP...");
+        self.stopping.send_replace(true);
+
+        // Esperar las confirmaciones de lo ya guardado; lo que quede sin confirmar
+        // se reentrega al cerrar la conexión
+        let ack_tasks = std::mem::take(&mut *self.ack_tasks.lock().unwrap());
+        for task in ack_tasks {
+            if tokio::time::timeout(ACK_DRAIN_TIMEOUT, task).await.is_err() {
+                warn!(
+                    "⚠️ Confirmaciones AMQP pendientes al desconectar; el broker las reentregará"
+                );
+            }
+        }
+
+        if let Some(connection) = self.connection.lock().await.take() {
+            if connection.status().connected() {
+                connection.close(200, "shutdown").await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rechaza una entrega sin reencolarla, para que vaya al dead-letter exchange de la
+/// cola si tiene uno
+async fn reject(channel: &Channel, delivery_tag: u64) {
+    let rejected = channel
+        .basic_nack(
+            delivery_tag,
+            BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            },
+        )
+        .await;
+    if let Err(e) = rejected {
+        error!("Error rechazando la entrega AMQP: {}", e);
+    }
+}
+
+/// Las colas AMQP no tienen tenant asignado (`TENANT_TOPICS` es solo de Kafka): con
+/// la verificación de firmas, el tenant del mensaje es el de la clave que lo firmó.
+/// Sin firma queda sin tenant; nunca se toma uno declarado por quien publica.
+pub(crate) fn assign_signer(device_msg: &mut DeviceMessage, signed_by: Option<String>) {
+    device_msg.tenant = signed_by.clone();
+    device_msg.signed_by = signed_by;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PayloadAuthConfig;
+    use hmac::{Hmac, Mac};
+    use lapin::types::AMQPValue;
+    use sha2::Sha256;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn tenant_comes_from_the_signing_key() {
+        let authenticator = PayloadAuthenticator::new(&PayloadAuthConfig {
+            keys: BTreeMap::from([
+                ("acme".to_string(), "secreto-acme".to_string()),
+                ("globex".to_string(), "secreto-globex".to_string()),
+            ]),
+            signature_header: "x-siscom-signature".to_string(),
+            key_id_header: "x-siscom-key-id".to_string(),
+        });
+
+        // acme firma con su clave un mensaje que se declara de globex
+        let mut payload: serde_json::Value = serde_json::from_slice(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        payload["tenant"] = "globex".into();
+        let payload = serde_json::to_vec(&payload).unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secreto-acme").unwrap();
+        mac.update(&payload);
+        let signature = hex::encode(mac.finalize().into_bytes());
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "x-siscom-signature".into(),
+            AMQPValue::LongString(signature.into()),
+        );
+        headers.insert(
+            "x-siscom-key-id".into(),
+            AMQPValue::LongString("acme".into()),
+        );
+
+        let signed_by = authenticator.verify_amqp(Some(&headers), &payload).unwrap();
+        assert_eq!(signed_by, "acme");
+        let mut message = DeviceMessage::from_json(&payload).unwrap();
+        assign_signer(&mut message, Some(signed_by));
+        assert_eq!(message.tenant.as_deref(), Some("acme"));
+        assert_eq!(message.signed_by.as_deref(), Some("acme"));
+
+        // Sin verificación de firmas queda sin tenant
+        assign_signer(&mut message, None);
+        assert_eq!(message.tenant, None);
+    }
+}
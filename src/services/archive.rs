@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::config::ArchiveConfig;
+use crate::models::DeviceMessage;
+
+/// Esquema de los archivos: campos para filtrar sin abrir el JSON y el mensaje
+/// completo serializado, suficiente para volver a procesarlo
+const PARQUET_SCHEMA: &str = "
+message device_message {
+    REQUIRED BYTE_ARRAY uuid (UTF8);
+    REQUIRED BYTE_ARRAY device_id (UTF8);
+    REQUIRED BYTE_ARRAY manufacturer (UTF8);
+    REQUIRED INT64 received_epoch;
+    REQUIRED INT64 decoded_epoch;
+    REQUIRED BYTE_ARRAY client_ip (UTF8);
+    REQUIRED INT32 client_port;
+    REQUIRED BYTE_ARRAY raw (UTF8);
+    REQUIRED BYTE_ARRAY message (UTF8);
+}
+";
+
+/// Fecha y fabricante de un archivo (`manufacturer=.../date=.../`)
+type Partition = (NaiveDate, &'static str);
+
+/// Archivo de mensajes crudos: agrupa los `DeviceMessage` por fecha de recepción y
+/// fabricante, los escribe como Parquet en un directorio local y los sube a un bucket
+/// compatible con S3. Los archivos solo se borran del spool después de subirlos, así
+/// que una caída del almacenamiento o un reinicio no pierden datos.
+pub struct ArchiveService {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    spool_dir: PathBuf,
+    max_records: usize,
+    pending: Mutex<HashMap<Partition, Vec<DeviceMessage>>>,
+}
+
+impl ArchiveService {
+    pub fn new(config: &ArchiveConfig) -> Result<Self> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(&config.bucket)
+            .with_region(&config.region);
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(access_key_id) = &config.access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = &config.secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+
+        std::fs::create_dir_all(&config.spool_dir)?;
+
+        info!(
+            "🗄️ Archivo de mensajes crudos en s3://{}/{} (spool: {})",
+            config.bucket, config.prefix, config.spool_dir
+        );
+
+        Ok(Self {
+            store: Arc::new(builder.build()?),
+            prefix: config.prefix.trim_matches('/').to_string(),
+            spool_dir: PathBuf::from(&config.spool_dir),
+            max_records: config.max_records,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Agrega un mensaje al archivo de su fecha y fabricante; el archivo se escribe
+    /// en el spool al llegar a `max_records`
+    pub async fn push(&self, message: &DeviceMessage) {
+        let partition = (
+            DateTime::<Utc>::from_timestamp(message.metadata.received_epoch, 0)
+                .unwrap_or_else(Utc::now)
+                .date_naive(),
+            message.get_manufacturer().as_str(),
+        );
+
+        let full = {
+            let mut pending = self.pending.lock().await;
+            let messages = pending.entry(partition).or_default();
+            messages.push(message.clone());
+            if messages.len() >= self.max_records {
+                pending.remove(&partition)
+            } else {
+                None
+            }
+        };
+
+        if let Some(messages) = full {
+            if let Err(e) = self.spool(partition, messages).await {
+                error!("❌ Error escribiendo archivo Parquet en el spool: {}", e);
+            }
+        }
+    }
+
+    /// Escribe en el spool todos los mensajes pendientes. Devuelve la cantidad de archivos.
+    pub async fn flush(&self) -> Result<usize> {
+        let pending = std::mem::take(&mut *self.pending.lock().await);
+        let files = pending.len();
+        for (partition, messages) in pending {
+            self.spool(partition, messages).await?;
+        }
+        Ok(files)
+    }
+
+    /// Sube los archivos del spool y borra los que se subieron. Los que fallan
+    /// quedan para el siguiente intento. Devuelve la cantidad de archivos subidos.
+    pub async fn upload_spooled(&self) -> Result<usize> {
+        let spool_dir = self.spool_dir.clone();
+        let files = tokio::task::spawn_blocking(move || spooled_files(&spool_dir)).await??;
+
+        let mut uploaded = 0;
+        for file in files {
+            let relative = file
+                .strip_prefix(&self.spool_dir)?
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            let key = ObjectPath::from(format!("{}/{}", self.prefix, relative));
+
+            let bytes = tokio::fs::read(&file).await?;
+            match self.store.put(&key, PutPayload::from(bytes)).await {
+                Ok(_) => {
+                    tokio::fs::remove_file(&file).await?;
+                    debug!("🗄️ {} subido", key);
+                    uploaded += 1;
+                }
+                Err(e) => {
+                    warn!("⚠️ No se pudo subir {} (se reintentará): {}", key, e);
+                    break;
+                }
+            }
+        }
+
+        Ok(uploaded)
+    }
+
+    /// Inicia la tarea que cierra los archivos abiertos y sube el spool cada `interval`
+    pub fn start(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let archive = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = archive.flush().await {
+                    error!("❌ Error cerrando archivos Parquet: {}", e);
+                }
+                match archive.upload_spooled().await {
+                    Ok(0) => {}
+                    Ok(uploaded) => info!("🗄️ {} archivos subidos al archivo S3", uploaded),
+                    Err(e) => error!("❌ Error subiendo el spool del archivo: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Escribe un archivo Parquet en `{spool}/manufacturer=.../date=.../`. Se escribe con
+    /// extensión `.tmp` y se renombra al terminar para no subir archivos incompletos.
+    async fn spool(&self, partition: Partition, messages: Vec<DeviceMessage>) -> Result<()> {
+        let (date, manufacturer) = partition;
+        let dir = self
+            .spool_dir
+            .join(format!("manufacturer={}", manufacturer))
+            .join(format!("date={}", date));
+        let name = format!(
+            "{}-{}.parquet",
+            Utc::now().format("%H%M%S"),
+            uuid::Uuid::new_v4().simple()
+        );
+        let count = messages.len();
+
+        let path = tokio::task::spawn_blocking(move || -> Result<PathBuf> {
+            std::fs::create_dir_all(&dir)?;
+            let tmp = dir.join(format!("{}.tmp", name));
+            write_parquet(&tmp, &messages)?;
+            let path = dir.join(name);
+            std::fs::rename(&tmp, &path)?;
+            Ok(path)
+        })
+        .await??;
+
+        debug!("🗄️ {} mensajes archivados en {}", count, path.display());
+        Ok(())
+    }
+}
+
+fn write_parquet(path: &Path, messages: &[DeviceMessage]) -> Result<()> {
+    let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+    let properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let file = std::fs::File::create(path)?;
+    let mut writer = SerializedFileWriter::new(file, schema, properties)?;
+
+    let serialized = messages
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut row_group = writer.next_row_group()?;
+    write_strings(&mut row_group, messages.iter().map(|m| m.uuid.as_str()))?;
+    write_strings(
+        &mut row_group,
+        messages.iter().map(|m| m.data.device_id.as_str()),
+    )?;
+    write_strings(
+        &mut row_group,
+        messages.iter().map(|m| m.get_manufacturer().as_str()),
+    )?;
+    write_column::<Int64Type, _>(
+        &mut row_group,
+        &messages
+            .iter()
+            .map(|m| m.metadata.received_epoch)
+            .collect::<Vec<_>>(),
+    )?;
+    write_column::<Int64Type, _>(
+        &mut row_group,
+        &messages
+            .iter()
+            .map(|m| m.metadata.decoded_epoch)
+            .collect::<Vec<_>>(),
+    )?;
+    write_strings(
+        &mut row_group,
+        messages.iter().map(|m| m.metadata.client_ip.as_str()),
+    )?;
+    write_column::<Int32Type, _>(
+        &mut row_group,
+        &messages
+            .iter()
+            .map(|m| m.metadata.client_port)
+            .collect::<Vec<_>>(),
+    )?;
+    write_strings(&mut row_group, messages.iter().map(|m| m.raw.as_str()))?;
+    write_strings(&mut row_group, serialized.iter().map(String::as_str))?;
+    row_group.close()?;
+
+    writer.close()?;
+    Ok(())
+}
+
+fn write_strings<'a, W: std::io::Write + Send>(
+    row_group: &mut SerializedRowGroupWriter<'_, W>,
+    values: impl Iterator<Item = &'a str>,
+) -> Result<()> {
+    let values: Vec<ByteArray> = values.map(ByteArray::from).collect();
+    write_column::<ByteArrayType, _>(row_group, &values)
+}
+
+fn write_column<T: DataType, W: std::io::Write + Send>(
+    row_group: &mut SerializedRowGroupWriter<'_, W>,
+    values: &[T::T],
+) -> Result<()> {
+    let mut column = row_group
+        .next_column()?
+        .ok_or_else(|| anyhow!("El esquema Parquet tiene menos columnas de las escritas"))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()?;
+    Ok(())
+}
+
+/// Archivos `.parquet` y capturas `.ndjson` terminados dentro del spool
+fn spooled_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(spooled_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "parquet" || ext == "ndjson")
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,398 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use object_store::aws::{AmazonS3Builder, AwsAuthorizer, AwsCredentialProvider};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::{AwsSinkConfig, RetryConfig};
+use crate::models::NormalizedPosition;
+use crate::services::message_sink::MessageSink;
+
+/// Tiempo máximo de cada petición a SNS o SQS
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Salida a AWS para despliegues serverless: las notificaciones (geocercas,
+/// viajes, conducción, conductores, conectividad) se publican en un tópico SNS y
+/// las posiciones normalizadas se envían a una cola SQS en lotes de hasta 10.
+///
+/// Las peticiones se firman con SigV4 usando la cadena de credenciales estándar
+/// (variables `AWS_*`, web identity, ECS, metadatos de EC2). Cada destino tiene su
+/// cola y su tarea de envío con reintentos, así AWS caído nunca frena el pipeline:
+/// con la cola llena las notificaciones nuevas se descartan, y las posiciones
+/// esperan en la cola de la salida del `SinkPipeline`.
+pub struct AwsSink {
+    sqs: Option<mpsc::Sender<SqsMessage>>,
+    sns: Option<mpsc::Sender<SnsMessage>>,
+    dropped: AtomicU64,
+}
+
+struct SqsMessage {
+    id: String,
+    group: String,
+    manufacturer: &'static str,
+    body: String,
+}
+
+struct SnsMessage {
+    event: String,
+    group: String,
+    body: String,
+}
+
+/// Cliente HTTP firmado compartido por SNS y SQS
+#[derive(Clone)]
+struct AwsClient {
+    http: reqwest::Client,
+    credentials: AwsCredentialProvider,
+    region: String,
+    endpoint: Option<String>,
+    retry: RetryConfig,
+}
+
+impl AwsSink {
+    pub fn new(config: &AwsSinkConfig) -> Result<Self> {
+        // object_store resuelve la cadena de credenciales de AWS; el bucket no se usa
+        let credentials = AmazonS3Builder::from_env()
+            .with_bucket_name("unused")
+            .with_region(&config.region)
+            .build()?
+            .credentials()
+            .clone();
+        let client = AwsClient {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            credentials,
+            region: config.region.clone(),
+            endpoint: config
+                .endpoint
+                .as_ref()
+                .map(|endpoint| endpoint.trim_end_matches('/').to_string()),
+            retry: config.retry.clone(),
+        };
+
+        let sqs = config.sqs_queue_url.as_ref().map(|queue_url| {
+            let (queue, messages) = mpsc::channel(config.queue_size);
+            info!(
+                "☁️ Posiciones enviadas a SQS {} (lotes de {})",
+                queue_url, config.sqs_batch_size
+            );
+            tokio::spawn(send_positions(
+                client.clone(),
+                queue_url.clone(),
+                config.sqs_batch_size,
+                Duration::from_millis(config.sqs_flush_interval_ms),
+                messages,
+            ));
+            queue
+        });
+        let sns = config.sns_topic_arn.as_ref().map(|topic_arn| {
+            let (queue, messages) = mpsc::channel(config.queue_size);
+            info!("☁️ Notificaciones publicadas en SNS {}", topic_arn);
+            tokio::spawn(publish_notifications(
+                client.clone(),
+                topic_arn.clone(),
+                messages,
+            ));
+            queue
+        });
+
+        Ok(Self {
+            sqs,
+            sns,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn has_sqs(&self) -> bool {
+        self.sqs.is_some()
+    }
+
+    pub fn has_sns(&self) -> bool {
+        self.sns.is_some()
+    }
+
+    /// Encola la notificación para SNS; `key` es el device_id, que agrupa los
+    /// mensajes en tópicos FIFO
+    pub fn notify(&self, key: &str, payload: &Value) {
+        let Some(sns) = &self.sns else {
+            return;
+        };
+        let message = SnsMessage {
+            event: payload["event"].as_str().unwrap_or_default().to_string(),
+            group: key.to_string(),
+            body: payload.to_string(),
+        };
+        if sns.try_send(message).is_err() {
+            self.record_drop("SNS");
+        }
+    }
+
+    fn record_drop(&self, target: &str) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        if dropped % 1000 == 1 {
+            warn!(
+                "⚠️ Cola de {} llena, {} mensajes descartados en total",
+                target, dropped
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl MessageSink for AwsSink {
+    fn name(&self) -> &str {
+        "sqs"
+    }
+
+    /// Encola las posiciones para SQS; espera solo si la cola está llena
+    async fn send(&self, positions: &[NormalizedPosition]) -> Result<()> {
+        let Some(sqs) = &self.sqs else {
+            return Ok(());
+        };
+        for position in positions {
+            let body = match serde_json::to_string(position) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("⚠️ Posición {} no serializable: {}", position.uuid, e);
+                    continue;
+                }
+            };
+            let message = SqsMessage {
+                id: position.uuid.clone(),
+                group: position.device_id.clone(),
+                manufacturer: position.manufacturer.as_str(),
+                body,
+            };
+            sqs.send(message)
+                .await
+                .map_err(|_| anyhow!("la tarea de envío a SQS terminó"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Junta las posiciones en lotes de SendMessageBatch y los envía en orden
+async fn send_positions(
+    client: AwsClient,
+    queue_url: String,
+    batch_size: usize,
+    flush_interval: Duration,
+    mut messages: mpsc::Receiver<SqsMessage>,
+) {
+    let fifo = queue_url.ends_with(".fifo");
+    let mut batch = Vec::with_capacity(batch_size);
+    loop {
+        let Some(first) = messages.recv().await else {
+            return;
+        };
+        batch.push(first);
+        let deadline = tokio::time::Instant::now() + flush_interval;
+        while batch.len() < batch_size {
+            match tokio::time::timeout_at(deadline, messages.recv()).await {
+                Ok(Some(message)) => batch.push(message),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let count = batch.len();
+        match client
+            .send_message_batch(&queue_url, fifo, &mut batch)
+            .await
+        {
+            Ok(()) => debug!("☁️ {} posiciones enviadas a SQS", count),
+            Err(e) => warn!(
+                "❌ {} de {} posiciones descartadas para SQS: {:#}",
+                batch.len(),
+                count,
+                e
+            ),
+        }
+        batch.clear();
+    }
+}
+
+/// Publica las notificaciones en SNS de a una, en orden
+async fn publish_notifications(
+    client: AwsClient,
+    topic_arn: String,
+    mut messages: mpsc::Receiver<SnsMessage>,
+) {
+    let fifo = topic_arn.ends_with(".fifo");
+    while let Some(message) = messages.recv().await {
+        match client.publish(&topic_arn, fifo, &message).await {
+            Ok(()) => debug!("☁️ Evento {} publicado en SNS", message.event),
+            Err(e) => warn!("❌ Evento {} descartado para SNS: {:#}", message.event, e),
+        }
+    }
+}
+
+impl AwsClient {
+    fn url(&self, service: &str) -> String {
+        match &self.endpoint {
+            Some(endpoint) => format!("{}/", endpoint),
+            None => format!("https://{}.{}.amazonaws.com/", service, self.region),
+        }
+    }
+
+    /// SendMessageBatch (protocolo JSON). Reintenta el lote completo ante errores
+    /// de red, throttling o 5xx y las entradas fallidas que no son culpa del
+    /// emisor; al terminar `batch` conserva solo las que no se enviaron.
+    async fn send_message_batch(
+        &self,
+        queue_url: &str,
+        fifo: bool,
+        batch: &mut Vec<SqsMessage>,
+    ) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let entries: Vec<Value> = batch
+                .iter()
+                .enumerate()
+                .map(|(index, message)| {
+                    let mut entry = json!({
+                        "Id": index.to_string(),
+                        "MessageBody": message.body,
+                        "MessageAttributes": {
+                            "manufacturer": {
+                                "DataType": "String",
+                                "StringValue": message.manufacturer,
+                            },
+                            "device_id": { "DataType": "String", "StringValue": message.group },
+                        },
+                    });
+                    if fifo {
+                        entry["MessageGroupId"] = json!(message.group);
+                        entry["MessageDeduplicationId"] = json!(message.id);
+                    }
+                    entry
+                })
+                .collect();
+            let body = json!({ "QueueUrl": queue_url, "Entries": entries }).to_string();
+            let request = self
+                .http
+                .post(self.url("sqs"))
+                .header(reqwest::header::CONTENT_TYPE, "application/x-amz-json-1.0")
+                .header("X-Amz-Target", "AmazonSQS.SendMessageBatch")
+                .body(body)
+                .build()?;
+
+            let (error, retryable) = match self.send(request, "sqs").await {
+                Ok(response) => {
+                    let failed = response["Failed"].as_array().cloned().unwrap_or_default();
+                    if failed.is_empty() {
+                        batch.clear();
+                        return Ok(());
+                    }
+                    // Los Id son la posición en el lote
+                    let retry: Vec<usize> = failed
+                        .iter()
+                        .filter(|entry| !entry["SenderFault"].as_bool().unwrap_or(false))
+                        .filter_map(|entry| entry["Id"].as_str()?.parse().ok())
+                        .collect();
+                    let error = anyhow!(
+                        "{}",
+                        failed[0]["Message"].as_str().unwrap_or("entrada rechazada")
+                    );
+                    let mut index = 0;
+                    batch.retain(|_| {
+                        index += 1;
+                        retry.contains(&(index - 1))
+                    });
+                    (error, !retry.is_empty())
+                }
+                Err((e, retryable)) => (e, retryable),
+            };
+
+            if !retryable || attempt >= self.retry.max_attempts {
+                return Err(error);
+            }
+            let delay = self.retry.delay(attempt);
+            warn!(
+                "⚠️ Error enviando a SQS (intento {}/{}), reintentando en {:?}: {:#}",
+                attempt, self.retry.max_attempts, delay, error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Publish (protocolo query), con el evento como atributo para filtrar suscripciones
+    async fn publish(&self, topic_arn: &str, fifo: bool, message: &SnsMessage) -> Result<()> {
+        let mut form = vec![
+            ("Action", "Publish"),
+            ("Version", "2010-03-31"),
+            ("TopicArn", topic_arn),
+            ("Message", message.body.as_str()),
+        ];
+        if !message.event.is_empty() {
+            form.extend([
+                ("MessageAttributes.entry.1.Name", "event"),
+                ("MessageAttributes.entry.1.Value.DataType", "String"),
+                (
+                    "MessageAttributes.entry.1.Value.StringValue",
+                    message.event.as_str(),
+                ),
+            ]);
+        }
+        if fifo {
+            form.push(("MessageGroupId", message.group.as_str()));
+        }
+
+        let mut attempt = 1;
+        loop {
+            let request = self.http.post(self.url("sns")).form(&form).build()?;
+            match self.send(request, "sns").await {
+                Ok(_) => return Ok(()),
+                Err((e, retryable)) if retryable && attempt < self.retry.max_attempts => {
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "⚠️ Error publicando en SNS (intento {}/{}), reintentando en {:?}: {:#}",
+                        attempt, self.retry.max_attempts, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err((e, _)) => return Err(e),
+            }
+        }
+    }
+
+    /// Firma y envía la petición. En el error indica si vale la pena reintentar:
+    /// errores de red, throttling y 5xx sí; el resto de 4xx no.
+    async fn send(
+        &self,
+        mut request: reqwest::Request,
+        service: &str,
+    ) -> std::result::Result<Value, (anyhow::Error, bool)> {
+        let credential = self
+            .credentials
+            .get_credential()
+            .await
+            .map_err(|e| (anyhow!("sin credenciales de AWS: {}", e), true))?;
+        AwsAuthorizer::new(&credential, service, &self.region).authorize(&mut request, None);
+
+        let response = self
+            .http
+            .execute(request)
+            .await
+            .map_err(|e| (e.into(), true))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if status.is_success() {
+            // SQS responde JSON; SNS responde XML, que no se usa
+            return Ok(serde_json::from_str(&text).unwrap_or(Value::Null));
+        }
+        let retryable = status.is_server_error()
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || text.contains("Throttl")
+            || text.contains("RequestThrottled");
+        Err((
+            anyhow!("{} respondió {}: {}", service, status, text.trim()),
+            retryable,
+        ))
+    }
+}
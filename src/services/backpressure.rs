@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Señal de presión compartida entre el procesador y el consumidor.
+///
+/// El procesador reporta la profundidad de su cola y el consumidor pausa la
+/// lectura cuando se supera el high-watermark, reanudándola al bajar del
+/// low-watermark (la mitad) para evitar oscilaciones. Además el consumo puede
+/// bloquearse explícitamente, p. ej. mientras la base de datos no responde,
+/// retenerse a pedido de un operador o frenarse al superar el límite de memoria.
+#[derive(Clone)]
+pub struct Backpressure {
+    state: Arc<watch::Sender<bool>>,
+    blocked: Arc<AtomicBool>,
+    held: Arc<AtomicBool>,
+    memory_limited: Arc<AtomicBool>,
+    high_watermark: usize,
+    low_watermark: usize,
+}
+
+impl Backpressure {
+    pub fn new(high_watermark: usize) -> Self {
+        let (state, _) = watch::channel(false);
+        Self {
+            state: Arc::new(state),
+            blocked: Arc::new(AtomicBool::new(false)),
+            held: Arc::new(AtomicBool::new(false)),
+            memory_limited: Arc::new(AtomicBool::new(false)),
+            high_watermark,
+            low_watermark: high_watermark / 2,
+        }
+    }
+
+    /// Actualiza el estado de presión según la profundidad actual de la cola
+    pub fn update(&self, depth: usize) {
+        if self.forced() {
+            return;
+        }
+
+        let under_pressure = *self.state.borrow();
+
+        if !under_pressure && depth >= self.high_watermark {
+            warn!(
+                "🚦 Cola de procesamiento en {} mensajes (límite {}), pausando consumo",
+                depth, self.high_watermark
+            );
+            self.state.send_replace(true);
+        } else if under_pressure && depth <= self.low_watermark {
+            info!(
+                "🟢 Cola de procesamiento en {} mensajes, reanudando consumo",
+                depth
+            );
+            self.state.send_replace(false);
+        }
+    }
+
+    /// Bloquea o desbloquea el consumo independientemente de la profundidad de la cola.
+    /// Al desbloquear, la siguiente llamada a `update` vuelve a evaluar la cola.
+    pub fn set_blocked(&self, blocked: bool) {
+        if self.blocked.swap(blocked, Ordering::Relaxed) != blocked {
+            self.state.send_replace(self.forced());
+        }
+    }
+
+    /// Retiene el consumo hasta que un operador lo libere, independientemente del
+    /// circuito de BD y de la profundidad de la cola
+    pub fn set_held(&self, held: bool) {
+        if self.held.swap(held, Ordering::Relaxed) != held {
+            self.state.send_replace(self.forced());
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::Relaxed)
+    }
+
+    /// Frena el consumo mientras la memoria estimada del pipeline supera el límite
+    pub fn set_memory_limited(&self, limited: bool) {
+        if self.memory_limited.swap(limited, Ordering::Relaxed) != limited {
+            self.state.send_replace(self.forced());
+        }
+    }
+
+    pub fn is_memory_limited(&self) -> bool {
+        self.memory_limited.load(Ordering::Relaxed)
+    }
+
+    /// Pausa impuesta por el circuito, un operador o la memoria, sin importar la cola
+    fn forced(&self) -> bool {
+        self.blocked.load(Ordering::Relaxed) || self.is_held() || self.is_memory_limited()
+    }
+
+    /// Si el consumo debe estar pausado ahora
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow()
+    }
+
+    /// Receptor para observar los cambios de presión
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.state.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pauses_at_the_high_watermark_and_resumes_at_half() {
+        let backpressure = Backpressure::new(100);
+
+        backpressure.update(99);
+        assert!(!backpressure.is_paused());
+        backpressure.update(100);
+        assert!(backpressure.is_paused());
+
+        // Entre ambos límites se mantiene el estado, para no oscilar
+        backpressure.update(51);
+        assert!(backpressure.is_paused());
+        backpressure.update(50);
+        assert!(!backpressure.is_paused());
+        backpressure.update(99);
+        assert!(!backpressure.is_paused());
+    }
+
+    #[test]
+    fn forced_pauses_ignore_the_queue_depth() {
+        let backpressure = Backpressure::new(100);
+
+        backpressure.set_blocked(true);
+        backpressure.update(0);
+        assert!(backpressure.is_paused());
+
+        // Se mantiene mientras quede alguna causa
+        backpressure.set_held(true);
+        backpressure.set_blocked(false);
+        assert!(backpressure.is_paused());
+        backpressure.set_memory_limited(true);
+        backpressure.set_held(false);
+        assert!(backpressure.is_paused());
+
+        // Sin causas vuelve a mandar la cola
+        backpressure.set_memory_limited(false);
+        assert!(!backpressure.is_paused());
+        backpressure.update(100);
+        assert!(backpressure.is_paused());
+    }
+
+    #[test]
+    fn subscribers_see_each_change() {
+        let backpressure = Backpressure::new(10);
+        let mut receiver = backpressure.subscribe();
+
+        backpressure.update(10);
+        assert!(receiver.has_changed().unwrap());
+        assert!(*receiver.borrow_and_update());
+
+        // Repetir el mismo estado no notifica
+        backpressure.update(20);
+        assert!(!receiver.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::config::AdaptiveBatchConfig;
+
+/// Cada cuánto se recalcula el intervalo de flush a partir del ritmo de ingreso
+const ADJUST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Ajusta el tamaño de lote y el intervalo de flush de los carriles según la carga.
+///
+/// El tamaño de lote sigue un esquema AIMD: cada lote completo escrito por debajo de
+/// la latencia objetivo lo aumenta en `increase_step`, y cada escritura más lenta lo
+/// reduce a la mitad. El intervalo de flush es el tiempo que tarda en llenarse un lote
+/// al ritmo de ingreso actual: largo de noche (menos escrituras pequeñas) y corto en
+/// hora pico. Ambos se mantienen entre los límites configurados.
+pub struct BatchController {
+    config: AdaptiveBatchConfig,
+    batch_size: AtomicUsize,
+    flush_interval_ms: AtomicU64,
+    received: AtomicU64,
+}
+
+impl BatchController {
+    pub fn new(config: AdaptiveBatchConfig, initial_batch_size: usize) -> Self {
+        let batch_size = initial_batch_size.clamp(config.min_batch_size, config.max_batch_size);
+        info!(
+            "📐 Lotes adaptativos: {}-{} mensajes, flush {}-{}ms, latencia objetivo {}ms",
+            config.min_batch_size,
+            config.max_batch_size,
+            config.min_flush_interval_ms,
+            config.max_flush_interval_ms,
+            config.target_latency_ms
+        );
+        Self {
+            flush_interval_ms: AtomicU64::new(config.max_flush_interval_ms),
+            batch_size: AtomicUsize::new(batch_size),
+            received: AtomicU64::new(0),
+            config,
+        }
+    }
+
+    /// Tamaño de lote vigente
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.load(Ordering::Relaxed)
+    }
+
+    /// Intervalo de flush vigente para los lotes incompletos
+    pub fn flush_interval(&self) -> Duration {
+        Duration::from_millis(self.flush_interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Cuenta un mensaje recibido por cualquier carril
+    pub fn record_message(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra la escritura de un lote completo de `size` mensajes
+    pub fn record_write(&self, size: usize, latency: Duration) {
+        let target = Duration::from_millis(self.config.target_latency_ms);
+        let current = self.batch_size();
+        let next = if latency > target {
+            (current / 2).max(self.config.min_batch_size)
+        } else if size >= current {
+            (current + self.config.increase_step).min(self.config.max_batch_size)
+        } else {
+            current
+        };
+
+        if next != current {
+            self.batch_size.store(next, Ordering::Relaxed);
+            debug!(
+                "📐 Tamaño de lote {} -> {} (escritura de {} en {:?})",
+                current, next, size, latency
+            );
+        }
+    }
+
+    /// Recalcula periódicamente el intervalo de flush a partir del ritmo de ingreso
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let controller = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ADJUST_INTERVAL);
+            let mut last = Instant::now();
+            loop {
+                interval.tick().await;
+                let elapsed = last.elapsed();
+                last = Instant::now();
+                let received = controller.received.swap(0, Ordering::Relaxed);
+                controller.adjust_flush_interval(received, elapsed);
+            }
+        })
+    }
+
+    fn adjust_flush_interval(&self, received: u64, elapsed: Duration) {
+        let rate = received as f64 / elapsed.as_secs_f64().max(0.001);
+        let fill_ms = if rate > 0.0 {
+            (self.batch_size() as f64 / rate * 1000.0) as u64
+        } else {
+            self.config.max_flush_interval_ms
+        };
+        let interval_ms = fill_ms.clamp(
+            self.config.min_flush_interval_ms,
+            self.config.max_flush_interval_ms,
+        );
+        self.flush_interval_ms.store(interval_ms, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(initial_batch_size: usize) -> BatchController {
+        BatchController::new(
+            AdaptiveBatchConfig {
+                min_batch_size: 50,
+                max_batch_size: 400,
+                min_flush_interval_ms: 100,
+                max_flush_interval_ms: 2_000,
+                target_latency_ms: 200,
+                increase_step: 100,
+            },
+            initial_batch_size,
+        )
+    }
+
+    const FAST: Duration = Duration::from_millis(50);
+    const SLOW: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn initial_size_is_clamped() {
+        assert_eq!(controller(10).batch_size(), 50);
+        assert_eq!(controller(1_000).batch_size(), 400);
+        assert_eq!(controller(120).batch_size(), 120);
+    }
+
+    #[test]
+    fn fast_full_batches_grow_up_to_the_maximum() {
+        let controller = controller(100);
+        controller.record_write(100, FAST);
+        assert_eq!(controller.batch_size(), 200);
+        controller.record_write(200, FAST);
+        controller.record_write(300, FAST);
+        assert_eq!(controller.batch_size(), 400);
+        controller.record_write(400, FAST);
+        assert_eq!(controller.batch_size(), 400);
+
+        // Un lote incompleto no dice nada de la capacidad
+        let controller = self::controller(100);
+        controller.record_write(60, FAST);
+        assert_eq!(controller.batch_size(), 100);
+    }
+
+    #[test]
+    fn slow_writes_halve_down_to_the_minimum() {
+        let controller = controller(400);
+        controller.record_write(400, SLOW);
+        assert_eq!(controller.batch_size(), 200);
+        // También un lote incompleto lento reduce el tamaño
+        controller.record_write(10, SLOW);
+        assert_eq!(controller.batch_size(), 100);
+        controller.record_write(100, SLOW);
+        controller.record_write(50, SLOW);
+        assert_eq!(controller.batch_size(), 50);
+
+        // Justo en la latencia objetivo se considera rápida
+        controller.record_write(50, Duration::from_millis(200));
+        assert_eq!(controller.batch_size(), 150);
+    }
+
+    #[test]
+    fn flush_interval_follows_the_ingest_rate_within_limits() {
+        let controller = controller(200);
+        assert_eq!(controller.flush_interval(), Duration::from_millis(2_000));
+
+        // 200 mensajes a 400/s tardan 500 ms en llenar un lote
+        controller.adjust_flush_interval(400, Duration::from_secs(1));
+        assert_eq!(controller.flush_interval(), Duration::from_millis(500));
+
+        controller.adjust_flush_interval(100_000, Duration::from_secs(1));
+        assert_eq!(controller.flush_interval(), Duration::from_millis(100));
+
+        controller.adjust_flush_interval(10, Duration::from_secs(1));
+        assert_eq!(controller.flush_interval(), Duration::from_millis(2_000));
+
+        controller.adjust_flush_interval(0, Duration::from_secs(1));
+        assert_eq!(controller.flush_interval(), Duration::from_millis(2_000));
+    }
+}
@@ -0,0 +1,397 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{BrokerConfig, CaptureConfig, PayloadFormat};
+use crate::models::DeviceMessage;
+use crate::services::raw_decoder::RawDecoder;
+use crate::services::schema_registry::SchemaRegistryClient;
+use crate::services::{Backpressure, FieldMapping, KafkaConsumerService};
+
+/// Una línea de un archivo de captura: el payload tal como llegó, antes de
+/// decodificarlo, con el formato necesario para volver a decodificarlo
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapturedPayload {
+    /// Recepción, en milisegundos desde epoch
+    pub at: i64,
+    /// Tópico, cola o `http`
+    pub source: String,
+    pub format: PayloadFormat,
+    /// `utf8` o `base64` (protobuf u otros binarios)
+    pub encoding: String,
+    pub payload: String,
+}
+
+impl CapturedPayload {
+    fn new(source: &str, format: PayloadFormat, payload: &[u8]) -> Self {
+        let (encoding, payload) = match (format, std::str::from_utf8(payload)) {
+            (PayloadFormat::Json | PayloadFormat::Raw, Ok(text)) => ("utf8", text.to_string()),
+            _ => (
+                "base64",
+                base64::engine::general_purpose::STANDARD.encode(payload),
+            ),
+        };
+        Self {
+            at: Utc::now().timestamp_millis(),
+            source: source.to_string(),
+            format,
+            encoding: encoding.to_string(),
+            payload,
+        }
+    }
+
+    /// Trama cruda recuperada de `raw_message`, para reproducirla con `replay-file`
+    pub fn raw_frame(frame: &str) -> Self {
+        Self::new("raw_message", PayloadFormat::Raw, frame.as_bytes())
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>> {
+        match self.encoding.as_str() {
+            "utf8" => Ok(self.payload.as_bytes().to_vec()),
+            "base64" => Ok(base64::engine::general_purpose::STANDARD.decode(&self.payload)?),
+            other => Err(anyhow!("encoding '{}' no soportado", other)),
+        }
+    }
+}
+
+/// Modo captura: escribe cada payload recibido, antes de decodificarlo, en archivos
+/// NDJSON que `replay-file` vuelve a pasar por el pipeline completo. Sirve para
+/// reproducir un problema o probar un cambio con tráfico real contra una BD de
+/// pruebas.
+///
+/// El archivo abierto se llama `capture-<fecha>.ndjson.part` y pierde el `.part` al
+/// cerrarse por tamaño, por antigüedad o en el shutdown; con `CAPTURE_ARCHIVE` el
+/// directorio está dentro del spool del archivo S3, que sube los cerrados.
+///
+/// La escritura va por una cola acotada y una tarea propia: si el disco no da
+/// abasto se descartan capturas, nunca se frena el consumo.
+pub struct CaptureWriter {
+    queue: mpsc::Sender<WriterCommand>,
+    dropped: AtomicU64,
+}
+
+enum WriterCommand {
+    Record(CapturedPayload),
+    Close(oneshot::Sender<()>),
+}
+
+impl CaptureWriter {
+    pub fn new(config: &CaptureConfig) -> Result<Self> {
+        let dir = PathBuf::from(&config.dir);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("No se pudo crear el directorio {}", config.dir))?;
+        // Archivos que quedaron abiertos en una ejecución anterior
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "part") {
+                std::fs::rename(&path, path.with_extension(""))?;
+            }
+        }
+
+        let (queue, commands) = mpsc::channel(config.queue_size);
+        info!(
+            "🎙️ Captura de payloads en {} (archivos de hasta {} MB o {} s)",
+            config.dir, config.max_file_size_mb, config.max_file_age_secs
+        );
+        tokio::spawn(write_captures(
+            dir,
+            config.max_file_size_mb.saturating_mul(1024 * 1024),
+            Duration::from_secs(config.max_file_age_secs.max(1)),
+            commands,
+        ));
+
+        Ok(Self {
+            queue,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Encola el payload tal como llegó; no espera al disco
+    pub fn record(&self, source: &str, format: PayloadFormat, payload: &[u8]) {
+        let captured = CapturedPayload::new(source, format, payload);
+        if self
+            .queue
+            .try_send(WriterCommand::Record(captured))
+            .is_err()
+        {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped % 1000 == 1 {
+                warn!(
+                    "⚠️ Cola de captura llena, {} payloads sin capturar en total",
+                    dropped
+                );
+            }
+        }
+    }
+
+    /// Escribe lo encolado y cierra el archivo abierto; lo capturado después abre uno nuevo
+    pub async fn close(&self) {
+        let (done, closed) = oneshot::channel();
+        if self.queue.send(WriterCommand::Close(done)).await.is_ok() {
+            let _ = closed.await;
+        }
+    }
+}
+
+/// Archivo de captura abierto
+struct OpenCapture {
+    path: PathBuf,
+    writer: BufWriter<tokio::fs::File>,
+    written: u64,
+    opened: tokio::time::Instant,
+}
+
+impl OpenCapture {
+    async fn create(dir: &Path) -> Result<Self> {
+        let path = dir.join(format!(
+            "capture-{}.ndjson.part",
+            Utc::now().format("%Y%m%dT%H%M%S%.3f")
+        ));
+        let file = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("No se pudo crear {}", path.display()))?;
+        debug!("🎙️ Capturando en {}", path.display());
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            written: 0,
+            opened: tokio::time::Instant::now(),
+        })
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.writer.flush().await?;
+        let closed = self.path.with_extension("");
+        tokio::fs::rename(&self.path, &closed).await?;
+        info!(
+            "🎙️ Captura cerrada: {} ({} bytes)",
+            closed.display(),
+            self.written
+        );
+        Ok(())
+    }
+}
+
+/// Escribe las capturas en orden y cierra el archivo al superar `max_bytes` o
+/// `max_age`. Vacía el buffer cada vez que la cola queda vacía, así lo capturado
+/// llega al disco aunque el proceso termine de golpe.
+async fn write_captures(
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    mut commands: mpsc::Receiver<WriterCommand>,
+) {
+    let mut open: Option<OpenCapture> = None;
+    let mut ticker = tokio::time::interval(max_age.min(Duration::from_secs(60)));
+    loop {
+        let command = tokio::select! {
+            command = commands.recv() => command,
+            _ = ticker.tick() => {
+                if open.as_ref().is_some_and(|file| file.opened.elapsed() >= max_age) {
+                    close(open.take()).await;
+                }
+                continue;
+            }
+        };
+
+        let captured = match command {
+            Some(WriterCommand::Record(captured)) => captured,
+            Some(WriterCommand::Close(done)) => {
+                close(open.take()).await;
+                let _ = done.send(());
+                continue;
+            }
+            None => {
+                close(open.take()).await;
+                break;
+            }
+        };
+
+        let mut line = match serde_json::to_vec(&captured) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("❌ Captura no serializable: {}", e);
+                continue;
+            }
+        };
+        line.push(b'\n');
+
+        if open
+            .as_ref()
+            .is_some_and(|file| file.written + line.len() as u64 > max_bytes)
+        {
+            close(open.take()).await;
+        }
+        let file = match open.as_mut() {
+            Some(file) => file,
+            None => match OpenCapture::create(&dir).await {
+                Ok(file) => open.insert(file),
+                Err(e) => {
+                    error!("❌ {:#}", e);
+                    continue;
+                }
+            },
+        };
+
+        if let Err(e) = file.writer.write_all(&line).await {
+            error!("❌ Error escribiendo captura: {}", e);
+            continue;
+        }
+        file.written += line.len() as u64;
+        if commands.is_empty() {
+            if let Err(e) = file.writer.flush().await {
+                error!("❌ Error escribiendo captura: {}", e);
+            }
+        }
+    }
+}
+
+async fn close(file: Option<OpenCapture>) {
+    if let Some(file) = file {
+        if let Err(e) = file.close().await {
+            error!("❌ Error cerrando archivo de captura: {}", e);
+        }
+    }
+}
+
+/// Reproduce archivos de captura a través del pipeline: cada payload se
+/// decodifica con su formato original y se envía al procesador respetando los
+/// tiempos entre mensajes, divididos por `speed` (0 = sin esperas)
+pub struct CaptureReplay {
+    schema_registry: Option<SchemaRegistryClient>,
+    field_mapping: Option<Arc<FieldMapping>>,
+    topic_tenants: BTreeMap<String, String>,
+    protobuf: RawDecoder,
+    raw: RawDecoder,
+    json: RawDecoder,
+    backpressure: Backpressure,
+}
+
+impl CaptureReplay {
+    pub fn new(config: &BrokerConfig, backpressure: Backpressure) -> Result<Self> {
+        Ok(Self {
+            schema_registry: config
+                .schema_registry
+                .as_ref()
+                .map(SchemaRegistryClient::new)
+                .transpose()?,
+            field_mapping: None,
+            topic_tenants: BTreeMap::new(),
+            protobuf: RawDecoder::new(PayloadFormat::Protobuf),
+            raw: RawDecoder::new(PayloadFormat::Raw),
+            json: RawDecoder::new(PayloadFormat::Json),
+            backpressure,
+        })
+    }
+
+    /// Aplica las mismas reglas de mapeo que el consumer principal
+    pub fn with_field_mapping(mut self, mapping: Arc<FieldMapping>) -> Self {
+        self.field_mapping = Some(mapping);
+        self
+    }
+
+    /// Asigna el tenant de los payloads capturados en los tópicos de cada tenant
+    pub fn with_topic_tenants(mut self, topics: &BTreeMap<String, String>) -> Self {
+        self.topic_tenants = topics.clone();
+        self
+    }
+
+    /// Envía al canal los mensajes de los archivos, en orden. Devuelve la cantidad
+    /// de mensajes enviados y la de payloads que no se pudieron decodificar.
+    pub async fn run(
+        &self,
+        files: &[PathBuf],
+        speed: f64,
+        tx: mpsc::UnboundedSender<DeviceMessage>,
+    ) -> Result<(u64, u64)> {
+        let mut pressure = self.backpressure.subscribe();
+        let started = tokio::time::Instant::now();
+        let mut first_at = None;
+        let (mut replayed, mut failed) = (0, 0);
+
+        for path in files {
+            info!("⏯️ Reproduciendo {}", path.display());
+            let mut lines = open_lines(path).await?;
+            let mut line_number = 0;
+            while let Some(line) = lines.next_line().await? {
+                line_number += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let captured: CapturedPayload = match serde_json::from_str(&line) {
+                    Ok(captured) => captured,
+                    Err(e) => {
+                        warn!(
+                            "⚠️ {}:{} no es una captura: {}",
+                            path.display(),
+                            line_number,
+                            e
+                        );
+                        failed += 1;
+                        continue;
+                    }
+                };
+
+                if speed > 0.0 {
+                    let first_at = *first_at.get_or_insert(captured.at);
+                    let offset_ms = (captured.at - first_at).max(0) as f64 / speed;
+                    tokio::time::sleep_until(started + Duration::from_secs_f64(offset_ms / 1000.0))
+                        .await;
+                }
+                pressure.wait_for(|under_pressure| !under_pressure).await?;
+
+                match self.decode(&captured).await {
+                    Ok(mut message) => {
+                        message.tenant = self.topic_tenants.get(&captured.source).cloned();
+                        message.source = Some(captured.source);
+                        tx.send(message)
+                            .map_err(|_| anyhow!("Canal del procesador cerrado"))?;
+                        replayed += 1;
+                    }
+                    Err(e) => {
+                        error!("❌ {}:{}: {:#}", path.display(), line_number, e);
+                        failed += 1;
+                    }
+                }
+            }
+            info!(
+                "⏯️ {} terminado ({} mensajes reproducidos hasta ahora)",
+                path.display(),
+                replayed
+            );
+        }
+
+        Ok((replayed, failed))
+    }
+
+    async fn decode(&self, captured: &CapturedPayload) -> Result<DeviceMessage> {
+        let decoder = match captured.format {
+            PayloadFormat::Protobuf => &self.protobuf,
+            PayloadFormat::Raw => &self.raw,
+            PayloadFormat::Json => &self.json,
+        };
+        KafkaConsumerService::decode_payload(
+            self.schema_registry.as_ref(),
+            self.field_mapping.as_deref(),
+            decoder,
+            &captured.bytes()?,
+        )
+        .await
+    }
+}
+
+async fn open_lines(path: &Path) -> Result<tokio::io::Lines<BufReader<tokio::fs::File>>> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("No se pudo abrir {}", path.display()))?;
+    Ok(BufReader::new(file).lines())
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::{ClickHouseConfig, ClickHouseMode, RetryConfig};
+use crate::models::{CommunicationRecord, Manufacturer};
+
+/// Tiempo máximo de cada INSERT contra ClickHouse
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Escribe el histórico de posiciones en ClickHouse mediante su interfaz HTTP
+/// (`INSERT ... FORMAT JSONEachRow`). El estado actual sigue en PostgreSQL.
+#[derive(Debug, Clone)]
+pub struct ClickHouseSink {
+    http: reqwest::Client,
+    url: String,
+    database: String,
+    username: Option<String>,
+    password: Option<String>,
+    table_template: String,
+    mode: ClickHouseMode,
+    retry: RetryConfig,
+}
+
+impl ClickHouseSink {
+    pub fn new(config: &ClickHouseConfig, retry: RetryConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        info!(
+            "🏠 ClickHouse configurado en {} (base: {}, modo: {:?})",
+            config.url, config.database, config.mode
+        );
+
+        Ok(Self {
+            http,
+            url: config.url.trim_end_matches('/').to_string(),
+            database: config.database.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            table_template: config.table.clone(),
+            mode: config.mode,
+            retry,
+        })
+    }
+
+    /// Indica si ClickHouse reemplaza a las tablas de histórico de PostgreSQL
+    pub fn replaces_history(&self) -> bool {
+        self.mode == ClickHouseMode::Replace
+    }
+
+    /// Tabla de ClickHouse de un fabricante
+    pub fn table(&self, manufacturer: Manufacturer) -> String {
+        format!(
+            "{}.{}",
+            self.database,
+            self.table_template
+                .replace("{manufacturer}", manufacturer.as_str())
+        )
+    }
+
+    /// Inserta el lote reintentando ante errores de red o respuestas 5xx.
+    /// Devuelve la cantidad de registros enviados.
+    pub async fn insert_batch(
+        &self,
+        records: &[CommunicationRecord],
+        manufacturer: Manufacturer,
+    ) -> Result<usize> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let table = self.table(manufacturer);
+        let mut body = String::new();
+        for record in records {
+            body.push_str(&serde_json::to_string(record)?);
+            body.push('\n');
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.send(&table, body.clone()).await {
+                Ok(()) => {
+                    debug!("🏠 {} registros enviados a {}", records.len(), table);
+                    return Ok(records.len());
+                }
+                Err(e) if attempt < self.retry.max_attempts && is_retryable(&e) => {
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "⚠️ Error escribiendo en ClickHouse {} (intento {}/{}): {}. Reintentando en {:?}",
+                        table, attempt, self.retry.max_attempts, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send(&self, table: &str, body: String) -> Result<()> {
+        let query = format!("INSERT INTO {} FORMAT JSONEachRow", table);
+        let mut request = self
+            .http
+            .post(&self.url)
+            .query(&[
+                ("query", query.as_str()),
+                // CommunicationRecord incluye campos que la tabla puede no tener (id)
+                ("input_format_skip_unknown_fields", "1"),
+                // Las fechas se serializan como ISO 8601 ("2024-05-01T10:00:00")
+                ("date_time_input_format", "best_effort"),
+            ])
+            .body(body);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(anyhow!(ClickHouseError {
+                status: status.as_u16(),
+                detail: detail.trim().to_string(),
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+/// Respuesta de error del servidor de ClickHouse
+#[derive(Debug, thiserror::Error)]
+#[error("ClickHouse respondió {status}: {detail}")]
+pub struct ClickHouseError {
+    pub status: u16,
+    pub detail: String,
+}
+
+/// Errores de red y respuestas 5xx; los 4xx (tabla inexistente, tipos) no se reintentan
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(e) = error.downcast_ref::<ClickHouseError>() {
+        return e.status >= 500;
+    }
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout() || e.is_request())
+}
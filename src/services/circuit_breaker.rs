@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use crate::error_reporter::{self, Incident};
+use crate::errors::ErrorCategory;
+use crate::services::{Backpressure, Clock, RecordStore};
+
+/// Circuit breaker de escritura en la base de datos.
+///
+/// Cuando un lote falla por un error transitorio después de agotar los reintentos,
+/// el circuito se abre: se bloquea el consumo de Kafka y el lote queda retenido en
+/// memoria mientras se sondea `health_check`. Al recuperarse PostgreSQL el circuito
+/// se cierra y el consumo se reanuda. Con varios carriles de procesamiento el
+/// circuito sigue abierto mientras alguno espera.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    // Carriles esperando a que la base de datos se recupere
+    waiting: Arc<AtomicUsize>,
+    probe_interval: Duration,
+    backpressure: Backpressure,
+}
+
+impl CircuitBreaker {
+    pub fn new(probe_interval: Duration, backpressure: Backpressure) -> Self {
+        Self {
+            waiting: Arc::new(AtomicUsize::new(0)),
+            probe_interval,
+            backpressure,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.waiting.load(Ordering::Relaxed) > 0
+    }
+
+    /// Abre el circuito y espera hasta que la base de datos vuelva a responder,
+    /// sondeándola cada `probe_interval` según `clock`
+    pub async fn wait_for_recovery(&self, database: &dyn RecordStore, clock: &dyn Clock) {
+        if self.waiting.fetch_add(1, Ordering::SeqCst) == 0 {
+            self.backpressure.set_blocked(true);
+            error!(
+                "🔴 Circuito de BD abierto: consumo pausado, verificando cada {:?}",
+                self.probe_interval
+            );
+            error_reporter::report(
+                Incident::new("circuit_breaker", "Circuito de BD abierto: consumo pausado")
+                    .with_category(ErrorCategory::DbUnavailable),
+            );
+        }
+
+        loop {
+            clock.sleep(self.probe_interval).await;
+            if database.health_check().await.unwrap_or(false) {
+                break;
+            }
+            debug!("🔴 Base de datos aún no disponible");
+        }
+
+        if self.waiting.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.backpressure.set_blocked(false);
+            info!("🟢 Circuito de BD cerrado: reanudando consumo");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::record_store::MockRecordStore;
+    use crate::services::ManualClock;
+
+    const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Store cuyo health_check falla las primeras `failures` veces
+    fn store(failures: usize, probes: Arc<AtomicUsize>) -> MockRecordStore {
+        let mut store = MockRecordStore::new();
+        store
+            .expect_health_check()
+            .returning(move || Ok(probes.fetch_add(1, Ordering::SeqCst) >= failures));
+        store
+    }
+
+    /// Deja correr a las tareas hasta que no tengan nada más que hacer
+    async fn settle() {
+        for _ in 0..50 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_probes_and_closes_when_the_database_recovers() {
+        let clock = Arc::new(ManualClock::new());
+        let backpressure = Backpressure::new(1000);
+        let breaker = CircuitBreaker::new(PROBE_INTERVAL, backpressure.clone());
+        let probes = Arc::new(AtomicUsize::new(0));
+        let store = Arc::new(store(1, probes.clone()));
+
+        let task = tokio::spawn({
+            let (breaker, store, clock) = (breaker.clone(), store.clone(), clock.clone());
+            async move {
+                breaker
+                    .wait_for_recovery(store.as_ref(), clock.as_ref())
+                    .await
+            }
+        });
+
+        // Abierto: se bloquea el consumo y no se sondea antes del intervalo
+        settle().await;
+        assert!(breaker.is_open());
+        assert!(backpressure.is_paused());
+        assert_eq!(probes.load(Ordering::SeqCst), 0);
+
+        // Semiabierto: el primer sondeo falla y el circuito sigue abierto
+        clock.advance(PROBE_INTERVAL);
+        settle().await;
+        assert_eq!(probes.load(Ordering::SeqCst), 1);
+        assert!(breaker.is_open());
+        assert!(backpressure.is_paused());
+
+        // Cerrado: el segundo sondeo responde y se reanuda el consumo
+        clock.advance(PROBE_INTERVAL);
+        task.await.unwrap();
+        assert_eq!(probes.load(Ordering::SeqCst), 2);
+        assert!(!breaker.is_open());
+        assert!(!backpressure.is_paused());
+    }
+
+    #[tokio::test]
+    async fn circuit_stays_open_while_any_lane_waits() {
+        let clock = Arc::new(ManualClock::new());
+        let backpressure = Backpressure::new(1000);
+        let breaker = CircuitBreaker::new(PROBE_INTERVAL, backpressure.clone());
+        let recovered = Arc::new(store(0, Arc::new(AtomicUsize::new(0))));
+        let down = Arc::new(store(usize::MAX, Arc::new(AtomicUsize::new(0))));
+
+        let first = tokio::spawn({
+            let (breaker, store, clock) = (breaker.clone(), recovered.clone(), clock.clone());
+            async move {
+                breaker
+                    .wait_for_recovery(store.as_ref(), clock.as_ref())
+                    .await
+            }
+        });
+        let second = tokio::spawn({
+            let (breaker, store, clock) = (breaker.clone(), down.clone(), clock.clone());
+            async move {
+                breaker
+                    .wait_for_recovery(store.as_ref(), clock.as_ref())
+                    .await
+            }
+        });
+
+        settle().await;
+        clock.advance(PROBE_INTERVAL);
+        first.await.unwrap();
+        settle().await;
+        assert!(breaker.is_open());
+        assert!(backpressure.is_paused());
+
+        second.abort();
+    }
+}
@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Fuente de tiempo del procesador: el timer de flush de los lotes, la latencia de
+/// escritura y la espera del circuit breaker. En producción es el reloj del sistema;
+/// en los tests `ManualClock` avanza solo cuando se le indica.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Espera hasta `deadline`; vuelve de inmediato si ya pasó
+    async fn sleep_until(&self, deadline: Instant);
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await
+    }
+}
+
+/// Reloj del sistema, sobre los timers de tokio
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        tokio::time::sleep_until(deadline.into()).await
+    }
+}
+
+/// Reloj detenido que solo avanza con `advance`, para probar timeouts sin esperas
+/// reales. Las tareas dormidas despiertan al alcanzar su deadline.
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    changed: watch::Sender<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+            changed: watch::Sender::new(Duration::ZERO),
+        }
+    }
+
+    /// Adelanta el reloj y despierta a las tareas cuyo deadline ya pasó
+    pub fn advance(&self, duration: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap_or_else(|e| e.into_inner());
+        *elapsed += duration;
+        self.changed.send_replace(*elapsed);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        let mut changed = self.changed.subscribe();
+        while self.now() < deadline {
+            // El sender vive mientras el reloj, así que `changed` no falla
+            if changed.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
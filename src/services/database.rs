@@ -1,312 +1,1842 @@
-use anyhow::Result;
-use sqlx::PgPool;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{Connection, PgPool, Postgres, QueryBuilder, Row};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-use crate::models::{CommunicationRecord, Manufacturer};
+use crate::config::{
+    BufferOverflowPolicy, CurrentStateMode, CurrentStateOrder, InsertMode, PartitionConfig,
+    RetryConfig, TableConfig,
+};
+use crate::error_reporter::{self, Incident};
+use crate::errors::ErrorCategory;
+use crate::models::{
+    CommandSource, CommandStatus, CommunicationRecord, DeviceCommand, DeviceCommandRecord,
+    DriverEvent, DriverTransition, Geofence, GeofenceEvent, GeofenceTransition, Manufacturer,
+    PowerEvent, PowerTransition, Trip, TripPoint,
+};
+use crate::redact;
+use crate::services::ch_sink::{self, ClickHouseSink};
+use crate::services::es_sink::ElasticsearchSink;
+use crate::services::live_tail::{LiveTail, TailStage};
+use crate::services::partitioning::PartitionManager;
+use crate::services::pipeline_control::{PipelineControl, PipelineStage};
+use crate::services::pipeline_metrics::PipelineMetrics;
+use crate::services::raw_encryption::RawCipher;
+use crate::services::redis_state::RedisStateSink;
+
+/// Columnas de las tablas de comunicaciones, en el orden de `push_record_values`
+const RECORD_COLUMNS: &str = "uuid, device_id, backup_battery_voltage, backup_battery_percent, cell_id, course, delivery_type,
+    engine_status, firmware, fix_status, gps_datetime, gps_epoch, idle_time,
+    lac, latitude, longitude, main_battery_voltage, mcc, mnc, model,
+    msg_class, msg_counter, alert_type, network_status, odometer, rx_lvl, satellites,
+    speed, speed_time, total_distance, trip_distance, trip_hourmeter,
+    bytes_count, client_ip, client_port, decoded_epoch, received_epoch,
+    raw_message, received_at, created_at, quality_score, decoded_extra,
+    ignition, panic_button, door_sensor, relay_output";
+
+/// Columnas para leer `CommunicationRecord`; las NUMERIC se convierten a float8
+const SELECT_RECORD_COLUMNS: &str =
+    "id, uuid, device_id, backup_battery_voltage::float8 AS backup_battery_voltage,
+    backup_battery_percent::float8 AS backup_battery_percent, cell_id, course::float8 AS course,
+    delivery_type, engine_status, firmware, fix_status, gps_datetime, gps_epoch, idle_time,
+    lac, latitude::float8 AS latitude, longitude::float8 AS longitude,
+    main_battery_voltage::float8 AS main_battery_voltage, mcc, mnc, model,
+    msg_class, msg_counter, alert_type, network_status, odometer, rx_lvl, satellites,
+    speed::float8 AS speed, speed_time, total_distance, trip_distance, trip_hourmeter,
+    bytes_count, client_ip, client_port, decoded_epoch, received_epoch,
+    raw_message, received_at, created_at, quality_score, decoded_extra,
+    ignition, panic_button, door_sensor, relay_output";
+
+/// Fila de un viaje abierto: id, device_id, tenant, start_time, start_epoch,
+/// start_latitude, start_longitude, end_uuid, end_time, end_epoch, end_latitude,
+/// end_longitude, distance_m
+type TripRow = (
+    String,
+    String,
+    Option<String>,
+    Option<NaiveDateTime>,
+    i64,
+    f64,
+    f64,
+    String,
+    Option<NaiveDateTime>,
+    i64,
+    f64,
+    f64,
+    f64,
+);
+
+/// Registro que no pudo insertarse, con la tabla destino y el error de PostgreSQL
+struct RejectedRecord {
+    record: CommunicationRecord,
+    target_table: String,
+    error: String,
+}
+
+/// Fila de `geofences`: id, name, device_id, tenant, shape
+type GeofenceRow = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    serde_json::Value,
+);
+
+/// Espera entre comprobaciones mientras el buffer está lleno
+const BUFFER_FULL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Registros pendientes de escribir y momento en que entró el más antiguo
+#[derive(Debug)]
+struct PendingBuffer {
+    records: Vec<CommunicationRecord>,
+    since: Option<Instant>,
+    // Avisos de escritura de cada tramo de `records`, en el mismo orden
+    receipts: VecDeque<BufferReceipt>,
+}
+
+/// Aviso de que un tramo de registros del buffer llegó a la BD. Si alguno de sus
+/// registros se pierde (desborde, error permanente o cierre) el aviso se descarta
+/// sin enviarse.
+#[derive(Debug)]
+struct BufferReceipt {
+    records: usize,
+    written: Vec<oneshot::Sender<()>>,
+}
+
+/// Escritura de un lote en una tabla de histórico, para la tabla de auditoría
+struct AuditEntry<'a> {
+    batch_id: uuid::Uuid,
+    source: &'a str,
+    manufacturer: Manufacturer,
+    records: &'a [CommunicationRecord],
+    result: &'a Result<usize>,
+    duration: Duration,
+    retries: u32,
+}
+
+/// Pool conectado a uno de los hosts configurados
+#[derive(Debug)]
+struct ActivePool {
+    pool: PgPool,
+    host_idx: usize,
+}
 
 #[derive(Debug, Clone)]
 pub struct DatabaseService {
-    pool: PgPool,
+    active: Arc<std::sync::RwLock<ActivePool>>,
+    // URLs del primario y las réplicas, en orden de preferencia para el failover
+    urls: Arc<Vec<String>>,
+    max_connections: u32,
+    // Evita failovers concurrentes desde distintas tareas
+    failover_lock: Arc<Mutex<()>>,
     // Buffer para batch inserts
-    buffer: Arc<RwLock<Vec<CommunicationRecord>>>,
+    buffer: Arc<RwLock<PendingBuffer>>,
+    // Un solo flush a la vez, para que los registros se escriban en orden de llegada
+    flush_lock: Arc<Mutex<()>>,
+    // Tamaño de lote a partir del cual el buffer se escribe sin esperar
+    batch_size: usize,
+    // Límite del buffer (0 = sin límite) y política al alcanzarlo
+    buffer_max_records: usize,
+    overflow_policy: BufferOverflowPolicy,
+    // Registros descartados por la política DropOldest
+    dropped_records: Arc<AtomicU64>,
+    // Esquema y nombres de las tablas destino
+    tables: TableConfig,
+    // Sufijo opcional para escribir en tablas alternativas (p. ej. replays)
+    table_suffix: String,
+    // Particionado por tiempo de las tablas de histórico
+    partitions: Option<PartitionManager>,
+    // Reintentos ante errores transitorios de PostgreSQL
+    retry: RetryConfig,
+    // Cómo se actualiza communications_current_state respecto al histórico
+    current_state_mode: CurrentStateMode,
+    // Columna que impide que un mensaje atrasado sobrescriba el estado actual
+    current_state_order: Option<CurrentStateOrder>,
+    // Forma de enviar las filas en los INSERT
+    insert_mode: InsertMode,
+    // Histórico en ClickHouse, como copia o en lugar de las tablas de PostgreSQL
+    clickhouse: Option<ClickHouseSink>,
+    // Copia de las posiciones en Elasticsearch/OpenSearch
+    elasticsearch: Option<ElasticsearchSink>,
+    // Copia del estado actual en Redis para la API en tiempo real
+    redis_state: Option<RedisStateSink>,
+    // Pausa de escrituras pedida por un operador
+    control: Option<Arc<PipelineControl>>,
+    // Errores de escritura por categoría
+    metrics: Option<Arc<PipelineMetrics>>,
+    // Origen registrado en la tabla de auditoría de lotes (None = sin auditoría)
+    audit_source: Option<String>,
+    // Vista en vivo de los registros escritos
+    live_tail: Option<Arc<LiveTail>>,
+    // Cifrado de raw_message antes de escribir (None = en claro)
+    raw_cipher: Option<Arc<RawCipher>>,
 }
 
 impl DatabaseService {
-    pub async fn new(database_url: &str, max_connections: u32, batch_size: usize) -> Result<Self> {
+    /// Conecta al primer host de `urls` que acepte escrituras
+    pub async fn new(urls: &[String], max_connections: u32, batch_size: usize) -> Result<Self> {
+        let mut last_error = anyhow!("No hay hosts de base de datos configurados");
+        for (host_idx, url) in urls.iter().enumerate() {
+            match Self::connect_writable(url, max_connections).await {
+                Ok(pool) => {
+                    info!("✅ Conexión a PostgreSQL establecida ({})", host_label(url));
+
+                    return Ok(Self {
+                        active: Arc::new(std::sync::RwLock::new(ActivePool { pool, host_idx })),
+                        urls: Arc::new(urls.to_vec()),
+                        max_connections,
+                        failover_lock: Arc::new(Mutex::new(())),
+                        flush_lock: Arc::new(Mutex::new(())),
+                        buffer: Arc::new(RwLock::new(PendingBuffer {
+                            records: Vec::with_capacity(batch_size),
+                            since: None,
+                            receipts: VecDeque::new(),
+                        })),
+                        batch_size,
+                        buffer_max_records: 0,
+                        overflow_policy: BufferOverflowPolicy::default(),
+                        dropped_records: Arc::new(AtomicU64::new(0)),
+                        tables: TableConfig::default(),
+                        table_suffix: String::new(),
+                        partitions: None,
+                        retry: RetryConfig::default(),
+                        current_state_mode: CurrentStateMode::default(),
+                        current_state_order: Some(CurrentStateOrder::GpsEpoch),
+                        insert_mode: InsertMode::default(),
+                        clickhouse: None,
+                        elasticsearch: None,
+                        redis_state: None,
+                        control: None,
+                        metrics: None,
+                        audit_source: None,
+                        live_tail: None,
+                        raw_cipher: None,
+                    });
+                }
+                Err(e) => {
+                    warn!("⚠️ No se pudo usar {}: {}", host_label(url), e);
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Crea un pool y verifica que el servidor acepte escrituras
+    /// (equivalente a `target_session_attrs=read-write`)
+    async fn connect_writable(url: &str, max_connections: u32) -> Result<PgPool> {
         let pool = sqlx::postgres::PgPoolOptions::new()
             .max_connections(max_connections)
             .min_connections(5)
             .acquire_timeout(std::time::Duration::from_secs(30))
             .idle_timeout(std::time::Duration::from_secs(600))
-            .connect(database_url)
+            .connect(url)
             .await?;
 
         // Test de conexión
-        sqlx::query("SELECT 1").fetch_one(&pool).await?;
+        let read_only: String = sqlx::query_scalar("SHOW transaction_read_only")
+            .fetch_one(&pool)
+            .await?;
+        if read_only == "on" {
+            pool.close().await;
+            return Err(anyhow!("el servidor es de solo lectura"));
+        }
 
-        info!("✅ Conexión a PostgreSQL establecida");
+        Ok(pool)
+    }
 
-        Ok(Self {
-            pool,
-            buffer: Arc::new(RwLock::new(Vec::with_capacity(batch_size))),
-        })
+    /// Pool del host activo
+    fn pool(&self) -> PgPool {
+        self.active.read().unwrap().pool.clone()
     }
 
-    /// Inserta registros agrupados por fabricante
-    pub async fn insert_records_by_manufacturer(
+    /// Reconecta al siguiente host con escritura disponible. Devuelve `true` si
+    /// hay un primario utilizable tras el intento.
+    async fn failover(&self) -> bool {
+        let _guard = self.failover_lock.lock().await;
+        let current = self.active.read().unwrap().host_idx;
+
+        // Se recorren primero los demás hosts y al final el actual
+        for offset in 1..=self.urls.len() {
+            let host_idx = (current + offset) % self.urls.len();
+            let url = &self.urls[host_idx];
+            match Self::connect_writable(url, self.max_connections).await {
+                Ok(pool) => {
+                    let previous = std::mem::replace(
+                        &mut *self.active.write().unwrap(),
+                        ActivePool { pool, host_idx },
+                    );
+                    // Cerrar el pool anterior sin esperar a las conexiones en uso
+                    tokio::spawn(async move { previous.pool.close().await });
+
+                    if host_idx != current {
+                        warn!(
+                            "🔀 Failover de PostgreSQL: {} → {}",
+                            host_label(&self.urls[current]),
+                            host_label(url)
+                        );
+                    }
+                    return true;
+                }
+                Err(e) => warn!("⚠️ Failover: {} no disponible: {}", host_label(url), e),
+            }
+        }
+
+        error!("❌ Ningún host de PostgreSQL acepta escrituras");
+        false
+    }
+
+    /// Aplica las migraciones embebidas de `migrations/` que falten en la base de datos
+    pub async fn run_migrations(&self) -> Result<()> {
+        info!("🗄️ Aplicando migraciones de base de datos...");
+        // Conexión fuera del pool: su search_path no debe llegar a otras consultas,
+        // ni siquiera si algo falla a mitad de camino
+        let mut conn = self.pool().acquire().await?.detach();
+
+        // Con un esquema configurado, las migraciones (y su tabla _sqlx_migrations) viven en él
+        if let Some(schema) = self.tables.schema_name() {
+            sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS {}", schema))
+                .execute(&mut conn)
+                .await?;
+            // 003 a 005 (ya publicadas) alteran communications_current_state si existe
+            // en cualquier esquema; en un esquema nuevo esa tabla aún no existe y el
+            // ALTER la resolvería en otro. Una tabla temporal, buscada después del
+            // esquema, recibe esos cambios y desaparece al cerrar la conexión.
+            sqlx::query(&format!("SET search_path TO {}, pg_temp", schema))
+                .execute(&mut conn)
+                .await?;
+            sqlx::query("CREATE TEMP TABLE communications_current_state (client_ip INET)")
+                .execute(&mut conn)
+                .await?;
+        }
+
+        let result = sqlx::migrate!("./migrations").run(&mut conn).await;
+        let _ = conn.close().await;
+        result?;
+        info!("✅ Migraciones aplicadas");
+        Ok(())
+    }
+
+    /// Escribe en tablas con el sufijo indicado (`communications_suntech{suffix}`, etc.)
+    pub fn with_table_suffix(mut self, suffix: &str) -> Self {
+        self.table_suffix = suffix.to_string();
+        self
+    }
+
+    /// Escribe en tablas de histórico particionadas por tiempo, creando las particiones necesarias
+    pub fn with_partitioning(mut self, config: PartitionConfig) -> Self {
+        self.partitions = Some(PartitionManager::new(config));
+        self
+    }
+
+    /// Política de reintentos para las escrituras por lotes
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Tablas de histórico de todos los fabricantes
+    fn history_tables(&self) -> Vec<String> {
+        Manufacturer::all()
+            .map(|manufacturer| self.history_table(manufacturer))
+            .collect()
+    }
+
+    /// Indica si hay particionado con retención configurada
+    pub fn partition_retention_enabled(&self) -> bool {
+        self.partitions
+            .as_ref()
+            .is_some_and(|partitions| partitions.retention_enabled())
+    }
+
+    /// Elimina las particiones de histórico que superan el período de retención
+    pub async fn drop_expired_partitions(&self) -> Result<usize> {
+        let Some(partitions) = &self.partitions else {
+            return Ok(0);
+        };
+
+        let mut dropped = 0;
+        for table in self.history_tables() {
+            dropped += partitions.drop_expired(&self.pool(), &table).await?;
+        }
+        Ok(dropped)
+    }
+
+    /// Tablas sujetas a mantenimiento: nombre lógico, tabla y columna con la fecha de la fila
+    pub fn maintenance_tables(&self) -> Vec<(&'static str, String, &'static str)> {
+        let mut tables: Vec<_> = Manufacturer::all()
+            .map(|manufacturer| {
+                (
+                    manufacturer.as_str(),
+                    self.history_table(manufacturer),
+                    "received_at",
+                )
+            })
+            .collect();
+        tables.extend([
+            ("current_state", self.current_state_table(), "received_at"),
+            ("rejected", self.rejected_table(), "rejected_at"),
+            (
+                "geofence_events",
+                self.tables.geofence_events_table(),
+                "created_at",
+            ),
+            (
+                "quarantine",
+                self.tables.quarantine_table(),
+                "quarantined_at",
+            ),
+            (
+                "driver_events",
+                self.tables.driver_events_table(),
+                "created_at",
+            ),
+            (
+                "device_commands",
+                self.tables.device_commands_table(),
+                "created_at",
+            ),
+            (
+                "power_events",
+                self.tables.power_events_table(),
+                "created_at",
+            ),
+        ]);
+        tables
+    }
+
+    /// Borra por tandas de `batch_size` las filas de `table` con `column` anterior a
+    /// `days` días. Devuelve la cantidad de filas borradas.
+    pub async fn delete_older_than(
         &self,
-        suntech_records: Vec<CommunicationRecord>,
-        queclink_records: Vec<CommunicationRecord>,
-    ) -> Result<usize> {
-        let mut total = 0;
+        table: &str,
+        column: &str,
+        days: u32,
+        batch_size: i64,
+    ) -> Result<u64> {
+        // tableoid + ctid identifican la fila también en tablas particionadas
+        let sql = format!(
+            "DELETE FROM {table} WHERE (tableoid, ctid) IN (
+                SELECT tableoid, ctid FROM {table}
+                WHERE {column} < NOW() - make_interval(days => $1)
+                LIMIT $2
+            )"
+        );
+
+        let mut deleted = 0;
+        loop {
+            let affected = sqlx::query(&sql)
+                .bind(i32::try_from(days).unwrap_or(i32::MAX))
+                .bind(batch_size)
+                .execute(&self.pool())
+                .await?
+                .rows_affected();
+            deleted += affected;
+            if affected < batch_size as u64 {
+                break;
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Ejecuta ANALYZE si la tabla acumula al menos `threshold` filas modificadas desde
+    /// el último (carga masiva, replay, retención). Devuelve `true` si se ejecutó.
+    pub async fn analyze_if_needed(&self, table: &str, threshold: i64) -> Result<bool> {
+        let modified: Option<i64> = sqlx::query_scalar(
+            "SELECT n_mod_since_analyze FROM pg_stat_user_tables WHERE relid = to_regclass($1)",
+        )
+        .bind(table)
+        .fetch_optional(&self.pool())
+        .await?;
 
-        // Insertar registros Suntech si hay
-        if !suntech_records.is_empty() {
-            let count = suntech_records.len();
-            debug!("📦 Insertando {} registros Suntech", count);
-            self.batch_insert(suntech_records, Manufacturer::Suntech)
+        if modified.is_some_and(|modified| modified >= threshold) {
+            sqlx::query(&format!("ANALYZE {}", table))
+                .execute(&self.pool())
                 .await?;
-            total += count;
+            return Ok(true);
         }
 
-        // Insertar registros Queclink si hay
-        if !queclink_records.is_empty() {
-            let count = queclink_records.len();
-            debug!("📦 Insertando {} registros Queclink", count);
-            self.batch_insert(queclink_records, Manufacturer::Queclink)
+        Ok(false)
+    }
+
+    /// Estado actual de un dispositivo: una fila por tipo de mensaje
+    pub async fn get_latest_by_device(&self, device_id: &str) -> Result<Vec<CommunicationRecord>> {
+        let sql = format!(
+            "SELECT {} FROM {} WHERE device_id = $1 ORDER BY msg_class",
+            SELECT_RECORD_COLUMNS,
+            self.current_state_table()
+        );
+
+        let mut records = sqlx::query_as::<_, CommunicationRecord>(&sql)
+            .bind(device_id)
+            .fetch_all(&self.pool())
+            .await?;
+        if let Some(cipher) = &self.raw_cipher {
+            cipher.decrypt_records(&mut records);
+        }
+        Ok(records)
+    }
+
+    /// Histórico de un dispositivo con `gps_datetime` en `[from, to)`, de todos los
+    /// fabricantes, ordenado por fecha GPS y limitado a `limit` filas
+    pub async fn get_range(
+        &self,
+        device_id: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<CommunicationRecord>> {
+        let mut records = Vec::new();
+        for manufacturer in Manufacturer::all() {
+            let sql = format!(
+                "SELECT {} FROM {}
+                WHERE device_id = $1 AND gps_datetime >= $2 AND gps_datetime < $3
+                ORDER BY gps_datetime LIMIT $4",
+                SELECT_RECORD_COLUMNS,
+                self.history_table(manufacturer)
+            );
+
+            let rows = sqlx::query_as::<_, CommunicationRecord>(&sql)
+                .bind(device_id)
+                .bind(from)
+                .bind(to)
+                .bind(limit)
+                .fetch_all(&self.pool())
                 .await?;
-            total += count;
+            records.extend(rows.into_iter().map(|mut record| {
+                record.manufacturer = Some(manufacturer);
+                record
+            }));
+        }
+
+        records.sort_by_key(|record| record.gps_datetime);
+        records.truncate(usize::try_from(limit).unwrap_or(0));
+        if let Some(cipher) = &self.raw_cipher {
+            cipher.decrypt_records(&mut records);
+        }
+        Ok(records)
+    }
+
+    /// UUIDs de `uuids` que no están en el histórico ni en la tabla de rechazados
+    pub async fn missing_uuids(&self, uuids: &[String]) -> Result<HashSet<String>> {
+        let mut missing: HashSet<String> = uuids.iter().cloned().collect();
+        if missing.is_empty() {
+            return Ok(missing);
+        }
+
+        let mut tables = self.history_tables();
+        tables.push(self.rejected_table());
+        for table in tables {
+            let found: Vec<String> =
+                sqlx::query_scalar(&format!("SELECT uuid FROM {} WHERE uuid = ANY($1)", table))
+                    .bind(uuids)
+                    .fetch_all(&self.pool())
+                    .await?;
+            for uuid in found {
+                missing.remove(&uuid);
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Geocercas activas de la tabla `geofences`. Las filas con una forma inválida se
+    /// registran y se omiten.
+    pub async fn load_geofences(&self) -> Result<Vec<Geofence>> {
+        let rows: Vec<GeofenceRow> = sqlx::query_as(&format!(
+            "SELECT id, name, device_id, tenant, shape FROM {} WHERE active",
+            self.tables.geofences_table()
+        ))
+        .fetch_all(&self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, name, device_id, tenant, shape)| {
+                match serde_json::from_value(shape) {
+                    Ok(shape) => Some(Geofence {
+                        id,
+                        name,
+                        device_id,
+                        tenant,
+                        shape,
+                    }),
+                    Err(e) => {
+                        warn!("⚠️ Geocerca {} con forma inválida, se omite: {}", id, e);
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    /// Último evento de cada par (dispositivo, geocerca), para no repetir una entrada
+    /// o salida ya notificada tras un reinicio
+    pub async fn geofence_states(&self) -> Result<Vec<(String, String, GeofenceTransition)>> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT ON (device_id, geofence_id) device_id, geofence_id, event
+             FROM {} ORDER BY device_id, geofence_id, id DESC",
+            self.tables.geofence_events_table()
+        ))
+        .fetch_all(&self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(device_id, geofence_id, event)| {
+                let event = if event == GeofenceTransition::Enter.as_str() {
+                    GeofenceTransition::Enter
+                } else {
+                    GeofenceTransition::Exit
+                };
+                (device_id, geofence_id, event)
+            })
+            .collect())
+    }
+
+    pub async fn insert_geofence_events(&self, events: &[GeofenceEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (geofence_id, device_id, tenant, event, uuid, latitude, longitude, gps_datetime) ",
+            self.tables.geofence_events_table()
+        ));
+        query_builder.push_values(events, |mut b, event| {
+            b.push_bind(&event.geofence_id)
+                .push_bind(&event.device_id)
+                .push_bind(&event.tenant)
+                .push_bind(event.event.as_str())
+                .push_bind(&event.uuid)
+                .push_bind(event.latitude)
+                .push_bind(event.longitude)
+                .push_bind(event.gps_datetime);
+        });
+        query_builder.build().execute(&self.pool()).await?;
+        Ok(())
+    }
+
+    /// Último evento de conductor de cada dispositivo, para retomar las sesiones
+    /// abiertas tras un reinicio
+    pub async fn driver_states(&self) -> Result<Vec<(String, String, DriverTransition)>> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT ON (device_id) device_id, driver_id, event
+             FROM {} ORDER BY device_id, id DESC",
+            self.tables.driver_events_table()
+        ))
+        .fetch_all(&self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(device_id, driver_id, event)| {
+                let event = if event == DriverTransition::DriverLogin.as_str() {
+                    DriverTransition::DriverLogin
+                } else {
+                    DriverTransition::DriverLogout
+                };
+                (device_id, driver_id, event)
+            })
+            .collect())
+    }
+
+    pub async fn insert_driver_events(&self, events: &[DriverEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (device_id, tenant, driver_id, event, uuid, latitude, longitude, gps_datetime) ",
+            self.tables.driver_events_table()
+        ));
+        query_builder.push_values(events, |mut b, event| {
+            b.push_bind(&event.device_id)
+                .push_bind(&event.tenant)
+                .push_bind(&event.driver_id)
+                .push_bind(event.event.as_str())
+                .push_bind(&event.uuid)
+                .push_bind(event.latitude)
+                .push_bind(event.longitude)
+                .push_bind(event.gps_datetime);
+        });
+        query_builder.build().execute(&self.pool()).await?;
+        Ok(())
+    }
+
+    /// Último corte o restablecimiento de cada dispositivo, para no repetirlo ni
+    /// perderlo tras un reinicio
+    pub async fn power_states(&self) -> Result<Vec<(String, PowerTransition)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT ON (device_id) device_id, event
+             FROM {} WHERE event IN ($1, $2) ORDER BY device_id, id DESC",
+            self.tables.power_events_table()
+        ))
+        .bind(PowerTransition::PowerLost.as_str())
+        .bind(PowerTransition::PowerRestored.as_str())
+        .fetch_all(&self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(device_id, event)| {
+                let event = if event == PowerTransition::PowerLost.as_str() {
+                    PowerTransition::PowerLost
+                } else {
+                    PowerTransition::PowerRestored
+                };
+                (device_id, event)
+            })
+            .collect())
+    }
+
+    pub async fn insert_power_events(&self, events: &[PowerEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (device_id, tenant, model, event, voltage, threshold, uuid, latitude, longitude, gps_datetime) ",
+            self.tables.power_events_table()
+        ));
+        query_builder.push_values(events, |mut b, event| {
+            b.push_bind(&event.device_id)
+                .push_bind(&event.tenant)
+                .push_bind(&event.model)
+                .push_bind(event.event.as_str())
+                .push_bind(event.voltage)
+                .push_bind(event.threshold)
+                .push_bind(&event.uuid)
+                .push_bind(event.latitude)
+                .push_bind(event.longitude)
+                .push_bind(event.gps_datetime);
+        });
+        query_builder.build().execute(&self.pool()).await?;
+        Ok(())
+    }
+
+    /// Guarda un comando como `pending`. Devuelve false si su id ya existía: el
+    /// comando ya se recibió y no se vuelve a enviar.
+    pub async fn insert_device_command(
+        &self,
+        command: &DeviceCommand,
+        source: CommandSource,
+    ) -> Result<bool> {
+        let result = sqlx::query(&format!(
+            "INSERT INTO {} (id, device_id, tenant, command, source, status)
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING",
+            self.tables.device_commands_table()
+        ))
+        .bind(command.id)
+        .bind(&command.device_id)
+        .bind(&command.tenant)
+        .bind(&command.command)
+        .bind(source.as_str())
+        .bind(CommandStatus::Pending.as_str())
+        .execute(&self.pool())
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Registra el resultado de la entrega de un comando
+    pub async fn update_device_command(
+        &self,
+        id: uuid::Uuid,
+        status: CommandStatus,
+        error: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(&format!(
+            "UPDATE {} SET status = $2, error = $3, updated_at = NOW(),
+             sent_at = CASE WHEN $2 = 'sent' THEN NOW() ELSE sent_at END
+             WHERE id = $1",
+            self.tables.device_commands_table()
+        ))
+        .bind(id)
+        .bind(status.as_str())
+        .bind(error)
+        .execute(&self.pool())
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_device_command(&self, id: uuid::Uuid) -> Result<Option<DeviceCommandRecord>> {
+        Ok(sqlx::query_as::<_, DeviceCommandRecord>(&format!(
+            "SELECT * FROM {} WHERE id = $1",
+            self.tables.device_commands_table()
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool())
+        .await?)
+    }
+
+    /// Últimos `limit` comandos de un dispositivo, del más reciente al más antiguo
+    pub async fn device_commands(
+        &self,
+        device_id: &str,
+        limit: i64,
+    ) -> Result<Vec<DeviceCommandRecord>> {
+        Ok(sqlx::query_as::<_, DeviceCommandRecord>(&format!(
+            "SELECT * FROM {} WHERE device_id = $1 ORDER BY created_at DESC LIMIT $2",
+            self.tables.device_commands_table()
+        ))
+        .bind(device_id)
+        .bind(limit)
+        .fetch_all(&self.pool())
+        .await?)
+    }
+
+    /// Guarda en `communications_quarantine` los registros de baja calidad GPS, con
+    /// los motivos de cada uno
+    pub async fn insert_quarantined(
+        &self,
+        records: &[(CommunicationRecord, Vec<&'static str>)],
+    ) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let encrypted;
+        let records = match &self.raw_cipher {
+            Some(cipher) => {
+                encrypted = records
+                    .iter()
+                    .map(|(record, reasons)| Ok((cipher.encrypt_record(record)?, reasons.clone())))
+                    .collect::<Result<Vec<_>>>()?;
+                &encrypted[..]
+            }
+            None => records,
+        };
+
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (uuid, device_id, quality_score, reasons, raw_message, record) ",
+            self.tables.quarantine_table()
+        ));
+        query_builder.push_values(records, |mut b, (record, reasons)| {
+            b.push_bind(&record.uuid)
+                .push_bind(&record.device_id)
+                .push_bind(record.quality_score)
+                .push_bind(reasons.clone())
+                .push_bind(&record.raw_message)
+                .push_bind(sqlx::types::Json(record));
+        });
+        query_builder.build().execute(&self.pool()).await?;
+        Ok(())
+    }
+
+    /// Viajes que seguían abiertos, para continuarlos tras un reinicio
+    pub async fn open_trips(&self) -> Result<Vec<Trip>> {
+        let rows: Vec<TripRow> = sqlx::query_as(&format!(
+            "SELECT id, device_id, tenant, start_time, start_epoch,
+                    start_latitude::float8, start_longitude::float8, end_uuid, end_time,
+                    end_epoch, end_latitude::float8, end_longitude::float8, distance_m
+             FROM {} WHERE NOT closed",
+            self.tables.trips_table()
+        ))
+        .fetch_all(&self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(
+                    id,
+                    device_id,
+                    tenant,
+                    start_time,
+                    start_epoch,
+                    start_latitude,
+                    start_longitude,
+                    end_uuid,
+                    end_time,
+                    end_epoch,
+                    end_latitude,
+                    end_longitude,
+                    distance_m,
+                )| Trip {
+                    start: TripPoint {
+                        uuid: id.clone(),
+                        gps_datetime: start_time,
+                        gps_epoch: start_epoch,
+                        latitude: start_latitude,
+                        longitude: start_longitude,
+                    },
+                    end: TripPoint {
+                        uuid: end_uuid,
+                        gps_datetime: end_time,
+                        gps_epoch: end_epoch,
+                        latitude: end_latitude,
+                        longitude: end_longitude,
+                    },
+                    id,
+                    device_id,
+                    tenant,
+                    distance_m,
+                    closed: false,
+                },
+            )
+            .collect())
+    }
+
+    /// Inserta los viajes nuevos y actualiza el avance o el cierre de los existentes
+    pub async fn upsert_trips(&self, trips: &[Trip]) -> Result<()> {
+        if trips.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (id, device_id, tenant, start_time, start_epoch, start_latitude, start_longitude,
+                end_uuid, end_time, end_epoch, end_latitude, end_longitude, distance_m, duration_secs, closed) ",
+            self.tables.trips_table()
+        ));
+        query_builder.push_values(trips, |mut b, trip| {
+            b.push_bind(&trip.id)
+                .push_bind(&trip.device_id)
+                .push_bind(&trip.tenant)
+                .push_bind(trip.start.gps_datetime)
+                .push_bind(trip.start.gps_epoch)
+                .push_bind(trip.start.latitude)
+                .push_bind(trip.start.longitude)
+                .push_bind(&trip.end.uuid)
+                .push_bind(trip.end.gps_datetime)
+                .push_bind(trip.end.gps_epoch)
+                .push_bind(trip.end.latitude)
+                .push_bind(trip.end.longitude)
+                .push_bind(trip.distance_m)
+                .push_bind(trip.duration_secs())
+                .push_bind(trip.closed);
+        });
+        query_builder.push(
+            " ON CONFLICT (id) DO UPDATE SET
+                end_uuid = EXCLUDED.end_uuid,
+                end_time = EXCLUDED.end_time,
+                end_epoch = EXCLUDED.end_epoch,
+                end_latitude = EXCLUDED.end_latitude,
+                end_longitude = EXCLUDED.end_longitude,
+                distance_m = EXCLUDED.distance_m,
+                duration_secs = EXCLUDED.duration_secs,
+                closed = EXCLUDED.closed,
+                updated_at = NOW()",
+        );
+        query_builder.build().execute(&self.pool()).await?;
+        Ok(())
+    }
+
+    /// Modo de actualización del estado actual (misma transacción, independiente o desactivado)
+    pub fn with_current_state_mode(mut self, mode: CurrentStateMode) -> Self {
+        self.current_state_mode = mode;
+        self
+    }
+
+    /// Solo actualiza el estado actual con mensajes al menos tan nuevos como el guardado,
+    /// según la columna indicada (None = el último procesado siempre gana)
+    pub fn with_current_state_order(mut self, order: Option<CurrentStateOrder>) -> Self {
+        self.current_state_order = order;
+        self
+    }
+
+    /// Forma de enviar las filas: VALUES multi-fila o arreglos por columna con UNNEST
+    pub fn with_insert_mode(mut self, mode: InsertMode) -> Self {
+        self.insert_mode = mode;
+        self
+    }
+
+    /// Con la etapa `db_writes` pausada el buffer no se escribe y los lotes se
+    /// acumulan en él (sujetos a `DB_BUFFER_MAX_RECORDS` y su política)
+    pub fn with_pipeline_control(mut self, control: Arc<PipelineControl>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Cuenta los errores de escritura y los registros rechazados por categoría
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Registra cada escritura de lote en la tabla de auditoría con `source` como origen
+    pub fn with_audit(mut self, source: &str) -> Self {
+        self.audit_source = Some(source.to_string());
+        self
+    }
+
+    /// Publica los registros escritos en la vista en vivo de `/tail`
+    pub fn with_live_tail(mut self, live_tail: Arc<LiveTail>) -> Self {
+        self.live_tail = Some(live_tail);
+        self
+    }
+
+    /// Cuenta un error en las estadísticas y devuelve su categoría para el log
+    fn record_error(&self, category: ErrorCategory) -> ErrorCategory {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_error(category);
+        }
+        category
+    }
+
+    /// Si un operador pausó la escritura de posiciones
+    pub fn writes_paused(&self) -> bool {
+        self.control
+            .as_ref()
+            .is_some_and(|control| control.is_paused(PipelineStage::DbWrites))
+    }
+
+    /// Escribe el histórico también (o solo) en ClickHouse
+    pub fn with_clickhouse(mut self, sink: ClickHouseSink) -> Self {
+        self.clickhouse = Some(sink);
+        self
+    }
+
+    /// Indexa una copia de las posiciones guardadas en Elasticsearch/OpenSearch
+    pub fn with_elasticsearch(mut self, sink: ElasticsearchSink) -> Self {
+        self.elasticsearch = Some(sink);
+        self
+    }
+
+    /// Replica el estado actual en Redis después de cada upsert
+    pub fn with_redis_state(mut self, sink: RedisStateSink) -> Self {
+        self.redis_state = Some(sink);
+        self
+    }
+
+    /// Limita el buffer de escritura a `max_records` (0 = sin límite)
+    pub fn with_buffer_limit(mut self, max_records: usize, policy: BufferOverflowPolicy) -> Self {
+        self.buffer_max_records = max_records;
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Cifra `raw_message` en todo lo que escribe (PostgreSQL, ClickHouse, Elasticsearch,
+    /// Redis) y lo descifra en las lecturas de la API
+    pub fn with_raw_encryption(mut self, cipher: Arc<RawCipher>) -> Self {
+        self.raw_cipher = Some(cipher);
+        self
+    }
+
+    /// Esquema y nombres de las tablas destino
+    pub fn with_tables(mut self, tables: TableConfig) -> Self {
+        self.tables = tables;
+        self
+    }
+
+    /// Vista de un tenant: comparte el pool y la configuración, pero resuelve `{tenant}`
+    /// con su nombre y tiene buffer y flush propios, para que la carga de un tenant no
+    /// retrase la escritura de los demás
+    pub fn for_tenant(&self, tenant: &str) -> Self {
+        let mut view = self.clone();
+        view.tables.tenant = Some(tenant.to_string());
+        view.buffer = Arc::new(RwLock::new(PendingBuffer {
+            records: Vec::with_capacity(self.batch_size),
+            since: None,
+            receipts: VecDeque::new(),
+        }));
+        view.flush_lock = Arc::new(Mutex::new(()));
+        view.dropped_records = Arc::new(AtomicU64::new(0));
+        if view.audit_source.is_some() {
+            view.audit_source = Some(tenant.to_string());
+        }
+        view
+    }
+
+    /// Tabla de histórico de un fabricante, con el sufijo configurado
+    pub fn history_table(&self, manufacturer: Manufacturer) -> String {
+        format!(
+            "{}{}",
+            self.tables.history_table(manufacturer.table()),
+            self.table_suffix
+        )
+    }
+
+    fn current_state_table(&self) -> String {
+        format!("{}{}", self.tables.current_state_table(), self.table_suffix)
+    }
+
+    fn rejected_table(&self) -> String {
+        format!("{}{}", self.tables.rejected_table(), self.table_suffix)
+    }
+
+    /// Inserta registros agrupados por fabricante (ver
+    /// `CommunicationRecord::group_by_manufacturer`)
+    pub async fn insert_records_by_manufacturer(
+        &self,
+        groups: &[(Manufacturer, Vec<CommunicationRecord>)],
+    ) -> Result<usize> {
+        // Las filas de auditoría de un mismo lote comparten batch_id
+        let batch_id = uuid::Uuid::new_v4();
+        let mut total = 0;
+        for (manufacturer, records) in groups {
+            if records.is_empty() {
+                continue;
+            }
+            debug!("📦 Insertando {} registros {}", records.len(), manufacturer);
+            let span = info_span!(
+                "db_insert",
+                manufacturer = %manufacturer,
+                records = records.len()
+            );
+            let started = Instant::now();
+            let mut retries = 0;
+            let result = self
+                .batch_insert(records, *manufacturer, &mut retries)
+                .instrument(span)
+                .await;
+            if let Some(source) = &self.audit_source {
+                self.audit_batch(AuditEntry {
+                    batch_id,
+                    source,
+                    manufacturer: *manufacturer,
+                    records,
+                    result: &result,
+                    duration: started.elapsed(),
+                    retries,
+                })
+                .await;
+            }
+            total += result?;
+            if let Some(live_tail) = &self.live_tail {
+                let table = self.history_table(*manufacturer);
+                for record in records {
+                    live_tail.publish(
+                        TailStage::Inserted,
+                        &record.device_id,
+                        &record.uuid,
+                        Some(&table),
+                        record,
+                    );
+                }
+            }
         }
 
         Ok(total)
     }
 
-    /// Procesa todos los registros del buffer agrupándolos por fabricante
+    /// Registra la escritura de un lote en la tabla de auditoría. Un fallo aquí no
+    /// afecta al lote, que ya se escribió (o falló) antes.
+    async fn audit_batch(&self, entry: AuditEntry<'_>) {
+        let (persisted, outcome, error) = match entry.result {
+            Ok(persisted) => (*persisted as i32, "persisted", None),
+            Err(e) => (0, "failed", Some(format!("{:#}", e))),
+        };
+        let uuids: Vec<&str> = entry
+            .records
+            .iter()
+            .map(|record| record.uuid.as_str())
+            .collect();
+        let result = sqlx::query(&format!(
+            "INSERT INTO {} (batch_id, source, target_table, record_count, persisted,
+                duration_ms, outcome, retries, error, uuids)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            self.tables.batch_audit_table()
+        ))
+        .bind(entry.batch_id)
+        .bind(entry.source)
+        .bind(self.history_table(entry.manufacturer))
+        .bind(entry.records.len() as i32)
+        .bind(persisted)
+        .bind(entry.duration.as_millis() as i64)
+        .bind(outcome)
+        .bind(entry.retries as i32)
+        .bind(error)
+        .bind(&uuids)
+        .execute(&self.pool())
+        .await;
+        if let Err(e) = result {
+            let e = anyhow::Error::from(e);
+            error!(
+                category = %self.record_error(ErrorCategory::of_database(&e)),
+                "❌ Error registrando el lote {} en la auditoría: {}",
+                entry.batch_id,
+                e
+            );
+        }
+    }
+
+    /// Agrega registros al buffer; la tarea de flush los escribe por tamaño o antigüedad.
+    /// Con el buffer lleno espera o descarta los más antiguos según la política.
+    /// `written` se avisa cuando todos estos registros quedan guardados.
+    pub async fn buffer_records(
+        &self,
+        records: Vec<CommunicationRecord>,
+        written: Option<oneshot::Sender<()>>,
+    ) {
+        if records.is_empty() {
+            if let Some(written) = written {
+                let _ = written.send(());
+            }
+            return;
+        }
+
+        if self.buffer_max_records > 0 && self.overflow_policy == BufferOverflowPolicy::Backpressure
+        {
+            let mut warned = false;
+            while self.buffer_size().await >= self.buffer_max_records {
+                if !warned {
+                    warn!(
+                        "⏸️ Buffer de BD lleno ({} registros), esperando a que se escriba",
+                        self.buffer_max_records
+                    );
+                    warned = true;
+                }
+                tokio::time::sleep(BUFFER_FULL_POLL_INTERVAL).await;
+            }
+        }
+
+        let mut buffer = self.buffer.write().await;
+        buffer.since.get_or_insert_with(Instant::now);
+        buffer.receipts.push_back(BufferReceipt {
+            records: records.len(),
+            written: written.into_iter().collect(),
+        });
+        buffer.records.extend(records);
+        self.drop_overflow(&mut buffer);
+    }
+
+    /// Con la política DropOldest, recorta el buffer a su tamaño máximo
+    fn drop_overflow(&self, buffer: &mut PendingBuffer) {
+        if self.buffer_max_records == 0
+            || self.overflow_policy != BufferOverflowPolicy::DropOldest
+            || buffer.records.len() <= self.buffer_max_records
+        {
+            return;
+        }
+
+        let excess = buffer.records.len() - self.buffer_max_records;
+        buffer.records.drain(..excess);
+        // Los tramos con algún registro descartado ya no se avisan
+        let mut pending = excess;
+        while pending > 0 {
+            let Some(receipt) = buffer.receipts.front_mut() else {
+                break;
+            };
+            if receipt.records <= pending {
+                pending -= receipt.records;
+                buffer.receipts.pop_front();
+            } else {
+                receipt.records -= pending;
+                receipt.written.clear();
+                pending = 0;
+            }
+        }
+        let total = self
+            .dropped_records
+            .fetch_add(excess as u64, Ordering::Relaxed)
+            + excess as u64;
+        warn!(
+            "🗑️ Buffer de BD lleno: {} registros más antiguos descartados ({} en total)",
+            excess, total
+        );
+        error_reporter::report(Incident::new(
+            "db_buffer",
+            format!(
+                "Buffer de BD lleno: {} registros más antiguos descartados",
+                excess
+            ),
+        ));
+    }
+
+    /// Registros descartados por desborde del buffer desde el arranque
+    pub fn dropped_records(&self) -> u64 {
+        self.dropped_records.load(Ordering::Relaxed)
+    }
+
+    /// Inicia la tarea que escribe el buffer cada `interval` cuando alcanza el tamaño
+    /// de lote o cuando su registro más antiguo supera `max_age`
+    pub fn start_flush_task(
+        self: &Arc<Self>,
+        interval: Duration,
+        max_age: Duration,
+    ) -> JoinHandle<()> {
+        let database = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+
+                let due = {
+                    let buffer = database.buffer.read().await;
+                    buffer.records.len() >= database.batch_size
+                        || buffer.since.is_some_and(|since| since.elapsed() >= max_age)
+                };
+                if !due {
+                    continue;
+                }
+
+                match database.flush_buffer().await {
+                    Ok(count) => debug!("🚿 Buffer de BD escrito: {} registros", count),
+                    Err(e) => error!(
+                        category = %database.record_error(ErrorCategory::of_database(&e)),
+                        "❌ Error escribiendo el buffer de BD: {}",
+                        e
+                    ),
+                }
+            }
+        })
+    }
+
+    /// Procesa todos los registros del buffer agrupándolos por fabricante.
+    /// Ante un error transitorio los registros vuelven al buffer. Si hay otro flush
+    /// en curso espera a que termine.
     pub async fn flush_buffer(&self) -> Result<usize> {
+        if self.writes_paused() {
+            return Ok(0);
+        }
+        let _flushing = self.flush_lock.lock().await;
         let mut buffer = self.buffer.write().await;
-        if buffer.is_empty() {
+        if buffer.records.is_empty() {
             return Ok(0);
         }
 
-        let count = buffer.len();
-        let records = std::mem::take(&mut *buffer);
+        let count = buffer.records.len();
+        let records = std::mem::take(&mut buffer.records);
+        let since = buffer.since.take();
+        let receipts = std::mem::take(&mut buffer.receipts);
         drop(buffer); // Liberar el lock lo antes posible
 
         // Agrupar por fabricante
-        let mut suntech_records = Vec::new();
-        let mut queclink_records = Vec::new();
+        let groups = CommunicationRecord::group_by_manufacturer(records);
 
-        for record in records {
-            match record.manufacturer {
-                Some(Manufacturer::Suntech) => suntech_records.push(record),
-                Some(Manufacturer::Queclink) => queclink_records.push(record),
-                None => {
-                    warn!("Registro sin fabricante asignado, usando Suntech por defecto");
-                    suntech_records.push(record);
-                }
+        if let Err(e) = self.insert_records_by_manufacturer(&groups).await {
+            if is_transient_error(&e) {
+                // Devolver los registros al frente del buffer para el próximo flush
+                let mut buffer = self.buffer.write().await;
+                let mut records: Vec<_> = groups
+                    .into_iter()
+                    .flat_map(|(_, records)| records)
+                    .collect();
+                records.append(&mut buffer.records);
+                buffer.records = records;
+                buffer.since = since;
+                // Al agruparlos por fabricante los registros cambiaron de orden: sus
+                // avisos vuelven como un solo tramo, que se pierde entero si se
+                // descarta cualquiera de ellos
+                buffer.receipts.push_front(BufferReceipt {
+                    records: count,
+                    written: receipts
+                        .into_iter()
+                        .flat_map(|receipt| receipt.written)
+                        .collect(),
+                });
+                self.drop_overflow(&mut buffer);
+            } else {
+                error_reporter::report(
+                    Incident::new(
+                        "database",
+                        format!("Buffer de {} registros perdido: {}", count, e),
+                    )
+                    .with_category(ErrorCategory::of_database(&e)),
+                );
             }
+            return Err(e);
+        }
+        for written in receipts.into_iter().flat_map(|receipt| receipt.written) {
+            let _ = written.send(());
         }
-
-        // Insertar usando el método que agrupa por fabricante
-        self.insert_records_by_manufacturer(suntech_records, queclink_records)
-            .await?;
         Ok(count)
     }
 
-    /// Inserción por lotes usando INSERT múltiple (simplificado)
+    /// Inserción por lotes usando INSERT múltiple (simplificado).
+    /// Devuelve la cantidad de registros persistidos en el histórico.
     async fn batch_insert(
         &self,
-        records: Vec<CommunicationRecord>,
+        records: &[CommunicationRecord],
         manufacturer: Manufacturer,
-    ) -> Result<()> {
+        retries: &mut u32,
+    ) -> Result<usize> {
         if records.is_empty() {
-            return Ok(());
+            return Ok(0);
         }
 
-        let table_name = match manufacturer {
-            Manufacturer::Suntech => "communications_suntech",
-            Manufacturer::Queclink => "communications_queclink",
+        // Todo lo que se escribe desde aquí, incluidos los rechazados y las copias, lleva
+        // raw_message cifrado
+        let encrypted;
+        let records = match &self.raw_cipher {
+            Some(cipher) => {
+                encrypted = cipher.encrypt_records(records)?;
+                &encrypted[..]
+            }
+            None => records,
         };
 
-        let mut tx = self.pool.begin().await?;
+        if let Some(clickhouse) = self.clickhouse.as_ref().filter(|ch| ch.replaces_history()) {
+            return self
+                .clickhouse_history_insert(clickhouse, records, manufacturer)
+                .await;
+        }
+
+        let table_name = self.history_table(manufacturer);
+
+        if let Some(partitions) = &self.partitions {
+            partitions
+                .ensure_partitions(&self.pool(), &table_name, records)
+                .await;
+        }
 
-        self.fallback_batch_insert(&mut tx, records.clone(), table_name)
+        // En tablas particionadas el índice único debe incluir la clave de partición
+        let conflict_target = match &self.partitions {
+            Some(partitions) => format!("uuid, {}", partitions.key_column()),
+            None => "uuid".to_string(),
+        };
+
+        let include_current_state = self.current_state_mode == CurrentStateMode::Transactional;
+        let (inserted, accepted) = self
+            .retrying_counted(&table_name, retries, || {
+                self.write_batch(
+                    &table_name,
+                    records,
+                    &conflict_target,
+                    include_current_state,
+                )
+            })
             .await?;
 
-        // Update current state
+        // En modo independiente un fallo del estado actual no afecta al histórico ya guardado
+        if self.current_state_mode == CurrentStateMode::Independent && !accepted.is_empty() {
+            let current_table = self.current_state_table();
+            match self
+                .retrying(&current_table, || self.write_current_state_batch(&accepted))
+                .await
+            {
+                Ok(()) => self.mirror_current_state(&accepted).await,
+                Err(e) => error!(
+                    category = %self.record_error(ErrorCategory::of_database(&e)),
+                    "❌ Error actualizando {} con {} registros (el histórico ya fue guardado): {}",
+                    current_table,
+                    accepted.len(),
+                    e
+                ),
+            }
+        } else if include_current_state {
+            self.mirror_current_state(&accepted).await;
+        }
+
+        // La copia en ClickHouse no debe frenar la escritura en PostgreSQL. Solo se
+        // copian las filas recién insertadas: un duplicado omitido por ON CONFLICT ya
+        // se copió la primera vez y ClickHouse no descarta filas repetidas por uuid.
+        if let Some(clickhouse) = &self.clickhouse {
+            if let Err(e) = clickhouse.insert_batch(&inserted, manufacturer).await {
+                error!(
+                    "❌ Error copiando {} registros a ClickHouse {}: {}",
+                    inserted.len(),
+                    clickhouse.table(manufacturer),
+                    e
+                );
+            }
+        }
+        self.index_positions(&accepted, manufacturer).await;
+
+        Ok(inserted.len())
+    }
 
-        self.fallback_batch_insert_current(&mut tx, &records)
+    /// Copia en Elasticsearch las posiciones ya guardadas; los errores solo se registran
+    async fn index_positions(&self, records: &[CommunicationRecord], manufacturer: Manufacturer) {
+        let Some(elasticsearch) = &self.elasticsearch else {
+            return;
+        };
+        if let Err(e) = elasticsearch.index_batch(records, manufacturer).await {
+            error!(
+                "❌ Error indexando {} registros en Elasticsearch: {}",
+                records.len(),
+                e
+            );
+        }
+    }
+
+    /// Histórico solo en ClickHouse; PostgreSQL conserva el estado actual
+    async fn clickhouse_history_insert(
+        &self,
+        clickhouse: &ClickHouseSink,
+        records: &[CommunicationRecord],
+        manufacturer: Manufacturer,
+    ) -> Result<usize> {
+        let persisted = clickhouse.insert_batch(records, manufacturer).await?;
+
+        if self.current_state_mode != CurrentStateMode::Disabled {
+            let current_table = self.current_state_table();
+            let result = self
+                .retrying(&current_table, || self.write_current_state_batch(records))
+                .await;
+            match result {
+                Err(e) if self.current_state_mode == CurrentStateMode::Transactional => {
+                    return Err(e)
+                }
+                Err(e) => error!(
+                    category = %self.record_error(ErrorCategory::of_database(&e)),
+                    "❌ Error actualizando {} con {} registros (el histórico ya fue guardado): {}",
+                    current_table,
+                    records.len(),
+                    e
+                ),
+                Ok(()) => self.mirror_current_state(records).await,
+            }
+        }
+        self.index_positions(records, manufacturer).await;
+
+        Ok(persisted)
+    }
+
+    /// Replica en Redis el estado actual ya guardado en PostgreSQL; los errores solo se registran
+    async fn mirror_current_state(&self, records: &[CommunicationRecord]) {
+        let Some(redis_state) = &self.redis_state else {
+            return;
+        };
+        if records.is_empty() {
+            return;
+        }
+
+        let latest = self.latest_per_device(records);
+        if let Err(e) = redis_state.write(&latest, self.current_state_order).await {
+            error!(
+                "❌ Error replicando {} registros del estado actual en Redis: {}",
+                latest.len(),
+                e
+            );
+        }
+    }
+
+    /// Ejecuta una escritura reintentándola ante errores transitorios (conexión, deadlock...)
+    async fn retrying<T, F, Fut>(&self, table_name: &str, operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.retrying_counted(table_name, &mut 0, operation).await
+    }
+
+    /// Como `retrying`, sumando a `retries` los reintentos hechos
+    async fn retrying_counted<T, F, Fut>(
+        &self,
+        table_name: &str,
+        retries: &mut u32,
+        mut operation: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.retry.max_attempts && is_transient_error(&e) => {
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "⚠️ Error transitorio escribiendo en {} (intento {}/{}): {}. Reintentando en {:?}",
+                        table_name, attempt, self.retry.max_attempts, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    *retries += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Escribe el lote en el histórico (y opcionalmente en el estado actual) dentro
+    /// de una transacción. Devuelve los registros nuevos en el histórico y los
+    /// registros aceptados (nuevos o ya existentes), que son los que deben
+    /// actualizar el estado actual.
+    async fn write_batch(
+        &self,
+        table_name: &str,
+        records: &[CommunicationRecord],
+        conflict_target: &str,
+        include_current_state: bool,
+    ) -> Result<(Vec<CommunicationRecord>, Vec<CommunicationRecord>)> {
+        let mut tx = self.pool().begin().await?;
+
+        let (inserted_uuids, history_errors) = self
+            .insert_isolated(&mut tx, table_name, records, |table, chunk| {
+                Self::history_insert_query(table, chunk, conflict_target, self.insert_mode)
+            })
+            .await?;
+
+        // Los registros rechazados en el histórico tampoco actualizan el estado actual
+        let mut rejected = Vec::with_capacity(history_errors.len());
+        let mut accepted = Vec::with_capacity(records.len());
+        let mut errors = history_errors.into_iter().peekable();
+        for (idx, record) in records.iter().enumerate() {
+            match errors.next_if(|(error_idx, _)| *error_idx == idx) {
+                Some((_, error)) => rejected.push(RejectedRecord {
+                    record: record.clone(),
+                    target_table: table_name.to_string(),
+                    error,
+                }),
+                None => accepted.push(record.clone()),
+            }
+        }
+        let inserted: Vec<CommunicationRecord> = accepted
+            .iter()
+            .filter(|record| inserted_uuids.contains(&record.uuid))
+            .cloned()
+            .collect();
+
+        // ON CONFLICT (uuid) DO NOTHING: las filas aceptadas no insertadas ya existían
+        let duplicates = accepted.len().saturating_sub(inserted.len());
+        if duplicates > 0 {
+            debug!(
+                "🔁 {} registros duplicados omitidos en {}",
+                duplicates, table_name
+            );
+        }
+
+        if include_current_state {
+            self.upsert_current_state(&mut tx, &accepted, &mut rejected)
+                .await?;
+        }
+
+        if !rejected.is_empty() {
+            self.insert_rejected(&mut tx, &rejected).await;
+        }
+
+        tx.commit().await?;
+        Ok((inserted, accepted))
+    }
+
+    /// Actualiza el estado actual en su propia transacción
+    async fn write_current_state_batch(&self, records: &[CommunicationRecord]) -> Result<()> {
+        let mut tx = self.pool().begin().await?;
+
+        let mut rejected = Vec::new();
+        self.upsert_current_state(&mut tx, records, &mut rejected)
             .await?;
+        if !rejected.is_empty() {
+            self.insert_rejected(&mut tx, &rejected).await;
+        }
 
         tx.commit().await?;
         Ok(())
     }
 
-    /// Fallback: Inserción por lotes usando INSERT con múltiples valores
-    async fn fallback_batch_insert(
+    /// Upsert del estado actual, agregando a `rejected` las filas que fallen
+    async fn upsert_current_state(
         &self,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-        records: Vec<CommunicationRecord>,
-        table_name: &str,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        records: &[CommunicationRecord],
+        rejected: &mut Vec<RejectedRecord>,
     ) -> Result<()> {
+        let current_table = self.current_state_table();
+
+        // Un mismo INSERT ... ON CONFLICT no puede actualizar dos veces la misma fila
+        let records = &self.latest_per_device(records);
+
+        let (_, current_errors) = self
+            .insert_isolated(tx, &current_table, records, |table, chunk| {
+                Self::current_state_upsert_query(
+                    table,
+                    chunk,
+                    self.current_state_order,
+                    self.insert_mode,
+                )
+            })
+            .await?;
+        for (idx, error) in current_errors {
+            rejected.push(RejectedRecord {
+                record: records[idx].clone(),
+                target_table: current_table.clone(),
+                error,
+            });
+        }
+        Ok(())
+    }
+
+    /// Ejecuta el INSERT por chunks dentro de savepoints. Si un chunk falla se
+    /// reintenta fila por fila para persistir las válidas; devuelve los valores de
+    /// `RETURNING` (los uuid insertados, vacío si la consulta no lo pide) junto con
+    /// el índice y el error de cada registro rechazado.
+    async fn insert_isolated(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        table_name: &str,
+        records: &[CommunicationRecord],
+        build_query: impl for<'r> Fn(&str, &'r [CommunicationRecord]) -> QueryBuilder<'r, Postgres>,
+    ) -> Result<(HashSet<String>, Vec<(usize, String)>)> {
         // Dividir en chunks más pequeños para evitar límites de PostgreSQL
         const CHUNK_SIZE: usize = 100;
 
-        for chunk in records.chunks(CHUNK_SIZE) {
-            let query = format!(
-                "INSERT INTO {} (
-                    uuid, device_id, backup_battery_voltage, backup_battery_percent, cell_id, course, delivery_type,
-                    engine_status, firmware, fix_status, gps_datetime, gps_epoch, idle_time,
-                    lac, latitude, longitude, main_battery_voltage, mcc, mnc, model,
-                    msg_class, msg_counter, alert_type, network_status, odometer, rx_lvl, satellites,
-                    speed, speed_time, total_distance, trip_distance, trip_hourmeter,
-                    bytes_count, client_ip, client_port, decoded_epoch, received_epoch,
-                    raw_message, received_at, created_at
-                ) ",
-                table_name
-            );
-            let mut query_builder = sqlx::QueryBuilder::new(query);
-
-            query_builder.push_values(chunk, |mut b, record| {
-                b.push_bind(&record.uuid)
-                    .push_bind(&record.device_id)
-                    .push_bind(record.backup_battery_voltage)
-                    .push_bind(record.backup_battery_percent)
-                    .push_bind(&record.cell_id)
-                    .push_bind(record.course)
-                    .push_bind(&record.delivery_type)
-                    .push_bind(&record.engine_status)
-                    .push_bind(&record.firmware)
-                    .push_bind(&record.fix_status)
-                    .push_bind(record.gps_datetime)
-                    .push_bind(record.gps_epoch)
-                    .push_bind(record.idle_time)
-                    .push_bind(&record.lac)
-                    .push_bind(record.latitude)
-                    .push_bind(record.longitude)
-                    .push_bind(record.main_battery_voltage)
-                    .push_bind(&record.mcc)
-                    .push_bind(&record.mnc)
-                    .push_bind(&record.model)
-                    .push_bind(&record.msg_class)
-                    .push_bind(record.msg_counter)
-                    .push_bind(&record.alert_type)
-                    .push_bind(&record.network_status)
-                    .push_bind(record.odometer)
-                    .push_bind(record.rx_lvl)
-                    .push_bind(record.satellites)
-                    .push_bind(record.speed)
-                    .push_bind(record.speed_time)
-                    .push_bind(record.total_distance)
-                    .push_bind(record.trip_distance)
-                    .push_bind(record.trip_hourmeter)
-                    .push_bind(record.bytes_count)
-                    .push_bind(&record.client_ip)
-                    .push_bind(record.client_port)
-                    .push_bind(record.decoded_epoch)
-                    .push_bind(record.received_epoch)
-                    .push_bind(&record.raw_message)
-                    .push_bind(record.received_at)
-                    .push_bind(record.created_at);
-            });
+        let mut returned = HashSet::new();
+        let mut rejected = Vec::new();
 
-            match query_builder.build().execute(&mut **tx).await {
-                Ok(_) => {}
+        for (chunk_idx, chunk) in records.chunks(CHUNK_SIZE).enumerate() {
+            let mut savepoint = tx.begin().await?;
+            match build_query(table_name, chunk)
+                .build()
+                .fetch_all(&mut *savepoint)
+                .await
+            {
+                Ok(rows) => {
+                    savepoint.commit().await?;
+                    returned.extend(Self::returned_uuids(&rows)?);
+                    continue;
+                }
+                // Un error transitorio afecta a todo el lote: se propaga para reintentarlo
+                Err(e) if is_transient_sqlx_error(&e) => return Err(e.into()),
                 Err(e) => {
-                    error!("❌ Error insertando batch en {}: {}", table_name, e);
-                    // Log de los registros problemáticos
-                    for (idx, record) in chunk.iter().enumerate() {
-                        warn!(
-                            "📝 Registro #{} - Device: {}, UUID: {}, Cell ID len: {}, LAC len: {}, MCC len: {}, MNC len: {}",
-                            idx,
-                            record.device_id,
-                            record.uuid,
-                            record.cell_id.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.lac.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.mcc.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.mnc.as_ref().map(|s| s.len()).unwrap_or(0),
+                    savepoint.rollback().await?;
+                    warn!(
+                        "⚠️ Error insertando chunk de {} registros en {}: {}. Reintentando fila por fila",
+                        chunk.len(),
+                        table_name,
+                        e
+                    );
+                }
+            }
+
+            for (offset, record) in chunk.iter().enumerate() {
+                let mut savepoint = tx.begin().await?;
+                match build_query(table_name, std::slice::from_ref(record))
+                    .build()
+                    .fetch_all(&mut *savepoint)
+                    .await
+                {
+                    Ok(rows) => {
+                        savepoint.commit().await?;
+                        returned.extend(Self::returned_uuids(&rows)?);
+                    }
+                    Err(e) if is_transient_sqlx_error(&e) => return Err(e.into()),
+                    Err(e) => {
+                        savepoint.rollback().await?;
+                        let code = e.as_database_error().and_then(|db_error| db_error.code());
+                        error!(
+                            category = %self.record_error(ErrorCategory::of_sqlstate(code.as_deref())),
+                            "❌ Registro rechazado en {} - Device: {}, UUID: {}: {}",
+                            table_name, redact::device_id(&record.device_id), record.uuid, e
                         );
-                        // Log campos que comúnmente tienen límites VARCHAR(10)
-                        Self::log_field_if_too_long("cell_id", record.cell_id.as_deref(), 10);
-                        Self::log_field_if_too_long("lac", record.lac.as_deref(), 10);
-                        Self::log_field_if_too_long("mcc", record.mcc.as_deref(), 10);
-                        Self::log_field_if_too_long("mnc", record.mnc.as_deref(), 10);
-                        Self::log_field_if_too_long("model", record.model.as_deref(), 50);
-                        Self::log_field_if_too_long("firmware", record.firmware.as_deref(), 50);
-                        Self::log_field_if_too_long("msg_class", record.msg_class.as_deref(), 20);
+                        Self::log_record_diagnostics(record);
+                        rejected.push((chunk_idx * CHUNK_SIZE + offset, e.to_string()));
                     }
-                    return Err(e.into());
                 }
             }
         }
 
-        Ok(())
+        Ok((returned, rejected))
     }
 
-    /// Helper para loguear campos que exceden el límite
-    fn log_field_if_too_long(field_name: &str, value: Option<&str>, max_len: usize) {
-        if let Some(val) = value {
-            if val.len() > max_len {
+    /// Lee la columna `uuid` de las filas devueltas por `RETURNING`
+    fn returned_uuids(rows: &[sqlx::postgres::PgRow]) -> Result<Vec<String>> {
+        rows.iter()
+            .map(|row| Ok(row.try_get::<String, _>("uuid")?))
+            .collect()
+    }
+
+    /// Guarda los registros rechazados en communications_rejected. Un fallo aquí
+    /// solo se registra en logs para no perder las filas válidas del lote.
+    async fn insert_rejected(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        rejected: &[RejectedRecord],
+    ) {
+        let table_name = self.rejected_table();
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} (uuid, device_id, target_table, error_message, raw_message, record) ",
+            table_name
+        ));
+
+        query_builder.push_values(rejected, |mut b, rejected| {
+            b.push_bind(&rejected.record.uuid)
+                .push_bind(&rejected.record.device_id)
+                .push_bind(&rejected.target_table)
+                .push_bind(&rejected.error)
+                .push_bind(&rejected.record.raw_message)
+                .push_bind(sqlx::types::Json(&rejected.record));
+        });
+
+        let result = async {
+            let mut savepoint = tx.begin().await?;
+            query_builder.build().execute(&mut *savepoint).await?;
+            savepoint.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                warn!(
+                    "🗃️ {} registros rechazados guardados en {}",
+                    rejected.len(),
+                    table_name
+                );
+                for rejected in rejected {
+                    error_reporter::report(
+                        Incident::new(
+                            "rejected",
+                            format!(
+                                "Registro rechazado en {} y guardado en {}: {}",
+                                rejected.target_table, table_name, rejected.error
+                            ),
+                        )
+                        .with_category(ErrorCategory::DbConstraint)
+                        .with_device(&rejected.record.device_id, &rejected.record.uuid),
+                    );
+                }
+            }
+            Err(e) => {
                 error!(
-                    "🚨 Campo '{}' excede límite: longitud {} > {}, valor: '{}'",
-                    field_name,
-                    val.len(),
-                    max_len,
-                    val
+                    "❌ Error guardando {} registros rechazados en {}: {}",
+                    rejected.len(),
+                    table_name,
+                    e
+                );
+                error_reporter::report(
+                    Incident::new(
+                        "rejected",
+                        format!(
+                            "{} registros rechazados perdidos: no se pudieron guardar en {}: {}",
+                            rejected.len(),
+                            table_name,
+                            e
+                        ),
+                    )
+                    .with_category(ErrorCategory::of_sqlstate(
+                        e.as_database_error()
+                            .and_then(|db_error| db_error.code())
+                            .as_deref(),
+                    )),
                 );
             }
         }
     }
 
-    /// Fallback: Inserción por lotes usando INSERT con múltiples valores on communications_current_state
-    async fn fallback_batch_insert_current(
-        &self,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-        records: &[CommunicationRecord],
-    ) -> Result<()> {
-        // Dividir en chunks más pequeños para evitar límites de PostgreSQL
-        const CHUNK_SIZE: usize = 100;
-
-        for chunk in records.chunks(CHUNK_SIZE) {
-            let mut query_builder = sqlx::QueryBuilder::new(
-                r#"INSERT INTO communications_current_state (
-                    uuid, device_id, backup_battery_voltage, backup_battery_percent, cell_id, course, delivery_type,
-                    engine_status, firmware, fix_status, gps_datetime, gps_epoch, idle_time,
-                    lac, latitude, longitude, main_battery_voltage, mcc, mnc, model,
-                    msg_class, msg_counter, alert_type, network_status, odometer, rx_lvl, satellites,
-                    speed, speed_time, total_distance, trip_distance, trip_hourmeter,
-                    bytes_count, client_ip, client_port, decoded_epoch, received_epoch,
-                    raw_message, received_at, created_at
-                ) "#,
-            );
+    /// INSERT multi-valor sobre una tabla de histórico, ignorando UUIDs ya guardados.
+    /// Devuelve el uuid de cada fila insertada para no copiar duplicados a ClickHouse.
+    pub fn history_insert_query<'r>(
+        table_name: &str,
+        chunk: &'r [CommunicationRecord],
+        conflict_target: &str,
+        mode: InsertMode,
+    ) -> QueryBuilder<'r, Postgres> {
+        let mut query_builder =
+            QueryBuilder::new(format!("INSERT INTO {} ({}) ", table_name, RECORD_COLUMNS));
+        Self::push_record_rows(&mut query_builder, chunk, mode);
+        query_builder.push(format!(
+            " ON CONFLICT ({}) DO NOTHING RETURNING uuid",
+            conflict_target
+        ));
+        query_builder
+    }
 
-            query_builder.push_values(chunk, |mut b, record| {
-                b.push_bind(&record.uuid)
-                    .push_bind(&record.device_id)
-                    .push_bind(record.backup_battery_voltage)
-                    .push_bind(record.backup_battery_percent)
-                    .push_bind(&record.cell_id)
-                    .push_bind(record.course)
-                    .push_bind(&record.delivery_type)
-                    .push_bind(&record.engine_status)
-                    .push_bind(&record.firmware)
-                    .push_bind(&record.fix_status)
-                    .push_bind(record.gps_datetime)
-                    .push_bind(record.gps_epoch)
-                    .push_bind(record.idle_time)
-                    .push_bind(&record.lac)
-                    .push_bind(record.latitude)
-                    .push_bind(record.longitude)
-                    .push_bind(record.main_battery_voltage)
-                    .push_bind(&record.mcc)
-                    .push_bind(&record.mnc)
-                    .push_bind(&record.model)
-                    .push_bind(&record.msg_class)
-                    .push_bind(record.msg_counter)
-                    .push_bind(&record.alert_type)
-                    .push_bind(&record.network_status)
-                    .push_bind(record.odometer)
-                    .push_bind(record.rx_lvl)
-                    .push_bind(record.satellites)
-                    .push_bind(record.speed)
-                    .push_bind(record.speed_time)
-                    .push_bind(record.total_distance)
-                    .push_bind(record.trip_distance)
-                    .push_bind(record.trip_hourmeter)
-                    .push_bind(record.bytes_count)
-                    .push_bind(&record.client_ip)
-                    .push_bind(record.client_port)
-                    .push_bind(record.decoded_epoch)
-                    .push_bind(record.received_epoch)
-                    .push_bind(&record.raw_message)
-                    .push_bind(record.received_at)
-                    .push_bind(record.created_at);
-            });
+    /// INSERT multi-valor con upsert por (device_id, msg_class) sobre el estado actual
+    pub fn current_state_upsert_query<'r>(
+        table_name: &str,
+        chunk: &'r [CommunicationRecord],
+        order: Option<CurrentStateOrder>,
+        mode: InsertMode,
+    ) -> QueryBuilder<'r, Postgres> {
+        let mut query_builder = QueryBuilder::new(format!(
+            "INSERT INTO {} AS current_state ({}) ",
+            table_name, RECORD_COLUMNS
+        ));
+        Self::push_record_rows(&mut query_builder, chunk, mode);
 
-            query_builder.push(
-                r#"
+        query_builder.push(
+            r#"
                 ON CONFLICT (device_id, msg_class) DO UPDATE SET
                     uuid = EXCLUDED.uuid,
                     backup_battery_voltage = EXCLUDED.backup_battery_voltage,
@@ -346,59 +1876,367 @@ impl DatabaseService {
                     received_epoch = EXCLUDED.received_epoch,
                     raw_message = EXCLUDED.raw_message,
                     received_at = NOW(),
-                    created_at = EXCLUDED.created_at
+                    created_at = EXCLUDED.created_at,
+                    quality_score = EXCLUDED.quality_score,
+                    decoded_extra = EXCLUDED.decoded_extra,
+                    ignition = EXCLUDED.ignition,
+                    panic_button = EXCLUDED.panic_button,
+                    door_sensor = EXCLUDED.door_sensor,
+                    relay_output = EXCLUDED.relay_output
                 "#,
-            );
+        );
 
-            match query_builder.build().execute(&mut **tx).await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(
-                        "❌ Error insertando batch en communications_current_state: {}",
-                        e
-                    );
-                    // Log de los registros problemáticos
-                    for (idx, record) in chunk.iter().enumerate() {
-                        warn!(
-                            "📝 Registro #{} - Device: {}, UUID: {}, Cell ID len: {}, LAC len: {}, MCC len: {}, MNC len: {}",
-                            idx,
-                            record.device_id,
-                            record.uuid,
-                            record.cell_id.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.lac.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.mcc.as_ref().map(|s| s.len()).unwrap_or(0),
-                            record.mnc.as_ref().map(|s| s.len()).unwrap_or(0),
-                        );
-                        // Log campos que comúnmente tienen límites VARCHAR(10)
-                        Self::log_field_if_too_long("cell_id", record.cell_id.as_deref(), 10);
-                        Self::log_field_if_too_long("lac", record.lac.as_deref(), 10);
-                        Self::log_field_if_too_long("mcc", record.mcc.as_deref(), 10);
-                        Self::log_field_if_too_long("mnc", record.mnc.as_deref(), 10);
-                        Self::log_field_if_too_long("model", record.model.as_deref(), 50);
-                        Self::log_field_if_too_long("firmware", record.firmware.as_deref(), 50);
-                        Self::log_field_if_too_long("msg_class", record.msg_class.as_deref(), 20);
+        // Un mensaje atrasado no debe hacer retroceder el estado guardado
+        if let Some(order) = order {
+            let column = order.column();
+            query_builder.push(format!(
+                " WHERE current_state.{column} IS NULL OR EXCLUDED.{column} >= current_state.{column}"
+            ));
+        }
+
+        query_builder
+    }
+
+    /// Conserva un registro por (device_id, msg_class): el más nuevo según la columna
+    /// de orden o, sin ella, el último del lote
+    fn latest_per_device(&self, records: &[CommunicationRecord]) -> Vec<CommunicationRecord> {
+        let mut positions: HashMap<(&str, Option<&str>), usize> = HashMap::new();
+        let mut latest: Vec<CommunicationRecord> = Vec::with_capacity(records.len());
+
+        for record in records {
+            let key = (record.device_id.as_str(), record.msg_class.as_deref());
+            match positions.get(&key) {
+                Some(&idx) => {
+                    let newer = match self.current_state_order {
+                        Some(order) => {
+                            order_value(record, order) >= order_value(&latest[idx], order)
+                        }
+                        None => true,
+                    };
+                    if newer {
+                        latest[idx] = record.clone();
                     }
-                    return Err(e.into());
+                }
+                None => {
+                    positions.insert(key, latest.len());
+                    latest.push(record.clone());
                 }
             }
         }
 
-        Ok(())
+        latest
+    }
+
+    /// Agrega los VALUES de cada registro en el orden de RECORD_COLUMNS
+    fn push_record_values<'r>(
+        query_builder: &mut QueryBuilder<'r, Postgres>,
+        chunk: &'r [CommunicationRecord],
+    ) {
+        query_builder.push_values(chunk, |mut b, record| {
+            b.push_bind(&record.uuid)
+                .push_bind(&record.device_id)
+                .push_bind(record.backup_battery_voltage)
+                .push_bind(record.backup_battery_percent)
+                .push_bind(&record.cell_id)
+                .push_bind(record.course)
+                .push_bind(&record.delivery_type)
+                .push_bind(&record.engine_status)
+                .push_bind(&record.firmware)
+                .push_bind(&record.fix_status)
+                .push_bind(record.gps_datetime)
+                .push_bind(record.gps_epoch)
+                .push_bind(record.idle_time)
+                .push_bind(&record.lac)
+                .push_bind(record.latitude)
+                .push_bind(record.longitude)
+                .push_bind(record.main_battery_voltage)
+                .push_bind(&record.mcc)
+                .push_bind(&record.mnc)
+                .push_bind(&record.model)
+                .push_bind(&record.msg_class)
+                .push_bind(record.msg_counter)
+                .push_bind(&record.alert_type)
+                .push_bind(&record.network_status)
+                .push_bind(record.odometer)
+                .push_bind(record.rx_lvl)
+                .push_bind(record.satellites)
+                .push_bind(record.speed)
+                .push_bind(record.speed_time)
+                .push_bind(record.total_distance)
+                .push_bind(record.trip_distance)
+                .push_bind(record.trip_hourmeter)
+                .push_bind(record.bytes_count)
+                .push_bind(&record.client_ip)
+                .push_bind(record.client_port)
+                .push_bind(record.decoded_epoch)
+                .push_bind(record.received_epoch)
+                .push_bind(&record.raw_message)
+                .push_bind(record.received_at)
+                .push_bind(record.created_at)
+                .push_bind(record.quality_score)
+                .push_bind(&record.decoded_extra)
+                .push_bind(record.ignition)
+                .push_bind(record.panic_button)
+                .push_bind(record.door_sensor)
+                .push_bind(record.relay_output);
+        });
+    }
+
+    /// Filas del chunk como un arreglo por columna (`SELECT * FROM UNNEST(...)`): el
+    /// texto de la consulta no depende del tamaño del chunk, así que PostgreSQL reutiliza
+    /// el statement preparado
+    fn push_record_arrays<'r>(
+        query_builder: &mut QueryBuilder<'r, Postgres>,
+        chunk: &'r [CommunicationRecord],
+    ) {
+        query_builder.push("SELECT * FROM UNNEST(");
+        let mut columns = query_builder.separated(", ");
+        columns.push_bind(chunk.iter().map(|r| r.uuid.as_str()).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.device_id.as_str())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.backup_battery_voltage)
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.backup_battery_percent)
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.cell_id.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.course).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.delivery_type.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.engine_status.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.firmware.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.fix_status.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.gps_datetime).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.gps_epoch).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.idle_time).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.lac.as_deref()).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.latitude).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.longitude).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.main_battery_voltage)
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.mcc.as_deref()).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.mnc.as_deref()).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.model.as_deref()).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.msg_class.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.msg_counter).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.alert_type.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.network_status.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.odometer).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.rx_lvl).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.satellites).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.speed).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.speed_time).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.total_distance).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.trip_distance).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.trip_hourmeter).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.bytes_count).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.client_ip.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.client_port).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.decoded_epoch).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.received_epoch).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.raw_message.as_deref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.received_at).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.created_at).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.quality_score).collect::<Vec<_>>());
+        columns.push_bind(
+            chunk
+                .iter()
+                .map(|r| r.decoded_extra.as_ref())
+                .collect::<Vec<_>>(),
+        );
+        columns.push_bind(chunk.iter().map(|r| r.ignition).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.panic_button).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.door_sensor).collect::<Vec<_>>());
+        columns.push_bind(chunk.iter().map(|r| r.relay_output).collect::<Vec<_>>());
+        query_builder.push(")");
+    }
+
+    /// Agrega las filas del chunk con la forma configurada
+    fn push_record_rows<'r>(
+        query_builder: &mut QueryBuilder<'r, Postgres>,
+        chunk: &'r [CommunicationRecord],
+        mode: InsertMode,
+    ) {
+        match mode {
+            InsertMode::Values => Self::push_record_values(query_builder, chunk),
+            InsertMode::Unnest => Self::push_record_arrays(query_builder, chunk),
+        }
+    }
+
+    /// Loguea los campos que comúnmente exceden los límites de las columnas
+    fn log_record_diagnostics(record: &CommunicationRecord) {
+        warn!(
+            "📝 Device: {}, UUID: {}, Cell ID len: {}, LAC len: {}, MCC len: {}, MNC len: {}",
+            redact::device_id(&record.device_id),
+            record.uuid,
+            record.cell_id.as_ref().map(|s| s.len()).unwrap_or(0),
+            record.lac.as_ref().map(|s| s.len()).unwrap_or(0),
+            record.mcc.as_ref().map(|s| s.len()).unwrap_or(0),
+            record.mnc.as_ref().map(|s| s.len()).unwrap_or(0),
+        );
+        // Log campos que comúnmente tienen límites VARCHAR(10)
+        Self::log_field_if_too_long("cell_id", record.cell_id.as_deref(), 10);
+        Self::log_field_if_too_long("lac", record.lac.as_deref(), 10);
+        Self::log_field_if_too_long("mcc", record.mcc.as_deref(), 10);
+        Self::log_field_if_too_long("mnc", record.mnc.as_deref(), 10);
+        Self::log_field_if_too_long("model", record.model.as_deref(), 50);
+        Self::log_field_if_too_long("firmware", record.firmware.as_deref(), 50);
+        Self::log_field_if_too_long("msg_class", record.msg_class.as_deref(), 20);
+    }
+
+    /// Helper para loguear campos que exceden el límite
+    fn log_field_if_too_long(field_name: &str, value: Option<&str>, max_len: usize) {
+        if let Some(val) = value {
+            if val.len() > max_len {
+                error!(
+                    "🚨 Campo '{}' excede límite: longitud {} > {}, valor: '{}'",
+                    field_name,
+                    val.len(),
+                    max_len,
+                    val
+                );
+            }
+        }
     }
 
     /// Obtiene el tamaño actual del buffer
     pub async fn buffer_size(&self) -> usize {
-        self.buffer.read().await.len()
+        self.buffer.read().await.records.len()
+    }
+
+    /// Antigüedad del registro más antiguo del buffer (None si está vacío)
+    pub async fn buffer_age(&self) -> Option<Duration> {
+        self.buffer.read().await.since.map(|since| since.elapsed())
     }
 
     /// Verifica el estado de salud de la conexión
     pub async fn health_check(&self) -> Result<bool> {
-        match sqlx::query("SELECT 1").fetch_one(&self.pool).await {
-            Ok(_) => Ok(true),
+        let healthy = match sqlx::query_scalar::<_, String>("SHOW transaction_read_only")
+            .fetch_one(&self.pool())
+            .await
+        {
+            Ok(read_only) if read_only == "off" => true,
+            Ok(_) => {
+                warn!("⚠️ El host de PostgreSQL activo pasó a solo lectura");
+                false
+            }
             Err(e) => {
                 error!("Database health check failed: {}", e);
-                Ok(false)
+                false
             }
+        };
+
+        // Con varios hosts configurados, buscar un nuevo primario
+        if healthy || self.urls.len() < 2 {
+            return Ok(healthy);
         }
+        Ok(self.failover().await)
+    }
+}
+
+/// Indica si un error de escritura es transitorio (conexión caída, pool agotado,
+/// deadlock, reinicio del servidor) y por tanto vale la pena reintentar.
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<sqlx::Error>()
+        .is_some_and(is_transient_sqlx_error)
+        // ClickHouse caído con CLICKHOUSE_MODE=replace: el lote se retiene igual que con PostgreSQL
+        || ch_sink::is_retryable(error)
+}
+
+fn is_transient_sqlx_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => true,
+        sqlx::Error::Database(db_error) => db_error.code().is_some_and(|code| {
+            // 08: conexión, 25006: host en solo lectura (tras un failover del cluster),
+            // 40001/40P01: serialización/deadlock, 53: recursos, 57P0x: shutdown
+            code.starts_with("08")
+                || code.starts_with("53")
+                || matches!(
+                    code.as_ref(),
+                    "25006" | "40001" | "40P01" | "57P01" | "57P02" | "57P03"
+                )
+        }),
+        _ => false,
+    }
+}
+
+/// `host:puerto` de una URL de conexión, para logs sin credenciales
+fn host_label(url: &str) -> &str {
+    let address = url.rsplit_once('@').map_or(url, |(_, rest)| rest);
+    address.split('/').next().unwrap_or(address)
+}
+
+/// Valor de la columna de orden de un registro, como epoch en segundos
+pub fn order_value(record: &CommunicationRecord, order: CurrentStateOrder) -> Option<i64> {
+    match order {
+        CurrentStateOrder::GpsEpoch => record.gps_epoch,
+        CurrentStateOrder::GpsDatetime => record.gps_datetime.map(|dt| dt.and_utc().timestamp()),
+        CurrentStateOrder::ReceivedEpoch => record.received_epoch,
+        CurrentStateOrder::DecodedEpoch => record.decoded_epoch,
     }
 }
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, info, warn};
+
+use crate::config::{DeviceAckConfig, ShardConfig};
+use crate::models::{server_ack, ServerAck};
+use crate::redact;
+use crate::services::mqtt_publisher::MqttPublisher;
+use crate::services::sharding::shard_of;
+
+/// Publica en MQTT las confirmaciones que esperan los equipos Queclink (`+SACK`)
+/// para que el front-end TCP las reenvíe y el equipo no retransmita la trama. Cada
+/// confirmación es un JSON con `device_id`, `report`, `msg_num` y la trama `frame`.
+///
+/// Una trama de datos se confirma cuando llega al procesador; una que no se pudo
+/// decodificar no se confirma, para que el equipo la reenvíe. Los heartbeat se
+/// confirman siempre. La publicación nunca frena el consumo; con la cola llena la
+/// confirmación se pierde y el equipo reenvía la trama, que se descarta como
+/// duplicada.
+pub struct DeviceAckPublisher {
+    publisher: MqttPublisher,
+    topic: String,
+    reports: Vec<String>,
+    // (índice, cantidad): solo se confirman los dispositivos de este shard
+    shard: Option<(u32, u32)>,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// Confirmaciones desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceAckStatistics {
+    pub sent: u64,
+    /// No encoladas por cola llena o conexión terminada
+    pub dropped: u64,
+}
+
+impl DeviceAckPublisher {
+    pub fn new(config: &DeviceAckConfig, shard: Option<&ShardConfig>) -> Result<Self> {
+        info!(
+            "📨 Confirmaciones Queclink en {} (tópico: {}, QoS {}, reportes: {})",
+            config.mqtt.url,
+            config.mqtt.topic,
+            config.mqtt.qos,
+            match config.reports.is_empty() {
+                true => "todos".to_string(),
+                false => config.reports.join(","),
+            }
+        );
+        Ok(Self {
+            publisher: MqttPublisher::connect(&config.mqtt)?,
+            topic: config.mqtt.topic.clone(),
+            reports: config.reports.clone(),
+            shard: shard.map(|shard| (shard.index, shard.count)),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Publica la confirmación si es de un reporte configurado y el dispositivo es de
+    /// este shard
+    pub fn acknowledge(&self, ack: &ServerAck) {
+        let wanted = self.reports.is_empty() || self.reports.contains(&ack.report);
+        let owned = self
+            .shard
+            .is_none_or(|(index, count)| shard_of(&ack.device_id, count) == index);
+        if !wanted || !owned {
+            return;
+        }
+
+        let level = |value: &str| value.replace(['/', '+', '#'], "_");
+        let topic = self
+            .topic
+            .replace("{device_id}", &level(&ack.device_id))
+            .replace("{msg_num}", &ack.msg_num);
+        let payload = serde_json::to_vec(ack).unwrap_or_default();
+        if self.publisher.try_publish(topic, payload) {
+            self.sent.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "📨 {} confirmado al dispositivo {} ({})",
+                ack.report,
+                redact::device_id(&ack.device_id),
+                ack.msg_num
+            );
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠️ Confirmación {} del dispositivo {} descartada: cola MQTT llena",
+                ack.msg_num,
+                redact::device_id(&ack.device_id)
+            );
+        }
+    }
+
+    /// Confirma la trama si es un heartbeat, que no trae posición y no llega al
+    /// procesador. Devuelve false para cualquier otra trama.
+    pub fn acknowledge_heartbeat(&self, raw: &[u8]) -> bool {
+        let heartbeat = std::str::from_utf8(raw)
+            .ok()
+            .and_then(server_ack)
+            .filter(is_heartbeat);
+        if let Some(ack) = &heartbeat {
+            self.acknowledge(ack);
+        }
+        heartbeat.is_some()
+    }
+
+    /// Termina cuando el broker rechaza las credenciales; nunca si no lo hace
+    pub async fn auth_failure(&self) {
+        self.publisher.auth_failure().await
+    }
+
+    pub fn statistics(&self) -> DeviceAckStatistics {
+        DeviceAckStatistics {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Los heartbeat no traen posición: el consumer los confirma y no los cuenta como
+/// error de decodificación
+fn is_heartbeat(ack: &ServerAck) -> bool {
+    ack.report == "GTHBD"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queclink_frames_get_their_server_ack() {
+        let report = server_ack(
+            "+RESP:GTFRI,060100,862524060204951,GV300,,10,1,1,12.4,87,2240.0,-99.133209,19.432608,20240501120000,0334,0020,1A2B,3C4D,00,,,,,100,210100,,,,20240501120001,0F2A$",
+        )
+        .unwrap();
+        assert_eq!(report.device_id, "862524060204951");
+        assert_eq!(report.report, "GTFRI");
+        assert_eq!(report.frame, "+SACK:0F2A$");
+
+        let heartbeat =
+            server_ack("+ACK:GTHBD,060100,862524060204951,GV300,20240501120000,11F0$").unwrap();
+        assert!(is_heartbeat(&heartbeat));
+        assert_eq!(heartbeat.frame, "+SACK:GTHBD,060100,11F0$");
+
+        // Sin contador válido, de otro fabricante o una confirmación del servidor
+        assert_eq!(server_ack("+RESP:GTFRI,060100,862524060204951$"), None);
+        assert_eq!(server_ack("ST300STT;900000001;04;1097B;20240501"), None);
+        assert_eq!(server_ack("+SACK:0F2A$"), None);
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::Message;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::config::{BrokerConfig, DeviceCommandConfig};
+use crate::models::{CommandSource, CommandStatus, DeviceCommand, DeviceCommandRecord};
+use crate::redact;
+use crate::services::mqtt_publisher::MqttPublisher;
+use crate::services::{DatabaseService, KafkaConsumerService};
+
+/// Reenvía a los equipos los comandos recibidos por Kafka o por la API de
+/// administración: cada comando se guarda en `device_commands` como `pending`, se
+/// publica como JSON en el tópico MQTT de bajada del dispositivo (del que lo toma el
+/// front-end TCP) y queda `sent` cuando el broker lo confirma, o `failed` si no se
+/// pudo encolar o no llegó la confirmación a tiempo.
+///
+/// Los comandos de un mismo dispositivo se publican en el orden en que llegan. Un
+/// comando que llega de nuevo con el mismo id no se reenvía.
+pub struct CommandForwarder {
+    publisher: MqttPublisher,
+    database: Arc<DatabaseService>,
+    topic: String,
+    timeout: Duration,
+    sent: AtomicU64,
+    failed: AtomicU64,
+    duplicates: AtomicU64,
+}
+
+/// Comandos desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandStatistics {
+    pub sent: u64,
+    pub failed: u64,
+    /// Con un id ya recibido, descartados
+    pub duplicates: u64,
+}
+
+impl CommandForwarder {
+    pub fn new(config: &DeviceCommandConfig, database: Arc<DatabaseService>) -> Result<Self> {
+        info!(
+            "📟 Comandos a los equipos en {} (tópico: {}, QoS {}, fuente Kafka: {})",
+            config.mqtt.url,
+            config.mqtt.topic,
+            config.mqtt.qos,
+            config.kafka_topic.as_deref().unwrap_or("-")
+        );
+        Ok(Self {
+            publisher: MqttPublisher::connect(&config.mqtt)?,
+            database,
+            topic: config.mqtt.topic.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            sent: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            duplicates: AtomicU64::new(0),
+        })
+    }
+
+    /// Guarda el comando y lo encola para publicarlo; la confirmación del broker se
+    /// espera en segundo plano. Devuelve false si el id ya se había recibido.
+    pub async fn submit(
+        self: &Arc<Self>,
+        command: DeviceCommand,
+        source: CommandSource,
+    ) -> Result<bool> {
+        if command.device_id.trim().is_empty() || command.command.is_empty() {
+            return Err(anyhow!("comando sin device_id o sin trama"));
+        }
+        if !self
+            .database
+            .insert_device_command(&command, source)
+            .await?
+        {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+            debug!("📟 Comando {} repetido, no se reenvía", command.id);
+            return Ok(false);
+        }
+
+        let payload = serde_json::to_vec(&command)?;
+        let Some(confirmed) = self
+            .publisher
+            .try_publish_tracked(self.topic_for(&command), payload)
+        else {
+            self.finish(&command, Err("cola MQTT llena".to_string()))
+                .await;
+            return Ok(true);
+        };
+
+        let forwarder = self.clone();
+        tokio::spawn(async move {
+            let outcome = match tokio::time::timeout(forwarder.timeout, confirmed).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(_)) => Err("la conexión MQTT terminó".to_string()),
+                Err(_) => Err(format!(
+                    "sin confirmación del broker en {}s",
+                    forwarder.timeout.as_secs()
+                )),
+            };
+            forwarder.finish(&command, outcome).await;
+        });
+        Ok(true)
+    }
+
+    /// Lee comandos JSON (`DeviceCommand`) del tópico Kafka con un group id propio.
+    /// Con varias instancias cada comando lo reenvía solo una.
+    pub fn start_kafka(
+        self: Arc<Self>,
+        broker: &BrokerConfig,
+        topic: &str,
+    ) -> Result<JoinHandle<()>> {
+        let consumer: StreamConsumer = KafkaConsumerService::client_config(broker)
+            .set("group.id", format!("{}-commands", broker.group_id))
+            .set("auto.offset.reset", "latest")
+            .set("enable.auto.commit", "true")
+            .create()?;
+        consumer.subscribe(&[topic])?;
+        info!("📟 Leyendo comandos del tópico {}", topic);
+
+        Ok(tokio::spawn(async move {
+            loop {
+                let message = match consumer.recv().await {
+                    Ok(message) => message,
+                    Err(e) => {
+                        error!("Error recibiendo comando de Kafka: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let command = match serde_json::from_slice::<DeviceCommand>(
+                    message.payload().unwrap_or_default(),
+                ) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        warn!("⚠️ Comando inválido en {}: {}", message.topic(), e);
+                        continue;
+                    }
+                };
+                let id = command.id;
+                if let Err(e) = self.submit(command, CommandSource::Kafka).await {
+                    error!("❌ No se pudo registrar el comando {}: {:#}", id, e);
+                }
+            }
+        }))
+    }
+
+    /// Comando y estado de su entrega
+    pub async fn get(&self, id: Uuid) -> Result<Option<DeviceCommandRecord>> {
+        self.database.get_device_command(id).await
+    }
+
+    /// Últimos comandos de un dispositivo
+    pub async fn recent(&self, device_id: &str, limit: i64) -> Result<Vec<DeviceCommandRecord>> {
+        self.database.device_commands(device_id, limit).await
+    }
+
+    /// Termina cuando el broker rechaza las credenciales; nunca si no lo hace
+    pub async fn auth_failure(&self) {
+        self.publisher.auth_failure().await
+    }
+
+    pub fn statistics(&self) -> CommandStatistics {
+        CommandStatistics {
+            sent: self.sent.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Tópico de bajada del dispositivo, con un solo nivel por valor
+    fn topic_for(&self, command: &DeviceCommand) -> String {
+        let level = |value: &str| value.replace(['/', '+', '#'], "_");
+        self.topic
+            .replace("{device_id}", &level(&command.device_id))
+            .replace(
+                "{tenant}",
+                &level(command.tenant.as_deref().unwrap_or("default")),
+            )
+            .replace("{command_id}", &command.id.to_string())
+    }
+
+    async fn finish(&self, command: &DeviceCommand, outcome: Result<(), String>) {
+        let (status, error) = match &outcome {
+            Ok(()) => {
+                self.sent.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "📟 Comando {} publicado para el dispositivo {}",
+                    command.id,
+                    redact::device_id(&command.device_id)
+                );
+                (CommandStatus::Sent, None)
+            }
+            Err(e) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "⚠️ Comando {} del dispositivo {} no publicado: {}",
+                    command.id,
+                    redact::device_id(&command.device_id),
+                    e
+                );
+                (CommandStatus::Failed, Some(e.as_str()))
+            }
+        };
+        if let Err(e) = self
+            .database
+            .update_device_command(command.id, status, error)
+            .await
+        {
+            error!(
+                "❌ No se pudo guardar el estado del comando {}: {:#}",
+                command.id, e
+            );
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::config::DownsamplingConfig;
+use crate::models::{EngineStatus, NormalizedPosition};
+
+/// Último estado conocido de un dispositivo
+struct DeviceSample {
+    /// `gps_epoch` de la última posición periódica guardada
+    last_kept: i64,
+    engine_status: Option<EngineStatus>,
+}
+
+/// Guarda como máximo una posición periódica (`STATUS` sin alerta) por dispositivo
+/// cada `interval_secs` de tiempo GPS. Alertas, eventos, cambios de ignición y
+/// posiciones sin `gps_epoch` se guardan siempre.
+pub struct Downsampler {
+    interval_secs: i64,
+    devices: Mutex<LruCache<String, DeviceSample>>,
+    dropped: AtomicU64,
+}
+
+impl Downsampler {
+    /// None si la configuración no activa el muestreo
+    pub fn from_config(config: &DownsamplingConfig) -> Option<Self> {
+        if config.interval_secs == 0 {
+            return None;
+        }
+        let devices = NonZeroUsize::new(config.max_devices).unwrap_or(NonZeroUsize::MIN);
+
+        info!(
+            "📉 Muestreo activo: 1 posición por dispositivo cada {}s",
+            config.interval_secs
+        );
+        Some(Self {
+            interval_secs: config.interval_secs as i64,
+            devices: Mutex::new(LruCache::new(devices)),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Posiciones descartadas por el muestreo desde el inicio
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Quita del lote las posiciones periódicas que caen dentro del intervalo
+    pub fn retain(&self, positions: &mut Vec<NormalizedPosition>) {
+        let before = positions.len();
+        let mut devices = self.devices.lock().unwrap();
+
+        positions.retain(|position| {
+            let Some(gps_epoch) = position.gps_epoch else {
+                return true;
+            };
+            let periodic =
+                position.msg_class.eq_ignore_ascii_case("STATUS") && position.alert.is_none();
+
+            let Some(sample) = devices.get_mut(&position.device_id) else {
+                devices.put(
+                    position.device_id.clone(),
+                    DeviceSample {
+                        last_kept: gps_epoch,
+                        engine_status: position.engine_status,
+                    },
+                );
+                return true;
+            };
+
+            let ignition_changed = position.engine_status.is_some()
+                && sample.engine_status.is_some()
+                && position.engine_status != sample.engine_status;
+            if position.engine_status.is_some() {
+                sample.engine_status = position.engine_status;
+            }
+
+            // Posiciones atrasadas (buffer del equipo) no mueven la ventana
+            let due =
+                gps_epoch - sample.last_kept >= self.interval_secs || gps_epoch < sample.last_kept;
+            if !periodic || ignition_changed || due {
+                if periodic {
+                    sample.last_kept = sample.last_kept.max(gps_epoch);
+                }
+                return true;
+            }
+            false
+        });
+
+        let dropped = before - positions.len();
+        if dropped > 0 {
+            self.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+            debug!("📉 {} posiciones descartadas por muestreo", dropped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn downsampler() -> Downsampler {
+        Downsampler::from_config(&DownsamplingConfig {
+            interval_secs: 60,
+            max_devices: 100,
+        })
+        .unwrap()
+    }
+
+    /// Posición periódica (`STATUS` sin alerta, motor encendido) de `device_id`
+    fn periodic(device_id: &str, gps_epoch: i64) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.device_id = device_id.to_string();
+        position.gps_epoch = Some(gps_epoch);
+        position.engine_status = Some(EngineStatus::On);
+        position
+    }
+
+    fn kept(downsampler: &Downsampler, positions: Vec<NormalizedPosition>) -> Vec<i64> {
+        let mut positions = positions;
+        downsampler.retain(&mut positions);
+        positions
+            .iter()
+            .map(|position| position.gps_epoch.unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn disabled_with_a_zero_interval() {
+        assert!(Downsampler::from_config(&DownsamplingConfig {
+            interval_secs: 0,
+            max_devices: 100,
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn one_periodic_position_per_interval_is_kept() {
+        let downsampler = downsampler();
+        let positions = [0, 10, 59, 60, 90, 125, 180]
+            .into_iter()
+            .map(|epoch| periodic("A", 1_000 + epoch))
+            .collect();
+        assert_eq!(kept(&downsampler, positions), vec![1_000, 1_060, 1_125]);
+        assert_eq!(downsampler.dropped(), 4);
+
+        // La ventana sigue en el lote siguiente y es propia de cada equipo
+        assert_eq!(
+            kept(
+                &downsampler,
+                vec![
+                    periodic("A", 1_150),
+                    periodic("B", 1_150),
+                    periodic("A", 1_185)
+                ]
+            ),
+            vec![1_150, 1_185]
+        );
+    }
+
+    #[test]
+    fn events_are_never_dropped() {
+        let downsampler = downsampler();
+        let mut alert = periodic("A", 1_010);
+        alert.alert = Some("PANIC".to_string());
+        let mut event = periodic("A", 1_020);
+        event.msg_class = "EVENT".to_string();
+        let mut ignition_off = periodic("A", 1_030);
+        ignition_off.engine_status = Some(EngineStatus::Off);
+        let mut no_fix = periodic("A", 0);
+        no_fix.gps_epoch = None;
+
+        let mut positions = vec![
+            periodic("A", 1_000),
+            alert,
+            event,
+            ignition_off.clone(),
+            NormalizedPosition {
+                gps_epoch: Some(1_040),
+                ..ignition_off
+            },
+            no_fix,
+        ];
+        downsampler.retain(&mut positions);
+        assert_eq!(positions.len(), 5);
+        assert_eq!(downsampler.dropped(), 1);
+        assert!(positions
+            .iter()
+            .all(|position| position.gps_epoch != Some(1_040)));
+    }
+
+    #[test]
+    fn late_buffered_positions_are_kept_without_moving_the_window() {
+        let downsampler = downsampler();
+        assert_eq!(
+            kept(
+                &downsampler,
+                vec![
+                    periodic("A", 1_000),
+                    periodic("A", 900),
+                    periodic("A", 1_030),
+                    periodic("A", 1_060)
+                ]
+            ),
+            vec![1_000, 900, 1_060]
+        );
+    }
+}
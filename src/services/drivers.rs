@@ -0,0 +1,164 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info};
+
+use crate::config::DriverEventsConfig;
+use crate::models::{DriverEvent, DriverTransition, EngineStatus, NormalizedPosition};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+use crate::services::DatabaseService;
+
+/// Sigue el conductor identificado en cada dispositivo a partir de los reportes de
+/// conductor (`DRIVER_ID` y `DRIVER_STATUS` de los datos decodificados) y genera los
+/// inicios y cierres de sesión, guardados en `driver_events` y publicados en el
+/// tópico de notificaciones.
+///
+/// Una identificación de otro conductor cierra la sesión del anterior; las lecturas
+/// repetidas del mismo conductor no generan eventos.
+pub struct DriverTracker {
+    config: DriverEventsConfig,
+    database: Arc<DatabaseService>,
+    notifications: Option<NotificationPublisher>,
+    // Conductor con sesión abierta por dispositivo
+    drivers: Mutex<HashMap<String, String>>,
+    events: AtomicU64,
+}
+
+impl DriverTracker {
+    /// Carga las sesiones abiertas desde `driver_events`
+    pub async fn new(
+        config: DriverEventsConfig,
+        database: Arc<DatabaseService>,
+        notifications: Option<NotificationPublisher>,
+    ) -> Result<Self> {
+        let drivers: HashMap<String, String> = database
+            .driver_states()
+            .await?
+            .into_iter()
+            .filter(|(_, _, event)| *event == DriverTransition::DriverLogin)
+            .map(|(device_id, driver_id, _)| (device_id, driver_id))
+            .collect();
+
+        info!(
+            "🪪 Identificación de conductores activa ({} sesiones abiertas, cierre con ignición apagada: {})",
+            drivers.len(),
+            config.logout_on_ignition_off
+        );
+        Ok(Self {
+            config,
+            database,
+            notifications,
+            drivers: Mutex::new(drivers),
+            events: AtomicU64::new(0),
+        })
+    }
+
+    /// Inicios y cierres de sesión generados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Evalúa el lote, guarda los eventos y los publica. Los errores solo se
+    /// registran: un fallo aquí no debe frenar la ingesta.
+    pub async fn process(&self, positions: &[NormalizedPosition]) {
+        let events = self.evaluate(positions);
+        if events.is_empty() {
+            return;
+        }
+
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "🪪 Device {} {} conductor {}",
+                redact::device_id(&event.device_id),
+                event.event.as_str(),
+                event.driver_id
+            );
+        }
+
+        if let Err(e) = self.database.insert_driver_events(&events).await {
+            error!(
+                "❌ Error guardando {} eventos de conductor: {}",
+                events.len(),
+                e
+            );
+        }
+
+        if let Some(notifications) = &self.notifications {
+            for event in &events {
+                if let Err(e) = notifications.publish(&event.device_id, event).await {
+                    error!("❌ Error publicando evento de conductor: {}", e);
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, positions: &[NormalizedPosition]) -> Vec<DriverEvent> {
+        let mut drivers = self.drivers.lock().unwrap();
+        let mut events = Vec::new();
+
+        for position in positions {
+            let fields = match &position.decoded_extra {
+                Some(Value::Object(fields)) => Some(fields),
+                _ => None,
+            };
+            let field = |key: &str| {
+                fields
+                    .and_then(|fields| fields.get(key))
+                    .and_then(Value::as_str)
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+            };
+            let event = |driver_id: String, event: DriverTransition| DriverEvent {
+                device_id: position.device_id.clone(),
+                tenant: position.tenant.clone(),
+                driver_id,
+                event,
+                uuid: position.uuid.clone(),
+                latitude: position.latitude,
+                longitude: position.longitude,
+                gps_datetime: position.gps_datetime,
+            };
+
+            let current = drivers.get(&position.device_id).cloned();
+            match field("DRIVER_ID") {
+                Some(driver_id) => {
+                    let transition =
+                        DriverTransition::from_status(field("DRIVER_STATUS").unwrap_or_default());
+                    match transition {
+                        DriverTransition::DriverLogout => {
+                            if current.as_deref() == Some(driver_id) {
+                                drivers.remove(&position.device_id);
+                                events.push(event(driver_id.to_string(), transition));
+                            }
+                        }
+                        DriverTransition::DriverLogin => {
+                            if current.as_deref() == Some(driver_id) {
+                                continue;
+                            }
+                            if let Some(previous) = current {
+                                events.push(event(previous, DriverTransition::DriverLogout));
+                            }
+                            drivers.insert(position.device_id.clone(), driver_id.to_string());
+                            events.push(event(driver_id.to_string(), transition));
+                        }
+                    }
+                }
+                None if self.config.logout_on_ignition_off
+                    && position.engine_status == Some(EngineStatus::Off) =>
+                {
+                    if let Some(previous) = drivers.remove(&position.device_id) {
+                        events.push(event(previous, DriverTransition::DriverLogout));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        events
+    }
+}
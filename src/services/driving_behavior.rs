@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::config::{DrivingBehaviorConfig, DrivingThresholds};
+use crate::models::{DrivingEvent, DrivingEventKind, NormalizedPosition, Severity};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+
+/// Umbrales de un tenant en `DRIVING_TENANT_THRESHOLDS_FILE`; los que faltan toman
+/// los valores generales
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdOverrides {
+    harsh_braking_kmh_s: Option<f64>,
+    harsh_acceleration_kmh_s: Option<f64>,
+    speed_limit_kmh: Option<f64>,
+    speeding_min_secs: Option<u64>,
+}
+
+/// Exceso de velocidad en curso
+struct SpeedingEpisode {
+    start: DrivingEvent,
+    start_epoch: i64,
+    max_speed: f64,
+}
+
+/// Última posición con velocidad de un dispositivo
+struct DeviceMotion {
+    gps_epoch: i64,
+    speed_kmh: f64,
+    speeding: Option<SpeedingEpisode>,
+}
+
+/// Detecta frenadas y aceleraciones bruscas (cambio de velocidad entre posiciones
+/// consecutivas) y excesos de velocidad sostenidos, y los publica en el tópico de
+/// notificaciones. Los umbrales pueden ajustarse por tenant.
+pub struct DrivingBehaviorDetector {
+    config: DrivingBehaviorConfig,
+    tenants: HashMap<String, DrivingThresholds>,
+    notifications: NotificationPublisher,
+    devices: Mutex<HashMap<String, DeviceMotion>>,
+    events: AtomicU64,
+}
+
+impl DrivingBehaviorDetector {
+    /// Carga los umbrales por tenant, si hay un archivo configurado
+    pub async fn new(
+        config: DrivingBehaviorConfig,
+        notifications: NotificationPublisher,
+    ) -> Result<Self> {
+        let tenants = match &config.tenant_thresholds_file {
+            Some(path) => {
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+                serde_json::from_str::<HashMap<String, ThresholdOverrides>>(&content)
+                    .map_err(|e| anyhow!("Umbrales de conducción inválidos en {}: {}", path, e))?
+                    .into_iter()
+                    .map(|(tenant, overrides)| {
+                        let defaults = &config.thresholds;
+                        let thresholds = DrivingThresholds {
+                            harsh_braking_kmh_s: overrides
+                                .harsh_braking_kmh_s
+                                .unwrap_or(defaults.harsh_braking_kmh_s),
+                            harsh_acceleration_kmh_s: overrides
+                                .harsh_acceleration_kmh_s
+                                .unwrap_or(defaults.harsh_acceleration_kmh_s),
+                            speed_limit_kmh: overrides
+                                .speed_limit_kmh
+                                .unwrap_or(defaults.speed_limit_kmh),
+                            speeding_min_secs: overrides
+                                .speeding_min_secs
+                                .unwrap_or(defaults.speeding_min_secs),
+                        };
+                        (tenant, thresholds)
+                    })
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+
+        info!(
+            "🏎️ Detección de conducción activa: frenada {} km/h/s, aceleración {} km/h/s, límite {} km/h ({} tenants con umbrales propios)",
+            config.thresholds.harsh_braking_kmh_s,
+            config.thresholds.harsh_acceleration_kmh_s,
+            config.thresholds.speed_limit_kmh,
+            tenants.len()
+        );
+        Ok(Self {
+            config,
+            tenants,
+            notifications,
+            devices: Mutex::new(HashMap::new()),
+            events: AtomicU64::new(0),
+        })
+    }
+
+    /// Eventos de conducción generados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Evalúa el lote y publica los eventos. Los errores solo se registran: un fallo
+    /// de notificación no debe frenar la ingesta.
+    pub async fn process(&self, positions: &[NormalizedPosition]) {
+        let events = self.evaluate(positions);
+        if events.is_empty() {
+            return;
+        }
+
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "🏎️ Device {} {:?} ({:?}): {:.1} / umbral {:.1}",
+                redact::device_id(&event.device_id),
+                event.event,
+                event.severity,
+                event.value,
+                event.threshold
+            );
+            if let Err(e) = self.notifications.publish(&event.device_id, event).await {
+                error!("❌ Error publicando evento de conducción: {}", e);
+            }
+        }
+    }
+
+    /// Eventos del lote según la última posición de cada dispositivo
+    fn evaluate(&self, positions: &[NormalizedPosition]) -> Vec<DrivingEvent> {
+        let mut devices = self.devices.lock().unwrap();
+        detect(&self.config, &self.tenants, &mut devices, positions)
+    }
+}
+
+/// Compara cada posición con la anterior del mismo dispositivo en `devices`, con
+/// los umbrales de su tenant si los tiene
+fn detect(
+    config: &DrivingBehaviorConfig,
+    tenants: &HashMap<String, DrivingThresholds>,
+    devices: &mut HashMap<String, DeviceMotion>,
+    positions: &[NormalizedPosition],
+) -> Vec<DrivingEvent> {
+    let mut events = Vec::new();
+
+    for position in positions {
+        let (Some(gps_epoch), Some(speed_kmh)) = (position.gps_epoch, position.speed_kmh) else {
+            continue;
+        };
+        let thresholds = position
+            .tenant
+            .as_deref()
+            .and_then(|tenant| tenants.get(tenant))
+            .unwrap_or(&config.thresholds);
+        let event_at = |event, value, threshold| DrivingEvent {
+            device_id: position.device_id.clone(),
+            tenant: position.tenant.clone(),
+            event,
+            severity: Severity::from_ratio(value, threshold),
+            uuid: position.uuid.clone(),
+            latitude: position.latitude,
+            longitude: position.longitude,
+            gps_datetime: position.gps_datetime,
+            value,
+            threshold,
+            duration_secs: 0,
+        };
+
+        let motion = devices
+            .entry(position.device_id.clone())
+            .or_insert(DeviceMotion {
+                gps_epoch,
+                speed_kmh,
+                speeding: None,
+            });
+
+        // Posiciones atrasadas (buffer del equipo) no dan un cambio de velocidad válido
+        let elapsed = gps_epoch - motion.gps_epoch;
+        if elapsed < 0 {
+            continue;
+        }
+        let gap = elapsed > config.max_gap_secs as i64;
+
+        if elapsed > 0 && !gap {
+            let rate = (speed_kmh - motion.speed_kmh) / elapsed as f64;
+            if -rate >= thresholds.harsh_braking_kmh_s {
+                events.push(event_at(
+                    DrivingEventKind::HarshBraking,
+                    -rate,
+                    thresholds.harsh_braking_kmh_s,
+                ));
+            } else if rate >= thresholds.harsh_acceleration_kmh_s {
+                events.push(event_at(
+                    DrivingEventKind::HarshAcceleration,
+                    rate,
+                    thresholds.harsh_acceleration_kmh_s,
+                ));
+            }
+        }
+
+        // Un exceso termina al bajar del límite o tras un hueco sin posiciones
+        let over_limit = speed_kmh > thresholds.speed_limit_kmh;
+        if gap || !over_limit {
+            if let Some(episode) = motion.speeding.take() {
+                let duration = motion.gps_epoch - episode.start_epoch;
+                if duration >= thresholds.speeding_min_secs as i64 {
+                    events.push(DrivingEvent {
+                        severity: Severity::from_ratio(episode.max_speed, episode.start.threshold),
+                        value: episode.max_speed,
+                        duration_secs: duration,
+                        ..episode.start
+                    });
+                }
+            }
+        }
+        if over_limit {
+            match &mut motion.speeding {
+                Some(episode) => episode.max_speed = episode.max_speed.max(speed_kmh),
+                None => {
+                    motion.speeding = Some(SpeedingEpisode {
+                        start: event_at(
+                            DrivingEventKind::Speeding,
+                            speed_kmh,
+                            thresholds.speed_limit_kmh,
+                        ),
+                        start_epoch: gps_epoch,
+                        max_speed: speed_kmh,
+                    })
+                }
+            }
+        }
+
+        motion.gps_epoch = gps_epoch;
+        motion.speed_kmh = speed_kmh;
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn config() -> DrivingBehaviorConfig {
+        DrivingBehaviorConfig {
+            thresholds: DrivingThresholds {
+                harsh_braking_kmh_s: 10.0,
+                harsh_acceleration_kmh_s: 8.0,
+                speed_limit_kmh: 200.0,
+                speeding_min_secs: 30,
+            },
+            tenant_thresholds_file: None,
+            max_gap_secs: 10,
+        }
+    }
+
+    fn position(gps_epoch: i64, speed_kmh: f64) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.device_id = "A".to_string();
+        position.gps_epoch = Some(gps_epoch);
+        position.speed_kmh = Some(speed_kmh);
+        position
+    }
+
+    /// Eventos de pasar de `from` a `to` km/h en `secs` segundos
+    fn events_for(
+        config: &DrivingBehaviorConfig,
+        tenants: &HashMap<String, DrivingThresholds>,
+        from: f64,
+        to: f64,
+        secs: i64,
+    ) -> Vec<(DrivingEventKind, f64)> {
+        let mut devices = HashMap::new();
+        detect(
+            config,
+            tenants,
+            &mut devices,
+            &[position(1_000, from), position(1_000 + secs, to)],
+        )
+        .into_iter()
+        .map(|event| (event.event, event.value))
+        .collect()
+    }
+
+    #[test]
+    fn harsh_braking_starts_at_the_threshold() {
+        let config = config();
+        let none = HashMap::new();
+        // 10 km/h/s exactos es frenada brusca; 9.5 no
+        assert_eq!(
+            events_for(&config, &none, 80.0, 60.0, 2),
+            vec![(DrivingEventKind::HarshBraking, 10.0)]
+        );
+        assert!(events_for(&config, &none, 80.0, 61.0, 2).is_empty());
+        // La misma caída repartida en más tiempo no lo es
+        assert!(events_for(&config, &none, 80.0, 20.0, 7).is_empty());
+    }
+
+    #[test]
+    fn harsh_acceleration_starts_at_the_threshold() {
+        let config = config();
+        let none = HashMap::new();
+        assert_eq!(
+            events_for(&config, &none, 0.0, 24.0, 3),
+            vec![(DrivingEventKind::HarshAcceleration, 8.0)]
+        );
+        assert!(events_for(&config, &none, 0.0, 23.0, 3).is_empty());
+        assert!(events_for(&config, &none, 50.0, 50.0, 3).is_empty());
+    }
+
+    #[test]
+    fn severity_grows_with_the_ratio_to_the_threshold() {
+        let mut devices = HashMap::new();
+        let events = detect(
+            &config(),
+            &HashMap::new(),
+            &mut devices,
+            &[
+                position(0, 100.0),
+                position(1, 88.0),
+                position(2, 73.0),
+                position(3, 53.0),
+            ],
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.severity)
+                .collect::<Vec<_>>(),
+            vec![Severity::Low, Severity::Medium, Severity::High]
+        );
+    }
+
+    #[test]
+    fn gaps_and_late_positions_are_not_compared() {
+        let config = config();
+        let none = HashMap::new();
+        // Más de `max_gap_secs` entre posiciones
+        assert!(events_for(&config, &none, 0.0, 120.0, 11).is_empty());
+        // Misma marca de tiempo o posición atrasada
+        assert!(events_for(&config, &none, 0.0, 120.0, 0).is_empty());
+        assert!(events_for(&config, &none, 120.0, 0.0, -2).is_empty());
+    }
+
+    #[test]
+    fn tenant_thresholds_override_the_general_ones() {
+        let config = config();
+        let tenants = HashMap::from([(
+            "acme".to_string(),
+            DrivingThresholds {
+                harsh_braking_kmh_s: 5.0,
+                ..config.thresholds.clone()
+            },
+        )]);
+        let mut devices = HashMap::new();
+        let mut positions = [position(0, 60.0), position(2, 48.0)];
+        for position in &mut positions {
+            position.tenant = Some("acme".to_string());
+        }
+        let events = detect(&config, &tenants, &mut devices, &positions);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].threshold, 5.0);
+        assert!(events_for(&config, &tenants, 60.0, 48.0, 2).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::models::{CommunicationRecord, NormalizedPosition};
+use crate::redact;
+use crate::services::pipeline_metrics::ErrorStatistics;
+
+/// Informe del modo dry-run (`--dry-run` / `PIPELINE_DRY_RUN`): el procesador
+/// decodifica, valida y convierte los mensajes como siempre, pero en lugar de
+/// escribirlos los registra aquí. Cada registro que se habría insertado va al log
+/// con su tabla destino; los totales y los problemas de validación se resumen en
+/// `/stats` y al terminar.
+#[derive(Default)]
+pub struct DryRunReport {
+    summary: Mutex<DryRunSummary>,
+}
+
+/// Totales del dry-run desde el arranque
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DryRunSummary {
+    /// Registros que se habrían insertado, por tabla de histórico
+    pub inserts: BTreeMap<String, u64>,
+    /// Campos inválidos por `campo: motivo`, con el primer caso visto
+    pub field_issues: BTreeMap<String, IssueSummary>,
+    /// Posiciones que no se pudieron convertir a registro
+    pub conversion_errors: u64,
+    pub last_conversion_error: Option<String>,
+    /// Posiciones que habrían ido a cuarentena, por motivo
+    pub quarantined: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IssueSummary {
+    pub count: u64,
+    /// `device_id`, `uuid` y valor del primer caso
+    pub example: String,
+}
+
+impl DryRunReport {
+    pub fn new() -> Self {
+        warn!("🧪 Modo dry-run: no se escribe en PostgreSQL, Kafka ni otros destinos");
+        Self::default()
+    }
+
+    /// Registra los registros que se habrían insertado en `table`
+    pub fn record_inserts(&self, table: &str, records: &[CommunicationRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        for record in records {
+            match serde_json::to_string(record) {
+                Ok(json) => info!("🧪 INSERT {}: {}", table, json),
+                Err(e) => warn!("🧪 INSERT {}: uuid={} ({})", table, record.uuid, e),
+            }
+        }
+        *self.lock().inserts.entry(table.to_string()).or_default() += records.len() as u64;
+    }
+
+    /// Registra los campos que la normalización marcó como inválidos
+    pub fn record_issues(&self, position: &NormalizedPosition) {
+        let mut summary = self.lock();
+        for issue in &position.issues {
+            summary
+                .field_issues
+                .entry(format!("{}: {}", issue.field, issue.reason))
+                .and_modify(|issues| issues.count += 1)
+                .or_insert_with(|| IssueSummary {
+                    count: 1,
+                    example: format!(
+                        "device_id={} uuid={} valor='{}'",
+                        redact::device_id(&position.device_id),
+                        position.uuid,
+                        redact::field(issue.field, &issue.value)
+                    ),
+                });
+        }
+    }
+
+    pub fn record_conversion_error(&self, position: &NormalizedPosition, error: &anyhow::Error) {
+        let mut summary = self.lock();
+        summary.conversion_errors += 1;
+        summary.last_conversion_error = Some(format!(
+            "device_id={} uuid={}: {:#}",
+            redact::device_id(&position.device_id),
+            position.uuid,
+            error
+        ));
+    }
+
+    pub fn record_quarantined(&self, reasons: &[&'static str]) {
+        let mut summary = self.lock();
+        for reason in reasons {
+            *summary.quarantined.entry(reason.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn summary(&self) -> DryRunSummary {
+        self.lock().clone()
+    }
+
+    /// Resumen final: totales por tabla, problemas de validación y errores del
+    /// pipeline (incluidos los payloads que no se pudieron decodificar)
+    pub fn log_summary(&self, errors: &[ErrorStatistics]) {
+        let summary = self.summary();
+        info!(
+            "🧪 Dry-run: {} registros se habrían insertado ({})",
+            summary.inserts.values().sum::<u64>(),
+            summary
+                .inserts
+                .iter()
+                .map(|(table, count)| format!("{}={}", table, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        for (issue, issues) in &summary.field_issues {
+            info!(
+                "🧪 Campo inválido '{}': {} veces (p. ej. {})",
+                issue, issues.count, issues.example
+            );
+        }
+        if summary.conversion_errors > 0 {
+            info!(
+                "🧪 {} posiciones sin convertir (último: {})",
+                summary.conversion_errors,
+                summary.last_conversion_error.as_deref().unwrap_or("-")
+            );
+        }
+        for (reason, count) in &summary.quarantined {
+            info!("🧪 {} posiciones en cuarentena por '{}'", count, reason);
+        }
+        for error in errors {
+            info!("🧪 Errores {}: {}", error.category, error.count);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DryRunSummary> {
+        self.summary.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
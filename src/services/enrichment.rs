@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Duration;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::config::{EnricherKind, EnrichmentConfig, SpeedUnit};
+use crate::models::{DigitalIo, NormalizedPosition};
+use crate::services::tenant_router::load_tenant_map;
+
+/// Etapa que completa o corrige las posiciones de un lote antes de construir los
+/// registros de la BD. Para agregar lógica propia (geocodificación inversa, reglas de
+/// un cliente) se implementa este trait y se agrega a la cadena con `EnricherChain::push`.
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    /// Nombre para los logs
+    fn name(&self) -> &str;
+
+    /// Modifica las posiciones del lote. Un error se registra y la cadena continúa
+    /// con las posiciones tal como quedaron.
+    async fn enrich(&self, positions: &mut [NormalizedPosition]) -> Result<()>;
+}
+
+/// Enriquecedores que se ejecutan en orden sobre cada lote
+#[derive(Clone, Default)]
+pub struct EnricherChain {
+    enrichers: Vec<Arc<dyn Enricher>>,
+}
+
+impl EnricherChain {
+    /// Cadena con los enriquecedores incluidos, en el orden configurado
+    pub fn from_config(config: &EnrichmentConfig) -> Result<Self> {
+        let mut chain = Self::default();
+        for kind in &config.enrichers {
+            let enricher: Arc<dyn Enricher> = match kind {
+                EnricherKind::SpeedUnit => Arc::new(SpeedUnitEnricher {
+                    units: config.speed_units.clone().into_iter().collect(),
+                }),
+                EnricherKind::Timezone => Arc::new(TimezoneEnricher {
+                    offset: Duration::minutes(config.gps_utc_offset_minutes as i64),
+                }),
+                EnricherKind::Tenant => {
+                    let path = config
+                        .tenant_map_file
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("ENRICH_TENANT_MAP_FILE no configurado"))?;
+                    Arc::new(TenantEnricher::from_file(path)?)
+                }
+                EnricherKind::IoState => {
+                    let path = config
+                        .io_map_file
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("ENRICH_IO_MAP_FILE no configurado"))?;
+                    Arc::new(IoStateEnricher::from_file(path)?)
+                }
+            };
+            chain = chain.push(enricher);
+        }
+        Ok(chain)
+    }
+
+    /// Agrega un enriquecedor al final de la cadena
+    pub fn push(mut self, enricher: Arc<dyn Enricher>) -> Self {
+        info!("🧩 Enriquecedor activo: {}", enricher.name());
+        self.enrichers.push(enricher);
+        self
+    }
+
+    /// Ejecuta la cadena completa sobre el lote
+    pub async fn run(&self, positions: &mut [NormalizedPosition]) {
+        for enricher in &self.enrichers {
+            if let Err(e) = enricher.enrich(positions).await {
+                error!("❌ Error en el enriquecedor {}: {}", enricher.name(), e);
+            }
+        }
+    }
+}
+
+/// Convierte la velocidad a km/h según la unidad que reporta cada fabricante
+struct SpeedUnitEnricher {
+    units: HashMap<String, SpeedUnit>,
+}
+
+#[async_trait]
+impl Enricher for SpeedUnitEnricher {
+    fn name(&self) -> &str {
+        "speed_unit"
+    }
+
+    async fn enrich(&self, positions: &mut [NormalizedPosition]) -> Result<()> {
+        for position in positions {
+            if let Some(unit) = self.units.get(position.manufacturer.as_str()) {
+                position.speed_kmh = position.speed_kmh.map(|speed| speed * unit.kmh_factor());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pasa `gps_datetime` de la hora local de los equipos a UTC. Las fechas que ya
+/// traían su zona no se modifican.
+struct TimezoneEnricher {
+    offset: Duration,
+}
+
+#[async_trait]
+impl Enricher for TimezoneEnricher {
+    fn name(&self) -> &str {
+        "timezone"
+    }
+
+    async fn enrich(&self, positions: &mut [NormalizedPosition]) -> Result<()> {
+        for position in positions.iter_mut().filter(|p| !p.gps_datetime_utc) {
+            position.gps_datetime = position.gps_datetime.map(|datetime| datetime - self.offset);
+            position.gps_datetime_utc = position.gps_datetime.is_some();
+        }
+        Ok(())
+    }
+}
+
+/// Asigna el tenant de cada dispositivo a partir de un archivo `device_id,tenant`
+struct TenantEnricher {
+    tenants: HashMap<String, String>,
+}
+
+impl TenantEnricher {
+    fn from_file(path: &str) -> Result<Self> {
+        Ok(Self {
+            tenants: load_tenant_map(path)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Enricher for TenantEnricher {
+    fn name(&self) -> &str {
+        "tenant"
+    }
+
+    async fn enrich(&self, positions: &mut [NormalizedPosition]) -> Result<()> {
+        for position in positions {
+            position.tenant = self.tenants.get(&position.device_id).cloned();
+        }
+        Ok(())
+    }
+}
+
+/// Modelo que usa el mapa de bits de los equipos sin entrada propia
+const DEFAULT_IO_MODEL: &str = "*";
+
+/// Representación del campo de bits en los datos decodificados
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BitFormat {
+    /// Texto de ceros y unos (`IN_STATE` del ST300); el bit 0 es el primer carácter
+    #[default]
+    Binary,
+    /// Número hexadecimal (`MODE_MAP`); el bit 0 es el menos significativo
+    Hex,
+}
+
+/// Ubicación de una señal: campo decodificado, bit y si está activa en bajo
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BitSpec {
+    field: String,
+    bit: u32,
+    #[serde(default)]
+    format: BitFormat,
+    #[serde(default)]
+    inverted: bool,
+}
+
+impl BitSpec {
+    /// Estado de la señal; None si el campo falta o no tiene ese bit
+    fn read(&self, fields: &serde_json::Map<String, Value>) -> Option<bool> {
+        let value = fields.get(&self.field)?.as_str()?.trim();
+        let set = match self.format {
+            BitFormat::Binary => match value.as_bytes().get(self.bit as usize)? {
+                b'1' => true,
+                b'0' => false,
+                _ => return None,
+            },
+            BitFormat::Hex => {
+                let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+                let bits = u64::from_str_radix(digits, 16).ok()?;
+                bits.checked_shr(self.bit)? & 1 == 1
+            }
+        };
+        Some(set != self.inverted)
+    }
+}
+
+/// Bits de cada señal para un modelo en `ENRICH_IO_MAP_FILE`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IoBitMap {
+    ignition: Option<BitSpec>,
+    panic_button: Option<BitSpec>,
+    door_sensor: Option<BitSpec>,
+    relay_output: Option<BitSpec>,
+}
+
+/// Decodifica las entradas y salidas digitales (`IN_STATE`, `OUT_STATE`, `MODE_MAP`)
+/// con el mapa de bits del modelo del equipo
+struct IoStateEnricher {
+    models: HashMap<String, IoBitMap>,
+}
+
+impl IoStateEnricher {
+    fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+        let models: HashMap<String, IoBitMap> = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Mapa de entradas/salidas inválido en {}: {}", path, e))?;
+        info!(
+            "🔌 Mapa de entradas/salidas cargado para {} modelos",
+            models.len()
+        );
+        Ok(Self { models })
+    }
+}
+
+#[async_trait]
+impl Enricher for IoStateEnricher {
+    fn name(&self) -> &str {
+        "io_state"
+    }
+
+    async fn enrich(&self, positions: &mut [NormalizedPosition]) -> Result<()> {
+        for position in positions {
+            let Some(bit_map) = self
+                .models
+                .get(&position.model)
+                .or_else(|| self.models.get(DEFAULT_IO_MODEL))
+            else {
+                continue;
+            };
+            let Some(Value::Object(fields)) = &position.decoded_extra else {
+                continue;
+            };
+            let read = |spec: &Option<BitSpec>| spec.as_ref().and_then(|spec| spec.read(fields));
+            position.digital_io = DigitalIo {
+                ignition: read(&bit_map.ignition),
+                panic_button: read(&bit_map.panic_button),
+                door_sensor: read(&bit_map.door_sensor),
+                relay_output: read(&bit_map.relay_output),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+    use serde_json::json;
+
+    fn spec(field: &str, bit: u32, format: BitFormat, inverted: bool) -> BitSpec {
+        BitSpec {
+            field: field.to_string(),
+            bit,
+            format,
+            inverted,
+        }
+    }
+
+    fn fields(value: Value) -> serde_json::Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Estado de los primeros `count` bits de `value` leídos con `format`
+    fn bits(value: &str, format: BitFormat, count: u32) -> Vec<Option<bool>> {
+        let fields = fields(json!({ "IN_STATE": value }));
+        (0..count)
+            .map(|bit| spec("IN_STATE", bit, format, false).read(&fields))
+            .collect()
+    }
+
+    #[test]
+    fn binary_masks_are_read_from_the_first_character() {
+        assert_eq!(
+            bits("10100001", BitFormat::Binary, 9),
+            vec![
+                Some(true),
+                Some(false),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(false),
+                Some(false),
+                Some(true),
+                // Más allá del largo de la máscara
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_masks_are_read_from_the_least_significant_bit() {
+        assert_eq!(
+            bits("0x25", BitFormat::Hex, 8),
+            vec![
+                Some(true),
+                Some(false),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(true),
+                Some(false),
+                Some(false),
+            ]
+        );
+        assert_eq!(
+            bits("25", BitFormat::Hex, 1),
+            bits("0X25", BitFormat::Hex, 1)
+        );
+        // Un bit fuera de los 64 del número
+        assert_eq!(
+            spec("IN_STATE", 64, BitFormat::Hex, false).read(&fields(json!({ "IN_STATE": "FF" }))),
+            None
+        );
+    }
+
+    #[test]
+    fn inverted_signals_are_active_low() {
+        let fields = fields(json!({ "IN_STATE": "01" }));
+        assert_eq!(
+            spec("IN_STATE", 0, BitFormat::Binary, true).read(&fields),
+            Some(true)
+        );
+        assert_eq!(
+            spec("IN_STATE", 1, BitFormat::Binary, true).read(&fields),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn malformed_masks_read_as_unknown() {
+        for value in [json!("1x01"), json!(""), json!(1), json!(null)] {
+            let fields = fields(json!({ "IN_STATE": value }));
+            assert_eq!(
+                spec("IN_STATE", 1, BitFormat::Binary, false).read(&fields),
+                None,
+                "{:?}",
+                fields
+            );
+        }
+        for value in ["0xZZ", "", "0x", "-1"] {
+            assert_eq!(bits(value, BitFormat::Hex, 1), vec![None], "{}", value);
+        }
+        // Campo ausente
+        assert_eq!(
+            spec("OUT_STATE", 0, BitFormat::Binary, false).read(&fields(json!({}))),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn io_state_uses_the_model_map_or_the_default() {
+        let models: HashMap<String, IoBitMap> = serde_json::from_value(json!({
+            "ST300": {
+                "ignition": { "field": "IN_STATE", "bit": 0 },
+                "panic_button": { "field": "IN_STATE", "bit": 1 },
+                "relay_output": { "field": "OUT_STATE", "bit": 0 }
+            },
+            "*": {
+                "door_sensor": { "field": "MODE_MAP", "bit": 4, "format": "hex", "inverted": true }
+            }
+        }))
+        .unwrap();
+        let enricher = IoStateEnricher { models };
+
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut st300 = NormalizedPosition::from_device_message(&message);
+        st300.model = "ST300".to_string();
+        st300.decoded_extra = Some(json!({ "IN_STATE": "10", "OUT_STATE": "1", "MODE_MAP": "10" }));
+        let mut other = st300.clone();
+        other.model = "ST4955".to_string();
+        let mut no_fields = st300.clone();
+        no_fields.decoded_extra = None;
+
+        let mut positions = [st300, other, no_fields];
+        enricher.enrich(&mut positions).await.unwrap();
+        assert_eq!(
+            positions[0].digital_io,
+            DigitalIo {
+                ignition: Some(true),
+                panic_button: Some(false),
+                door_sensor: None,
+                relay_output: Some(true),
+            }
+        );
+        assert_eq!(
+            positions[1].digital_io,
+            DigitalIo {
+                door_sensor: Some(false),
+                ..DigitalIo::default()
+            }
+        );
+        assert_eq!(positions[2].digital_io, DigitalIo::default());
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::{ElasticsearchConfig, RetryConfig};
+use crate::models::{CommunicationRecord, Manufacturer};
+
+/// Tiempo máximo de cada petición `_bulk`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Indexa las posiciones en Elasticsearch u OpenSearch con la API `_bulk`, en un
+/// índice por mes (`{prefijo}-YYYY.MM`) con `location` como `geo_point`, para
+/// consultarlas en mapas de Kibana/OpenSearch Dashboards. Es una copia: PostgreSQL
+/// sigue siendo el histórico y los errores de indexación solo se registran.
+#[derive(Debug, Clone)]
+pub struct ElasticsearchSink {
+    http: reqwest::Client,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    api_key: Option<String>,
+    index_prefix: String,
+    retry: RetryConfig,
+}
+
+impl ElasticsearchSink {
+    pub fn new(config: &ElasticsearchConfig, retry: RetryConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        info!(
+            "🔎 Elasticsearch configurado en {} (índices: {}-YYYY.MM)",
+            config.url, config.index_prefix
+        );
+
+        Ok(Self {
+            http,
+            url: config.url.trim_end_matches('/').to_string(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            api_key: config.api_key.clone(),
+            index_prefix: config.index_prefix.clone(),
+            retry,
+        })
+    }
+
+    /// Crea o actualiza el index template que aplica el mapeo a los índices
+    /// mensuales. Se llama al arrancar; los índices se crean al indexar.
+    pub async fn install_template(&self) -> Result<()> {
+        let name = format!("{}-template", self.index_prefix);
+        let template = json!({
+            "index_patterns": [format!("{}-*", self.index_prefix)],
+            "template": {
+                "mappings": {
+                    "dynamic_templates": [{
+                        "strings_as_keywords": {
+                            "match_mapping_type": "string",
+                            "mapping": { "type": "keyword", "ignore_above": 1024 }
+                        }
+                    }],
+                    "properties": {
+                        "location": { "type": "geo_point" },
+                        "@timestamp": { "type": "date" },
+                        "gps_datetime": { "type": "date" },
+                        "received_at": { "type": "date" },
+                        "created_at": { "type": "date" },
+                        "speed": { "type": "float" },
+                        "course": { "type": "float" },
+                        "raw_message": { "type": "keyword", "index": false },
+                        // Campos propios de cada modelo; no se indexan para no chocar tipos
+                        "decoded_extra": { "type": "object", "enabled": false }
+                    }
+                }
+            }
+        });
+
+        let response = self
+            .authorized(
+                self.http
+                    .put(format!("{}/_index_template/{}", self.url, name)),
+            )
+            .json(&template)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(anyhow!(ElasticsearchError {
+                status: status.as_u16(),
+                detail: detail.trim().to_string(),
+            }));
+        }
+
+        info!("🔎 Index template {} instalado", name);
+        Ok(())
+    }
+
+    /// Índice mensual de un registro, según la fecha GPS o la de recepción
+    pub fn index(&self, record: &CommunicationRecord) -> String {
+        let date = record
+            .gps_datetime
+            .or(record.received_at)
+            .unwrap_or_else(|| Utc::now().naive_utc());
+        format!("{}-{}", self.index_prefix, date.format("%Y.%m"))
+    }
+
+    /// Indexa el lote reintentando ante errores de red, 429 o 5xx. El `_id` es el
+    /// uuid del registro, así los reintentos y redeliveries no duplican documentos.
+    /// Devuelve la cantidad de documentos indexados.
+    pub async fn index_batch(
+        &self,
+        records: &[CommunicationRecord],
+        manufacturer: Manufacturer,
+    ) -> Result<usize> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let mut body = String::new();
+        for record in records {
+            let action = json!({ "index": { "_index": self.index(record), "_id": record.uuid } });
+            body.push_str(&action.to_string());
+            body.push('\n');
+            body.push_str(&document(record, manufacturer)?.to_string());
+            body.push('\n');
+        }
+
+        let mut attempt = 1;
+        let response = loop {
+            match self.send(body.clone()).await {
+                Ok(response) => break response,
+                Err(e) if attempt < self.retry.max_attempts && is_retryable(&e) => {
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "⚠️ Error indexando en Elasticsearch (intento {}/{}): {}. Reintentando en {:?}",
+                        attempt, self.retry.max_attempts, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        // `_bulk` responde 200 aunque fallen documentos sueltos (mapeo, validación)
+        let failed: Vec<&Value> = response["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item["index"]["error"].as_object().map(|_| item))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !failed.is_empty() {
+            warn!(
+                "⚠️ {} de {} documentos rechazados por Elasticsearch; primero: {}",
+                failed.len(),
+                records.len(),
+                failed[0]["index"]["error"]
+            );
+        }
+
+        let indexed = records.len() - failed.len();
+        debug!("🔎 {} posiciones indexadas en Elasticsearch", indexed);
+        Ok(indexed)
+    }
+
+    async fn send(&self, body: String) -> Result<Value> {
+        let response = self
+            .authorized(self.http.post(format!("{}/_bulk", self.url)))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(anyhow!(ElasticsearchError {
+                status: status.as_u16(),
+                detail: detail.trim().to_string(),
+            }));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(api_key) = &self.api_key {
+            request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("ApiKey {}", api_key),
+            )
+        } else if let Some(username) = &self.username {
+            request.basic_auth(username, self.password.as_ref())
+        } else {
+            request
+        }
+    }
+}
+
+/// Documento de una posición: el registro más `manufacturer`, `@timestamp` y
+/// `location` cuando las coordenadas son válidas
+fn document(record: &CommunicationRecord, manufacturer: Manufacturer) -> Result<Value> {
+    let mut document = serde_json::to_value(record)?;
+    let Some(fields) = document.as_object_mut() else {
+        return Ok(document);
+    };
+    fields.remove("id");
+    fields.insert("manufacturer".to_string(), json!(manufacturer.as_str()));
+    if let Some(timestamp) = record.gps_datetime.or(record.received_at) {
+        fields.insert(
+            "@timestamp".to_string(),
+            json!(timestamp.and_utc().to_rfc3339()),
+        );
+    }
+    if let (Some(lat), Some(lon)) = (record.latitude, record.longitude) {
+        if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+            fields.insert("location".to_string(), json!({ "lat": lat, "lon": lon }));
+        }
+    }
+    Ok(document)
+}
+
+/// Respuesta de error de Elasticsearch/OpenSearch a la petición completa
+#[derive(Debug, thiserror::Error)]
+#[error("Elasticsearch respondió {status}: {detail}")]
+pub struct ElasticsearchError {
+    pub status: u16,
+    pub detail: String,
+}
+
+/// Errores de red, 429 (cola de indexación llena) y 5xx; el resto de 4xx no se reintenta
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(e) = error.downcast_ref::<ElasticsearchError>() {
+        return e.status == 429 || e.status >= 500;
+    }
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout() || e.is_request())
+}
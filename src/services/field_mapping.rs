@@ -0,0 +1,349 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::siscom::KafkaMessage;
+use crate::models::Manufacturer;
+
+/// Archivo de mapeo tal como se escribe (YAML, JSON o TOML según la extensión)
+#[derive(Debug, Deserialize)]
+struct MappingFile {
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    target: String,
+    source: Option<String>,
+    manufacturer: Option<String>,
+    #[serde(default)]
+    transforms: Vec<TransformSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TransformSpec {
+    Scale(f64),
+    Offset(f64),
+    Regex(String),
+    Default(String),
+}
+
+/// Campo de origen: el mapa `data` normalizado o los campos crudos del fabricante
+#[derive(Debug)]
+enum Source {
+    Data(String),
+    Decoded(String),
+}
+
+#[derive(Debug)]
+enum Transform {
+    /// Multiplica el valor numérico
+    Scale(f64),
+    /// Suma al valor numérico
+    Offset(f64),
+    /// Extrae el primer grupo de captura (o la coincidencia completa)
+    Regex(Regex),
+    /// Valor cuando el origen no existe, está vacío o un paso anterior no produjo valor
+    Default(String),
+}
+
+#[derive(Debug)]
+struct Rule {
+    target: String,
+    source: Option<Source>,
+    manufacturer: Option<Manufacturer>,
+    transforms: Vec<Transform>,
+}
+
+/// Mapeo declarativo de campos del mensaje entrante a las claves de `data` que usa
+/// `DeviceData` (`SPEED`, `LATITUD`, ...), para aceptar variantes de payload sin
+/// cambiar código. Las reglas se aplican en orden, antes de construir el DeviceMessage.
+#[derive(Debug)]
+pub struct FieldMapping {
+    rules: Vec<Rule>,
+}
+
+impl FieldMapping {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let file: MappingFile = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .map_err(|e| anyhow!("Mapeo de campos inválido en {}: {}", path, e))?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(Rule::compile)
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| anyhow!("Mapeo de campos inválido en {}: {}", path, e))?;
+
+        info!("🗺️ {} reglas de mapeo cargadas de {}", rules.len(), path);
+        Ok(Self { rules })
+    }
+
+    /// Aplica las reglas sobre el mapa `data` del mensaje
+    pub fn apply(&self, message: &mut KafkaMessage) {
+        let (manufacturer, decoded) = match message
+            .decoded
+            .as_ref()
+            .and_then(Manufacturer::from_decoded)
+        {
+            Some((manufacturer, fields)) => (Some(manufacturer), Some(fields)),
+            None => (None, None),
+        };
+
+        // Las reglas anteriores ya aplicadas son visibles como origen de las siguientes
+        let data = &mut message.data;
+        for rule in &self.rules {
+            if rule.manufacturer.is_some() && rule.manufacturer != manufacturer {
+                continue;
+            }
+
+            let value = match &rule.source {
+                Some(Source::Data(key)) => data.get(key),
+                Some(Source::Decoded(key)) => decoded.and_then(|fields| fields.get(key)),
+                None => None,
+            }
+            .filter(|value| !value.is_empty())
+            .cloned();
+
+            if let Some(value) = rule.transform(value) {
+                data.insert(rule.target.clone(), value);
+            }
+        }
+    }
+}
+
+impl Rule {
+    fn compile(spec: RuleSpec) -> Result<Self> {
+        let source = match spec.source.as_deref().map(|source| source.split_once('.')) {
+            None => None,
+            Some(Some(("data", key))) => Some(Source::Data(key.to_string())),
+            Some(Some(("decoded", key))) => Some(Source::Decoded(key.to_string())),
+            Some(_) => {
+                return Err(anyhow!(
+                    "origen '{}' de {} inválido (usar data.CAMPO o decoded.CAMPO)",
+                    spec.source.unwrap_or_default(),
+                    spec.target
+                ))
+            }
+        };
+
+        let manufacturer = match spec.manufacturer.as_deref() {
+            None => None,
+            Some(name) => Some(
+                Manufacturer::from_name(name)
+                    .ok_or_else(|| anyhow!("fabricante '{}' no soportado", name))?,
+            ),
+        };
+
+        let transforms = spec
+            .transforms
+            .into_iter()
+            .map(|transform| {
+                Ok(match transform {
+                    TransformSpec::Scale(factor) => Transform::Scale(factor),
+                    TransformSpec::Offset(offset) => Transform::Offset(offset),
+                    TransformSpec::Regex(pattern) => Transform::Regex(Regex::new(&pattern)?),
+                    TransformSpec::Default(value) => Transform::Default(value),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if source.is_none()
+            && !transforms
+                .iter()
+                .any(|t| matches!(t, Transform::Default(_)))
+        {
+            return Err(anyhow!(
+                "la regla de {} no tiene source ni default",
+                spec.target
+            ));
+        }
+
+        Ok(Self {
+            target: spec.target,
+            source,
+            manufacturer,
+            transforms,
+        })
+    }
+
+    /// Aplica las transformaciones en orden. Un valor no numérico en `scale`/`offset`
+    /// o sin coincidencia en `regex` deja la regla sin valor hasta un `default`.
+    fn transform(&self, mut value: Option<String>) -> Option<String> {
+        for transform in &self.transforms {
+            value = match transform {
+                Transform::Scale(factor) => numeric(value, |number| number * factor),
+                Transform::Offset(offset) => numeric(value, |number| number + offset),
+                Transform::Regex(regex) => value.and_then(|value| {
+                    regex.captures(&value).map(|captures| {
+                        captures
+                            .get(1)
+                            .or_else(|| captures.get(0))
+                            .map(|capture| capture.as_str().to_string())
+                            .unwrap_or_default()
+                    })
+                }),
+                Transform::Default(default) => value
+                    .filter(|value| !value.is_empty())
+                    .or_else(|| Some(default.clone())),
+            };
+        }
+        value
+    }
+}
+
+fn numeric(value: Option<String>, apply: impl Fn(f64) -> f64) -> Option<String> {
+    let number: f64 = value?.trim().parse().ok()?;
+    Some(apply(number).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::siscom::kafka_message::Decoded;
+    use crate::config::siscom::SuntechDecoded;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    /// Archivo de mapeo temporal propio del test; la extensión decide el formato
+    fn mapping_file(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("siscom-mapping-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn load(name: &str, content: &str) -> Result<FieldMapping> {
+        let path = mapping_file(name, content);
+        let mapping = FieldMapping::from_file(&path.to_string_lossy());
+        std::fs::remove_file(path).unwrap();
+        mapping
+    }
+
+    fn message(data: &[(&str, &str)], decoded: &[(&str, &str)]) -> KafkaMessage {
+        let fields = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        KafkaMessage {
+            data: fields(data),
+            decoded: Some(Decoded::Suntech(SuntechDecoded {
+                fields: fields(decoded),
+            })),
+            ..KafkaMessage::default()
+        }
+    }
+
+    #[test]
+    fn fields_are_renamed_and_transformed_in_order() {
+        let mapping = load(
+            "rename.yaml",
+            r#"
+rules:
+  - target: SPEED
+    source: data.VELOCIDAD_MPH
+    transforms:
+      - scale: 1.609344
+  - target: ODOMETER
+    source: decoded.ODO_KM
+    manufacturer: suntech
+    transforms:
+      - scale: 1000
+  - target: FIRMWARE
+    source: data.VERSION
+    transforms:
+      - regex: "v([0-9.]+)"
+  # Las reglas ven el resultado de las anteriores
+  - target: ALTITUDE
+    source: data.SPEED
+    transforms:
+      - offset: 1
+"#,
+        )
+        .unwrap();
+
+        let mut message = message(
+            &[("VELOCIDAD_MPH", "10"), ("VERSION", "fw v1.2.3")],
+            &[("ODO_KM", "1.5")],
+        );
+        mapping.apply(&mut message);
+        assert_eq!(message.data["SPEED"], "16.09344");
+        assert_eq!(message.data["ODOMETER"], "1500");
+        assert_eq!(message.data["FIRMWARE"], "1.2.3");
+        assert_eq!(message.data["ALTITUDE"], "17.09344");
+        // El campo de origen se conserva
+        assert_eq!(message.data["VELOCIDAD_MPH"], "10");
+    }
+
+    #[test]
+    fn missing_source_fields_leave_the_target_untouched_unless_defaulted() {
+        let mapping = load(
+            "missing.json",
+            r#"{"rules": [
+                {"target": "SPEED", "source": "data.VELOCIDAD_MPH", "transforms": [{"scale": 2}]},
+                {"target": "MODEL", "source": "data.MODELO", "transforms": [{"default": "ST300"}]},
+                {"target": "FIRMWARE", "source": "data.VERSION", "transforms": [{"regex": "v([0-9]+)"}, {"default": "0"}]},
+                {"target": "DELIVERY_TYPE", "transforms": [{"default": "REALTIME"}]},
+                {"target": "COURSE", "source": "decoded.CRS", "manufacturer": "queclink"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut message = message(
+            &[("SPEED", "42"), ("MODELO", ""), ("VERSION", "sin versión")],
+            &[("CRS", "87.5")],
+        );
+        mapping.apply(&mut message);
+        assert_eq!(message.data["SPEED"], "42");
+        assert_eq!(message.data["MODEL"], "ST300");
+        assert_eq!(message.data["FIRMWARE"], "0");
+        assert_eq!(message.data["DELIVERY_TYPE"], "REALTIME");
+        // Regla de otro fabricante
+        assert!(!message.data.contains_key("COURSE"));
+
+        // Un valor no numérico no se escala
+        let mut message = self::message(&[("VELOCIDAD_MPH", "n/d")], &[]);
+        mapping.apply(&mut message);
+        assert!(!message.data.contains_key("SPEED"));
+    }
+
+    #[test]
+    fn bad_mapping_files_are_rejected() {
+        for (name, content, reason) in [
+            (
+                "source.yaml",
+                "rules:\n  - target: SPEED\n    source: VELOCIDAD\n",
+                "origen 'VELOCIDAD'",
+            ),
+            (
+                "manufacturer.yaml",
+                "rules:\n  - target: SPEED\n    source: data.V\n    manufacturer: acme\n",
+                "fabricante 'acme'",
+            ),
+            (
+                "regex.yaml",
+                "rules:\n  - target: SPEED\n    source: data.V\n    transforms:\n      - regex: \"([0-9\"\n",
+                "regex",
+            ),
+            (
+                "no_source.yaml",
+                "rules:\n  - target: SPEED\n    transforms:\n      - scale: 2\n",
+                "no tiene source ni default",
+            ),
+            ("transform.yaml", "rules:\n  - target: SPEED\n    source: data.V\n    transforms:\n      - round: 2\n", "round"),
+            ("syntax.json", "{\"rules\": [", "inválido"),
+        ] {
+            let error = format!("{:#}", load(name, content).unwrap_err());
+            assert!(error.contains("Mapeo de campos inválido"), "{}: {}", name, error);
+            assert!(error.contains(reason), "{}: {}", name, error);
+        }
+        assert!(FieldMapping::from_file("/no/existe/mapping.yaml").is_err());
+    }
+}
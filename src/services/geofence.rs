@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::config::{GeofenceConfig, GeofenceSource};
+use crate::models::{Geofence, GeofenceEvent, GeofenceTransition, NormalizedPosition};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+use crate::services::DatabaseService;
+
+/// Evalúa cada posición contra las geocercas de su dispositivo o tenant y genera
+/// eventos de entrada/salida, guardados en `geofence_events` y publicados en el
+/// tópico de notificaciones
+pub struct GeofenceService {
+    config: GeofenceConfig,
+    database: Arc<DatabaseService>,
+    notifications: Option<NotificationPublisher>,
+    geofences: RwLock<Vec<Geofence>>,
+    // Último estado conocido por (device_id, geofence_id): true = dentro
+    inside: Mutex<HashMap<(String, String), bool>>,
+    events: AtomicU64,
+}
+
+impl GeofenceService {
+    /// Carga las geocercas y el último estado de cada dispositivo desde `geofence_events`
+    pub async fn new(
+        config: GeofenceConfig,
+        database: Arc<DatabaseService>,
+        notifications: Option<NotificationPublisher>,
+    ) -> Result<Self> {
+        let inside = database
+            .geofence_states()
+            .await?
+            .into_iter()
+            .map(|(device_id, geofence_id, event)| {
+                ((device_id, geofence_id), event == GeofenceTransition::Enter)
+            })
+            .collect();
+
+        let service = Self {
+            config,
+            database,
+            notifications,
+            geofences: RwLock::new(Vec::new()),
+            inside: Mutex::new(inside),
+            events: AtomicU64::new(0),
+        };
+        service.reload().await?;
+        Ok(service)
+    }
+
+    /// Vuelve a leer las definiciones desde el archivo o la tabla configurada
+    pub async fn reload(&self) -> Result<usize> {
+        let geofences = match self.config.source {
+            GeofenceSource::File => {
+                let path = self
+                    .config
+                    .file
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("GEOFENCE_FILE no configurado"))?;
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+                serde_json::from_str::<Vec<Geofence>>(&content)
+                    .map_err(|e| anyhow!("Geocercas inválidas en {}: {}", path, e))?
+            }
+            GeofenceSource::Database => self.database.load_geofences().await?,
+        };
+
+        // Los estados de geocercas eliminadas ya no se usan
+        let ids: HashSet<&str> = geofences.iter().map(|g| g.id.as_str()).collect();
+        self.inside
+            .lock()
+            .unwrap()
+            .retain(|(_, geofence_id), _| ids.contains(geofence_id.as_str()));
+
+        let count = geofences.len();
+        *self.geofences.write().unwrap() = geofences;
+        info!("📍 {} geocercas cargadas", count);
+        Ok(count)
+    }
+
+    /// Inicia la recarga periódica de las definiciones (None si `refresh_secs` es 0)
+    pub fn start(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        if self.config.refresh_secs == 0 {
+            return None;
+        }
+
+        let service = self.clone();
+        let interval = Duration::from_secs(self.config.refresh_secs);
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = service.reload().await {
+                    error!("❌ Error recargando geocercas: {}", e);
+                }
+            }
+        }))
+    }
+
+    /// Eventos de entrada/salida generados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Evalúa el lote, guarda los eventos y los publica. Los errores solo se registran:
+    /// un fallo de notificación no debe frenar la ingesta.
+    pub async fn process(&self, positions: &[NormalizedPosition]) {
+        let events = self.evaluate(positions);
+        if events.is_empty() {
+            return;
+        }
+
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "📍 Device {} {} geocerca {} ({})",
+                redact::device_id(&event.device_id),
+                event.event.as_str(),
+                event.geofence_id,
+                event.geofence_name
+            );
+        }
+
+        if let Err(e) = self.database.insert_geofence_events(&events).await {
+            error!(
+                "❌ Error guardando {} eventos de geocerca: {}",
+                events.len(),
+                e
+            );
+        }
+
+        if let Some(notifications) = &self.notifications {
+            for event in &events {
+                if let Err(e) = notifications.publish(&event.device_id, event).await {
+                    error!("❌ Error publicando evento de geocerca: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Eventos del lote según el último estado de cada dispositivo y geocerca
+    fn evaluate(&self, positions: &[NormalizedPosition]) -> Vec<GeofenceEvent> {
+        let geofences = self.geofences.read().unwrap();
+        let mut inside = self.inside.lock().unwrap();
+        transitions(&geofences, &mut inside, positions)
+    }
+}
+
+/// Compara cada posición con el estado anterior del dispositivo en cada geocerca y
+/// actualiza `inside`. Un par sin estado previo solo genera evento si la posición
+/// está dentro.
+fn transitions(
+    geofences: &[Geofence],
+    inside: &mut HashMap<(String, String), bool>,
+    positions: &[NormalizedPosition],
+) -> Vec<GeofenceEvent> {
+    let mut events = Vec::new();
+
+    for position in positions {
+        let (Some(latitude), Some(longitude)) = (position.latitude, position.longitude) else {
+            continue;
+        };
+
+        for geofence in geofences
+            .iter()
+            .filter(|g| g.applies_to(&position.device_id, position.tenant.as_deref()))
+        {
+            let now_inside = geofence.shape.contains(latitude, longitude);
+            let previous = inside.insert(
+                (position.device_id.clone(), geofence.id.clone()),
+                now_inside,
+            );
+
+            let transition = match (previous.unwrap_or(false), now_inside) {
+                (false, true) => GeofenceTransition::Enter,
+                (true, false) => GeofenceTransition::Exit,
+                _ => continue,
+            };
+            events.push(GeofenceEvent {
+                geofence_id: geofence.id.clone(),
+                geofence_name: geofence.name.clone(),
+                device_id: position.device_id.clone(),
+                tenant: position.tenant.clone(),
+                event: transition,
+                uuid: position.uuid.clone(),
+                latitude,
+                longitude,
+                gps_datetime: position.gps_datetime,
+            });
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DeviceMessage, GeofenceShape};
+
+    fn position(device_id: &str, latitude: f64, longitude: f64) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.device_id = device_id.to_string();
+        position.latitude = Some(latitude);
+        position.longitude = Some(longitude);
+        position
+    }
+
+    fn base(device_id: Option<&str>) -> Geofence {
+        Geofence {
+            id: "base".to_string(),
+            name: "Base".to_string(),
+            device_id: device_id.map(str::to_string),
+            tenant: None,
+            shape: GeofenceShape::Circle {
+                latitude: 0.0,
+                longitude: 0.0,
+                radius_m: 1_000.0,
+            },
+        }
+    }
+
+    fn events(
+        geofences: &[Geofence],
+        inside: &mut HashMap<(String, String), bool>,
+        positions: &[NormalizedPosition],
+    ) -> Vec<GeofenceTransition> {
+        transitions(geofences, inside, positions)
+            .into_iter()
+            .map(|event| event.event)
+            .collect()
+    }
+
+    #[test]
+    fn enter_and_exit_are_emitted_once_per_transition() {
+        let geofences = [base(None)];
+        let mut inside = HashMap::new();
+
+        // Sin estado previo, fuera no genera evento
+        assert!(events(&geofences, &mut inside, &[position("A", 1.0, 1.0)]).is_empty());
+        assert_eq!(
+            events(
+                &geofences,
+                &mut inside,
+                &[
+                    position("A", 0.0, 0.0),
+                    position("A", 0.001, 0.0),
+                    position("A", 1.0, 1.0),
+                    position("A", 1.0, 1.0),
+                ]
+            ),
+            vec![GeofenceTransition::Enter, GeofenceTransition::Exit]
+        );
+        assert_eq!(
+            inside.get(&("A".to_string(), "base".to_string())),
+            Some(&false)
+        );
+    }
+
+    #[test]
+    fn state_loaded_at_startup_is_respected() {
+        // Dentro según `geofence_events`: la primera posición dentro no repite la entrada
+        let geofences = [base(None)];
+        let mut inside = HashMap::from([(("A".to_string(), "base".to_string()), true)]);
+        assert!(events(&geofences, &mut inside, &[position("A", 0.0, 0.0)]).is_empty());
+        assert_eq!(
+            events(&geofences, &mut inside, &[position("A", 1.0, 1.0)]),
+            vec![GeofenceTransition::Exit]
+        );
+    }
+
+    #[test]
+    fn positions_without_coordinates_or_other_devices_are_skipped() {
+        let geofences = [base(Some("A"))];
+        let mut inside = HashMap::new();
+
+        let mut no_fix = position("A", 0.0, 0.0);
+        no_fix.latitude = None;
+        assert!(events(&geofences, &mut inside, &[no_fix, position("B", 0.0, 0.0)]).is_empty());
+        assert!(inside.is_empty());
+
+        let emitted = transitions(&geofences, &mut inside, &[position("A", 0.0, 0.0)]);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].device_id, "A");
+        assert_eq!(emitted[0].geofence_id, "base");
+    }
+}
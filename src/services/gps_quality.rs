@@ -0,0 +1,300 @@
+use geoutils::Location;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::config::{GpsQualityConfig, GpsQualityPolicy};
+use crate::models::NormalizedPosition;
+use crate::redact;
+
+/// Dispositivos cuya última posición válida se recuerda para detectar saltos
+const DEVICE_CACHE_SIZE: usize = 100_000;
+
+/// Puntos que resta un salto imposible respecto a la posición válida anterior
+const JUMP_PENALTY: u8 = 60;
+/// Puntos que restan pocos satélites
+const LOW_SATELLITES_PENALTY: u8 = 30;
+
+/// Posición separada del lote por baja calidad, con los motivos
+pub type QuarantinedPosition = (NormalizedPosition, Vec<&'static str>);
+
+/// Última posición válida de un dispositivo: gps_epoch, latitud, longitud
+type LastFix = (i64, f64, f64);
+
+/// Asigna a cada posición con coordenadas una calidad de 0 a 100 y aplica la política
+/// configurada a las que quedan por debajo del mínimo.
+///
+/// Coordenadas 0,0 o fuera de rango dejan la calidad en 0; un salto de más de
+/// `max_jump_km` en menos de `max_jump_secs` respecto a la última posición válida del
+/// dispositivo resta 60 puntos, y menos de `min_satellites` satélites resta 30.
+pub struct GpsQualityChecker {
+    config: GpsQualityConfig,
+    last_fix: Mutex<LruCache<String, LastFix>>,
+    invalid: AtomicU64,
+}
+
+impl GpsQualityChecker {
+    pub fn new(config: GpsQualityConfig) -> Self {
+        info!(
+            "🛰️ Calidad GPS activa: política {:?}, mínimo {} puntos, saltos de {} km en {}s, {} satélites",
+            config.policy,
+            config.min_score,
+            config.max_jump_km,
+            config.max_jump_secs,
+            config.min_satellites
+        );
+        Self {
+            config,
+            last_fix: Mutex::new(LruCache::new(NonZeroUsize::new(DEVICE_CACHE_SIZE).unwrap())),
+            invalid: AtomicU64::new(0),
+        }
+    }
+
+    /// Posiciones por debajo de la calidad mínima desde el inicio
+    pub fn invalid(&self) -> u64 {
+        self.invalid.load(Ordering::Relaxed)
+    }
+
+    /// Califica el lote. Con la política `quarantine` devuelve las posiciones inválidas
+    /// quitadas del lote; con `drop` las descarta y con `flag` solo las califica.
+    pub fn apply(&self, positions: &mut Vec<NormalizedPosition>) -> Vec<QuarantinedPosition> {
+        let mut quarantined = Vec::new();
+        let mut last_fix = self.last_fix.lock().unwrap();
+
+        let batch = std::mem::take(positions);
+        for mut position in batch {
+            let Some(reasons) = self.assess(&mut position, &mut last_fix) else {
+                positions.push(position);
+                continue;
+            };
+            let score = position.quality_score.unwrap_or(0);
+            if score >= self.config.min_score {
+                positions.push(position);
+                continue;
+            }
+
+            self.invalid.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "🛰️ Posición de baja calidad ({}): Device {}, UUID {}, motivos: {}",
+                score,
+                redact::device_id(&position.device_id),
+                position.uuid,
+                reasons.join(", ")
+            );
+            match self.config.policy {
+                GpsQualityPolicy::Flag => positions.push(position),
+                GpsQualityPolicy::Quarantine => quarantined.push((position, reasons)),
+                GpsQualityPolicy::Drop => {}
+            }
+        }
+
+        quarantined
+    }
+
+    /// Calcula la calidad de la posición y devuelve los motivos de las penalizaciones
+    /// (None si no trae coordenadas que evaluar)
+    fn assess(
+        &self,
+        position: &mut NormalizedPosition,
+        last_fix: &mut LruCache<String, LastFix>,
+    ) -> Option<Vec<&'static str>> {
+        let out_of_range = position
+            .issues
+            .iter()
+            .any(|issue| matches!(issue.field, "latitude" | "longitude"));
+        if out_of_range {
+            position.quality_score = Some(0);
+            return Some(vec!["out_of_range"]);
+        }
+
+        let (Some(latitude), Some(longitude)) = (position.latitude, position.longitude) else {
+            return None;
+        };
+        if latitude == 0.0 && longitude == 0.0 {
+            position.quality_score = Some(0);
+            return Some(vec!["null_island"]);
+        }
+
+        let mut score = 100u8;
+        let mut reasons = Vec::new();
+
+        if let (Some(gps_epoch), Some(&(last_epoch, last_lat, last_lon))) =
+            (position.gps_epoch, last_fix.peek(&position.device_id))
+        {
+            let elapsed = (gps_epoch - last_epoch).unsigned_abs();
+            let distance_km = Location::new(latitude, longitude)
+                .haversine_distance_to(&Location::new(last_lat, last_lon))
+                .meters()
+                / 1000.0;
+            if elapsed <= self.config.max_jump_secs && distance_km > self.config.max_jump_km {
+                score = score.saturating_sub(JUMP_PENALTY);
+                reasons.push("impossible_jump");
+            }
+        }
+
+        if position
+            .satellites
+            .is_some_and(|satellites| satellites < self.config.min_satellites)
+        {
+            score = score.saturating_sub(LOW_SATELLITES_PENALTY);
+            reasons.push("low_satellites");
+        }
+
+        // Solo las posiciones válidas sirven de referencia para el siguiente salto
+        if let (true, Some(gps_epoch)) = (score >= self.config.min_score, position.gps_epoch) {
+            let newer = last_fix
+                .peek(&position.device_id)
+                .is_none_or(|&(last_epoch, _, _)| gps_epoch >= last_epoch);
+            if newer {
+                last_fix.put(position.device_id.clone(), (gps_epoch, latitude, longitude));
+            }
+        }
+
+        position.quality_score = Some(score);
+        Some(reasons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn checker(policy: GpsQualityPolicy) -> GpsQualityChecker {
+        GpsQualityChecker::new(GpsQualityConfig {
+            policy,
+            min_score: 40,
+            max_jump_km: 10.0,
+            max_jump_secs: 60,
+            min_satellites: 4,
+        })
+    }
+
+    fn position(gps_epoch: i64, latitude: f64, satellites: i32) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.device_id = "A".to_string();
+        position.gps_epoch = Some(gps_epoch);
+        position.latitude = Some(latitude);
+        position.longitude = Some(-99.0);
+        position.satellites = Some(satellites);
+        position
+    }
+
+    fn scores(checker: &GpsQualityChecker, positions: Vec<NormalizedPosition>) -> Vec<Option<u8>> {
+        let mut positions = positions;
+        checker.apply(&mut positions);
+        positions
+            .iter()
+            .map(|position| position.quality_score)
+            .collect()
+    }
+
+    #[test]
+    fn satellite_threshold_is_inclusive() {
+        let checker = checker(GpsQualityPolicy::Flag);
+        assert_eq!(
+            scores(
+                &checker,
+                vec![position(0, 19.0, 4), position(1_000, 19.0, 3)]
+            ),
+            vec![Some(100), Some(70)]
+        );
+    }
+
+    #[test]
+    fn jump_boundaries_in_distance_and_time() {
+        // 0.089° de latitud son ~9.9 km y 0.091° ~10.1 km
+        let checker = checker(GpsQualityPolicy::Flag);
+        assert_eq!(
+            scores(
+                &checker,
+                vec![
+                    position(0, 19.0, 10),
+                    position(60, 19.089, 10),
+                    position(120, 19.18, 10)
+                ]
+            ),
+            vec![Some(100), Some(100), Some(40)]
+        );
+
+        // Pasado `max_jump_secs` cualquier distancia es plausible
+        let checker = self::checker(GpsQualityPolicy::Flag);
+        assert_eq!(
+            scores(
+                &checker,
+                vec![
+                    position(0, 19.0, 10),
+                    position(61, 20.0, 10),
+                    position(122, 21.0, 10)
+                ]
+            ),
+            vec![Some(100), Some(100), Some(100)]
+        );
+    }
+
+    #[test]
+    fn teleport_is_rejected_and_does_not_become_the_reference() {
+        let checker = checker(GpsQualityPolicy::Drop);
+        let mut positions = vec![
+            position(0, 19.0, 10),
+            // 100 km en 10 s con pocos satélites: 100 - 60 - 30
+            position(10, 19.9, 3),
+            // Movimiento normal respecto a la última posición válida
+            position(20, 19.001, 10),
+        ];
+        checker.apply(&mut positions);
+        assert_eq!(
+            positions
+                .iter()
+                .map(|position| position.gps_epoch.unwrap_or_default())
+                .collect::<Vec<_>>(),
+            vec![0, 20]
+        );
+        assert_eq!(checker.invalid(), 1);
+    }
+
+    #[test]
+    fn null_island_and_out_of_range_score_zero() {
+        let checker = checker(GpsQualityPolicy::Quarantine);
+        let null_island = NormalizedPosition {
+            latitude: Some(0.0),
+            longitude: Some(0.0),
+            ..position(0, 0.0, 10)
+        };
+        let mut message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        message.data.latitude = "91".to_string();
+        let out_of_range = NormalizedPosition::from_device_message(&message);
+        let mut no_fix = position(0, 0.0, 10);
+        no_fix.latitude = None;
+
+        let mut positions = vec![null_island, out_of_range, no_fix, position(5, 19.0, 10)];
+        let quarantined = checker.apply(&mut positions);
+        assert_eq!(
+            quarantined
+                .iter()
+                .map(|(position, reasons)| (position.quality_score, reasons.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(0), vec!["null_island"]),
+                (Some(0), vec!["out_of_range"])
+            ]
+        );
+        // Sin coordenadas no se califica
+        assert_eq!(
+            positions
+                .iter()
+                .map(|position| position.quality_score)
+                .collect::<Vec<_>>(),
+            vec![None, Some(100)]
+        );
+    }
+}
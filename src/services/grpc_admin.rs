@@ -0,0 +1,183 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status};
+use tracing::{error, info, warn};
+
+use crate::services::{GeofenceService, MessageProcessor, PipelineControl, PipelineStage};
+
+// Código generado desde siscom_admin.proto por build.rs
+#[path = "../siscom.admin.v1.rs"]
+#[allow(clippy::all)]
+mod proto;
+
+use proto::consumer_admin_server::{ConsumerAdmin, ConsumerAdminServer};
+use proto::*;
+
+/// Servicio gRPC de administración (`siscom_admin.proto`) para las herramientas de
+/// operación: estadísticas, pausa de etapas, flush, recarga y shutdown
+pub struct GrpcAdminServer {
+    processor: MessageProcessor,
+    pipeline: Arc<PipelineControl>,
+    geofences: Option<Arc<GeofenceService>>,
+    // Inicia el shutdown graceful, como Ctrl+C
+    shutdown: Arc<Notify>,
+}
+
+impl GrpcAdminServer {
+    pub fn new(
+        processor: MessageProcessor,
+        pipeline: Arc<PipelineControl>,
+        shutdown: Arc<Notify>,
+    ) -> Self {
+        Self {
+            processor,
+            pipeline,
+            geofences: None,
+            shutdown,
+        }
+    }
+
+    /// Permite recargar las geocercas con `ReloadConfig`
+    pub fn with_geofences(mut self, geofences: Arc<GeofenceService>) -> Self {
+        self.geofences = Some(geofences);
+        self
+    }
+
+    /// Empieza a escuchar en `bind` y atiende las peticiones en segundo plano
+    pub async fn start(self, bind: &str) -> Result<JoinHandle<()>> {
+        let listener = tokio::net::TcpListener::bind(bind).await?;
+        info!("🛠️ API gRPC de administración escuchando en {}", bind);
+
+        Ok(tokio::spawn(async move {
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(ConsumerAdminServer::new(self))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+            {
+                error!("❌ API gRPC de administración detenida: {}", e);
+            }
+        }))
+    }
+
+    fn pipeline_status(&self) -> PipelineStatus {
+        let status = self.pipeline.status();
+        PipelineStatus {
+            intake_paused: status.intake_paused,
+            db_writes_paused: status.db_writes_paused,
+            kafka_output_paused: status.kafka_output_paused,
+        }
+    }
+
+    /// Pausa o reanuda la etapa pedida; None si la etapa no es válida
+    fn set_paused(&self, request: Request<StageRequest>, paused: bool) -> Option<PipelineStatus> {
+        let stage = match Stage::try_from(request.into_inner().stage).ok()? {
+            Stage::Intake => PipelineStage::Intake,
+            Stage::DbWrites => PipelineStage::DbWrites,
+            Stage::KafkaOutput => PipelineStage::KafkaOutput,
+            Stage::Unspecified => return None,
+        };
+        self.pipeline.set_paused(stage, paused);
+        Some(self.pipeline_status())
+    }
+}
+
+#[tonic::async_trait]
+impl ConsumerAdmin for GrpcAdminServer {
+    async fn get_stats(
+        &self,
+        _request: Request<GetStatsRequest>,
+    ) -> Result<Response<GetStatsResponse>, Status> {
+        let stats = self.processor.get_statistics().await;
+        let status = self.processor.drain_status().await;
+        let stats_json =
+            serde_json::to_string(&stats).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(GetStatsResponse {
+            queued: status.queued as u64,
+            in_lanes: status.in_lanes as u64,
+            db_buffered: status.buffered as u64,
+            db_buffer_dropped: stats.db_buffer_dropped,
+            circuit_open: stats.circuit_open,
+            batch_size: stats.batch_size as u64,
+            flush_interval_ms: stats.flush_interval_ms,
+            lanes: stats
+                .lanes
+                .iter()
+                .map(|lane| LaneStats {
+                    messages: lane.messages,
+                    batches: lane.batches,
+                    pending: lane.pending as u64,
+                })
+                .collect(),
+            pipeline: Some(self.pipeline_status()),
+            stats_json,
+        }))
+    }
+
+    async fn pause(
+        &self,
+        request: Request<StageRequest>,
+    ) -> Result<Response<PipelineStatus>, Status> {
+        self.set_paused(request, true)
+            .map(Response::new)
+            .ok_or_else(|| Status::invalid_argument("etapa desconocida"))
+    }
+
+    async fn resume(
+        &self,
+        request: Request<StageRequest>,
+    ) -> Result<Response<PipelineStatus>, Status> {
+        self.set_paused(request, false)
+            .map(Response::new)
+            .ok_or_else(|| Status::invalid_argument("etapa desconocida"))
+    }
+
+    async fn flush_buffers(
+        &self,
+        _request: Request<FlushBuffersRequest>,
+    ) -> Result<Response<FlushBuffersResponse>, Status> {
+        info!("🛠️ Flush de buffers pedido por gRPC");
+        self.processor
+            .flush_all_buffers()
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(FlushBuffersResponse {
+            db_buffered: self.processor.drain_status().await.buffered as u64,
+        }))
+    }
+
+    async fn reload_config(
+        &self,
+        _request: Request<ReloadConfigRequest>,
+    ) -> Result<Response<ReloadConfigResponse>, Status> {
+        let Some(geofences) = &self.geofences else {
+            return Err(Status::failed_precondition(
+                "sin configuración recargable: las geocercas están desactivadas",
+            ));
+        };
+        let count = geofences
+            .reload()
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(ReloadConfigResponse {
+            geofences: count as u64,
+        }))
+    }
+
+    async fn drain_and_exit(
+        &self,
+        _request: Request<DrainAndExitRequest>,
+    ) -> Result<Response<DrainAndExitResponse>, Status> {
+        let pending = self.processor.drain_status().await.total();
+        warn!(
+            "🛠️ Shutdown pedido por gRPC ({} mensajes pendientes)",
+            pending
+        );
+        self.shutdown.notify_one();
+        Ok(Response::new(DrainAndExitResponse {
+            pending: pending as u64,
+        }))
+    }
+}
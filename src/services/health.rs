@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::HealthConfig;
+use crate::services::{DatabaseService, MessageConsumer, MessageProcessor};
+
+/// Resultado de la última revisión, devuelto por `/health/live` y `/health/ready`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthStatus {
+    pub database: bool,
+    pub kafka: bool,
+    /// Mensajes en el canal de entrada y en los carriles
+    pub queue_depth: usize,
+    /// Registros en los buffers de BD
+    pub db_buffer: usize,
+    /// Motivos por los que la instancia no está lista
+    pub problems: Vec<String>,
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Desde cuándo la BD o Kafka no responden
+    pub unhealthy_since: Option<DateTime<Utc>>,
+}
+
+/// Revisa periódicamente la conexión a la BD y a Kafka y la profundidad de las colas.
+/// Las sondas leen el último resultado, sin consultar la BD en cada petición.
+pub struct HealthMonitor {
+    config: HealthConfig,
+    database: Arc<DatabaseService>,
+    consumer: Arc<dyn MessageConsumer>,
+    processor: MessageProcessor,
+    status: Mutex<HealthStatus>,
+}
+
+impl HealthMonitor {
+    pub fn new(
+        config: HealthConfig,
+        database: Arc<DatabaseService>,
+        consumer: Arc<dyn MessageConsumer>,
+        processor: MessageProcessor,
+    ) -> Self {
+        Self {
+            config,
+            database,
+            consumer,
+            processor,
+            status: Mutex::new(HealthStatus::default()),
+        }
+    }
+
+    /// Intervalo entre revisiones
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.check_interval_secs)
+    }
+
+    /// Ejecuta los health checks y guarda el resultado para las sondas
+    pub async fn check(&self) -> HealthStatus {
+        let database = self.database.health_check().await.unwrap_or(false);
+        let kafka = self.consumer.health_check().await.unwrap_or(false);
+        let drain = self.processor.drain_status().await;
+        let queue_depth = drain.queued + drain.in_lanes;
+
+        let mut problems = Vec::new();
+        if !database {
+            problems.push("base de datos no disponible".to_string());
+        }
+        if !kafka {
+            problems.push("Kafka no disponible".to_string());
+        }
+        if self.config.max_queue_depth > 0 && queue_depth > self.config.max_queue_depth {
+            problems.push(format!(
+                "{} mensajes en cola (límite {})",
+                queue_depth, self.config.max_queue_depth
+            ));
+        }
+        if self.config.max_db_buffer > 0 && drain.buffered > self.config.max_db_buffer {
+            problems.push(format!(
+                "{} registros en el buffer de BD (límite {})",
+                drain.buffered, self.config.max_db_buffer
+            ));
+        }
+
+        let mut status = self.status.lock().unwrap();
+        let was_ready = status.checked_at.is_some() && status.problems.is_empty();
+        if problems.is_empty() && !was_ready {
+            info!("💚 Instancia lista (BD y Kafka disponibles)");
+        } else if !problems.is_empty() {
+            warn!("⚠️ Instancia no lista: {}", problems.join(", "));
+        }
+
+        let now = Utc::now();
+        *status = HealthStatus {
+            database,
+            kafka,
+            queue_depth,
+            db_buffer: drain.buffered,
+            problems,
+            checked_at: Some(now),
+            unhealthy_since: match database && kafka {
+                true => None,
+                false => status.unhealthy_since.or(Some(now)),
+            },
+        };
+        status.clone()
+    }
+
+    /// Lista para recibir tráfico: la última revisión no encontró problemas
+    pub fn readiness(&self) -> (bool, HealthStatus) {
+        let status = self.status.lock().unwrap().clone();
+        let ready = status.checked_at.is_some() && status.problems.is_empty();
+        (ready, status)
+    }
+
+    /// Viva mientras la BD y Kafka no lleven caídos más de `liveness_grace_secs` y
+    /// las revisiones sigan ejecutándose. Antes de la primera revisión se considera viva.
+    pub fn liveness(&self) -> (bool, HealthStatus) {
+        let status = self.status.lock().unwrap().clone();
+        let grace = chrono::Duration::seconds(self.config.liveness_grace_secs as i64);
+        let now = Utc::now();
+        let down_too_long = status
+            .unhealthy_since
+            .is_some_and(|since| now - since > grace);
+        let checks_stalled = status.checked_at.is_some_and(|checked_at| {
+            now - checked_at > grace + chrono::Duration::from_std(self.interval()).unwrap()
+        });
+        (!down_too_long && !checks_stalled, status)
+    }
+}
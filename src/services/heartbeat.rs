@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::{AppConfig, HeartbeatConfig};
+use crate::services::{MessageProcessor, NotificationPublisher};
+
+/// Estado de la instancia publicado en `HEARTBEAT_TOPIC`, con el instance_id como clave
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub instance_id: String,
+    pub service: &'static str,
+    pub version: &'static str,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: i64,
+    /// Mensajes recibidos desde el arranque, de todos los orígenes
+    pub messages: u64,
+    /// Promedio del último minuto, de todos los orígenes
+    pub messages_per_sec: f64,
+    pub queued: usize,
+    pub in_lanes: usize,
+    /// Registros en los buffers de BD (principal, tenants y fan-out)
+    pub db_buffered: usize,
+    /// Memoria estimada de colas y buffers, en bytes
+    pub memory_bytes: usize,
+    pub circuit_open: bool,
+    /// Hash de la configuración sin secretos: instancias con el mismo hash corren la
+    /// misma configuración
+    pub config_hash: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Publica periódicamente un `Heartbeat` para que el monitoreo de la flota siga cada
+/// instancia sin consultar su API. No respeta la pausa de `kafka_output`: un heartbeat
+/// retenido ya no describe la instancia.
+pub struct HeartbeatPublisher {
+    config: HeartbeatConfig,
+    publisher: NotificationPublisher,
+    processor: MessageProcessor,
+    config_hash: String,
+    started_at: DateTime<Utc>,
+}
+
+impl HeartbeatPublisher {
+    pub fn new(
+        config: HeartbeatConfig,
+        publisher: NotificationPublisher,
+        processor: MessageProcessor,
+        app_config: &AppConfig,
+    ) -> Self {
+        let config_hash = config_hash(app_config);
+        info!(
+            "💓 Heartbeat de '{}' cada {}s en {} (config {})",
+            config.instance_id, config.interval_secs, config.topic, config_hash
+        );
+        Self {
+            config,
+            publisher,
+            processor,
+            config_hash,
+            started_at: Utc::now(),
+        }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+            loop {
+                interval.tick().await;
+                let heartbeat = self.heartbeat().await;
+                match self
+                    .publisher
+                    .publish(&self.config.instance_id, &heartbeat)
+                    .await
+                {
+                    Ok(()) => debug!("💓 Heartbeat publicado"),
+                    Err(e) => warn!("⚠️ No se pudo publicar el heartbeat: {:#}", e),
+                }
+            }
+        })
+    }
+
+    async fn heartbeat(&self) -> Heartbeat {
+        let stats = self.processor.get_statistics().await;
+        let status = self.processor.drain_status().await;
+        let now = Utc::now();
+        Heartbeat {
+            instance_id: self.config.instance_id.clone(),
+            service: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            started_at: self.started_at,
+            uptime_secs: (now - self.started_at).num_seconds(),
+            messages: stats.sources.iter().map(|source| source.messages).sum(),
+            messages_per_sec: stats
+                .sources
+                .iter()
+                .map(|source| source.messages_per_sec)
+                .sum(),
+            queued: status.queued,
+            in_lanes: status.in_lanes,
+            db_buffered: status.buffered,
+            memory_bytes: stats.memory.total,
+            circuit_open: stats.circuit_open,
+            config_hash: self.config_hash.clone(),
+            at: now,
+        }
+    }
+}
+
+/// SHA-256 (16 primeros dígitos hex) de la configuración sin secretos. Se excluye la
+/// sección del heartbeat, que incluye el instance_id y cambia en cada instancia, y
+/// el índice de shard.
+fn config_hash(config: &AppConfig) -> String {
+    let mut safe = config.display_safe();
+    safe.heartbeat = None;
+    // Cada instancia tiene su propio shard
+    if let Some(sharding) = &mut safe.sharding {
+        sharding.index = 0;
+    }
+    // Pasar por Value ordena las claves de los HashMap, cuyo orden cambia entre procesos
+    let serialized = serde_json::to_value(&safe)
+        .and_then(|value| serde_json::to_vec(&value))
+        .unwrap_or_default();
+    let digest = format!("{:x}", Sha256::digest(&serialized));
+    digest[..16].to_string()
+}
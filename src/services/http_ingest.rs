@@ -0,0 +1,394 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{IngestConfig, PayloadFormat};
+use crate::errors::ErrorCategory;
+use crate::models::DeviceMessage;
+use crate::services::message_consumer::PartitionLag;
+use crate::services::raw_decoder::RawFrameStatistics;
+use crate::services::{Backpressure, CaptureWriter, MessageConsumer, PipelineMetrics};
+
+/// Tamaño máximo del cuerpo de `POST /ingest`
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Origen de los mensajes ingeridos por HTTP, para las estadísticas
+const SOURCE: &str = "http";
+
+/// Ingesta de mensajes por HTTP como fuente adicional al broker: `POST /ingest`
+/// recibe un DeviceMessage JSON o un arreglo de ellos y los pasa al mismo canal
+/// del procesador. Sirve para backfills, pruebas de integración y clientes que no
+/// pueden publicar en el broker.
+///
+/// Un lote inválido se rechaza completo. Con el consumo bajo presión o detenido
+/// responde 503 sin encolar nada para que el cliente reintente; si el procesador se
+/// detiene a mitad del lote, `accepted` indica cuántos mensajes alcanzó a recibir. Un token de ingesta no
+/// elige tenant: los mensajes con `tenant` se rechazan en lugar de guardarse en los
+/// carriles y tablas de otro.
+pub struct HttpIngest {
+    config: IngestConfig,
+    // Hashes de los tokens, para compararlos en tiempo constante
+    tokens: Vec<[u8; 32]>,
+    backpressure: Backpressure,
+    metrics: Option<Arc<PipelineMetrics>>,
+    // Canal del procesador actual; None mientras no se consume
+    sender: Mutex<Option<mpsc::UnboundedSender<DeviceMessage>>>,
+    capture: Option<Arc<CaptureWriter>>,
+}
+
+impl HttpIngest {
+    pub fn new(config: IngestConfig, backpressure: Backpressure) -> Self {
+        Self {
+            tokens: config
+                .tokens
+                .iter()
+                .map(|token| token_hash(token))
+                .collect(),
+            config,
+            backpressure,
+            metrics: None,
+            sender: Mutex::new(None),
+            capture: None,
+        }
+    }
+
+    /// Cuenta los lotes inválidos en las estadísticas del pipeline
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Guarda cada mensaje aceptado, como JSON, para reproducirlo con `replay-file`
+    pub fn with_capture(mut self, capture: Arc<CaptureWriter>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Consumidor que entrega los mensajes de `consumer` y los de la ingesta HTTP
+    /// por el mismo canal
+    pub fn merge(self: &Arc<Self>, consumer: Arc<dyn MessageConsumer>) -> Arc<dyn MessageConsumer> {
+        Arc::new(IngestingConsumer {
+            inner: consumer,
+            ingest: self.clone(),
+        })
+    }
+
+    /// Empieza a escuchar en la dirección configurada y atiende las peticiones en
+    /// segundo plano
+    pub async fn start(self: &Arc<Self>) -> Result<JoinHandle<()>> {
+        let listener = tokio::net::TcpListener::bind(&self.config.bind).await?;
+        info!(
+            "📥 Ingesta HTTP escuchando en {} (máximo {} mensajes por petición)",
+            self.config.bind, self.config.max_batch
+        );
+
+        let router = Router::new()
+            .route("/ingest", post(ingest))
+            .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+            .with_state(self.clone());
+
+        Ok(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("❌ Ingesta HTTP detenida: {}", e);
+            }
+        }))
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        let hash = token_hash(token.trim());
+        self.tokens.contains(&hash)
+    }
+
+    fn attach(&self, sender: mpsc::UnboundedSender<DeviceMessage>) {
+        *self.sender.lock().unwrap() = Some(sender);
+    }
+
+    /// Suelta el canal del procesador para que pueda cerrarse; si se indica
+    /// `sender`, solo cuando sigue siendo el actual
+    fn detach(&self, sender: Option<&mpsc::UnboundedSender<DeviceMessage>>) {
+        let mut current = self.sender.lock().unwrap();
+        let matches = match (current.as_ref(), sender) {
+            (Some(current), Some(sender)) => current.same_channel(sender),
+            _ => true,
+        };
+        if matches {
+            *current = None;
+        }
+    }
+
+    fn reject(&self, status: StatusCode, reason: String) -> Response {
+        if status == StatusCode::BAD_REQUEST {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_error(ErrorCategory::ParseError);
+            }
+        }
+        warn!("📥 Ingesta HTTP rechazada ({}): {}", status, reason);
+        (status, reason).into_response()
+    }
+}
+
+fn token_hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+async fn ingest(
+    State(ingest): State<Arc<HttpIngest>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !ingest.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if *ingest.backpressure.subscribe().borrow() {
+        return busy("consumo pausado por presión");
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return ingest.reject(StatusCode::BAD_REQUEST, format!("JSON inválido: {}", e)),
+    };
+    let items = match payload {
+        Value::Array(items) => items,
+        item => vec![item],
+    };
+    if items.len() > ingest.config.max_batch {
+        return ingest.reject(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "{} mensajes, el máximo por petición es {}",
+                items.len(),
+                ingest.config.max_batch
+            ),
+        );
+    }
+
+    let mut messages = Vec::with_capacity(items.len());
+    let mut captured = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        if ingest.capture.is_some() {
+            captured.push(item.to_string());
+        }
+        if item.get("tenant").is_some_and(|tenant| !tenant.is_null()) {
+            return ingest.reject(
+                StatusCode::BAD_REQUEST,
+                format!("mensaje {}: el tenant no se acepta por HTTP", index),
+            );
+        }
+        match DeviceMessage::from_json_value(item) {
+            Ok(mut message) => {
+                message.source = Some(SOURCE.to_string());
+                messages.push(message);
+            }
+            Err(e) => {
+                return ingest.reject(
+                    StatusCode::BAD_REQUEST,
+                    format!("mensaje {}: {:#}", index, e),
+                )
+            }
+        }
+    }
+
+    let Some(sender) = ingest.sender.lock().unwrap().clone() else {
+        return busy("consumo detenido");
+    };
+    if sender.is_closed() {
+        return busy("procesador no disponible");
+    }
+    let total = messages.len();
+    let mut accepted = 0;
+    for message in messages {
+        if sender.send(message).is_err() {
+            break;
+        }
+        accepted += 1;
+    }
+    if accepted == 0 {
+        return busy("procesador no disponible");
+    }
+    if accepted < total {
+        warn!(
+            "⚠️ Procesador detenido durante la ingesta HTTP: {} de {} mensajes entregados",
+            accepted, total
+        );
+    }
+    if let Some(capture) = &ingest.capture {
+        for item in &captured[..accepted] {
+            capture.record(SOURCE, PayloadFormat::Json, item.as_bytes());
+        }
+    }
+    debug!("📥 {} mensajes recibidos por HTTP", accepted);
+
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "accepted": accepted })),
+    )
+        .into_response()
+}
+
+fn busy(reason: &str) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, "1")],
+        reason.to_string(),
+    )
+        .into_response()
+}
+
+/// Consumidor del broker con los mensajes de la ingesta HTTP sumados a su canal.
+/// El canal se cierra cuando terminan el consumidor y la ingesta, así el drenado
+/// y los reinicios del procesador funcionan igual que sin ingesta.
+struct IngestingConsumer {
+    inner: Arc<dyn MessageConsumer>,
+    ingest: Arc<HttpIngest>,
+}
+
+#[async_trait]
+impl MessageConsumer for IngestingConsumer {
+    async fn start_consuming(&self) -> Result<mpsc::UnboundedReceiver<DeviceMessage>> {
+        let mut messages = self.inner.start_consuming().await?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.ingest.attach(tx.clone());
+
+        let ingest = self.ingest.clone();
+        tokio::spawn(async move {
+            while let Some(message) = messages.recv().await {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+            // Sin broker el procesador debe ver el canal cerrado
+            ingest.detach(Some(&tx));
+        });
+
+        Ok(rx)
+    }
+
+    fn raw_frames(&self) -> RawFrameStatistics {
+        self.inner.raw_frames()
+    }
+
+    async fn lag(&self) -> Result<Vec<PartitionLag>> {
+        self.inner.lag().await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        self.inner.health_check().await
+    }
+
+    fn since_last_poll(&self) -> Option<Duration> {
+        self.inner.since_last_poll()
+    }
+
+    async fn stop_consuming(&self) -> Result<()> {
+        self.ingest.detach(None);
+        self.inner.stop_consuming().await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const SUNTECH_STT: &[u8] = include_bytes!("../../tests/fixtures/messages/suntech_stt.json");
+
+    fn ingest_with_channel() -> (Arc<HttpIngest>, mpsc::UnboundedReceiver<DeviceMessage>) {
+        let ingest = Arc::new(HttpIngest::new(
+            IngestConfig {
+                bind: "127.0.0.1:0".to_string(),
+                tokens: vec!["token-backfill".to_string()],
+                max_batch: 10,
+            },
+            Backpressure::new(1_000),
+        ));
+        let (tx, rx) = mpsc::unbounded_channel();
+        ingest.attach(tx);
+        (ingest, rx)
+    }
+
+    async fn post(ingest: &Arc<HttpIngest>, body: Value) -> StatusCode {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer token-backfill"),
+        );
+        super::ingest(
+            State(ingest.clone()),
+            headers,
+            Bytes::from(body.to_string()),
+        )
+        .await
+        .status()
+    }
+
+    #[tokio::test]
+    async fn messages_are_accepted_without_a_tenant() {
+        let (ingest, mut rx) = ingest_with_channel();
+        let message: Value = serde_json::from_slice(SUNTECH_STT).unwrap();
+
+        assert_eq!(
+            post(&ingest, Value::Array(vec![message.clone(), message])).await,
+            StatusCode::ACCEPTED
+        );
+        for _ in 0..2 {
+            let message = rx.try_recv().unwrap();
+            assert_eq!(message.source.as_deref(), Some(SOURCE));
+            assert_eq!(message.tenant, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_tenant_in_the_body_rejects_the_whole_batch() {
+        let (ingest, mut rx) = ingest_with_channel();
+        let message: Value = serde_json::from_slice(SUNTECH_STT).unwrap();
+        let mut spoofed = message.clone();
+        spoofed["tenant"] = Value::from("globex");
+
+        assert_eq!(
+            post(&ingest, Value::Array(vec![message.clone(), spoofed])).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(rx.try_recv().is_err());
+
+        // `null` equivale a no enviarlo
+        let mut unset = message;
+        unset["tenant"] = Value::Null;
+        assert_eq!(post(&ingest, unset).await, StatusCode::ACCEPTED);
+        assert!(rx.try_recv().unwrap().tenant.is_none());
+    }
+
+    #[tokio::test]
+    async fn nothing_is_queued_once_the_processor_is_gone() {
+        let (ingest, rx) = ingest_with_channel();
+        drop(rx);
+        let message: Value = serde_json::from_slice(SUNTECH_STT).unwrap();
+
+        assert_eq!(
+            post(&ingest, Value::Array(vec![message.clone(), message])).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
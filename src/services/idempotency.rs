@@ -0,0 +1,277 @@
+use anyhow::Result;
+use lru::LruCache;
+use redis::aio::ConnectionManager;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::config::{IdempotencyBackend, IdempotencyConfig, IdempotencyKey};
+use crate::models::DeviceMessage;
+
+/// Claves de idempotencia compartidas en Redis
+struct RedisKeys {
+    connection: ConnectionManager,
+    key_prefix: String,
+    ttl_secs: u64,
+}
+
+/// Recuerda las claves de los mensajes ya guardados para descartar los que vuelven a
+/// llegar: redeliveries tras un rebalanceo o reconexión y mensajes de un replay. Con
+/// Redis las claves se comparten entre instancias y sobreviven a los reinicios. El
+/// índice único de `uuid` en la BD sigue siendo la última garantía.
+pub struct IdempotencyStore {
+    key: IdempotencyKey,
+    recent: Option<Mutex<LruCache<String, ()>>>,
+    redis: Option<RedisKeys>,
+    skipped: AtomicU64,
+}
+
+impl IdempotencyStore {
+    /// None si no hay caché en memoria ni Redis. `redis_url` solo se usa con el
+    /// backend `redis`.
+    pub async fn from_config(
+        config: &IdempotencyConfig,
+        cache_size: usize,
+        redis_url: Option<&str>,
+    ) -> Result<Option<Self>> {
+        let recent = NonZeroUsize::new(cache_size).map(|size| Mutex::new(LruCache::new(size)));
+
+        let redis = match (config.backend, redis_url) {
+            (IdempotencyBackend::Redis, Some(url)) => {
+                let client = redis::Client::open(url)?;
+                Some(RedisKeys {
+                    connection: ConnectionManager::new(client).await?,
+                    key_prefix: config.key_prefix.clone(),
+                    ttl_secs: config.ttl_secs.max(1),
+                })
+            }
+            _ => None,
+        };
+
+        if recent.is_none() && redis.is_none() {
+            return Ok(None);
+        }
+
+        info!(
+            "🔑 Idempotencia por {:?} (memoria: {}, Redis: {})",
+            config.key,
+            cache_size,
+            if redis.is_some() { "sí" } else { "no" }
+        );
+        Ok(Some(Self {
+            key: config.key,
+            recent,
+            redis,
+            skipped: AtomicU64::new(0),
+        }))
+    }
+
+    /// Mensajes descartados por estar ya procesados desde el inicio
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Clave estable del mensaje según la configuración
+    pub fn key_for(&self, message: &DeviceMessage) -> String {
+        match self.key {
+            IdempotencyKey::DeviceCounter
+                if !message.data.msg_counter.is_empty() && !message.data.gps_epoch.is_empty() =>
+            {
+                format!(
+                    "{}:{}:{}",
+                    message.data.device_id, message.data.msg_counter, message.data.gps_epoch
+                )
+            }
+            _ => message.uuid.clone(),
+        }
+    }
+
+    /// Quita del lote los mensajes ya procesados y los repetidos dentro del mismo lote
+    pub async fn drop_seen(&self, batch: &mut Vec<DeviceMessage>) {
+        let before = batch.len();
+        let mut keys = HashSet::with_capacity(batch.len());
+        {
+            let mut recent = self.recent.as_ref().map(|recent| recent.lock().unwrap());
+            batch.retain(|message| {
+                let key = self.key_for(message);
+                let seen = recent
+                    .as_mut()
+                    .is_some_and(|recent| recent.get(&key).is_some());
+                !seen && keys.insert(key)
+            });
+        }
+
+        if let Some(redis) = &self.redis {
+            if !batch.is_empty() {
+                let keys: Vec<String> = batch
+                    .iter()
+                    .map(|message| format!("{}{}", redis.key_prefix, self.key_for(message)))
+                    .collect();
+                let mut pipe = redis::pipe();
+                for key in &keys {
+                    pipe.exists(key);
+                }
+                let mut connection = redis.connection.clone();
+                match pipe.query_async::<Vec<bool>>(&mut connection).await {
+                    Ok(seen) => {
+                        let mut seen = seen.into_iter();
+                        batch.retain(|_| !seen.next().unwrap_or(false));
+                    }
+                    // Sin Redis se sigue con la caché en memoria y el índice de la BD
+                    Err(e) => warn!("⚠️ No se pudieron consultar claves de idempotencia: {}", e),
+                }
+            }
+        }
+
+        let skipped = before - batch.len();
+        if skipped > 0 {
+            self.skipped.fetch_add(skipped as u64, Ordering::Relaxed);
+            debug!("🔁 {} mensajes ya procesados descartados", skipped);
+        }
+    }
+
+    /// Recuerda las claves de un lote ya guardado
+    pub async fn remember(&self, batch: &[DeviceMessage]) {
+        self.remember_keys(batch.iter().map(|message| self.key_for(message)).collect())
+            .await;
+    }
+
+    /// Recuerda claves calculadas con `key_for`, para lotes que se guardan después
+    pub async fn remember_keys(&self, keys: Vec<String>) {
+        if let Some(recent) = &self.recent {
+            let mut recent = recent.lock().unwrap();
+            for key in &keys {
+                recent.put(key.clone(), ());
+            }
+        }
+
+        if let Some(redis) = &self.redis {
+            if keys.is_empty() {
+                return;
+            }
+            let mut pipe = redis::pipe();
+            for key in &keys {
+                pipe.set_ex(format!("{}{}", redis.key_prefix, key), 1, redis.ttl_secs)
+                    .ignore();
+            }
+            let mut connection = redis.connection.clone();
+            if let Err(e) = pipe.query_async::<()>(&mut connection).await {
+                warn!("⚠️ No se pudieron guardar claves de idempotencia: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store(key: IdempotencyKey, cache_size: usize) -> IdempotencyStore {
+        let config = IdempotencyConfig {
+            key,
+            ..IdempotencyConfig::default()
+        };
+        IdempotencyStore::from_config(&config, cache_size, None)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn message(uuid: &str) -> DeviceMessage {
+        let mut message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        message.uuid = uuid.to_string();
+        message
+    }
+
+    fn uuids(batch: &[DeviceMessage]) -> Vec<&str> {
+        batch.iter().map(|message| message.uuid.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn disabled_without_memory_cache_or_redis() {
+        let config = IdempotencyConfig::default();
+        assert!(IdempotencyStore::from_config(&config, 0, None)
+            .await
+            .unwrap()
+            .is_none());
+
+        // El backend Redis sin REDIS_URL tampoco guarda claves
+        let config = IdempotencyConfig {
+            backend: IdempotencyBackend::Redis,
+            ..config
+        };
+        assert!(IdempotencyStore::from_config(&config, 0, None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn uuid_key_is_the_message_uuid() {
+        let store = store(IdempotencyKey::Uuid, 10).await;
+
+        assert_eq!(store.key_for(&message("a")), "a");
+    }
+
+    #[tokio::test]
+    async fn device_counter_key_ignores_the_uuid() {
+        let store = store(IdempotencyKey::DeviceCounter, 10).await;
+
+        assert_eq!(store.key_for(&message("a")), "205700001:3187:1710167422");
+        assert_eq!(store.key_for(&message("a")), store.key_for(&message("b")));
+    }
+
+    #[tokio::test]
+    async fn device_counter_key_falls_back_to_the_uuid() {
+        let store = store(IdempotencyKey::DeviceCounter, 10).await;
+
+        let mut without_counter = message("a");
+        without_counter.data.msg_counter.clear();
+        assert_eq!(store.key_for(&without_counter), "a");
+
+        let mut without_epoch = message("b");
+        without_epoch.data.gps_epoch.clear();
+        assert_eq!(store.key_for(&without_epoch), "b");
+    }
+
+    #[tokio::test]
+    async fn only_remembered_keys_are_dropped() {
+        let store = store(IdempotencyKey::Uuid, 10).await;
+
+        // Consultar un lote no lo recuerda: si su escritura falla, la redelivery pasa
+        let mut batch = vec![message("a"), message("b"), message("a")];
+        store.drop_seen(&mut batch).await;
+        assert_eq!(uuids(&batch), ["a", "b"]);
+        assert_eq!(store.skipped(), 1);
+
+        let mut batch = vec![message("a"), message("b")];
+        store.drop_seen(&mut batch).await;
+        assert_eq!(uuids(&batch), ["a", "b"]);
+
+        store.remember_keys(vec!["a".to_string()]).await;
+        store.drop_seen(&mut batch).await;
+        assert_eq!(uuids(&batch), ["b"]);
+        assert_eq!(store.skipped(), 2);
+    }
+
+    #[tokio::test]
+    async fn least_recently_seen_keys_are_evicted_at_cache_size() {
+        let store = store(IdempotencyKey::Uuid, 2).await;
+        store.remember(&[message("a"), message("b")]).await;
+
+        // Consultar "a" la vuelve la más reciente: "b" sale al recordar "c"
+        let mut batch = vec![message("a")];
+        store.drop_seen(&mut batch).await;
+        assert!(batch.is_empty());
+        store.remember(&[message("c")]).await;
+
+        let mut batch = vec![message("a"), message("b"), message("c")];
+        store.drop_seen(&mut batch).await;
+        assert_eq!(uuids(&batch), ["b"]);
+    }
+}
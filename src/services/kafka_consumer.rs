@@ -1,82 +1,320 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use opentelemetry::trace::TraceContextExt;
 use prost::Message as ProstMessage;
 use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::Message;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use rdkafka::consumer::{
+    BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer,
+};
+use rdkafka::error::{KafkaError, KafkaResult, RDKafkaErrorCode};
+use rdkafka::{ClientContext, Message, Offset};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::config::BrokerConfig;
-use crate::models::DeviceMessage;
-use crate::services::MessageConsumer;
+use crate::config::{BrokerConfig, KafkaConfig, PayloadFormat};
+use crate::errors::ErrorCategory;
+use crate::models::{schema_version, server_ack, DeviceMessage, CURRENT_SCHEMA_VERSION};
+use crate::redact;
+use crate::services::device_ack::DeviceAckPublisher;
+use crate::services::message_consumer::PartitionLag;
+use crate::services::raw_decoder::{RawDecoder, RawFrameStatistics};
+use crate::services::schema_registry::{self, SchemaRegistryClient};
+use crate::services::{
+    Backpressure, CaptureWriter, FieldMapping, MessageConsumer, PayloadAuthenticator,
+    PipelineMetrics, ShardFilter,
+};
+use crate::telemetry;
+
+/// Tiempo máximo para consultar offsets y watermarks al broker
+const LAG_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Máximo entre vueltas del loop de consumo aunque no lleguen mensajes, para que el
+/// watchdog distinga un loop sin tráfico de uno atascado
+const POLL_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Espera entre reintentos mientras el Schema Registry no responde
+const REGISTRY_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+const REGISTRY_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Contexto del consumer principal. Las particiones que llegan con un rebalanceo
+/// vienen reanudadas; si el consumo está pausado por presión se pausan también.
+struct PressureContext {
+    backpressure: Backpressure,
+}
+
+impl ClientContext for PressureContext {}
+
+impl ConsumerContext for PressureContext {
+    fn post_rebalance(&self, base_consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        let Rebalance::Assign(partitions) = rebalance else {
+            return;
+        };
+        if !self.backpressure.is_paused() || partitions.count() == 0 {
+            return;
+        }
+        match base_consumer.pause(partitions) {
+            Ok(()) => info!(
+                "⏸️ {} particiones asignadas en el rebalanceo quedan pausadas",
+                partitions.count()
+            ),
+            Err(e) => error!("Error pausando las particiones asignadas: {}", e),
+        }
+    }
+}
+
+type PressureConsumer = StreamConsumer<PressureContext>;
 
 /// Servicio consumidor de Kafka que lee mensajes protobuf
 #[derive(Clone)]
 pub struct KafkaConsumerService {
-    consumer: Arc<StreamConsumer>,
+    consumer: Arc<PressureConsumer>,
     topic: String,
+    schema_registry: Option<SchemaRegistryClient>,
+    raw_decoder: Arc<RawDecoder>,
+    field_mapping: Option<Arc<FieldMapping>>,
+    // Tópicos adicionales de cada tenant; sus mensajes llegan con el tenant asignado
+    topic_tenants: Arc<HashMap<String, String>>,
+    backpressure: Backpressure,
+    // Errores de recepción y decodificación para las estadísticas
+    metrics: Option<Arc<PipelineMetrics>>,
+    // Detiene las tareas de consumo al iniciar el drenado del shutdown
+    stopping: Arc<watch::Sender<bool>>,
+    // Última vuelta del loop de consumo, vigilada por el watchdog
+    last_poll: Arc<Mutex<Option<Instant>>>,
+    // Copia de los payloads recibidos, antes de decodificarlos
+    capture: Option<Arc<CaptureWriter>>,
+    // Verifica la firma de cada payload antes de capturarlo y decodificarlo
+    authenticator: Option<Arc<PayloadAuthenticator>>,
+    // Confirmaciones Queclink que el front-end TCP reenvía a los equipos
+    device_acks: Option<Arc<DeviceAckPublisher>>,
+    // Entrega los mensajes de otros shards antes de guardar su offset
+    shard: Option<Arc<ShardFilter>>,
 }
 
 impl KafkaConsumerService {
     /// Crea un nuevo consumidor Kafka
-    pub fn new(config: &BrokerConfig) -> Result<Self> {
-        // Crear configuración base con binding para evitar problemas de lifetime
-        let mut binding = ClientConfig::new();
-        let base_config = binding
-            .set("bootstrap.servers", &config.host)
+    pub fn new(config: &BrokerConfig, backpressure: Backpressure) -> Result<Self> {
+        let mut base_config = Self::client_config(config);
+        base_config
             .set("group.id", &config.group_id)
             .set("auto.offset.reset", "latest")
             .set("enable.auto.commit", "true")
+            // El offset se guarda a mano cuando el mensaje queda resuelto, para que el
+            // auto-commit no avance sobre uno que aún se está reintentando
+            .set("enable.auto.offset.store", "false")
             .set("auto.commit.interval.ms", "1000")
             .set("session.timeout.ms", "6000");
 
-        // Configurar SASL authentication si las variables de entorno están presentes
-        info!(
-            "KAFKA_SASL_MECHANISM env: {:?}",
-            std::env::var("KAFKA_SASL_MECHANISM")
-        );
-        let client_config = if let Ok(security_protocol) = std::env::var("KAFKA_SECURITY_PROTOCOL")
-        {
-            info!("🔐 Configurando security.protocol: {}", security_protocol);
-            base_config.set("security.protocol", security_protocol)
-        } else {
-            base_config
-        };
-
-        let client_config = if let Ok(sasl_mechanism) = std::env::var("KAFKA_SASL_MECHANISM") {
-            info!("🔐 Configurando sasl.mechanism: {}", sasl_mechanism);
-            client_config.set("sasl.mechanism", sasl_mechanism)
-        } else {
-            client_config
-        };
-
-        let client_config = if let Ok(username) = std::env::var("KAFKA_USERNAME") {
-            info!("🔐 Configurando sasl.username: {}", username);
-            client_config.set("sasl.username", username)
-        } else {
-            client_config
-        };
-
-        let client_config = if let Ok(password) = std::env::var("KAFKA_PASSWORD") {
-            info!("🔐 Configurando sasl.password: [PROTECTED]");
-            client_config.set("sasl.password", password)
-        } else {
-            client_config
-        };
+        let consumer: PressureConsumer = base_config.create_with_context(PressureContext {
+            backpressure: backpressure.clone(),
+        })?;
 
-        let consumer: StreamConsumer = client_config.create()?;
+        let schema_registry = config
+            .schema_registry
+            .as_ref()
+            .map(SchemaRegistryClient::new)
+            .transpose()?;
 
         info!("✅ Kafka Consumer configurado para broker: {}", config.host);
 
         Ok(Self {
             consumer: Arc::new(consumer),
             topic: config.topic.clone(),
+            schema_registry,
+            raw_decoder: Arc::new(RawDecoder::new(config.payload_format)),
+            field_mapping: None,
+            topic_tenants: Arc::new(HashMap::new()),
+            backpressure,
+            metrics: None,
+            stopping: Arc::new(watch::channel(false).0),
+            last_poll: Arc::new(Mutex::new(None)),
+            capture: None,
+            authenticator: None,
+            device_acks: None,
+            shard: None,
         })
     }
 
+    /// Se suscribe también a los tópicos de `topics` (tópico -> tenant) y marca sus
+    /// mensajes con el tenant correspondiente
+    pub fn with_topic_tenants(mut self, topics: &BTreeMap<String, String>) -> Self {
+        self.topic_tenants = Arc::new(
+            topics
+                .iter()
+                .map(|(topic, tenant)| (topic.clone(), tenant.clone()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Guarda cada payload recibido, tal como llegó, para reproducirlo con `replay-file`
+    pub fn with_capture(mut self, capture: Arc<CaptureWriter>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Descarta los mensajes sin una firma válida de su tenant
+    pub fn with_authenticator(mut self, authenticator: Arc<PayloadAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Publica la confirmación `+SACK` de cada trama Queclink recibida
+    pub fn with_device_acks(mut self, device_acks: Arc<DeviceAckPublisher>) -> Self {
+        self.device_acks = Some(device_acks);
+        self
+    }
+
+    /// Republica o descarta los mensajes de dispositivos de otros shards; el offset de
+    /// uno republicado se guarda solo cuando el broker confirma la publicación
+    pub fn with_shard(mut self, shard: Arc<ShardFilter>) -> Self {
+        self.shard = Some(shard);
+        self
+    }
+
+    /// Aplica las reglas de mapeo a cada mensaje antes de convertirlo a DeviceMessage
+    pub fn with_field_mapping(mut self, mapping: Arc<FieldMapping>) -> Self {
+        self.field_mapping = Some(mapping);
+        self
+    }
+
+    /// Cuenta los errores de recepción y decodificación en las estadísticas del pipeline
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// ClientConfig común a todos los clientes Kafka: broker y seguridad TLS/SASL
+    pub fn client_config(config: &BrokerConfig) -> ClientConfig {
+        let mut client_config = ClientConfig::new();
+        client_config.set("bootstrap.servers", &config.host);
+        Self::apply_security(&mut client_config, &config.kafka);
+        client_config
+    }
+
+    /// Aplica la configuración TLS/SASL sobre un ClientConfig de rdkafka
+    fn apply_security(client_config: &mut ClientConfig, kafka: &KafkaConfig) {
+        let settings = [
+            ("security.protocol", &kafka.security_protocol),
+            ("sasl.mechanism", &kafka.sasl_mechanism),
+            ("sasl.username", &kafka.sasl_username),
+            ("ssl.ca.location", &kafka.ssl_ca_location),
+            ("ssl.certificate.location", &kafka.ssl_certificate_location),
+            ("ssl.key.location", &kafka.ssl_key_location),
+        ];
+
+        for (key, value) in settings {
+            if let Some(value) = value {
+                info!("🔐 Configurando {}: {}", key, value);
+                client_config.set(key, value);
+            }
+        }
+
+        // Valores sensibles: se aplican sin mostrarlos en los logs
+        let secrets = [
+            ("sasl.password", &kafka.sasl_password),
+            ("sasl.oauthbearer.config", &kafka.sasl_oauthbearer_config),
+            ("ssl.key.password", &kafka.ssl_key_password),
+        ];
+
+        for (key, value) in secrets {
+            if let Some(value) = value {
+                info!("🔐 Configurando {}: [PROTECTED]", key);
+                client_config.set(key, value);
+            }
+        }
+    }
+
+    /// Quita el framing de Schema Registry, decodifica el protobuf (o la trama cruda
+    /// con `PayloadFormat::Raw`), lo lleva al layout actual, aplica el mapeo de campos
+    /// y lo convierte a DeviceMessage. Los payloads JSON ya son DeviceMessage y no
+    /// pasan por el mapeo.
+    pub async fn decode_payload(
+        registry: Option<&SchemaRegistryClient>,
+        mapping: Option<&FieldMapping>,
+        raw_decoder: &RawDecoder,
+        payload: &[u8],
+    ) -> Result<DeviceMessage> {
+        let mut kafka_msg = match raw_decoder.format() {
+            PayloadFormat::Protobuf => {
+                let body = schema_registry::unwrap_payload(registry, payload)
+                    .await
+                    .context("Error resolviendo schema del mensaje")?;
+                let mut kafka_msg = crate::config::siscom::KafkaMessage::decode(body)
+                    .context("Error decodificando mensaje protobuf")?;
+                schema_version::upgrade_data_map(kafka_msg.schema_version, &mut kafka_msg.data)?;
+                raw_decoder.fill_from_raw(&mut kafka_msg);
+                kafka_msg
+            }
+            PayloadFormat::Raw => raw_decoder.message_from_frame(payload)?,
+            PayloadFormat::Json => {
+                return DeviceMessage::from_json(payload)
+                    .context("Error decodificando mensaje JSON");
+            }
+        };
+        if let Some(mapping) = mapping {
+            mapping.apply(&mut kafka_msg);
+        }
+        Self::kafka_message_to_device_message(&kafka_msg)
+            .context("Error convirtiendo mensaje protobuf a DeviceMessage")
+    }
+
+    /// Como `decode_payload`, pero reintenta con backoff mientras el Schema Registry
+    /// no responda, llamando a `on_retry` en cada intento fallido. None si se pidió
+    /// detener el consumo antes de lograrlo.
+    pub async fn decode_retrying(
+        registry: Option<&SchemaRegistryClient>,
+        mapping: Option<&FieldMapping>,
+        raw_decoder: &RawDecoder,
+        payload: &[u8],
+        stopping: &mut watch::Receiver<bool>,
+        on_retry: impl Fn(),
+    ) -> Option<Result<DeviceMessage>> {
+        let mut delay = REGISTRY_RETRY_MIN_DELAY;
+        loop {
+            match Self::decode_payload(registry, mapping, raw_decoder, payload).await {
+                Err(e) if schema_registry::is_unavailable(&e) => {
+                    on_retry();
+                    warn!(
+                        category = %ErrorCategory::SchemaRegistryUnavailable,
+                        "📚 {:#}; reintentando en {}s",
+                        e,
+                        delay.as_secs()
+                    );
+                    tokio::select! {
+                        _ = stopping.wait_for(|stopping| *stopping) => return None,
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                    delay = (delay * 2).min(REGISTRY_RETRY_MAX_DELAY);
+                }
+                decoded => return Some(decoded),
+            }
+        }
+    }
+
+    /// Pausa o reanuda todas las particiones asignadas actualmente
+    fn set_paused(consumer: &PressureConsumer, paused: bool) -> KafkaResult<()> {
+        let assignment = consumer.assignment()?;
+        if paused {
+            consumer.pause(&assignment)?;
+            info!(
+                "⏸️ Consumo Kafka pausado ({} particiones)",
+                assignment.count()
+            );
+        } else {
+            consumer.resume(&assignment)?;
+            info!(
+                "▶️ Consumo Kafka reanudado ({} particiones)",
+                assignment.count()
+            );
+        }
+        Ok(())
+    }
+
     /// Convierte un mensaje protobuf KafkaMessage a DeviceMessage
     fn kafka_message_to_device_message(
         kafka_msg: &crate::config::siscom::KafkaMessage,
@@ -130,136 +368,7 @@ impl KafkaConsumerService {
                 trip_distance: data_map.get("TRIP_DISTANCE").cloned().unwrap_or_default(),
                 trip_hourmeter: data_map.get("TRIP_HOURMETER").cloned().unwrap_or_default(),
             },
-            decoded: match &kafka_msg.decoded {
-                Some(crate::config::siscom::kafka_message::Decoded::Suntech(suntech)) => {
-                    crate::models::DecodedData::Suntech {
-                        suntech_raw: Box::new(crate::models::SuntechRaw {
-                            assign_map: suntech
-                                .fields
-                                .get("ASSIGN_MAP")
-                                .cloned()
-                                .unwrap_or_default(),
-                            axis_x: suntech.fields.get("AXIS_X").cloned().unwrap_or_default(),
-                            axis_y: suntech.fields.get("AXIST_Y").cloned().unwrap_or_default(),
-                            axis_z: suntech.fields.get("AXIS_Z").cloned().unwrap_or_default(),
-                            cell_id: suntech.fields.get("CELL_ID").cloned().unwrap_or_default(),
-                            course: suntech.fields.get("CRS").cloned().unwrap_or_default(),
-                            device_id: suntech.fields.get("DEVICE_ID").cloned().unwrap_or_default(),
-                            fix: suntech.fields.get("FIX").cloned().unwrap_or_default(),
-                            firmware: suntech.fields.get("FW").cloned().unwrap_or_default(),
-                            gps_date: suntech.fields.get("GPS_DATE").cloned().unwrap_or_default(),
-                            gps_time: suntech.fields.get("GPS_TIME").cloned().unwrap_or_default(),
-                            header: suntech.fields.get("HEADER").cloned().unwrap_or_default(),
-                            idle_time: suntech.fields.get("IDLE_TIME").cloned().unwrap_or_default(),
-                            in_state: suntech.fields.get("IN_STATE").cloned().unwrap_or_default(),
-                            lac: suntech.fields.get("LAC").cloned().unwrap_or_default(),
-                            latitude: suntech.fields.get("LAT").cloned().unwrap_or_default(),
-                            longitude: suntech.fields.get("LON").cloned().unwrap_or_default(),
-                            mcc: suntech.fields.get("MCC").cloned().unwrap_or_default(),
-                            mnc: suntech.fields.get("MNC").cloned().unwrap_or_default(),
-                            model: suntech.fields.get("MODEL").cloned().unwrap_or_default(),
-                            mode_map: suntech.fields.get("MODE_MAP").cloned().unwrap_or_default(),
-                            msg_num: suntech.fields.get("MSG_NUM").cloned().unwrap_or_default(),
-                            msg_type: suntech.fields.get("MSG_TYPE").cloned().unwrap_or_default(),
-                            net_status: suntech
-                                .fields
-                                .get("NET_STATUS")
-                                .cloned()
-                                .unwrap_or_default(),
-                            odometer_mts: suntech
-                                .fields
-                                .get("ODOMETER_MTS")
-                                .cloned()
-                                .unwrap_or_default(),
-                            out_state: suntech.fields.get("OUT_STATE").cloned().unwrap_or_default(),
-                            report_map: suntech
-                                .fields
-                                .get("REPORT_MAP")
-                                .cloned()
-                                .unwrap_or_default(),
-                            rx_lvl: suntech.fields.get("RX_LVL").cloned().unwrap_or_default(),
-                            satellites: suntech.fields.get("SAT").cloned().unwrap_or_default(),
-                            speed: suntech.fields.get("SPD").cloned().unwrap_or_default(),
-                            speed_time: suntech
-                                .fields
-                                .get("SPEED_TIME")
-                                .cloned()
-                                .unwrap_or_default(),
-                            stt_rpt_type: suntech
-                                .fields
-                                .get("STT_RPT_TYPE")
-                                .cloned()
-                                .unwrap_or_default(),
-                            total_distance: suntech
-                                .fields
-                                .get("TOTAL_DISTANCE")
-                                .cloned()
-                                .unwrap_or_default(),
-                            trip_distance: suntech
-                                .fields
-                                .get("TRIP_DISTANCE")
-                                .cloned()
-                                .unwrap_or_default(),
-                            trip_hourmeter: suntech
-                                .fields
-                                .get("TRIP_HOURMETER")
-                                .cloned()
-                                .unwrap_or_default(),
-                            volt_backup: suntech
-                                .fields
-                                .get("VOLT_BACKUP")
-                                .cloned()
-                                .unwrap_or_default(),
-                            volt_main: suntech.fields.get("VOLT_MAIN").cloned().unwrap_or_default(),
-                        }),
-                    }
-                }
-                Some(crate::config::siscom::kafka_message::Decoded::Queclink(queclink)) => {
-                    crate::models::DecodedData::Queclink {
-                        queclink_raw: Box::new(crate::models::QueclinkRaw {
-                            altitude: queclink.fields.get("ALTITUDE").cloned().unwrap_or_default(),
-                            cell_id: queclink.fields.get("CELL_ID").cloned().unwrap_or_default(),
-                            course: queclink.fields.get("CRS").cloned().unwrap_or_default(),
-                            device_id: queclink
-                                .fields
-                                .get("DEVICE_ID")
-                                .cloned()
-                                .unwrap_or_default(),
-                            fix: queclink.fields.get("FIX").cloned().unwrap_or_default(),
-                            gps_date_time: queclink
-                                .fields
-                                .get("GPS_DATE_TIME")
-                                .cloned()
-                                .unwrap_or_default(),
-                            header: queclink.fields.get("HEADER").cloned().unwrap_or_default(),
-                            lac: queclink.fields.get("LAC").cloned().unwrap_or_default(),
-                            latitude: queclink.fields.get("LAT").cloned().unwrap_or_default(),
-                            longitude: queclink.fields.get("LON").cloned().unwrap_or_default(),
-                            mcc: queclink.fields.get("MCC").cloned().unwrap_or_default(),
-                            mnc: queclink.fields.get("MNC").cloned().unwrap_or_default(),
-                            msg_num: queclink.fields.get("MSG_NUM").cloned().unwrap_or_default(),
-                            protocol_version: queclink
-                                .fields
-                                .get("PROTOCOL_VERSION")
-                                .cloned()
-                                .unwrap_or_default(),
-                            reserved: queclink.fields.get("RESERVED").cloned().unwrap_or_default(),
-                            send_date_time: queclink
-                                .fields
-                                .get("SEND_DATE_TIME")
-                                .cloned()
-                                .unwrap_or_default(),
-                            speed: queclink.fields.get("SPD").cloned().unwrap_or_default(),
-                        }),
-                    }
-                }
-                None => {
-                    // Si no hay datos decodificados, usar valores por defecto
-                    crate::models::DecodedData::Suntech {
-                        suntech_raw: Box::new(crate::models::SuntechRaw::default()),
-                    }
-                }
-            },
+            decoded: crate::models::DecodedData::from_proto(kafka_msg.decoded.as_ref()),
             metadata: crate::models::DeviceMetadata {
                 bytes: metadata.bytes as i32,
                 client_ip: metadata.client_ip.clone(),
@@ -270,6 +379,12 @@ impl KafkaConsumerService {
             },
             raw: kafka_msg.raw.clone(),
             uuid: kafka_msg.uuid.clone(),
+            tenant: None,
+            schema_version: CURRENT_SCHEMA_VERSION,
+            source: None,
+            trace_context: None,
+            ack: None,
+            signed_by: None,
         };
 
         Ok(device_message)
@@ -281,48 +396,183 @@ impl MessageConsumer for KafkaConsumerService {
     async fn start_consuming(&self) -> Result<mpsc::UnboundedReceiver<DeviceMessage>> {
         let (tx, rx) = mpsc::unbounded_channel();
 
-        // Suscribirse al topic
-        self.consumer.subscribe(&[&self.topic])?;
+        // Suscribirse al topic principal y a los de cada tenant
+        let mut topics = vec![self.topic.as_str()];
+        topics.extend(self.topic_tenants.keys().map(String::as_str));
+        self.consumer.subscribe(&topics)?;
 
-        info!("🔌 Suscrito al topic Kafka: {}", self.topic);
+        info!("🔌 Suscrito a los topics Kafka: {}", topics.join(", "));
 
         // Clonar referencias para la tarea
         let consumer = Arc::clone(&self.consumer);
+        let registry = self.schema_registry.clone();
+        let raw_decoder = Arc::clone(&self.raw_decoder);
+        let mapping = self.field_mapping.clone();
+        let topic_tenants = self.topic_tenants.clone();
+        let metrics = self.metrics.clone();
+        let record_error = move |category| {
+            if let Some(metrics) = &metrics {
+                metrics.record_error(category);
+            }
+        };
+        let mut pressure = self.backpressure.subscribe();
+        let mut stopping = self.stopping.subscribe();
+        let last_poll = self.last_poll.clone();
+        let capture = self.capture.clone();
+        let authenticator = self.authenticator.clone();
+        let device_acks = self.device_acks.clone();
+        let shard = self.shard.clone();
         let tx_clone = tx.clone();
 
-        // Iniciar tarea de consumo
+        // Iniciar tarea de consumo. Al terminar se cierra el canal y el procesador
+        // escribe lo que tenga pendiente.
         tokio::spawn(async move {
+            let mut paused = false;
             loop {
-                match consumer.recv().await {
+                *last_poll.lock().unwrap() = Some(Instant::now());
+                if *stopping.borrow_and_update() {
+                    info!("⏹️ Consumo de Kafka detenido");
+                    break;
+                }
+
+                // Pausar/reanudar las particiones asignadas según la presión del procesador
+                let under_pressure = *pressure.borrow_and_update();
+                if under_pressure != paused {
+                    if let Err(e) = Self::set_paused(&consumer, under_pressure) {
+                        error!("Error cambiando estado de pausa del consumer: {}", e);
+                    }
+                    paused = under_pressure;
+                }
+
+                let result = tokio::select! {
+                    Ok(()) = stopping.changed() => continue,
+                    Ok(()) = pressure.changed() => continue,
+                    result = consumer.recv() => result,
+                    _ = tokio::time::sleep(POLL_HEARTBEAT) => continue,
+                };
+
+                match result {
                     Ok(message) => {
-                        if let Some(payload) = message.payload() {
-                            match ProstMessage::decode(payload) {
-                                Ok(kafka_msg) => {
-                                    match Self::kafka_message_to_device_message(&kafka_msg) {
-                                        Ok(device_msg) => {
-                                            debug!(
-                                                "✅ Mensaje protobuf parseado para dispositivo: {}",
-                                                device_msg.data.device_id
-                                            );
-
-                                            if let Err(e) = tx_clone.send(device_msg) {
-                                                error!("Error enviando mensaje al canal: {}", e);
-                                                break;
+                        // true: mensaje resuelto (entregado al procesador o descartado
+                        // por inválido); false: se detiene el consumo sin resolverlo
+                        let handled = 'message: {
+                            let Some(payload) = message.payload() else {
+                                break 'message true;
+                            };
+                            let mut signed_by = None;
+                            if let Some(authenticator) = &authenticator {
+                                let topic_tenant =
+                                    topic_tenants.get(message.topic()).map(String::as_str);
+                                match authenticator.verify_kafka(
+                                    message.headers(),
+                                    topic_tenant,
+                                    payload,
+                                ) {
+                                    Ok(key_id) => signed_by = Some(key_id),
+                                    Err(e) => {
+                                        record_error(ErrorCategory::SignatureInvalid);
+                                        warn!(
+                                            category = %ErrorCategory::SignatureInvalid,
+                                            "🔏 Mensaje de {} descartado: {}",
+                                            message.topic(),
+                                            e
+                                        );
+                                        break 'message true;
+                                    }
+                                }
+                            }
+                            if let Some(capture) = &capture {
+                                capture.record(message.topic(), raw_decoder.format(), payload);
+                            }
+
+                            // Continúa la traza del productor si la envió en los headers
+                            let span = info_span!(
+                                "consume",
+                                messaging.destination.name = message.topic(),
+                                messaging.kafka.partition = message.partition(),
+                                messaging.kafka.offset = message.offset(),
+                            );
+                            span.set_parent(telemetry::extract_kafka_context(message.headers()));
+
+                            let decoded = Self::decode_retrying(
+                                registry.as_ref(),
+                                mapping.as_deref(),
+                                &raw_decoder,
+                                payload,
+                                &mut stopping,
+                                || {
+                                    record_error(ErrorCategory::SchemaRegistryUnavailable);
+                                    *last_poll.lock().unwrap() = Some(Instant::now());
+                                },
+                            )
+                            .instrument(info_span!(parent: &span, "parse"))
+                            .await;
+                            let Some(decoded) = decoded else {
+                                break 'message false;
+                            };
+                            match decoded {
+                                Ok(mut device_msg) => {
+                                    device_msg.tenant = topic_tenants.get(message.topic()).cloned();
+                                    device_msg.source = Some(message.topic().to_string());
+                                    device_msg.trace_context =
+                                        Some(span.context().span().span_context().clone());
+                                    device_msg.signed_by = signed_by;
+
+                                    // Los mensajes de otro shard se entregan antes de guardar
+                                    // el offset: si no se logra republicarlos, se vuelven a leer
+                                    if let Some(shard) =
+                                        shard.as_ref().filter(|shard| !shard.owns(&device_msg))
+                                    {
+                                        tokio::select! {
+                                            _ = shard.hand_off(device_msg) => break 'message true,
+                                            _ = stopping.wait_for(|stopping| *stopping) => {
+                                                break 'message false
                                             }
                                         }
-                                        Err(e) => {
-                                            error!("❌ Error convirtiendo mensaje protobuf a DeviceMessage: {}", e);
-                                        }
+                                    }
+
+                                    // Se confirma al equipo solo cuando el mensaje llega al procesador
+                                    let ack = device_acks
+                                        .as_ref()
+                                        .and_then(|_| server_ack(&device_msg.raw));
+                                    debug!(
+                                        "✅ Mensaje protobuf parseado para dispositivo: {}",
+                                        redact::device_id(&device_msg.data.device_id)
+                                    );
+
+                                    if let Err(e) = tx_clone.send(device_msg) {
+                                        error!("Error enviando mensaje al canal: {}", e);
+                                        break 'message false;
+                                    }
+                                    if let (Some(acks), Some(ack)) = (&device_acks, &ack) {
+                                        acks.acknowledge(ack);
                                     }
                                 }
                                 Err(e) => {
-                                    error!("❌ Error decodificando mensaje protobuf: {}", e);
+                                    if device_acks
+                                        .as_ref()
+                                        .is_some_and(|acks| acks.acknowledge_heartbeat(payload))
+                                    {
+                                        break 'message true;
+                                    }
+                                    record_error(ErrorCategory::ParseError);
+                                    error!(category = %ErrorCategory::ParseError, "❌ {:#}", e);
                                 }
                             }
+                            true
+                        };
+                        if !handled {
+                            info!("⏹️ Consumo de Kafka detenido");
+                            break;
+                        }
+                        if let Err(e) = consumer.store_offset_from_message(&message) {
+                            warn!("⚠️ No se pudo guardar el offset del mensaje: {}", e);
                         }
                     }
                     Err(e) => {
-                        error!("Error recibiendo mensaje de Kafka: {}", e);
+                        let category = ErrorCategory::of_kafka(&e);
+                        record_error(category);
+                        error!(category = %category, "Error recibiendo mensaje de Kafka: {}", e);
                         tokio::time::sleep(Duration::from_secs(1)).await;
                     }
                 }
@@ -332,10 +582,87 @@ impl MessageConsumer for KafkaConsumerService {
         Ok(rx)
     }
 
+    fn raw_frames(&self) -> RawFrameStatistics {
+        self.raw_decoder.statistics()
+    }
+
+    async fn lag(&self) -> Result<Vec<PartitionLag>> {
+        let consumer = Arc::clone(&self.consumer);
+
+        // Las consultas de offsets de rdkafka son bloqueantes
+        tokio::task::spawn_blocking(move || {
+            let committed = consumer.committed(LAG_QUERY_TIMEOUT)?;
+            let mut lags = Vec::new();
+
+            for element in committed.elements() {
+                let (low, high) = consumer.fetch_watermarks(
+                    element.topic(),
+                    element.partition(),
+                    LAG_QUERY_TIMEOUT,
+                )?;
+
+                let committed_offset = match element.offset() {
+                    Offset::Offset(offset) => Some(offset),
+                    _ => None,
+                };
+                // Sin offset confirmado se considera pendiente todo lo retenido
+                let lag = (high - committed_offset.unwrap_or(low)).max(0);
+
+                lags.push(PartitionLag {
+                    topic: element.topic().to_string(),
+                    partition: element.partition(),
+                    committed_offset,
+                    high_watermark: high,
+                    lag,
+                });
+            }
+
+            Ok(lags)
+        })
+        .await?
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        let consumer = Arc::clone(&self.consumer);
+        let topic = self.topic.clone();
+
+        let metadata = tokio::task::spawn_blocking(move || {
+            consumer.fetch_metadata(Some(&topic), LAG_QUERY_TIMEOUT)
+        })
+        .await?;
+        match metadata {
+            Ok(metadata) => Ok(metadata
+                .topics()
+                .iter()
+                .any(|topic| topic.error().is_none() && !topic.partitions().is_empty())),
+            Err(e) => {
+                error!("Kafka health check failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    fn since_last_poll(&self) -> Option<Duration> {
+        self.last_poll.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    async fn stop_consuming(&self) -> Result<()> {
+        self.stopping.send_replace(true);
+        Ok(())
+    }
+
     async fn disconnect(&self) -> Result<()> {
         info!("🔌 Desconectando de Kafka...");
-        // El consumer se desconectará automáticamente al ser dropped
-        Ok(())
+        // Confirmar los offsets de lo consumido antes de salir del grupo; el consumer
+        // se desconectará automáticamente al ser dropped
+        let consumer = Arc::clone(&self.consumer);
+        let committed =
+            tokio::task::spawn_blocking(move || consumer.commit_consumer_state(CommitMode::Sync))
+                .await?;
+        match committed {
+            Ok(()) | Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => Ok(()),
+            Err(e) => Err(anyhow!("No se pudieron confirmar los offsets: {}", e)),
+        }
     }
 }
 
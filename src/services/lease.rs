@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use sqlx::{ConnectOptions, Connection, PgConnection};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::config::HandoverConfig;
+
+/// Intentos fallidos entre dos logs de espera del lease
+const WAIT_LOG_EVERY: u64 = 30;
+
+/// Lease exclusivo de consumo sobre un advisory lock de sesión de PostgreSQL.
+///
+/// La instancia que lo tiene consume; las demás esperan con sus servicios ya
+/// iniciados. En un despliegue la instancia nueva arranca, espera mientras la
+/// anterior drena tras su SIGTERM y, en cuanto esta libera el lock (o su conexión se
+/// cierra al terminar), empieza a consumir: sin hueco de consumo y sin dos instancias
+/// leyendo a la vez. El lock vive en una conexión propia, fuera del pool.
+pub struct InstanceLease {
+    config: HandoverConfig,
+    urls: Vec<String>,
+    // Conexión que tiene el lock (None = lease no tomado)
+    connection: Mutex<Option<PgConnection>>,
+}
+
+impl InstanceLease {
+    pub fn new(config: HandoverConfig, urls: Vec<String>) -> Self {
+        Self {
+            config,
+            urls,
+            connection: Mutex::new(None),
+        }
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.config.poll_interval_ms)
+    }
+
+    /// Espera hasta tomar el lease. Los errores de conexión se reintentan: la BD
+    /// caída no debe terminar una instancia en espera.
+    pub async fn acquire(&self) {
+        info!(
+            "🔐 Esperando el lease de consumo '{}'...",
+            self.config.lease_key
+        );
+        let mut attempts = 0u64;
+        loop {
+            match self.try_acquire().await {
+                Ok(true) => {
+                    info!(
+                        "🔐 Lease '{}' tomado: iniciando consumo",
+                        self.config.lease_key
+                    );
+                    return;
+                }
+                Ok(false) => {
+                    if attempts.is_multiple_of(WAIT_LOG_EVERY) {
+                        info!(
+                            "⏳ Lease '{}' en uso por otra instancia, esperando que drene",
+                            self.config.lease_key
+                        );
+                    }
+                }
+                Err(e) => warn!("⚠️ Error intentando tomar el lease: {:#}", e),
+            }
+            attempts += 1;
+            tokio::time::sleep(self.poll_interval()).await;
+        }
+    }
+
+    async fn try_acquire(&self) -> Result<bool> {
+        let mut connection = self.connect().await?;
+        let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtext($1))")
+            .bind(&self.config.lease_key)
+            .fetch_one(&mut connection)
+            .await?;
+        if acquired {
+            *self.connection.lock().await = Some(connection);
+        } else {
+            let _ = connection.close().await;
+        }
+        Ok(acquired)
+    }
+
+    /// Primera URL que acepta la conexión, identificada en `pg_stat_activity`
+    async fn connect(&self) -> Result<PgConnection> {
+        let mut last_error = anyhow!("sin URLs de PostgreSQL");
+        for url in &self.urls {
+            let options = sqlx::postgres::PgConnectOptions::from_str(url)?
+                .application_name("siscom-consumer-lease");
+            match options.connect().await {
+                Ok(connection) => return Ok(connection),
+                Err(e) => last_error = e.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Termina si se pierde la conexión que tiene el lock: PostgreSQL lo libera y
+    /// otra instancia puede empezar a consumir
+    pub async fn lost(&self) {
+        loop {
+            tokio::time::sleep(self.poll_interval()).await;
+            let mut connection = self.connection.lock().await;
+            let Some(held) = connection.as_mut() else {
+                return;
+            };
+            if let Err(e) = held.ping().await {
+                error!(
+                    "❌ Conexión del lease '{}' perdida: {}",
+                    self.config.lease_key, e
+                );
+                *connection = None;
+                return;
+            }
+        }
+    }
+
+    /// Libera el lease tras el drenado para que la siguiente instancia empiece ya
+    pub async fn release(&self) {
+        let Some(mut connection) = self.connection.lock().await.take() else {
+            return;
+        };
+        match sqlx::query("SELECT pg_advisory_unlock(hashtext($1))")
+            .bind(&self.config.lease_key)
+            .execute(&mut connection)
+            .await
+        {
+            Ok(_) => info!("🔓 Lease '{}' liberado", self.config.lease_key),
+            Err(e) => warn!("⚠️ Error liberando el lease (se libera al cerrar): {}", e),
+        }
+        let _ = connection.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEASE_KEY: &str = "siscom-consumer-test";
+
+    fn lease(urls: Vec<String>) -> InstanceLease {
+        InstanceLease::new(
+            HandoverConfig {
+                lease_key: LEASE_KEY.to_string(),
+                poll_interval_ms: 50,
+            },
+            urls,
+        )
+    }
+
+    /// URL de una base de pruebas, p. ej. `postgres://postgres:x@127.0.0.1:5432/postgres`
+    fn database_url() -> String {
+        std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL no definida")
+    }
+
+    #[tokio::test]
+    async fn unreachable_databases_are_reported_not_acquired() {
+        let lease = lease(vec!["postgres://postgres@127.0.0.1:1/postgres".to_string()]);
+        assert!(lease.try_acquire().await.is_err());
+        assert!(lease.connection.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn lost_returns_at_once_without_the_lease() {
+        tokio::time::timeout(Duration::from_secs(1), lease(Vec::new()).lost())
+            .await
+            .expect("lost() esperó sin tener el lease");
+    }
+
+    #[tokio::test]
+    #[ignore = "requiere PostgreSQL (TEST_DATABASE_URL)"]
+    async fn lease_is_exclusive_until_released() {
+        let first = lease(vec![database_url()]);
+        let second = lease(vec![database_url()]);
+
+        first.acquire().await;
+        assert!(!second.try_acquire().await.unwrap());
+
+        first.release().await;
+        assert!(second.try_acquire().await.unwrap());
+        second.release().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requiere PostgreSQL (TEST_DATABASE_URL)"]
+    async fn losing_the_connection_frees_the_lease() {
+        let first = lease(vec![database_url()]);
+        let second = lease(vec![database_url()]);
+        first.acquire().await;
+
+        // Cierra la sesión que tiene el lock, como un reinicio de PostgreSQL
+        let pid: i32 = {
+            let mut held = first.connection.lock().await;
+            sqlx::query_scalar("SELECT pg_backend_pid()")
+                .fetch_one(held.as_mut().unwrap())
+                .await
+                .unwrap()
+        };
+        let mut admin = PgConnection::connect(&database_url()).await.unwrap();
+        sqlx::query("SELECT pg_terminate_backend($1)")
+            .bind(pid)
+            .execute(&mut admin)
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), first.lost())
+            .await
+            .expect("no se detectó la pérdida del lease");
+        assert!(first.connection.lock().await.is_none());
+        assert!(second.try_acquire().await.unwrap());
+        second.release().await;
+    }
+}
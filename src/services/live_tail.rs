@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::config::LiveTailConfig;
+
+/// Eventos en vuelo por suscriptor; uno lento pierde los más antiguos
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Punto del pipeline en el que se observó el mensaje
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TailStage {
+    /// Mensaje recibido y deserializado, antes de los carriles
+    Parsed,
+    /// Registro de BD construido tras enriquecedores y filtros
+    Record,
+    /// Registro escrito (o ya presente) en la tabla de histórico
+    Inserted,
+}
+
+/// Mensaje visto en una etapa, con los campos sensibles reemplazados
+#[derive(Debug, Clone, Serialize)]
+pub struct TailEvent {
+    pub stage: TailStage,
+    pub device_id: String,
+    pub uuid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table: Option<String>,
+    pub at: DateTime<Utc>,
+    pub data: Value,
+}
+
+/// Vista en vivo de los mensajes en cada etapa, para depurar un equipo en producción
+/// sin acceso a la BD. Sin suscriptores no construye eventos.
+///
+/// Los suscriptores de un device_id reciben todos sus mensajes; los demás, una
+/// muestra de uno de cada `sample_rate` mensajes elegida por uuid, para que un
+/// mensaje muestreado se vea en las tres etapas.
+#[derive(Debug)]
+pub struct LiveTail {
+    config: LiveTailConfig,
+    sender: broadcast::Sender<Arc<TailEvent>>,
+    // Suscriptores por filtro de dispositivo (None = sin filtro)
+    watchers: Mutex<HashMap<Option<String>, usize>>,
+}
+
+impl LiveTail {
+    pub fn new(config: LiveTailConfig) -> Self {
+        Self {
+            config,
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            watchers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Suscripción a los eventos de `device_id`, o a la muestra de todos los mensajes
+    pub fn subscribe(self: &Arc<Self>, device_id: Option<String>) -> TailSubscription {
+        *self
+            .watchers
+            .lock()
+            .unwrap()
+            .entry(device_id.clone())
+            .or_default() += 1;
+        TailSubscription {
+            receiver: self.sender.subscribe(),
+            tail: self.clone(),
+            device_id,
+        }
+    }
+
+    /// Publica el evento si algún suscriptor lo quiere; `data` solo se serializa entonces
+    pub fn publish<T: Serialize>(
+        &self,
+        stage: TailStage,
+        device_id: &str,
+        uuid: &str,
+        table: Option<&str>,
+        data: &T,
+    ) {
+        if !self.wanted(device_id, uuid) {
+            return;
+        }
+        let Ok(mut data) = serde_json::to_value(data) else {
+            return;
+        };
+        redact(&mut data, &self.config.redact_fields);
+        let _ = self.sender.send(Arc::new(TailEvent {
+            stage,
+            device_id: device_id.to_string(),
+            uuid: uuid.to_string(),
+            table: table.map(str::to_string),
+            at: Utc::now(),
+            data,
+        }));
+    }
+
+    fn wanted(&self, device_id: &str, uuid: &str) -> bool {
+        let watchers = self.watchers.lock().unwrap();
+        if watchers.is_empty() {
+            return false;
+        }
+        if watchers
+            .keys()
+            .any(|watched| watched.as_deref() == Some(device_id))
+        {
+            return true;
+        }
+        watchers.contains_key(&None) && sampled(uuid, self.config.sample_rate)
+    }
+
+    fn unsubscribe(&self, device_id: &Option<String>) {
+        let mut watchers = self.watchers.lock().unwrap();
+        if let Some(count) = watchers.get_mut(device_id) {
+            *count -= 1;
+            if *count == 0 {
+                watchers.remove(device_id);
+            }
+        }
+    }
+}
+
+/// Eventos de un suscriptor; al soltarla deja de pedirse su dispositivo
+pub struct TailSubscription {
+    receiver: broadcast::Receiver<Arc<TailEvent>>,
+    tail: Arc<LiveTail>,
+    device_id: Option<String>,
+}
+
+impl TailSubscription {
+    /// Siguiente evento del filtro. `Err(n)` si se perdieron `n` eventos por lentitud;
+    /// `None` si el canal se cerró.
+    pub async fn next(&mut self) -> Option<Result<Arc<TailEvent>, u64>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    let matches = self
+                        .device_id
+                        .as_ref()
+                        .is_none_or(|device_id| *device_id == event.device_id);
+                    if matches {
+                        return Some(Ok(event));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => return Some(Err(skipped)),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for TailSubscription {
+    fn drop(&mut self) {
+        self.tail.unsubscribe(&self.device_id);
+    }
+}
+
+/// Uno de cada `rate` uuids, siempre los mismos
+fn sampled(uuid: &str, rate: u64) -> bool {
+    let mut hasher = DefaultHasher::new();
+    uuid.hash(&mut hasher);
+    hasher.finish().is_multiple_of(rate)
+}
+
+/// Reemplaza los campos indicados, a cualquier profundidad
+fn redact(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if fields.iter().any(|redacted| redacted == key) {
+                    if !field.is_null() {
+                        *field = Value::String("[redacted]".to_string());
+                    }
+                } else {
+                    redact(field, fields);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact(item, fields);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::config::MaintenanceConfig;
+use crate::services::DatabaseService;
+
+/// Resultado del mantenimiento de una tabla en la última ejecución
+#[derive(Debug, Clone, Serialize)]
+pub struct TableMaintenanceStatus {
+    pub name: &'static str,
+    pub table: String,
+    pub retention_days: Option<u32>,
+    pub deleted_rows: u64,
+    pub analyzed: bool,
+    pub error: Option<String>,
+}
+
+/// Estado de la última ejecución, expuesto en `/maintenance` de la API de administración
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceStatus {
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub tables: Vec<TableMaintenanceStatus>,
+}
+
+/// Mantenimiento periódico de las tablas: borra las filas que superan la retención
+/// configurada y ejecuta ANALYZE tras cargas grandes
+pub struct MaintenanceService {
+    database: Arc<DatabaseService>,
+    config: MaintenanceConfig,
+    status: RwLock<MaintenanceStatus>,
+}
+
+impl MaintenanceService {
+    pub fn new(database: Arc<DatabaseService>, config: MaintenanceConfig) -> Self {
+        Self {
+            database,
+            config,
+            status: RwLock::new(MaintenanceStatus::default()),
+        }
+    }
+
+    /// Estado de la última ejecución
+    pub async fn status(&self) -> MaintenanceStatus {
+        self.status.read().await.clone()
+    }
+
+    /// Inicia la tarea que ejecuta el mantenimiento cada `interval_secs`
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let maintenance = self.clone();
+        let interval = Duration::from_secs(self.config.interval_secs);
+        info!(
+            "🧹 Mantenimiento de BD cada {:?} (retención: {:?})",
+            interval, self.config.retention_days
+        );
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                maintenance.run().await;
+            }
+        })
+    }
+
+    /// Ejecuta retención y ANALYZE sobre todas las tablas. Los errores de una tabla
+    /// quedan en su estado y no impiden procesar las demás.
+    pub async fn run(&self) -> MaintenanceStatus {
+        let started = Instant::now();
+        let mut tables = Vec::new();
+
+        for (name, table, column) in self.database.maintenance_tables() {
+            let retention_days = self.config.retention_days.get(name).copied();
+            let mut status = TableMaintenanceStatus {
+                name,
+                table: table.clone(),
+                retention_days,
+                deleted_rows: 0,
+                analyzed: false,
+                error: None,
+            };
+
+            if let Some(days) = retention_days {
+                match self
+                    .database
+                    .delete_older_than(&table, column, days, self.config.delete_batch_size)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(deleted) => {
+                        info!(
+                            "🧹 {}: {} filas con más de {} días eliminadas",
+                            table, deleted, days
+                        );
+                        status.deleted_rows = deleted;
+                    }
+                    Err(e) => {
+                        error!("❌ Error aplicando retención en {}: {}", table, e);
+                        status.error = Some(e.to_string());
+                    }
+                }
+            }
+
+            if self.config.analyze_threshold > 0 && status.error.is_none() {
+                match self
+                    .database
+                    .analyze_if_needed(&table, self.config.analyze_threshold)
+                    .await
+                {
+                    Ok(true) => {
+                        info!("📈 ANALYZE ejecutado en {}", table);
+                        status.analyzed = true;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("❌ Error ejecutando ANALYZE en {}: {}", table, e);
+                        status.error = Some(e.to_string());
+                    }
+                }
+            }
+
+            tables.push(status);
+        }
+
+        let status = MaintenanceStatus {
+            last_run_at: Some(Utc::now()),
+            last_duration_ms: Some(started.elapsed().as_millis() as u64),
+            tables,
+        };
+        *self.status.write().await = status.clone();
+        status
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::models::{CommunicationRecord, DeviceMessage};
+use crate::services::{Backpressure, MessageProcessor};
+
+/// Intervalo entre evaluaciones del límite de memoria
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Peso de cada muestra nueva en el tamaño promedio (1/N)
+const AVERAGE_WEIGHT: usize = 16;
+
+/// Memoria aproximada retenida por cada etapa del pipeline, en bytes
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct MemoryUsage {
+    /// Canal de entrada, aún sin repartir entre los carriles
+    pub input_channel: usize,
+    /// Canales y lotes en curso de los carriles
+    pub lanes: usize,
+    /// Buffers de BD (principal, tenants y fan-out)
+    pub db_buffers: usize,
+    /// Notificaciones retenidas con la etapa `kafka_output` pausada
+    pub kafka_output: usize,
+    pub total: usize,
+    /// El consumo está frenado por superar el límite
+    pub limited: bool,
+}
+
+/// Tamaño promedio de los mensajes y registros del pipeline, para estimar la memoria
+/// de cada etapa a partir de su profundidad sin recorrer las colas
+#[derive(Debug)]
+pub struct MemoryAccounting {
+    message_bytes: AtomicUsize,
+    record_bytes: AtomicUsize,
+    // Bytes exactos de las notificaciones retenidas
+    kafka_output: AtomicUsize,
+}
+
+impl Default for MemoryAccounting {
+    fn default() -> Self {
+        Self {
+            message_bytes: AtomicUsize::new(std::mem::size_of::<DeviceMessage>()),
+            record_bytes: AtomicUsize::new(std::mem::size_of::<CommunicationRecord>()),
+            kafka_output: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl MemoryAccounting {
+    /// Suma un mensaje recibido al tamaño promedio
+    pub fn record_message(&self, message: &DeviceMessage) {
+        update_average(&self.message_bytes, message.approx_size());
+    }
+
+    /// Suma una muestra (el primer registro) de un lote al tamaño promedio
+    pub fn record_records(&self, records: &[CommunicationRecord]) {
+        if let Some(record) = records.first() {
+            update_average(&self.record_bytes, record.approx_size());
+        }
+    }
+
+    pub fn add_kafka_output(&self, bytes: usize) {
+        self.kafka_output.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn remove_kafka_output(&self, bytes: usize) {
+        let _ = self
+            .kafka_output
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |held| {
+                Some(held.saturating_sub(bytes))
+            });
+    }
+
+    /// Estimación por etapa con las profundidades actuales
+    pub fn usage(&self, queued: usize, in_lanes: usize, buffered: usize) -> MemoryUsage {
+        let message_bytes = self.message_bytes.load(Ordering::Relaxed);
+        let mut usage = MemoryUsage {
+            input_channel: queued * message_bytes,
+            lanes: in_lanes * message_bytes,
+            db_buffers: buffered * self.record_bytes.load(Ordering::Relaxed),
+            kafka_output: self.kafka_output.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        usage.total = usage.input_channel + usage.lanes + usage.db_buffers + usage.kafka_output;
+        usage
+    }
+}
+
+/// Promedio móvil exponencial; con varios carriles alguna muestra puede perderse
+fn update_average(average: &AtomicUsize, sample: usize) {
+    let current = average.load(Ordering::Relaxed);
+    let next = (current * (AVERAGE_WEIGHT - 1) + sample) / AVERAGE_WEIGHT;
+    average.store(next, Ordering::Relaxed);
+}
+
+/// Frena el consumo cuando la memoria estimada del pipeline supera `soft_limit`
+/// bytes, antes de que el contenedor llegue a su límite y lo mate el OOM killer.
+/// El consumo se reanuda al bajar del 80% del límite.
+pub struct MemoryLimiter {
+    soft_limit: usize,
+    processor: MessageProcessor,
+    backpressure: Backpressure,
+}
+
+impl MemoryLimiter {
+    pub fn new(
+        soft_limit_mb: u64,
+        processor: MessageProcessor,
+        backpressure: Backpressure,
+    ) -> Self {
+        info!(
+            "🧮 Límite blando de memoria del pipeline: {} MB",
+            soft_limit_mb
+        );
+        Self {
+            soft_limit: (soft_limit_mb * 1024 * 1024) as usize,
+            processor,
+            backpressure,
+        }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let resume_below = self.soft_limit / 10 * 8;
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let usage = self.processor.memory_usage().await;
+                let limited = self.backpressure.is_memory_limited();
+                if !limited && usage.total >= self.soft_limit {
+                    warn!(
+                        "🧮 Memoria del pipeline en {} (límite {}): cola {}, carriles {}, buffers de BD {}, salida Kafka {}; pausando consumo",
+                        format_bytes(usage.total),
+                        format_bytes(self.soft_limit),
+                        format_bytes(usage.input_channel),
+                        format_bytes(usage.lanes),
+                        format_bytes(usage.db_buffers),
+                        format_bytes(usage.kafka_output)
+                    );
+                    self.backpressure.set_memory_limited(true);
+                } else if limited && usage.total <= resume_below {
+                    info!(
+                        "🟢 Memoria del pipeline en {}, reanudando consumo",
+                        format_bytes(usage.total)
+                    );
+                    self.backpressure.set_memory_limited(false);
+                }
+            }
+        })
+    }
+}
+
+/// Bytes en MB con un decimal, para los logs
+pub fn format_bytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -1,8 +1,20 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use std::time::Duration;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::models::DeviceMessage;
+use crate::services::raw_decoder::RawFrameStatistics;
+
+/// Lag del consumidor para una partición
+#[derive(Debug, Clone)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub committed_offset: Option<i64>,
+    pub high_watermark: i64,
+    pub lag: i64,
+}
 
 /// Trait para abstraer diferentes tipos de consumidores de mensajes (Kafka, etc.)
 #[async_trait]
@@ -10,6 +22,22 @@ pub trait MessageConsumer: Send + Sync {
     /// Inicia el consumo de mensajes
     async fn start_consuming(&self) -> Result<UnboundedReceiver<DeviceMessage>>;
 
+    /// Tramas crudas decodificadas por el consumer
+    fn raw_frames(&self) -> RawFrameStatistics;
+
+    /// Calcula el lag por partición (high-watermark vs offset confirmado)
+    async fn lag(&self) -> Result<Vec<PartitionLag>>;
+
+    /// Verifica que el broker responda y que el tópico principal exista
+    async fn health_check(&self) -> Result<bool>;
+
+    /// Tiempo desde la última vuelta del loop de consumo (None si aún no empezó)
+    fn since_last_poll(&self) -> Option<Duration>;
+
+    /// Deja de leer mensajes nuevos. El canal de `start_consuming` se cierra cuando
+    /// termina la tarea de consumo, lo que permite drenar el procesador.
+    async fn stop_consuming(&self) -> Result<()>;
+
     /// Detiene el consumo de mensajes y desconecta
     async fn disconnect(&self) -> Result<()>;
 }
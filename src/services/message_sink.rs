@@ -0,0 +1,180 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{OutputSinksConfig, RetryConfig};
+use crate::models::NormalizedPosition;
+
+/// Salida de las posiciones ya enriquecidas y filtradas (MQTT, Redis Streams, SQS).
+/// Para agregar un destino se implementa este trait y se agrega al pipeline con
+/// `SinkPipeline::push`; el procesador no cambia.
+#[async_trait]
+pub trait MessageSink: Send + Sync {
+    /// Nombre para los logs y las estadísticas
+    fn name(&self) -> &str;
+
+    /// Envía un lote. Un error reintenta el lote completo según la política del
+    /// pipeline, así que el envío debe tolerar duplicados.
+    async fn send(&self, positions: &[NormalizedPosition]) -> Result<()>;
+}
+
+/// Destinos de salida en el orden configurado. Cada uno tiene su cola de lotes y
+/// su tarea de envío con reintentos: un destino lento o caído no frena a los demás
+/// ni al pipeline, y con su cola llena los lotes nuevos se descartan.
+#[derive(Clone, Default)]
+pub struct SinkPipeline {
+    sinks: Vec<SinkWorker>,
+}
+
+#[derive(Clone)]
+struct SinkWorker {
+    name: String,
+    queue: mpsc::Sender<Arc<[NormalizedPosition]>>,
+    counters: Arc<SinkCounters>,
+}
+
+#[derive(Default)]
+struct SinkCounters {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Estado de un destino de salida desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct SinkStatistics {
+    pub name: String,
+    /// Lotes esperando en la cola del destino
+    pub queued: usize,
+    /// Posiciones enviadas
+    pub sent: u64,
+    /// Posiciones descartadas tras agotar los reintentos
+    pub failed: u64,
+    /// Posiciones descartadas con la cola llena
+    pub dropped: u64,
+    pub last_error: Option<String>,
+}
+
+impl SinkPipeline {
+    /// Agrega un destino al final del pipeline e inicia su tarea de envío
+    pub fn push(&mut self, sink: Arc<dyn MessageSink>, config: &OutputSinksConfig) {
+        let (queue, batches) = mpsc::channel(config.queue_size);
+        let counters = Arc::new(SinkCounters::default());
+        let name = sink.name().to_string();
+        info!(
+            "📦 Salida '{}' agregada (cola de {} lotes)",
+            name, config.queue_size
+        );
+        tokio::spawn(run(sink, batches, counters.clone(), config.retry.clone()));
+        self.sinks.push(SinkWorker {
+            name,
+            queue,
+            counters,
+        });
+    }
+
+    /// Encola el lote en cada destino; no espera a ninguno
+    pub fn dispatch(&self, positions: &[NormalizedPosition]) {
+        if positions.is_empty() || self.sinks.is_empty() {
+            return;
+        }
+
+        let batch: Arc<[NormalizedPosition]> = positions.into();
+        for sink in &self.sinks {
+            if sink.queue.try_send(batch.clone()).is_err() {
+                let before = sink
+                    .counters
+                    .dropped
+                    .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                let dropped = before + batch.len() as u64;
+                // Un aviso al primer descarte y cada mil posiciones
+                if before == 0 || before / 1000 != dropped / 1000 {
+                    warn!(
+                        "⚠️ Cola de la salida '{}' llena, {} posiciones descartadas en total",
+                        sink.name, dropped
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn statistics(&self) -> Vec<SinkStatistics> {
+        self.sinks
+            .iter()
+            .map(|sink| SinkStatistics {
+                name: sink.name.clone(),
+                queued: sink.queue.max_capacity() - sink.queue.capacity(),
+                sent: sink.counters.sent.load(Ordering::Relaxed),
+                failed: sink.counters.failed.load(Ordering::Relaxed),
+                dropped: sink.counters.dropped.load(Ordering::Relaxed),
+                last_error: sink
+                    .counters
+                    .last_error
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone(),
+            })
+            .collect()
+    }
+}
+
+/// Envía los lotes del destino en orden, reintentando cada uno con backoff
+async fn run(
+    sink: Arc<dyn MessageSink>,
+    mut batches: mpsc::Receiver<Arc<[NormalizedPosition]>>,
+    counters: Arc<SinkCounters>,
+    retry: RetryConfig,
+) {
+    while let Some(batch) = batches.recv().await {
+        let mut attempt = 1;
+        loop {
+            match sink.send(&batch).await {
+                Ok(()) => {
+                    counters
+                        .sent
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    debug!("📦 {} posiciones enviadas a '{}'", batch.len(), sink.name());
+                    break;
+                }
+                Err(e) if attempt < retry.max_attempts => {
+                    let delay = retry.delay(attempt);
+                    warn!(
+                        "⚠️ Error enviando a '{}' (intento {}/{}), reintentando en {:?}: {:#}",
+                        sink.name(),
+                        attempt,
+                        retry.max_attempts,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    counters
+                        .failed
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    error!(
+                        "❌ {} posiciones descartadas para '{}': {:#}",
+                        batch.len(),
+                        sink.name(),
+                        e
+                    );
+                    *counters
+                        .last_error
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner()) = Some(format!("{:#}", e));
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,113 @@
+pub mod admin;
+pub mod amqp_consumer;
+pub mod archive;
+pub mod aws_sink;
+pub mod backpressure;
+pub mod batch_controller;
+pub mod capture;
+pub mod ch_sink;
+pub mod circuit_breaker;
+pub mod clock;
 pub mod database;
+pub mod device_ack;
+pub mod device_commands;
+pub mod downsampling;
+pub mod drivers;
+pub mod driving_behavior;
+pub mod dry_run;
+pub mod enrichment;
+pub mod es_sink;
+pub mod field_mapping;
+pub mod geofence;
+pub mod gps_quality;
+pub mod grpc_admin;
+pub mod health;
+pub mod heartbeat;
+pub mod http_ingest;
+pub mod idempotency;
 pub mod kafka_consumer;
+pub mod lease;
+pub mod live_tail;
+pub mod maintenance;
+pub mod memory;
 pub mod message_consumer;
+pub mod message_sink;
+pub mod mqtt_publisher;
+pub mod notifications;
+pub mod partitioning;
+pub mod payload_auth;
+pub mod pipeline_control;
+pub mod pipeline_metrics;
+pub mod position_filter;
+pub mod power;
+pub mod presence;
 pub mod processor;
+pub mod raw_decoder;
+pub mod raw_encryption;
+pub mod record_store;
+pub mod redis_state;
+pub mod redis_stream;
+pub mod replay;
+pub mod schema_registry;
+pub mod sharding;
+pub mod startup;
+pub mod supervisor;
+pub mod tenant_router;
+pub mod trips;
+pub mod watchdog;
+pub mod webhooks;
 
+pub use admin::AdminServer;
+pub use amqp_consumer::AmqpConsumerService;
+pub use archive::ArchiveService;
+pub use aws_sink::AwsSink;
+pub use backpressure::Backpressure;
+pub use batch_controller::BatchController;
+pub use capture::{CaptureReplay, CaptureWriter};
+pub use ch_sink::ClickHouseSink;
+pub use clock::{Clock, ManualClock, SystemClock};
 pub use database::DatabaseService;
+pub use device_ack::DeviceAckPublisher;
+pub use device_commands::CommandForwarder;
+pub use downsampling::Downsampler;
+pub use drivers::DriverTracker;
+pub use driving_behavior::DrivingBehaviorDetector;
+pub use dry_run::DryRunReport;
+pub use enrichment::EnricherChain;
+pub use es_sink::ElasticsearchSink;
+pub use field_mapping::FieldMapping;
+pub use geofence::GeofenceService;
+pub use gps_quality::GpsQualityChecker;
+pub use grpc_admin::GrpcAdminServer;
+pub use health::HealthMonitor;
+pub use heartbeat::HeartbeatPublisher;
+pub use http_ingest::HttpIngest;
+pub use idempotency::IdempotencyStore;
 pub use kafka_consumer::KafkaConsumerService;
+pub use lease::InstanceLease;
+pub use live_tail::LiveTail;
+pub use maintenance::MaintenanceService;
+pub use memory::{MemoryAccounting, MemoryLimiter};
 pub use message_consumer::MessageConsumer;
+pub use message_sink::{MessageSink, SinkPipeline};
+pub use mqtt_publisher::MqttPublisher;
+pub use notifications::NotificationPublisher;
+pub use payload_auth::PayloadAuthenticator;
+pub use pipeline_control::{PipelineControl, PipelineStage};
+pub use pipeline_metrics::PipelineMetrics;
+pub use position_filter::PositionFilter;
+pub use power::PowerMonitor;
+pub use presence::PresenceMonitor;
 pub use processor::MessageProcessor;
+pub use raw_encryption::RawCipher;
+pub use record_store::RecordStore;
+pub use redis_state::RedisStateSink;
+pub use redis_stream::RedisStreamSink;
+pub use replay::ReplayService;
+pub use sharding::ShardFilter;
+pub use startup::DependencyWait;
+pub use supervisor::Supervisor;
+pub use tenant_router::TenantRouter;
+pub use trips::TripDetector;
+pub use watchdog::Watchdog;
+pub use webhooks::WebhookNotifier;
@@ -0,0 +1,529 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use rumqttc::{
+    AsyncClient, ConnectReturnCode, ConnectionError, Event, EventLoop, Incoming, MqttOptions,
+    Outgoing, QoS, Request, TlsConfiguration, Transport,
+};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+use tokio_rustls::rustls;
+use tracing::{error, info, warn};
+
+use crate::config::MqttPublishConfig;
+use crate::models::NormalizedPosition;
+use crate::services::message_sink::MessageSink;
+
+/// Tiempo máximo para conectar y recibir el CONNACK
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Espera máxima entre reconexiones
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Publicaciones QoS 1/2 sin confirmar antes de dejar de enviar nuevas
+const MAX_IN_FLIGHT: u16 = 100;
+
+/// Tamaño máximo de un paquete MQTT (rumqttc limita a 10 KiB por omisión)
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+/// Espera entre intentos de encolar cuando la cola del cliente está llena
+const QUEUE_FULL_RETRY: Duration = Duration::from_millis(10);
+
+/// Republica las posiciones normalizadas y enriquecidas como JSON en un broker
+/// MQTT, un mensaje por posición en `normalized/{manufacturer}/{device_id}` (o el
+/// tópico configurado), para suscriptores livianos que no consumen Kafka.
+///
+/// El protocolo (MQTT 3.1.1, QoS 0, 1 y 2, retain, TLS) lo implementa `rumqttc`;
+/// aquí solo se atiende su event loop, que reconecta con backoff, y se relacionan
+/// las confirmaciones del broker con cada publicación. Mientras el broker no
+/// responde las posiciones esperan en la cola acotada del cliente y luego en la
+/// cola de la salida del `SinkPipeline`, que descarta al llenarse: la
+/// republicación nunca frena el pipeline.
+pub struct MqttPublisher {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    confirmations: Arc<Mutex<Confirmations>>,
+    auth_failed: watch::Receiver<bool>,
+}
+
+impl MqttPublisher {
+    pub fn new(config: &MqttPublishConfig) -> Result<Self> {
+        info!(
+            "📤 Republicación MQTT en {} (tópico: {}, QoS {}, retain: {})",
+            config.url, config.topic, config.qos, config.retain
+        );
+        Self::connect(config)
+    }
+
+    /// Conexión sin el registro de la republicación, para otros publicadores que
+    /// solo usan `try_publish`
+    pub fn connect(config: &MqttPublishConfig) -> Result<Self> {
+        let endpoint = Endpoint::parse(&config.url)?;
+        if !rumqttc::valid_topic(&config.topic) {
+            bail!("Tópico MQTT '{}' con comodines", config.topic);
+        }
+        let qos = rumqttc::qos(config.qos).map_err(|e| anyhow!("QoS MQTT inválido: {}", e))?;
+
+        let mut options = MqttOptions::new(&config.client_id, &endpoint.host, endpoint.port);
+        options
+            .set_keep_alive(Duration::from_secs(config.keep_alive_secs.into()))
+            .set_inflight(MAX_IN_FLIGHT)
+            .set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE)
+            // Con sesión persistente las QoS 1/2 sin confirmar se reenvían al
+            // reconectar; sin client id el broker no puede guardarla
+            .set_clean_session(config.client_id.is_empty());
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        if endpoint.tls {
+            options.set_transport(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
+                tls_config()?,
+            ))));
+        }
+
+        let (client, mut eventloop) = AsyncClient::new(options, config.queue_size.max(1));
+        eventloop
+            .network_options
+            .set_connection_timeout(CONNECT_TIMEOUT.as_secs());
+        let confirmations = Arc::new(Mutex::new(Confirmations::default()));
+        let (auth_sender, auth_failed) = watch::channel(false);
+        tokio::spawn(run(endpoint, eventloop, confirmations.clone(), auth_sender));
+
+        Ok(Self {
+            client,
+            topic: config.topic.clone(),
+            qos,
+            retain: config.retain,
+            confirmations,
+            auth_failed,
+        })
+    }
+
+    /// `host:puerto` del broker, para esperar a que acepte conexiones al arrancar
+    pub fn address(config: &MqttPublishConfig) -> Result<String> {
+        let endpoint = Endpoint::parse(&config.url)?;
+        Ok(format!("{}:{}", endpoint.host, endpoint.port))
+    }
+
+    /// Termina cuando el broker rechaza las credenciales; nunca si no lo hace
+    pub async fn auth_failure(&self) {
+        let mut auth_failed = self.auth_failed.clone();
+        if auth_failed.wait_for(|failed| *failed).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Encola una publicación sin esperar; false si la cola está llena o la conexión
+    /// terminó
+    pub fn try_publish(&self, topic: String, payload: Vec<u8>) -> bool {
+        self.enqueue(topic, payload, None)
+    }
+
+    /// Como `try_publish`, pero devuelve un aviso que se completa cuando el broker
+    /// confirma la publicación: PUBACK con QoS 1, PUBREC con QoS 2 o al escribirla
+    /// con QoS 0. El aviso se cancela si la tarea de publicación termina o si el
+    /// broker perdió la sesión antes de confirmarla.
+    pub fn try_publish_tracked(
+        &self,
+        topic: String,
+        payload: Vec<u8>,
+    ) -> Option<oneshot::Receiver<()>> {
+        let (confirm, confirmed) = oneshot::channel();
+        self.enqueue(topic, payload, Some(confirm))
+            .then_some(confirmed)
+    }
+
+    /// Registra el aviso y encola la publicación bajo el mismo lock, para que los
+    /// avisos queden en el orden en que el event loop envía las publicaciones
+    fn enqueue(
+        &self,
+        topic: String,
+        payload: Vec<u8>,
+        confirm: Option<oneshot::Sender<()>>,
+    ) -> bool {
+        let mut confirmations = self.confirmations.lock().unwrap_or_else(|e| e.into_inner());
+        confirmations.queued.push_back(confirm);
+        let queued = self
+            .client
+            .try_publish(topic, self.qos, self.retain, payload)
+            .is_ok();
+        if !queued {
+            confirmations.queued.pop_back();
+        }
+        queued
+    }
+
+    /// Tópico de una posición. Los comodines y separadores de MQTT en los valores
+    /// se reemplazan para que cada dispositivo quede en un solo nivel.
+    fn topic_for(&self, position: &NormalizedPosition) -> String {
+        let level = |value: &str| value.replace(['/', '+', '#'], "_");
+        self.topic
+            .replace("{manufacturer}", position.manufacturer.as_str())
+            .replace("{device_id}", &level(&position.device_id))
+            .replace(
+                "{tenant}",
+                &level(position.tenant.as_deref().unwrap_or("default")),
+            )
+    }
+}
+
+/// Al soltar el publicador se envía DISCONNECT después de lo ya encolado
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        let _ = self.client.try_disconnect();
+    }
+}
+
+#[async_trait]
+impl MessageSink for MqttPublisher {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    /// Encola una publicación por posición; espera solo si la cola está llena
+    async fn send(&self, positions: &[NormalizedPosition]) -> Result<()> {
+        for position in positions {
+            let payload = match serde_json::to_vec(position) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("⚠️ Posición {} no serializable: {}", position.uuid, e);
+                    continue;
+                }
+            };
+            let topic = self.topic_for(position);
+            // rumqttc no permite esperar lugar en su cola sin perder el orden de
+            // los avisos, así que se reintenta hasta que hay lugar
+            while !self.enqueue(topic.clone(), payload.clone(), None) {
+                if *self.auth_failed.borrow() {
+                    bail!("la tarea de publicación MQTT terminó");
+                }
+                tokio::time::sleep(QUEUE_FULL_RETRY).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Atiende el event loop de rumqttc, que reconecta en el siguiente `poll` tras un
+/// error, hasta el DISCONNECT o hasta que el broker rechaza las credenciales
+async fn run(
+    endpoint: Endpoint,
+    mut eventloop: EventLoop,
+    confirmations: Arc<Mutex<Confirmations>>,
+    auth_failed: watch::Sender<bool>,
+) {
+    let client_id = eventloop.mqtt_options.client_id();
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let event = eventloop.poll().await;
+        let confirmations = || confirmations.lock().unwrap_or_else(|e| e.into_inner());
+        match event {
+            Ok(Event::Incoming(Incoming::ConnAck(connack))) => {
+                info!("✅ Conectado al broker MQTT como {}", client_id);
+                confirmations().connected(connack.session_present);
+                delay = Duration::from_secs(1);
+            }
+            Ok(Event::Incoming(Incoming::PubAck(ack))) => confirmations().acknowledged(ack.pkid),
+            Ok(Event::Incoming(Incoming::PubRec(ack))) => confirmations().acknowledged(ack.pkid),
+            Ok(Event::Outgoing(Outgoing::Publish(packet_id))) => {
+                confirmations().published(packet_id)
+            }
+            Ok(Event::Outgoing(Outgoing::AwaitAck(packet_id))) => {
+                confirmations().collided(packet_id)
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                info!("🔌 Cliente MQTT {} desconectado", client_id);
+                return;
+            }
+            Ok(_) => {}
+            Err(ConnectionError::ConnectionRefused(
+                code @ (ConnectReturnCode::BadUserNamePassword | ConnectReturnCode::NotAuthorized),
+            )) => {
+                error!(
+                    "❌ El broker MQTT {} rechazó la conexión: {:?}",
+                    endpoint, code
+                );
+                auth_failed.send_replace(true);
+                return;
+            }
+            Err(e) => {
+                // rumqttc devuelve a `pending` lo que estaba en la cola sin enviar
+                // (identificador 0) y lo descarta si el broker no conserva la sesión
+                let unsent = eventloop
+                    .pending
+                    .iter()
+                    .filter(
+                        |request| matches!(request, Request::Publish(publish) if publish.pkid == 0),
+                    )
+                    .count();
+                confirmations().disconnected(unsent);
+                warn!("⚠️ Conexión MQTT con {} perdida: {}", endpoint, e);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Avisos de confirmación pendientes. rumqttc no devuelve el identificador de
+/// cada publicación, pero emite `Outgoing::Publish` al enviarlas en el orden en
+/// que se encolaron: ese evento asocia el siguiente aviso en cola con su
+/// identificador, y el PUBACK/PUBREC de ese identificador lo completa.
+#[derive(Default)]
+struct Confirmations {
+    /// Un aviso (o ninguno) por publicación encolada y aún no enviada, en orden
+    queued: VecDeque<Option<oneshot::Sender<()>>>,
+    /// Publicaciones QoS 1/2 enviadas y sin confirmar
+    in_flight: HashMap<u16, Option<oneshot::Sender<()>>>,
+    /// Publicación retenida porque su identificador seguía en uso
+    collision: Option<(u16, Option<oneshot::Sender<()>>)>,
+    /// Publicaciones sin enviar al perderse la conexión
+    unsent: usize,
+}
+
+impl Confirmations {
+    fn published(&mut self, packet_id: u16) {
+        // Reenvío de una publicación sin confirmar al reanudar la sesión
+        if packet_id != 0 && self.in_flight.contains_key(&packet_id) {
+            return;
+        }
+        let confirm = match self.collision.take() {
+            Some((id, confirm)) if id == packet_id => confirm,
+            collision => {
+                self.collision = collision;
+                self.queued.pop_front().flatten()
+            }
+        };
+        match packet_id {
+            0 => confirm.into_iter().for_each(|confirm| {
+                let _ = confirm.send(());
+            }),
+            packet_id => {
+                self.in_flight.insert(packet_id, confirm);
+            }
+        }
+    }
+
+    /// La publicación espera a que se libere `packet_id`; sale de la cola ahora y
+    /// se envía más tarde con ese identificador
+    fn collided(&mut self, packet_id: u16) {
+        let confirm = self.queued.pop_front().flatten();
+        self.collision = Some((packet_id, confirm));
+    }
+
+    fn acknowledged(&mut self, packet_id: u16) {
+        if let Some(Some(confirm)) = self.in_flight.remove(&packet_id) {
+            let _ = confirm.send(());
+        }
+    }
+
+    fn disconnected(&mut self, unsent: usize) {
+        self.unsent = unsent;
+    }
+
+    /// Sin sesión en el broker, rumqttc descarta lo que no envió o no le
+    /// confirmaron: sus avisos se cancelan
+    fn connected(&mut self, session_present: bool) {
+        if !session_present {
+            let unsent = self.unsent.min(self.queued.len());
+            self.queued.drain(..unsent);
+            self.in_flight.clear();
+            self.collision = None;
+        }
+        self.unsent = 0;
+    }
+}
+
+/// Raíces de Mozilla con el proveedor ring, el mismo que usa el resto del binario
+fn tls_config() -> Result<rustls::ClientConfig> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    Ok(rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(roots)
+    .with_no_client_auth())
+}
+
+/// Dirección del broker, de `mqtt://host[:puerto]` o `mqtts://host[:puerto]`
+#[derive(Debug, Clone)]
+struct Endpoint {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+impl Endpoint {
+    fn parse(url: &str) -> Result<Self> {
+        let (tls, address) = if let Some(address) = url.strip_prefix("mqtts://") {
+            (true, address)
+        } else if let Some(address) = url.strip_prefix("mqtt://") {
+            (false, address)
+        } else {
+            bail!("URL MQTT '{}' sin esquema mqtt:// o mqtts://", url);
+        };
+        let address = address.trim_end_matches('/');
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Puerto MQTT inválido en '{}'", url))?,
+            ),
+            None => (address, if tls { 8883 } else { 1883 }),
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            tls,
+        })
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scheme = if self.tls { "mqtts" } else { "mqtt" };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encola un aviso como lo hace `enqueue`
+    fn queue(confirmations: &mut Confirmations) -> oneshot::Receiver<()> {
+        let (confirm, confirmed) = oneshot::channel();
+        confirmations.queued.push_back(Some(confirm));
+        confirmed
+    }
+
+    #[test]
+    fn endpoint_from_url() {
+        let endpoint = Endpoint::parse("mqtt://broker").unwrap();
+        assert_eq!(
+            (endpoint.host.as_str(), endpoint.port, endpoint.tls),
+            ("broker", 1883, false)
+        );
+        let endpoint = Endpoint::parse("mqtts://broker/").unwrap();
+        assert_eq!(
+            (endpoint.host.as_str(), endpoint.port, endpoint.tls),
+            ("broker", 8883, true)
+        );
+        let endpoint = Endpoint::parse("mqtt://10.0.0.5:1884").unwrap();
+        assert_eq!(endpoint.to_string(), "mqtt://10.0.0.5:1884");
+
+        assert!(Endpoint::parse("tcp://broker").is_err());
+        assert!(Endpoint::parse("mqtt://broker:puerto").is_err());
+    }
+
+    #[test]
+    fn confirmations_follow_the_publish_order() {
+        let mut confirmations = Confirmations::default();
+        let mut first = queue(&mut confirmations);
+        confirmations.queued.push_back(None);
+        let mut third = queue(&mut confirmations);
+
+        // QoS 0: se confirma al enviarla
+        confirmations.published(0);
+        assert!(first.try_recv().is_ok());
+
+        // QoS 1: la publicación sin aviso también ocupa su identificador
+        confirmations.published(1);
+        confirmations.published(2);
+        assert!(confirmations.queued.is_empty());
+        confirmations.acknowledged(1);
+        assert!(third.try_recv().is_err());
+        confirmations.acknowledged(2);
+        assert!(third.try_recv().is_ok());
+        assert!(confirmations.in_flight.is_empty());
+    }
+
+    #[test]
+    fn resent_publishes_keep_their_confirmation() {
+        let mut confirmations = Confirmations::default();
+        let mut first = queue(&mut confirmations);
+        let mut second = queue(&mut confirmations);
+        confirmations.published(1);
+
+        // Se perdió la conexión con la segunda aún en cola; el broker conserva la
+        // sesión y rumqttc reenvía ambas
+        confirmations.disconnected(1);
+        confirmations.connected(true);
+        confirmations.published(1);
+        confirmations.published(2);
+        confirmations.acknowledged(1);
+        confirmations.acknowledged(2);
+        assert!(first.try_recv().is_ok());
+        assert!(second.try_recv().is_ok());
+    }
+
+    #[test]
+    fn lost_session_cancels_pending_confirmations() {
+        let mut confirmations = Confirmations::default();
+        let mut sent = queue(&mut confirmations);
+        let mut unsent = queue(&mut confirmations);
+        confirmations.published(1);
+        confirmations.disconnected(1);
+
+        // Encolada después del corte: rumqttc todavía la enviará
+        let mut later = queue(&mut confirmations);
+        confirmations.connected(false);
+        assert!(matches!(
+            sent.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+        assert!(matches!(
+            unsent.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+
+        confirmations.published(1);
+        confirmations.acknowledged(1);
+        assert!(later.try_recv().is_ok());
+    }
+
+    #[test]
+    fn collision_waits_for_the_packet_id() {
+        let mut confirmations = Confirmations::default();
+        let mut old = queue(&mut confirmations);
+        let mut new = queue(&mut confirmations);
+        confirmations.published(7);
+
+        // El identificador 7 sigue sin confirmar: la nueva espera y se envía al
+        // llegar el PUBACK
+        confirmations.collided(7);
+        confirmations.acknowledged(7);
+        assert!(old.try_recv().is_ok());
+        confirmations.published(7);
+        confirmations.acknowledged(7);
+        assert!(new.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_configuration() {
+        let config = |url: &str, topic: &str, qos: u8| MqttPublishConfig {
+            url: url.to_string(),
+            client_id: "siscom".to_string(),
+            username: None,
+            password: None,
+            topic: topic.to_string(),
+            qos,
+            retain: false,
+            keep_alive_secs: 30,
+            queue_size: 10,
+        };
+        assert!(MqttPublisher::connect(&config("http://broker", "a/{device_id}", 1)).is_err());
+        assert!(MqttPublisher::connect(&config("mqtt://broker", "a/+/b", 1)).is_err());
+        assert!(MqttPublisher::connect(&config("mqtt://broker", "a/{device_id}", 3)).is_err());
+
+        let publisher = MqttPublisher::connect(&config("mqtt://127.0.0.1:1", "a/b", 1)).unwrap();
+        assert!(publisher.try_publish("a/b".to_string(), b"1".to_vec()));
+        assert!(!publisher.try_publish("a/#".to_string(), b"1".to_vec()));
+        assert_eq!(publisher.confirmations.lock().unwrap().queued.len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info, info_span, Instrument};
+
+use crate::config::BrokerConfig;
+use crate::errors::ErrorCategory;
+use crate::services::{
+    AwsSink, KafkaConsumerService, MemoryAccounting, PipelineControl, PipelineMetrics,
+    PipelineStage, WebhookNotifier,
+};
+use crate::telemetry;
+
+/// Tiempo máximo esperando espacio en la cola del producer
+const ENQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Clave y payload de una notificación retenida
+type HeldNotification = (String, Vec<u8>);
+
+/// Publica notificaciones JSON en `KAFKA_NOTIFICATIONS_TOPIC`, con la misma
+/// configuración de conexión y seguridad que el consumidor, y opcionalmente en
+/// webhooks y SNS
+#[derive(Clone)]
+pub struct NotificationPublisher {
+    // None = solo webhooks
+    producer: Option<FutureProducer>,
+    topic: String,
+    webhooks: Option<Arc<WebhookNotifier>>,
+    // Tópico SNS que también recibe las notificaciones
+    aws: Option<Arc<AwsSink>>,
+    // Con la publicación pausada las notificaciones esperan aquí
+    control: Option<Arc<PipelineControl>>,
+    held: Arc<Mutex<Vec<HeldNotification>>>,
+    // Publicaciones fallidas por categoría
+    metrics: Option<Arc<PipelineMetrics>>,
+    // Bytes retenidos, para el límite de memoria del pipeline
+    memory: Option<Arc<MemoryAccounting>>,
+}
+
+impl NotificationPublisher {
+    pub fn new(broker: &BrokerConfig, topic: &str) -> Result<Self> {
+        let producer: FutureProducer = KafkaConsumerService::client_config(broker)
+            .set("acks", "all")
+            .set("linger.ms", "5")
+            .create()?;
+
+        info!("📣 Notificaciones publicadas en el tópico {}", topic);
+        Ok(Self {
+            producer: Some(producer),
+            topic: topic.to_string(),
+            ..Self::without_kafka()
+        })
+    }
+
+    /// Publicador sin tópico de Kafka, para enviar las notificaciones solo a webhooks o SNS
+    pub fn without_kafka() -> Self {
+        Self {
+            producer: None,
+            topic: String::new(),
+            webhooks: None,
+            aws: None,
+            control: None,
+            held: Arc::new(Mutex::new(Vec::new())),
+            metrics: None,
+            memory: None,
+        }
+    }
+
+    /// Envía también cada notificación a los webhooks que la acepten
+    pub fn with_webhooks(mut self, webhooks: Arc<WebhookNotifier>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Publica también cada notificación en el tópico SNS
+    pub fn with_sns(mut self, aws: Arc<AwsSink>) -> Self {
+        self.aws = Some(aws);
+        self
+    }
+
+    /// Retiene las notificaciones mientras la etapa `kafka_output` está pausada y
+    /// las publica, en orden, al reanudarla
+    pub fn with_pipeline_control(mut self, control: Arc<PipelineControl>) -> Self {
+        let mut output_paused = control.subscribe_kafka_output();
+        let publisher = self.clone();
+        tokio::spawn(async move {
+            while output_paused.changed().await.is_ok() {
+                if !*output_paused.borrow_and_update() {
+                    if let Err(e) = publisher.publish_held().await {
+                        error!("❌ {}", e);
+                    }
+                }
+            }
+        });
+        self.control = Some(control);
+        self
+    }
+
+    /// Cuenta las publicaciones fallidas por categoría
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Cuenta las notificaciones retenidas en la memoria del pipeline
+    pub fn with_memory(mut self, memory: Arc<MemoryAccounting>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Publica `payload` con `key` como clave (normalmente el device_id, para que
+    /// las notificaciones de un dispositivo conserven el orden)
+    pub async fn publish<T: Serialize>(&self, key: &str, payload: &T) -> Result<()> {
+        let payload = serde_json::to_value(payload)?;
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(&payload);
+        }
+        if let Some(aws) = &self.aws {
+            aws.notify(key, &payload);
+        }
+        if self.producer.is_none() {
+            return Ok(());
+        }
+
+        let payload = serde_json::to_vec(&payload)?;
+        if self
+            .control
+            .as_ref()
+            .is_some_and(|control| control.is_paused(PipelineStage::KafkaOutput))
+        {
+            if let Some(memory) = &self.memory {
+                memory.add_kafka_output(key.len() + payload.len());
+            }
+            self.held.lock().unwrap().push((key.to_string(), payload));
+            return Ok(());
+        }
+
+        self.publish_held().await?;
+        self.send(key, &payload).await
+    }
+
+    fn record_error(&self, error: &anyhow::Error) {
+        let category = error
+            .downcast_ref::<KafkaError>()
+            .map_or(ErrorCategory::KafkaError, ErrorCategory::of_kafka);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_error(category);
+        }
+    }
+
+    /// Publica las notificaciones retenidas durante la pausa
+    async fn publish_held(&self) -> Result<()> {
+        let held = std::mem::take(&mut *self.held.lock().unwrap());
+        if held.is_empty() {
+            return Ok(());
+        }
+        info!("📣 Publicando {} notificaciones retenidas", held.len());
+        for (index, (key, payload)) in held.iter().enumerate() {
+            if let Some(memory) = &self.memory {
+                memory.remove_kafka_output(key.len() + payload.len());
+            }
+            if let Err(e) = self.send(key, payload).await {
+                if let Some(memory) = &self.memory {
+                    memory.add_kafka_output(
+                        held[index..]
+                            .iter()
+                            .map(|(key, payload)| key.len() + payload.len())
+                            .sum(),
+                    );
+                }
+                // Devolver las no publicadas al frente para el próximo intento
+                let mut pending = self.held.lock().unwrap();
+                let newer = std::mem::take(&mut *pending);
+                pending.extend(held[index..].iter().cloned());
+                pending.extend(newer);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Publica con el contexto de traza en los headers, dentro del span `kafka_produce`
+    async fn send(&self, key: &str, payload: &[u8]) -> Result<()> {
+        let Some(producer) = &self.producer else {
+            return Ok(());
+        };
+        let span = info_span!("kafka_produce", messaging.destination.name = %self.topic);
+        async {
+            producer
+                .send(
+                    FutureRecord::to(&self.topic)
+                        .key(key)
+                        .payload(payload)
+                        .headers(telemetry::kafka_headers()),
+                    ENQUEUE_TIMEOUT,
+                )
+                .await
+                .map_err(|(e, _)| {
+                    // El error de Kafka se conserva para clasificarlo
+                    let message = format!("No se pudo publicar en {}: {}", self.topic, e);
+                    anyhow::Error::new(e).context(message)
+                })
+        }
+        .instrument(span)
+        .await
+        .inspect_err(|e| self.record_error(e))?;
+        Ok(())
+    }
+}
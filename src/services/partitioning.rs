@@ -0,0 +1,272 @@
+use anyhow::Result;
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::config::{PartitionConfig, PartitionInterval, PartitionKey};
+use crate::models::CommunicationRecord;
+
+/// Crea y elimina particiones por tiempo (`{tabla}_pYYYYMMDD` o `{tabla}_pYYYYMM`)
+/// de las tablas de histórico particionadas de forma declarativa.
+#[derive(Debug, Clone)]
+pub struct PartitionManager {
+    config: PartitionConfig,
+    // Particiones que ya se sabe que existen, para no consultar en cada lote
+    known: Arc<Mutex<HashSet<String>>>,
+}
+
+impl PartitionManager {
+    pub fn new(config: PartitionConfig) -> Self {
+        Self {
+            config,
+            known: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Indica si hay retención configurada
+    pub fn retention_enabled(&self) -> bool {
+        self.config.retention_days.is_some()
+    }
+
+    /// Columna por la que están particionadas las tablas
+    pub fn key_column(&self) -> &'static str {
+        self.config.key.column()
+    }
+
+    /// Crea las particiones que faltan para los registros del lote. Los errores solo
+    /// se registran: sin partición la fila cae en la partición DEFAULT o se rechaza.
+    pub async fn ensure_partitions(
+        &self,
+        pool: &PgPool,
+        table: &str,
+        records: &[CommunicationRecord],
+    ) {
+        let starts: BTreeSet<NaiveDate> = records
+            .iter()
+            .filter_map(|record| self.key_value(record))
+            .map(|value| self.range_start(value.date()))
+            .collect();
+
+        let mut known = self.known.lock().await;
+        for start in starts {
+            let name = self.partition_name(table, start);
+            if known.contains(&name) {
+                continue;
+            }
+
+            match self.create_partition(pool, table, &name, start).await {
+                Ok(()) => {
+                    known.insert(name);
+                }
+                Err(e) => error!("❌ Error creando partición {}: {}", name, e),
+            }
+        }
+    }
+
+    /// Elimina las particiones cuyo rango terminó antes del período de retención.
+    /// Devuelve la cantidad de particiones eliminadas.
+    pub async fn drop_expired(&self, pool: &PgPool, table: &str) -> Result<usize> {
+        let Some(retention_days) = self.config.retention_days else {
+            return Ok(0);
+        };
+        let cutoff = Utc::now().date_naive() - Days::new(retention_days.into());
+
+        let partitions: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT ns.nspname::text, child.relname::text
+            FROM pg_inherits
+            JOIN pg_class child ON child.oid = pg_inherits.inhrelid
+            JOIN pg_namespace ns ON ns.oid = child.relnamespace
+            WHERE pg_inherits.inhparent = $1::regclass
+            "#,
+        )
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+
+        // `table` puede venir calificado con el esquema; los hijos se comparan por nombre
+        let base_name = table.rsplit('.').next().unwrap_or(table);
+
+        let mut dropped = 0;
+        for (schema, relname) in partitions {
+            // Solo se tocan las particiones creadas con nuestro esquema de nombres
+            let Some(start) = self.parse_partition_name(base_name, &relname) else {
+                continue;
+            };
+            if self.range_end(start) > cutoff {
+                continue;
+            }
+
+            let name = format!("{}.{}", schema, relname);
+            sqlx::query(&format!("DROP TABLE IF EXISTS {}", name))
+                .execute(pool)
+                .await?;
+            self.known
+                .lock()
+                .await
+                .remove(&self.partition_name(table, start));
+            info!(
+                "🗑️ Partición {} eliminada (retención: {} días)",
+                name, retention_days
+            );
+            dropped += 1;
+        }
+
+        Ok(dropped)
+    }
+
+    async fn create_partition(
+        &self,
+        pool: &PgPool,
+        table: &str,
+        name: &str,
+        start: NaiveDate,
+    ) -> Result<()> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} FOR VALUES FROM ('{}') TO ('{}')",
+            name,
+            table,
+            start,
+            self.range_end(start)
+        );
+
+        if let Err(e) = sqlx::query(&sql).execute(pool).await {
+            // Otra instancia pudo crearla al mismo tiempo
+            let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+                .bind(name)
+                .fetch_one(pool)
+                .await?;
+            if !exists {
+                return Err(e.into());
+            }
+            warn!("⚠️ Partición {} creada de forma concurrente", name);
+        } else {
+            info!("🧩 Partición {} lista", name);
+        }
+
+        Ok(())
+    }
+
+    fn key_value(&self, record: &CommunicationRecord) -> Option<NaiveDateTime> {
+        match self.config.key {
+            PartitionKey::GpsDatetime => record.gps_datetime,
+            PartitionKey::ReceivedAt => record.received_at,
+        }
+    }
+
+    fn range_start(&self, date: NaiveDate) -> NaiveDate {
+        match self.config.interval {
+            PartitionInterval::Daily => date,
+            PartitionInterval::Monthly => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    fn range_end(&self, start: NaiveDate) -> NaiveDate {
+        match self.config.interval {
+            PartitionInterval::Daily => start + Days::new(1),
+            PartitionInterval::Monthly => start + Months::new(1),
+        }
+    }
+
+    fn partition_name(&self, table: &str, start: NaiveDate) -> String {
+        match self.config.interval {
+            PartitionInterval::Daily => format!("{}_p{}", table, start.format("%Y%m%d")),
+            PartitionInterval::Monthly => format!("{}_p{}", table, start.format("%Y%m")),
+        }
+    }
+
+    fn parse_partition_name(&self, table: &str, name: &str) -> Option<NaiveDate> {
+        let suffix = name.strip_prefix(table)?.strip_prefix("_p")?;
+        match self.config.interval {
+            PartitionInterval::Daily if suffix.len() == 8 => {
+                NaiveDate::parse_from_str(suffix, "%Y%m%d").ok()
+            }
+            PartitionInterval::Monthly if suffix.len() == 6 => {
+                NaiveDate::parse_from_str(&format!("{}01", suffix), "%Y%m%d").ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(interval: PartitionInterval) -> PartitionManager {
+        PartitionManager::new(PartitionConfig {
+            interval,
+            key: PartitionKey::GpsDatetime,
+            retention_days: Some(30),
+        })
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn daily_partitions_cover_one_day() {
+        let manager = manager(PartitionInterval::Daily);
+        let start = manager.range_start(date(2024, 2, 29));
+        assert_eq!(start, date(2024, 2, 29));
+        assert_eq!(manager.range_end(start), date(2024, 3, 1));
+        assert_eq!(
+            manager.partition_name("communications_suntech", start),
+            "communications_suntech_p20240229"
+        );
+    }
+
+    #[test]
+    fn monthly_partitions_cover_the_whole_month() {
+        let manager = manager(PartitionInterval::Monthly);
+        let start = manager.range_start(date(2024, 12, 31));
+        assert_eq!(start, date(2024, 12, 1));
+        // El fin es exclusivo y cruza el año
+        assert_eq!(manager.range_end(start), date(2025, 1, 1));
+        assert_eq!(
+            manager.partition_name("communications_queclink", start),
+            "communications_queclink_p202412"
+        );
+    }
+
+    #[test]
+    fn partition_names_round_trip() {
+        for interval in [PartitionInterval::Daily, PartitionInterval::Monthly] {
+            let manager = manager(interval);
+            let start = manager.range_start(date(2023, 7, 15));
+            let name = manager.partition_name("communications_suntech", start);
+            assert_eq!(
+                manager.parse_partition_name("communications_suntech", &name),
+                Some(start)
+            );
+        }
+    }
+
+    #[test]
+    fn foreign_partition_names_are_ignored() {
+        let daily = manager(PartitionInterval::Daily);
+        // Otra tabla, partición DEFAULT, sufijo mensual o fecha inválida
+        for name in [
+            "communications_queclink_p20240101",
+            "communications_suntech_default",
+            "communications_suntech_p202401",
+            "communications_suntech_p20240230",
+        ] {
+            assert_eq!(
+                daily.parse_partition_name("communications_suntech", name),
+                None
+            );
+        }
+
+        let monthly = manager(PartitionInterval::Monthly);
+        assert_eq!(
+            monthly
+                .parse_partition_name("communications_suntech", "communications_suntech_p20240101"),
+            None
+        );
+    }
+}
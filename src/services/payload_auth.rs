@@ -0,0 +1,302 @@
+use hmac::{Hmac, Mac};
+use lapin::types::{AMQPValue, FieldTable};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::info;
+
+use crate::config::PayloadAuthConfig;
+
+/// Motivo por el que un mensaje no pasa la verificación
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// Sin el header de la firma
+    MissingSignature,
+    /// Sin tenant en el header ni por el tópico, o un tenant sin clave
+    UnknownKey(String),
+    /// El tenant firmante no es el asignado al tópico de origen
+    TenantMismatch {
+        key_id: String,
+        topic_tenant: String,
+    },
+    /// La firma no es hex o no corresponde al payload con la clave del tenant
+    BadSignature(String),
+    /// El mensaje firmado se enruta a otro tenant (o a ninguno)
+    WrongTenant {
+        key_id: String,
+        tenant: Option<String>,
+    },
+}
+
+impl fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthFailure::MissingSignature => write!(f, "mensaje sin firma"),
+            AuthFailure::UnknownKey(key_id) if key_id.is_empty() => {
+                write!(f, "mensaje sin tenant firmante")
+            }
+            AuthFailure::UnknownKey(key_id) => write!(f, "tenant '{}' sin clave", key_id),
+            AuthFailure::TenantMismatch {
+                key_id,
+                topic_tenant,
+            } => write!(
+                f,
+                "firmado por '{}' en un tópico del tenant '{}'",
+                key_id, topic_tenant
+            ),
+            AuthFailure::BadSignature(key_id) => {
+                write!(f, "firma inválida para el tenant '{}'", key_id)
+            }
+            AuthFailure::WrongTenant {
+                key_id,
+                tenant: Some(tenant),
+            } => write!(
+                f,
+                "firmado por '{}' para un dispositivo del tenant '{}'",
+                key_id, tenant
+            ),
+            AuthFailure::WrongTenant {
+                key_id,
+                tenant: None,
+            } => write!(f, "firmado por '{}' para un dispositivo sin tenant", key_id),
+        }
+    }
+}
+
+/// Verifica la firma HMAC-SHA256 de cada payload del broker con la clave de su
+/// tenant, antes de decodificarlo. El broker es compartido con productores de
+/// terceros: solo entra al pipeline lo firmado con una clave configurada, y un
+/// tenant no puede publicar en el tópico de otro (`TENANT_TOPICS`).
+pub struct PayloadAuthenticator {
+    keys: HashMap<String, Vec<u8>>,
+    signature_header: String,
+    key_id_header: String,
+}
+
+impl PayloadAuthenticator {
+    pub fn new(config: &PayloadAuthConfig) -> Self {
+        info!(
+            "🔏 Verificando la firma de los mensajes ({} tenants con clave, headers {} / {})",
+            config.keys.len(),
+            config.signature_header,
+            config.key_id_header
+        );
+        Self {
+            keys: config
+                .keys
+                .iter()
+                .map(|(tenant, key)| (tenant.clone(), key.as_bytes().to_vec()))
+                .collect(),
+            signature_header: config.signature_header.clone(),
+            key_id_header: config.key_id_header.clone(),
+        }
+    }
+
+    /// Verifica un mensaje Kafka con la firma y el tenant en sus headers. Devuelve
+    /// el tenant cuya clave lo firmó.
+    pub fn verify_kafka<H: Headers>(
+        &self,
+        headers: Option<&H>,
+        topic_tenant: Option<&str>,
+        payload: &[u8],
+    ) -> Result<String, AuthFailure> {
+        let header = |name: &str| {
+            headers?
+                .iter()
+                .find(|header| header.key.eq_ignore_ascii_case(name))
+                .and_then(|header| std::str::from_utf8(header.value?).ok())
+                .map(str::to_string)
+        };
+        self.verify(
+            header(&self.signature_header),
+            header(&self.key_id_header),
+            topic_tenant,
+            payload,
+        )
+    }
+
+    /// Verifica una entrega AMQP con la firma y el tenant en sus headers. Devuelve
+    /// el tenant cuya clave la firmó.
+    pub fn verify_amqp(
+        &self,
+        headers: Option<&FieldTable>,
+        payload: &[u8],
+    ) -> Result<String, AuthFailure> {
+        let header = |name: &str| {
+            headers?
+                .inner()
+                .iter()
+                .find(|(key, _)| key.as_str().eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| match value {
+                    AMQPValue::LongString(value) => std::str::from_utf8(value.as_bytes())
+                        .ok()
+                        .map(str::to_string),
+                    AMQPValue::ShortString(value) => Some(value.as_str().to_string()),
+                    _ => None,
+                })
+        };
+        self.verify(
+            header(&self.signature_header),
+            header(&self.key_id_header),
+            None,
+            payload,
+        )
+    }
+
+    /// Headers con la firma de `payload` hecha con la clave de `key_id`, para
+    /// republicar un mensaje ya verificado. None si el tenant no tiene clave.
+    pub fn sign_kafka(&self, key_id: &str, payload: &[u8]) -> Option<OwnedHeaders> {
+        let key = self.keys.get(key_id)?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key).expect("HMAC acepta claves de cualquier largo");
+        mac.update(payload);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        Some(
+            OwnedHeaders::new()
+                .insert(Header {
+                    key: &self.signature_header,
+                    value: Some(&signature),
+                })
+                .insert(Header {
+                    key: &self.key_id_header,
+                    value: Some(key_id),
+                }),
+        )
+    }
+
+    /// Sin header del tenant se usa el del tópico de origen, si tiene uno
+    fn verify(
+        &self,
+        signature: Option<String>,
+        key_id: Option<String>,
+        topic_tenant: Option<&str>,
+        payload: &[u8],
+    ) -> Result<String, AuthFailure> {
+        let signature = signature.ok_or(AuthFailure::MissingSignature)?;
+        let key_id = key_id
+            .or_else(|| topic_tenant.map(str::to_string))
+            .unwrap_or_default();
+        if let Some(topic_tenant) = topic_tenant.filter(|tenant| *tenant != key_id) {
+            return Err(AuthFailure::TenantMismatch {
+                key_id,
+                topic_tenant: topic_tenant.to_string(),
+            });
+        }
+        let key = self
+            .keys
+            .get(&key_id)
+            .ok_or_else(|| AuthFailure::UnknownKey(key_id.clone()))?;
+
+        let signature = signature.trim();
+        let expected = hex::decode(signature.strip_prefix("sha256=").unwrap_or(signature))
+            .map_err(|_| AuthFailure::BadSignature(key_id.clone()))?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key).expect("HMAC acepta claves de cualquier largo");
+        mac.update(payload);
+        // Comparación en tiempo constante
+        match mac.verify_slice(&expected) {
+            Ok(()) => Ok(key_id),
+            Err(_) => Err(AuthFailure::BadSignature(key_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn authenticator() -> PayloadAuthenticator {
+        PayloadAuthenticator::new(&PayloadAuthConfig {
+            keys: BTreeMap::from([
+                ("acme".to_string(), "secreto-acme".to_string()),
+                ("globex".to_string(), "secreto-globex".to_string()),
+            ]),
+            signature_header: "x-siscom-signature".to_string(),
+            key_id_header: "x-siscom-key-id".to_string(),
+        })
+    }
+
+    fn sign(key: &str, payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(payload);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn headers(signature: &str, key_id: Option<&str>) -> OwnedHeaders {
+        let headers = OwnedHeaders::new().insert(Header {
+            key: "x-siscom-signature",
+            value: Some(signature),
+        });
+        match key_id {
+            Some(key_id) => headers.insert(Header {
+                key: "x-siscom-key-id",
+                value: Some(key_id),
+            }),
+            None => headers,
+        }
+    }
+
+    #[test]
+    fn payload_signed_with_the_tenant_key_is_accepted() {
+        let payload = b"{\"uuid\":\"1\"}";
+        let signed = headers(&sign("secreto-acme", payload), Some("acme"));
+        assert_eq!(
+            authenticator().verify_kafka(Some(&signed), None, payload),
+            Ok("acme".to_string())
+        );
+
+        // Sin header del tenant se usa la clave del tenant del tópico
+        let signed = headers(&sign("secreto-globex", payload), None);
+        assert_eq!(
+            authenticator().verify_kafka(Some(&signed), Some("globex"), payload),
+            Ok("globex".to_string())
+        );
+    }
+
+    #[test]
+    fn republished_payloads_carry_a_valid_signature() {
+        let authenticator = authenticator();
+        let payload = b"{\"uuid\":\"1\"}";
+        let signed = authenticator.sign_kafka("globex", payload).unwrap();
+        assert_eq!(
+            authenticator.verify_kafka(Some(&signed), None, payload),
+            Ok("globex".to_string())
+        );
+        assert!(authenticator.sign_kafka("initech", payload).is_none());
+    }
+
+    #[test]
+    fn forged_payloads_are_rejected() {
+        let authenticator = authenticator();
+        let payload = b"{\"uuid\":\"1\"}";
+
+        assert_eq!(
+            authenticator.verify_kafka(None::<&OwnedHeaders>, None, payload),
+            Err(AuthFailure::MissingSignature)
+        );
+
+        let tampered = headers(&sign("secreto-acme", b"otro payload"), Some("acme"));
+        assert_eq!(
+            authenticator.verify_kafka(Some(&tampered), None, payload),
+            Err(AuthFailure::BadSignature("acme".to_string()))
+        );
+
+        let unknown = headers(&sign("secreto-initech", payload), Some("initech"));
+        assert_eq!(
+            authenticator.verify_kafka(Some(&unknown), None, payload),
+            Err(AuthFailure::UnknownKey("initech".to_string()))
+        );
+
+        // Un tenant con clave válida no puede publicar en el tópico de otro
+        let other_tenant = headers(&sign("secreto-acme", payload), Some("acme"));
+        assert_eq!(
+            authenticator.verify_kafka(Some(&other_tenant), Some("globex"), payload),
+            Err(AuthFailure::TenantMismatch {
+                key_id: "acme".to_string(),
+                topic_tenant: "globex".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::services::Backpressure;
+
+/// Etapa del pipeline que puede pausarse en caliente
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    /// Lectura de Kafka: las particiones se pausan como con la backpressure
+    Intake,
+    /// Escritura de posiciones en PostgreSQL: los lotes se acumulan en el buffer de BD
+    DbWrites,
+    /// Publicación en Kafka (notificaciones): los mensajes se retienen en memoria
+    KafkaOutput,
+}
+
+/// Estado de pausa de cada etapa
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineStatus {
+    pub intake_paused: bool,
+    pub db_writes_paused: bool,
+    pub kafka_output_paused: bool,
+}
+
+/// Pausa y reanuda etapas del pipeline de forma independiente, p. ej. para un
+/// mantenimiento planificado de la BD sin que los lotes fallen
+pub struct PipelineControl {
+    backpressure: Backpressure,
+    db_writes: watch::Sender<bool>,
+    kafka_output: watch::Sender<bool>,
+}
+
+impl std::fmt::Debug for PipelineControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineControl")
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+impl PipelineControl {
+    pub fn new(backpressure: Backpressure) -> Self {
+        Self {
+            backpressure,
+            db_writes: watch::channel(false).0,
+            kafka_output: watch::channel(false).0,
+        }
+    }
+
+    pub fn set_paused(&self, stage: PipelineStage, paused: bool) {
+        if self.is_paused(stage) == paused {
+            return;
+        }
+        match stage {
+            PipelineStage::Intake => self.backpressure.set_held(paused),
+            PipelineStage::DbWrites => {
+                self.db_writes.send_replace(paused);
+            }
+            PipelineStage::KafkaOutput => {
+                self.kafka_output.send_replace(paused);
+            }
+        }
+        info!(
+            "{} Etapa {:?} {}",
+            if paused { "⏸️" } else { "▶️" },
+            stage,
+            if paused { "pausada" } else { "reanudada" }
+        );
+    }
+
+    pub fn is_paused(&self, stage: PipelineStage) -> bool {
+        match stage {
+            PipelineStage::Intake => self.backpressure.is_held(),
+            PipelineStage::DbWrites => *self.db_writes.borrow(),
+            PipelineStage::KafkaOutput => *self.kafka_output.borrow(),
+        }
+    }
+
+    /// Receptor para esperar la reanudación de la publicación en Kafka
+    pub fn subscribe_kafka_output(&self) -> watch::Receiver<bool> {
+        self.kafka_output.subscribe()
+    }
+
+    pub fn status(&self) -> PipelineStatus {
+        PipelineStatus {
+            intake_paused: self.is_paused(PipelineStage::Intake),
+            db_writes_paused: self.is_paused(PipelineStage::DbWrites),
+            kafka_output_paused: self.is_paused(PipelineStage::KafkaOutput),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::errors::{ErrorCategory, ErrorClass};
+
+/// Segundos de la ventana sobre la que se calcula el ritmo de mensajes
+const RATE_WINDOW_SECS: usize = 60;
+
+/// Escrituras de lote que se conservan para calcular los percentiles de latencia
+const LATENCY_SAMPLES: usize = 1024;
+
+/// Mensajes recibidos de un origen (tópico)
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatistics {
+    pub source: String,
+    pub messages: u64,
+    /// Promedio del último minuto
+    pub messages_per_sec: f64,
+}
+
+/// Errores de una categoría desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorStatistics {
+    pub category: ErrorCategory,
+    pub class: ErrorClass,
+    pub count: u64,
+    pub last_at: DateTime<Utc>,
+}
+
+/// Contadores del pipeline que no pertenecen a una etapa concreta: ritmo por origen,
+/// mensajes por fabricante, errores por categoría y latencia de escritura de lotes
+#[derive(Debug)]
+pub struct PipelineMetrics {
+    started: Instant,
+    sources: Mutex<HashMap<String, SourceCounter>>,
+    manufacturers: Mutex<BTreeMap<String, u64>>,
+    errors: Mutex<BTreeMap<ErrorCategory, (u64, DateTime<Utc>)>>,
+    flush_latencies: Mutex<VecDeque<Duration>>,
+}
+
+/// Total de un origen y mensajes por segundo de la última ventana
+#[derive(Debug)]
+struct SourceCounter {
+    total: u64,
+    buckets: [u64; RATE_WINDOW_SECS],
+    // Segundo (desde el arranque) del último mensaje registrado
+    second: u64,
+}
+
+impl SourceCounter {
+    /// Lleva la ventana hasta `second`, vaciando los segundos sin mensajes
+    fn advance(&mut self, second: u64) {
+        let skipped = second.saturating_sub(self.second);
+        for offset in 1..=skipped.min(RATE_WINDOW_SECS as u64) {
+            self.buckets[((self.second + offset) % RATE_WINDOW_SECS as u64) as usize] = 0;
+        }
+        self.second = self.second.max(second);
+    }
+}
+
+impl Default for PipelineMetrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            sources: Mutex::new(HashMap::new()),
+            manufacturers: Mutex::new(BTreeMap::new()),
+            errors: Mutex::new(BTreeMap::new()),
+            flush_latencies: Mutex::new(VecDeque::with_capacity(LATENCY_SAMPLES)),
+        }
+    }
+}
+
+impl PipelineMetrics {
+    fn second(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    /// Cuenta un mensaje recibido de `source` y de `manufacturer`
+    pub fn record_message(&self, source: &str, manufacturer: &str) {
+        let second = self.second();
+        {
+            let mut sources = self.sources.lock().unwrap();
+            let counter = sources
+                .entry(source.to_string())
+                .or_insert_with(|| SourceCounter {
+                    total: 0,
+                    buckets: [0; RATE_WINDOW_SECS],
+                    second,
+                });
+            counter.advance(second);
+            counter.total += 1;
+            counter.buckets[(second % RATE_WINDOW_SECS as u64) as usize] += 1;
+        }
+        *self
+            .manufacturers
+            .lock()
+            .unwrap()
+            .entry(manufacturer.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn record_error(&self, category: ErrorCategory) {
+        let mut errors = self.errors.lock().unwrap();
+        let entry = errors.entry(category).or_insert((0, Utc::now()));
+        entry.0 += 1;
+        entry.1 = Utc::now();
+    }
+
+    /// Registra la duración de la escritura de un lote en la BD
+    pub fn record_flush(&self, latency: Duration) {
+        let mut latencies = self.flush_latencies.lock().unwrap();
+        if latencies.len() == LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    pub fn sources(&self) -> Vec<SourceStatistics> {
+        let second = self.second();
+        // Al arrancar la ventana todavía no está completa
+        let window = (second + 1).min(RATE_WINDOW_SECS as u64) as f64;
+        let mut sources = self.sources.lock().unwrap();
+        let mut statistics: Vec<_> = sources
+            .iter_mut()
+            .map(|(source, counter)| {
+                counter.advance(second);
+                SourceStatistics {
+                    source: source.clone(),
+                    messages: counter.total,
+                    messages_per_sec: counter.buckets.iter().sum::<u64>() as f64 / window,
+                }
+            })
+            .collect();
+        statistics.sort_by(|a, b| a.source.cmp(&b.source));
+        statistics
+    }
+
+    pub fn manufacturers(&self) -> BTreeMap<String, u64> {
+        self.manufacturers.lock().unwrap().clone()
+    }
+
+    pub fn errors(&self) -> Vec<ErrorStatistics> {
+        self.errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(category, (count, last_at))| ErrorStatistics {
+                category: *category,
+                class: category.class(),
+                count: *count,
+                last_at: *last_at,
+            })
+            .collect()
+    }
+
+    /// Percentil `p` (0-100) de las últimas escrituras de lote, en milisegundos
+    pub fn flush_latency_ms(&self, p: f64) -> Option<f64> {
+        let mut latencies: Vec<_> = self
+            .flush_latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let index = ((p / 100.0) * (latencies.len() - 1) as f64).round() as usize;
+        Some(latencies[index].as_secs_f64() * 1000.0)
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::config::PositionFilterConfig;
+use crate::models::NormalizedPosition;
+use crate::redact;
+
+/// Identidad de una posición: mismo dispositivo, mismo instante GPS y mismas coordenadas
+type PositionKey = (String, i64, Option<u64>, Option<u64>);
+
+/// Descarta las posiciones que no aportan filas útiles al histórico: las repetidas
+/// exactas (reenvíos del buffer del equipo con otro UUID) y las más antiguas que la
+/// ventana configurada
+pub struct PositionFilter {
+    recent: Option<Mutex<LruCache<PositionKey, ()>>>,
+    max_age: Option<chrono::Duration>,
+    duplicates: AtomicU64,
+    stale: AtomicU64,
+}
+
+impl PositionFilter {
+    /// None si la configuración no activa ningún filtro
+    pub fn from_config(config: &PositionFilterConfig) -> Option<Self> {
+        let recent = NonZeroUsize::new(config.duplicate_cache_size)
+            .map(|size| Mutex::new(LruCache::new(size)));
+        let max_age = (config.max_age_secs > 0)
+            .then(|| chrono::Duration::seconds(config.max_age_secs as i64));
+        if recent.is_none() && max_age.is_none() {
+            return None;
+        }
+
+        info!(
+            "🧹 Filtro de posiciones activo (repetidas: {}, antigüedad máxima: {}s)",
+            config.duplicate_cache_size, config.max_age_secs
+        );
+        Some(Self {
+            recent,
+            max_age,
+            duplicates: AtomicU64::new(0),
+            stale: AtomicU64::new(0),
+        })
+    }
+
+    /// Posiciones repetidas descartadas desde el inicio
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    /// Posiciones antiguas descartadas desde el inicio
+    pub fn stale(&self) -> u64 {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Quita del lote las posiciones antiguas y las repetidas
+    pub fn retain(&self, positions: &mut Vec<NormalizedPosition>) {
+        let before = positions.len();
+
+        if let Some(max_age) = self.max_age {
+            let oldest = Utc::now().naive_utc() - max_age;
+            positions.retain(|position| match position_time(position) {
+                Some(time) if time < oldest => {
+                    debug!(
+                        "🕰️ Posición antigua descartada: Device {} ({})",
+                        redact::device_id(&position.device_id),
+                        time
+                    );
+                    self.stale.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        // Sin gps_epoch no hay fix que comparar: los mensajes sin posición no se filtran
+        if let Some(recent) = &self.recent {
+            let mut recent = recent.lock().unwrap();
+            positions.retain(|position| {
+                let Some(gps_epoch) = position.gps_epoch else {
+                    return true;
+                };
+                let key = (
+                    position.device_id.clone(),
+                    gps_epoch,
+                    position.latitude.map(f64::to_bits),
+                    position.longitude.map(f64::to_bits),
+                );
+                if recent.put(key, ()).is_some() {
+                    self.duplicates.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                true
+            });
+        }
+
+        let filtered = before - positions.len();
+        if filtered > 0 {
+            debug!("🧹 {} posiciones filtradas del lote", filtered);
+        }
+    }
+}
+
+/// Instante de la posición en UTC: `gps_datetime` o, si falta, `gps_epoch`
+fn position_time(position: &NormalizedPosition) -> Option<NaiveDateTime> {
+    position.gps_datetime.or_else(|| {
+        position
+            .gps_epoch
+            .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+            .map(|datetime| datetime.naive_utc())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn filter(duplicate_cache_size: usize, max_age_secs: u64) -> PositionFilter {
+        PositionFilter::from_config(&PositionFilterConfig {
+            duplicate_cache_size,
+            max_age_secs,
+        })
+        .unwrap()
+    }
+
+    /// Posición de `device_id` hace `age_secs` segundos en (lat, lon)
+    fn position(
+        device_id: &str,
+        age_secs: i64,
+        latitude: f64,
+        longitude: f64,
+    ) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        let time = Utc::now() - chrono::Duration::seconds(age_secs);
+        position.device_id = device_id.to_string();
+        position.gps_datetime = Some(time.naive_utc());
+        position.gps_epoch = Some(time.timestamp());
+        position.latitude = Some(latitude);
+        position.longitude = Some(longitude);
+        position
+    }
+
+    fn coordinates(positions: &[NormalizedPosition]) -> Vec<(Option<f64>, Option<f64>)> {
+        positions
+            .iter()
+            .map(|position| (position.latitude, position.longitude))
+            .collect()
+    }
+
+    #[test]
+    fn no_filter_without_configuration() {
+        assert!(PositionFilter::from_config(&PositionFilterConfig::default()).is_none());
+    }
+
+    #[test]
+    fn repeated_position_is_dropped_and_a_normal_move_kept() {
+        let filter = filter(100, 0);
+        let first = position("A", 60, 19.4326, -99.1332);
+        let mut repeated = first.clone();
+        repeated.uuid = "otro-uuid".to_string();
+        let mut moved = first.clone();
+        moved.gps_epoch = first.gps_epoch.map(|epoch| epoch + 30);
+        moved.latitude = Some(19.4330);
+
+        let mut batch = vec![first.clone(), repeated, moved];
+        filter.retain(&mut batch);
+        assert_eq!(
+            coordinates(&batch),
+            vec![
+                (Some(19.4326), Some(-99.1332)),
+                (Some(19.4330), Some(-99.1332))
+            ]
+        );
+        assert_eq!(filter.duplicates(), 1);
+
+        // La repetición se reconoce también en el lote siguiente, solo para el mismo equipo
+        let mut other_device = first.clone();
+        other_device.device_id = "B".to_string();
+        let mut batch = vec![first, other_device];
+        filter.retain(&mut batch);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].device_id, "B");
+        assert_eq!(filter.duplicates(), 2);
+    }
+
+    #[test]
+    fn positions_without_gps_epoch_are_not_deduplicated() {
+        let filter = filter(100, 0);
+        let mut no_fix = position("A", 0, 19.4326, -99.1332);
+        no_fix.gps_epoch = None;
+
+        let mut batch = vec![no_fix.clone(), no_fix];
+        filter.retain(&mut batch);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(filter.duplicates(), 0);
+    }
+
+    #[test]
+    fn stale_positions_are_dropped() {
+        let filter = filter(0, 3_600);
+        let mut batch = vec![
+            position("A", 60, 19.4326, -99.1332),
+            position("A", 7_200, 19.4330, -99.1332),
+        ];
+        // Sin gps_datetime se usa gps_epoch
+        batch[1].gps_datetime = None;
+        filter.retain(&mut batch);
+        assert_eq!(coordinates(&batch), vec![(Some(19.4326), Some(-99.1332))]);
+        assert_eq!(filter.stale(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info};
+
+use crate::config::{PowerEventsConfig, PowerThresholds};
+use crate::models::{NormalizedPosition, PowerEvent, PowerTransition};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+use crate::services::DatabaseService;
+
+/// Lo que debe subir la batería de respaldo sobre su umbral para volver a avisar
+const BACKUP_HYSTERESIS_VOLTS: f64 = 0.2;
+
+/// Umbrales de un modelo en `POWER_MODEL_THRESHOLDS_FILE`; los que faltan toman los
+/// valores generales
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdOverrides {
+    power_lost_volts: Option<f64>,
+    power_restored_volts: Option<f64>,
+    low_backup_volts: Option<f64>,
+}
+
+/// Estado de alimentación conocido de un dispositivo (None = aún sin lectura)
+#[derive(Debug, Default)]
+struct DevicePower {
+    gps_epoch: Option<i64>,
+    powered: Option<bool>,
+    backup_low: Option<bool>,
+}
+
+/// Detecta cortes y restablecimientos de la alimentación principal y la batería de
+/// respaldo baja a partir de los voltajes de posiciones consecutivas de cada
+/// dispositivo, y los guarda en `power_events` y los publica en el tópico de
+/// notificaciones. Los umbrales pueden ajustarse por modelo de equipo.
+///
+/// La primera lectura de un dispositivo solo fija su estado; los eventos salen de
+/// los cambios posteriores. Entre el umbral de corte y el de restablecimiento el
+/// estado no cambia, para no alternar eventos con un voltaje oscilante.
+pub struct PowerMonitor {
+    thresholds: PowerThresholds,
+    models: HashMap<String, PowerThresholds>,
+    database: Arc<DatabaseService>,
+    notifications: Option<NotificationPublisher>,
+    devices: Mutex<HashMap<String, DevicePower>>,
+    events: AtomicU64,
+}
+
+impl PowerMonitor {
+    /// Carga los umbrales por modelo y el último corte o restablecimiento de cada
+    /// dispositivo desde `power_events`
+    pub async fn new(
+        config: PowerEventsConfig,
+        database: Arc<DatabaseService>,
+        notifications: Option<NotificationPublisher>,
+    ) -> Result<Self> {
+        let models = match &config.model_thresholds_file {
+            Some(path) => {
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+                serde_json::from_str::<HashMap<String, ThresholdOverrides>>(&content)
+                    .map_err(|e| anyhow!("Umbrales de alimentación inválidos en {}: {}", path, e))?
+                    .into_iter()
+                    .map(|(model, overrides)| {
+                        let defaults = &config.thresholds;
+                        let thresholds = PowerThresholds {
+                            power_lost_volts: overrides
+                                .power_lost_volts
+                                .unwrap_or(defaults.power_lost_volts),
+                            power_restored_volts: overrides
+                                .power_restored_volts
+                                .unwrap_or(defaults.power_restored_volts),
+                            low_backup_volts: overrides
+                                .low_backup_volts
+                                .unwrap_or(defaults.low_backup_volts),
+                        };
+                        (model.trim().to_uppercase(), thresholds)
+                    })
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+
+        let devices: HashMap<String, DevicePower> = database
+            .power_states()
+            .await?
+            .into_iter()
+            .map(|(device_id, event)| {
+                let state = DevicePower {
+                    powered: Some(event == PowerTransition::PowerRestored),
+                    ..DevicePower::default()
+                };
+                (device_id, state)
+            })
+            .collect();
+
+        info!(
+            "🔋 Eventos de alimentación activos: corte < {} V, restablecida >= {} V, respaldo bajo < {} V ({} modelos con umbrales propios, {} dispositivos con estado)",
+            config.thresholds.power_lost_volts,
+            config.thresholds.power_restored_volts,
+            config.thresholds.low_backup_volts,
+            models.len(),
+            devices.len()
+        );
+        Ok(Self {
+            thresholds: config.thresholds,
+            models,
+            database,
+            notifications,
+            devices: Mutex::new(devices),
+            events: AtomicU64::new(0),
+        })
+    }
+
+    /// Eventos de alimentación generados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Evalúa el lote, guarda los eventos y los publica. Los errores solo se
+    /// registran: un fallo aquí no debe frenar la ingesta.
+    pub async fn process(&self, positions: &[NormalizedPosition]) {
+        let events = self.evaluate(positions);
+        if events.is_empty() {
+            return;
+        }
+
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "🔋 Device {} {}: {:.2} V / umbral {:.2} V",
+                redact::device_id(&event.device_id),
+                event.event.as_str(),
+                event.voltage,
+                event.threshold
+            );
+        }
+
+        if let Err(e) = self.database.insert_power_events(&events).await {
+            error!(
+                "❌ Error guardando {} eventos de alimentación: {}",
+                events.len(),
+                e
+            );
+        }
+
+        if let Some(notifications) = &self.notifications {
+            for event in &events {
+                if let Err(e) = notifications.publish(&event.device_id, event).await {
+                    error!("❌ Error publicando evento de alimentación: {}", e);
+                }
+            }
+        }
+    }
+
+    fn thresholds_for(&self, model: &str) -> &PowerThresholds {
+        self.models
+            .get(&model.trim().to_uppercase())
+            .unwrap_or(&self.thresholds)
+    }
+
+    fn evaluate(&self, positions: &[NormalizedPosition]) -> Vec<PowerEvent> {
+        let mut devices = self.devices.lock().unwrap();
+        let mut events = Vec::new();
+        for position in positions {
+            let state = devices.entry(position.device_id.clone()).or_default();
+            events.extend(transitions(
+                state,
+                self.thresholds_for(&position.model),
+                position,
+            ));
+        }
+        events
+    }
+}
+
+/// Eventos de una posición según el estado anterior del dispositivo, que actualiza
+fn transitions(
+    state: &mut DevicePower,
+    thresholds: &PowerThresholds,
+    position: &NormalizedPosition,
+) -> Vec<PowerEvent> {
+    // Posiciones atrasadas (buffer del equipo) no describen el estado actual
+    if let (Some(gps_epoch), Some(last)) = (position.gps_epoch, state.gps_epoch) {
+        if gps_epoch < last {
+            return Vec::new();
+        }
+    }
+    if position.gps_epoch.is_some() {
+        state.gps_epoch = position.gps_epoch;
+    }
+
+    let event = |event, voltage, threshold| PowerEvent {
+        device_id: position.device_id.clone(),
+        tenant: position.tenant.clone(),
+        model: Some(position.model.clone()).filter(|model| !model.is_empty()),
+        event,
+        voltage,
+        threshold,
+        uuid: position.uuid.clone(),
+        latitude: position.latitude,
+        longitude: position.longitude,
+        gps_datetime: position.gps_datetime,
+    };
+    let mut events = Vec::new();
+
+    if let Some(voltage) = position.main_battery_voltage_v {
+        let lost = voltage < thresholds.power_lost_volts;
+        let restored = voltage >= thresholds.power_restored_volts;
+        match state.powered {
+            Some(true) if lost => {
+                events.push(event(
+                    PowerTransition::PowerLost,
+                    voltage,
+                    thresholds.power_lost_volts,
+                ));
+                state.powered = Some(false);
+            }
+            Some(false) if restored => {
+                events.push(event(
+                    PowerTransition::PowerRestored,
+                    voltage,
+                    thresholds.power_restored_volts,
+                ));
+                state.powered = Some(true);
+            }
+            None if lost || restored => state.powered = Some(restored),
+            _ => {}
+        }
+    }
+
+    if let Some(voltage) = position.backup_battery_voltage_v {
+        let low = voltage < thresholds.low_backup_volts;
+        match state.backup_low {
+            Some(false) if low => {
+                events.push(event(
+                    PowerTransition::LowBackupBattery,
+                    voltage,
+                    thresholds.low_backup_volts,
+                ));
+                state.backup_low = Some(true);
+            }
+            Some(true) if voltage >= thresholds.low_backup_volts + BACKUP_HYSTERESIS_VOLTS => {
+                state.backup_low = Some(false);
+            }
+            None => state.backup_low = Some(low),
+            _ => {}
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    fn position(gps_epoch: i64, main: f64, backup: f64) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.gps_epoch = Some(gps_epoch);
+        position.main_battery_voltage_v = Some(main);
+        position.backup_battery_voltage_v = Some(backup);
+        position
+    }
+
+    #[test]
+    fn power_events_follow_voltage_crossings_with_hysteresis() {
+        let thresholds = PowerThresholds::default();
+        let mut state = DevicePower::default();
+        let mut kinds = |gps_epoch, main, backup| {
+            transitions(&mut state, &thresholds, &position(gps_epoch, main, backup))
+                .iter()
+                .map(|event| event.event)
+                .collect::<Vec<_>>()
+        };
+
+        // La primera lectura solo fija el estado
+        assert!(kinds(100, 12.6, 4.1).is_empty());
+        assert_eq!(kinds(110, 0.0, 4.0), [PowerTransition::PowerLost]);
+        // Entre el corte y el restablecimiento no cambia; una posición atrasada tampoco
+        assert!(kinds(120, 7.5, 3.9).is_empty());
+        assert!(kinds(105, 12.6, 3.9).is_empty());
+        assert_eq!(
+            kinds(130, 12.4, 3.5),
+            [
+                PowerTransition::PowerRestored,
+                PowerTransition::LowBackupBattery
+            ]
+        );
+        // La batería de respaldo vuelve a avisar solo tras recuperarse
+        assert!(kinds(140, 12.4, 3.7).is_empty());
+        assert!(kinds(150, 12.4, 3.5).is_empty());
+        assert!(kinds(160, 12.4, 3.9).is_empty());
+        assert_eq!(kinds(170, 12.4, 3.4), [PowerTransition::LowBackupBattery]);
+    }
+}
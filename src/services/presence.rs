@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::config::PresenceConfig;
+use crate::models::{NormalizedPosition, PresenceEvent, PresenceTransition};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+
+/// Dispositivo sin mensajes, devuelto por `/devices/offline`
+#[derive(Debug, Clone, Serialize)]
+pub struct OfflineDevice {
+    pub device_id: String,
+    pub tenant: Option<String>,
+    pub last_seen: DateTime<Utc>,
+    pub silent_secs: i64,
+}
+
+/// Último mensaje de un dispositivo
+struct LastSeen {
+    tenant: Option<String>,
+    at: DateTime<Utc>,
+    offline: bool,
+}
+
+/// Registra la hora del último mensaje de cada dispositivo y marca como
+/// desconectados los que llevan más de `offline_after_secs` sin reportar. Los
+/// cambios se publican como `DEVICE_OFFLINE` y `DEVICE_ONLINE` en el tópico de
+/// notificaciones.
+///
+/// El estado vive en memoria: tras un reinicio solo se vigilan los dispositivos que
+/// vuelven a reportar.
+pub struct PresenceMonitor {
+    config: PresenceConfig,
+    notifications: Option<NotificationPublisher>,
+    devices: Mutex<HashMap<String, LastSeen>>,
+    events: AtomicU64,
+}
+
+impl PresenceMonitor {
+    pub fn new(config: PresenceConfig, notifications: Option<NotificationPublisher>) -> Self {
+        info!(
+            "📶 Detección de dispositivos desconectados tras {}s sin mensajes",
+            config.offline_after_secs
+        );
+        Self {
+            config,
+            notifications,
+            devices: Mutex::new(HashMap::new()),
+            events: AtomicU64::new(0),
+        }
+    }
+
+    /// Cambios de conectividad publicados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Inicia la revisión periódica de los dispositivos sin mensajes
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(monitor.config.check_interval_secs));
+            loop {
+                interval.tick().await;
+                let events = monitor.mark_offline(Utc::now());
+                monitor.publish(events).await;
+            }
+        })
+    }
+
+    /// Actualiza la hora del último mensaje de los dispositivos del lote y publica
+    /// `DEVICE_ONLINE` para los que estaban desconectados
+    pub async fn record(&self, positions: &[NormalizedPosition]) {
+        let now = Utc::now();
+        let mut events = Vec::new();
+        {
+            let mut devices = self.devices.lock().unwrap();
+            for position in positions {
+                match devices.get_mut(&position.device_id) {
+                    Some(seen) => {
+                        if seen.offline {
+                            events.push(PresenceEvent {
+                                device_id: position.device_id.clone(),
+                                tenant: position.tenant.clone(),
+                                event: PresenceTransition::DeviceOnline,
+                                last_seen: seen.at,
+                                silent_secs: (now - seen.at).num_seconds(),
+                            });
+                        }
+                        seen.tenant = position.tenant.clone();
+                        seen.at = now;
+                        seen.offline = false;
+                    }
+                    None => {
+                        devices.insert(
+                            position.device_id.clone(),
+                            LastSeen {
+                                tenant: position.tenant.clone(),
+                                at: now,
+                                offline: false,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        self.publish(events).await;
+    }
+
+    /// Dispositivos desconectados, del silencio más largo al más corto
+    pub fn offline_devices(&self) -> Vec<OfflineDevice> {
+        let now = Utc::now();
+        let mut offline: Vec<_> = self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, seen)| seen.offline)
+            .map(|(device_id, seen)| OfflineDevice {
+                device_id: device_id.clone(),
+                tenant: seen.tenant.clone(),
+                last_seen: seen.at,
+                silent_secs: (now - seen.at).num_seconds(),
+            })
+            .collect();
+        offline.sort_by_key(|device| device.last_seen);
+        offline
+    }
+
+    fn mark_offline(&self, now: DateTime<Utc>) -> Vec<PresenceEvent> {
+        let threshold = chrono::Duration::seconds(self.config.offline_after_secs as i64);
+        self.devices
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(_, seen)| !seen.offline && now - seen.at > threshold)
+            .map(|(device_id, seen)| {
+                seen.offline = true;
+                PresenceEvent {
+                    device_id: device_id.clone(),
+                    tenant: seen.tenant.clone(),
+                    event: PresenceTransition::DeviceOffline,
+                    last_seen: seen.at,
+                    silent_secs: (now - seen.at).num_seconds(),
+                }
+            })
+            .collect()
+    }
+
+    async fn publish(&self, events: Vec<PresenceEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "📶 Device {} {:?} (último mensaje {})",
+                redact::device_id(&event.device_id),
+                event.event,
+                event.last_seen
+            );
+        }
+
+        if let Some(notifications) = &self.notifications {
+            for event in &events {
+                if let Err(e) = notifications.publish(&event.device_id, event).await {
+                    error!("❌ Error publicando cambio de conectividad: {}", e);
+                }
+            }
+        }
+    }
+}
@@ -1,44 +1,493 @@
 use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
-use tokio::time;
-use tracing::{debug, error, info};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::models::{CommunicationRecord, DeviceMessage, Manufacturer};
-use crate::services::DatabaseService;
+use crate::config::SanitizationConfig;
+use crate::error_reporter::{self, Incident};
+use crate::errors::ErrorCategory;
+use crate::models::{
+    AckToken, CommunicationRecord, DeviceMessage, Manufacturer, NormalizedPosition,
+};
+use crate::redact;
+use crate::services::circuit_breaker::CircuitBreaker;
+use crate::services::database::is_transient_error;
+use crate::services::dry_run::{DryRunReport, DryRunSummary};
+use crate::services::enrichment::EnricherChain;
+use crate::services::gps_quality::QuarantinedPosition;
+use crate::services::live_tail::{LiveTail, TailStage};
+use crate::services::memory::MemoryUsage;
+use crate::services::message_sink::SinkStatistics;
+use crate::services::pipeline_metrics::{ErrorStatistics, SourceStatistics};
+use crate::services::sharding::ShardStatistics;
+use crate::services::{
+    ArchiveService, Backpressure, BatchController, Clock, Downsampler, DriverTracker,
+    DrivingBehaviorDetector, GeofenceService, GpsQualityChecker, IdempotencyStore,
+    MemoryAccounting, PipelineMetrics, PositionFilter, PowerMonitor, PresenceMonitor, RecordStore,
+    ShardFilter, SinkPipeline, SystemClock, TenantRouter, TripDetector,
+};
+
+/// Intervalo por defecto entre health checks con el circuito de BD abierto
+const DEFAULT_CIRCUIT_PROBE_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct MessageProcessor {
-    database: Arc<DatabaseService>,
+    database: Arc<dyn RecordStore>,
     batch_size: usize,
     flush_interval: Duration,
+    backpressure: Backpressure,
+    sanitization: SanitizationConfig,
+    // Tamaño de lote e intervalo de flush ajustados a la carga (None = fijos)
+    batch_controller: Option<Arc<BatchController>>,
+    // Claves de mensajes ya guardados, para descartar redeliveries sin ir a la BD
+    idempotency: Option<Arc<IdempotencyStore>>,
+    // Retiene el lote y pausa el consumo mientras la BD no está disponible
+    circuit_breaker: CircuitBreaker,
+    // Copia cruda de los mensajes recibidos en S3/MinIO
+    archive: Option<Arc<ArchiveService>>,
+    // Bases de datos adicionales, cada una con su buffer y tarea de flush
+    fanout: Vec<(String, Arc<dyn RecordStore>)>,
+    // Campos que no pudieron convertirse a su tipo o estaban fuera de rango
+    invalid_fields: Arc<AtomicU64>,
+    // Posiciones con un GPS_DATETIME en un formato no reconocido
+    invalid_datetimes: Arc<AtomicU64>,
+    // Mensajes en el canal de entrada, aún sin repartir entre los carriles
+    queued: Arc<AtomicUsize>,
+    // Ritmo por origen, mensajes por fabricante, errores y latencia de escritura
+    metrics: Arc<PipelineMetrics>,
+    // Tamaño promedio de mensajes y registros para estimar la memoria de cada etapa
+    memory: Arc<MemoryAccounting>,
+    // Vista en vivo de los mensajes recibidos y los registros construidos
+    live_tail: Option<Arc<LiveTail>>,
+    // Loops de lotes en paralelo; cada dispositivo siempre va al mismo
+    lanes: usize,
+    // Contadores de cada carril, en el orden de los carriles
+    lane_counters: Arc<Vec<LaneCounters>>,
+    // Etapas que completan las posiciones antes de construir los registros
+    enrichers: EnricherChain,
+    // Califica el fix GPS y aparta o descarta las posiciones de baja calidad
+    gps_quality: Option<Arc<GpsQualityChecker>>,
+    // Descarta posiciones repetidas o antiguas antes de escribirlas
+    position_filter: Option<Arc<PositionFilter>>,
+    // Eventos de entrada/salida de geocercas, evaluados tras los enriquecedores
+    geofences: Option<Arc<GeofenceService>>,
+    // Abre y cierra viajes por dispositivo, con todas las posiciones del lote
+    trips: Option<Arc<TripDetector>>,
+    // Frenadas, aceleraciones y excesos de velocidad, con todas las posiciones del lote
+    driving_behavior: Option<Arc<DrivingBehaviorDetector>>,
+    // Sesiones de conductor a partir de los reportes de identificación
+    drivers: Option<Arc<DriverTracker>>,
+    // Cortes de alimentación y batería de respaldo baja
+    power: Option<Arc<PowerMonitor>>,
+    // Último mensaje de cada dispositivo, para detectar los que dejan de reportar
+    presence: Option<Arc<PresenceMonitor>>,
+    // Descarta posiciones periódicas dentro del intervalo de muestreo, tras las geocercas
+    downsampler: Option<Arc<Downsampler>>,
+    // Salidas (MQTT, SQS, Redis Streams) de las posiciones ya guardadas en la BD,
+    // enriquecidas; cada una con su cola y sus reintentos
+    sinks: SinkPipeline,
+    // Deja pasar solo los dispositivos del shard de esta instancia
+    shard: Option<Arc<ShardFilter>>,
+    // Resuelve el tenant de cada mensaje para enviarlo a los carriles de su tenant
+    tenant_router: Option<Arc<TenantRouter>>,
+    // Base de datos (buffer propio) de cada tenant, en el orden de sus grupos de carriles
+    tenants: Vec<(String, Arc<dyn RecordStore>)>,
+    lanes_per_tenant: usize,
+    // Tenant de los carriles que ejecuta esta copia del procesador
+    tenant: Option<String>,
+    // Modo dry-run: los registros van al informe en lugar de a la BD
+    dry_run: Option<Arc<DryRunReport>>,
+    // Timer de flush de los lotes, latencia de escritura y sondeo del circuito
+    clock: Arc<dyn Clock>,
+}
+
+/// Mensajes y lotes procesados por un carril
+#[derive(Debug, Default)]
+struct LaneCounters {
+    messages: AtomicU64,
+    batches: AtomicU64,
+    // Mensajes en el canal del carril más los del lote en curso
+    pending: AtomicUsize,
 }
 
 impl MessageProcessor {
-    pub fn new(database: Arc<DatabaseService>, batch_size: usize, flush_interval_ms: u64) -> Self {
+    pub fn new(
+        database: Arc<dyn RecordStore>,
+        batch_size: usize,
+        flush_interval_ms: u64,
+        backpressure: Backpressure,
+        sanitization: SanitizationConfig,
+    ) -> Self {
         Self {
             database,
             batch_size,
             flush_interval: Duration::from_millis(flush_interval_ms),
+            circuit_breaker: CircuitBreaker::new(
+                DEFAULT_CIRCUIT_PROBE_INTERVAL,
+                backpressure.clone(),
+            ),
+            backpressure,
+            sanitization,
+            idempotency: None,
+            batch_controller: None,
+            archive: None,
+            fanout: Vec::new(),
+            invalid_fields: Arc::new(AtomicU64::new(0)),
+            invalid_datetimes: Arc::new(AtomicU64::new(0)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            metrics: Arc::new(PipelineMetrics::default()),
+            memory: Arc::new(MemoryAccounting::default()),
+            live_tail: None,
+            lanes: 1,
+            lane_counters: Arc::new(vec![LaneCounters::default()]),
+            enrichers: EnricherChain::default(),
+            gps_quality: None,
+            position_filter: None,
+            geofences: None,
+            trips: None,
+            driving_behavior: None,
+            drivers: None,
+            power: None,
+            presence: None,
+            downsampler: None,
+            sinks: SinkPipeline::default(),
+            shard: None,
+            tenant_router: None,
+            tenants: Vec::new(),
+            lanes_per_tenant: 0,
+            tenant: None,
+            dry_run: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Reloj de los timers del procesador; los tests usan `ManualClock` para avanzar
+    /// el flush de los lotes y el sondeo del circuito sin esperas reales
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// No escribe nada: registra en `report` lo que se habría insertado y los
+    /// problemas de validación
+    pub fn with_dry_run(mut self, report: Arc<DryRunReport>) -> Self {
+        self.dry_run = Some(report);
+        self
+    }
+
+    /// Resumen final del dry-run, si está activo
+    pub fn log_dry_run_summary(&self) {
+        if let Some(report) = &self.dry_run {
+            report.log_summary(&self.metrics.errors());
         }
     }
 
+    /// Comparte las métricas del pipeline con el consumidor, que cuenta sus errores
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Comparte la contabilidad de memoria con el publicador de notificaciones
+    pub fn with_memory(mut self, memory: Arc<MemoryAccounting>) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Publica los mensajes recibidos y los registros construidos en `/tail`
+    pub fn with_live_tail(mut self, live_tail: Arc<LiveTail>) -> Self {
+        self.live_tail = Some(live_tail);
+        self
+    }
+
+    /// Intervalo entre health checks mientras el circuito de BD está abierto
+    pub fn with_circuit_probe_interval(mut self, probe_interval: Duration) -> Self {
+        self.circuit_breaker = CircuitBreaker::new(probe_interval, self.backpressure.clone());
+        self
+    }
+
+    /// Ajusta el tamaño de lote y el intervalo de flush de los carriles según la
+    /// latencia de escritura y el ritmo de ingreso
+    pub fn with_batch_controller(mut self, controller: Arc<BatchController>) -> Self {
+        self.batch_controller = Some(controller);
+        self
+    }
+
+    /// Tamaño de lote vigente
+    fn batch_size(&self) -> usize {
+        self.batch_controller
+            .as_ref()
+            .map_or(self.batch_size, |controller| controller.batch_size())
+    }
+
+    /// Intervalo de flush vigente
+    fn flush_interval(&self) -> Duration {
+        self.batch_controller
+            .as_ref()
+            .map_or(self.flush_interval, |controller| {
+                controller.flush_interval()
+            })
+    }
+
+    /// Descarta los mensajes cuya clave de idempotencia ya fue guardada
+    pub fn with_idempotency(mut self, idempotency: Arc<IdempotencyStore>) -> Self {
+        self.idempotency = Some(idempotency);
+        self
+    }
+
+    /// Archiva cada mensaje recibido, antes de cualquier filtro
+    pub fn with_archive(mut self, archive: Arc<ArchiveService>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Copia cada lote al buffer de otra base de datos. Sus errores y reintentos no
+    /// afectan al primario; con la política `backpressure` un buffer lleno sí lo frena.
+    pub fn with_fanout(mut self, name: &str, database: Arc<dyn RecordStore>) -> Self {
+        self.fanout.push((name.to_string(), database));
+        self
+    }
+
+    /// Enriquecedores que se ejecutan sobre cada lote, en orden
+    pub fn with_enrichers(mut self, enrichers: EnricherChain) -> Self {
+        self.enrichers = enrichers;
+        self
+    }
+
+    /// Califica cada posición tras los enriquecedores, antes de los filtros y las geocercas
+    pub fn with_gps_quality(mut self, checker: GpsQualityChecker) -> Self {
+        self.gps_quality = Some(Arc::new(checker));
+        self
+    }
+
+    /// Filtra las posiciones repetidas o antiguas tras los enriquecedores
+    pub fn with_position_filter(mut self, filter: PositionFilter) -> Self {
+        self.position_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Segmenta viajes sobre cada posición ya enriquecida
+    pub fn with_trips(mut self, trips: Arc<TripDetector>) -> Self {
+        self.trips = Some(trips);
+        self
+    }
+
+    /// Detecta eventos de conducción sobre cada posición ya enriquecida
+    pub fn with_driving_behavior(mut self, detector: Arc<DrivingBehaviorDetector>) -> Self {
+        self.driving_behavior = Some(detector);
+        self
+    }
+
+    /// Sigue el conductor identificado en cada dispositivo
+    pub fn with_drivers(mut self, drivers: Arc<DriverTracker>) -> Self {
+        self.drivers = Some(drivers);
+        self
+    }
+
+    /// Detecta cortes de alimentación y batería de respaldo baja
+    pub fn with_power(mut self, power: Arc<PowerMonitor>) -> Self {
+        self.power = Some(power);
+        self
+    }
+
+    /// Registra la hora del último mensaje de cada dispositivo
+    pub fn with_presence(mut self, presence: Arc<PresenceMonitor>) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
+    /// Guarda una posición periódica por dispositivo por intervalo; geocercas, viajes
+    /// y eventos de conducción se siguen evaluando sobre todas las posiciones
+    pub fn with_downsampler(mut self, downsampler: Downsampler) -> Self {
+        self.downsampler = Some(Arc::new(downsampler));
+        self
+    }
+
+    /// Envía a las salidas del pipeline las posiciones normalizadas tras los
+    /// enriquecedores y filtros
+    pub fn with_sinks(mut self, sinks: SinkPipeline) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    /// Procesa solo los dispositivos del shard; los demás se descartan o republican
+    /// antes de archivarlos o contarlos
+    pub fn with_shard(mut self, shard: Arc<ShardFilter>) -> Self {
+        self.shard = Some(shard);
+        self
+    }
+
+    /// Evalúa las geocercas sobre cada posición ya enriquecida
+    pub fn with_geofences(mut self, geofences: Arc<GeofenceService>) -> Self {
+        self.geofences = Some(geofences);
+        self
+    }
+
+    /// Reparte los mensajes en `lanes` loops de lotes según el hash del device_id: los
+    /// mensajes de un dispositivo se procesan en orden y los de distintos dispositivos
+    /// en paralelo. Con `lanes <= 1` hay un único loop.
+    pub fn with_lanes(mut self, lanes: usize) -> Self {
+        self.lanes = lanes.max(1);
+        self.reset_lane_counters();
+        self
+    }
+
+    /// Envía los mensajes de cada tenant a `lanes_per_tenant` carriles propios que
+    /// escriben en su base de datos (`databases`, con buffer independiente). Los
+    /// mensajes sin tenant usan los carriles y la base de datos principales.
+    pub fn with_tenant_routing(
+        mut self,
+        router: Arc<TenantRouter>,
+        databases: Vec<(String, Arc<dyn RecordStore>)>,
+        lanes_per_tenant: usize,
+    ) -> Self {
+        self.tenant_router = Some(router);
+        self.tenants = databases;
+        self.lanes_per_tenant = lanes_per_tenant.max(1);
+        self.reset_lane_counters();
+        self
+    }
+
+    /// Un contador por carril: primero los principales y luego los de cada tenant
+    fn reset_lane_counters(&mut self) {
+        let total = self.lanes + self.tenants.len() * self.lanes_per_tenant;
+        self.lane_counters = Arc::new((0..total).map(|_| LaneCounters::default()).collect());
+    }
+
+    /// Copias del procesador para cada grupo de carriles, con su primer carril y la
+    /// cantidad de carriles del grupo
+    fn lane_groups(&self) -> Vec<(MessageProcessor, usize, usize)> {
+        let mut groups = vec![(self.clone(), 0, self.lanes)];
+        for (index, (tenant, database)) in self.tenants.iter().enumerate() {
+            let mut processor = self.clone();
+            processor.database = database.clone();
+            processor.tenant = Some(tenant.clone());
+            groups.push((
+                processor,
+                self.lanes + index * self.lanes_per_tenant,
+                self.lanes_per_tenant,
+            ));
+        }
+        groups
+    }
+
     /// Inicia el procesador principal que consume mensajes del canal Kafka
     pub async fn start_processing(
         &self,
         mut message_receiver: mpsc::UnboundedReceiver<DeviceMessage>,
     ) -> Result<()> {
-        info!("🚀 Iniciando procesador de mensajes...");
+        info!(
+            "🚀 Iniciando procesador de mensajes ({} carriles, {} tenants con {} carriles cada uno)...",
+            self.lanes,
+            self.tenants.len(),
+            self.lanes_per_tenant
+        );
+
+        // Un procesador abortado (watchdog) deja sus contadores de pendientes sin vaciar
+        for counters in self.lane_counters.iter() {
+            counters.pending.store(0, Ordering::Relaxed);
+        }
 
-        // Canal interno para batch processing
-        let (batch_sender, batch_receiver) = mpsc::channel::<DeviceMessage>(self.batch_size * 2);
+        let controller_task = self
+            .batch_controller
+            .as_ref()
+            .map(|controller| controller.start());
 
-        // Task para recibir mensajes del Kafka y enviar al batch processor
-        let sender_clone = batch_sender.clone();
-        tokio::spawn(async move {
+        // Un canal interno y un loop de lotes por carril
+        let groups = self.lane_groups();
+        let mut lane_senders = Vec::with_capacity(self.lane_counters.len());
+        let mut lane_tasks = JoinSet::new();
+        for (processor, first_lane, lanes) in &groups {
+            for lane in *first_lane..first_lane + lanes {
+                let (batch_sender, batch_receiver) =
+                    mpsc::channel::<DeviceMessage>(self.batch_size * 2);
+                lane_senders.push(batch_sender);
+                let processor = processor.clone();
+                lane_tasks.spawn(async move {
+                    processor.batch_processing_loop(lane, batch_receiver).await
+                });
+            }
+        }
+
+        // Primer carril del grupo de cada tenant
+        let tenant_groups: HashMap<String, (usize, usize)> = groups
+            .iter()
+            .filter_map(|(processor, first_lane, lanes)| {
+                processor
+                    .tenant
+                    .clone()
+                    .map(|tenant| (tenant, (*first_lane, *lanes)))
+            })
+            .collect();
+        let router = self.tenant_router.clone();
+        let default_lanes = self.lanes;
+
+        // Task para recibir mensajes del Kafka y repartirlos entre los carriles.
+        // Los senders se mueven a la tarea para que los loops terminen al cerrarse el canal.
+        let backpressure = self.backpressure.clone();
+        let archive = self.archive.clone();
+        let queued = self.queued.clone();
+        let metrics = self.metrics.clone();
+        let memory = self.memory.clone();
+        let live_tail = self.live_tail.clone();
+        let shard = self.shard.clone();
+        let forward_task = tokio::spawn(async move {
             while let Some(message) = message_receiver.recv().await {
-                if let Err(e) = sender_clone.send(message).await {
+                // Reportar la profundidad de la cola para pausar/reanudar el consumo
+                backpressure.update(message_receiver.len());
+                queued.store(message_receiver.len(), Ordering::Relaxed);
+                if let Some(shard) = shard.as_ref().filter(|shard| !shard.owns(&message)) {
+                    shard.hand_off(message).await;
+                    continue;
+                }
+                let tenant = match router
+                    .as_ref()
+                    .map(|router| router.resolve_signed(&message))
+                    .transpose()
+                {
+                    Ok(tenant) => tenant.flatten(),
+                    Err(e) => {
+                        metrics.record_error(ErrorCategory::SignatureInvalid);
+                        warn!(
+                            category = %ErrorCategory::SignatureInvalid,
+                            "🔏 Mensaje {} descartado: {}",
+                            message.uuid,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                metrics.record_message(
+                    message.source.as_deref().unwrap_or("desconocido"),
+                    message.get_manufacturer().as_str(),
+                );
+                memory.record_message(&message);
+                if let Some(live_tail) = &live_tail {
+                    live_tail.publish(
+                        TailStage::Parsed,
+                        &message.data.device_id,
+                        &message.uuid,
+                        None,
+                        &message,
+                    );
+                }
+
+                if let Some(archive) = &archive {
+                    archive.push(&message).await;
+                }
+
+                let (first_lane, lanes) = tenant
+                    .and_then(|tenant| tenant_groups.get(tenant).copied())
+                    .unwrap_or((0, default_lanes));
+                let lane = first_lane + lane_for(&message.data.device_id, lanes);
+                if let Err(e) = lane_senders[lane].send(message).await {
                     error!("Error enviando mensaje al batch processor: {}", e);
                     break;
                 }
@@ -46,17 +495,59 @@ impl MessageProcessor {
             info!("Canal de recepción Kafka cerrado");
         });
 
-        // Task principal de procesamiento por lotes
-        self.batch_processing_loop(batch_receiver).await
+        // Un carril caído deja al procesador incompleto: se detienen los demás y la
+        // tarea de reparto para que el supervisor lo reinicie con un canal nuevo
+        let mut failure = None;
+        while let Some(result) = lane_tasks.join_next().await {
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => failure = Some(e),
+                Err(e) => failure = Some(e.into()),
+            }
+            if failure.is_some() {
+                lane_tasks.abort_all();
+                forward_task.abort();
+                break;
+            }
+        }
+        if let Some(task) = controller_task {
+            task.abort();
+        }
+        if let Some(e) = failure {
+            return Err(e);
+        }
+
+        // Con todos los carriles terminados, escribir lo pendiente en los buffers de BD
+        if let Err(e) = self.database.flush_buffer().await {
+            self.database_error("Error haciendo flush del buffer de BD", &e);
+        }
+        for (tenant, database) in &self.tenants {
+            if let Err(e) = database.flush_buffer().await {
+                self.database_error(
+                    &format!(
+                        "Error haciendo flush del buffer de BD del tenant {}",
+                        tenant
+                    ),
+                    &e,
+                );
+            }
+        }
+        self.flush_fanout_buffers().await;
+
+        info!("✅ Procesador de mensajes terminado");
+        Ok(())
     }
 
     /// Loop principal de procesamiento por lotes
     async fn batch_processing_loop(
         &self,
+        lane: usize,
         mut receiver: mpsc::Receiver<DeviceMessage>,
     ) -> Result<()> {
+        let counters = &self.lane_counters[lane];
         let mut batch = Vec::with_capacity(self.batch_size);
-        let mut flush_timer = time::interval(self.flush_interval);
+        let mut flush_interval = self.flush_interval();
+        let mut next_flush = self.clock.now() + flush_interval;
 
         loop {
             tokio::select! {
@@ -65,107 +556,451 @@ impl MessageProcessor {
                     match message {
                         Some(msg) => {
                             batch.push(msg);
+                            counters.messages.fetch_add(1, Ordering::Relaxed);
+                            if let Some(controller) = &self.batch_controller {
+                                controller.record_message();
+                            }
 
-                            // Si el batch está lleno, procesarlo inmediatamente
-                            if batch.len() >= self.batch_size {
+                            // Si el batch está lleno, procesarlo inmediatamente. Con las
+                            // escrituras pausadas se acumula en el buffer de BD.
+                            if batch.len() >= self.batch_size() && self.database.writes_paused() {
+                                self.buffer_batch(&mut batch).await;
+                                counters.batches.fetch_add(1, Ordering::Relaxed);
+                            } else if batch.len() >= self.batch_size() {
                                 self.process_batch(&mut batch).await;
+                                counters.batches.fetch_add(1, Ordering::Relaxed);
                             }
                         }
                         None => {
-                            // Canal cerrado, procesar batch final y salir
+                            // Canal cerrado, procesar el batch final del carril
                             if !batch.is_empty() {
                                 self.process_batch(&mut batch).await;
+                                counters.batches.fetch_add(1, Ordering::Relaxed);
                             }
                             break;
                         }
                     }
                 }
 
-                // Timer para flush periódico
-                _ = flush_timer.tick() => {
+                // Timer para flush periódico: los lotes incompletos van al buffer de la BD
+                _ = self.clock.sleep_until(next_flush) => {
                     if !batch.is_empty() {
-                        self.process_batch(&mut batch).await;
+                        self.buffer_batch(&mut batch).await;
+                        counters.batches.fetch_add(1, Ordering::Relaxed);
                     }
+
+                    // Con lotes adaptativos el intervalo cambia con el ritmo de ingreso
+                    flush_interval = self.flush_interval();
+                    next_flush = self.clock.now() + flush_interval;
                 }
             }
+
+            counters
+                .pending
+                .store(receiver.len() + batch.len(), Ordering::Relaxed);
         }
+        counters.pending.store(0, Ordering::Relaxed);
 
-        info!("✅ Procesador de mensajes terminado");
         Ok(())
     }
 
     /// Procesa un lote de mensajes
     async fn process_batch(&self, batch: &mut Vec<DeviceMessage>) {
+        self.drop_processed(batch).await;
         if batch.is_empty() {
             return;
         }
 
-        let batch_size = batch.len();
-        debug!("📦 Procesando lote de {} mensajes", batch_size);
+        let span = batch_span("batch", batch);
+        async {
+            let batch_size = batch.len();
+            debug!("📦 Procesando lote de {} mensajes", batch_size);
 
-        // Convertir mensajes a registros de BD, agrupando por fabricante
-        let mut suntech_records = Vec::new();
-        let mut queclink_records = Vec::new();
+            // Convertir mensajes a registros de BD, agrupando por fabricante
+            let (records, positions) = self.to_records(batch).await;
+            if let Some(report) = &self.dry_run {
+                self.report_dry_run(report, records);
+                return;
+            }
+            self.fan_out(&records).await;
+            let groups = CommunicationRecord::group_by_manufacturer(records);
 
-        for message in batch.iter() {
-            let manufacturer = message.get_manufacturer();
+            debug!(
+                "📊 Agrupados: {}",
+                groups
+                    .iter()
+                    .map(|(manufacturer, records)| format!("{} {}", records.len(), manufacturer))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
 
-            // Preparar registro para BD
-            match CommunicationRecord::from_device_message(message) {
-                Ok(record) => {
-                    // Agrupar por fabricante
-                    match manufacturer {
-                        Manufacturer::Suntech => suntech_records.push(record),
-                        Manufacturer::Queclink => queclink_records.push(record),
+            // Los lotes incompletos de este carril que siguen en el buffer se escriben
+            // antes, para no guardar posiciones de un dispositivo fuera de orden
+            if let Err(e) = self.database.flush_buffer().await {
+                self.database_error("Error haciendo flush del buffer de BD", &e);
+            }
+
+            // Procesar en BD. Si la BD sigue caída tras los reintentos se abre el
+            // circuito y el lote se reintenta cuando vuelva a responder.
+            let db_result = loop {
+                let started = self.clock.now();
+                match self.process_database_batch_by_manufacturer(&groups).await {
+                    Ok(count) => {
+                        let elapsed = self.clock.now().saturating_duration_since(started);
+                        self.metrics.record_flush(elapsed);
+                        if let Some(controller) = &self.batch_controller {
+                            controller.record_write(batch_size, elapsed);
+                        }
+                        break Ok(count);
                     }
+                    Err(e) if is_transient_error(&e) => {
+                        self.database_error("Base de datos no disponible", &e);
+                        self.circuit_breaker
+                            .wait_for_recovery(self.database.as_ref(), self.clock.as_ref())
+                            .await;
+                    }
+                    result => break result,
+                }
+            };
+
+            // Reportar resultados
+            match db_result {
+                Ok(count) => {
+                    debug!("✅ Guardados {} registros en BD", count);
+                    self.remember_processed(batch).await;
+                    self.sinks.dispatch(&positions);
                 }
                 Err(e) => {
-                    error!(
-                        "Error convirtiendo mensaje a registro de BD: {} | Device: {}, UUID: {}, Manufacturer: {:?}",
-                        e, message.data.device_id, message.uuid, manufacturer
+                    self.database_error("Error guardando en BD", &e);
+                    error_reporter::report(
+                        Incident::new(
+                            "database",
+                            format!("Lote de {} registros perdido: {}", batch_size, e),
+                        )
+                        .with_category(ErrorCategory::of_database(&e)),
                     );
-                    continue;
                 }
             }
         }
+        .instrument(span)
+        .await;
 
-        debug!(
-            "📊 Agrupados: {} Suntech, {} Queclink",
-            suntech_records.len(),
-            queclink_records.len()
-        );
+        // Limpiar el batch
+        batch.clear();
+    }
+
+    /// Entrega un lote incompleto al buffer de la BD, que lo escribe junto con los
+    /// siguientes según su intervalo de flush y antigüedad máxima
+    async fn buffer_batch(&self, batch: &mut Vec<DeviceMessage>) {
+        self.drop_processed(batch).await;
+        if batch.is_empty() {
+            return;
+        }
+
+        debug!("🪣 {} mensajes enviados al buffer de BD", batch.len());
+        let span = batch_span("buffer_batch", batch);
+        async {
+            let (records, positions) = self.to_records(batch).await;
+            if let Some(report) = &self.dry_run {
+                self.report_dry_run(report, records);
+                return;
+            }
+            self.fan_out(&records).await;
+            // Las claves se recuerdan y las posiciones van a las salidas cuando el
+            // buffer confirma la escritura: si los registros se pierden, las
+            // redeliveries se vuelven a procesar
+            let keys = self.idempotency.as_ref().map(|idempotency| {
+                let keys: Vec<String> = batch
+                    .iter()
+                    .map(|message| idempotency.key_for(message))
+                    .collect();
+                (idempotency.clone(), keys)
+            });
+            let outputs = (!positions.is_empty() && !self.sinks.is_empty())
+                .then(|| (self.sinks.clone(), positions));
+            let written = (keys.is_some() || outputs.is_some()).then(|| {
+                let (written, confirmed) = oneshot::channel();
+                tokio::spawn(async move {
+                    if confirmed.await.is_ok() {
+                        if let Some((idempotency, keys)) = keys {
+                            idempotency.remember_keys(keys).await;
+                        }
+                        if let Some((sinks, positions)) = outputs {
+                            sinks.dispatch(&positions);
+                        }
+                    }
+                });
+                written
+            });
+            self.database.buffer_records(records, written).await;
+        }
+        .instrument(span)
+        .await;
+        batch.clear();
+    }
 
-        // Procesar en BD
-        let db_future =
-            self.process_database_batch_by_manufacturer(suntech_records, queclink_records);
+    /// Registra en el informe del dry-run los registros de un lote, por tabla
+    fn report_dry_run(&self, report: &DryRunReport, records: Vec<CommunicationRecord>) {
+        for (manufacturer, records) in CommunicationRecord::group_by_manufacturer(records) {
+            report.record_inserts(&self.database.history_table(manufacturer), &records);
+        }
+    }
 
-        // Ejecutar operación
-        let db_result = db_future.await;
+    /// Entrega una copia de los registros al buffer de cada destino de fan-out
+    async fn fan_out(&self, records: &[CommunicationRecord]) {
+        for (_, target) in &self.fanout {
+            target.buffer_records(records.to_vec(), None).await;
+        }
+    }
+
+    /// Cuenta un error de la BD en su categoría y lo registra en el log
+    fn database_error(&self, context: &str, error: &anyhow::Error) {
+        let category = ErrorCategory::of_database(error);
+        self.metrics.record_error(category);
+        error!(category = %category, "❌ {}: {}", context, error);
+    }
 
-        // Reportar resultados
-        match db_result {
-            Ok(count) => {
-                debug!("✅ Guardados {} registros en BD", count);
+    /// Escribe lo pendiente en los buffers de fan-out
+    async fn flush_fanout_buffers(&self) {
+        for (name, target) in &self.fanout {
+            if let Err(e) = target.flush_buffer().await {
+                self.database_error(
+                    &format!("Error haciendo flush del buffer de BD '{}'", name),
+                    &e,
+                );
             }
-            Err(e) => {
-                error!("❌ Error guardando en BD: {}", e);
+        }
+    }
+
+    /// Descarta mensajes ya guardados anteriormente
+    async fn drop_processed(&self, batch: &mut Vec<DeviceMessage>) {
+        if let Some(idempotency) = &self.idempotency {
+            idempotency.drop_seen(batch).await;
+        }
+    }
+
+    /// Recuerda las claves del lote para descartar sus redeliveries
+    async fn remember_processed(&self, batch: &[DeviceMessage]) {
+        if let Some(idempotency) = &self.idempotency {
+            idempotency.remember(batch).await;
+        }
+    }
+
+    /// Normaliza los mensajes, ejecuta los enriquecedores y el filtro de posiciones y
+    /// construye los registros de BD, descartando los que fallan. Devuelve también
+    /// las posiciones de esos registros, para las salidas una vez guardados.
+    async fn to_records(
+        &self,
+        batch: &[DeviceMessage],
+    ) -> (Vec<CommunicationRecord>, Vec<NormalizedPosition>) {
+        let mut positions: Vec<NormalizedPosition> = batch
+            .iter()
+            .map(|message| {
+                let position = NormalizedPosition::from_device_message(message);
+                if !position.issues.is_empty() {
+                    if let Some(report) = &self.dry_run {
+                        report.record_issues(&position);
+                    }
+                    self.invalid_fields
+                        .fetch_add(position.issues.len() as u64, Ordering::Relaxed);
+                    if position
+                        .issues
+                        .iter()
+                        .any(|issue| issue.field == "gps_datetime")
+                    {
+                        self.invalid_datetimes.fetch_add(1, Ordering::Relaxed);
+                    }
+                    debug!(
+                        "⚠️ Campos inválidos en Device {} (UUID: {}): {}",
+                        redact::device_id(&position.device_id),
+                        position.uuid,
+                        position
+                            .issues
+                            .iter()
+                            .map(|issue| format!(
+                                "{}='{}' ({})",
+                                issue.field,
+                                redact::field(issue.field, &issue.value),
+                                issue.reason
+                            ))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                position
+            })
+            .collect();
+
+        // Los carriles de un tenant marcan sus posiciones; el enriquecedor de tenant
+        // puede reemplazarlo
+        if let Some(tenant) = &self.tenant {
+            for position in &mut positions {
+                position.tenant = Some(tenant.clone());
             }
         }
 
-        // Limpiar el batch
-        batch.clear();
+        self.enrichers.run(&mut positions).await;
+        // Cualquier mensaje cuenta como señal de vida, aunque luego se descarte
+        if let Some(presence) = &self.presence {
+            presence.record(&positions).await;
+        }
+        if let Some(checker) = &self.gps_quality {
+            let quarantined = checker.apply(&mut positions);
+            self.quarantine(quarantined).await;
+        }
+        if let Some(filter) = &self.position_filter {
+            filter.retain(&mut positions);
+        }
+        if let Some(geofences) = &self.geofences {
+            geofences.process(&positions).await;
+        }
+        if let Some(trips) = &self.trips {
+            trips.process(&positions).await;
+        }
+        if let Some(detector) = &self.driving_behavior {
+            detector.process(&positions).await;
+        }
+        if let Some(drivers) = &self.drivers {
+            drivers.process(&positions).await;
+        }
+        if let Some(power) = &self.power {
+            power.process(&positions).await;
+        }
+        if let Some(downsampler) = &self.downsampler {
+            downsampler.retain(&mut positions);
+        }
+
+        let mut records = Vec::with_capacity(positions.len());
+        positions.retain(|position| {
+                match CommunicationRecord::from_position(position, &self.sanitization) {
+                    Ok(record) => {
+                        records.push(record);
+                        true
+                    }
+                    Err(e) => {
+                        if let Some(report) = &self.dry_run {
+                            report.record_conversion_error(position, &e);
+                        }
+                        self.metrics.record_error(ErrorCategory::ConversionError);
+                        error!(
+                            category = %ErrorCategory::ConversionError,
+                            "Error convirtiendo mensaje a registro de BD: {} | Device: {}, UUID: {}, Manufacturer: {:?}",
+                            e, redact::device_id(&position.device_id), position.uuid, position.manufacturer
+                        );
+                        false
+                    }
+                }
+            });
+        attach_acks(batch, &mut records);
+        self.memory.record_records(&records);
+        if let Some(live_tail) = &self.live_tail {
+            for record in &records {
+                live_tail.publish(
+                    TailStage::Record,
+                    &record.device_id,
+                    &record.uuid,
+                    None,
+                    record,
+                );
+            }
+        }
+        (records, positions)
+    }
+
+    /// Guarda en cuarentena las posiciones de baja calidad. Un fallo solo se registra:
+    /// la cuarentena no debe frenar la ingesta.
+    async fn quarantine(&self, quarantined: Vec<QuarantinedPosition>) {
+        if quarantined.is_empty() {
+            return;
+        }
+        if let Some(report) = &self.dry_run {
+            for (_, reasons) in &quarantined {
+                report.record_quarantined(reasons);
+            }
+            return;
+        }
+
+        let records: Vec<_> = quarantined
+            .into_iter()
+            .filter_map(|(position, reasons)| {
+                CommunicationRecord::from_position(&position, &self.sanitization)
+                    .ok()
+                    .map(|record| (record, reasons))
+            })
+            .collect();
+        match self.database.insert_quarantined(&records).await {
+            Ok(()) => debug!("🛰️ {} posiciones en cuarentena", records.len()),
+            Err(e) => error!(
+                "❌ Error guardando {} posiciones en cuarentena: {}",
+                records.len(),
+                e
+            ),
+        }
     }
 
     /// Procesa un lote de registros para la base de datos, agrupados por fabricante
     async fn process_database_batch_by_manufacturer(
         &self,
-        suntech_records: Vec<CommunicationRecord>,
-        queclink_records: Vec<CommunicationRecord>,
+        groups: &[(Manufacturer, Vec<CommunicationRecord>)],
     ) -> Result<usize> {
         // Insertar registros directamente usando el método que separa por fabricante
-        self.database
-            .insert_records_by_manufacturer(suntech_records, queclink_records)
-            .await
+        self.database.insert_records_by_manufacturer(groups).await
+    }
+
+    /// Mensajes que aún no llegaron a la BD, para seguir el progreso del drenado
+    pub async fn drain_status(&self) -> DrainStatus {
+        let mut buffered = self.database.buffer_size().await;
+        for (_, database) in self.tenants.iter().chain(&self.fanout) {
+            buffered += database.buffer_size().await;
+        }
+        DrainStatus {
+            queued: self.queued.load(Ordering::Relaxed),
+            in_lanes: self
+                .lane_counters
+                .iter()
+                .map(|counters| counters.pending.load(Ordering::Relaxed))
+                .sum(),
+            buffered,
+        }
+    }
+
+    /// Antigüedad del registro más antiguo entre los buffers de BD
+    pub async fn oldest_buffer_age(&self) -> Option<Duration> {
+        let mut oldest = self.database.buffer_age().await;
+        for (_, database) in self.tenants.iter().chain(&self.fanout) {
+            oldest = oldest.max(database.buffer_age().await);
+        }
+        oldest
+    }
+
+    /// Memoria aproximada retenida por cada etapa
+    pub async fn memory_usage(&self) -> MemoryUsage {
+        let status = self.drain_status().await;
+        let mut usage = self
+            .memory
+            .usage(status.queued, status.in_lanes, status.buffered);
+        usage.limited = self.backpressure.is_memory_limited();
+        usage
+    }
+
+    /// Mensajes recibidos más lotes cerrados por los carriles: si no cambia con
+    /// mensajes pendientes, los carriles están atascados
+    pub fn progress(&self) -> u64 {
+        self.lane_counters
+            .iter()
+            .map(|counters| {
+                counters.messages.load(Ordering::Relaxed) + counters.batches.load(Ordering::Relaxed)
+            })
+            .sum()
+    }
+
+    /// Con el circuito abierto los carriles esperan a la BD a propósito
+    pub fn circuit_open(&self) -> bool {
+        self.circuit_breaker.is_open()
+    }
+
+    pub fn writes_paused(&self) -> bool {
+        self.database.writes_paused()
     }
 
     /// Procesa un lote de mensajes para Kafka
@@ -174,7 +1009,30 @@ impl MessageProcessor {
         info!("🔄 Flushing buffer de BD...");
 
         if let Err(e) = self.database.flush_buffer().await {
-            error!("Error haciendo flush del buffer de BD: {}", e);
+            self.database_error("Error haciendo flush del buffer de BD", &e);
+        }
+        for (tenant, database) in &self.tenants {
+            if let Err(e) = database.flush_buffer().await {
+                self.database_error(
+                    &format!(
+                        "Error haciendo flush del buffer de BD del tenant {}",
+                        tenant
+                    ),
+                    &e,
+                );
+            }
+        }
+        self.flush_fanout_buffers().await;
+
+        if let Some(archive) = &self.archive {
+            info!("🔄 Cerrando archivos Parquet pendientes...");
+            if let Err(e) = archive.flush().await {
+                error!("Error cerrando archivos Parquet: {}", e);
+            }
+            // Lo que no se pueda subir queda en el spool para el próximo arranque
+            if let Err(e) = archive.upload_spooled().await {
+                error!("Error subiendo el spool del archivo: {}", e);
+            }
         }
 
         Ok(())
@@ -184,15 +1042,592 @@ impl MessageProcessor {
     pub async fn get_statistics(&self) -> ProcessorStatistics {
         let db_buffer_size = self.database.buffer_size().await;
 
+        let mut fanout = Vec::with_capacity(self.fanout.len());
+        for (name, target) in &self.fanout {
+            fanout.push(FanoutStatistics {
+                name: name.clone(),
+                buffer_size: target.buffer_size().await,
+                buffer_dropped: target.dropped_records(),
+            });
+        }
+
+        let mut tenants = Vec::with_capacity(self.tenants.len());
+        for (index, (tenant, database)) in self.tenants.iter().enumerate() {
+            let first_lane = self.lanes + index * self.lanes_per_tenant;
+            let counters = &self.lane_counters[first_lane..first_lane + self.lanes_per_tenant];
+            tenants.push(TenantStatistics {
+                tenant: tenant.clone(),
+                messages: counters
+                    .iter()
+                    .map(|counters| counters.messages.load(Ordering::Relaxed))
+                    .sum(),
+                batches: counters
+                    .iter()
+                    .map(|counters| counters.batches.load(Ordering::Relaxed))
+                    .sum(),
+                buffer_size: database.buffer_size().await,
+                buffer_dropped: database.dropped_records(),
+            });
+        }
+
         ProcessorStatistics {
             db_buffer_size,
-            batch_size: self.batch_size,
+            db_buffer_dropped: self.database.dropped_records(),
+            fanout,
+            sinks: self.sinks.statistics(),
+            dry_run: self.dry_run.as_ref().map(|report| report.summary()),
+            shard: self.shard.as_ref().map(|shard| shard.statistics()),
+            invalid_fields: self.invalid_fields.load(Ordering::Relaxed),
+            invalid_datetimes: self.invalid_datetimes.load(Ordering::Relaxed),
+            processed_duplicates: self
+                .idempotency
+                .as_ref()
+                .map_or(0, |idempotency| idempotency.skipped()),
+            low_quality_positions: self
+                .gps_quality
+                .as_ref()
+                .map_or(0, |checker| checker.invalid()),
+            duplicate_positions: self
+                .position_filter
+                .as_ref()
+                .map_or(0, |filter| filter.duplicates()),
+            stale_positions: self
+                .position_filter
+                .as_ref()
+                .map_or(0, |filter| filter.stale()),
+            downsampled_positions: self
+                .downsampler
+                .as_ref()
+                .map_or(0, |downsampler| downsampler.dropped()),
+            geofence_events: self
+                .geofences
+                .as_ref()
+                .map_or(0, |geofences| geofences.events_emitted()),
+            trip_events: self
+                .trips
+                .as_ref()
+                .map_or(0, |trips| trips.events_emitted()),
+            driving_events: self
+                .driving_behavior
+                .as_ref()
+                .map_or(0, |detector| detector.events_emitted()),
+            driver_events: self
+                .drivers
+                .as_ref()
+                .map_or(0, |drivers| drivers.events_emitted()),
+            power_events: self
+                .power
+                .as_ref()
+                .map_or(0, |power| power.events_emitted()),
+            presence_events: self
+                .presence
+                .as_ref()
+                .map_or(0, |presence| presence.events_emitted()),
+            lanes: self
+                .lane_counters
+                .iter()
+                .map(|counters| LaneStatistics {
+                    messages: counters.messages.load(Ordering::Relaxed),
+                    batches: counters.batches.load(Ordering::Relaxed),
+                    pending: counters.pending.load(Ordering::Relaxed),
+                })
+                .collect(),
+            tenants,
+            sources: self.metrics.sources(),
+            manufacturers: self.metrics.manufacturers(),
+            errors: self.metrics.errors(),
+            flush_latency_p50_ms: self.metrics.flush_latency_ms(50.0),
+            flush_latency_p95_ms: self.metrics.flush_latency_ms(95.0),
+            batch_size: self.batch_size(),
+            flush_interval_ms: self.flush_interval().as_millis() as u64,
+            circuit_open: self.circuit_breaker.is_open(),
+            memory: self.memory_usage().await,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Span de un lote, enlazado a los spans de consumo de sus mensajes (el exportador
+/// conserva los primeros 128 enlaces)
+fn batch_span(name: &'static str, batch: &[DeviceMessage]) -> Span {
+    let span = info_span!("batch", otel.name = name, messages = batch.len());
+    for context in batch.iter().filter_map(|msg| msg.trace_context.as_ref()) {
+        span.add_link(context.clone());
+    }
+    span
+}
+
+/// Pasa la confirmación de cada mensaje a su registro, para que el broker la reciba
+/// cuando el registro se escriba y no cuando el lote pase al buffer de BD
+fn attach_acks(batch: &[DeviceMessage], records: &mut [CommunicationRecord]) {
+    if batch.iter().all(|message| message.ack.is_none()) {
+        return;
+    }
+    let acks: HashMap<&str, &AckToken> = batch
+        .iter()
+        .filter_map(|message| message.ack.as_ref().map(|ack| (message.uuid.as_str(), ack)))
+        .collect();
+    for record in records {
+        record.ack = acks.get(record.uuid.as_str()).map(|ack| (*ack).clone());
+    }
+}
+
+/// Carril de un dispositivo: siempre el mismo para el mismo device_id
+fn lane_for(device_id: &str, lanes: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    device_id.hash(&mut hasher);
+    (hasher.finish() % lanes as u64) as usize
+}
+
+/// Estadísticas del procesador, en el log periódico y en `/stats` de la API de
+/// administración
+#[derive(Debug, Clone, Serialize)]
 pub struct ProcessorStatistics {
     pub db_buffer_size: usize,
+    pub db_buffer_dropped: u64,
+    pub fanout: Vec<FanoutStatistics>,
+    /// Colas, envíos y errores de cada salida de posiciones
+    pub sinks: Vec<SinkStatistics>,
+    /// Lo que se habría escrito, con `--dry-run`
+    pub dry_run: Option<DryRunSummary>,
+    /// Mensajes de otros shards descartados o republicados
+    pub shard: Option<ShardStatistics>,
+    pub invalid_fields: u64,
+    pub invalid_datetimes: u64,
+    pub processed_duplicates: u64,
+    pub geofence_events: u64,
+    pub trip_events: u64,
+    pub driving_events: u64,
+    pub driver_events: u64,
+    pub power_events: u64,
+    pub presence_events: u64,
+    pub low_quality_positions: u64,
+    pub duplicate_positions: u64,
+    pub stale_positions: u64,
+    pub downsampled_positions: u64,
+    pub lanes: Vec<LaneStatistics>,
+    pub tenants: Vec<TenantStatistics>,
+    pub sources: Vec<SourceStatistics>,
+    /// Mensajes recibidos por fabricante
+    pub manufacturers: BTreeMap<String, u64>,
+    pub errors: Vec<ErrorStatistics>,
+    /// Percentiles de la duración de las últimas escrituras de lotes completos
+    pub flush_latency_p50_ms: Option<f64>,
+    pub flush_latency_p95_ms: Option<f64>,
     pub batch_size: usize,
+    pub flush_interval_ms: u64,
+    pub circuit_open: bool,
+    /// Memoria aproximada por etapa, en bytes
+    pub memory: MemoryUsage,
+}
+
+/// Totales de un carril desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct LaneStatistics {
+    pub messages: u64,
+    pub batches: u64,
+    /// Mensajes en el canal y en el lote en curso del carril
+    pub pending: usize,
+}
+
+/// Mensajes pendientes durante el drenado
+#[derive(Debug, Clone, Copy)]
+pub struct DrainStatus {
+    /// En el canal de entrada
+    pub queued: usize,
+    /// En los canales y lotes en curso de los carriles
+    pub in_lanes: usize,
+    /// Registros en los buffers de BD (principal, tenants y fan-out)
+    pub buffered: usize,
+}
+
+impl DrainStatus {
+    pub fn total(&self) -> usize {
+        self.queued + self.in_lanes + self.buffered
+    }
+}
+
+/// Carga y buffer de BD de un tenant
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantStatistics {
+    pub tenant: String,
+    pub messages: u64,
+    pub batches: u64,
+    pub buffer_size: usize,
+    pub buffer_dropped: u64,
+}
+
+/// Buffer de un destino de fan-out
+#[derive(Debug, Clone, Serialize)]
+pub struct FanoutStatistics {
+    pub name: String,
+    pub buffer_size: usize,
+    pub buffer_dropped: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{IdempotencyConfig, OutputSinksConfig};
+    use crate::services::record_store::MockRecordStore;
+    use crate::services::ManualClock;
+    use crate::services::MessageSink;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    const FLUSH_INTERVAL: Duration = Duration::from_millis(1000);
+    const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn message(index: usize) -> DeviceMessage {
+        let mut message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        message.uuid = format!("{}-{}", message.uuid, index);
+        message
+    }
+
+    fn processor(
+        store: MockRecordStore,
+        batch_size: usize,
+        clock: &Arc<ManualClock>,
+    ) -> MessageProcessor {
+        MessageProcessor::new(
+            Arc::new(store),
+            batch_size,
+            FLUSH_INTERVAL.as_millis() as u64,
+            Backpressure::new(1000),
+            SanitizationConfig::default(),
+        )
+        .with_circuit_probe_interval(PROBE_INTERVAL)
+        .with_clock(clock.clone())
+    }
+
+    /// Store que acepta todo y avisa por el canal el tamaño de cada escritura
+    fn store(writes: mpsc::UnboundedSender<usize>) -> MockRecordStore {
+        let mut store = MockRecordStore::new();
+        store.expect_writes_paused().return_const(false);
+        store.expect_flush_buffer().returning(|| Ok(0));
+        store
+            .expect_insert_records_by_manufacturer()
+            .returning(move |groups| {
+                let count = groups.iter().map(|(_, records)| records.len()).sum();
+                writes.send(count).unwrap();
+                Ok(count)
+            });
+        store
+    }
+
+    /// Deja correr a los carriles hasta que no tengan nada más que hacer
+    async fn settle() {
+        for _ in 0..50 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn wait_for(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("condición no alcanzada");
+    }
+
+    async fn next(receiver: &mut UnboundedReceiver<usize>) -> usize {
+        tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("escritura no recibida")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn full_batch_is_written_without_waiting_for_the_timer() {
+        let clock = Arc::new(ManualClock::new());
+        let (writes, mut written) = mpsc::unbounded_channel();
+        let processor = processor(store(writes), 2, &clock);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        sender.send(message(0)).unwrap();
+        sender.send(message(1)).unwrap();
+        assert_eq!(next(&mut written).await, 2);
+
+        drop(sender);
+        task.await.unwrap().unwrap();
+        assert!(written.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn incomplete_batch_goes_to_buffer_when_the_flush_interval_elapses() {
+        let clock = Arc::new(ManualClock::new());
+        let (buffers, mut buffered) = mpsc::unbounded_channel();
+        let mut store = MockRecordStore::new();
+        store.expect_flush_buffer().times(1).returning(|| Ok(0));
+        store
+            .expect_buffer_records()
+            .times(1)
+            .returning(move |records, _| {
+                buffers.send(records.len()).unwrap();
+            });
+        let processor = processor(store, 10, &clock);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        for index in 0..3 {
+            sender.send(message(index)).unwrap();
+        }
+        wait_for(|| processor.progress() == 3).await;
+
+        clock.advance(FLUSH_INTERVAL - Duration::from_millis(1));
+        settle().await;
+        assert!(buffered.try_recv().is_err());
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(next(&mut buffered).await, 3);
+
+        // Al cerrar el canal solo queda el flush final del buffer
+        drop(sender);
+        task.await.unwrap().unwrap();
+    }
+
+    /// Entrega de nuevo el mismo mensaje y espera a que un carril lo reciba
+    async fn deliver(processor: &MessageProcessor, sender: &mpsc::UnboundedSender<DeviceMessage>) {
+        let before = processor.progress();
+        sender.send(message(0)).unwrap();
+        wait_for(|| processor.progress() > before).await;
+    }
+
+    #[tokio::test]
+    async fn redelivery_is_processed_when_the_buffered_write_is_lost() {
+        let clock = Arc::new(ManualClock::new());
+        let (buffers, mut buffered) = mpsc::unbounded_channel();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut store = MockRecordStore::new();
+        store.expect_flush_buffer().returning(|| Ok(0));
+        store.expect_buffer_records().returning({
+            let calls = calls.clone();
+            move |records, written| {
+                // El primer tramo se pierde sin escribirse (p. ej. error permanente
+                // en el flush); el segundo se guarda
+                if calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                    written.unwrap().send(()).unwrap();
+                }
+                buffers.send(records.len()).unwrap();
+            }
+        });
+        let idempotency = Arc::new(
+            IdempotencyStore::from_config(&IdempotencyConfig::default(), 100, None)
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+        let processor = processor(store, 10, &clock).with_idempotency(idempotency.clone());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        // Primera entrega: va al buffer y se pierde
+        deliver(&processor, &sender).await;
+        clock.advance(FLUSH_INTERVAL);
+        assert_eq!(next(&mut buffered).await, 1);
+
+        // La redelivery no se descarta y esta vez se guarda
+        deliver(&processor, &sender).await;
+        clock.advance(FLUSH_INTERVAL);
+        assert_eq!(next(&mut buffered).await, 1);
+        assert_eq!(idempotency.skipped(), 0);
+        settle().await;
+
+        // Ya guardada, la siguiente redelivery se descarta
+        deliver(&processor, &sender).await;
+        clock.advance(FLUSH_INTERVAL);
+        wait_for(|| idempotency.skipped() == 1).await;
+        assert!(buffered.try_recv().is_err());
+
+        drop(sender);
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_writes_pending_batch_and_flushes_buffer() {
+        let clock = Arc::new(ManualClock::new());
+        let (writes, mut written) = mpsc::unbounded_channel();
+        let mut store = MockRecordStore::new();
+        // Antes del lote final y al terminar los carriles
+        store.expect_flush_buffer().times(2).returning(|| Ok(0));
+        store
+            .expect_insert_records_by_manufacturer()
+            .times(1)
+            .returning(move |groups| {
+                writes.send(groups[0].1.len()).unwrap();
+                Ok(groups[0].1.len())
+            });
+        let processor = processor(store, 10, &clock);
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        sender.send(message(0)).unwrap();
+        sender.send(message(1)).unwrap();
+        drop(sender);
+        processor.start_processing(receiver).await.unwrap();
+
+        assert_eq!(next(&mut written).await, 2);
+    }
+
+    #[tokio::test]
+    async fn transient_error_holds_batch_until_database_recovers() {
+        let clock = Arc::new(ManualClock::new());
+        let (writes, mut written) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let mut store = MockRecordStore::new();
+        store.expect_writes_paused().return_const(false);
+        store.expect_flush_buffer().returning(|| Ok(0));
+        store.expect_health_check().returning(|| Ok(true));
+        store.expect_insert_records_by_manufacturer().returning({
+            let attempts = attempts.clone();
+            move |groups| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(sqlx::Error::PoolTimedOut.into());
+                }
+                writes.send(groups[0].1.len()).unwrap();
+                Ok(groups[0].1.len())
+            }
+        });
+        let processor = processor(store, 1, &clock);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        sender.send(message(0)).unwrap();
+        wait_for(|| processor.circuit_open()).await;
+
+        clock.advance(PROBE_INTERVAL - Duration::from_millis(1));
+        settle().await;
+        assert!(processor.circuit_open());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(next(&mut written).await, 1);
+        assert!(!processor.circuit_open());
+
+        drop(sender);
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn permanent_error_drops_batch_and_keeps_processing() {
+        let clock = Arc::new(ManualClock::new());
+        let (writes, mut written) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let mut store = MockRecordStore::new();
+        store.expect_writes_paused().return_const(false);
+        store.expect_flush_buffer().returning(|| Ok(0));
+        store.expect_insert_records_by_manufacturer().returning({
+            let attempts = attempts.clone();
+            move |groups| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(anyhow::anyhow!("columna inexistente"));
+                }
+                writes.send(groups[0].1.len()).unwrap();
+                Ok(groups[0].1.len())
+            }
+        });
+        let processor = processor(store, 1, &clock);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        sender.send(message(0)).unwrap();
+        sender.send(message(1)).unwrap();
+        assert_eq!(next(&mut written).await, 1);
+        assert!(!processor.circuit_open());
+        assert_eq!(
+            processor
+                .metrics
+                .errors()
+                .iter()
+                .map(|error| error.count)
+                .sum::<u64>(),
+            1
+        );
+
+        drop(sender);
+        task.await.unwrap().unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    /// Salida que avisa por el canal el uuid de cada posición recibida
+    struct ChannelSink(mpsc::UnboundedSender<String>);
+
+    #[async_trait::async_trait]
+    impl MessageSink for ChannelSink {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn send(&self, positions: &[NormalizedPosition]) -> Result<()> {
+            for position in positions {
+                self.0.send(position.uuid.clone()).unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn only_written_positions_reach_the_sinks() {
+        let clock = Arc::new(ManualClock::new());
+        let (writes, mut written) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let mut store = MockRecordStore::new();
+        store.expect_writes_paused().return_const(false);
+        store.expect_flush_buffer().returning(|| Ok(0));
+        store.expect_insert_records_by_manufacturer().returning({
+            let attempts = attempts.clone();
+            move |groups| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(anyhow::anyhow!("columna inexistente"));
+                }
+                writes.send(groups[0].1.len()).unwrap();
+                Ok(groups[0].1.len())
+            }
+        });
+        let (outputs, mut dispatched) = mpsc::unbounded_channel();
+        let mut sinks = SinkPipeline::default();
+        sinks.push(
+            Arc::new(ChannelSink(outputs)),
+            &OutputSinksConfig::default(),
+        );
+        let processor = processor(store, 1, &clock).with_sinks(sinks);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn({
+            let processor = processor.clone();
+            async move { processor.start_processing(receiver).await }
+        });
+
+        // El primer lote no se guarda y no llega a la salida; el segundo sí
+        sender.send(message(0)).unwrap();
+        sender.send(message(1)).unwrap();
+        assert_eq!(next(&mut written).await, 1);
+        let uuid = tokio::time::timeout(Duration::from_secs(5), dispatched.recv())
+            .await
+            .expect("posición no enviada a la salida")
+            .unwrap();
+        assert_eq!(uuid, message(1).uuid);
+
+        drop(sender);
+        task.await.unwrap().unwrap();
+        settle().await;
+        assert!(dispatched.try_recv().is_err());
+    }
 }
@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::config::siscom::{KafkaMessage, Metadata};
+use crate::config::PayloadFormat;
+use crate::models::{Manufacturer, RawFrame, RawFrameError, CURRENT_SCHEMA_VERSION};
+use crate::redact;
+
+/// Totales de tramas crudas decodificadas por el consumer desde el arranque
+#[derive(Debug, Clone, Default)]
+pub struct RawFrameStatistics {
+    pub decoded: u64,
+    /// Tramas de un fabricante conocido descartadas por estar incompletas o corruptas
+    pub invalid: u64,
+    /// Tramas descartadas por tipo de reporte no soportado, por fabricante y tipo
+    pub unsupported: BTreeMap<String, u64>,
+}
+
+/// Decodifica las tramas ASCII de los equipos cuando el decodificador no está
+/// desplegado: payloads con la trama tal cual (`BROKER_PAYLOAD_FORMAT=raw`) o
+/// `KafkaMessage` sin datos decodificados
+pub struct RawDecoder {
+    format: PayloadFormat,
+    decoded: AtomicU64,
+    invalid: AtomicU64,
+    unsupported: Mutex<BTreeMap<String, u64>>,
+}
+
+impl RawDecoder {
+    pub fn new(format: PayloadFormat) -> Self {
+        Self {
+            format,
+            decoded: AtomicU64::new(0),
+            invalid: AtomicU64::new(0),
+            unsupported: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn format(&self) -> PayloadFormat {
+        self.format
+    }
+
+    pub fn statistics(&self) -> RawFrameStatistics {
+        RawFrameStatistics {
+            decoded: self.decoded.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+            unsupported: self.unsupported.lock().unwrap().clone(),
+        }
+    }
+
+    /// Completa un `KafkaMessage` que llegó sin datos decodificados a partir de su
+    /// trama cruda. Los mensajes que ya traen datos no se modifican.
+    pub fn fill_from_raw(&self, message: &mut KafkaMessage) {
+        if message.decoded.is_some() || message.data.contains_key("DEVICE_ID") {
+            return;
+        }
+        match self.parse(&message.raw) {
+            Ok(frame) => {
+                message.data.extend(frame.data);
+                message.decoded = Some(frame.decoded);
+            }
+            Err(e) => debug!("🔎 Mensaje {} sin datos decodificados: {}", message.uuid, e),
+        }
+    }
+
+    /// Construye un `KafkaMessage` desde una trama recibida tal cual como payload, con
+    /// un UUID nuevo y la hora de recepción actual
+    pub fn message_from_frame(&self, payload: &[u8]) -> Result<KafkaMessage> {
+        let raw = std::str::from_utf8(payload)
+            .map_err(|_| anyhow!("Trama cruda que no es texto UTF-8"))?
+            .trim();
+        let frame = self.parse(raw)?;
+
+        let now = Utc::now().timestamp() as u64;
+        Ok(KafkaMessage {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            decoded: Some(frame.decoded),
+            data: frame.data,
+            metadata: Some(Metadata {
+                worker_id: 0,
+                received_epoch: now,
+                decoded_epoch: now,
+                bytes: payload.len() as u32,
+                client_ip: String::new(),
+                client_port: 0,
+            }),
+            raw: raw.to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+        })
+    }
+
+    /// Decodifica la trama con el fabricante que la reconozca y actualiza los totales
+    fn parse(&self, raw: &str) -> Result<RawFrame> {
+        let Some((manufacturer, result)) = Manufacturer::parse_raw(raw) else {
+            let header = raw.split([';', ',']).next().unwrap_or_default();
+            return Err(anyhow!(
+                "Trama cruda no reconocida (cabecera '{}')",
+                redact::raw(header)
+            ));
+        };
+
+        match result {
+            Ok(frame) => {
+                self.decoded.fetch_add(1, Ordering::Relaxed);
+                Ok(frame)
+            }
+            Err(e) => {
+                match &e {
+                    RawFrameError::Unsupported(report) => {
+                        *self
+                            .unsupported
+                            .lock()
+                            .unwrap()
+                            .entry(format!("{}/{}", manufacturer, report))
+                            .or_default() += 1;
+                    }
+                    RawFrameError::Invalid(_) => {
+                        self.invalid.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(anyhow!("Trama {} descartada: {}", manufacturer, e))
+            }
+        }
+    }
+}
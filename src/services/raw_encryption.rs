@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::{info, warn};
+
+use crate::config::RawEncryptionConfig;
+use crate::models::CommunicationRecord;
+
+/// Prefijo de los valores cifrados; lo que no lo lleva se guardó en claro
+const PREFIX: &str = "enc:v1:";
+
+/// Cifra `raw_message` con AES-256-GCM antes de guardarlo. El valor guardado es
+/// `enc:v1:<key_id>:<base64(nonce || texto cifrado || tag)>`, con un nonce aleatorio
+/// por valor; sigue siendo texto, así que las columnas no cambian de tipo.
+pub struct RawCipher {
+    key_id: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+// Sin las claves, solo sus ids
+impl fmt::Debug for RawCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawCipher")
+            .field("key_id", &self.key_id)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl RawCipher {
+    pub fn new(config: &RawEncryptionConfig) -> Result<Self> {
+        let mut keys = HashMap::new();
+        keys.insert(config.key_id.clone(), key_from_base64(&config.key)?);
+        for (key_id, key) in &config.previous_keys {
+            keys.insert(key_id.clone(), key_from_base64(key)?);
+        }
+        info!(
+            "🔐 raw_message se guarda cifrado con la clave '{}' ({} claves para descifrar)",
+            config.key_id,
+            keys.len()
+        );
+        Ok(Self {
+            key_id: config.key_id.clone(),
+            keys,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("No se pudo generar el nonce"))?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.keys[&self.key_id]
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("No se pudo cifrar raw_message"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        Ok(format!(
+            "{}{}:{}",
+            PREFIX,
+            self.key_id,
+            base64::engine::general_purpose::STANDARD.encode(payload)
+        ))
+    }
+
+    /// Descifra un valor guardado; los valores sin el prefijo se devuelven tal cual
+    pub fn decrypt<'a>(&self, value: &'a str) -> Result<Cow<'a, str>> {
+        let Some(encrypted) = value.strip_prefix(PREFIX) else {
+            return Ok(Cow::Borrowed(value));
+        };
+        let (key_id, payload) = encrypted
+            .split_once(':')
+            .context("Valor cifrado sin id de clave")?;
+        let key = self
+            .keys
+            .get(key_id)
+            .with_context(|| format!("Clave '{}' desconocida", key_id))?;
+        let mut payload = base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .context("Valor cifrado que no es base64")?;
+        if payload.len() < NONCE_LEN {
+            return Err(anyhow!("Valor cifrado demasiado corto"));
+        }
+        let mut sealed = payload.split_off(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(&payload).map_err(|_| anyhow!("Nonce inválido"))?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| anyhow!("No se pudo descifrar con la clave '{}'", key_id))?;
+        Ok(Cow::Owned(String::from_utf8(plaintext.to_vec())?))
+    }
+
+    /// Copia del registro con `raw_message` cifrado, lista para escribir
+    pub fn encrypt_record(&self, record: &CommunicationRecord) -> Result<CommunicationRecord> {
+        let mut record = record.clone();
+        if let Some(raw) = &record.raw_message {
+            record.raw_message = Some(self.encrypt(raw)?);
+        }
+        Ok(record)
+    }
+
+    pub fn encrypt_records(
+        &self,
+        records: &[CommunicationRecord],
+    ) -> Result<Vec<CommunicationRecord>> {
+        records
+            .iter()
+            .map(|record| self.encrypt_record(record))
+            .collect()
+    }
+
+    /// Descifra `raw_message` de registros leídos de la BD. Un valor que no se puede
+    /// descifrar (p. ej. con una clave retirada) se deja cifrado.
+    pub fn decrypt_records(&self, records: &mut [CommunicationRecord]) {
+        for record in records {
+            let Some(raw) = &record.raw_message else {
+                continue;
+            };
+            match self.decrypt(raw) {
+                Ok(Cow::Owned(plaintext)) => record.raw_message = Some(plaintext),
+                Ok(Cow::Borrowed(_)) => {}
+                Err(e) => warn!("⚠️ raw_message de {} sin descifrar: {:#}", record.uuid, e),
+            }
+        }
+    }
+}
+
+fn key_from_base64(key: &str) -> Result<LessSafeKey> {
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .context("Clave de cifrado que no es base64")?;
+    let key = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| anyhow!("La clave de cifrado debe ser de 32 bytes"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn config(key_id: &str, key: u8, previous: &[(&str, u8)]) -> RawEncryptionConfig {
+        let encode = |byte: u8| base64::engine::general_purpose::STANDARD.encode([byte; 32]);
+        RawEncryptionConfig {
+            key_id: key_id.to_string(),
+            key: encode(key),
+            previous_keys: previous
+                .iter()
+                .map(|(key_id, key)| (key_id.to_string(), encode(*key)))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn raw_message_round_trips_across_key_rotation() {
+        let raw = "STT;900000001;3FFFFF;95;1.0.12;1;20240501;12:00:00";
+        let old = RawCipher::new(&config("2024", 1, &[])).unwrap();
+        let encrypted = old.encrypt(raw).unwrap();
+        assert!(encrypted.starts_with("enc:v1:2024:"));
+        assert!(!encrypted.contains("900000001"));
+        // Nonce aleatorio: el mismo texto no da el mismo valor
+        assert_ne!(encrypted, old.encrypt(raw).unwrap());
+
+        let rotated = RawCipher::new(&config("2025", 2, &[("2024", 1)])).unwrap();
+        assert_eq!(rotated.decrypt(&encrypted).unwrap(), raw);
+        assert_eq!(rotated.decrypt(raw).unwrap(), raw);
+
+        let other = RawCipher::new(&config("2024", 3, &[])).unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+}
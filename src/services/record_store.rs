@@ -0,0 +1,106 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::models::{CommunicationRecord, Manufacturer};
+use crate::services::DatabaseService;
+
+/// Destino de escritura del procesador: inserción directa de lotes, buffer de lotes
+/// incompletos y cuarentena. `DatabaseService` es la implementación real; los tests
+/// del procesador usan un mock para simular caídas y errores sin PostgreSQL.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait RecordStore: Send + Sync {
+    /// Inserta registros agrupados por fabricante y devuelve cuántos se guardaron
+    async fn insert_records_by_manufacturer(
+        &self,
+        groups: &[(Manufacturer, Vec<CommunicationRecord>)],
+    ) -> Result<usize>;
+
+    /// Guarda los registros de baja calidad GPS con sus motivos
+    async fn insert_quarantined(
+        &self,
+        records: &[(CommunicationRecord, Vec<&'static str>)],
+    ) -> Result<()>;
+
+    /// Agrega registros al buffer, que se escribe según su intervalo y antigüedad;
+    /// `written` se avisa cuando quedan guardados
+    async fn buffer_records(
+        &self,
+        records: Vec<CommunicationRecord>,
+        written: Option<oneshot::Sender<()>>,
+    );
+
+    /// Escribe lo pendiente en el buffer y devuelve cuántos registros guardó
+    async fn flush_buffer(&self) -> Result<usize>;
+
+    async fn buffer_size(&self) -> usize;
+
+    /// Antigüedad del registro más antiguo del buffer (None si está vacío)
+    async fn buffer_age(&self) -> Option<Duration>;
+
+    /// Registros descartados por desborde del buffer
+    fn dropped_records(&self) -> u64;
+
+    /// Si un operador pausó la escritura de posiciones
+    fn writes_paused(&self) -> bool;
+
+    /// Tabla de histórico de un fabricante
+    fn history_table(&self, manufacturer: Manufacturer) -> String;
+
+    async fn health_check(&self) -> Result<bool>;
+}
+
+#[async_trait]
+impl RecordStore for DatabaseService {
+    async fn insert_records_by_manufacturer(
+        &self,
+        groups: &[(Manufacturer, Vec<CommunicationRecord>)],
+    ) -> Result<usize> {
+        DatabaseService::insert_records_by_manufacturer(self, groups).await
+    }
+
+    async fn insert_quarantined(
+        &self,
+        records: &[(CommunicationRecord, Vec<&'static str>)],
+    ) -> Result<()> {
+        DatabaseService::insert_quarantined(self, records).await
+    }
+
+    async fn buffer_records(
+        &self,
+        records: Vec<CommunicationRecord>,
+        written: Option<oneshot::Sender<()>>,
+    ) {
+        DatabaseService::buffer_records(self, records, written).await
+    }
+
+    async fn flush_buffer(&self) -> Result<usize> {
+        DatabaseService::flush_buffer(self).await
+    }
+
+    async fn buffer_size(&self) -> usize {
+        DatabaseService::buffer_size(self).await
+    }
+
+    async fn buffer_age(&self) -> Option<Duration> {
+        DatabaseService::buffer_age(self).await
+    }
+
+    fn dropped_records(&self) -> u64 {
+        DatabaseService::dropped_records(self)
+    }
+
+    fn writes_paused(&self) -> bool {
+        DatabaseService::writes_paused(self)
+    }
+
+    fn history_table(&self, manufacturer: Manufacturer) -> String {
+        DatabaseService::history_table(self, manufacturer)
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        DatabaseService::health_check(self).await
+    }
+}
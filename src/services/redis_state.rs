@@ -0,0 +1,131 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::Script;
+use tracing::{debug, info};
+
+use crate::config::{CurrentStateOrder, RedisStateConfig};
+use crate::models::CommunicationRecord;
+use crate::services::database::order_value;
+
+/// Escribe el hash solo si la posición no es más antigua que la guardada
+/// (ARGV: campo de orden, valor de orden, TTL, pares campo/valor)
+const UPSERT_SCRIPT: &str = r#"
+if ARGV[2] ~= '' then
+    local current = tonumber(redis.call('HGET', KEYS[1], ARGV[1]))
+    if current and current > tonumber(ARGV[2]) then
+        return 0
+    end
+end
+redis.call('DEL', KEYS[1])
+redis.call('HSET', KEYS[1], unpack(ARGV, 4))
+if tonumber(ARGV[3]) > 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[3])
+end
+return 1
+"#;
+
+/// Copia del estado actual en Redis: un hash `{prefijo}{device_id}` con la última
+/// posición de cada dispositivo, para que la API en tiempo real no consulte PostgreSQL
+#[derive(Clone)]
+pub struct RedisStateSink {
+    connection: ConnectionManager,
+    key_prefix: String,
+    ttl_secs: u64,
+    script: Script,
+}
+
+impl std::fmt::Debug for RedisStateSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisStateSink")
+            .field("key_prefix", &self.key_prefix)
+            .field("ttl_secs", &self.ttl_secs)
+            .finish()
+    }
+}
+
+impl RedisStateSink {
+    pub async fn new(config: &RedisStateConfig) -> Result<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+
+        info!(
+            "🧠 Estado actual replicado en Redis (prefijo: {}, TTL: {}s)",
+            config.key_prefix, config.ttl_secs
+        );
+
+        Ok(Self {
+            connection,
+            key_prefix: config.key_prefix.clone(),
+            ttl_secs: config.ttl_secs,
+            script: Script::new(UPSERT_SCRIPT),
+        })
+    }
+
+    /// Escribe la posición más nueva de cada dispositivo. Con `order` se respeta el
+    /// mismo criterio que el upsert de PostgreSQL: un mensaje atrasado no sobrescribe.
+    pub async fn write(
+        &self,
+        records: &[CommunicationRecord],
+        order: Option<CurrentStateOrder>,
+    ) -> Result<usize> {
+        let mut connection = self.connection.clone();
+        let mut written = 0;
+
+        for record in records {
+            let (order_field, order_value) = match order {
+                Some(order) => (
+                    order.column(),
+                    order_value(record, order)
+                        .map(|value| value.to_string())
+                        .unwrap_or_default(),
+                ),
+                None => ("", String::new()),
+            };
+
+            let mut invocation = self.script.prepare_invoke();
+            invocation
+                .key(format!("{}{}", self.key_prefix, record.device_id))
+                .arg(order_field)
+                .arg(order_value)
+                .arg(self.ttl_secs);
+            for (field, value) in hash_fields(record, order)? {
+                invocation.arg(field).arg(value);
+            }
+
+            let updated: i32 = invocation.invoke_async(&mut connection).await?;
+            written += updated as usize;
+        }
+
+        debug!("🧠 {} dispositivos actualizados en Redis", written);
+        Ok(written)
+    }
+}
+
+/// Campos no nulos del registro como texto; el campo de orden se guarda como epoch
+/// para poder compararlo en el script
+fn hash_fields(
+    record: &CommunicationRecord,
+    order: Option<CurrentStateOrder>,
+) -> Result<Vec<(String, String)>> {
+    let serde_json::Value::Object(map) = serde_json::to_value(record)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut fields: Vec<(String, String)> = map
+        .into_iter()
+        .filter_map(|(field, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(text) => Some((field, text)),
+            other => Some((field, other.to_string())),
+        })
+        .collect();
+
+    if let Some(order) = order {
+        if let Some(value) = order_value(record, order) {
+            fields.retain(|(field, _)| field != order.column());
+            fields.push((order.column().to_string(), value.to_string()));
+        }
+    }
+
+    Ok(fields)
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use tracing::{debug, info};
+
+use crate::config::RedisStreamConfig;
+use crate::models::NormalizedPosition;
+use crate::services::message_sink::MessageSink;
+
+/// Publica las posiciones procesadas en Redis Streams con `XADD`, recortando cada
+/// stream con `MAXLEN ~`. Alternativa liviana a Kafka para instalaciones chicas:
+/// los lectores usan `XREAD` o grupos de consumidores (`XREADGROUP`).
+///
+/// Cada entrada tiene `uuid`, `device_id`, `manufacturer` y `data` (la posición
+/// normalizada en JSON). Un lote va en un solo pipeline.
+#[derive(Clone)]
+pub struct RedisStreamSink {
+    connection: ConnectionManager,
+    key: String,
+    max_len: usize,
+}
+
+impl std::fmt::Debug for RedisStreamSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisStreamSink")
+            .field("key", &self.key)
+            .field("max_len", &self.max_len)
+            .finish()
+    }
+}
+
+impl RedisStreamSink {
+    pub async fn new(config: &RedisStreamConfig) -> Result<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+
+        info!(
+            "🌊 Posiciones publicadas en Redis Streams (clave: {}, MAXLEN ~{})",
+            config.key, config.max_len
+        );
+
+        Ok(Self {
+            connection,
+            key: config.key.clone(),
+            max_len: config.max_len,
+        })
+    }
+
+    /// Agrega una entrada por posición. Devuelve la cantidad agregada.
+    pub async fn publish(&self, positions: &[NormalizedPosition]) -> Result<usize> {
+        if positions.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        for position in positions {
+            let command = pipe.cmd("XADD").arg(self.key_for(position));
+            if self.max_len > 0 {
+                command.arg("MAXLEN").arg("~").arg(self.max_len);
+            }
+            command
+                .arg("*")
+                .arg("uuid")
+                .arg(&position.uuid)
+                .arg("device_id")
+                .arg(&position.device_id)
+                .arg("manufacturer")
+                .arg(position.manufacturer.as_str())
+                .arg("data")
+                .arg(serde_json::to_string(position)?)
+                .ignore();
+        }
+
+        let mut connection = self.connection.clone();
+        pipe.query_async::<()>(&mut connection).await?;
+
+        debug!(
+            "🌊 {} posiciones agregadas a Redis Streams",
+            positions.len()
+        );
+        Ok(positions.len())
+    }
+
+    /// Stream de una posición; sin tenant u origen se usa `default`
+    fn key_for(&self, position: &NormalizedPosition) -> String {
+        self.key
+            .replace("{tenant}", position.tenant.as_deref().unwrap_or("default"))
+            .replace("{source}", position.source.as_deref().unwrap_or("default"))
+            .replace("{manufacturer}", position.manufacturer.as_str())
+    }
+}
+
+#[async_trait]
+impl MessageSink for RedisStreamSink {
+    fn name(&self) -> &str {
+        "redis_stream"
+    }
+
+    async fn send(&self, positions: &[NormalizedPosition]) -> Result<()> {
+        self.publish(positions).await.map(|_| ())
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::config::{BrokerConfig, BrokerType};
+use crate::models::DeviceMessage;
+use crate::services::raw_decoder::RawDecoder;
+use crate::services::schema_registry::SchemaRegistryClient;
+use crate::services::{Backpressure, FieldMapping, KafkaConsumerService, PayloadAuthenticator};
+
+/// Tiempo máximo para consultas de metadata y offsets al broker
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tiempo sin mensajes tras el cual se da por terminado el replay
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reprocesa los mensajes de un topic dentro de un rango de timestamps
+pub struct ReplayService {
+    consumer: StreamConsumer,
+    topic: String,
+    schema_registry: Option<SchemaRegistryClient>,
+    raw_decoder: RawDecoder,
+    field_mapping: Option<Arc<FieldMapping>>,
+    authenticator: Option<Arc<PayloadAuthenticator>>,
+    topic_tenants: BTreeMap<String, String>,
+    backpressure: Backpressure,
+}
+
+impl ReplayService {
+    /// Crea un consumer con un group id propio para no mover los offsets del consumer principal
+    pub fn new(config: &BrokerConfig, backpressure: Backpressure) -> Result<Self> {
+        if config.broker_type != BrokerType::Kafka {
+            return Err(anyhow!(
+                "El replay lee tópicos Kafka y requiere BROKER_TYPE=kafka"
+            ));
+        }
+        let group_id = format!("{}-replay-{}", config.group_id, uuid::Uuid::new_v4());
+        let consumer: StreamConsumer = KafkaConsumerService::client_config(config)
+            .set("group.id", &group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+
+        let schema_registry = config
+            .schema_registry
+            .as_ref()
+            .map(SchemaRegistryClient::new)
+            .transpose()?;
+
+        Ok(Self {
+            consumer,
+            topic: config.topic.clone(),
+            schema_registry,
+            raw_decoder: RawDecoder::new(config.payload_format),
+            field_mapping: None,
+            authenticator: None,
+            topic_tenants: BTreeMap::new(),
+            backpressure,
+        })
+    }
+
+    /// Aplica las mismas reglas de mapeo que el consumer principal
+    pub fn with_field_mapping(mut self, mapping: Arc<FieldMapping>) -> Self {
+        self.field_mapping = Some(mapping);
+        self
+    }
+
+    /// Verifica las firmas igual que el consumer principal: lo que este descartó
+    /// tampoco se reprocesa
+    pub fn with_authenticator(mut self, authenticator: Arc<PayloadAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Asigna el tenant del tópico (`TENANT_TOPICS`) y exige que la firma sea de
+    /// ese tenant, como el consumer principal
+    pub fn with_topic_tenants(mut self, topics: &BTreeMap<String, String>) -> Self {
+        self.topic_tenants = topics.clone();
+        self
+    }
+
+    /// Posiciona el consumer en `from` y envía al canal los mensajes anteriores a `to`.
+    /// Devuelve la cantidad de mensajes reenviados.
+    pub async fn run(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        tx: mpsc::UnboundedSender<DeviceMessage>,
+    ) -> Result<u64> {
+        // Partición -> high-watermark al iniciar: el replay no lee más allá
+        let mut pending = tokio::task::block_in_place(|| self.assign_from(from))?;
+        if pending.is_empty() {
+            info!("⏪ No hay mensajes en {} desde {}", self.topic, from);
+            return Ok(0);
+        }
+
+        info!(
+            "⏪ Replay de {} particiones de {} en curso",
+            pending.len(),
+            self.topic
+        );
+
+        let mut replayed = 0;
+        let mut pressure = self.backpressure.subscribe();
+
+        while !pending.is_empty() {
+            // Esperar a que el procesador libere su cola
+            pressure.wait_for(|under_pressure| !under_pressure).await?;
+
+            let message = match tokio::time::timeout(IDLE_TIMEOUT, self.consumer.recv()).await {
+                Ok(Ok(message)) => message,
+                Ok(Err(e)) => {
+                    error!("Error recibiendo mensaje de Kafka: {}", e);
+                    continue;
+                }
+                Err(_) => {
+                    warn!(
+                        "⚠️ Sin mensajes durante {:?}, terminando replay con {} particiones pendientes",
+                        IDLE_TIMEOUT,
+                        pending.len()
+                    );
+                    break;
+                }
+            };
+
+            let partition = message.partition();
+            let Some(&high_watermark) = pending.get(&partition) else {
+                continue;
+            };
+
+            let within_range = match message.timestamp().to_millis() {
+                Some(timestamp) => timestamp < to.timestamp_millis(),
+                None => true,
+            };
+
+            if within_range {
+                let topic_tenant = self.topic_tenants.get(&self.topic);
+                let mut signed_by = None;
+                let verified = match (&self.authenticator, message.payload()) {
+                    (Some(authenticator), Some(payload)) => {
+                        match authenticator.verify_kafka(
+                            message.headers(),
+                            topic_tenant.map(String::as_str),
+                            payload,
+                        ) {
+                            Ok(key_id) => {
+                                signed_by = Some(key_id);
+                                true
+                            }
+                            Err(e) => {
+                                warn!("🔏 Mensaje descartado: {}", e);
+                                false
+                            }
+                        }
+                    }
+                    _ => true,
+                };
+                if let Some(payload) = message.payload().filter(|_| verified) {
+                    match KafkaConsumerService::decode_payload(
+                        self.schema_registry.as_ref(),
+                        self.field_mapping.as_deref(),
+                        &self.raw_decoder,
+                        payload,
+                    )
+                    .await
+                    {
+                        Ok(mut device_msg) => {
+                            device_msg.tenant = topic_tenant.cloned();
+                            device_msg.signed_by = signed_by;
+                            tx.send(device_msg)
+                                .map_err(|_| anyhow!("Canal del procesador cerrado"))?;
+                            replayed += 1;
+                        }
+                        Err(e) => error!("❌ {:#}", e),
+                    }
+                }
+            }
+
+            // La partición termina al superar `to` o al alcanzar el high-watermark inicial
+            if !within_range || message.offset() + 1 >= high_watermark {
+                pending.remove(&partition);
+                let mut finished = TopicPartitionList::new();
+                finished.add_partition(&self.topic, partition);
+                self.consumer.pause(&finished)?;
+                info!(
+                    "⏪ Partición {} completada ({} mensajes reprocesados hasta ahora)",
+                    partition, replayed
+                );
+            }
+        }
+
+        Ok(replayed)
+    }
+
+    /// Asigna cada partición al primer offset con timestamp >= `from`
+    fn assign_from(&self, from: DateTime<Utc>) -> Result<HashMap<i32, i64>> {
+        let metadata = self
+            .consumer
+            .fetch_metadata(Some(&self.topic), QUERY_TIMEOUT)?;
+        let topic = metadata
+            .topics()
+            .iter()
+            .find(|topic| topic.name() == self.topic)
+            .ok_or_else(|| anyhow!("Topic {} no encontrado", self.topic))?;
+
+        let mut timestamps = TopicPartitionList::new();
+        for partition in topic.partitions() {
+            timestamps.add_partition_offset(
+                &self.topic,
+                partition.id(),
+                Offset::Offset(from.timestamp_millis()),
+            )?;
+        }
+
+        let offsets = self.consumer.offsets_for_times(timestamps, QUERY_TIMEOUT)?;
+
+        let mut pending = HashMap::new();
+        let mut assignment = TopicPartitionList::new();
+        for element in offsets.elements() {
+            // Offset::End indica que no hay mensajes posteriores a `from`
+            let Offset::Offset(offset) = element.offset() else {
+                continue;
+            };
+
+            let (_, high) =
+                self.consumer
+                    .fetch_watermarks(&self.topic, element.partition(), QUERY_TIMEOUT)?;
+            if offset < high {
+                pending.insert(element.partition(), high);
+                assignment.add_partition_offset(
+                    &self.topic,
+                    element.partition(),
+                    Offset::Offset(offset),
+                )?;
+            }
+        }
+
+        self.consumer.assign(&assignment)?;
+        Ok(pending)
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::config::SchemaRegistryConfig;
+
+/// Magic byte con el que Confluent antepone los payloads serializados con schema
+const MAGIC_BYTE: u8 = 0;
+
+/// Tamaño del encabezado: magic byte + schema id (u32 big endian)
+const HEADER_LEN: usize = 5;
+
+/// Tipos de schema que puede devolver el registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaType {
+    Protobuf,
+    Avro,
+    Json,
+}
+
+/// Schema registrado obtenido del registry
+#[derive(Debug, Clone)]
+pub struct RegisteredSchema {
+    pub id: u32,
+    pub schema_type: SchemaType,
+}
+
+#[derive(Debug, Deserialize)]
+struct SchemaResponse {
+    schema: String,
+    // El registry omite el campo cuando el schema es Avro
+    #[serde(rename = "schemaType", default)]
+    schema_type: Option<String>,
+}
+
+/// El registry no respondió o respondió con un error. El payload podrá
+/// decodificarse cuando vuelva, así que el mensaje no debe descartarse.
+#[derive(Debug, thiserror::Error)]
+#[error("Schema Registry no disponible al consultar el schema {schema_id}: {detail}")]
+pub struct RegistryUnavailable {
+    schema_id: u32,
+    detail: String,
+}
+
+/// Si el error viene de una consulta fallida al registry y vale la pena reintentar
+pub fn is_unavailable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<RegistryUnavailable>())
+}
+
+/// Cliente de Confluent Schema Registry con caché de schemas por id
+#[derive(Clone)]
+pub struct SchemaRegistryClient {
+    http: reqwest::Client,
+    base_url: String,
+    username: Option<String>,
+    password: Option<String>,
+    cache: Arc<RwLock<HashMap<u32, RegisteredSchema>>>,
+}
+
+impl SchemaRegistryClient {
+    pub fn new(config: &SchemaRegistryConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        info!("📚 Schema Registry configurado en: {}", config.url);
+
+        Ok(Self {
+            http,
+            base_url: config.url.trim_end_matches('/').to_string(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Obtiene un schema por id, consultando el registry solo la primera vez
+    pub async fn get_schema(&self, id: u32) -> Result<RegisteredSchema> {
+        if let Some(schema) = self.cache.read().await.get(&id) {
+            return Ok(schema.clone());
+        }
+
+        let url = format!("{}/schemas/ids/{}", self.base_url, id);
+        let mut request = self.http.get(&url);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let unavailable = |e: reqwest::Error| RegistryUnavailable {
+            schema_id: id,
+            detail: e.to_string(),
+        };
+        let response = request.send().await.map_err(unavailable)?;
+        // Un id inexistente no aparecerá reintentando
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow!("Schema {} no existe en el registry", id));
+        }
+        let response: SchemaResponse = response
+            .error_for_status()
+            .map_err(unavailable)?
+            .json()
+            .await
+            .map_err(unavailable)?;
+
+        let schema_type = match response.schema_type.as_deref() {
+            None | Some("AVRO") => SchemaType::Avro,
+            Some("PROTOBUF") => SchemaType::Protobuf,
+            Some("JSON") => SchemaType::Json,
+            Some(other) => return Err(anyhow!("Tipo de schema desconocido: {}", other)),
+        };
+
+        if schema_type == SchemaType::Protobuf && !response.schema.contains("KafkaMessage") {
+            warn!(
+                "⚠️ Schema {} no define KafkaMessage; el payload podría no ser compatible",
+                id
+            );
+        }
+
+        let schema = RegisteredSchema { id, schema_type };
+
+        info!("📚 Schema {} obtenido del registry ({:?})", id, schema_type);
+        self.cache.write().await.insert(id, schema.clone());
+
+        Ok(schema)
+    }
+}
+
+/// Lee el encabezado de Confluent y devuelve el schema id y el resto del payload.
+///
+/// Un `KafkaMessage` protobuf sin framing nunca empieza con 0x00 (el primer byte es
+/// siempre un tag de campo), por lo que la detección no es ambigua.
+pub fn split_framing(payload: &[u8]) -> Option<(u32, &[u8])> {
+    if payload.len() < HEADER_LEN || payload[0] != MAGIC_BYTE {
+        return None;
+    }
+
+    let schema_id = u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
+    Some((schema_id, &payload[HEADER_LEN..]))
+}
+
+/// Salta los message-indexes que Confluent agrega a los payloads protobuf
+fn skip_message_indexes(body: &[u8]) -> Result<&[u8]> {
+    let (count, mut rest) = read_zigzag_varint(body)?;
+    // Un único 0 es la forma corta de [0] (primer mensaje del schema)
+    for _ in 0..count.max(0) {
+        let (_, next) = read_zigzag_varint(rest)?;
+        rest = next;
+    }
+    Ok(rest)
+}
+
+fn read_zigzag_varint(buf: &[u8]) -> Result<(i64, &[u8])> {
+    let mut value: u64 = 0;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            let decoded = ((value >> 1) as i64) ^ -((value & 1) as i64);
+            return Ok((decoded, &buf[i + 1..]));
+        }
+    }
+    Err(anyhow!(
+        "Varint inválido en message-indexes de Schema Registry"
+    ))
+}
+
+/// Devuelve el cuerpo protobuf de un payload, quitando el framing de Schema Registry
+/// si está presente. Sin registry configurado se asume que el schema es protobuf.
+pub async fn unwrap_payload<'a>(
+    registry: Option<&SchemaRegistryClient>,
+    payload: &'a [u8],
+) -> Result<&'a [u8]> {
+    let Some((schema_id, body)) = split_framing(payload) else {
+        return Ok(payload);
+    };
+
+    if let Some(registry) = registry {
+        let schema = registry.get_schema(schema_id).await?;
+        if schema.schema_type != SchemaType::Protobuf {
+            return Err(anyhow!(
+                "Schema {} es {:?}; solo se soportan payloads protobuf",
+                schema.id,
+                schema.schema_type
+            ));
+        }
+    } else {
+        debug!(
+            "Payload con framing de Schema Registry (schema {}) sin registry configurado",
+            schema_id
+        );
+    }
+
+    skip_message_indexes(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing_is_magic_byte_and_big_endian_schema_id() {
+        assert_eq!(
+            split_framing(&[0, 0, 0, 1, 2, 0x0a, 0x01]),
+            Some((258, &[0x0a, 0x01][..]))
+        );
+        assert_eq!(split_framing(&[0, 0, 0, 0, 7]), Some((7, &[][..])));
+        // Protobuf sin framing (empieza con un tag) y encabezado incompleto
+        assert_eq!(split_framing(&[0x0a, 0, 0, 0, 1, 2]), None);
+        assert_eq!(split_framing(&[0, 0, 0, 1]), None);
+    }
+
+    #[test]
+    fn message_indexes_are_zigzag_varints() {
+        // Un único 0: primer mensaje del schema
+        assert_eq!(skip_message_indexes(&[0x00, 0x0a]).unwrap(), &[0x0a]);
+        // [1, 2]: cantidad 2 (zigzag 4), índices 1 (2) y 2 (4)
+        assert_eq!(
+            skip_message_indexes(&[0x04, 0x02, 0x04, 0x0a]).unwrap(),
+            &[0x0a]
+        );
+        // [64]: el índice ocupa dos bytes (zigzag 128)
+        assert_eq!(
+            skip_message_indexes(&[0x02, 0x80, 0x01, 0x0a]).unwrap(),
+            &[0x0a]
+        );
+        assert_eq!(read_zigzag_varint(&[0x01]).unwrap().0, -1);
+        assert_eq!(read_zigzag_varint(&[0x03]).unwrap().0, -2);
+    }
+
+    #[test]
+    fn truncated_message_indexes_are_rejected() {
+        assert!(skip_message_indexes(&[]).is_err());
+        // Varint sin su último byte
+        assert!(skip_message_indexes(&[0x80]).is_err());
+        // Anuncia dos índices y trae uno
+        assert!(skip_message_indexes(&[0x04, 0x02]).is_err());
+        assert!(read_zigzag_varint(&[0xff; 11]).is_err());
+    }
+
+    #[tokio::test]
+    async fn framed_payload_without_registry_yields_the_protobuf_body() {
+        let payload = [0, 0, 0, 0, 9, 0x00, 0x0a, 0x01];
+        assert_eq!(unwrap_payload(None, &payload).await.unwrap(), &[0x0a, 0x01]);
+        // Sin framing el payload se devuelve tal cual
+        assert_eq!(
+            unwrap_payload(None, &[0x0a, 0x01]).await.unwrap(),
+            &[0x0a, 0x01]
+        );
+    }
+
+    #[test]
+    fn only_registry_lookup_failures_are_retryable() {
+        let unavailable = anyhow::Error::new(RegistryUnavailable {
+            schema_id: 9,
+            detail: "connection refused".to_string(),
+        })
+        .context("Error resolviendo schema del mensaje");
+        assert!(is_unavailable(&unavailable));
+        assert!(!is_unavailable(&anyhow!(
+            "Schema 9 no existe en el registry"
+        )));
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::Result;
+use rdkafka::message::OwnedHeaders;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use crate::config::{BrokerConfig, ShardConfig};
+use crate::models::DeviceMessage;
+use crate::redact;
+use crate::services::{KafkaConsumerService, PayloadAuthenticator};
+
+/// Tiempo máximo esperando espacio en la cola del producer
+const ENQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Espera entre reintentos de una republicación fallida, duplicada en cada intento
+const REPUBLISH_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+const REPUBLISH_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// FNV-1a de 64 bits
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Shard dueño de un dispositivo. Usa FNV-1a sobre los bytes del device_id para que
+/// todas las instancias (y otras herramientas) lo calculen igual, sin depender de
+/// la versión de Rust como `DefaultHasher`.
+pub fn shard_of(device_id: &str, count: u32) -> u32 {
+    let hash = device_id.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    (hash % count.max(1) as u64) as u32
+}
+
+/// Deja pasar solo los mensajes de los dispositivos de este shard. Los demás se
+/// descartan (otra instancia los recibe del mismo broker) o se republican en el
+/// tópico del shard dueño.
+pub struct ShardFilter {
+    config: ShardConfig,
+    // None = descartar los mensajes de otros shards
+    producer: Option<FutureProducer>,
+    // Firma los mensajes republicados para que el shard dueño los acepte
+    authenticator: Option<Arc<PayloadAuthenticator>>,
+    skipped: AtomicU64,
+    republished: AtomicU64,
+    republish_errors: AtomicU64,
+}
+
+/// Mensajes de otros shards desde el arranque
+#[derive(Debug, Clone, Serialize)]
+pub struct ShardStatistics {
+    pub index: u32,
+    pub count: u32,
+    pub skipped: u64,
+    pub republished: u64,
+    pub republish_errors: u64,
+}
+
+impl ShardFilter {
+    pub fn new(config: ShardConfig, broker: &BrokerConfig) -> Result<Self> {
+        let producer = match &config.republish_topic {
+            Some(topic) => {
+                info!(
+                    "🧩 Shard {}/{}: los mensajes de otros shards se republican en {}",
+                    config.index, config.count, topic
+                );
+                Some(
+                    KafkaConsumerService::client_config(broker)
+                        .set("acks", "all")
+                        .set("linger.ms", "5")
+                        .create()?,
+                )
+            }
+            None => {
+                info!(
+                    "🧩 Shard {}/{}: los mensajes de otros shards se descartan",
+                    config.index, config.count
+                );
+                None
+            }
+        };
+        Ok(Self {
+            config,
+            producer,
+            authenticator: None,
+            skipped: AtomicU64::new(0),
+            republished: AtomicU64::new(0),
+            republish_errors: AtomicU64::new(0),
+        })
+    }
+
+    /// Firma cada mensaje republicado con la clave del tenant que firmó el original
+    pub fn with_authenticator(mut self, authenticator: Arc<PayloadAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Si el dispositivo del mensaje pertenece a este shard
+    pub fn owns(&self, message: &DeviceMessage) -> bool {
+        shard_of(&message.data.device_id, self.config.count) == self.config.index
+    }
+
+    /// Entrega un mensaje de otro shard: lo republica como `DeviceMessage` JSON, con
+    /// el device_id como clave, o lo descarta. Un error de publicación se reintenta
+    /// con backoff hasta que el broker la confirma; quien llama decide cuándo dejar
+    /// de esperar (por ejemplo, al detener el consumo sin guardar el offset).
+    pub async fn hand_off(&self, message: DeviceMessage) {
+        let Some(producer) = &self.producer else {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let shard = shard_of(&message.data.device_id, self.config.count);
+        let topic = self
+            .config
+            .republish_topic
+            .as_deref()
+            .unwrap_or_default()
+            .replace("{shard}", &shard.to_string());
+        let payload = match serde_json::to_vec(&message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                self.republish_errors.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "❌ Error serializando el mensaje {} para {}: {}",
+                    message.uuid, topic, e
+                );
+                return;
+            }
+        };
+        let headers = match &self.authenticator {
+            Some(authenticator) => {
+                let key_id = message.signed_by.as_ref().or(message.tenant.as_ref());
+                match key_id.and_then(|key_id| authenticator.sign_kafka(key_id, &payload)) {
+                    Some(headers) => Some(headers),
+                    None => {
+                        // El shard dueño lo rechazaría sin firma
+                        self.republish_errors.fetch_add(1, Ordering::Relaxed);
+                        error!(
+                            "❌ Mensaje {} no republicado en {}: no hay clave para firmarlo",
+                            message.uuid, topic
+                        );
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let mut delay = REPUBLISH_RETRY_MIN_DELAY;
+        loop {
+            let mut record = FutureRecord::to(&topic)
+                .key(&message.data.device_id)
+                .payload(&payload);
+            if let Some(headers) = &headers {
+                record = record.headers(OwnedHeaders::clone(headers));
+            }
+            match producer.send(record, ENQUEUE_TIMEOUT).await {
+                Ok(_) => {
+                    self.republished.fetch_add(1, Ordering::Relaxed);
+                    debug!(
+                        "🧩 Mensaje {} del dispositivo {} republicado en {}",
+                        message.uuid,
+                        redact::device_id(&message.data.device_id),
+                        topic
+                    );
+                    return;
+                }
+                Err((e, _)) => {
+                    self.republish_errors.fetch_add(1, Ordering::Relaxed);
+                    error!(
+                        "❌ Error republicando el mensaje {} en {}, reintento en {:?}: {}",
+                        message.uuid, topic, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(REPUBLISH_RETRY_MAX_DELAY);
+                }
+            }
+        }
+    }
+
+    /// Grupo de consumo del broker para este shard. Sin tópico de republicación cada
+    /// shard descarta lo ajeno, así que necesita su propio grupo para recibir todas
+    /// las particiones; con republicación el grupo se comparte y cada mensaje llega
+    /// a una sola instancia.
+    pub fn consumer_group(&self, group_id: &str) -> String {
+        match &self.config.republish_topic {
+            Some(_) => group_id.to_string(),
+            None => format!("{}-shard-{}", group_id, self.config.index),
+        }
+    }
+
+    pub fn statistics(&self) -> ShardStatistics {
+        ShardStatistics {
+            index: self.config.index,
+            count: self.config.count,
+            skipped: self.skipped.load(Ordering::Relaxed),
+            republished: self.republished.load(Ordering::Relaxed),
+            republish_errors: self.republish_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    fn filter(republish_topic: Option<&str>) -> ShardFilter {
+        let config = ShardConfig {
+            index: 2,
+            count: 4,
+            republish_topic: republish_topic.map(str::to_string),
+        };
+        ShardFilter::new(config, &AppConfig::default_dev().broker).unwrap()
+    }
+
+    #[test]
+    fn dropping_shards_use_their_own_consumer_group() {
+        // Cada shard debe leer todas las particiones para no perder los suyos
+        assert_eq!(filter(None).consumer_group("siscom"), "siscom-shard-2");
+        // Con republicación el grupo se comparte: cada mensaje llega a una instancia
+        assert_eq!(
+            filter(Some("positions.shard.{shard}")).consumer_group("siscom"),
+            "siscom"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use sqlx::Connection;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::config::StartupConfig;
+use crate::services::database::is_transient_error;
+
+/// Tiempo máximo de cada intento de conexión
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Espera al arrancar a que PostgreSQL y los brokers acepten conexiones, reintentando
+/// con backoff exponencial hasta `STARTUP_MAX_WAIT_SECS` por dependencia. Así el
+/// consumer puede iniciar junto con sus dependencias (docker-compose) en lugar de
+/// fallar al primer intento. Solo comprueba que respondan: las credenciales y el
+/// resto de la configuración se validan al crear cada servicio.
+pub struct DependencyWait {
+    config: StartupConfig,
+}
+
+impl DependencyWait {
+    pub fn new(config: StartupConfig) -> Self {
+        Self { config }
+    }
+
+    /// Espera a que alguna de las URLs de PostgreSQL acepte una conexión. Los errores
+    /// que no son transitorios (p. ej. credenciales) terminan la espera.
+    pub async fn postgres(&self, urls: &[String]) -> Result<()> {
+        self.wait_for("PostgreSQL", is_transient_error, || async {
+            let mut last_error = anyhow!("sin URLs de PostgreSQL");
+            for url in urls {
+                match sqlx::PgConnection::connect(url).await {
+                    Ok(connection) => {
+                        let _ = connection.close().await;
+                        return Ok(());
+                    }
+                    Err(e) => last_error = e.into(),
+                }
+            }
+            Err(last_error)
+        })
+        .await
+    }
+
+    /// Espera a que alguna de las direcciones `host:puerto` acepte una conexión TCP
+    pub async fn tcp(&self, name: &str, addresses: &[String]) -> Result<()> {
+        self.wait_for(
+            name,
+            |_| true,
+            || async {
+                let mut last_error = anyhow!("sin direcciones");
+                for address in addresses {
+                    match TcpStream::connect(address.as_str()).await {
+                        Ok(_) => return Ok(()),
+                        Err(e) => last_error = anyhow!("{}: {}", address, e),
+                    }
+                }
+                Err(last_error)
+            },
+        )
+        .await
+    }
+
+    /// Reintenta `probe` hasta que responde, falla con un error que `retryable`
+    /// descarta o vence la espera máxima; devuelve el último error
+    async fn wait_for<F, Fut>(
+        &self,
+        name: &str,
+        retryable: fn(&anyhow::Error) -> bool,
+        mut probe: F,
+    ) -> Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(self.config.max_wait_secs);
+        let max_backoff = Duration::from_millis(self.config.max_backoff_ms);
+        let mut backoff = Duration::from_millis(self.config.initial_backoff_ms).min(max_backoff);
+        let mut attempt = 1;
+        loop {
+            let (error, retry) = match tokio::time::timeout(PROBE_TIMEOUT, probe()).await {
+                Ok(Ok(())) => {
+                    if attempt > 1 {
+                        info!("✅ {} disponible tras {} intentos", name, attempt);
+                    }
+                    return Ok(());
+                }
+                Ok(Err(e)) => {
+                    let retry = retryable(&e);
+                    (e, retry)
+                }
+                Err(_) => (anyhow!("sin respuesta en {:?}", PROBE_TIMEOUT), true),
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !retry || remaining.is_zero() {
+                return Err(error.context(format!("{} no disponible", name)));
+            }
+            warn!(
+                "⏳ {} no disponible (intento {}): {:#}; reintentando en {:?}",
+                name, attempt, error, backoff
+            );
+            tokio::time::sleep(backoff.min(remaining)).await;
+            backoff = (backoff * 2).min(max_backoff);
+            attempt += 1;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::task::JoinError;
+use tracing::{error, info, warn};
+
+use crate::config::SupervisorConfig;
+
+/// Reinicios de una tarea desde la última vez que estuvo estable
+struct TaskState {
+    restarts: u32,
+    started: Instant,
+}
+
+/// Decide si una tarea principal que terminó se reinicia y tras cuánto tiempo.
+///
+/// Cada reinicio seguido duplica la espera (hasta `max_backoff_ms`); una tarea que
+/// corre más de `stable_secs` vuelve a empezar la cuenta. Tras `max_restarts`
+/// reinicios seguidos la falla se escala y el servicio se apaga.
+pub struct Supervisor {
+    config: SupervisorConfig,
+    tasks: HashMap<&'static str, TaskState>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Registra el (re)inicio de una tarea
+    pub fn started(&mut self, task: &'static str) {
+        self.tasks
+            .entry(task)
+            .and_modify(|state| state.started = Instant::now())
+            .or_insert(TaskState {
+                restarts: 0,
+                started: Instant::now(),
+            });
+    }
+
+    /// Registra el fin inesperado de una tarea. Devuelve la espera antes de
+    /// reiniciarla, o None si agotó los reinicios y hay que apagar el servicio.
+    pub fn on_exit(
+        &mut self,
+        task: &'static str,
+        outcome: Result<Result<()>, JoinError>,
+    ) -> Option<Duration> {
+        match outcome {
+            Ok(Ok(())) => warn!("⚙️ Tarea '{}' terminó inesperadamente", task),
+            Ok(Err(e)) => error!("❌ Tarea '{}' terminó con error: {:#}", task, e),
+            Err(e) if e.is_panic() => error!("💥 Tarea '{}' entró en pánico: {}", task, e),
+            Err(e) => error!("❌ Tarea '{}' cancelada: {}", task, e),
+        }
+
+        let stable = Duration::from_secs(self.config.stable_secs);
+        let state = self.tasks.entry(task).or_insert(TaskState {
+            restarts: 0,
+            started: Instant::now(),
+        });
+        if state.started.elapsed() >= stable {
+            state.restarts = 0;
+        }
+
+        if state.restarts >= self.config.max_restarts {
+            error!(
+                "🛑 Tarea '{}' falló {} veces seguidas, apagando el servicio",
+                task,
+                state.restarts + 1
+            );
+            return None;
+        }
+
+        let backoff = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul(1u64 << state.restarts.min(20))
+            .min(self.config.max_backoff_ms);
+        state.restarts += 1;
+        info!(
+            "🔁 Reiniciando tarea '{}' en {}ms (reinicio {}/{})",
+            task, backoff, state.restarts, self.config.max_restarts
+        );
+        Some(Duration::from_millis(backoff))
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap};
+use tracing::info;
+
+use crate::config::{is_sql_identifier, TenantRoutingConfig, TenantSource};
+use crate::models::DeviceMessage;
+use crate::services::payload_auth::AuthFailure;
+
+/// Lee un archivo con un `device_id,tenant` por línea (`#` para comentarios)
+pub fn load_tenant_map(path: &str) -> Result<HashMap<String, String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("No se pudo leer el mapa de tenants {}: {}", path, e))?;
+
+    let mut tenants = HashMap::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (device_id, tenant) = line
+            .split_once(',')
+            .ok_or_else(|| anyhow!("Línea {} inválida en {}: '{}'", number + 1, path, line))?;
+        tenants.insert(device_id.trim().to_string(), tenant.trim().to_string());
+    }
+
+    info!(
+        "🏷️ {} dispositivos con tenant cargados de {}",
+        tenants.len(),
+        path
+    );
+    Ok(tenants)
+}
+
+/// Resuelve el tenant de cada mensaje consultando las fuentes configuradas en orden
+pub struct TenantRouter {
+    sources: Vec<TenantSource>,
+    // Ordenados del prefijo más largo al más corto
+    device_prefixes: Vec<(String, String)>,
+    devices: HashMap<String, String>,
+    tenants: BTreeSet<String>,
+}
+
+impl TenantRouter {
+    /// Falla si algún tenant de la configuración o del mapa no es un identificador SQL
+    pub fn from_config(config: &TenantRoutingConfig) -> Result<Self> {
+        let devices = match &config.map_file {
+            Some(path) if config.sources.contains(&TenantSource::Lookup) => load_tenant_map(path)?,
+            _ => HashMap::new(),
+        };
+
+        let mut device_prefixes: Vec<(String, String)> = config
+            .device_prefixes
+            .iter()
+            .map(|(prefix, tenant)| (prefix.clone(), tenant.clone()))
+            .collect();
+        device_prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        let tenants: BTreeSet<String> = config
+            .topics
+            .values()
+            .chain(config.device_prefixes.values())
+            .chain(devices.values())
+            .cloned()
+            .collect();
+        // El tenant reemplaza {tenant} en los nombres de tablas y esquemas del SQL
+        if let Some(tenant) = tenants.iter().find(|tenant| !is_sql_identifier(tenant)) {
+            return Err(anyhow!(
+                "Tenant inválido '{}': solo se admiten letras, números y '_'",
+                tenant
+            ));
+        }
+        info!(
+            "🏢 Enrutamiento por tenant ({:?}): {} tenants",
+            config.sources,
+            tenants.len()
+        );
+
+        Ok(Self {
+            sources: config.sources.clone(),
+            device_prefixes,
+            devices,
+            tenants,
+        })
+    }
+
+    /// Todos los tenants a los que puede resolver un mensaje
+    pub fn tenants(&self) -> &BTreeSet<String> {
+        &self.tenants
+    }
+
+    /// Tenant del mensaje, o None si ninguna fuente lo resuelve
+    pub fn resolve<'a>(&'a self, message: &'a DeviceMessage) -> Option<&'a str> {
+        let device_id = message.data.device_id.as_str();
+        self.sources.iter().find_map(|source| match source {
+            TenantSource::Topic => message.tenant.as_deref(),
+            TenantSource::DevicePrefix => self
+                .device_prefixes
+                .iter()
+                .find(|(prefix, _)| device_id.starts_with(prefix.as_str()))
+                .map(|(_, tenant)| tenant.as_str()),
+            TenantSource::Lookup => self.devices.get(device_id).map(String::as_str),
+        })
+    }
+
+    /// Como `resolve`, pero un mensaje firmado solo puede ir al tenant de su clave:
+    /// en un tópico compartido la clave de un tenant no sirve para escribir en los
+    /// carriles de otro (por prefijo o mapa de dispositivos) ni en los principales
+    pub fn resolve_signed<'a>(
+        &'a self,
+        message: &'a DeviceMessage,
+    ) -> Result<Option<&'a str>, AuthFailure> {
+        let tenant = self.resolve(message);
+        match &message.signed_by {
+            Some(key_id) if tenant != Some(key_id.as_str()) => Err(AuthFailure::WrongTenant {
+                key_id: key_id.clone(),
+                tenant: tenant.map(str::to_string),
+            }),
+            _ => Ok(tenant),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    /// Mapa de tenants en un archivo temporal propio del test
+    fn map_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "siscom-tenants-{}-{}.csv",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn routing(sources: Vec<TenantSource>) -> TenantRoutingConfig {
+        TenantRoutingConfig {
+            sources,
+            topics: BTreeMap::from([("positions.acme".to_string(), "acme".to_string())]),
+            device_prefixes: BTreeMap::from([("GLX".to_string(), "globex".to_string())]),
+            map_file: None,
+            lanes_per_tenant: 1,
+        }
+    }
+
+    #[test]
+    fn valid_tenants_are_collected_from_every_source() {
+        let map = map_file("valid", "# device_id,tenant\n8640001,initech\n");
+        let mut config = routing(vec![TenantSource::Topic, TenantSource::Lookup]);
+        config.map_file = Some(map.to_string_lossy().into_owned());
+
+        let router = TenantRouter::from_config(&config).unwrap();
+        std::fs::remove_file(map).unwrap();
+        assert_eq!(
+            router
+                .tenants()
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            vec!["acme", "globex", "initech"]
+        );
+    }
+
+    #[test]
+    fn tenants_that_are_not_sql_identifiers_are_rejected() {
+        // Por tópico o por prefijo
+        let mut config = routing(vec![TenantSource::Topic]);
+        config
+            .topics
+            .insert("positions.evil".to_string(), "x;drop table y".to_string());
+        assert!(TenantRouter::from_config(&config).is_err());
+
+        let mut config = routing(vec![TenantSource::DevicePrefix]);
+        config
+            .device_prefixes
+            .insert("EVL".to_string(), "evil-corp".to_string());
+        assert!(TenantRouter::from_config(&config).is_err());
+
+        // En el mapa de dispositivos
+        let map = map_file("invalid", "8640001,x;drop table communications_suntech\n");
+        let mut config = routing(vec![TenantSource::Lookup]);
+        config.map_file = Some(map.to_string_lossy().into_owned());
+        let error = TenantRouter::from_config(&config).err().unwrap();
+        std::fs::remove_file(map).unwrap();
+        assert!(error.to_string().contains("Tenant inválido"));
+    }
+
+    fn message(device_id: &str, tenant: Option<&str>, signed_by: Option<&str>) -> DeviceMessage {
+        let mut message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        message.data.device_id = device_id.to_string();
+        message.tenant = tenant.map(str::to_string);
+        message.signed_by = signed_by.map(str::to_string);
+        message
+    }
+
+    #[test]
+    fn signed_messages_only_reach_the_signing_tenant() {
+        let router = TenantRouter::from_config(&routing(vec![
+            TenantSource::DevicePrefix,
+            TenantSource::Topic,
+        ]))
+        .unwrap();
+
+        // Sin firma (verificación desactivada) se enruta como siempre
+        assert_eq!(
+            router.resolve_signed(&message("GLX001", None, None)),
+            Ok(Some("globex"))
+        );
+        assert_eq!(
+            router.resolve_signed(&message("GLX001", None, Some("globex"))),
+            Ok(Some("globex"))
+        );
+        assert_eq!(
+            router.resolve_signed(&message("ACM001", Some("acme"), Some("acme"))),
+            Ok(Some("acme"))
+        );
+
+        // La clave de acme no sirve para un dispositivo de globex, aunque el tópico
+        // sea de acme, ni para los carriles principales
+        assert_eq!(
+            router.resolve_signed(&message("GLX001", Some("acme"), Some("acme"))),
+            Err(AuthFailure::WrongTenant {
+                key_id: "acme".to_string(),
+                tenant: Some("globex".to_string()),
+            })
+        );
+        assert_eq!(
+            router.resolve_signed(&message("8640001", None, Some("acme"))),
+            Err(AuthFailure::WrongTenant {
+                key_id: "acme".to_string(),
+                tenant: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info};
+
+use crate::config::TripConfig;
+use crate::models::{EngineStatus, NormalizedPosition, Trip, TripEvent, TripPoint, TripTransition};
+use crate::redact;
+use crate::services::notifications::NotificationPublisher;
+use crate::services::DatabaseService;
+
+/// Viaje abierto de un dispositivo
+struct OpenTrip {
+    trip: Trip,
+    // La última posición iba en movimiento: el tramo hasta la siguiente suma distancia
+    moving: bool,
+    // Primera posición detenida desde que el vehículo dejó de moverse
+    stopped_at: Option<TripPoint>,
+}
+
+/// Segmenta viajes a partir de la ignición y la velocidad de cada dispositivo.
+///
+/// Un viaje empieza con la primera posición en movimiento (velocidad de al menos
+/// `start_speed_kmh` sin ignición apagada) y termina al apagar la ignición o tras
+/// `stop_secs` detenido; en ese caso el fin es la posición en la que se detuvo. Los
+/// viajes se guardan en `trips` (los abiertos se actualizan con cada lote) y el
+/// inicio y el fin se publican en el tópico de notificaciones.
+pub struct TripDetector {
+    config: TripConfig,
+    database: Arc<DatabaseService>,
+    notifications: Option<NotificationPublisher>,
+    open: Mutex<HashMap<String, OpenTrip>>,
+    events: AtomicU64,
+}
+
+impl TripDetector {
+    /// Retoma los viajes que quedaron abiertos en `trips`
+    pub async fn new(
+        config: TripConfig,
+        database: Arc<DatabaseService>,
+        notifications: Option<NotificationPublisher>,
+    ) -> Result<Self> {
+        let open: HashMap<String, OpenTrip> = database
+            .open_trips()
+            .await?
+            .into_iter()
+            .map(|trip| {
+                (
+                    trip.device_id.clone(),
+                    OpenTrip {
+                        trip,
+                        moving: true,
+                        stopped_at: None,
+                    },
+                )
+            })
+            .collect();
+
+        info!(
+            "🚗 Detección de viajes activa: inicio a {} km/h, fin tras {}s detenido ({} abiertos)",
+            config.start_speed_kmh,
+            config.stop_secs,
+            open.len()
+        );
+        Ok(Self {
+            config,
+            database,
+            notifications,
+            open: Mutex::new(open),
+            events: AtomicU64::new(0),
+        })
+    }
+
+    /// Inicios y fines de viaje generados desde el inicio
+    pub fn events_emitted(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Evalúa el lote, guarda los viajes afectados y publica los eventos. Los errores
+    /// solo se registran: un fallo aquí no debe frenar la ingesta.
+    pub async fn process(&self, positions: &[NormalizedPosition]) {
+        let (trips, events) = self.evaluate(positions);
+        if trips.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.database.upsert_trips(&trips).await {
+            error!("❌ Error guardando {} viajes: {}", trips.len(), e);
+        }
+
+        if events.is_empty() {
+            return;
+        }
+        self.events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        for event in &events {
+            debug!(
+                "🚗 Device {} {:?} viaje {} ({:.0} m, {}s)",
+                redact::device_id(&event.device_id),
+                event.event,
+                event.trip_id,
+                event.distance_m,
+                event.duration_secs
+            );
+        }
+
+        if let Some(notifications) = &self.notifications {
+            for event in &events {
+                if let Err(e) = notifications.publish(&event.device_id, event).await {
+                    error!("❌ Error publicando evento de viaje: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Viajes modificados y eventos del lote según los viajes abiertos
+    fn evaluate(&self, positions: &[NormalizedPosition]) -> (Vec<Trip>, Vec<TripEvent>) {
+        let mut open = self.open.lock().unwrap();
+        segment(&self.config, &mut open, positions)
+    }
+}
+
+/// Aplica cada posición al viaje abierto de su dispositivo en `open`. Devuelve el
+/// último estado de cada viaje modificado y los eventos de inicio y fin.
+fn segment(
+    config: &TripConfig,
+    open: &mut HashMap<String, OpenTrip>,
+    positions: &[NormalizedPosition],
+) -> (Vec<Trip>, Vec<TripEvent>) {
+    let mut touched: HashMap<String, Trip> = HashMap::new();
+    let mut events = Vec::new();
+
+    for position in positions {
+        let (Some(gps_epoch), Some(latitude), Some(longitude)) =
+            (position.gps_epoch, position.latitude, position.longitude)
+        else {
+            continue;
+        };
+        let point = TripPoint {
+            uuid: position.uuid.clone(),
+            gps_datetime: position.gps_datetime,
+            gps_epoch,
+            latitude,
+            longitude,
+        };
+        let ignition_off = position.engine_status == Some(EngineStatus::Off);
+        let moving = !ignition_off && position.speed_kmh.unwrap_or(0.0) >= config.start_speed_kmh;
+
+        let Some(current) = open.get_mut(&position.device_id) else {
+            if moving {
+                let trip = Trip {
+                    id: point.uuid.clone(),
+                    device_id: position.device_id.clone(),
+                    tenant: position.tenant.clone(),
+                    start: point.clone(),
+                    end: point,
+                    distance_m: 0.0,
+                    closed: false,
+                };
+                events.push(TripEvent::new(&trip, TripTransition::TripStart));
+                touched.insert(trip.id.clone(), trip.clone());
+                open.insert(
+                    position.device_id.clone(),
+                    OpenTrip {
+                        trip,
+                        moving: true,
+                        stopped_at: None,
+                    },
+                );
+            }
+            continue;
+        };
+
+        // Posiciones atrasadas (buffer del equipo) no modifican el viaje
+        if gps_epoch < current.trip.end.gps_epoch {
+            continue;
+        }
+
+        if current.moving || moving {
+            current.trip.distance_m += current.trip.end.distance_m(&point);
+        }
+        current.moving = moving;
+
+        let closing = if ignition_off {
+            current.trip.end = point;
+            true
+        } else if moving {
+            current.stopped_at = None;
+            current.trip.end = point;
+            false
+        } else {
+            let stopped_at = current.stopped_at.get_or_insert_with(|| point.clone());
+            if gps_epoch - stopped_at.gps_epoch >= config.stop_secs as i64 {
+                current.trip.end = stopped_at.clone();
+                true
+            } else {
+                current.trip.end = point;
+                false
+            }
+        };
+
+        if closing {
+            let mut trip = open.remove(&position.device_id).unwrap().trip;
+            trip.closed = true;
+            events.push(TripEvent::new(&trip, TripTransition::TripEnd));
+            touched.insert(trip.id.clone(), trip);
+        } else {
+            touched.insert(current.trip.id.clone(), current.trip.clone());
+        }
+    }
+
+    (touched.into_values().collect(), events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceMessage;
+
+    const CONFIG: TripConfig = TripConfig {
+        start_speed_kmh: 5.0,
+        stop_secs: 300,
+    };
+
+    /// Posición de "A" en el segundo `gps_epoch`, avanzando hacia el norte
+    fn position(gps_epoch: i64, speed_kmh: f64, engine: EngineStatus) -> NormalizedPosition {
+        let message = DeviceMessage::from_json(include_bytes!(
+            "../../tests/fixtures/messages/suntech_stt.json"
+        ))
+        .unwrap();
+        let mut position = NormalizedPosition::from_device_message(&message);
+        position.uuid = format!("uuid-{}", gps_epoch);
+        position.device_id = "A".to_string();
+        position.gps_datetime = None;
+        position.gps_epoch = Some(gps_epoch);
+        position.latitude = Some(19.0 + gps_epoch as f64 * 0.0001);
+        position.longitude = Some(-99.0);
+        position.speed_kmh = Some(speed_kmh);
+        position.engine_status = Some(engine);
+        position
+    }
+
+    fn moving(gps_epoch: i64) -> NormalizedPosition {
+        position(gps_epoch, 40.0, EngineStatus::On)
+    }
+
+    fn idle(gps_epoch: i64) -> NormalizedPosition {
+        position(gps_epoch, 0.0, EngineStatus::On)
+    }
+
+    fn transitions(events: &[TripEvent]) -> Vec<(TripTransition, String)> {
+        events
+            .iter()
+            .map(|event| (event.event, event.uuid.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn trip_runs_from_ignition_on_to_ignition_off() {
+        let mut open = HashMap::new();
+
+        // Encendido pero detenido: todavía no hay viaje
+        let (trips, events) = segment(&CONFIG, &mut open, &[idle(0)]);
+        assert!(trips.is_empty() && events.is_empty());
+
+        let (trips, events) = segment(&CONFIG, &mut open, &[moving(10), moving(70)]);
+        assert_eq!(
+            transitions(&events),
+            vec![(TripTransition::TripStart, "uuid-10".to_string())]
+        );
+        assert_eq!(trips.len(), 1);
+        assert!(!trips[0].closed);
+        assert_eq!(trips[0].end.gps_epoch, 70);
+
+        let (trips, events) = segment(&CONFIG, &mut open, &[position(100, 0.0, EngineStatus::Off)]);
+        assert_eq!(
+            transitions(&events),
+            vec![(TripTransition::TripEnd, "uuid-100".to_string())]
+        );
+        assert!(trips[0].closed);
+        assert_eq!(trips[0].id, "uuid-10");
+        assert_eq!(events[0].duration_secs, 90);
+        // ~10 m por segundo de avance hacia el norte
+        assert!(
+            (trips[0].distance_m - 1_000.0).abs() < 20.0,
+            "{}",
+            trips[0].distance_m
+        );
+        assert!(open.is_empty());
+    }
+
+    #[test]
+    fn trip_ends_where_it_stopped_after_the_idle_timeout() {
+        let mut open = HashMap::new();
+        segment(&CONFIG, &mut open, &[moving(0), idle(60), idle(200)]);
+        assert!(open.contains_key("A"));
+
+        // Volver a moverse reinicia la espera
+        let (_, events) = segment(&CONFIG, &mut open, &[moving(300), idle(400), idle(650)]);
+        assert!(events.is_empty());
+
+        let (trips, events) = segment(&CONFIG, &mut open, &[idle(700)]);
+        assert_eq!(
+            transitions(&events),
+            vec![(TripTransition::TripEnd, "uuid-400".to_string())]
+        );
+        assert_eq!(trips[0].end.gps_epoch, 400);
+        assert!(open.is_empty());
+    }
+
+    #[test]
+    fn late_positions_do_not_change_the_open_trip() {
+        let mut open = HashMap::new();
+        segment(&CONFIG, &mut open, &[moving(100), moving(200)]);
+        let distance = open["A"].trip.distance_m;
+
+        // Un apagado atrasado del buffer del equipo no cierra el viaje
+        let (trips, events) = segment(
+            &CONFIG,
+            &mut open,
+            &[position(150, 0.0, EngineStatus::Off), moving(50)],
+        );
+        assert!(trips.is_empty() && events.is_empty());
+        assert_eq!(open["A"].trip.end.gps_epoch, 200);
+        assert_eq!(open["A"].trip.distance_m, distance);
+    }
+}
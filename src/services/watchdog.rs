@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::config::{WatchdogAction, WatchdogConfig};
+use crate::error_reporter::{self, Incident};
+use crate::services::{MessageConsumer, MessageProcessor};
+
+/// Código de salida con `WATCHDOG_ACTION=exit`, distinto del de un error al arrancar
+/// (1) para que el orquestador sepa que el proceso se terminó por una etapa atascada
+pub const WATCHDOG_EXIT_CODE: i32 = 3;
+
+/// Etapa del pipeline vigilada por el watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StalledStage {
+    /// El loop de consumo no da vueltas
+    Consumer,
+    /// Hay mensajes en cola o en los carriles pero los carriles no avanzan
+    Processing,
+    /// El buffer de BD tiene registros más antiguos que el umbral
+    DbFlush,
+}
+
+impl fmt::Display for StalledStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Consumer => "consumer",
+            Self::Processing => "processing",
+            Self::DbFlush => "db_flush",
+        })
+    }
+}
+
+/// Detecta etapas que dejaron de avanzar durante `stall_secs` y aplica la acción
+/// configurada: registrar el estado de las etapas, pedir el reinicio del procesador
+/// o terminar el proceso.
+///
+/// Con el circuito de BD abierto o las escrituras pausadas los carriles y el buffer
+/// esperan a propósito, así que esas etapas no se evalúan.
+pub struct Watchdog {
+    config: WatchdogConfig,
+    processor: MessageProcessor,
+    consumer: Arc<dyn MessageConsumer>,
+}
+
+impl Watchdog {
+    pub fn new(
+        config: WatchdogConfig,
+        processor: MessageProcessor,
+        consumer: Arc<dyn MessageConsumer>,
+    ) -> Self {
+        info!(
+            "🐕 Watchdog: etapas sin avance por {}s → {:?}",
+            config.stall_secs, config.action
+        );
+        Self {
+            config,
+            processor,
+            consumer,
+        }
+    }
+
+    /// Inicia la revisión periódica. Con la acción `restart` las etapas atascadas se
+    /// envían a `restarts` para que el loop principal reinicie el procesador.
+    pub fn start(self, restarts: mpsc::UnboundedSender<StalledStage>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let stall = Duration::from_secs(self.config.stall_secs);
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.config.check_interval_secs));
+            let mut progress = self.processor.progress();
+            let mut progress_at = Instant::now();
+            let mut stalled = HashSet::new();
+            loop {
+                interval.tick().await;
+
+                let status = self.processor.drain_status().await;
+                let waiting_on_db = self.processor.circuit_open() || self.processor.writes_paused();
+                let current = self.processor.progress();
+                if current != progress || status.queued + status.in_lanes == 0 || waiting_on_db {
+                    progress = current;
+                    progress_at = Instant::now();
+                }
+
+                let mut now_stalled = HashSet::new();
+                if self
+                    .consumer
+                    .since_last_poll()
+                    .is_some_and(|since| since >= stall)
+                {
+                    now_stalled.insert(StalledStage::Consumer);
+                }
+                if progress_at.elapsed() >= stall {
+                    now_stalled.insert(StalledStage::Processing);
+                }
+                if !waiting_on_db
+                    && self
+                        .processor
+                        .oldest_buffer_age()
+                        .await
+                        .is_some_and(|age| age >= stall)
+                {
+                    now_stalled.insert(StalledStage::DbFlush);
+                }
+
+                for stage in stalled.difference(&now_stalled) {
+                    info!("🐕 Etapa '{}' vuelve a avanzar", stage);
+                }
+                let new: Vec<_> = now_stalled.difference(&stalled).copied().collect();
+                stalled = now_stalled;
+                if new.is_empty() {
+                    continue;
+                }
+
+                for stage in &new {
+                    error!(
+                        "🐕 Etapa '{}' sin avanzar desde hace al menos {}s",
+                        stage, self.config.stall_secs
+                    );
+                    error_reporter::report(Incident::new(
+                        "watchdog",
+                        format!("Etapa '{}' atascada ({:?})", stage, self.config.action),
+                    ));
+                }
+                self.dump().await;
+
+                match self.config.action {
+                    WatchdogAction::Log => {}
+                    WatchdogAction::Restart => {
+                        // Un solo reinicio aunque se atasquen varias etapas a la vez
+                        let _ = restarts.send(new[0]);
+                        // Dar tiempo al procesador nuevo antes de volver a evaluarlo
+                        stalled.clear();
+                        progress_at = Instant::now();
+                    }
+                    WatchdogAction::Exit => {
+                        error!(
+                            "🛑 Terminando el proceso con código {} por el watchdog",
+                            WATCHDOG_EXIT_CODE
+                        );
+                        std::process::exit(WATCHDOG_EXIT_CODE);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Registra el estado de las etapas y del runtime para diagnosticar el atasco
+    async fn dump(&self) {
+        let status = self.processor.drain_status().await;
+        let stats = self.processor.get_statistics().await;
+        let runtime = tokio::runtime::Handle::current().metrics();
+        warn!(
+            "🐕 Estado: cola {}, carriles {}, buffers de BD {} (más antiguo {}), \
+             último poll del consumo {}, circuito {}, escrituras {}",
+            status.queued,
+            status.in_lanes,
+            status.buffered,
+            format_age(self.processor.oldest_buffer_age().await),
+            format_age(self.consumer.since_last_poll()),
+            if stats.circuit_open {
+                "abierto"
+            } else {
+                "cerrado"
+            },
+            if self.processor.writes_paused() {
+                "pausadas"
+            } else {
+                "activas"
+            },
+        );
+        warn!(
+            "🐕 Runtime: {} workers, {} tareas vivas, {} en la cola global",
+            runtime.num_workers(),
+            runtime.num_alive_tasks(),
+            runtime.global_queue_depth()
+        );
+        for (lane, lane_stats) in stats.lanes.iter().enumerate() {
+            warn!(
+                "🐕 Carril {}: {} pendientes, {} mensajes en {} lotes",
+                lane, lane_stats.pending, lane_stats.messages, lane_stats.batches
+            );
+        }
+    }
+}
+
+fn format_age(age: Option<Duration>) -> String {
+    match age {
+        Some(age) => format!("hace {:.1}s", age.as_secs_f64()),
+        None => "-".to_string(),
+    }
+}
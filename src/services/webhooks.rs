@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::{RetryConfig, WebhookConfig, WebhookTargetConfig};
+
+/// Notificaciones en cola por endpoint; con el endpoint caído se descartan las nuevas
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Notificación serializada una sola vez y compartida entre endpoints
+struct Notification {
+    /// Identificador de la entrega, igual en todos los reintentos
+    id: String,
+    event: String,
+    body: Vec<u8>,
+}
+
+/// Envía las notificaciones (geocercas, viajes, conducción, conductores,
+/// conectividad) por POST a los endpoints configurados, para clientes sin
+/// consumidores de Kafka.
+///
+/// Cada endpoint tiene su cola y su tarea de envío, así uno lento o caído no frena
+/// a los demás ni al pipeline; las notificaciones de un endpoint se envían en orden.
+/// Con un secreto configurado el cuerpo va firmado con HMAC-SHA256.
+pub struct WebhookNotifier {
+    targets: Vec<WebhookTarget>,
+}
+
+struct WebhookTarget {
+    config: WebhookTargetConfig,
+    queue: mpsc::Sender<Arc<Notification>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+
+        let targets = config
+            .targets
+            .iter()
+            .map(|target| {
+                let (queue, notifications) = mpsc::channel(QUEUE_CAPACITY);
+                tokio::spawn(deliver_all(
+                    client.clone(),
+                    target.clone(),
+                    config.retry.clone(),
+                    notifications,
+                ));
+                info!(
+                    "🪝 Webhook '{}' habilitado (eventos: {}, firmado: {})",
+                    target.name,
+                    if target.events.is_empty() {
+                        "todos".to_string()
+                    } else {
+                        target.events.join(", ")
+                    },
+                    target.secret.is_some()
+                );
+                WebhookTarget {
+                    config: target.clone(),
+                    queue,
+                }
+            })
+            .collect();
+
+        Ok(Self { targets })
+    }
+
+    /// Encola la notificación en los endpoints cuyos filtros la aceptan. Los filtros
+    /// usan los campos `event` y `tenant` presentes en todos los eventos.
+    pub fn notify(&self, payload: &Value) {
+        let event = payload["event"].as_str().unwrap_or_default();
+        let tenant = payload["tenant"].as_str();
+        let mut notification = None;
+
+        for target in &self.targets {
+            let wants_event =
+                target.config.events.is_empty() || target.config.events.iter().any(|e| e == event);
+            let wants_tenant = target.config.tenants.is_empty()
+                || tenant.is_some_and(|tenant| target.config.tenants.iter().any(|t| t == tenant));
+            if !(wants_event && wants_tenant) {
+                continue;
+            }
+
+            let notification = notification
+                .get_or_insert_with(|| {
+                    Arc::new(Notification {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        event: event.to_string(),
+                        body: serde_json::to_vec(payload).unwrap_or_default(),
+                    })
+                })
+                .clone();
+            if target.queue.try_send(notification).is_err() {
+                warn!(
+                    "⚠️ Cola del webhook '{}' llena, se descarta un evento {}",
+                    target.config.name, event
+                );
+            }
+        }
+    }
+}
+
+/// Envía las notificaciones de un endpoint en orden, reintentando cada una
+async fn deliver_all(
+    client: reqwest::Client,
+    target: WebhookTargetConfig,
+    retry: RetryConfig,
+    mut notifications: mpsc::Receiver<Arc<Notification>>,
+) {
+    while let Some(notification) = notifications.recv().await {
+        let mut attempt = 1;
+        loop {
+            match deliver(&client, &target, &notification).await {
+                Ok(()) => {
+                    debug!(
+                        "🪝 Evento {} enviado al webhook '{}'",
+                        notification.event, target.name
+                    );
+                    break;
+                }
+                Err((e, retryable)) if retryable && attempt < retry.max_attempts => {
+                    let delay = retry.delay(attempt);
+                    warn!(
+                        "⚠️ Webhook '{}' falló (intento {}/{}), reintentando en {:?}: {}",
+                        target.name, attempt, retry.max_attempts, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err((e, _)) => {
+                    warn!(
+                        "❌ Evento {} descartado para el webhook '{}' tras {} intentos: {}",
+                        notification.event, target.name, attempt, e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Un intento de envío. En el error indica si vale la pena reintentar: errores de
+/// red, 429 y 5xx sí; el resto de 4xx no.
+async fn deliver(
+    client: &reqwest::Client,
+    target: &WebhookTargetConfig,
+    notification: &Notification,
+) -> std::result::Result<(), (anyhow::Error, bool)> {
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    let mut request = client
+        .post(&target.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Siscom-Event", &notification.event)
+        .header("X-Siscom-Delivery", &notification.id)
+        .header("X-Siscom-Timestamp", &timestamp);
+    if let Some(secret) = &target.secret {
+        request = request.header(
+            "X-Siscom-Signature",
+            format!("sha256={}", sign(secret, &timestamp, &notification.body)),
+        );
+    }
+
+    let response = request
+        .body(notification.body.clone())
+        .send()
+        .await
+        .map_err(|e| (e.into(), true))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+    Err((anyhow!("respuesta {}", status), retryable))
+}
+
+/// HMAC-SHA256 en hex de `{timestamp}.{body}`; el timestamp firmado impide reenviar
+/// una notificación vieja capturada
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC acepta claves de cualquier largo");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use serde_json::json;
+
+    fn target(name: &str, events: &[&str], tenants: &[&str]) -> WebhookTargetConfig {
+        WebhookTargetConfig {
+            name: name.to_string(),
+            url: "http://127.0.0.1:9/".to_string(),
+            secret: None,
+            events: events.iter().map(|event| event.to_string()).collect(),
+            tenants: tenants.iter().map(|tenant| tenant.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn signature_header_verifies_against_the_body() {
+        // Endpoint local que devuelve los headers y el cuerpo recibidos
+        let (received_tx, mut received) = mpsc::unbounded_channel::<(HeaderMap, Bytes)>();
+        let router = axum::Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                received_tx.send((headers, body)).unwrap();
+                "ok"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let notifier = WebhookNotifier::new(&WebhookConfig {
+            targets: vec![WebhookTargetConfig {
+                url,
+                secret: Some("secreto".to_string()),
+                ..target("crm", &[], &[])
+            }],
+            retry: RetryConfig::default(),
+            timeout_ms: 5_000,
+        })
+        .unwrap();
+        let payload = json!({"event": "geofence_enter", "tenant": "acme", "device_id": "A"});
+        notifier.notify(&payload);
+
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let header = |name: &str| headers[name].to_str().unwrap().to_string();
+        assert_eq!(header("x-siscom-event"), "geofence_enter");
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), payload);
+
+        // Lo que haría el receptor: HMAC de `{timestamp}.{cuerpo}` con el secreto
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secreto").unwrap();
+        mac.update(format!("{}.", header("x-siscom-timestamp")).as_bytes());
+        mac.update(&body);
+        let signature = header("x-siscom-signature");
+        let expected = hex::decode(signature.strip_prefix("sha256=").unwrap()).unwrap();
+        assert!(mac.verify_slice(&expected).is_ok());
+
+        // Con otro cuerpo o con otro timestamp la firma no coincide
+        assert_ne!(
+            sign("secreto", &header("x-siscom-timestamp"), b"{}"),
+            signature["sha256=".len()..]
+        );
+        assert_ne!(sign("secreto", "0", &body), signature["sha256=".len()..]);
+    }
+
+    #[tokio::test]
+    async fn filtered_events_and_tenants_are_not_queued() {
+        let configs = [
+            target("todo", &[], &[]),
+            target("geocercas", &["geofence_enter", "geofence_exit"], &[]),
+            target("acme", &[], &["acme"]),
+        ];
+        let mut queues = Vec::new();
+        let targets = configs
+            .into_iter()
+            .map(|config| {
+                let (queue, notifications) = mpsc::channel(10);
+                queues.push(notifications);
+                WebhookTarget { config, queue }
+            })
+            .collect();
+        let notifier = WebhookNotifier { targets };
+
+        notifier.notify(&json!({"event": "geofence_enter", "tenant": "acme"}));
+        notifier.notify(&json!({"event": "trip_start", "tenant": "globex"}));
+        notifier.notify(&json!({"event": "harsh_braking"}));
+
+        let events = |queue: &mut mpsc::Receiver<Arc<Notification>>| {
+            let mut events = Vec::new();
+            while let Ok(notification) = queue.try_recv() {
+                events.push(notification.event.clone());
+            }
+            events
+        };
+        assert_eq!(
+            events(&mut queues[0]),
+            vec!["geofence_enter", "trip_start", "harsh_braking"]
+        );
+        assert_eq!(events(&mut queues[1]), vec!["geofence_enter"]);
+        // Sin tenant no pasa un filtro de tenants
+        assert_eq!(events(&mut queues[2]), vec!["geofence_enter"]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Motivo por el que termina el proceso. Cada uno sale con su propio código para
+/// que el orquestador distinga, p. ej., una configuración inválida (no reintentar)
+/// de una BD caída al arrancar (reintentar con backoff).
+///
+/// Se adjunta como contexto a los errores (`.context(ShutdownReason::...)`) en el
+/// punto donde se conoce la causa; `ShutdownReason::of` lo recupera en `main`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// Shutdown graceful completo o subcomando terminado
+    Completed,
+    /// Error sin clasificar
+    Failed,
+    ConfigInvalid,
+    /// PostgreSQL no respondió al conectar durante el arranque
+    DatabaseUnreachable,
+    /// El broker de entrada o el de MQTT no respondió durante el arranque
+    BrokerUnreachable,
+    /// El broker MQTT de republicación rechazó las credenciales
+    MqttAuthFailed,
+    /// `PROCESSING_DRAIN_TIMEOUT_SECS` venció con mensajes sin escribir
+    DrainTimeout,
+    /// Una tarea agotó `SUPERVISOR_MAX_RESTARTS`
+    SupervisorGaveUp,
+    /// Se perdió la conexión del lease de consumo (`HANDOVER_LEASE_KEY`)
+    LeaseLost,
+}
+
+impl ShutdownReason {
+    /// Código de salida. 2 lo usa clap para argumentos inválidos y 3
+    /// (`WATCHDOG_EXIT_CODE`) el watchdog, que termina el proceso por su cuenta.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ShutdownReason::Completed => 0,
+            ShutdownReason::Failed => 1,
+            ShutdownReason::ConfigInvalid => 4,
+            ShutdownReason::DatabaseUnreachable => 5,
+            ShutdownReason::MqttAuthFailed => 6,
+            ShutdownReason::DrainTimeout => 7,
+            ShutdownReason::SupervisorGaveUp => 8,
+            ShutdownReason::BrokerUnreachable => 9,
+            ShutdownReason::LeaseLost => 10,
+        }
+    }
+
+    /// Motivo adjuntado a un error, o `Failed` si no tiene
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<ShutdownReason>()
+            .copied()
+            .unwrap_or(ShutdownReason::Failed)
+    }
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShutdownReason::Completed => "terminado correctamente",
+            ShutdownReason::Failed => "error",
+            ShutdownReason::ConfigInvalid => "configuración inválida",
+            ShutdownReason::DatabaseUnreachable => "base de datos no disponible al arrancar",
+            ShutdownReason::BrokerUnreachable => "broker no disponible al arrancar",
+            ShutdownReason::MqttAuthFailed => "credenciales MQTT rechazadas",
+            ShutdownReason::DrainTimeout => "límite de drenado superado",
+            ShutdownReason::SupervisorGaveUp => "el supervisor agotó los reinicios",
+            ShutdownReason::LeaseLost => "lease de consumo perdido",
+        })
+    }
+}
+
+impl From<ShutdownReason> for ExitCode {
+    fn from(reason: ShutdownReason) -> Self {
+        ExitCode::from(reason.exit_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use tracking_consumer_rust::services::watchdog::WATCHDOG_EXIT_CODE;
+
+    const ALL: [ShutdownReason; 9] = [
+        ShutdownReason::Completed,
+        ShutdownReason::Failed,
+        ShutdownReason::ConfigInvalid,
+        ShutdownReason::DatabaseUnreachable,
+        ShutdownReason::BrokerUnreachable,
+        ShutdownReason::MqttAuthFailed,
+        ShutdownReason::DrainTimeout,
+        ShutdownReason::SupervisorGaveUp,
+        ShutdownReason::LeaseLost,
+    ];
+
+    #[test]
+    fn exit_codes_match_the_documented_table() {
+        let codes: Vec<(ShutdownReason, u8)> = ALL
+            .iter()
+            .map(|reason| (*reason, reason.exit_code()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (ShutdownReason::Completed, 0),
+                (ShutdownReason::Failed, 1),
+                (ShutdownReason::ConfigInvalid, 4),
+                (ShutdownReason::DatabaseUnreachable, 5),
+                (ShutdownReason::BrokerUnreachable, 9),
+                (ShutdownReason::MqttAuthFailed, 6),
+                (ShutdownReason::DrainTimeout, 7),
+                (ShutdownReason::SupervisorGaveUp, 8),
+                (ShutdownReason::LeaseLost, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn exit_codes_are_unique_and_avoid_reserved_ones() {
+        let mut codes: Vec<u8> = ALL.iter().map(|reason| reason.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), ALL.len());
+        // 2 es de clap y 3 del watchdog
+        assert!(!codes.contains(&2));
+        assert!(!codes.contains(&(WATCHDOG_EXIT_CODE as u8)));
+    }
+
+    #[test]
+    fn reason_is_recovered_from_the_error_context() {
+        let error = Err::<(), _>(anyhow::anyhow!("connection refused"))
+            .context(ShutdownReason::DatabaseUnreachable)
+            .context("conectando a PostgreSQL")
+            .unwrap_err();
+        assert_eq!(
+            ShutdownReason::of(&error),
+            ShutdownReason::DatabaseUnreachable
+        );
+        assert_eq!(
+            ShutdownReason::of(&anyhow::anyhow!("sin motivo")),
+            ShutdownReason::Failed
+        );
+    }
+}
@@ -88,6 +88,9 @@ pub struct KafkaMessage {
     /// Raw payload (original message)
     #[prost(string, tag = "6")]
     pub raw: ::prost::alloc::string::String,
+    /// Envelope layout version (0 or 1 = current layout, 2 = renamed fields)
+    #[prost(uint32, tag = "7")]
+    pub schema_version: u32,
     /// Decoded message as oneof per vendor
     #[prost(oneof = "kafka_message::Decoded", tags = "2, 3")]
     pub decoded: ::core::option::Option<kafka_message::Decoded>,
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+use crate::config::{LoggingConfig, TracingConfig};
+use crate::redact;
+
+/// Filtros que recorre SIGUSR1 tras el configurado, antes de volver a él
+const SIGNAL_LEVELS: [&str; 2] = ["debug", "trace"];
+
+static LOG_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+
+/// Filtro de los logs modificable en caliente, sin reiniciar ni perder los buffers
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+    configured: String,
+    current: Mutex<String>,
+}
+
+impl LogLevel {
+    /// Filtro activo, con la sintaxis de `RUST_LOG`
+    pub fn current(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Reemplaza el filtro; `None` vuelve al configurado al arrancar
+    pub fn set(&self, directives: Option<&str>) -> Result<String> {
+        let directives = directives.unwrap_or(&self.configured).trim().to_string();
+        let filter = EnvFilter::try_new(&directives)
+            .map_err(|e| anyhow!("filtro de logs inválido '{}': {}", directives, e))?;
+        self.handle.reload(filter)?;
+        *self.current.lock().unwrap() = directives.clone();
+        info!("📝 Nivel de logs cambiado a {}", directives);
+        Ok(directives)
+    }
+
+    /// Siguiente filtro para SIGUSR1: configurado → debug → trace → configurado
+    pub fn cycle(&self) -> Result<String> {
+        let current = self.current();
+        let next = match SIGNAL_LEVELS.iter().position(|level| *level == current) {
+            Some(index) => SIGNAL_LEVELS.get(index + 1).copied(),
+            None => Some(SIGNAL_LEVELS[0]),
+        };
+        self.set(next)
+    }
+}
+
+/// Control del nivel de logs; `None` antes de `init`
+pub fn log_level() -> Option<&'static LogLevel> {
+    LOG_LEVEL.get()
+}
+
+/// Exportador de trazas y escritor del archivo de log; hay que conservarlo hasta
+/// `shutdown` para no perder lo pendiente
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+    _log_file: Option<WorkerGuard>,
+}
+
+/// Inicializa los logs (stdout y, con `LOGGING_FILE_PATH`, un archivo rotado) con
+/// sus reglas de ocultación y, con `config`, la exportación de las trazas del
+/// pipeline por OTLP. Debe llamarse dentro del runtime de tokio: el exportador
+/// envía los spans en segundo plano.
+pub fn init(logging: &LoggingConfig, config: Option<&TracingConfig>) -> Result<Telemetry> {
+    redact::init(&logging.redaction);
+
+    // El archivo se escribe desde un hilo propio para no bloquear el pipeline
+    let (file, log_file) = match &logging.file_path {
+        Some(path) => {
+            let (writer, guard) = tracing_appender::non_blocking(open_log_file(path, logging)?);
+            (
+                Some(fmt_layer(writer, logging.json_format, false)),
+                Some(guard),
+            )
+        }
+        None => (None, None),
+    };
+    let configured = match EnvFilter::try_new(&logging.level) {
+        Ok(_) => logging.level.clone(),
+        Err(_) => "info".to_string(),
+    };
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(&configured));
+    let logs = fmt_layer(std::io::stdout, logging.json_format, true)
+        .and_then(file)
+        .with_filter(filter);
+
+    let provider = config.map(build_provider).transpose()?;
+    // Solo los spans de este crate, sin importar el nivel de los logs
+    let traces = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+            .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), LevelFilter::INFO))
+    });
+
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(traces)
+        .init();
+
+    let _ = LOG_LEVEL.set(LogLevel {
+        handle,
+        current: Mutex::new(configured.clone()),
+        configured,
+    });
+
+    if let Some(path) = &logging.file_path {
+        info!(
+            "📝 Logs también en {} (rotación {}, {} MB, {} archivos)",
+            path, logging.rotation, logging.max_file_size_mb, logging.max_files
+        );
+    }
+    if let Some(config) = config {
+        info!(
+            "🔭 Trazas OpenTelemetry exportadas a {} como {} (muestreo {})",
+            config.endpoint, config.service_name, config.sample_ratio
+        );
+    }
+    Ok(Telemetry {
+        provider,
+        _log_file: log_file,
+    })
+}
+
+/// Logs en JSON o en texto sobre `writer`
+fn fmt_layer<W>(writer: W, json: bool, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match json {
+        true => layer
+            .json()
+            .with_current_span(false)
+            .with_span_list(false)
+            .boxed(),
+        false => layer.boxed(),
+    }
+}
+
+/// Archivo de log que rota por tiempo (`rotation`) y al llegar a `max_file_size_mb`,
+/// conservando `max_files` archivos anteriores (`app.log.1` es el más reciente)
+fn open_log_file(path: &str, config: &LoggingConfig) -> Result<BasicRollingFileAppender> {
+    let condition = match config.rotation.as_str() {
+        "hourly" => RollingConditionBasic::new().hourly(),
+        "never" => RollingConditionBasic::new(),
+        _ => RollingConditionBasic::new().daily(),
+    };
+    let condition = match config.max_file_size_mb {
+        0 => condition,
+        mb => condition.max_size(mb * 1024 * 1024),
+    };
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(BasicRollingFileAppender::new(
+        path,
+        condition,
+        config.max_files as usize,
+    )?)
+}
+
+fn build_provider(config: &TracingConfig) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", config.endpoint))
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build())
+}
+
+/// Envía los spans y logs pendientes antes de salir
+pub fn shutdown(telemetry: Telemetry) {
+    if let Some(provider) = telemetry.provider {
+        if let Err(e) = provider.shutdown() {
+            error!("❌ Error enviando las trazas pendientes: {}", e);
+        }
+    }
+}
+
+/// Contexto de traza (`traceparent`/`tracestate`) recibido en los headers de un
+/// mensaje Kafka; vacío si el productor no lo envió
+pub fn extract_kafka_context<H: Headers>(headers: Option<&H>) -> Context {
+    let carrier: HashMap<String, String> = headers
+        .into_iter()
+        .flat_map(|headers| headers.iter())
+        .filter_map(|header| {
+            let value = std::str::from_utf8(header.value?).ok()?;
+            Some((header.key.to_string(), value.to_string()))
+        })
+        .collect();
+    TraceContextPropagator::new().extract(&carrier)
+}
+
+/// Headers Kafka con el contexto del span actual, para que los consumidores del
+/// tópico continúen la misma traza
+pub fn kafka_headers() -> OwnedHeaders {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&tracing::Span::current().context(), &mut carrier);
+    carrier
+        .iter()
+        .fold(OwnedHeaders::new(), |headers, (key, value)| {
+            headers.insert(Header {
+                key,
+                value: Some(value.as_str()),
+            })
+        })
+}
@@ -0,0 +1,350 @@
+//! Pruebas de extremo a extremo: levantan PostgreSQL, Redpanda y Mosquitto con
+//! testcontainers, arrancan el binario contra ellos, publican los mensajes de
+//! `tests/fixtures` y comprueban lo que llega a cada destino.
+//!
+//! Necesitan Docker, por eso no corren con `cargo test`:
+//!
+//! ```bash
+//! cargo test --test integration -- --ignored
+//! ```
+
+use std::fs::File;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use testcontainers::clients::Cli;
+use testcontainers::core::{ExecCommand, WaitFor};
+use testcontainers::{Container, GenericImage, RunnableImage};
+
+const POSTGRES_PASSWORD: &str = "siscom";
+const DATABASE: &str = "siscom";
+const INPUT_TOPIC: &str = "siscom-messages";
+const NOTIFICATIONS_TOPIC: &str = "siscom-notifications";
+const HEARTBEAT_TOPIC: &str = "siscom-consumer-heartbeats";
+const INSTANCE_ID: &str = "integration";
+
+/// Puerto de Kafka dentro del contenedor para `rpk`; el 9092 se anuncia con el
+/// puerto del host para el consumidor
+const REDPANDA_INTERNAL_PORT: u16 = 9093;
+
+/// Tiempo máximo para que un mensaje llegue a su destino
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Fixture {
+    file: &'static str,
+    uuid: &'static str,
+    device_id: &'static str,
+    table: &'static str,
+    manufacturer: &'static str,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        file: "suntech.json",
+        uuid: "4f1c2a9e-8b0d-4c55-9e2a-3b7d1c6f0a11",
+        device_id: "900000001",
+        table: "communications_suntech",
+        manufacturer: "suntech",
+    },
+    Fixture {
+        file: "queclink.json",
+        uuid: "9a3e5d71-2c4b-4f08-b6d2-7e1f0c8a5b22",
+        device_id: "860000000000001",
+        table: "communications_queclink",
+        manufacturer: "queclink",
+    },
+];
+
+#[test]
+#[ignore = "requiere Docker"]
+fn kafka_messages_reach_every_output() {
+    let docker = Cli::default();
+    let postgres = start_postgres(&docker);
+    let kafka_port = free_port();
+    let redpanda = start_redpanda(&docker, kafka_port);
+    let mosquitto = start_mosquitto(&docker);
+
+    rpk(
+        &redpanda,
+        &format!("topic create {} {}", INPUT_TOPIC, NOTIFICATIONS_TOPIC),
+    );
+
+    let work_dir = std::env::temp_dir().join(format!("siscom-it-{}", std::process::id()));
+    std::fs::create_dir_all(&work_dir).unwrap();
+    let _consumer = Consumer::start(
+        &work_dir,
+        &[
+            ("DB_HOST", "127.0.0.1".to_string()),
+            ("DB_PORT", postgres.get_host_port_ipv4(5432).to_string()),
+            ("DB_DATABASE", DATABASE.to_string()),
+            ("DB_USERNAME", "postgres".to_string()),
+            ("DB_PASSWORD", POSTGRES_PASSWORD.to_string()),
+            ("DB_RUN_MIGRATIONS", "true".to_string()),
+            ("DB_BUFFER_MAX_AGE_SECS", "1".to_string()),
+            ("BROKER_TYPE", "kafka".to_string()),
+            ("BROKER_HOST", format!("127.0.0.1:{}", kafka_port)),
+            ("BROKER_TOPIC", INPUT_TOPIC.to_string()),
+            ("BROKER_PAYLOAD_FORMAT", "json".to_string()),
+            ("KAFKA_NOTIFICATIONS_TOPIC", NOTIFICATIONS_TOPIC.to_string()),
+            ("HEARTBEAT_INTERVAL_SECS", "1".to_string()),
+            ("HEARTBEAT_TOPIC", HEARTBEAT_TOPIC.to_string()),
+            ("INSTANCE_ID", INSTANCE_ID.to_string()),
+            ("GEOFENCE_SOURCE", "file".to_string()),
+            (
+                "GEOFENCE_FILE",
+                fixture_path("geofences.json").display().to_string(),
+            ),
+            (
+                "MQTT_PUBLISH_URL",
+                format!("mqtt://127.0.0.1:{}", mosquitto.get_host_port_ipv4(1883)),
+            ),
+            ("MQTT_PUBLISH_QOS", "1".to_string()),
+            ("MQTT_PUBLISH_RETAIN", "true".to_string()),
+        ],
+    );
+
+    // El consumidor empieza en `latest`: se republican los mensajes hasta que la
+    // asignación de particiones los recoge; los duplicados los descarta el índice
+    // único de `uuid`
+    wait_until("filas en el histórico", || {
+        for fixture in FIXTURES {
+            rpk(
+                &redpanda,
+                &format!("topic produce {} < /fixtures/{}", INPUT_TOPIC, fixture.file),
+            );
+        }
+        FIXTURES
+            .iter()
+            .all(|fixture| history_rows(&postgres, fixture) == 1)
+    });
+
+    for fixture in FIXTURES {
+        assert_eq!(history_rows(&postgres, fixture), 1, "{}", fixture.table);
+        assert_eq!(
+            psql(
+                &postgres,
+                &format!(
+                    "SELECT uuid FROM communications_current_state WHERE device_id = '{}'",
+                    fixture.device_id
+                ),
+            ),
+            fixture.uuid,
+        );
+    }
+
+    let notifications = rpk_consume(&redpanda, NOTIFICATIONS_TOPIC, FIXTURES.len());
+    for fixture in FIXTURES {
+        assert!(
+            notifications.iter().any(|notification| {
+                notification["geofence_id"] == "zocalo"
+                    && notification["event"] == "enter"
+                    && notification["uuid"] == fixture.uuid
+            }),
+            "falta la entrada a la geocerca de {}: {:?}",
+            fixture.device_id,
+            notifications
+        );
+    }
+
+    let heartbeats = rpk_consume(&redpanda, HEARTBEAT_TOPIC, 1);
+    assert_eq!(heartbeats[0]["instance_id"], INSTANCE_ID);
+
+    // Con retain el broker entrega la última posición de cada dispositivo al suscribirse
+    let published = exec(
+        &mosquitto,
+        &format!(
+            "mosquitto_sub -t 'normalized/#' -v -C {} -W {}",
+            FIXTURES.len(),
+            TIMEOUT.as_secs()
+        ),
+    );
+    for fixture in FIXTURES {
+        let topic = format!("normalized/{}/{}", fixture.manufacturer, fixture.device_id);
+        let payload = published
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} ", topic)))
+            .unwrap_or_else(|| panic!("sin publicación MQTT en {}: {}", topic, published));
+        let position: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(position["uuid"], fixture.uuid);
+    }
+
+    std::fs::remove_dir_all(&work_dir).ok();
+}
+
+fn start_postgres(docker: &Cli) -> Container<'_, GenericImage> {
+    let image = GenericImage::new("postgres", "16-alpine")
+        .with_env_var("POSTGRES_PASSWORD", POSTGRES_PASSWORD)
+        .with_env_var("POSTGRES_DB", DATABASE)
+        .with_exposed_port(5432)
+        .with_wait_for(WaitFor::message_on_stderr(
+            "database system is ready to accept connections",
+        ));
+    let container = docker.run(image);
+    // El mensaje aparece también durante la inicialización, que solo escucha en el
+    // socket local; por TCP responde ya el servidor definitivo
+    wait_until("PostgreSQL", || {
+        exec(
+            &container,
+            &format!("pg_isready -h 127.0.0.1 -U postgres -d {}", DATABASE),
+        )
+        .contains("accepting")
+    });
+    container
+}
+
+fn start_redpanda(docker: &Cli, kafka_port: u16) -> Container<'_, GenericImage> {
+    let image = GenericImage::new("redpandadata/redpanda", "v24.2.4")
+        .with_volume(fixture_path("").display().to_string(), "/fixtures")
+        .with_wait_for(WaitFor::message_on_stderr("Successfully started Redpanda!"));
+    let args = [
+        "redpanda",
+        "start",
+        "--mode",
+        "dev-container",
+        "--smp",
+        "1",
+        "--kafka-addr",
+        &format!(
+            "internal://0.0.0.0:{},external://0.0.0.0:9092",
+            REDPANDA_INTERNAL_PORT
+        ),
+        "--advertise-kafka-addr",
+        &format!(
+            "internal://localhost:{},external://127.0.0.1:{}",
+            REDPANDA_INTERNAL_PORT, kafka_port
+        ),
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect::<Vec<_>>();
+    docker.run(RunnableImage::from((image, args)).with_mapped_port((kafka_port, 9092)))
+}
+
+fn start_mosquitto(docker: &Cli) -> Container<'_, GenericImage> {
+    let image = GenericImage::new("eclipse-mosquitto", "2")
+        .with_exposed_port(1883)
+        .with_wait_for(WaitFor::message_on_stderr("running"));
+    let args = vec![
+        "mosquitto".to_string(),
+        "-c".to_string(),
+        "/mosquitto-no-auth.conf".to_string(),
+    ];
+    docker.run((image, args))
+}
+
+/// Proceso del consumidor; se mata al terminar la prueba, aunque falle
+struct Consumer(Child);
+
+impl Consumer {
+    fn start(work_dir: &Path, env: &[(&str, String)]) -> Self {
+        let log = work_dir.join("consumer.log");
+        eprintln!("Log del consumidor: {}", log.display());
+        let log = File::create(log).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_siscom-consumer"))
+            .current_dir(work_dir)
+            .env_clear()
+            .env("RUST_LOG", "info")
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .stdout(log.try_clone().unwrap())
+            .stderr(log)
+            .stdin(Stdio::null())
+            .spawn()
+            .expect("no se pudo arrancar siscom-consumer");
+        Self(child)
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+fn history_rows(postgres: &Container<'_, GenericImage>, fixture: &Fixture) -> u64 {
+    // Antes de las migraciones la tabla no existe y la consulta no devuelve nada
+    psql(
+        postgres,
+        &format!(
+            "SELECT count(*) FROM {} WHERE uuid = '{}'",
+            fixture.table, fixture.uuid
+        ),
+    )
+    .parse()
+    .unwrap_or(0)
+}
+
+fn psql(postgres: &Container<'_, GenericImage>, query: &str) -> String {
+    exec(
+        postgres,
+        &format!("psql -U postgres -d {} -tAc \"{}\"", DATABASE, query),
+    )
+    .trim()
+    .to_string()
+}
+
+fn rpk(redpanda: &Container<'_, GenericImage>, command: &str) -> String {
+    exec(
+        redpanda,
+        &format!(
+            "rpk {} -X brokers=localhost:{}",
+            command, REDPANDA_INTERNAL_PORT
+        ),
+    )
+}
+
+/// Lee los primeros `count` mensajes JSON de `topic`
+fn rpk_consume(
+    redpanda: &Container<'_, GenericImage>,
+    topic: &str,
+    count: usize,
+) -> Vec<serde_json::Value> {
+    let output = rpk(
+        redpanda,
+        &format!("topic consume {} -o start -n {} -f '%v\\n'", topic, count),
+    );
+    let messages = output
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect::<Vec<_>>();
+    assert_eq!(messages.len(), count, "{}: {}", topic, output);
+    messages
+}
+
+fn exec(container: &Container<'_, GenericImage>, cmd: &str) -> String {
+    // `timeout` evita que un comando que espera mensajes bloquee la prueba
+    let output = container.exec(ExecCommand {
+        cmd: format!("timeout {} {}", TIMEOUT.as_secs(), cmd),
+        ready_conditions: Vec::new(),
+    });
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !done() {
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "tiempo agotado esperando {}",
+            what
+        );
+        sleep(Duration::from_secs(1));
+    }
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}